@@ -1,21 +1,14 @@
-use bt_hci::cmd::le::*;
-use bt_hci::controller::ControllerCmdSync;
 use embassy_futures::join::join;
 use embassy_time::{Duration, Timer};
 use trouble_host::prelude::*;
 
-pub async fn run<C>(controller: C)
+pub async fn run<C, S>(controller: C, device: &S)
 where
-    C: Controller
-        + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
-        + ControllerCmdSync<LeClearAdvSets>
-        + ControllerCmdSync<LeSetExtAdvParams>
-        + ControllerCmdSync<LeSetAdvSetRandomAddr>
-        + ControllerCmdSync<LeReadNumberOfSupportedAdvSets>
-        + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
-        + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>,
+    C: ControllerWithExtAdv,
+    S: DeviceAddressSource + ?Sized,
 {
-    let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
+    // Derive the address from the device identifier, so boards running the same example do not collide.
+    let address: Address = Address::from_device_id(device);
     info!("Our address = {:?}", address);
 
     let mut resources: HostResources<DefaultPacketPool, 0, 0> = HostResources::new();
@@ -9,14 +9,14 @@ const CONNECTIONS_MAX: usize = 1;
 /// Max number of L2CAP channels.
 const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
 
-pub async fn run<C, RNG>(controller: C, random_generator: &mut RNG)
+pub async fn run<C, RNG, S>(controller: C, device: &S, random_generator: &mut RNG)
 where
     C: Controller,
     RNG: RngCore + CryptoRng,
+    S: DeviceAddressSource + ?Sized,
 {
-    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
-    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
-    let address: Address = Address::random([0xff, 0x8f, 0x1b, 0x05, 0xe4, 0xff]);
+    // Derive the address from the device identifier, so boards running the same example do not collide.
+    let address: Address = Address::from_device_id(device);
     info!("Our address = {:?}", address);
 
     let mut resources: HostResources<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
@@ -62,12 +62,12 @@ where
 
         let _ = join(client.task(), async {
             info!("Looking for battery service");
-            let services = client.services_by_uuid(&Uuid::new_short(0x180f)).await.unwrap();
+            let services = client.services_by_uuid(&service::BATTERY.into()).await.unwrap();
             let service = services.first().unwrap().clone();
 
             info!("Looking for value handle");
             let c: Characteristic<u8> = client
-                .characteristic_by_uuid(&service, &Uuid::new_short(0x2a19))
+                .characteristic_by_uuid(&service, &characteristic::BATTERY_LEVEL.into())
                 .await
                 .unwrap();
 
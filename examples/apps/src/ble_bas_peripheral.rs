@@ -1,5 +1,4 @@
 use embassy_futures::join::join;
-use embassy_futures::select::select;
 use embassy_time::Timer;
 use trouble_host::prelude::*;
 
@@ -32,8 +31,8 @@ pub async fn run<C>(controller: C)
 where
     C: Controller,
 {
-    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
-    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
+    // The central examples connect to this fixed address. In real scenarios, derive the address from
+    // the device identifier with `Address::from_device_id`, as the central examples do.
     let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
     info!("Our address = {:?}", address);
 
@@ -44,10 +43,10 @@ where
     } = stack.build();
 
     info!("Starting advertising and GATT service");
-    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig {
-        name: "TrouBLE",
-        appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
-    }))
+    let server = Server::new_with_config(GapConfig::Peripheral(PeripheralConfig::new(
+        "TrouBLE",
+        &appearance::power_device::GENERIC_POWER_DEVICE,
+    )))
     .unwrap();
 
     let _ = join(ble_task(runner), async {
@@ -57,9 +56,8 @@ where
                     // set up tasks when the connection is established to a central, so they don't run when no one is connected.
                     let a = gatt_events_task(&server, &conn);
                     let b = custom_task(&server, &conn, &stack);
-                    // run until any task ends (usually because the connection has been closed),
-                    // then return to advertising state.
-                    select(a, b).await;
+                    // run the tasks until the connection is closed, then return to advertising state.
+                    let _ = conn.scope(join(a, b)).await;
                 }
                 Err(e) => {
                     #[cfg(feature = "defmt")]
@@ -145,7 +143,7 @@ async fn advertise<'values, 'server, C: Controller>(
     let len = AdStructure::encode_slice(
         &[
             AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-            AdStructure::ServiceUuids16(&[[0x0f, 0x18]]),
+            AdStructure::ServiceUuids16(&[service::BATTERY.to_le_bytes()]),
             AdStructure::CompleteLocalName(name.as_bytes()),
         ],
         &mut advertiser_data[..],
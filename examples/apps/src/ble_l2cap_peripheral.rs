@@ -14,7 +14,8 @@ pub async fn run<C>(controller: C)
 where
     C: Controller,
 {
-    // Hardcoded peripheral address
+    // The central examples connect to this fixed address. In real scenarios, derive the address from
+    // the device identifier with `Address::from_device_id`, as the central examples do.
     let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
     info!("Our address = {:?}", address);
 
@@ -37,52 +38,51 @@ where
     let scan_data_len =
         AdStructure::encode_slice(&[AdStructure::CompleteLocalName(b"Trouble")], &mut scan_data[..]).unwrap();
 
+    let stack = &stack;
     let _ = join(runner.run(), async {
-        loop {
-            info!("Advertising, waiting for connection...");
-            let advertiser = peripheral
-                .advertise(
-                    &Default::default(),
-                    Advertisement::ConnectableScannableUndirected {
-                        adv_data: &adv_data[..adv_data_len],
-                        scan_data: &scan_data[..scan_data_len],
-                    },
-                )
-                .await
-                .unwrap();
-            let conn = advertiser.accept().await.unwrap();
+        info!("Advertising, waiting for connection...");
+        peripheral
+            .advertise_loop(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data[..adv_data_len],
+                    scan_data: &scan_data[..scan_data_len],
+                },
+                |conn| async move {
+                    info!("Connection established");
 
-            info!("Connection established");
+                    let config = L2capChannelConfig {
+                        mtu: Some(PAYLOAD_LEN as u16),
+                        ..Default::default()
+                    };
+                    let mut ch1 = L2capChannel::accept(stack, &conn, &[PSM_L2CAP_EXAMPLES], &config).await?;
 
-            let config = L2capChannelConfig {
-                mtu: Some(PAYLOAD_LEN as u16),
-                ..Default::default()
-            };
-            let mut ch1 = L2capChannel::accept(&stack, &conn, &[PSM_L2CAP_EXAMPLES], &config)
-                .await
-                .unwrap();
+                    info!("L2CAP channel accepted");
 
-            info!("L2CAP channel accepted");
+                    // Size of payload we're expecting
+                    const PAYLOAD_LEN: usize = 27;
+                    let mut rx = [0; PAYLOAD_LEN];
+                    for i in 0..10 {
+                        let len = ch1.receive(stack, &mut rx).await?;
+                        assert_eq!(len, rx.len());
+                        assert_eq!(rx, [i; PAYLOAD_LEN]);
+                    }
 
-            // Size of payload we're expecting
-            const PAYLOAD_LEN: usize = 27;
-            let mut rx = [0; PAYLOAD_LEN];
-            for i in 0..10 {
-                let len = ch1.receive(&stack, &mut rx).await.unwrap();
-                assert_eq!(len, rx.len());
-                assert_eq!(rx, [i; PAYLOAD_LEN]);
-            }
+                    info!("L2CAP data received, echoing");
+                    Timer::after(Duration::from_secs(1)).await;
+                    for i in 0..10 {
+                        let tx = [i; PAYLOAD_LEN];
+                        ch1.send(stack, &tx).await?;
+                    }
+                    info!("L2CAP data echoed");
 
-            info!("L2CAP data received, echoing");
-            Timer::after(Duration::from_secs(1)).await;
-            for i in 0..10 {
-                let tx = [i; PAYLOAD_LEN];
-                ch1.send(&stack, &tx).await.unwrap();
-            }
-            info!("L2CAP data echoed");
-
-            Timer::after(Duration::from_secs(60)).await;
-        }
+                    Timer::after(Duration::from_secs(60)).await;
+                    info!("Advertising, waiting for connection...");
+                    Ok(())
+                },
+            )
+            .await
+            .unwrap();
     })
     .await;
 }
@@ -1,5 +1,4 @@
-use bt_hci::cmd::le::{LeReadLocalSupportedFeatures, LeSetDataLength, LeSetPhy};
-use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
+use bt_hci::cmd::le::{LeReadLocalSupportedFeatures, LeSetDataLength};
 use embassy_futures::join::join;
 use embassy_time::{Duration, Instant, Timer};
 use trouble_host::prelude::*;
@@ -12,17 +11,14 @@ const CONNECTIONS_MAX: usize = 1;
 /// Max number of L2CAP channels.
 const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
 
-pub async fn run<C, P>(controller: C)
+pub async fn run<C, P, S>(controller: C, device: &S)
 where
-    C: Controller
-        + ControllerCmdSync<LeSetDataLength>
-        + ControllerCmdAsync<LeSetPhy>
-        + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    C: ControllerWithPhy + ControllerWithDle,
     P: PacketPool,
+    S: DeviceAddressSource + ?Sized,
 {
-    // Using a fixed "random" address can be useful for testing. In real scenarios, one would
-    // use e.g. the MAC 6 byte array as the address (how to get that varies by the platform).
-    let address: Address = Address::random([0xff, 0x8f, 0x1b, 0x05, 0xe4, 0xff]);
+    // Derive the address from the device identifier, so boards running the same example do not collide.
+    let address: Address = Address::from_device_id(device);
     info!("Our address = {:?}", address);
 
     let mut resources: HostResources<P, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX> = HostResources::new();
@@ -83,6 +79,7 @@ where
                 // Ensure there will be enough credits to send data throughout the entire connection event.
                 flow_policy: CreditFlowPolicy::Every(50),
                 initial_credits: Some(200),
+                ..Default::default()
             };
 
             let mut ch1 = L2capChannel::create(&stack, &conn, PSM_L2CAP_EXAMPLES, &l2cap_channel_config)
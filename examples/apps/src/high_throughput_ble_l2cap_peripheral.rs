@@ -17,7 +17,8 @@ where
     C: Controller + ControllerCmdSync<LeReadLocalSupportedFeatures>,
     P: PacketPool,
 {
-    // Hardcoded peripheral address
+    // The central examples connect to this fixed address. In real scenarios, derive the address from
+    // the device identifier with `Address::from_device_id`, as the central examples do.
     let address: Address = Address::random([0xff, 0x8f, 0x1a, 0x05, 0xe4, 0xff]);
     info!("Our address = {:?}", address);
 
@@ -73,6 +74,7 @@ where
                 // Ensure there will be enough credits to send data throughout the entire connection event.
                 flow_policy: CreditFlowPolicy::Every(50),
                 initial_credits: Some(200),
+                ..Default::default()
             };
 
             let mut ch1 = L2capChannel::accept(&stack, &conn, &[PSM_L2CAP_EXAMPLES], &l2cap_channel_config)
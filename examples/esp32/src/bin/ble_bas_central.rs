@@ -36,5 +36,8 @@ async fn main(_s: Spawner) {
     let connector = BleConnector::new(&init, bluetooth);
     let controller: ExternalController<_, 20> = ExternalController::new(connector);
 
-    ble_bas_central::run(controller).await;
+    // The factory MAC address in the efuse, most significant byte first.
+    let mut device_id = esp_hal::efuse::Efuse::read_base_mac_address();
+    device_id.reverse();
+    ble_bas_central::run(controller, &device_id).await;
 }
@@ -0,0 +1,73 @@
+//! Host and controller on different cores of the ESP32-S3.
+//!
+//! The PRO core drives an HCI controller attached to UART1 (TX on GPIO17, RX on GPIO18), for example an nRF52
+//! running the Zephyr `hci_uart` sample. The APP core runs the host, exchanging HCI packets with the PRO core
+//! over channels.
+#![no_std]
+#![no_main]
+
+use core::ptr::addr_of_mut;
+
+use embassy_executor::Spawner;
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use esp_hal::clock::CpuClock;
+use esp_hal::system::{CpuControl, Stack};
+use esp_hal::timer::systimer::SystemTimer;
+use esp_hal::uart::{self, Uart};
+use esp_hal_embassy::Executor;
+use static_cell::StaticCell;
+use trouble_example_apps::ble_bas_peripheral;
+use trouble_host::prelude::ExternalController;
+use trouble_host::split::{self, ChannelLink, HciMessage, SplitTransport};
+use {esp_alloc as _, esp_backtrace as _};
+
+/// Largest HCI packet exchanged between the cores, after the packet indicator.
+const HCI_SIZE: usize = 259;
+
+type Queue = Channel<CriticalSectionRawMutex, HciMessage<HCI_SIZE>, 4>;
+type Link = ChannelLink<'static, CriticalSectionRawMutex, HCI_SIZE, 4>;
+
+static TO_CONTROLLER: Queue = Channel::new();
+static TO_HOST: Queue = Channel::new();
+
+static mut APP_CORE_STACK: Stack<16384> = Stack::new();
+
+#[embassy_executor::task]
+async fn host_task(link: Link) {
+    let controller: ExternalController<_, 20> = ExternalController::new(SplitTransport::new(link));
+    ble_bas_peripheral::run(controller).await;
+}
+
+#[esp_hal_embassy::main]
+async fn main(_s: Spawner) {
+    esp_println::logger::init_logger_from_env();
+    let peripherals = esp_hal::init(esp_hal::Config::default().with_cpu_clock(CpuClock::max()));
+    esp_alloc::heap_allocator!(size: 72 * 1024);
+    let systimer = SystemTimer::new(peripherals.SYSTIMER);
+    esp_hal_embassy::init(systimer.alarm0);
+
+    let (host, controller) = ChannelLink::pair(&TO_CONTROLLER, &TO_HOST);
+
+    let mut cpu_control = CpuControl::new(peripherals.CPU_CTRL);
+    let _guard = cpu_control
+        .start_app_core(unsafe { &mut *addr_of_mut!(APP_CORE_STACK) }, move || {
+            static EXECUTOR: StaticCell<Executor> = StaticCell::new();
+            let executor = EXECUTOR.init(Executor::new());
+            executor.run(|spawner| {
+                spawner.must_spawn(host_task(host));
+            });
+        })
+        .unwrap();
+
+    let uart = Uart::new(peripherals.UART1, uart::Config::default().with_baudrate(1_000_000))
+        .unwrap()
+        .with_tx(peripherals.GPIO17)
+        .with_rx(peripherals.GPIO18)
+        .into_async();
+    let (rx, tx) = uart.split();
+
+    if let Err(e) = split::run_controller(&controller, rx, tx).await {
+        esp_println::println!("controller stream failed: {:?}", e);
+    }
+}
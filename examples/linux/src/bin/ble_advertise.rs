@@ -14,6 +14,6 @@ async fn main() -> Result<(), std::io::Error> {
     };
     let transport = Transport::new(dev)?;
     let controller = ExternalController::<_, 8>::new(transport);
-    ble_advertise::run(controller).await;
+    ble_advertise::run(controller, &rand::random::<[u8; 6]>()).await;
     Ok(())
 }
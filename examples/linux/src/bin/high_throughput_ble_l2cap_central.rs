@@ -14,6 +14,6 @@ async fn main() -> Result<(), std::io::Error> {
     };
     let transport = Transport::new(dev)?;
     let controller = ExternalController::<_, 8>::new(transport);
-    high_throughput_ble_l2cap_central::run::<_, BigAlloc>(controller).await;
+    high_throughput_ble_l2cap_central::run::<_, BigAlloc, _>(controller, &rand::random::<[u8; 6]>()).await;
     Ok(())
 }
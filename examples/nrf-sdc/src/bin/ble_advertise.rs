@@ -10,6 +10,7 @@ use nrf_sdc::mpsl::MultiprotocolServiceLayer;
 use nrf_sdc::{self as sdc, mpsl};
 use static_cell::StaticCell;
 use trouble_example_apps::ble_advertise;
+use trouble_nrf_sdc_examples::Ficr;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -59,5 +60,5 @@ async fn main(spawner: Spawner) {
     let mut sdc_mem = sdc::Mem::<1112>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
 
-    ble_advertise::run(sdc).await;
+    ble_advertise::run(sdc, &Ficr).await;
 }
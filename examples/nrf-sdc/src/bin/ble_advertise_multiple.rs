@@ -10,6 +10,7 @@ use nrf_sdc::mpsl::MultiprotocolServiceLayer;
 use nrf_sdc::{self as sdc, mpsl};
 use static_cell::StaticCell;
 use trouble_example_apps::ble_advertise_multiple;
+use trouble_nrf_sdc_examples::Ficr;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -65,5 +66,5 @@ async fn main(spawner: Spawner) {
     let mut sdc_mem = sdc::Mem::<12848>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
 
-    ble_advertise_multiple::run(sdc).await;
+    ble_advertise_multiple::run(sdc, &Ficr).await;
 }
@@ -11,6 +11,7 @@ use nrf_sdc::{self as sdc, mpsl};
 use static_cell::StaticCell;
 use trouble_example_apps::ble_bas_central;
 use trouble_host::prelude::*;
+use trouble_nrf_sdc_examples::Ficr;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -77,5 +78,5 @@ async fn main(spawner: Spawner) {
     let mut sdc_mem = sdc::Mem::<4864>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
 
-    ble_bas_central::run(sdc).await;
+    ble_bas_central::run(sdc, &Ficr).await;
 }
@@ -12,6 +12,7 @@ use rand_chacha::ChaCha12Rng;
 use rand_core::SeedableRng;
 use static_cell::StaticCell;
 use trouble_example_apps::ble_bas_central_sec;
+use trouble_nrf_sdc_examples::Ficr;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -77,5 +78,5 @@ async fn main(spawner: Spawner) {
     let mut sdc_mem = sdc::Mem::<6544>::new();
     let sdc = unwrap!(build_sdc(sdc_p, &mut rng, mpsl, &mut sdc_mem));
 
-    ble_bas_central_sec::run(sdc, &mut rng_2).await;
+    ble_bas_central_sec::run(sdc, &Ficr, &mut rng_2).await;
 }
@@ -12,6 +12,7 @@ use nrf_sdc::{self as sdc, mpsl};
 use static_cell::StaticCell;
 use trouble_example_apps::ble_l2cap_central;
 use trouble_host::prelude::*;
+use trouble_nrf_sdc_examples::Ficr;
 use {defmt_rtt as _, panic_probe as _};
 
 bind_interrupts!(struct Irqs {
@@ -80,5 +81,5 @@ async fn main(spawner: Spawner) {
 
     Timer::after(Duration::from_millis(200)).await;
 
-    ble_l2cap_central::run(sdc).await;
+    ble_l2cap_central::run(sdc, &Ficr).await;
 }
@@ -0,0 +1,18 @@
+#![no_std]
+
+use embassy_nrf::pac;
+use trouble_host::prelude::DeviceAddressSource;
+
+/// The device address programmed in the factory information configuration registers (FICR).
+pub struct Ficr;
+
+impl DeviceAddressSource for Ficr {
+    fn device_id(&self) -> [u8; 6] {
+        let low = pac::FICR.deviceaddr(0).read();
+        let high = pac::FICR.deviceaddr(1).read() as u16;
+        let mut id = [0; 6];
+        id[..4].copy_from_slice(&low.to_le_bytes());
+        id[4..].copy_from_slice(&high.to_le_bytes());
+        id
+    }
+}
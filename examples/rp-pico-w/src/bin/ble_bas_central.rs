@@ -62,8 +62,11 @@ async fn main(spawner: Spawner) {
     let (_net_device, bt_device, mut control, runner) = cyw43::new_with_bluetooth(state, pwr, spi, fw, btfw).await;
     unwrap!(spawner.spawn(cyw43_task(runner)));
     control.init(clm).await;
+    // The MAC address of the radio, most significant byte first.
+    let mut device_id = control.address().await;
+    device_id.reverse();
 
     let controller: ExternalController<_, 10> = ExternalController::new(bt_device);
 
-    ble_bas_central::run(controller).await;
+    ble_bas_central::run(controller, &device_id).await;
 }
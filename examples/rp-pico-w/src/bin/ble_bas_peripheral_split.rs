@@ -0,0 +1,83 @@
+//! Host and controller on different cores of the RP2040.
+//!
+//! Core 0 drives an HCI controller attached to UART0 (TX on GPIO0, RX on GPIO1), for example an nRF52 running
+//! the Zephyr `hci_uart` sample. Core 1 runs the host, exchanging HCI packets with core 0 over channels.
+#![no_std]
+#![no_main]
+
+use defmt::*;
+use embassy_executor::Executor;
+use embassy_rp::bind_interrupts;
+use embassy_rp::multicore::{spawn_core1, Stack};
+use embassy_rp::peripherals::UART0;
+use embassy_rp::uart::{BufferedInterruptHandler, BufferedUart, BufferedUartRx, BufferedUartTx, Config};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::channel::Channel;
+use static_cell::StaticCell;
+use trouble_example_apps::ble_bas_peripheral;
+use trouble_host::prelude::ExternalController;
+use trouble_host::split::{self, ChannelLink, HciMessage, SplitTransport};
+use {defmt_rtt as _, embassy_time as _, panic_probe as _};
+
+bind_interrupts!(struct Irqs {
+    UART0_IRQ => BufferedInterruptHandler<UART0>;
+});
+
+/// Largest HCI packet exchanged between the cores, after the packet indicator.
+const HCI_SIZE: usize = 259;
+
+type Queue = Channel<CriticalSectionRawMutex, HciMessage<HCI_SIZE>, 4>;
+type Link = ChannelLink<'static, CriticalSectionRawMutex, HCI_SIZE, 4>;
+
+static TO_CONTROLLER: Queue = Channel::new();
+static TO_HOST: Queue = Channel::new();
+
+static mut CORE1_STACK: Stack<16384> = Stack::new();
+static EXECUTOR0: StaticCell<Executor> = StaticCell::new();
+static EXECUTOR1: StaticCell<Executor> = StaticCell::new();
+
+#[embassy_executor::task]
+async fn controller_task(link: Link, rx: BufferedUartRx, tx: BufferedUartTx) {
+    if let Err(e) = split::run_controller(&link, rx, tx).await {
+        error!("controller stream failed: {:?}", e);
+    }
+}
+
+#[embassy_executor::task]
+async fn host_task(link: Link) {
+    let controller: ExternalController<_, 10> = ExternalController::new(SplitTransport::new(link));
+    ble_bas_peripheral::run(controller).await;
+}
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let p = embassy_rp::init(Default::default());
+    let (host, controller) = ChannelLink::pair(&TO_CONTROLLER, &TO_HOST);
+
+    spawn_core1(
+        p.CORE1,
+        unsafe { &mut *core::ptr::addr_of_mut!(CORE1_STACK) },
+        move || {
+            let executor1 = EXECUTOR1.init(Executor::new());
+            executor1.run(|spawner| unwrap!(spawner.spawn(host_task(host))));
+        },
+    );
+
+    static TX_BUF: StaticCell<[u8; 512]> = StaticCell::new();
+    static RX_BUF: StaticCell<[u8; 512]> = StaticCell::new();
+    let mut config = Config::default();
+    config.baudrate = 1_000_000;
+    let uart = BufferedUart::new(
+        p.UART0,
+        p.PIN_0,
+        p.PIN_1,
+        Irqs,
+        TX_BUF.init([0; 512]),
+        RX_BUF.init([0; 512]),
+        config,
+    );
+    let (tx, rx) = uart.split();
+
+    let executor0 = EXECUTOR0.init(Executor::new());
+    executor0.run(|spawner| unwrap!(spawner.spawn(controller_task(controller, rx, tx))));
+}
@@ -0,0 +1,36 @@
+// Use with a serial HCI exposed over TCP by hci_tcp_bridge, or any controller speaking HCI over TCP
+use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+use log::*;
+use tokio::net::TcpStream;
+use trouble_example_apps::ble_bas_peripheral;
+use trouble_host::prelude::{ExternalController, SerialTransport};
+
+#[tokio::main]
+async fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Trace)
+        .format_timestamp_nanos()
+        .init();
+
+    if std::env::args().len() != 2 {
+        println!("Provide the address of the controller as the one and only command line argument, e.g. 192.168.1.2:9000");
+        return;
+    }
+
+    let args: Vec<String> = std::env::args().collect();
+
+    let socket = TcpStream::connect(args[1].as_str()).await.unwrap();
+    // HCI packets are small and latency sensitive
+    socket.set_nodelay(true).unwrap();
+    info!("Connected to {}", args[1]);
+
+    let (reader, writer) = socket.into_split();
+
+    let reader = embedded_io_adapters::tokio_1::FromTokio::new(reader);
+    let writer = embedded_io_adapters::tokio_1::FromTokio::new(writer);
+
+    let driver: SerialTransport<NoopRawMutex, _, _> = SerialTransport::new(reader, writer);
+    let controller: ExternalController<_, 10> = ExternalController::new(driver);
+
+    ble_bas_peripheral::run(controller).await;
+}
@@ -50,5 +50,5 @@ async fn main() {
     let driver: SerialTransport<NoopRawMutex, _, _> = SerialTransport::new(reader, writer);
     let controller: ExternalController<_, 10> = ExternalController::new(driver);
 
-    ble_l2cap_central::run(controller).await;
+    ble_l2cap_central::run(controller, &rand::random::<[u8; 6]>()).await;
 }
@@ -0,0 +1,56 @@
+// Expose a serial HCI controller over TCP, so that a host on another machine can use it.
+use log::*;
+use tokio::net::TcpListener;
+use tokio::time::Duration;
+use tokio_serial::{DataBits, Parity, SerialStream, StopBits};
+
+#[tokio::main]
+async fn main() {
+    env_logger::builder()
+        .filter_level(log::LevelFilter::Info)
+        .format_timestamp_nanos()
+        .init();
+
+    let baudrate = 1000000;
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 3 {
+        println!("Provide the serial port and the address to listen on, e.g. /dev/ttyACM0 0.0.0.0:9000");
+        return;
+    }
+
+    let listener = TcpListener::bind(args[2].as_str()).await.unwrap();
+    loop {
+        let mut port = SerialStream::open(
+            &tokio_serial::new(args[1].as_str(), baudrate)
+                .baud_rate(baudrate)
+                .data_bits(DataBits::Eight)
+                .parity(Parity::None)
+                .stop_bits(StopBits::One),
+        )
+        .unwrap();
+
+        // Drain input
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        loop {
+            let mut buf = [0; 1];
+            match port.try_read(&mut buf[..]) {
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                _ => {}
+            }
+        }
+
+        info!("Waiting for a host on {}", args[2]);
+        let (mut socket, peer) = listener.accept().await.unwrap();
+        socket.set_nodelay(true).unwrap();
+        info!("Host {} connected", peer);
+
+        // The host resets the controller when it starts, so the next host starts afresh
+        match tokio::io::copy_bidirectional(&mut socket, &mut port).await {
+            Ok((to_controller, to_host)) => {
+                info!("Host disconnected after {} bytes sent and {} received", to_controller, to_host)
+            }
+            Err(e) => warn!("Host disconnected: {:?}", e),
+        }
+    }
+}
@@ -52,5 +52,5 @@ async fn main() {
 
     // Setting the L2CAP MTU to be ten times the size of the PDU.
     // This size of the L2CAP MTU does not consume all the controller buffers.
-    high_throughput_ble_l2cap_central::run::<_, BigAlloc>(controller).await;
+    high_throughput_ble_l2cap_central::run::<_, BigAlloc, _>(controller, &rand::random::<[u8; 6]>()).await;
 }
@@ -42,6 +42,8 @@ pub struct AccessArgs {
     pub write: bool,
     /// If true, the characteristic can be written without a response.
     pub write_without_response: bool,
+    /// If true, the characteristic can be written with a signed write command.
+    pub signed_write: bool,
     /// If true, the characteristic can send notifications.
     pub notify: bool,
     /// If true, the characteristic can send indications.
@@ -72,6 +74,22 @@ pub struct CharacteristicArgs {
     pub uuid: TokenStream,
     /// Starting value for this characteristic.
     pub default_value: Option<syn::Expr>,
+    /// Function producing the starting value when the server is created (`fn() -> Result<T, E>`).
+    pub init_with: Option<syn::Expr>,
+    /// User provided storage for the characteristic value (`&'static mut [u8]`).
+    /// If not set, static storage sized for the value type is allocated.
+    pub store: Option<syn::Expr>,
+    /// Capacity of the static storage for the characteristic value, in bytes.
+    /// Values shorter than this are tracked by length, so reads return only the bytes written.
+    pub max_len: Option<syn::Expr>,
+    /// If true, notifications are sent ahead of bulk data.
+    pub latency_critical: bool,
+    /// If true, reading the value, or subscribing to it, requires an encrypted link.
+    pub read_encrypted: bool,
+    /// If true, writing the value requires an encrypted link.
+    pub write_encrypted: bool,
+    /// Number of copies of the characteristic, declared on an array field.
+    pub count: Option<usize>,
     /// Descriptors for the characteristic.
     /// Descriptors are optional and can be used to add additional metadata to the characteristic.
     /// Parsed in super::check_for_characteristic.
@@ -124,7 +142,15 @@ impl CharacteristicArgs {
         let mut notify: Option<bool> = None;
         let mut indicate: Option<bool> = None;
         let mut default_value: Option<syn::Expr> = None;
+        let mut init_with: Option<syn::Expr> = None;
+        let mut store: Option<syn::Expr> = None;
+        let mut max_len: Option<syn::Expr> = None;
         let mut write_without_response: Option<bool> = None;
+        let mut signed_write: Option<bool> = None;
+        let mut latency_critical: Option<bool> = None;
+        let mut read_encrypted: Option<bool> = None;
+        let mut write_encrypted: Option<bool> = None;
+        let mut count: Option<usize> = None;
         attribute.parse_nested_meta(|meta| {
             match meta.path.get_ident().ok_or(meta.error("no ident"))?.to_string().as_str() {
                 "uuid" => check_multi(&mut uuid, "uuid", &meta, parse_uuid(&meta)?)?,
@@ -133,18 +159,50 @@ impl CharacteristicArgs {
                 "notify" => check_multi(&mut notify, "notify", &meta, true)?,
                 "indicate" => check_multi(&mut indicate, "indicate", &meta, true)?,
                 "write_without_response" => check_multi(&mut write_without_response, "write_without_response", &meta, true)?,
+                "signed_write" => check_multi(&mut signed_write, "signed_write", &meta, true)?,
+                "latency_critical" => check_multi(&mut latency_critical, "latency_critical", &meta, true)?,
+                "read_encrypted" => check_multi(&mut read_encrypted, "read_encrypted", &meta, true)?,
+                "write_encrypted" => check_multi(&mut write_encrypted, "write_encrypted", &meta, true)?,
                 "value" => {
                     let value = meta
                         .value()
                         .map_err(|_| meta.error("'value' must be followed by '= [data]'.  i.e. value = \"42\""))?;
                     check_multi(&mut default_value, "value", &meta, value.parse()?)?
                 }
+                "init_with" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'init_with' must be followed by '= [function]'.  i.e. init_with = read_calibration"))?;
+                    check_multi(&mut init_with, "init_with", &meta, value.parse()?)?
+                }
+                "store" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'store' must be followed by '= [buffer]'.  i.e. store = BUFFER.init([0; 512])"))?;
+                    check_multi(&mut store, "store", &meta, value.parse()?)?
+                }
+                "max_len" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'max_len' must be followed by '= [number]'.  i.e. max_len = 64"))?;
+                    check_multi(&mut max_len, "max_len", &meta, value.parse()?)?
+                }
+                "count" => {
+                    let value = meta
+                        .value()
+                        .map_err(|_| meta.error("'count' must be followed by '= [number]'.  i.e. count = 4"))?;
+                    let value: usize = value.parse::<syn::LitInt>()?.base10_parse()?;
+                    if value == 0 {
+                        return Err(meta.error("'count' must be at least 1"));
+                    }
+                    check_multi(&mut count, "count", &meta, value)?
+                }
                 "default_value" => return Err(meta.error("Use 'value' for default value")),
                 "descriptor" => return Err(meta.error("Descriptors are added as separate tags i.e. #[descriptor(uuid = \"1234\", value = 42, read, write, notify, indicate)]")),
                 other => return Err(
                     meta.error(
                         format!(
-                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, notify, indicate, value\n"
+                            "Unsupported characteristic property: '{other}'.\nSupported properties are:\nuuid, read, write, write_without_response, signed_write, notify, indicate, value, init_with, store, max_len, latency_critical, read_encrypted, write_encrypted, count\n"
                         ))),
             };
             Ok(())
@@ -154,8 +212,16 @@ impl CharacteristicArgs {
             doc_string: String::new(),
             descriptors: Vec::new(),
             default_value,
+            init_with,
+            store,
+            max_len,
+            latency_critical: latency_critical.unwrap_or_default(),
+            read_encrypted: read_encrypted.unwrap_or_default(),
+            write_encrypted: write_encrypted.unwrap_or_default(),
+            count,
             access: AccessArgs {
                 write_without_response: write_without_response.unwrap_or_default(),
+                signed_write: signed_write.unwrap_or_default(),
                 indicate: indicate.unwrap_or_default(),
                 notify: notify.unwrap_or_default(),
                 write: write.unwrap_or_default(),
@@ -222,6 +288,7 @@ impl DescriptorArgs {
                 notify: false,   // not possible for descriptor
                 read: read.unwrap_or_default(),
                 write_without_response: false,
+                signed_write: false,
                 write: false,
             },
         })
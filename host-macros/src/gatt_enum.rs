@@ -0,0 +1,76 @@
+//! Gatt Enum Builder
+//!
+//! This module is responsible for generating the conversions of a `#[repr(u8)]` enum to and from GATT bytes,
+//! so the enum can be used as a characteristic value.
+//! Written values are converted with the enum's `TryFrom<u8>` implementation, and rejected if the conversion fails.
+
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{Error, Result};
+
+/// Construct the macro blueprint for the enum conversions.
+pub(crate) fn build(item: syn::DeriveInput) -> Result<TokenStream2> {
+    let name = &item.ident;
+    let syn::Data::Enum(data) = &item.data else {
+        return Err(Error::new(item.span(), "GattEnum can only be derived for enums"));
+    };
+    if !item.generics.params.is_empty() {
+        return Err(Error::new(
+            item.generics.span(),
+            "GattEnum cannot be derived for generic enums",
+        ));
+    }
+
+    let mut repr_u8 = false;
+    for attr in item.attrs.iter().filter(|attr| attr.path().is_ident("repr")) {
+        attr.parse_nested_meta(|meta| {
+            repr_u8 |= meta.path.is_ident("u8");
+            Ok(())
+        })?;
+    }
+    if !repr_u8 {
+        return Err(Error::new(name.span(), "GattEnum requires the enum to be #[repr(u8)]"));
+    }
+
+    let mut code_variants = TokenStream2::new();
+    for variant in &data.variants {
+        if !matches!(variant.fields, syn::Fields::Unit) {
+            return Err(Error::new(variant.span(), "GattEnum variants cannot have fields"));
+        }
+        let ident = &variant.ident;
+        code_variants.extend(quote! {
+            #name::#ident => {
+                const VALUE: u8 = #name::#ident as u8;
+                &[VALUE]
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl trouble_host::types::gatt_traits::AsGatt for #name {
+            const MIN_SIZE: usize = 1;
+            const MAX_SIZE: usize = 1;
+
+            fn as_gatt(&self) -> &[u8] {
+                match self {
+                    #code_variants
+                }
+            }
+
+            fn validate(data: &[u8]) -> Result<(), trouble_host::types::gatt_traits::FromGattError> {
+                <Self as trouble_host::types::gatt_traits::FromGatt>::from_gatt(data).map(|_| ())
+            }
+        }
+
+        impl trouble_host::types::gatt_traits::FromGatt for #name {
+            fn from_gatt(data: &[u8]) -> Result<Self, trouble_host::types::gatt_traits::FromGattError> {
+                match data {
+                    [value] => <Self as core::convert::TryFrom<u8>>::try_from(*value)
+                        .map_err(|_| trouble_host::types::gatt_traits::FromGattError::InvalidValue),
+                    _ => Err(trouble_host::types::gatt_traits::FromGattError::InvalidLength),
+                }
+            }
+        }
+    })
+}
@@ -7,6 +7,7 @@ extern crate proc_macro;
 
 mod characteristic;
 mod ctxt;
+mod gatt_enum;
 mod server;
 mod service;
 mod uuid;
@@ -56,6 +57,10 @@ pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
 
 /// Gatt Service attribute macro.
 ///
+/// The service declaration can be given a fixed handle with `handle_start = 0x100`, keeping its
+/// handles stable for clients that hardcode them. A `#[gatt_server]` fails to build if a fixed
+/// service overlaps the services declared before it.
+///
 /// # Example
 ///
 /// ```rust no_run
@@ -74,10 +79,37 @@ pub fn gatt_server(args: TokenStream, item: TokenStream) -> TokenStream {
 ///    #[characteristic(uuid = "2a28", read, write, notify, value = 42.0)]
 ///    /// Can be in any order
 ///    location: f32,
-///    #[characteristic(uuid = "2a39", write)]
+///    /// Signed writes are accepted from bonded peers that distributed a signing key
+///    #[characteristic(uuid = "2a39", write, signed_write)]
 ///    control: u8,
 ///    #[characteristic(uuid = "2a63", read, notify)]
 ///    energy_expended: u16,
+///    /// Notifications of latency critical characteristics are sent ahead of bulk data
+///    #[characteristic(uuid = "2a4d", read, notify, latency_critical)]
+///    report: [u8; 8],
+///    /// Access can require an encrypted link meeting the security policy of the stack
+///    #[characteristic(uuid = "2a2b", read, write, read_encrypted, write_encrypted)]
+///    settings: u16,
+///    /// Large values can use storage provided by the application instead of a static buffer. The array must hold
+///    /// the maximum size of the value, or `max_len` bytes, and is read in place with `Characteristic::with_value`
+///    #[characteristic(uuid = "2a64", write, store = CONFIG_STORE.init([0; 128]))]
+///    config: heapless::Vec<u8, 128>,
+///    /// Values without a bounded size get storage of `max_len` bytes, and are read back at their written length
+///    #[characteristic(uuid = "2a00", read, write, value = "trouble", max_len = 32)]
+///    name: &'static str,
+///    /// Repeated characteristics are declared on an array, with one handle per copy
+///    #[characteristic(uuid = "2a6e", read, notify, count = 4)]
+///    temperatures: [i16; 4],
+///    /// Values produced when the server is created, i.e. read from calibration data. If the function
+///    /// fails, creating the server fails with the name of the characteristic
+///    #[characteristic(uuid = "2a6f", read, init_with = read_offset)]
+///    offset: i16,
+/// }
+///
+/// static CONFIG_STORE: static_cell::StaticCell<[u8; 128]> = static_cell::StaticCell::new();
+///
+/// fn read_offset() -> Result<i16, ()> {
+///     Ok(-3)
 /// }
 /// ```
 #[proc_macro_attribute]
@@ -128,6 +160,52 @@ pub fn gatt_service(args: TokenStream, item: TokenStream) -> TokenStream {
     }
 }
 
+/// Gatt Enum derive macro.
+///
+/// Allows a `#[repr(u8)]` enum to be used as a characteristic value. Values written by a client are
+/// converted with the enum's `TryFrom<u8>` implementation, and rejected with `Value Not Allowed` if
+/// the conversion fails.
+///
+/// # Example
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+///
+/// #[derive(GattEnum, Default, Clone, Copy)]
+/// #[repr(u8)]
+/// enum Command {
+///     #[default]
+///     Stop = 0,
+///     Start = 1,
+/// }
+///
+/// impl TryFrom<u8> for Command {
+///     type Error = ();
+///
+///     fn try_from(value: u8) -> Result<Self, ()> {
+///         match value {
+///             0 => Ok(Command::Stop),
+///             1 => Ok(Command::Start),
+///             _ => Err(()),
+///         }
+///     }
+/// }
+///
+/// #[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b0")]
+/// struct ControlService {
+///    #[characteristic(uuid = "2a9f", write)]
+///    command: Command,
+/// }
+/// ```
+#[proc_macro_derive(GattEnum)]
+pub fn gatt_enum(item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as syn::DeriveInput);
+    match gatt_enum::build(item) {
+        Ok(result) => result.into(),
+        Err(e) => e.into_compile_error().into(),
+    }
+}
+
 /// Check if a field has a characteristic attribute and parse it.
 ///
 /// If so also check if that field has descriptors and/or docstrings.
@@ -226,6 +304,49 @@ fn check_for_characteristic(
             return REMOVE; // If there was an error parsing the characteristic, remove the field.
         }
     };
+    if let (Some(_), Some(init_with)) = (&args.default_value, &args.init_with) {
+        *err = Some(Error::new(
+            init_with.span(),
+            "'init_with' cannot be used with 'value', the starting value is produced by the function",
+        ));
+        return REMOVE;
+    }
+    if let (Some(_), Some(max_len)) = (&args.store, &args.max_len) {
+        *err = Some(Error::new(
+            max_len.span(),
+            "'max_len' cannot be used with 'store', the storage size is set by the provided buffer",
+        ));
+        return REMOVE;
+    }
+    if let Some(count) = args.count {
+        let result = match &field.ty {
+            syn::Type::Array(array) => match &array.len {
+                syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Int(len),
+                    ..
+                }) if len.base10_parse::<usize>().ok() != Some(count) => {
+                    Err(Error::new(array.len.span(), "array length must match 'count'"))
+                }
+                _ => Ok(()),
+            },
+            ty => Err(Error::new(ty.span(), "'count' requires an array field, i.e. [u16; 4]")),
+        }
+        .and_then(|_| match args.store {
+            Some(ref store) => Err(Error::new(
+                store.span(),
+                "'store' cannot be used with 'count', each copy needs its own storage",
+            )),
+            None => Ok(()),
+        })
+        .and_then(|_| match descriptors.iter().find_map(|d| d.name.as_ref()) {
+            Some(name) => Err(Error::new(name.span(), "named descriptors cannot be used with 'count'")),
+            None => Ok(()),
+        });
+        if let Err(e) = result {
+            *err = Some(e);
+            return REMOVE;
+        }
+    }
     args.doc_string = doc_string;
     args.descriptors = descriptors;
     characteristics.push(Characteristic::new(field, args));
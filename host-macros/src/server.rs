@@ -1,7 +1,8 @@
 //! Gatt Server Builder
 //!
 //! This module is responsible for generating the Gatt Server struct and its implementation.
-//! It should contain one or more Gatt Services, which are decorated with the `#[gatt_service(uuid = "...")]` attribute.
+//! It should contain one or more Gatt Services, which are decorated with the `#[gatt_service(uuid = "...")]` attribute,
+//! or implement `trouble_host::attribute::GattService` by hand.
 
 use darling::Error;
 use proc_macro2::TokenStream as TokenStream2;
@@ -102,43 +103,77 @@ impl ServerBuilder {
         let mut code_server_populate = TokenStream2::new();
         let mut code_attribute_summation = TokenStream2::new();
         let mut code_cccd_summation = TokenStream2::new();
+        let mut code_handle_check = TokenStream2::new();
         for service in &self.properties.fields {
             let vis = &service.vis;
             let service_span = service.span();
             let service_name = service.ident.as_ref().expect("All fields should have names");
             let service_type = &service.ty;
+            // Feature gated services are left out of the struct, its construction and the table sizes
+            let cfgs: Vec<_> = service
+                .attrs
+                .iter()
+                .filter(|attr| attr.path().is_ident("cfg"))
+                .collect();
+            let docs = service.attrs.iter().filter(|attr| attr.path().is_ident("doc"));
 
             code_service_definition.extend(quote_spanned! {service_span=>
+                #(#docs)*
+                #(#cfgs)*
                 #vis #service_name: #service_type,
             });
 
             code_service_init.extend(quote_spanned! {service_span=>
-                let #service_name = #service_type::new(&mut table);
+                #(#cfgs)*
+                let #service_name = <#service_type as trouble_host::attribute::GattService>::try_register(&mut table)?;
             });
 
             code_server_populate.extend(quote_spanned! {service_span=>
+                #(#cfgs)*
                 #service_name,
             });
 
             code_attribute_summation.extend(quote_spanned! {service_span=>
-               + #service_type::ATTRIBUTE_COUNT
+                #(#cfgs)*
+                let count = count + <#service_type as trouble_host::attribute::GattService>::ATTRIBUTE_COUNT;
             });
 
             code_cccd_summation.extend(quote_spanned! {service_span=>
-               + #service_type::CCCD_COUNT
-            })
+                #(#cfgs)*
+                let count = count + <#service_type as trouble_host::attribute::GattService>::CCCD_COUNT;
+            });
+
+            code_handle_check.extend(quote_spanned! {service_span=>
+                #(#cfgs)*
+                let next = trouble_host::attribute::next_service_handle(
+                    next,
+                    <#service_type as trouble_host::attribute::GattService>::HANDLE_START,
+                    <#service_type as trouble_host::attribute::GattService>::ATTRIBUTE_COUNT,
+                );
+            });
         }
+        let code_attribute_count = quote! {
+            {
+                let count = trouble_host::gap::GAP_SERVICE_ATTRIBUTE_COUNT;
+                #code_attribute_summation
+                count
+            }
+        };
 
         let attribute_table_size = if let Some(value) = self.arguments.attribute_table_size {
             value
         } else {
-            parse_quote!(trouble_host::gap::GAP_SERVICE_ATTRIBUTE_COUNT #code_attribute_summation)
+            parse_quote!(#code_attribute_count)
         };
 
         let cccd_table_size = if let Some(value) = self.arguments.cccd_table_size {
             value
         } else {
-            parse_quote!(0 #code_cccd_summation)
+            parse_quote!({
+                let count = 0;
+                #code_cccd_summation
+                count
+            })
         };
 
         let connections_max = if let Some(value) = self.arguments.connections_max {
@@ -149,11 +184,17 @@ impl ServerBuilder {
 
         quote! {
             const _ATTRIBUTE_TABLE_SIZE: usize = #attribute_table_size;
+            const _CCCD_TABLE_SIZE: usize = #cccd_table_size;
             // This pattern causes the assertion to happen at compile time
             const _: () = {
-                core::assert!(_ATTRIBUTE_TABLE_SIZE >= trouble_host::gap::GAP_SERVICE_ATTRIBUTE_COUNT #code_attribute_summation, "Specified attribute table size is insufficient. Please increase attribute_table_size or remove the argument entirely to allow automatic sizing of the attribute table.");
+                trouble_host::attribute::assert_table_size("attribute_table_size", _ATTRIBUTE_TABLE_SIZE, #code_attribute_count, "attributes");
+            };
+            // Fixed service handles must not overlap the services before them, including the GAP service
+            const _: () = {
+                let next = trouble_host::gap::GAP_SERVICE_HANDLE_END;
+                #code_handle_check
+                let _ = next;
             };
-            const _CCCD_TABLE_SIZE: usize = #cccd_table_size;
             const _CONNECTIONS_MAX: usize = #connections_max;
 
             #visibility struct #name<'values>
@@ -167,20 +208,21 @@ impl ServerBuilder {
                 /// Create a new Gatt Server instance.
                 ///
                 /// Requires you to add your own GAP Service.  Use `new_default(name)` or `new_with_config(name, gap_config)` if you want to add a GAP Service.
-                #visibility fn new(mut table: trouble_host::attribute::AttributeTable<'values, #mutex_type, _ATTRIBUTE_TABLE_SIZE>) -> Self {
+                /// If the `init_with` function of a characteristic fails, Err() names the characteristic.
+                #visibility fn new(mut table: trouble_host::attribute::AttributeTable<'values, #mutex_type, _ATTRIBUTE_TABLE_SIZE>) -> Result<Self, &'static str> {
 
                     #code_service_init
 
-                    Self {
+                    Ok(Self {
                         server: trouble_host::prelude::AttributeServer::new(table),
                         #code_server_populate
-                    }
+                    })
                 }
                 /// Create a new Gatt Server instance.
                 ///
                 /// This function will add a Generic GAP Service with the given name.
                 /// The maximum length which the name can be is 22 bytes (limited by the size of the advertising packet).
-                /// If a name longer than this is passed, Err() is returned.
+                /// If a name longer than this is passed, or the `init_with` function of a characteristic fails, Err() is returned.
                 #visibility fn new_default(name: &'values str) -> Result<Self, &'static str> {
                     let mut table: trouble_host::attribute::AttributeTable<'_, #mutex_type, _ATTRIBUTE_TABLE_SIZE> = trouble_host::attribute::AttributeTable::new();
 
@@ -198,7 +240,7 @@ impl ServerBuilder {
                 ///
                 /// This function will add a GAP Service.
                 /// The maximum length which the device name can be is 22 bytes (limited by the size of the advertising packet).
-                /// If a name longer than this is passed, Err() is returned.
+                /// If a name longer than this is passed, or the `init_with` function of a characteristic fails, Err() is returned.
                 #visibility fn new_with_config(gap: trouble_host::gap::GapConfig<'values>) -> Result<Self, &'static str> {
                     let mut table: trouble_host::attribute::AttributeTable<'_, #mutex_type, _ATTRIBUTE_TABLE_SIZE> = trouble_host::attribute::AttributeTable::new();
 
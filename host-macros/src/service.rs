@@ -18,11 +18,13 @@ use crate::uuid::Uuid;
 #[derive(Debug)]
 pub(crate) struct ServiceArgs {
     pub uuid: TokenStream2,
+    /// Fixed handle of the service declaration.
+    pub handle_start: Option<syn::Expr>,
 }
 
 /// Parse the UUID argument of the service attribute.
 ///
-/// The UUID can be specified as a string literal, an integer literal, or an expression that impl Into<Uuid>.
+/// The UUID can be specified as a string literal, an integer literal of up to 128 bits, or an expression that impl Into<Uuid>.
 fn parse_arg_uuid(value: &Expr) -> Result<TokenStream2> {
     match value {
         Expr::Lit(lit) => {
@@ -35,9 +37,20 @@ fn parse_arg_uuid(value: &Expr) -> Result<TokenStream2> {
                 })?;
                 Ok(quote::quote! {#uuid_string})
             } else if let syn::Lit::Int(lit_int) = &lit.lit {
-                let uuid_string = Uuid::Uuid16(lit_int.base10_parse::<u16>().map_err(|_| {
-                    Error::custom("Invalid 16bit UUID literal.  Expect i.e. \"0x180f\"").with_span(&lit.span())
-                })?);
+                let uuid_string = if let Ok(u) = lit_int.base10_parse::<u16>() {
+                    Uuid::Uuid16(u)
+                } else if let Ok(u) = lit_int.base10_parse::<u32>() {
+                    Uuid::Uuid32(u)
+                } else {
+                    Uuid::Uuid128(
+                        lit_int
+                            .base10_parse::<u128>()
+                            .map_err(|_| {
+                                Error::custom("Invalid UUID literal.  Expect i.e. \"0x180f\"").with_span(&lit.span())
+                            })?
+                            .to_le_bytes(),
+                    )
+                };
                 Ok(quote::quote! {#uuid_string})
             } else {
                 Err(Error::custom(
@@ -62,6 +75,7 @@ fn parse_arg_uuid(value: &Expr) -> Result<TokenStream2> {
 impl syn::parse::Parse for ServiceArgs {
     fn parse(input: syn::parse::ParseStream) -> Result<Self> {
         let mut uuid: Option<_> = None;
+        let mut handle_start: Option<syn::Expr> = None;
 
         while !input.is_empty() {
             let meta = input.parse()?;
@@ -83,9 +97,17 @@ impl syn::parse::Parse for ServiceArgs {
                             }
                             uuid = Some(parse_arg_uuid(&name_value.value)?);
                         }
+                        "handle_start" => {
+                            if handle_start.is_some() {
+                                return Err(Error::custom("handle_start cannot be specified more than once")
+                                    .with_span(&name_value.span())
+                                    .into());
+                            }
+                            handle_start = Some(name_value.value.clone());
+                        }
                         other => {
                             return Err(Error::unknown_field(&format!(
-                                "Unsupported service property: '{other}'.\nSupported properties are: uuid"
+                                "Unsupported service property: '{other}'.\nSupported properties are: uuid, handle_start"
                             ))
                             .with_span(&name_value.span())
                             .into())
@@ -101,6 +123,7 @@ impl syn::parse::Parse for ServiceArgs {
             uuid: uuid.ok_or(Error::custom(
                 "Service must have a UUID (i.e. `#[gatt_service(uuid = '1234')]` or `#[gatt_service(uuid = service::BATTERY)]`)",
             ))?,
+            handle_start,
         })
     }
 }
@@ -110,6 +133,8 @@ pub(crate) struct ServiceBuilder {
     args: ServiceArgs,
     attribute_count: usize,
     cccd_count: usize,
+    /// Set if a characteristic value is produced by an `init_with` function, which can fail.
+    fallible: bool,
     code_impl: TokenStream2,
     code_build_chars: TokenStream2,
     code_struct_init: TokenStream2,
@@ -123,6 +148,7 @@ impl ServiceBuilder {
             args,
             attribute_count: 1, // Service counts as an attribute
             cccd_count: 0,
+            fallible: false,
             code_struct_init: TokenStream2::new(),
             code_impl: TokenStream2::new(),
             code_fields: TokenStream2::new(),
@@ -156,8 +182,59 @@ impl ServiceBuilder {
         let fields = self.code_fields;
         let code_build_chars = self.code_build_chars;
         let uuid = self.args.uuid;
+        let (add_service, handle_start) = match self.args.handle_start {
+            Some(handle_start) => (
+                quote! {
+                    table
+                        .add_service_at(trouble_host::attribute::Service::new(#uuid), #handle_start)
+                        .expect("handle_start overlaps a previous service")
+                },
+                quote! { Some(#handle_start) },
+            ),
+            None => (
+                quote! { table.add_service(trouble_host::attribute::Service::new(#uuid)) },
+                quote! { None },
+            ),
+        };
         let attribute_count = self.attribute_count;
         let cccd_count = self.cccd_count;
+        let (new_result, new_ok, code_register) = if self.fallible {
+            (
+                quote! { Result<Self, &'static str> },
+                quote! { Ok },
+                quote! {
+                    fn register<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Self
+                    where
+                        M: embassy_sync::blocking_mutex::raw::RawMutex,
+                    {
+                        match Self::new(table) {
+                            Ok(service) => service,
+                            Err(e) => panic!("{}", e),
+                        }
+                    }
+
+                    fn try_register<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Result<Self, &'static str>
+                    where
+                        M: embassy_sync::blocking_mutex::raw::RawMutex,
+                    {
+                        Self::new(table)
+                    }
+                },
+            )
+        } else {
+            (
+                quote! { Self },
+                TokenStream2::new(),
+                quote! {
+                    fn register<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Self
+                    where
+                        M: embassy_sync::blocking_mutex::raw::RawMutex,
+                    {
+                        Self::new(table)
+                    }
+                },
+            )
+        };
         quote! {
             #visibility struct #struct_name {
                 #fields
@@ -169,49 +246,152 @@ impl ServiceBuilder {
                 #visibility const ATTRIBUTE_COUNT: usize = #attribute_count;
                 #visibility const CCCD_COUNT: usize = #cccd_count;
 
-                #visibility fn new<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> Self
+                #visibility fn new<M, const MAX_ATTRIBUTES: usize>(table: &mut trouble_host::attribute::AttributeTable<'_, M, MAX_ATTRIBUTES>) -> #new_result
                 where
                     M: embassy_sync::blocking_mutex::raw::RawMutex,
                 {
-                    let mut service = table.add_service(trouble_host::attribute::Service::new(#uuid));
+                    let mut service = #add_service;
                     #code_build_chars
 
-                    Self {
+                    #new_ok(Self {
                         handle: service.build(),
                         #code_struct_init
-                    }
+                    })
                 }
                 #code_impl
             }
+
+            impl trouble_host::attribute::GattService for #struct_name {
+                const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+                const CCCD_COUNT: usize = Self::CCCD_COUNT;
+                const HANDLE_START: Option<u16> = #handle_start;
+
+                #code_register
+            }
         }
     }
 
     /// Construct instructions for adding a characteristic to the service, with static storage.
     fn construct_characteristic_static(&mut self, characteristic: Characteristic) {
-        let (code_descriptors, named_descriptors) = self.build_descriptors(&characteristic);
         let name_screaming = format_ident!("{}", characteristic.name.as_str().to_case(Case::Constant));
         let char_name = format_ident!("{}", characteristic.name);
-        let ty = characteristic.ty;
+        // Repeated characteristics are declared on an array of the value type
+        let ty = match (&characteristic.args.count, &characteristic.ty) {
+            (Some(_), syn::Type::Array(array)) => array.elem.as_ref().clone(),
+            _ => characteristic.ty.clone(),
+        };
         let access = &characteristic.args.access;
         let properties = set_access_properties(access);
-        let uuid = characteristic.args.uuid;
-        let default_value = match characteristic.args.default_value {
-            Some(val) => quote!(#val),                                       // if set by user
-            None => quote_spanned!(characteristic.span => <#ty>::default()), // or default otherwise
+        let uuid = &characteristic.args.uuid;
+        let default_value = match (&characteristic.args.default_value, &characteristic.args.init_with) {
+            (Some(val), _) => quote!(#val), // if set by user
+            // produced when the service is created, failing with the name of the characteristic
+            (None, Some(init_with)) => {
+                self.fallible = true;
+                let error = format!(
+                    "failed to initialize characteristic '{}::{}'",
+                    self.properties.ident, characteristic.name
+                );
+                quote_spanned! {init_with.span()=>
+                    {
+                        let value: Result<#ty, _> = (#init_with)();
+                        match value {
+                            Ok(value) => value,
+                            Err(_) => return Err(#error),
+                        }
+                    }
+                }
+            }
+            (None, None) => quote_spanned!(characteristic.span => <#ty>::default()), // or default otherwise
         };
 
-        self.code_build_chars.extend(quote_spanned! {characteristic.span=>
-            let (#char_name, #(#named_descriptors),*) = {
-                static #name_screaming: static_cell::StaticCell<[u8; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]> = static_cell::StaticCell::new();
-                let store = #name_screaming.init([0; <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE]);
-                let mut builder = service
-                    .add_characteristic(#uuid, &[#(#properties),*], #default_value, store);
-                #code_descriptors
+        let size = match &characteristic.args.max_len {
+            // storage sized by the user, i.e. for values without a bounded size such as `&'static str`
+            Some(max_len) => quote_spanned! {max_len.span()=>
+                {
+                    const MAX_LEN: usize = #max_len;
+                    #[allow(clippy::absurd_extreme_comparisons)]
+                    const _: () = assert!(
+                        MAX_LEN >= <#ty as trouble_host::types::gatt_traits::AsGatt>::MIN_SIZE,
+                        "'max_len' is smaller than the minimum size of the value type"
+                    );
+                    MAX_LEN
+                }
+            },
+            None => quote_spanned! {characteristic.span=>
+                <#ty as trouble_host::types::gatt_traits::AsGatt>::MAX_SIZE
+            },
+        };
+        let store = match &characteristic.args.store {
+            // borrowed storage provided by the user, i.e. shared with application state
+            Some(store) => quote_spanned! {store.span()=>
+                let store: &'static mut [u8] = trouble_host::attribute::StoreCheck::<{ #size }>::check(#store);
+            },
+            None => quote_spanned! {characteristic.span=>
+                static #name_screaming: static_cell::StaticCell<[u8; #size]> = static_cell::StaticCell::new();
+                let store = #name_screaming.init([0; #size]);
+            },
+        };
+
+        let latency_critical = characteristic.args.latency_critical.then(|| {
+            quote_spanned! {characteristic.span=>
+                builder = builder.latency_critical();
+            }
+        });
 
-                (builder.build(), #(#named_descriptors),*)
+        let args = &characteristic.args;
+        let permissions = (args.read_encrypted || args.write_encrypted).then(|| {
+            let security = |encrypted: bool| match encrypted {
+                true => quote!(trouble_host::attribute::AttributeSecurity::Encrypted),
+                false => quote!(trouble_host::attribute::AttributeSecurity::None),
             };
+            let (read, write) = (security(args.read_encrypted), security(args.write_encrypted));
+            quote_spanned! {characteristic.span=>
+                builder = builder.permissions(trouble_host::attribute::AttributePermissions {
+                    read: #read,
+                    write: #write,
+                });
+            }
         });
 
+        if let Some(count) = characteristic.args.count {
+            // Each copy is built in its own block, so it gets its own static storage
+            let copies: Vec<TokenStream2> = (0..count)
+                .map(|_| {
+                    let (code_descriptors, _) = self.build_descriptors(&characteristic);
+                    quote_spanned! {characteristic.span=>
+                        {
+                            #store
+                            let mut builder = service
+                                .add_characteristic(#uuid, &[#(#properties),*], #default_value, store);
+                            #latency_critical
+                            #permissions
+                            #code_descriptors
+
+                            builder.build()
+                        }
+                    }
+                })
+                .collect();
+            self.code_build_chars.extend(quote_spanned! {characteristic.span=>
+                let #char_name = [#(#copies),*];
+            });
+        } else {
+            let (code_descriptors, named_descriptors) = self.build_descriptors(&characteristic);
+            self.code_build_chars.extend(quote_spanned! {characteristic.span=>
+                let (#char_name, #(#named_descriptors),*) = {
+                    #store
+                    let mut builder = service
+                        .add_characteristic(#uuid, &[#(#properties),*], #default_value, store);
+                    #latency_critical
+                    #permissions
+                    #code_descriptors
+
+                    (builder.build(), #(#named_descriptors),*)
+                };
+            });
+        }
+
         self.code_struct_init.extend(quote_spanned!(characteristic.span=>
             #char_name,
         ));
@@ -238,11 +418,17 @@ impl ServiceBuilder {
         // Process characteristic fields
         for ch in characteristics {
             let char_name = format_ident!("{}", ch.name);
-            let ty = &ch.ty;
             // add fields for each characteristic value handle
+            let ty = match (&ch.args.count, &ch.ty) {
+                (Some(_), syn::Type::Array(array)) => {
+                    let (elem, len) = (&array.elem, &array.len);
+                    quote!([trouble_host::attribute::Characteristic<#elem>; #len])
+                }
+                (_, ty) => quote!(trouble_host::attribute::Characteristic<#ty>),
+            };
             fields.push(syn::Field {
                 ident: Some(char_name.clone()),
-                ty: syn::Type::Verbatim(quote!(trouble_host::attribute::Characteristic<#ty>)),
+                ty: syn::Type::Verbatim(ty),
                 attrs: Vec::new(),
                 colon_token: Default::default(),
                 vis: ch.vis.clone(),
@@ -250,7 +436,9 @@ impl ServiceBuilder {
             });
             doc_strings.push(ch.args.doc_string.to_owned());
 
-            self.increment_attributes(&ch.args.access);
+            for _ in 0..ch.args.count.unwrap_or(1) {
+                self.increment_attributes(&ch.args.access);
+            }
 
             self.construct_characteristic_static(ch);
         }
@@ -359,6 +547,11 @@ fn set_access_properties(args: &AccessArgs) -> Vec<TokenStream2> {
         quote! {trouble_host::attribute::CharacteristicProp::WriteWithoutResponse},
         &mut properties,
     );
+    parse_property_into_list(
+        args.signed_write,
+        quote! {trouble_host::attribute::CharacteristicProp::AuthenticatedWrite},
+        &mut properties,
+    );
     parse_property_into_list(
         args.notify,
         quote! {trouble_host::attribute::CharacteristicProp::Notify},
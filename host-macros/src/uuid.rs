@@ -11,6 +11,7 @@ use quote::quote;
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum Uuid {
     Uuid16(u16),
+    Uuid32(u32),
     Uuid128([u8; 16]),
 }
 
@@ -28,8 +29,14 @@ impl FromMeta for Uuid {
             }
         }
 
+        if value.len() == 8 {
+            if let Ok(u) = u32::from_str_radix(value, 16) {
+                return Ok(Uuid::Uuid32(u));
+            }
+        }
+
         Err(darling::Error::custom(
-            "Invalid UUID (must be a 16-bit or 128-bit UUID)",
+            "Invalid UUID (must be a 16-bit, 32-bit or 128-bit UUID)",
         ))
     }
 }
@@ -38,6 +45,7 @@ impl quote::ToTokens for Uuid {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
         match self {
             Uuid::Uuid16(u) => tokens.extend(quote!(::trouble_host::types::uuid::Uuid::new_short(#u))),
+            Uuid::Uuid32(u) => tokens.extend(quote!(::trouble_host::types::uuid::Uuid::from_u32(#u))),
             Uuid::Uuid128(u) => {
                 let mut s = TokenStream2::new();
                 for b in u {
@@ -13,6 +13,8 @@ static CONFIGS: &[(&str, usize)] = &[
     ("DEFAULT_PACKET_POOL_MTU", 251),
     ("GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS", 1),
     ("GATT_CLIENT_NOTIFICATION_QUEUE_SIZE", 1),
+    ("GATT_WRITE_QUEUE_SIZE", 0),
+    ("GATT_PREPARE_QUEUE_SIZE", 512),
     // END AUTOGENERATED CONFIG FEATURES
 ];
 
@@ -58,6 +58,12 @@ feature("gatt_client_notification_max_subscribers",
 feature("gatt_client_notification_queue_size",
         "When using the GATT client, this controls how many notifications can be queued for each subscriber.",
         default=1, min=1, max=512, pow2=True)
+feature("gatt_write_queue_size",
+        "When using the GATT server, this controls how many bytes of write commands can be queued per connection.",
+        default=0, vals = [0, 64, 128, 256, 512, 1024, 2048, 4096])
+feature("gatt_prepare_queue_size",
+        "When using the GATT server, this controls how many bytes of prepared writes can be queued per connection.",
+        default=512, vals = [0, 64, 128, 256, 512, 1024, 2048])
 
 # ========= Update Cargo.toml
 
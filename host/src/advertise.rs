@@ -101,6 +101,22 @@ pub struct AdvertisementParameters {
 
     /// Fragmentation preference
     pub fragment: bool,
+
+    /// Advertise from a non-resolvable private address instead of the host address.
+    ///
+    /// The address is generated by the controller and changed periodically, see
+    /// [`Stack::set_private_address_interval`](crate::Stack::set_private_address_interval), so that the
+    /// advertiser can't be tracked by its address. Only for non-connectable extended advertising sets without
+    /// a timeout or maximum number of events. The set is enabled once
+    /// [`Stack::rotate_private_addresses`](crate::Stack::rotate_private_addresses) has given it an address.
+    pub private_address: bool,
+
+    /// Index of the local identity to advertise with, among the identities set with
+    /// [`Stack::set_identities`](crate::Stack::set_identities), instead of the host address.
+    ///
+    /// Only for extended advertising sets, connections from the set report it as
+    /// [`Connection::local_identity`](crate::connection::Connection::local_identity).
+    pub identity: Option<u8>,
 }
 
 impl Default for AdvertisementParameters {
@@ -116,6 +132,8 @@ impl Default for AdvertisementParameters {
             filter_policy: AdvFilterPolicy::default(),
             channel_map: None,
             fragment: false,
+            private_address: false,
+            identity: None,
         }
     }
 }
@@ -368,6 +386,10 @@ pub enum AdStructure<'a> {
     /// The UUID data matches the ble network's endian order (should be little endian).
     ServiceUuids16(&'a [[u8; 2]]),
 
+    /// List of 32-bit service UUIDs.
+    /// The UUID data matches the ble network's endian order (should be little endian).
+    ServiceUuids32(&'a [[u8; 4]]),
+
     /// List of 128-bit service UUIDs.
     /// The UUID data matches the ble network's endian order (should be little endian).
     ServiceUuids128(&'a [[u8; 16]]),
@@ -381,6 +403,15 @@ pub enum AdStructure<'a> {
         data: &'a [u8],
     },
 
+    /// Service data with 32-bit service UUID.
+    /// The UUID data matches the ble network's endian order (should be little endian).
+    ServiceData32 {
+        /// The 32-bit service UUID.
+        uuid: [u8; 4],
+        /// The associated service data. May be empty.
+        data: &'a [u8],
+    },
+
     /// Sets the full (unabbreviated) device name.
     ///
     /// This will be shown to the user when this device is found.
@@ -427,6 +458,12 @@ impl AdStructure<'_> {
                     w.write_ref(&Uuid::Uuid16(*uuid))?;
                 }
             }
+            AdStructure::ServiceUuids32(uuids) => {
+                w.append(&[(uuids.len() * 4 + 1) as u8, 0x05])?;
+                for uuid in uuids.iter() {
+                    w.append(uuid)?;
+                }
+            }
             AdStructure::ServiceUuids128(uuids) => {
                 w.append(&[(uuids.len() * 16 + 1) as u8, 0x07])?;
                 for uuid in uuids.iter() {
@@ -446,6 +483,11 @@ impl AdStructure<'_> {
                 w.write(Uuid::Uuid16(*uuid))?;
                 w.append(data)?;
             }
+            AdStructure::ServiceData32 { uuid, data } => {
+                w.append(&[(data.len() + 5) as u8, 0x20])?;
+                w.append(uuid)?;
+                w.append(data)?;
+            }
             AdStructure::ManufacturerSpecificData {
                 company_identifier,
                 payload,
@@ -498,10 +540,14 @@ impl<'d> AdStructureIter<'d> {
                     Err(codec::Error::InvalidValue)
                 }
             },
-            // Incomplete List of 32-bit Service or Service Class UUIDs
-            // 0x04 =>
-            // Complete List of 32-bit Service or Service Class UUIDs
-            // 0x05
+            // Incomplete and Complete List of 32-bit Service or Service Class UUIDs
+            0x04 | 0x05 => match zerocopy::FromBytes::ref_from_bytes(data) {
+                Ok(x) => Ok(AdStructure::ServiceUuids32(x)),
+                Err(e) => {
+                    let _ = zerocopy::SizeError::from(e);
+                    Err(codec::Error::InvalidValue)
+                }
+            },
             // Incomplete List of 128-bit Service or Service Class UUIDs
             // 0x06
             // Complete List of 128-bit Service or Service Class UUIDs
@@ -546,7 +592,16 @@ impl<'d> AdStructureIter<'d> {
             0x1D Simple Pairing Hash C-256
             0x1E Simple Pairing Randomizer R-256
             0x1F List of 32-bit Service Solicitation UUIDs
-            0x20 Service Data - 32-bit UUID
+            */
+            // Service Data - 32-bit UUID
+            0x20 => {
+                if data.len() < 4 {
+                    return Err(codec::Error::InvalidValue);
+                }
+                let uuid = data[0..4].try_into().unwrap();
+                Ok(AdStructure::ServiceData32 { uuid, data: &data[4..] })
+            }
+            /*
             0x21 Service Data - 128-bit UUID
             0x22 LE Secure Connections Confirmation Value
             0x23 LE Secure Connections Random Value
@@ -598,11 +653,39 @@ mod tests {
         assert!(AdStructure::encode_slice(
             &[
                 AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
-                AdStructure::ServiceUuids16(&[[0x0f, 0x18]]),
+                AdStructure::ServiceUuids16(&[0x180f_u16.to_le_bytes()]),
                 AdStructure::CompleteLocalName(b"12345678901234567890123"),
             ],
             &mut adv_data[..],
         )
         .is_err());
     }
+
+    #[test]
+    fn adv_uuid32() {
+        let mut adv_data = [0; 31];
+        let len = unwrap!(AdStructure::encode_slice(
+            &[
+                AdStructure::ServiceUuids32(&[0x1234_5678u32.to_le_bytes()]),
+                AdStructure::ServiceData32 {
+                    uuid: 0x1234_5678u32.to_le_bytes(),
+                    data: &[1, 2],
+                },
+            ],
+            &mut adv_data[..],
+        ));
+        let mut it = AdStructure::decode(&adv_data[..len]);
+        assert!(matches!(
+            it.next(),
+            Some(Ok(AdStructure::ServiceUuids32([[0x78, 0x56, 0x34, 0x12]])))
+        ));
+        assert!(matches!(
+            it.next(),
+            Some(Ok(AdStructure::ServiceData32 {
+                uuid: [0x78, 0x56, 0x34, 0x12],
+                data: [1, 2],
+            }))
+        ));
+        assert!(it.next().is_none());
+    }
 }
@@ -0,0 +1,19 @@
+//! Bluetooth SIG assigned numbers.
+//!
+//! Service, characteristic, descriptor, declaration and appearance values come from `bt-hci`, which
+//! generates them from the Bluetooth SIG repository. Company identifiers are maintained here, as used
+//! in manufacturer specific advertising data.
+//!
+//! ```rust
+//! use trouble_host::assigned_numbers::{company_id, service};
+//! use trouble_host::prelude::*;
+//!
+//! let battery = Uuid::from(service::BATTERY);
+//! let data = AdStructure::ManufacturerSpecificData {
+//!     company_identifier: company_id::NORDIC_SEMICONDUCTOR,
+//!     payload: &[0x01],
+//! };
+//! ```
+pub use bt_hci::uuid::{appearance, characteristic, declarations, descriptors, service};
+
+pub mod company_id;
@@ -0,0 +1,71 @@
+//! Company identifiers, for manufacturer specific data.
+//!
+//! Only a selection of the identifiers assigned by the Bluetooth SIG is listed, see
+//! <https://www.bluetooth.com/specifications/assigned-numbers/> for the complete list.
+
+/// `0x0000` Ericsson AB
+pub const ERICSSON: u16 = 0x0000;
+/// `0x0001` Nokia Mobile Phones
+pub const NOKIA: u16 = 0x0001;
+/// `0x0002` Intel Corp.
+pub const INTEL: u16 = 0x0002;
+/// `0x0003` IBM Corp.
+pub const IBM: u16 = 0x0003;
+/// `0x0004` Toshiba Corp.
+pub const TOSHIBA: u16 = 0x0004;
+/// `0x0006` Microsoft
+pub const MICROSOFT: u16 = 0x0006;
+/// `0x0008` Motorola
+pub const MOTOROLA: u16 = 0x0008;
+/// `0x0009` Infineon Technologies AG
+pub const INFINEON: u16 = 0x0009;
+/// `0x000A` Qualcomm Technologies International, Ltd. (QTIL)
+pub const QUALCOMM_TECHNOLOGIES_INTERNATIONAL: u16 = 0x000A;
+/// `0x000D` Texas Instruments Inc.
+pub const TEXAS_INSTRUMENTS: u16 = 0x000D;
+/// `0x000F` Broadcom Corporation
+pub const BROADCOM: u16 = 0x000F;
+/// `0x0013` Atmel Corporation
+pub const ATMEL: u16 = 0x0013;
+/// `0x001D` Qualcomm
+pub const QUALCOMM: u16 = 0x001D;
+/// `0x0025` NXP Semiconductors (formerly Philips Semiconductors)
+pub const NXP_SEMICONDUCTORS: u16 = 0x0025;
+/// `0x0030` ST Microelectronics
+pub const ST_MICROELECTRONICS: u16 = 0x0030;
+/// `0x004C` Apple, Inc.
+pub const APPLE: u16 = 0x004C;
+/// `0x0059` Nordic Semiconductor ASA
+pub const NORDIC_SEMICONDUCTOR: u16 = 0x0059;
+/// `0x005D` Realtek Semiconductor Corporation
+pub const REALTEK_SEMICONDUCTOR: u16 = 0x005D;
+/// `0x006B` Polar Electro OY
+pub const POLAR_ELECTRO: u16 = 0x006B;
+/// `0x0075` Samsung Electronics Co. Ltd.
+pub const SAMSUNG_ELECTRONICS: u16 = 0x0075;
+/// `0x0087` Garmin International, Inc.
+pub const GARMIN: u16 = 0x0087;
+/// `0x009E` Bose Corporation
+pub const BOSE: u16 = 0x009E;
+/// `0x00CD` Microchip Technology Inc.
+pub const MICROCHIP_TECHNOLOGY: u16 = 0x00CD;
+/// `0x00D2` Dialog Semiconductor B.V.
+pub const DIALOG_SEMICONDUCTOR: u16 = 0x00D2;
+/// `0x00E0` Google
+pub const GOOGLE: u16 = 0x00E0;
+/// `0x012D` Sony Corporation
+pub const SONY: u16 = 0x012D;
+/// `0x0131` Cypress Semiconductor
+pub const CYPRESS_SEMICONDUCTOR: u16 = 0x0131;
+/// `0x0171` Amazon.com Services, LLC
+pub const AMAZON: u16 = 0x0171;
+/// `0x027D` HUAWEI Technologies Co., Ltd.
+pub const HUAWEI: u16 = 0x027D;
+/// `0x02E5` Espressif Systems (Shanghai) Co., Ltd.
+pub const ESPRESSIF: u16 = 0x02E5;
+/// `0x02FF` Silicon Laboratories
+pub const SILICON_LABS: u16 = 0x02FF;
+/// `0x038F` Xiaomi Inc.
+pub const XIAOMI: u16 = 0x038F;
+/// `0xFFFF` Reserved for internal use and testing, must not be used in shipping products.
+pub const TESTING: u16 = 0xFFFF;
@@ -6,6 +6,11 @@ use crate::codec;
 use crate::cursor::{ReadCursor, WriteCursor};
 use crate::types::uuid::*;
 
+#[cfg(feature = "gatt")]
+mod bearer;
+#[cfg(feature = "gatt")]
+pub(crate) use bearer::AttBearer;
+
 pub(crate) const ATT_READ_BY_GROUP_TYPE_REQ: u8 = 0x10;
 pub(crate) const ATT_READ_BY_GROUP_TYPE_RSP: u8 = 0x11;
 pub(crate) const ATT_ERROR_RSP: u8 = 0x01;
@@ -15,6 +20,7 @@ pub(crate) const ATT_READ_REQ: u8 = 0x0a;
 pub(crate) const ATT_READ_RSP: u8 = 0x0b;
 pub(crate) const ATT_WRITE_REQ: u8 = 0x12;
 pub(crate) const ATT_WRITE_CMD: u8 = 0x52;
+pub(crate) const ATT_SIGNED_WRITE_CMD: u8 = 0xd2;
 pub(crate) const ATT_WRITE_RSP: u8 = 0x13;
 pub(crate) const ATT_EXCHANGE_MTU_REQ: u8 = 0x02;
 pub(crate) const ATT_EXCHANGE_MTU_RSP: u8 = 0x03;
@@ -26,14 +32,40 @@ pub(crate) const ATT_PREPARE_WRITE_REQ: u8 = 0x16;
 pub(crate) const ATT_PREPARE_WRITE_RSP: u8 = 0x17;
 pub(crate) const ATT_EXECUTE_WRITE_REQ: u8 = 0x18;
 pub(crate) const ATT_EXECUTE_WRITE_RSP: u8 = 0x19;
-pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x20;
-pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x21;
+pub(crate) const ATT_READ_MULTIPLE_REQ: u8 = 0x0e;
+pub(crate) const ATT_READ_MULTIPLE_RSP: u8 = 0x0f;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_REQ: u8 = 0x20;
+pub(crate) const ATT_READ_MULTIPLE_VARIABLE_RSP: u8 = 0x21;
 pub(crate) const ATT_READ_BLOB_REQ: u8 = 0x0c;
 pub(crate) const ATT_READ_BLOB_RSP: u8 = 0x0d;
 pub(crate) const ATT_HANDLE_VALUE_NTF: u8 = 0x1b;
 pub(crate) const ATT_HANDLE_VALUE_IND: u8 = 0x1d;
 pub(crate) const ATT_HANDLE_VALUE_CMF: u8 = 0x1e;
 
+/// Whether the opcode is a request, which must be answered before the client sends another one.
+pub(crate) fn is_request(opcode: u8) -> bool {
+    matches!(
+        opcode,
+        ATT_EXCHANGE_MTU_REQ
+            | ATT_FIND_INFORMATION_REQ
+            | ATT_FIND_BY_TYPE_VALUE_REQ
+            | ATT_READ_BY_TYPE_REQ
+            | ATT_READ_REQ
+            | ATT_READ_BLOB_REQ
+            | ATT_READ_MULTIPLE_REQ
+            | ATT_READ_BY_GROUP_TYPE_REQ
+            | ATT_WRITE_REQ
+            | ATT_PREPARE_WRITE_REQ
+            | ATT_EXECUTE_WRITE_REQ
+            | ATT_READ_MULTIPLE_VARIABLE_REQ
+    )
+}
+
+/// Whether the opcode is a response, which ends the transaction of a request.
+pub(crate) fn is_response(opcode: u8) -> bool {
+    opcode == ATT_ERROR_RSP || (is_request(opcode.wrapping_sub(1)) && opcode & 1 == 1)
+}
+
 /// Attribute Error Code
 ///
 /// This enum type describes the `ATT_ERROR_RSP` PDU from the Bluetooth Core Specification
@@ -94,6 +126,12 @@ impl AttErrorCode {
     pub const PROCEDURE_ALREADY_IN_PROGRESS: Self = Self { value: 0xFE };
     /// The attribute value is out of range as defined by a profile or service specification
     pub const OUT_OF_RANGE: Self = Self { value: 0xFF };
+
+    /// Application error code defined by a profile or service, in the range 0x80 to 0x9F.
+    pub const fn application(code: u8) -> Self {
+        core::assert!(code >= 0x80 && code <= 0x9f, "application error codes are 0x80 to 0x9F");
+        Self { value: code }
+    }
 }
 
 impl Display for AttErrorCode {
@@ -174,7 +212,7 @@ pub enum AttClient<'d> {
 
 /// ATT Request PDU
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum AttReq<'d> {
     /// Read By Group Type Request
     ReadByGroupType {
@@ -248,6 +286,11 @@ pub enum AttReq<'d> {
         /// Attribute handles
         handles: &'d [u8],
     },
+    /// Read Multiple Variable Length Request
+    ReadMultipleVariable {
+        /// Attribute handles
+        handles: &'d [u8],
+    },
     /// Read Blob Request
     ReadBlob {
         /// Attribute handle
@@ -268,6 +311,15 @@ pub enum AttCmd<'d> {
         /// Attribute value
         data: &'d [u8],
     },
+    /// Signed Write Command
+    SignedWrite {
+        /// Attribute handle
+        handle: u16,
+        /// Attribute value
+        data: &'d [u8],
+        /// Sign counter followed by the MAC of the opcode, handle and value
+        signature: [u8; 12],
+    },
 }
 
 /// ATT Confirmation PDU
@@ -302,6 +354,11 @@ pub enum AttRsp<'d> {
         /// Iterator over the found handles
         it: FindByTypeValueIter<'d>,
     },
+    /// Find Information Response
+    FindInformation {
+        /// Iterator over the found handles and their types
+        it: FindInformationIter<'d>,
+    },
     /// Error Response
     Error {
         /// Request opcode
@@ -321,8 +378,29 @@ pub enum AttRsp<'d> {
         /// Attribute value
         data: &'d [u8],
     },
+    /// Read Multiple Response
+    ReadMultiple {
+        /// Concatenated attribute values
+        data: &'d [u8],
+    },
+    /// Read Multiple Variable Length Response
+    ReadMultipleVariable {
+        /// Iterator over the attribute values
+        it: ReadMultipleVariableIter<'d>,
+    },
     /// Write Response
     Write,
+    /// Prepare Write Response
+    PrepareWrite {
+        /// Attribute handle
+        handle: u16,
+        /// Attribute offset
+        offset: u16,
+        /// Attribute value, echoed from the request
+        value: &'d [u8],
+    },
+    /// Execute Write Response
+    ExecuteWrite,
 }
 
 /// ATT Unsolicited PDU
@@ -408,6 +486,66 @@ impl<'d> ReadByTypeIter<'d> {
     }
 }
 
+/// An Iterator-like type for iterating over the handles and types in a Find Information Response
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct FindInformationIter<'d> {
+    format: u8,
+    cursor: ReadCursor<'d>,
+}
+
+impl FindInformationIter<'_> {
+    fn uuid_len(&self) -> usize {
+        if self.format == 0x02 {
+            16
+        } else {
+            2
+        }
+    }
+
+    /// Get the next pair of attribute handle and attribute type
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(u16, Uuid), crate::Error>> {
+        let uuid_len = self.uuid_len();
+        if self.cursor.available() >= 2 + uuid_len {
+            let res = (|| {
+                let handle: u16 = self.cursor.read()?;
+                let uuid = Uuid::try_from(self.cursor.slice(uuid_len)?)?;
+                Ok((handle, uuid))
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
+/// An Iterator-like type for iterating over the values in a Read Multiple Variable Length Response
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Clone, Debug)]
+pub struct ReadMultipleVariableIter<'d> {
+    cursor: ReadCursor<'d>,
+}
+
+impl<'d> ReadMultipleVariableIter<'d> {
+    /// Get the next pair of attribute value length and attribute value.
+    ///
+    /// The value may be shorter than the length if it was truncated to fit the response.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<Result<(u16, &'d [u8]), crate::Error>> {
+        if self.cursor.available() >= 2 {
+            let res = (|| {
+                let len: u16 = self.cursor.read()?;
+                let value = self.cursor.slice((len as usize).min(self.cursor.available()))?;
+                Ok((len, value))
+            })();
+            Some(res)
+        } else {
+            None
+        }
+    }
+}
+
 impl<'d> AttServer<'d> {
     fn size(&self) -> usize {
         match self {
@@ -437,10 +575,15 @@ impl<'d> AttRsp<'d> {
         1 + match self {
             Self::ExchangeMtu { mtu: u16 } => 2,
             Self::FindByTypeValue { it } => it.cursor.len(),
+            Self::FindInformation { it } => 1 + it.cursor.len(),
             Self::Error { .. } => 4,
             Self::Read { data } => data.len(),
+            Self::ReadMultiple { data } => data.len(),
+            Self::ReadMultipleVariable { it } => it.cursor.len(),
             Self::ReadByType { it } => it.cursor.len(),
             Self::Write => 0,
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite => 0,
         }
     }
 
@@ -459,6 +602,15 @@ impl<'d> AttRsp<'d> {
                     w.write(end)?;
                 }
             }
+            Self::FindInformation { it } => {
+                w.write(ATT_FIND_INFORMATION_RSP)?;
+                w.write(it.format)?;
+                let mut it = it.clone();
+                while let Some(Ok((handle, uuid))) = it.next() {
+                    w.write(handle)?;
+                    w.append(uuid.as_raw())?;
+                }
+            }
             Self::Error { request, handle, code } => {
                 w.write(ATT_ERROR_RSP)?;
                 w.write(*request)?;
@@ -478,9 +630,30 @@ impl<'d> AttRsp<'d> {
                 w.write(ATT_READ_RSP)?;
                 w.append(data)?;
             }
+            Self::ReadMultiple { data } => {
+                w.write(ATT_READ_MULTIPLE_RSP)?;
+                w.append(data)?;
+            }
+            Self::ReadMultipleVariable { it } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_RSP)?;
+                let mut it = it.clone();
+                while let Some(Ok((len, value))) = it.next() {
+                    w.write(len)?;
+                    w.append(value)?;
+                }
+            }
             Self::Write => {
                 w.write(ATT_WRITE_RSP)?;
             }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_RSP)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite => {
+                w.write(ATT_EXECUTE_WRITE_RSP)?;
+            }
         }
         Ok(())
     }
@@ -494,6 +667,12 @@ impl<'d> AttRsp<'d> {
                 let mtu: u16 = r.read()?;
                 Ok(Self::ExchangeMtu { mtu })
             }
+            ATT_FIND_INFORMATION_RSP => {
+                let format: u8 = r.read()?;
+                Ok(Self::FindInformation {
+                    it: FindInformationIter { format, cursor: r },
+                })
+            }
             ATT_ERROR_RSP => {
                 let request = r.read()?;
                 let handle = r.read()?;
@@ -501,6 +680,10 @@ impl<'d> AttRsp<'d> {
                 Ok(Self::Error { request, handle, code })
             }
             ATT_READ_RSP => Ok(Self::Read { data: r.remaining() }),
+            ATT_READ_MULTIPLE_RSP => Ok(Self::ReadMultiple { data: r.remaining() }),
+            ATT_READ_MULTIPLE_VARIABLE_RSP => Ok(Self::ReadMultipleVariable {
+                it: ReadMultipleVariableIter { cursor: r },
+            }),
             ATT_READ_BY_TYPE_RSP => {
                 let item_len: u8 = r.read()?;
                 Ok(Self::ReadByType {
@@ -511,6 +694,16 @@ impl<'d> AttRsp<'d> {
                 })
             }
             ATT_WRITE_RSP => Ok(Self::Write),
+            ATT_PREPARE_WRITE_RSP => {
+                let handle = r.read()?;
+                let offset = r.read()?;
+                Ok(Self::PrepareWrite {
+                    handle,
+                    offset,
+                    value: r.remaining(),
+                })
+            }
+            ATT_EXECUTE_WRITE_RSP => Ok(Self::ExecuteWrite),
             _ => Err(codec::Error::InvalidValue),
         }
     }
@@ -581,7 +774,7 @@ impl<'d> AttClient<'d> {
 
     fn decode_with_opcode(opcode: u8, r: ReadCursor<'d>) -> Result<Self, codec::Error> {
         let decoded = match opcode {
-            ATT_WRITE_CMD => Self::Command(AttCmd::decode_with_opcode(opcode, r)?),
+            ATT_WRITE_CMD | ATT_SIGNED_WRITE_CMD => Self::Command(AttCmd::decode_with_opcode(opcode, r)?),
             ATT_HANDLE_VALUE_CMF => Self::Confirmation(AttCfm::decode_with_opcode(opcode, r)?),
             _ => Self::Request(AttReq::decode_with_opcode(opcode, r)?),
         };
@@ -604,8 +797,12 @@ impl<'d> AttReq<'d> {
                 end,
                 attribute_type,
             } => 4 + attribute_type.as_raw().len(),
+            Self::FindInformation { .. } => 4,
             Self::Read { .. } => 2,
             Self::Write { handle, data } => 2 + data.len(),
+            Self::PrepareWrite { value, .. } => 4 + value.len(),
+            Self::ExecuteWrite { .. } => 1,
+            Self::ReadMultiple { handles } | Self::ReadMultipleVariable { handles } => handles.len(),
             _ => unimplemented!(),
         }
     }
@@ -638,6 +835,14 @@ impl<'d> AttReq<'d> {
                 w.write(*end)?;
                 w.write_ref(attribute_type)?;
             }
+            Self::FindInformation {
+                start_handle,
+                end_handle,
+            } => {
+                w.write(ATT_FIND_INFORMATION_REQ)?;
+                w.write(*start_handle)?;
+                w.write(*end_handle)?;
+            }
             Self::Read { handle } => {
                 w.write(ATT_READ_REQ)?;
                 w.write(*handle)?;
@@ -647,6 +852,24 @@ impl<'d> AttReq<'d> {
                 w.write(*handle)?;
                 w.append(data)?;
             }
+            Self::ReadMultiple { handles } => {
+                w.write(ATT_READ_MULTIPLE_REQ)?;
+                w.append(handles)?;
+            }
+            Self::ReadMultipleVariable { handles } => {
+                w.write(ATT_READ_MULTIPLE_VARIABLE_REQ)?;
+                w.append(handles)?;
+            }
+            Self::PrepareWrite { handle, offset, value } => {
+                w.write(ATT_PREPARE_WRITE_REQ)?;
+                w.write(*handle)?;
+                w.write(*offset)?;
+                w.append(value)?;
+            }
+            Self::ExecuteWrite { flags } => {
+                w.write(ATT_EXECUTE_WRITE_REQ)?;
+                w.write(*flags)?;
+            }
             _ => unimplemented!(),
         }
         Ok(())
@@ -654,6 +877,16 @@ impl<'d> AttReq<'d> {
 
     fn decode_with_opcode(opcode: u8, r: ReadCursor<'d>) -> Result<Self, codec::Error> {
         let payload = r.remaining();
+        let min_len = match opcode {
+            ATT_READ_BY_GROUP_TYPE_REQ | ATT_READ_BY_TYPE_REQ | ATT_FIND_BY_TYPE_VALUE_REQ => 6,
+            ATT_FIND_INFORMATION_REQ | ATT_PREPARE_WRITE_REQ | ATT_READ_BLOB_REQ => 4,
+            ATT_READ_REQ | ATT_WRITE_REQ | ATT_EXCHANGE_MTU_REQ => 2,
+            ATT_EXECUTE_WRITE_REQ => 1,
+            _ => 0,
+        };
+        if payload.len() < min_len {
+            return Err(codec::Error::InvalidValue);
+        }
         match opcode {
             ATT_READ_BY_GROUP_TYPE_REQ => {
                 let start_handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
@@ -662,7 +895,7 @@ impl<'d> AttReq<'d> {
                 let group_type = if payload.len() == 6 {
                     Uuid::Uuid16([payload[4], payload[5]])
                 } else if payload.len() == 20 {
-                    let uuid = payload[4..21].try_into().map_err(|_| codec::Error::InvalidValue)?;
+                    let uuid = payload[4..20].try_into().map_err(|_| codec::Error::InvalidValue)?;
                     Uuid::Uuid128(uuid)
                 } else {
                     return Err(codec::Error::InvalidValue);
@@ -744,6 +977,7 @@ impl<'d> AttReq<'d> {
                 Ok(Self::ExecuteWrite { flags })
             }
             ATT_READ_MULTIPLE_REQ => Ok(Self::ReadMultiple { handles: payload }),
+            ATT_READ_MULTIPLE_VARIABLE_REQ => Ok(Self::ReadMultipleVariable { handles: payload }),
             ATT_READ_BLOB_REQ => {
                 let handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
                 let offset = (payload[2] as u16) + ((payload[3] as u16) << 8);
@@ -761,6 +995,7 @@ impl<'d> AttCmd<'d> {
     fn size(&self) -> usize {
         1 + match self {
             Self::Write { handle, data } => 2 + data.len(),
+            Self::SignedWrite { handle, data, .. } => 2 + data.len() + 12,
         }
     }
 
@@ -772,6 +1007,16 @@ impl<'d> AttCmd<'d> {
                 w.write(*handle)?;
                 w.append(data)?;
             }
+            Self::SignedWrite {
+                handle,
+                data,
+                signature,
+            } => {
+                w.write(ATT_SIGNED_WRITE_CMD)?;
+                w.write(*handle)?;
+                w.append(data)?;
+                w.append(signature)?;
+            }
         }
         Ok(())
     }
@@ -780,11 +1025,26 @@ impl<'d> AttCmd<'d> {
         let payload = r.remaining();
         match opcode {
             ATT_WRITE_CMD => {
+                if payload.len() < 2 {
+                    return Err(codec::Error::InvalidValue);
+                }
                 let handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
                 let data = &payload[2..];
 
                 Ok(Self::Write { handle, data })
             }
+            ATT_SIGNED_WRITE_CMD => {
+                if payload.len() < 2 + 12 {
+                    return Err(codec::Error::InvalidValue);
+                }
+                let handle = (payload[0] as u16) + ((payload[1] as u16) << 8);
+                let (data, signature) = payload[2..].split_at(payload.len() - 2 - 12);
+                Ok(Self::SignedWrite {
+                    handle,
+                    data,
+                    signature: unwrap!(signature.try_into()),
+                })
+            }
             code => {
                 warn!("[att] unknown opcode {:x}", code);
                 Err(codec::Error::InvalidValue)
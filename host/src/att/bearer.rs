@@ -0,0 +1,190 @@
+//! Transports carrying ATT PDUs.
+use super::Att;
+use crate::connection::Connection;
+use crate::cursor::WriteCursor;
+use crate::pdu::Pdu;
+use crate::types::l2cap::{L2capHeader, L2CAP_CID_ATT};
+use crate::{Error, PacketPool};
+
+/// A bearer carrying ATT PDUs to a peer.
+///
+/// The attribute server and client frame their PDUs through this trait rather than
+/// assuming the LE fixed channel, so that other bearers (e.g. EATT) can be added later.
+pub(crate) trait AttBearer<P: PacketPool> {
+    /// ATT MTU agreed for the bearer.
+    fn att_mtu(&self) -> u16;
+
+    /// Number of bytes preceding the ATT PDU in a packet.
+    fn header_len(&self) -> usize;
+
+    /// Write the transport header for an ATT PDU of `len` bytes.
+    fn write_header(&self, header: &mut WriteCursor<'_>, len: usize) -> Result<(), Error>;
+
+    /// Check that ATT PDUs may be sent on the bearer.
+    fn check_open(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Send a framed packet, waiting for space in the outbound queue.
+    async fn send(&self, pdu: Pdu<P::Packet>);
+
+    /// Send a framed packet, failing if the outbound queue is full.
+    fn try_send(&self, pdu: Pdu<P::Packet>) -> Result<(), Error>;
+
+    /// Frame an ATT PDU for this bearer.
+    ///
+    /// The closure writes the ATT PDU into the provided buffer and returns its length, or `None`
+    /// if there is nothing to send. The PDU is truncated to the ATT MTU of the bearer.
+    fn frame<F>(&self, mut packet: P::Packet, f: F) -> Result<Option<Pdu<P::Packet>>, Error>
+    where
+        F: FnOnce(&mut [u8]) -> Result<Option<usize>, Error>,
+    {
+        self.check_open()?;
+        let header_len = self.header_len();
+        let mtu = self.att_mtu() as usize;
+        let (header, body) = packet.as_mut().split_at_mut(header_len);
+        let Some(len) = f(body)? else {
+            return Ok(None);
+        };
+        let len = len.min(mtu);
+        self.write_header(&mut WriteCursor::new(header), len)?;
+        Ok(Some(Pdu::new(packet, header_len + len)))
+    }
+
+    /// Encode an ATT PDU into a packet framed for this bearer.
+    fn encode(&self, packet: P::Packet, att: Att<'_>) -> Result<Pdu<P::Packet>, Error> {
+        let pdu = self.frame(packet, |buf| {
+            let mut w = WriteCursor::new(buf);
+            w.write(att)?;
+            Ok(Some(w.len()))
+        })?;
+        Ok(unwrap!(pdu))
+    }
+}
+
+/// The LE fixed ATT channel of a connection.
+impl<P: PacketPool> AttBearer<P> for Connection<'_, P> {
+    fn att_mtu(&self) -> u16 {
+        self.get_att_mtu()
+    }
+
+    fn header_len(&self) -> usize {
+        4
+    }
+
+    fn write_header(&self, header: &mut WriteCursor<'_>, len: usize) -> Result<(), Error> {
+        header.write_hci(&L2capHeader {
+            length: len as u16,
+            channel: L2CAP_CID_ATT,
+        })?;
+        Ok(())
+    }
+
+    fn check_open(&self) -> Result<(), Error> {
+        // No further ATT PDUs may be sent after a transaction timeout, until reconnected.
+        if self.att_timed_out() {
+            Err(Error::Timeout)
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn send(&self, pdu: Pdu<P::Packet>) {
+        Connection::send(self, pdu).await
+    }
+
+    fn try_send(&self, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        Connection::try_send(self, pdu)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::att::{AttErrorCode, AttRsp, AttServer, ATT_ERROR_RSP, ATT_READ_REQ};
+    use crate::prelude::DefaultPacketPool;
+
+    /// A bearer without a transport header, recording all sent PDUs.
+    pub(crate) struct MockBearer {
+        pub(crate) mtu: u16,
+        pub(crate) sent: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl MockBearer {
+        pub(crate) fn new(mtu: u16) -> Self {
+            Self {
+                mtu,
+                sent: RefCell::new(Vec::new()),
+            }
+        }
+    }
+
+    impl<P: PacketPool> AttBearer<P> for MockBearer {
+        fn att_mtu(&self) -> u16 {
+            self.mtu
+        }
+
+        fn header_len(&self) -> usize {
+            0
+        }
+
+        fn write_header(&self, _header: &mut WriteCursor<'_>, _len: usize) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn send(&self, pdu: Pdu<P::Packet>) {
+            self.sent.borrow_mut().push(pdu.as_ref().to_vec());
+        }
+
+        fn try_send(&self, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+            self.sent.borrow_mut().push(pdu.as_ref().to_vec());
+            Ok(())
+        }
+    }
+
+    fn encode(bearer: &MockBearer, rsp: AttRsp<'_>) -> Pdu<<DefaultPacketPool as PacketPool>::Packet> {
+        let packet = DefaultPacketPool::allocate().unwrap();
+        AttBearer::<DefaultPacketPool>::encode(bearer, packet, Att::Server(AttServer::Response(rsp))).unwrap()
+    }
+
+    #[test]
+    fn att_over_mock_bearer() {
+        let bearer = MockBearer::new(23);
+        let pdu = encode(
+            &bearer,
+            AttRsp::Error {
+                request: ATT_READ_REQ,
+                handle: 3,
+                code: AttErrorCode::READ_NOT_PERMITTED,
+            },
+        );
+        block_on(AttBearer::<DefaultPacketPool>::send(&bearer, pdu));
+
+        let sent = bearer.sent.borrow();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0][0], ATT_ERROR_RSP);
+        assert!(matches!(
+            Att::decode(&sent[0]),
+            Ok(Att::Server(AttServer::Response(AttRsp::Error { handle: 3, .. })))
+        ));
+    }
+
+    #[test]
+    fn pdu_truncated_to_mtu() {
+        let bearer = MockBearer::new(5);
+        let pdu = encode(
+            &bearer,
+            AttRsp::Read {
+                data: &[1, 2, 3, 4, 5, 6],
+            },
+        );
+        assert_eq!(pdu.as_ref(), &[crate::att::ATT_READ_RSP, 1, 2, 3, 4]);
+    }
+}
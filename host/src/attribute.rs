@@ -7,11 +7,15 @@ use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
 use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
+#[cfg(feature = "gatt-last-modified")]
+use embassy_time::Instant;
 use heapless::Vec;
 
-use crate::att::AttErrorCode;
+use crate::att::{AttBearer, AttErrorCode};
 use crate::attribute_server::AttributeServer;
+use crate::connection::Connection;
 use crate::cursor::{ReadCursor, WriteCursor};
+use crate::gap::DeviceNameWrite;
 use crate::prelude::{AsGatt, FixedGattValue, FromGatt, GattConnection};
 use crate::types::gatt_traits::FromGattError;
 pub use crate::types::uuid::Uuid;
@@ -39,11 +43,33 @@ pub enum CharacteristicProp {
     Extended = 0x80,
 }
 
+/// Security required to access an attribute
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AttributeSecurity {
+    /// Accessible on any link
+    #[default]
+    None,
+    /// Requires an encrypted link meeting the security policy of the stack, e.g. its minimum encryption key size
+    Encrypted,
+}
+
+/// Security required to read and write an attribute
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct AttributePermissions {
+    /// Security required to read the value
+    pub read: AttributeSecurity,
+    /// Security required to write the value
+    pub write: AttributeSecurity,
+}
+
 /// Attribute metadata.
 pub struct Attribute<'a> {
     pub(crate) uuid: Uuid,
     pub(crate) handle: u16,
     pub(crate) last_handle_in_group: u16,
+    pub(crate) permissions: AttributePermissions,
     pub(crate) data: AttributeData<'a>,
 }
 
@@ -57,6 +83,11 @@ impl<'a> Attribute<'a> {
         self.data.read(offset, data)
     }
 
+    /// Length of the attribute value.
+    pub(crate) fn value_len(&self) -> usize {
+        self.data.value_len()
+    }
+
     pub(crate) fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
         if !self.data.writable() {
             return Err(AttErrorCode::WRITE_NOT_PERMITTED);
@@ -79,6 +110,9 @@ pub(crate) enum AttributeData<'d> {
         variable_len: bool,
         len: u16,
         value: &'d mut [u8],
+        validate: fn(&[u8]) -> Result<(), FromGattError>,
+        #[cfg(feature = "gatt-last-modified")]
+        modified: Option<LastModified>,
     },
     Declaration {
         props: CharacteristicProps,
@@ -91,6 +125,13 @@ pub(crate) enum AttributeData<'d> {
     },
 }
 
+fn validation_error(e: FromGattError) -> AttErrorCode {
+    match e {
+        FromGattError::InvalidLength => AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH,
+        FromGattError::InvalidCharacter | FromGattError::InvalidValue => AttErrorCode::VALUE_NOT_ALLOWED,
+    }
+}
+
 impl AttributeData<'_> {
     pub(crate) fn readable(&self) -> bool {
         match self {
@@ -116,6 +157,31 @@ impl AttributeData<'_> {
         }
     }
 
+    /// Whether the attribute accepts write commands.
+    pub(crate) fn command_writable(&self) -> bool {
+        match self {
+            Self::Data { props, .. } => props.0 & (CharacteristicProp::WriteWithoutResponse as u8) != 0,
+            _ => self.writable(),
+        }
+    }
+
+    pub(crate) fn signed_writable(&self) -> bool {
+        match self {
+            Self::Data { props, .. } => props.0 & (CharacteristicProp::AuthenticatedWrite as u8) != 0,
+            _ => false,
+        }
+    }
+
+    fn value_len(&self) -> usize {
+        match self {
+            Self::Service { uuid } => uuid.as_raw().len(),
+            Self::ReadOnlyData { value, .. } => value.len(),
+            Self::Data { len, .. } => *len as usize,
+            Self::Declaration { uuid, .. } => 3 + uuid.as_raw().len(),
+            Self::Cccd { .. } => 2,
+        }
+    }
+
     fn read(&self, offset: usize, data: &mut [u8]) -> Result<usize, AttErrorCode> {
         if !self.readable() {
             return Err(AttErrorCode::READ_NOT_PERMITTED);
@@ -136,6 +202,7 @@ impl AttributeData<'_> {
                 value,
                 variable_len,
                 len,
+                ..
             } => {
                 let value = &value[..*len as usize];
                 if offset > value.len() {
@@ -204,6 +271,39 @@ impl AttributeData<'_> {
         }
     }
 
+    /// Swap a segment of a prepared write with the bytes of the value it replaces, and set the value length.
+    ///
+    /// Returns the previous length. Swapping the segments back in reverse order with the returned lengths
+    /// restores the value.
+    pub(crate) fn swap_segment(
+        &mut self,
+        offset: usize,
+        data: &mut [u8],
+        new_len: usize,
+    ) -> Result<usize, AttErrorCode> {
+        match self {
+            Self::Data { value, len, .. } => {
+                let end = offset + data.len();
+                if end > value.len() {
+                    return Err(AttErrorCode::INVALID_OFFSET);
+                }
+                value[offset..end].swap_with_slice(data);
+                Ok(core::mem::replace(len, new_len as u16) as usize)
+            }
+            _ => Err(AttErrorCode::WRITE_NOT_PERMITTED),
+        }
+    }
+
+    /// Validate the complete value, after a prepared write was executed.
+    pub(crate) fn validate(&self) -> Result<(), AttErrorCode> {
+        match self {
+            Self::Data {
+                value, len, validate, ..
+            } => validate(&value[..*len as usize]).map_err(validation_error),
+            _ => Ok(()),
+        }
+    }
+
     fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), AttErrorCode> {
         let writable = self.writable();
 
@@ -213,11 +313,18 @@ impl AttributeData<'_> {
                 props,
                 variable_len,
                 len,
+                validate,
+                ..
             } => {
                 if !writable {
                     return Err(AttErrorCode::WRITE_NOT_PERMITTED);
                 }
 
+                // Writes at an offset are prepared writes, validated once executed.
+                if offset == 0 {
+                    validate(data).map_err(validation_error)?;
+                }
+
                 if offset + data.len() <= value.len() {
                     value[offset..offset + data.len()].copy_from_slice(data);
                     *len = (offset + data.len()) as u16;
@@ -282,14 +389,153 @@ impl<'a> Attribute<'a> {
             handle: 0,
             data,
             last_handle_in_group: 0xffff,
+            permissions: AttributePermissions::default(),
         }
     }
 }
 
+/// Compile time check of the attribute table size used by the `gatt_server` macro.
+///
+/// Panics with a message including the required size, which surfaces as a compile error when
+/// evaluated in a const item.
+#[doc(hidden)]
+pub const fn assert_table_size(argument: &str, size: usize, required: usize, entries: &str) {
+    if size >= required {
+        return;
+    }
+    let mut msg = ConstMessage::new();
+    msg = msg
+        .push(argument)
+        .push(" = ")
+        .push_usize(size)
+        .push(" is too small, the services need ");
+    msg = msg
+        .push_usize(required)
+        .push(" ")
+        .push(entries)
+        .push(". Increase ")
+        .push(argument);
+    msg = msg.push(" or remove the argument entirely to size the table automatically.");
+    core::panic!("{}", msg.as_str());
+}
+
+/// Compile time check of the storage provided with `store` in the `gatt_service` macro, which must hold `SIZE` bytes.
+#[doc(hidden)]
+pub struct StoreCheck<const SIZE: usize>;
+
+impl<const SIZE: usize> StoreCheck<SIZE> {
+    const fn fits<const N: usize>() {
+        core::assert!(
+            N >= SIZE,
+            "'store' is smaller than the maximum size of the value type, set 'max_len' to store shorter values"
+        );
+    }
+
+    /// Evaluated when the generated code is monomorphized, so a storage that is too small fails the build.
+    pub fn check<const N: usize>(store: &mut [u8; N]) -> &mut [u8] {
+        const { Self::fits::<N>() };
+        store
+    }
+}
+
+/// Return the first handle available after a service of `count` attributes, which starts at `start` if
+/// given or else at `next`, the first handle available after the previous services.
+///
+/// Panics if `start` overlaps the previous services, which surfaces as a compile error when evaluated
+/// in a const item.
+#[doc(hidden)]
+pub const fn next_service_handle(next: u16, start: Option<u16>, count: usize) -> u16 {
+    let start = match start {
+        Some(start) if start < next => {
+            let msg = ConstMessage::new()
+                .push("handle_start = ")
+                .push_usize(start as usize)
+                .push(" overlaps the previous services, the first free handle is ")
+                .push_usize(next as usize);
+            core::panic!("{}", msg.as_str());
+        }
+        Some(start) => start,
+        None => next,
+    };
+    // Services are aligned to 16 handles, see `ServiceBuilder::drop`
+    let end = start as usize + count;
+    let next = end + (0x10 - end % 0x10);
+    if next > u16::MAX as usize {
+        core::panic!("services exceed the attribute handle range");
+    }
+    next as u16
+}
+
+struct ConstMessage {
+    buf: [u8; 256],
+    len: usize,
+}
+
+impl ConstMessage {
+    const fn new() -> Self {
+        Self { buf: [0; 256], len: 0 }
+    }
+
+    const fn push(mut self, s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() && self.len < self.buf.len() {
+            self.buf[self.len] = bytes[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    const fn push_usize(mut self, value: usize) -> Self {
+        let mut digits = [0u8; 20];
+        let mut n = value;
+        let mut count = 0;
+        loop {
+            digits[count] = b'0' + (n % 10) as u8;
+            count += 1;
+            n /= 10;
+            if n == 0 {
+                break;
+            }
+        }
+        while count > 0 && self.len < self.buf.len() {
+            count -= 1;
+            self.buf[self.len] = digits[count];
+            self.len += 1;
+        }
+        self
+    }
+
+    const fn as_str(&self) -> &str {
+        let (bytes, _) = self.buf.split_at(self.len);
+        match core::str::from_utf8(bytes) {
+            Ok(s) => s,
+            Err(_) => "table size is insufficient",
+        }
+    }
+}
+
+/// Last change to the value of a characteristic, by a client write or locally.
+#[cfg(feature = "gatt-last-modified")]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LastModified {
+    /// Sequence number of the change, increasing with each change to a value of the table.
+    pub sequence: u64,
+    /// Time of the change.
+    pub at: Instant,
+}
+
 /// A table of attributes.
 pub struct AttributeTable<'d, M: RawMutex, const MAX: usize> {
     inner: Mutex<M, RefCell<InnerTable<'d, MAX>>>,
     handle: u16,
+    /// Sequence number of the last change to a value.
+    #[cfg(feature = "gatt-last-modified")]
+    sequence: Mutex<M, core::cell::Cell<u64>>,
+    /// Value handle of the writable GAP device name, with its permission.
+    pub(crate) device_name_write: Option<(u16, DeviceNameWrite<'d>)>,
 }
 
 pub(crate) struct InnerTable<'d, const MAX: usize> {
@@ -314,6 +560,22 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         Self {
             handle: 1,
             inner: Mutex::new(RefCell::new(InnerTable { attributes: Vec::new() })),
+            device_name_write: None,
+            #[cfg(feature = "gatt-last-modified")]
+            sequence: Mutex::new(core::cell::Cell::new(0)),
+        }
+    }
+
+    /// Record a change to a value, returning its sequence number and time.
+    #[cfg(feature = "gatt-last-modified")]
+    pub(crate) fn modified(&self) -> LastModified {
+        let sequence = self.sequence.lock(|sequence| {
+            sequence.set(sequence.get() + 1);
+            sequence.get()
+        });
+        LastModified {
+            sequence,
+            at: Instant::now(),
         }
     }
 
@@ -346,6 +608,19 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         handle
     }
 
+    /// Add a service to the attribute table, starting at the given handle.
+    ///
+    /// Fixing the handle keeps the handles of the service stable when services are added to or
+    /// removed from the table before it. Returns `Error::InvalidValue` if the handle is already
+    /// taken by a previous service.
+    pub fn add_service_at(&mut self, service: Service, handle: u16) -> Result<ServiceBuilder<'_, 'd, M, MAX>, Error> {
+        if handle < self.handle {
+            return Err(Error::InvalidValue);
+        }
+        self.handle = handle;
+        Ok(self.add_service(service))
+    }
+
     /// Add a service to the attribute table (group of characteristics)
     pub fn add_service(&mut self, service: Service) -> ServiceBuilder<'_, 'd, M, MAX> {
         let len = self.inner.lock(|i| i.borrow().attributes.len());
@@ -354,6 +629,7 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
             uuid: PRIMARY_SERVICE.into(),
             handle: 0,
             last_handle_in_group: 0,
+            permissions: AttributePermissions::default(),
             data: AttributeData::Service { uuid: service.uuid },
         });
         ServiceBuilder {
@@ -372,6 +648,9 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
                         value,
                         variable_len,
                         len,
+                        #[cfg(feature = "gatt-last-modified")]
+                        modified,
+                        ..
                     } = &mut att.data
                     {
                         let expected_len = value.len();
@@ -379,17 +658,21 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
 
                         if expected_len == actual_len {
                             value.copy_from_slice(input);
-                            return Ok(());
+                            *len = actual_len as u16;
                         } else if *variable_len && actual_len <= expected_len {
                             value[..input.len()].copy_from_slice(input);
                             *len = input.len() as u16;
-                            return Ok(());
                         } else {
                             return Err(Error::UnexpectedDataLength {
                                 expected: expected_len,
                                 actual: actual_len,
                             });
                         }
+                        #[cfg(feature = "gatt-last-modified")]
+                        {
+                            *modified = Some(self.modified());
+                        }
+                        return Ok(());
                     }
                 }
             }
@@ -397,6 +680,20 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         })
     }
 
+    /// Return the handle of the first characteristic value with the given UUID.
+    pub(crate) fn find_value_handle(&self, uuid: &Uuid) -> Option<u16> {
+        self.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if &att.uuid == uuid {
+                    if let AttributeData::Data { .. } | AttributeData::ReadOnlyData { .. } = att.data {
+                        return Some(att.handle);
+                    }
+                }
+            }
+            None
+        })
+    }
+
     /// Set the value of a characteristic
     ///
     /// The provided data must exactly match the size of the storage for the characteristic,
@@ -423,6 +720,7 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
                         value,
                         variable_len,
                         len,
+                        ..
                     } = &mut att.data
                     {
                         let value_slice = if *variable_len { &value[..*len as usize] } else { value };
@@ -444,6 +742,54 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
         })
     }
 
+    /// Access the stored value of a characteristic in place, without copying it out of the table.
+    ///
+    /// Useful for large values kept in storage provided by the application, such as a firmware chunk or a
+    /// configuration written by a client. The table is locked while the closure runs.
+    ///
+    /// If the characteristic for the handle cannot be found, an error is returned.
+    pub fn with_value<T: AttributeHandle, R>(
+        &self,
+        attribute_handle: &T,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, Error> {
+        let mut f = Some(f);
+        self.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == attribute_handle.handle() {
+                    if let AttributeData::Data {
+                        value,
+                        variable_len,
+                        len,
+                        ..
+                    } = &att.data
+                    {
+                        let value_slice = if *variable_len { &value[..*len as usize] } else { value };
+                        return Ok(unwrap!(f.take())(value_slice));
+                    }
+                }
+            }
+            Err(Error::NotFound)
+        })
+    }
+
+    /// Return the last change to the value of a characteristic, or `None` if it still has its starting value.
+    ///
+    /// If the characteristic for the handle cannot be found, an error is returned.
+    #[cfg(feature = "gatt-last-modified")]
+    pub fn last_modified<T: AttributeHandle>(&self, attribute_handle: &T) -> Result<Option<LastModified>, Error> {
+        self.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == attribute_handle.handle() {
+                    if let AttributeData::Data { modified, .. } = &att.data {
+                        return Ok(*modified);
+                    }
+                }
+            }
+            Err(Error::NotFound)
+        })
+    }
+
     /// Return the characteristic which corresponds to the supplied value handle
     ///
     /// If no characteristic corresponding to the given value handle was found, returns an error
@@ -461,12 +807,14 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
                             return Ok(Characteristic {
                                 handle,
                                 cccd_handle: Some(next.handle),
+                                latency_critical: false,
                                 phantom: PhantomData,
                             });
                         } else {
                             return Ok(Characteristic {
                                 handle,
                                 cccd_handle: None,
+                                latency_critical: false,
                                 phantom: PhantomData,
                             });
                         }
@@ -474,6 +822,7 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
                         return Ok(Characteristic {
                             handle,
                             cccd_handle: None,
+                            latency_critical: false,
                             phantom: PhantomData,
                         });
                     }
@@ -484,6 +833,30 @@ impl<'d, M: RawMutex, const MAX: usize> AttributeTable<'d, M, MAX> {
     }
 }
 
+/// A service which can be embedded as a field of a `#[gatt_server]` struct.
+///
+/// Implemented by `#[gatt_service]` structs and by the services in [`crate::services`]. Library crates
+/// implement it to export pre-built services: the server registers each service in its own attribute
+/// table, which allocates the handles, and sizes the table from the counts.
+pub trait GattService: Sized {
+    /// Number of attributes the service adds to the table.
+    const ATTRIBUTE_COUNT: usize;
+    /// Number of CCCDs the service adds to the table.
+    const CCCD_COUNT: usize;
+    /// Fixed handle of the service declaration, if any.
+    const HANDLE_START: Option<u16> = None;
+
+    /// Add the service to the attribute table.
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self;
+
+    /// Add the service to the attribute table, failing if the starting value of a characteristic cannot be produced.
+    fn try_register<M: RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'_, M, MAX>,
+    ) -> Result<Self, &'static str> {
+        Ok(Self::register(table))
+    }
+}
+
 /// A type which holds a handle to an attribute in the attribute table
 pub trait AttributeHandle {
     /// The data type which the attribute contains
@@ -522,6 +895,7 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
             uuid: CHARACTERISTIC.into(),
             handle: 0,
             last_handle_in_group: 0,
+            permissions: AttributePermissions::default(),
             data: AttributeData::Declaration {
                 props,
                 handle: next,
@@ -534,6 +908,7 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
             uuid,
             handle: 0,
             last_handle_in_group: 0,
+            permissions: AttributePermissions::default(),
             data,
         });
 
@@ -543,6 +918,7 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
                 uuid: CLIENT_CHARACTERISTIC_CONFIGURATION.into(),
                 handle: 0,
                 last_handle_in_group: 0,
+                permissions: AttributePermissions::default(),
                 data: AttributeData::Cccd {
                     notifications: false,
                     indications: false,
@@ -557,6 +933,7 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
             handle: Characteristic {
                 handle: next,
                 cccd_handle,
+                latency_critical: false,
                 phantom: PhantomData,
             },
             table: self.table,
@@ -584,6 +961,9 @@ impl<'d, M: RawMutex, const MAX: usize> ServiceBuilder<'_, 'd, M, MAX> {
                 value: store,
                 variable_len,
                 len,
+                validate: T::validate,
+                #[cfg(feature = "gatt-last-modified")]
+                modified: None,
             },
         )
     }
@@ -633,6 +1013,8 @@ pub struct Characteristic<T: AsGatt> {
     pub cccd_handle: Option<u16>,
     /// Handle value assigned to this characteristic when it is added to the Gatt Attribute Table
     pub handle: u16,
+    /// Notifications are sent ahead of other outbound data on the connection.
+    pub(crate) latency_critical: bool,
     pub(crate) phantom: PhantomData<T>,
 }
 
@@ -642,6 +1024,8 @@ impl<T: FromGatt> Characteristic<T> {
     /// If the provided connection has not subscribed for this characteristic, it will not be notified.
     ///
     /// If the characteristic does not support notifications, an error is returned.
+    ///
+    /// If the packet pool is exhausted, waits until a packet is available.
     pub async fn notify<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &T) -> Result<(), Error> {
         let value = value.as_gatt();
         let server = connection.server;
@@ -654,22 +1038,99 @@ impl<T: FromGatt> Characteristic<T> {
             return Ok(());
         }
 
-        let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
-        let mut w = WriteCursor::new(tx.as_mut());
-        let (mut header, mut data) = w.split(4)?;
-        data.write(crate::att::ATT_HANDLE_VALUE_NTF)?;
-        data.write(self.handle)?;
-        data.append(value)?;
+        let tx = P::allocate_async().await;
+        let pdu = self.notification(connection, tx, value)?;
+        if !server.filter_outgoing(connection, &pdu.as_ref()[connection.header_len()..]) {
+            return Ok(());
+        }
+        if self.latency_critical {
+            connection.send_priority(pdu).await;
+        } else {
+            connection.send(pdu).await;
+        }
+        Ok(())
+    }
+
+    /// Write a value to a characteristic, and notify a connection with the new value of the characteristic.
+    ///
+    /// If the provided connection has not subscribed for this characteristic, it will not be notified.
+    ///
+    /// If the characteristic does not support notifications, an error is returned.
+    ///
+    /// If the packet pool is exhausted or the outbound queue is full, returns Error::OutOfMemory.
+    pub fn try_notify<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>, value: &T) -> Result<(), Error> {
+        let value = value.as_gatt();
+        let server = connection.server;
+        server.set(self.handle, value)?;
+
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        let connection = connection.raw();
+        if !server.should_notify(connection, cccd_handle) {
+            // No reason to fail?
+            return Ok(());
+        }
+
+        let tx = P::allocate().ok_or(Error::OutOfMemory)?;
+        let pdu = self.notification(connection, tx, value)?;
+        if !server.filter_outgoing(connection, &pdu.as_ref()[connection.header_len()..]) {
+            return Ok(());
+        }
+        if self.latency_critical {
+            connection.try_send_priority(pdu)
+        } else {
+            connection.try_send(pdu)
+        }
+    }
 
-        header.write(data.len() as u16)?;
-        header.write(4_u16)?;
-        let total = header.len() + data.len();
+    /// Stream an arbitrary length payload to a connection as a sequence of notifications.
+    ///
+    /// The payload is split into chunks that fit the ATT MTU of the connection, which are sent in order.
+    /// Waits for packets and outbound queue space between chunks, so a slow peer slows down the stream
+    /// rather than dropping data. The stored value of the characteristic is not changed.
+    ///
+    /// If the provided connection has not subscribed for this characteristic, nothing is sent.
+    ///
+    /// If the characteristic does not support notifications, an error is returned.
+    pub async fn notify_stream<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        data: &[u8],
+    ) -> Result<(), Error> {
+        let server = connection.server;
+        let cccd_handle = self.cccd_handle.ok_or(Error::NotFound)?;
+        let connection = connection.raw();
+        if !server.should_notify(connection, cccd_handle) {
+            return Ok(());
+        }
 
-        let pdu = crate::pdu::Pdu::new(tx, total);
-        connection.send(pdu).await;
+        // Each notification carries a 3 byte opcode and handle, and the packet a 4 byte L2CAP header
+        let chunk_len = (usize::from(connection.att_mtu()) - 3).min(P::MTU - 4 - 3);
+        for chunk in data.chunks(chunk_len) {
+            let tx = P::allocate_async().await;
+            let pdu = self.notification(connection, tx, chunk)?;
+            if server.filter_outgoing(connection, &pdu.as_ref()[connection.header_len()..]) {
+                connection.send(pdu).await;
+            }
+        }
         Ok(())
     }
 
+    fn notification<P: PacketPool>(
+        &self,
+        connection: &Connection<'_, P>,
+        tx: P::Packet,
+        value: &[u8],
+    ) -> Result<crate::pdu::Pdu<P::Packet>, Error> {
+        let pdu = connection.frame(tx, |buf| {
+            let mut w = WriteCursor::new(buf);
+            w.write(crate::att::ATT_HANDLE_VALUE_NTF)?;
+            w.write(self.handle)?;
+            w.append(value)?;
+            Ok(Some(w.len()))
+        })?;
+        Ok(unwrap!(pdu))
+    }
+
     /// Set the value of the characteristic in the provided attribute server.
     pub fn set<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
         &self,
@@ -692,6 +1153,28 @@ impl<T: FromGatt> Characteristic<T> {
         server.table().get(self)
     }
 
+    /// Access the stored value of the characteristic in place, see [`AttributeTable::with_value`].
+    pub fn with_value<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize, R>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> Result<R, Error> {
+        server.table().with_value(self, f)
+    }
+
+    /// Return the last change to the value of the characteristic, by a client write or locally, or `None` if it
+    /// still has its starting value.
+    ///
+    /// Sequence numbers increase with each change to a value of the server, so they order changes to different
+    /// characteristics, i.e. to resolve conflicts with a peer after reconnecting.
+    #[cfg(feature = "gatt-last-modified")]
+    pub fn last_modified<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+    ) -> Result<Option<LastModified>, Error> {
+        server.table().last_modified(self)
+    }
+
     /// Returns the attribute handle for the characteristic's properties (if available)
     pub fn cccd_handle(&self) -> Option<CharacteristicPropertiesHandle> {
         self.cccd_handle.map(CharacteristicPropertiesHandle)
@@ -727,6 +1210,7 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
             uuid,
             handle: 0,
             last_handle_in_group: 0,
+            permissions: AttributePermissions::default(),
             data,
         });
 
@@ -753,6 +1237,9 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
                 value: data,
                 variable_len: false,
                 len,
+                validate: DT::validate,
+                #[cfg(feature = "gatt-last-modified")]
+                modified: None,
             },
         )
     }
@@ -763,6 +1250,34 @@ impl<'d, T: AsGatt, M: RawMutex, const MAX: usize> CharacteristicBuilder<'_, 'd,
         self.add_descriptor_internal(uuid.into(), props, AttributeData::ReadOnlyData { props, value: data })
     }
 
+    /// Mark the characteristic as latency critical.
+    ///
+    /// Notifications for a latency critical characteristic, such as HID input reports, are queued ahead of other
+    /// outbound data on the connection, including data on L2CAP channels.
+    pub fn latency_critical(mut self) -> Self {
+        self.handle.latency_critical = true;
+        self
+    }
+
+    /// Set the security required to access the characteristic value.
+    ///
+    /// Requests on links not meeting it are rejected with insufficient encryption, or with insufficient
+    /// encryption key size when the link key is shorter than the minimum of the security policy. Subscribing to
+    /// notifications or indications requires the security to read the value.
+    pub fn permissions(self, permissions: AttributePermissions) -> Self {
+        let (value, cccd) = (self.handle.handle, self.handle.cccd_handle);
+        self.table.with_inner(|table| {
+            for att in table.attributes.iter_mut() {
+                if att.handle == value {
+                    att.permissions = permissions;
+                } else if Some(att.handle) == cccd {
+                    att.permissions.write = permissions.read;
+                }
+            }
+        });
+        self
+    }
+
     /// Return the built characteristic.
     pub fn build(self) -> Characteristic<T> {
         self.handle
@@ -1,15 +1,84 @@
-use core::cell::RefCell;
+use core::cell::{Cell, RefCell};
 use core::marker::PhantomData;
 
+use bt_hci::param::ConnHandle;
 use embassy_sync::blocking_mutex::raw::RawMutex;
 use embassy_sync::blocking_mutex::Mutex;
 
-use crate::att::{self, AttClient, AttCmd, AttErrorCode, AttReq};
-use crate::attribute::{Attribute, AttributeData, AttributeTable, CCCD};
-use crate::cursor::WriteCursor;
+use crate::att::{self, Att, AttClient, AttCmd, AttErrorCode, AttReq};
+use crate::attribute::{Attribute, AttributeData, AttributeSecurity, AttributeTable, CCCD};
+use crate::cursor::{ReadCursor, WriteCursor};
 use crate::prelude::Connection;
 use crate::types::uuid::Uuid;
-use crate::{codec, Error, Identity, PacketPool};
+use crate::{codec, config, Error, Identity, PacketPool};
+
+/// Version of the snapshot format written by [`AttributeServer::snapshot`].
+const SNAPSHOT_VERSION: u8 = 1;
+
+/// Bytes of prepared writes held for a client until executed.
+const PREPARE_QUEUE_SIZE: usize = config::GATT_PREPARE_QUEUE_SIZE;
+
+/// Number of prepared writes held for a client, enough for a long write split at the minimum ATT MTU.
+const PREPARE_QUEUE_ENTRIES: usize = PREPARE_QUEUE_SIZE / 16;
+
+struct PreparedWrite {
+    handle: u16,
+    offset: u16,
+    len: u16,
+    /// Length of the value before the write was applied, to undo it.
+    previous_len: u16,
+}
+
+/// Prepared writes of a client, applied to the attributes once executed.
+struct PrepareQueue {
+    owner: Option<ConnHandle>,
+    writes: heapless::Vec<PreparedWrite, PREPARE_QUEUE_ENTRIES>,
+    data: [u8; PREPARE_QUEUE_SIZE],
+    used: usize,
+}
+
+impl PrepareQueue {
+    const fn new() -> Self {
+        Self {
+            owner: None,
+            writes: heapless::Vec::new(),
+            data: [0; PREPARE_QUEUE_SIZE],
+            used: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.owner = None;
+        self.writes.clear();
+        self.used = 0;
+    }
+
+    fn push(&mut self, owner: ConnHandle, handle: u16, offset: u16, value: &[u8]) -> Result<(), AttErrorCode> {
+        if self.owner.is_some_and(|o| o != owner) || self.used + value.len() > self.data.len() {
+            return Err(AttErrorCode::PREPARE_QUEUE_FULL);
+        }
+        let write = PreparedWrite {
+            handle,
+            offset,
+            len: value.len() as u16,
+            previous_len: 0,
+        };
+        self.writes.push(write).map_err(|_| AttErrorCode::PREPARE_QUEUE_FULL)?;
+        self.data[self.used..self.used + value.len()].copy_from_slice(value);
+        self.used += value.len();
+        self.owner = Some(owner);
+        Ok(())
+    }
+}
+
+/// The prepare queue of `owner`, or a free one to hold its writes if `reserve` is set.
+fn prepare_queue(queues: &mut [PrepareQueue], owner: ConnHandle, reserve: bool) -> Option<&mut PrepareQueue> {
+    let index = queues
+        .iter()
+        .position(|queue| queue.owner == Some(owner))
+        .or_else(|| queues.iter().position(|queue| reserve && queue.owner.is_none()))?;
+    Some(&mut queues[index])
+}
 
 #[derive(Default)]
 struct Client {
@@ -228,6 +297,44 @@ impl<M: RawMutex, const CCCD_MAX: usize, const CONN_MAX: usize> CccdTables<M, CC
         })
     }
 
+    fn snapshot(&self, w: &mut WriteCursor<'_>) -> Result<(), Error> {
+        self.state.lock(|n| {
+            let n = n.borrow();
+            for (client, table) in n.iter() {
+                client.identity.write_snapshot(w)?;
+                for (handle, value) in table.inner.iter() {
+                    w.write(*handle)?;
+                    w.write(value.raw())?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn restore(&self, r: &mut ReadCursor<'_>, apply: bool) -> Result<(), Error> {
+        self.state.lock(|n| {
+            let mut n = n.borrow_mut();
+            for (client, table) in n.iter_mut() {
+                let identity = Identity::read_snapshot(r)?;
+                for (handle, value) in table.inner.iter_mut() {
+                    let stored_handle: u16 = r.read()?;
+                    let stored_value: u16 = r.read()?;
+                    if stored_handle != *handle {
+                        return Err(Error::InvalidValue);
+                    }
+                    if apply {
+                        *value = CCCD(stored_value);
+                    }
+                }
+                if apply {
+                    client.identity = identity;
+                    client.is_connected = false;
+                }
+            }
+            Ok(())
+        })
+    }
+
     fn update_identity(&self, identity: Identity) -> Result<(), Error> {
         self.state.lock(|n| {
             let mut n = n.borrow_mut();
@@ -253,9 +360,137 @@ pub struct AttributeServer<
 > {
     att_table: AttributeTable<'values, M, ATT_MAX>,
     cccd_tables: CccdTables<M, CCCD_MAX, CONN_MAX>,
+    middleware: Mutex<M, Cell<Option<&'values (dyn AttMiddleware + Sync)>>>,
+    prepare_queues: Mutex<M, RefCell<[PrepareQueue; CONN_MAX]>>,
     _p: PhantomData<P>,
 }
 
+/// Middleware observing the ATT PDUs entering and leaving an [`AttributeServer`].
+///
+/// Registered with [`AttributeServer::set_middleware`], e.g. for protocol analyzers, access logs or conformance
+/// test shims. PDUs are passed without the L2CAP header, and can be decoded with [`Att::decode`](crate::att::Att::decode).
+pub trait AttMiddleware {
+    /// A PDU was received from the client of `connection`.
+    ///
+    /// Returning an error rejects the PDU before it reaches the application: requests are answered with an error
+    /// response carrying the code, other PDUs are dropped.
+    fn incoming(&self, connection: ConnHandle, pdu: &[u8]) -> Result<(), AttErrorCode> {
+        let _ = (connection, pdu);
+        Ok(())
+    }
+
+    /// A PDU is about to be sent to the client of `connection`. Returning `false` vetoes it.
+    ///
+    /// A vetoed response is replaced with an error response with [`AttErrorCode::UNLIKELY_ERROR`], so that the
+    /// client does not wait for it until the ATT transaction times out. Other PDUs are dropped.
+    fn outgoing(&self, connection: ConnHandle, pdu: &[u8]) -> bool {
+        let _ = (connection, pdu);
+        true
+    }
+}
+
+/// The client of an [`AttributeServer`], as seen by the server while processing its PDUs.
+///
+/// Implemented by [`Connection`] for servers run by the host, and by [`PeerInfo`] to drive a server from another
+/// runtime with [`AttributeServer::process_pdu`].
+pub trait AttPeer {
+    /// Handle of the connection, passed to the [`AttMiddleware`].
+    fn handle(&self) -> ConnHandle;
+
+    /// Identity of the client, keying its CCCD values.
+    fn identity(&self) -> Identity;
+
+    /// ATT MTU of the connection.
+    fn att_mtu(&self) -> u16;
+
+    /// ATT MTU the server can receive, sent in response to MTU exchange requests. Defaults to the ATT MTU.
+    fn rx_mtu(&self) -> u16 {
+        self.att_mtu()
+    }
+
+    /// Whether the connection is encrypted.
+    fn encrypted(&self) -> bool;
+
+    /// Whether the encryption of the connection meets the security policy, e.g. its minimum encryption key size.
+    ///
+    /// Attributes requiring encryption are only accessible on links meeting it. Defaults to [`encrypted`](Self::encrypted).
+    fn encryption_sufficient(&self) -> bool {
+        self.encrypted()
+    }
+
+    /// Verify the signature of a signed write from the client. Signed writes failing verification are dropped.
+    fn verify_signature(&self, parts: &[&[u8]], signature: &[u8; 12]) -> bool {
+        let _ = (parts, signature);
+        false
+    }
+}
+
+impl<P: PacketPool> AttPeer for Connection<'_, P> {
+    fn handle(&self) -> ConnHandle {
+        Connection::handle(self)
+    }
+
+    fn identity(&self) -> Identity {
+        self.peer_identity()
+    }
+
+    fn att_mtu(&self) -> u16 {
+        self.get_att_mtu()
+    }
+
+    fn encrypted(&self) -> bool {
+        Connection::encrypted(self)
+    }
+
+    #[cfg(feature = "security")]
+    fn encryption_sufficient(&self) -> bool {
+        Connection::encryption_meets_policy(self)
+    }
+
+    #[cfg(feature = "security")]
+    fn verify_signature(&self, parts: &[&[u8]], signature: &[u8; 12]) -> bool {
+        Connection::verify_signature(self, parts, signature)
+    }
+}
+
+/// State of a client connected through another stack than the host, for [`AttributeServer::process_pdu`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PeerInfo {
+    /// Handle of the connection.
+    pub handle: ConnHandle,
+    /// Identity of the client.
+    pub identity: Identity,
+    /// ATT MTU of the connection, bounding the responses of the server.
+    pub att_mtu: u16,
+    /// ATT MTU the server can receive, sent in response to MTU exchange requests.
+    pub rx_mtu: u16,
+    /// Whether the connection is encrypted.
+    pub encrypted: bool,
+}
+
+impl AttPeer for PeerInfo {
+    fn handle(&self) -> ConnHandle {
+        self.handle
+    }
+
+    fn identity(&self) -> Identity {
+        self.identity
+    }
+
+    fn att_mtu(&self) -> u16 {
+        self.att_mtu
+    }
+
+    fn rx_mtu(&self) -> u16 {
+        self.rx_mtu
+    }
+
+    fn encrypted(&self) -> bool {
+        self.encrypted
+    }
+}
+
 pub(crate) mod sealed {
     use super::*;
 
@@ -271,6 +506,9 @@ pub(crate) mod sealed {
         fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool;
         fn set(&self, characteristic: u16, input: &[u8]) -> Result<(), Error>;
         fn update_identity(&self, identity: Identity) -> Result<(), Error>;
+        fn filter_incoming(&self, connection: &Connection<'_, P>, pdu: &[u8]) -> Result<(), AttErrorCode>;
+        fn filter_outgoing(&self, connection: &Connection<'_, P>, pdu: &[u8]) -> bool;
+        fn accepts_write_cmd(&self, connection: &Connection<'_, P>, handle: u16) -> bool;
     }
 }
 
@@ -289,7 +527,7 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
     }
 
     fn disconnect(&self, connection: &Connection<'_, P>) {
-        self.cccd_tables.disconnect(&connection.peer_identity());
+        AttributeServer::disconnect(self, connection)
     }
 
     fn process(
@@ -313,6 +551,24 @@ impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, co
     fn update_identity(&self, identity: Identity) -> Result<(), Error> {
         self.cccd_tables.update_identity(identity)
     }
+
+    fn filter_incoming(&self, connection: &Connection<'_, P>, pdu: &[u8]) -> Result<(), AttErrorCode> {
+        match self.middleware.lock(|m| m.get()) {
+            Some(middleware) => middleware.incoming(connection.handle(), pdu),
+            None => Ok(()),
+        }
+    }
+
+    fn accepts_write_cmd(&self, connection: &Connection<'_, P>, handle: u16) -> bool {
+        AttributeServer::accepts_write_cmd(self, connection, handle)
+    }
+
+    fn filter_outgoing(&self, connection: &Connection<'_, P>, pdu: &[u8]) -> bool {
+        match self.middleware.lock(|m| m.get()) {
+            Some(middleware) => middleware.outgoing(connection.handle(), pdu),
+            None => true,
+        }
+    }
 }
 
 impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, const CONN_MAX: usize>
@@ -326,30 +582,63 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         AttributeServer {
             att_table,
             cccd_tables,
+            middleware: Mutex::new(Cell::new(None)),
+            prepare_queues: Mutex::new(RefCell::new([const { PrepareQueue::new() }; CONN_MAX])),
             _p: PhantomData,
         }
     }
 
-    pub(crate) fn connect(&self, connection: &Connection<'_, P>) -> Result<(), Error> {
-        self.cccd_tables.connect(&connection.peer_identity())
+    /// Register a connected client, restoring the CCCD values of a returning client.
+    ///
+    /// Called by the host for each new connection, only needed when driving the server with
+    /// [`AttributeServer::process_pdu`].
+    pub fn connect(&self, connection: &impl AttPeer) -> Result<(), Error> {
+        self.cccd_tables.connect(&connection.identity())
     }
 
-    pub(crate) fn should_notify(&self, connection: &Connection<'_, P>, cccd_handle: u16) -> bool {
-        self.cccd_tables.should_notify(&connection.peer_identity(), cccd_handle)
+    /// Register the disconnection of a client, keeping its CCCD values until the slot is needed by another client.
+    ///
+    /// Called by the host when a connection is closed, only needed when driving the server with
+    /// [`AttributeServer::process_pdu`].
+    pub fn disconnect(&self, connection: &impl AttPeer) {
+        self.cccd_tables.disconnect(&connection.identity());
+        self.prepare_queues.lock(|queues| {
+            if let Some(queue) = prepare_queue(&mut queues.borrow_mut()[..], connection.handle(), false) {
+                queue.clear();
+            }
+        });
+    }
+
+    /// Whether the client subscribed to notifications or indications of the characteristic with the CCCD handle.
+    pub fn should_notify(&self, connection: &impl AttPeer, cccd_handle: u16) -> bool {
+        self.cccd_tables.should_notify(&connection.identity(), cccd_handle)
+    }
+
+    /// Check the security of the link against the security required to access an attribute.
+    fn check_security(connection: &impl AttPeer, security: AttributeSecurity) -> Result<(), AttErrorCode> {
+        match security {
+            AttributeSecurity::None => Ok(()),
+            AttributeSecurity::Encrypted if !connection.encrypted() => Err(AttErrorCode::INSUFFICIENT_ENCRYPTION),
+            AttributeSecurity::Encrypted if !connection.encryption_sufficient() => {
+                Err(AttErrorCode::INSUFFICIENT_ENCRYPTION_KEY_SIZE)
+            }
+            AttributeSecurity::Encrypted => Ok(()),
+        }
     }
 
     fn read_attribute_data(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         offset: usize,
         att: &mut Attribute<'values>,
         data: &mut [u8],
     ) -> Result<usize, AttErrorCode> {
+        Self::check_security(connection, att.permissions.read)?;
         if let AttributeData::Cccd { .. } = att.data {
             // CCCD values for each connected client are held in the CCCD tables:
             // the value is written back into att.data so att.read() has the final
             // say when parsing at the requested offset.
-            if let Some(value) = self.cccd_tables.get_value(&connection.peer_identity(), att.handle) {
+            if let Some(value) = self.cccd_tables.get_value(&connection.identity(), att.handle) {
                 let _ = att.write(0, value.as_slice());
             }
         }
@@ -358,20 +647,25 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
 
     fn write_attribute_data(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         offset: usize,
         att: &mut Attribute<'values>,
         data: &[u8],
     ) -> Result<(), AttErrorCode> {
+        Self::check_security(connection, att.permissions.write)?;
         let err = att.write(offset, data);
         if err.is_ok() {
+            #[cfg(feature = "gatt-last-modified")]
+            if let AttributeData::Data { modified, .. } = &mut att.data {
+                *modified = Some(self.att_table.modified());
+            }
             if let AttributeData::Cccd {
                 notifications,
                 indications,
             } = att.data
             {
                 self.cccd_tables
-                    .set_notify(&connection.peer_identity(), att.handle, notifications);
+                    .set_notify(&connection.identity(), att.handle, notifications);
             }
         }
         err
@@ -379,7 +673,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
 
     fn handle_read_by_type_req(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         start: u16,
         end: u16,
@@ -394,16 +688,36 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
             while let Some(att) = it.next() {
                 // trace!("[read_by_type] Check attribute {:?} {}", att.uuid, att.handle);
                 if &att.uuid == attribute_type && att.handle >= start && att.handle <= end {
-                    body.write(att.handle)?;
-                    handle = att.handle;
-
-                    err = self.read_attribute_data(connection, 0, att, body.write_buf());
-                    if let Ok(len) = err {
-                        body.commit(len)?;
+                    match err {
+                        Err(_) => {
+                            body.write(att.handle)?;
+                            handle = att.handle;
+
+                            // The length of each entry is encoded in a single byte.
+                            let max = body.available().min(u8::MAX as usize - 2);
+                            err = self.read_attribute_data(connection, 0, att, &mut body.write_buf()[..max]);
+                            match err {
+                                Ok(len) => body.commit(len)?,
+                                Err(_) => break,
+                            }
+                        }
+                        // Batch further attributes with the same value length into the response.
+                        Ok(len) => {
+                            if att.value_len() != len || body.available() < 2 + len {
+                                break;
+                            }
+                            let pos = body.len();
+                            body.write(att.handle)?;
+                            match self.read_attribute_data(connection, 0, att, &mut body.write_buf()[..len]) {
+                                Ok(n) => body.commit(n)?,
+                                Err(_) => {
+                                    body.truncate(pos);
+                                    break;
+                                }
+                            }
+                        }
                     }
-
                     // debug!("[read_by_type] found! {:?} {}", att.uuid, att.handle);
-                    break;
                 }
             }
             err
@@ -421,13 +735,12 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
 
     fn handle_read_by_group_type_req(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         start: u16,
         end: u16,
         group_type: &Uuid,
     ) -> Result<usize, codec::Error> {
-        // TODO respond with all finds - not just one
         let mut handle = start;
         let mut data = WriteCursor::new(buf);
 
@@ -438,15 +751,37 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 // trace!("[read_by_group] Check attribute {:x} {}", att.uuid, att.handle);
                 if &att.uuid == group_type && att.handle >= start && att.handle <= end {
                     // debug!("[read_by_group] found! {:x} {}", att.uuid, att.handle);
-                    handle = att.handle;
-
-                    body.write(att.handle)?;
-                    body.write(att.last_handle_in_group)?;
-                    err = self.read_attribute_data(connection, 0, att, body.write_buf());
-                    if let Ok(len) = err {
-                        body.commit(len)?;
+                    match err {
+                        Err(_) => {
+                            handle = att.handle;
+
+                            body.write(att.handle)?;
+                            body.write(att.last_handle_in_group)?;
+                            // The length of each entry is encoded in a single byte.
+                            let max = body.available().min(u8::MAX as usize - 4);
+                            err = self.read_attribute_data(connection, 0, att, &mut body.write_buf()[..max]);
+                            match err {
+                                Ok(len) => body.commit(len)?,
+                                Err(_) => break,
+                            }
+                        }
+                        // Batch further groups with the same value length into the response.
+                        Ok(len) => {
+                            if att.value_len() != len || body.available() < 4 + len {
+                                break;
+                            }
+                            let pos = body.len();
+                            body.write(att.handle)?;
+                            body.write(att.last_handle_in_group)?;
+                            match self.read_attribute_data(connection, 0, att, &mut body.write_buf()[..len]) {
+                                Ok(n) => body.commit(n)?,
+                                Err(_) => {
+                                    body.truncate(pos);
+                                    break;
+                                }
+                            }
+                        }
                     }
-                    break;
                 }
             }
             err
@@ -462,12 +797,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         }
     }
 
-    fn handle_read_req(
-        &self,
-        connection: &Connection<'_, P>,
-        buf: &mut [u8],
-        handle: u16,
-    ) -> Result<usize, codec::Error> {
+    fn handle_read_req(&self, connection: &impl AttPeer, buf: &mut [u8], handle: u16) -> Result<usize, codec::Error> {
         let mut data = WriteCursor::new(buf);
 
         data.write(att::ATT_READ_RSP)?;
@@ -494,7 +824,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
 
     fn handle_write_cmd(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         handle: u16,
         data: &[u8],
@@ -503,7 +833,9 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
             while let Some(att) = it.next() {
                 if att.handle == handle {
                     // Write commands can't respond with an error.
-                    let _ = self.write_attribute_data(connection, 0, att, data);
+                    if att.data.command_writable() {
+                        let _ = self.write_attribute_data(connection, 0, att, data);
+                    }
                     break;
                 }
             }
@@ -511,9 +843,45 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         Ok(0)
     }
 
+    /// Whether a write command to the attribute would be accepted, without writing it.
+    fn accepts_write_cmd(&self, connection: &impl AttPeer, handle: u16) -> bool {
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if att.handle == handle {
+                    return att.data.command_writable()
+                        && Self::check_security(connection, att.permissions.write).is_ok();
+                }
+            }
+            false
+        })
+    }
+
+    fn handle_signed_write_cmd(
+        &self,
+        connection: &impl AttPeer,
+        handle: u16,
+        data: &[u8],
+        signature: &[u8; 12],
+    ) -> Result<usize, codec::Error> {
+        // Signed writes without a valid signature are dropped, as commands can't respond with an error.
+        if connection.verify_signature(&[&[att::ATT_SIGNED_WRITE_CMD], &handle.to_le_bytes(), data], signature) {
+            self.att_table.iterate(|mut it| {
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        if att.data.signed_writable() {
+                            let _ = self.write_attribute_data(connection, 0, att, data);
+                        }
+                        break;
+                    }
+                }
+            });
+        }
+        Ok(0)
+    }
+
     fn handle_write_req(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         handle: u16,
         data: &[u8],
@@ -595,6 +963,9 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                     } else if t != att.uuid.get_type() {
                         break;
                     }
+                    if body.available() < 2 + att.uuid.as_raw().len() {
+                        break;
+                    }
                     body.write(att.handle)?;
                     body.append(att.uuid.as_raw())?;
                 }
@@ -631,7 +1002,7 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
 
     fn handle_prepare_write(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         handle: u16,
         offset: u16,
@@ -642,33 +1013,140 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         w.write(handle)?;
         w.write(offset)?;
 
+        // Values are validated and written once executed, only the access is checked here.
         let err = self.att_table.iterate(|mut it| {
-            let mut err = Err(AttErrorCode::ATTRIBUTE_NOT_FOUND);
             while let Some(att) = it.next() {
                 if att.handle == handle {
-                    err = self.write_attribute_data(connection, offset as usize, att, value);
-                    w.append(value)?;
-                    break;
+                    Self::check_security(connection, att.permissions.write)?;
+                    if !matches!(att.data, AttributeData::Data { .. }) || !att.data.writable() {
+                        return Err(AttErrorCode::WRITE_NOT_PERMITTED);
+                    }
+                    return self.prepare_queues.lock(|queues| {
+                        let owner = connection.handle();
+                        match prepare_queue(&mut queues.borrow_mut()[..], owner, true) {
+                            Some(queue) => queue.push(owner, handle, offset, value),
+                            None => Err(AttErrorCode::PREPARE_QUEUE_FULL),
+                        }
+                    });
                 }
             }
-            err
+            Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
         });
 
         match err {
-            Ok(()) => Ok(w.len()),
+            Ok(()) => {
+                w.append(value)?;
+                Ok(w.len())
+            }
             Err(e) => Ok(Self::error_response(w, att::ATT_PREPARE_WRITE_REQ, handle, e)?),
         }
     }
 
-    fn handle_execute_write(&self, buf: &mut [u8], _flags: u8) -> Result<usize, codec::Error> {
+    fn handle_execute_write(
+        &self,
+        connection: &impl AttPeer,
+        buf: &mut [u8],
+        flags: u8,
+    ) -> Result<usize, codec::Error> {
         let mut w = WriteCursor::new(buf);
-        w.write(att::ATT_EXECUTE_WRITE_RSP)?;
-        Ok(w.len())
+        let result = self.prepare_queues.lock(|queues| {
+            let mut queues = queues.borrow_mut();
+            let Some(queue) = prepare_queue(&mut queues[..], connection.handle(), false) else {
+                return Ok(());
+            };
+            // Flags of 0 cancel the prepared writes
+            let result = if flags == 1 {
+                self.execute_prepared_writes(queue)
+            } else {
+                Ok(())
+            };
+            queue.clear();
+            result
+        });
+
+        match result {
+            Ok(()) => {
+                w.write(att::ATT_EXECUTE_WRITE_RSP)?;
+                Ok(w.len())
+            }
+            Err((handle, e)) => Ok(Self::error_response(w, att::ATT_EXECUTE_WRITE_REQ, handle, e)?),
+        }
+    }
+
+    /// Apply the prepared writes in order and validate the resulting values.
+    ///
+    /// The writes are swapped with the bytes they replace, so that all of them are undone if any is out of
+    /// bounds or leaves an invalid value.
+    fn execute_prepared_writes(&self, queue: &mut PrepareQueue) -> Result<(), (u16, AttErrorCode)> {
+        let with_data = |handle: u16, f: &mut dyn FnMut(&mut AttributeData<'values>) -> Result<usize, AttErrorCode>| {
+            self.att_table.iterate(|mut it| {
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        return f(&mut att.data);
+                    }
+                }
+                Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+            })
+        };
+
+        let PrepareQueue { writes, data, .. } = queue;
+        let mut result = Ok(());
+        let mut applied = 0;
+        let mut start = 0;
+        for write in writes.iter_mut() {
+            let segment = &mut data[start..start + write.len as usize];
+            let offset = write.offset as usize;
+            let end = offset + segment.len();
+            match with_data(write.handle, &mut |att| att.swap_segment(offset, segment, end)) {
+                Ok(previous_len) => write.previous_len = previous_len as u16,
+                Err(e) => {
+                    result = Err((write.handle, e));
+                    break;
+                }
+            }
+            start += write.len as usize;
+            applied += 1;
+        }
+
+        if result.is_ok() {
+            for write in writes.iter() {
+                if let Err(e) = with_data(write.handle, &mut |att| att.validate().map(|_| 0)) {
+                    result = Err((write.handle, e));
+                    break;
+                }
+            }
+        }
+
+        match result {
+            Ok(()) =>
+            {
+                #[cfg(feature = "gatt-last-modified")]
+                for write in writes.iter() {
+                    let _ = with_data(write.handle, &mut |att| {
+                        if let AttributeData::Data { modified, .. } = att {
+                            *modified = Some(self.att_table.modified());
+                        }
+                        Ok(0)
+                    });
+                }
+            }
+            Err(_) => {
+                for write in writes[..applied].iter().rev() {
+                    start -= write.len as usize;
+                    let segment = &mut data[start..start + write.len as usize];
+                    let previous_len = write.previous_len as usize;
+                    let _ = with_data(write.handle, &mut |att| {
+                        att.swap_segment(write.offset as usize, segment, previous_len)
+                    });
+                }
+            }
+        }
+        result
     }
 
     fn handle_read_blob(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         buf: &mut [u8],
         handle: u16,
         offset: u16,
@@ -696,23 +1174,69 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         }
     }
 
-    fn handle_read_multiple(&self, buf: &mut [u8], handles: &[u8]) -> Result<usize, codec::Error> {
-        let w = WriteCursor::new(buf);
-        Self::error_response(
-            w,
-            att::ATT_READ_MULTIPLE_REQ,
-            u16::from_le_bytes([handles[0], handles[1]]),
-            AttErrorCode::ATTRIBUTE_NOT_FOUND,
-        )
+    fn handle_read_multiple(
+        &self,
+        connection: &impl AttPeer,
+        buf: &mut [u8],
+        handles: &[u8],
+        variable: bool,
+    ) -> Result<usize, codec::Error> {
+        let (request, response) = if variable {
+            (att::ATT_READ_MULTIPLE_VARIABLE_REQ, att::ATT_READ_MULTIPLE_VARIABLE_RSP)
+        } else {
+            (att::ATT_READ_MULTIPLE_REQ, att::ATT_READ_MULTIPLE_RSP)
+        };
+        let mut w = WriteCursor::new(buf);
+        if handles.len() < 4 || handles.len() % 2 != 0 {
+            return Self::error_response(w, request, 0, AttErrorCode::INVALID_PDU);
+        }
+
+        w.write(response)?;
+        for handle in handles.chunks_exact(2) {
+            let handle = u16::from_le_bytes([handle[0], handle[1]]);
+            let err = self.att_table.iterate(|mut it| {
+                while let Some(att) = it.next() {
+                    if att.handle == handle {
+                        if !variable {
+                            return self.read_attribute_data(connection, 0, att, w.write_buf()).map(Some);
+                        }
+                        if w.available() < 2 {
+                            return Ok(None);
+                        }
+                        // The length is that of the complete value, even if it is truncated.
+                        let (len, value) = w.write_buf().split_at_mut(2);
+                        let n = self.read_attribute_data(connection, 0, att, value)?;
+                        len.copy_from_slice(&(att.value_len() as u16).to_le_bytes());
+                        return Ok(Some(2 + n));
+                    }
+                }
+                Err(AttErrorCode::ATTRIBUTE_NOT_FOUND)
+            });
+            match err {
+                Ok(Some(n)) => w.commit(n)?,
+                Ok(None) => break,
+                Err(e) => return Self::error_response(w, request, handle, e),
+            }
+        }
+        Ok(w.len())
     }
 
     /// Process an event and produce a response if necessary
     pub fn process(
         &self,
-        connection: &Connection<'_, P>,
+        connection: &impl AttPeer,
         packet: &AttClient,
         rx: &mut [u8],
     ) -> Result<Option<usize>, codec::Error> {
+        // Bound responses by the ATT MTU, so that batched responses are never cut short.
+        let mtu = (connection.att_mtu() as usize).min(rx.len());
+        let rx = &mut rx[..mtu];
+        // The name only accepts write requests, its permissions are checked like any other attribute
+        let name_write = self.att_table.device_name_write.filter(|(handle, _)| match packet {
+            AttClient::Request(AttReq::Write { handle: h, .. } | AttReq::PrepareWrite { handle: h, .. }) => h == handle,
+            AttClient::Request(AttReq::ExecuteWrite { flags }) => *flags == 1,
+            _ => false,
+        });
         let len = match packet {
             AttClient::Request(AttReq::ReadByType {
                 start,
@@ -735,6 +1259,12 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 0
             }
 
+            AttClient::Command(AttCmd::SignedWrite {
+                handle,
+                data,
+                signature,
+            }) => self.handle_signed_write_cmd(connection, *handle, data, signature)?,
+
             AttClient::Request(AttReq::Write { handle, data }) => {
                 self.handle_write_req(connection, rx, *handle, data)?
             }
@@ -752,16 +1282,34 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
                 self.handle_prepare_write(connection, rx, *handle, *offset, value)?
             }
 
-            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(rx, *flags)?,
+            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.handle_execute_write(connection, rx, *flags)?,
 
             AttClient::Request(AttReq::ReadBlob { handle, offset }) => {
                 self.handle_read_blob(connection, rx, *handle, *offset)?
             }
 
-            AttClient::Request(AttReq::ReadMultiple { handles }) => self.handle_read_multiple(rx, handles)?,
+            AttClient::Request(AttReq::ReadMultiple { handles }) => {
+                self.handle_read_multiple(connection, rx, handles, false)?
+            }
+
+            AttClient::Request(AttReq::ReadMultipleVariable { handles }) => {
+                self.handle_read_multiple(connection, rx, handles, true)?
+            }
 
             AttClient::Confirmation(_) => 0,
         };
+        // Prepared writes are stored once executed
+        if let Some((_, write)) = name_write {
+            let written = match packet {
+                AttClient::Request(AttReq::PrepareWrite { .. }) => false,
+                _ => rx[0] != att::ATT_ERROR_RSP,
+            };
+            if written {
+                if let Ok(name) = self.device_name() {
+                    write.store.store(&name);
+                }
+            }
+        }
         if len > 0 {
             Ok(Some(len))
         } else {
@@ -769,6 +1317,69 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
         }
     }
 
+    /// Process an ATT PDU received from a client, writing the PDU to send back, if any, to `rx`.
+    ///
+    /// Drives the server without the host, e.g. from another runtime or a stack bridging ATT over another transport.
+    /// PDUs are passed without the L2CAP header, and go through the [`AttMiddleware`]. Unlike the host, every
+    /// request is accepted without an application event, and requests that can't be decoded are answered with an
+    /// error response. MTU exchange requests are answered with [`AttPeer::rx_mtu`], after which the caller sets
+    /// the ATT MTU of the peer to the smaller of both. Clients are registered with [`AttributeServer::connect`]
+    /// before their first PDU.
+    pub fn process_pdu(&self, connection: &impl AttPeer, pdu: &[u8], rx: &mut [u8]) -> Result<Option<usize>, Error> {
+        let packet = match Att::decode(pdu) {
+            Ok(Att::Client(packet)) => packet,
+            Ok(Att::Server(_)) => return Ok(None),
+            Err(_) => {
+                let code = match pdu.first().copied() {
+                    // Commands are ignored, and only requests are answered
+                    None | Some(att::ATT_HANDLE_VALUE_CMF) => return Ok(None),
+                    Some(opcode) if opcode & 0x41 != 0 => return Ok(None),
+                    Some(opcode) if att::is_request(opcode) => AttErrorCode::INVALID_PDU,
+                    Some(_) => AttErrorCode::REQUEST_NOT_SUPPORTED,
+                };
+                return Ok(Some(Self::error_response(WriteCursor::new(rx), pdu[0], 0, code)?));
+            }
+        };
+        let reject = |rx: &mut [u8], code| -> Result<Option<usize>, Error> {
+            let AttClient::Request(request) = &packet else {
+                return Ok(None);
+            };
+            let handle = match request {
+                AttReq::Read { handle } | AttReq::ReadBlob { handle, .. } | AttReq::Write { handle, .. } => *handle,
+                _ => 0,
+            };
+            Ok(Some(Self::error_response(WriteCursor::new(rx), pdu[0], handle, code)?))
+        };
+        if let Some(middleware) = self.middleware.lock(|m| m.get()) {
+            if let Err(code) = middleware.incoming(connection.handle(), pdu) {
+                return reject(rx, code);
+            }
+        }
+        let len = match packet {
+            AttClient::Request(AttReq::ExchangeMtu { .. }) => {
+                let mut w = WriteCursor::new(rx);
+                w.write(att::ATT_EXCHANGE_MTU_RSP)?;
+                w.write(connection.rx_mtu())?;
+                Some(w.len())
+            }
+            _ => self.process(connection, &packet, rx)?,
+        };
+        match (len, self.middleware.lock(|m| m.get())) {
+            (Some(len), Some(middleware)) if !middleware.outgoing(connection.handle(), &rx[..len]) => {
+                reject(rx, AttErrorCode::UNLIKELY_ERROR)
+            }
+            _ => Ok(len),
+        }
+    }
+
+    /// Set the middleware observing the ATT PDUs of the server, replacing any previous one.
+    ///
+    /// PDUs sent with [`GattData::reply`](crate::gatt::GattData::reply) or
+    /// [`GattData::send_unsolicited`](crate::gatt::GattData::send_unsolicited) bypass the middleware.
+    pub fn set_middleware(&self, middleware: &'values (dyn AttMiddleware + Sync)) {
+        self.middleware.lock(|m| m.set(Some(middleware)));
+    }
+
     /// Get a reference to the attribute table
     pub fn table(&self) -> &AttributeTable<'values, M, ATT_MAX> {
         &self.att_table
@@ -783,4 +1394,608 @@ impl<'values, M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX:
     pub fn set_cccd_table(&self, connection: &Connection<'_, P>, table: CccdTable<CCCD_MAX>) {
         self.cccd_tables.set_cccd_table(&connection.peer_identity(), table);
     }
+
+    /// Serialize the mutable state of the server into `buf`, returning the number of bytes written.
+    ///
+    /// The snapshot holds the values of the writable attributes and the CCCD tables of the known clients,
+    /// so that a device powering off its RAM between connections can [`restore`](Self::restore) the server
+    /// instead of setting every value again. Take the snapshot while no client is connected.
+    pub fn snapshot(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut w = WriteCursor::new(buf);
+        w.write(SNAPSHOT_VERSION)?;
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if let AttributeData::Data {
+                    value,
+                    variable_len,
+                    len,
+                    ..
+                } = &att.data
+                {
+                    let value = if *variable_len {
+                        &value[..*len as usize]
+                    } else {
+                        &value[..]
+                    };
+                    w.write(att.handle)?;
+                    w.write(value.len() as u16)?;
+                    w.append(value)?;
+                }
+            }
+            Ok::<_, Error>(())
+        })?;
+        self.cccd_tables.snapshot(&mut w)?;
+        Ok(w.len())
+    }
+
+    /// Restore the state serialized by [`snapshot`](Self::snapshot).
+    ///
+    /// The server must be built from the same attribute table as the one the snapshot was taken from.
+    /// If it is not, or the snapshot is truncated, `Error::InvalidValue` is returned and the server is left
+    /// unchanged. Restore the snapshot
+    /// before accepting connections.
+    pub fn restore(&self, buf: &[u8]) -> Result<(), Error> {
+        self.restore_from(buf, false).map_err(|_| Error::InvalidValue)?;
+        self.restore_from(buf, true)
+    }
+
+    fn restore_from(&self, buf: &[u8], apply: bool) -> Result<(), Error> {
+        let mut r = ReadCursor::new(buf);
+        let version: u8 = r.read()?;
+        if version != SNAPSHOT_VERSION {
+            return Err(Error::InvalidValue);
+        }
+        self.att_table.iterate(|mut it| {
+            while let Some(att) = it.next() {
+                if let AttributeData::Data {
+                    value,
+                    variable_len,
+                    len,
+                    ..
+                } = &mut att.data
+                {
+                    let handle: u16 = r.read()?;
+                    let stored_len: u16 = r.read()?;
+                    let stored = r.slice(stored_len as usize)?;
+                    if handle != att.handle
+                        || stored.len() > value.len()
+                        || (!*variable_len && stored.len() != value.len())
+                    {
+                        return Err(Error::InvalidValue);
+                    }
+                    if apply {
+                        value[..stored.len()].copy_from_slice(stored);
+                        *len = stored.len() as u16;
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        self.cccd_tables.restore(&mut r, apply)?;
+        if r.available() != 0 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::Poll;
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+    use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
+    use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, NoopRawMutex};
+
+    use super::*;
+    use crate::attribute::{AttributePermissions, CharacteristicProp, Service};
+    use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+    use crate::prelude::{AsGatt, DefaultPacketPool};
+    use crate::types::gatt_traits::FromGattError;
+
+    fn connection() -> Connection<'static, DefaultPacketPool> {
+        let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 1]));
+        let mgr = Box::leak(Box::new(ConnectionManager::new(&mut storage[..], 23)));
+        mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new([1; 6]),
+            LeConnRole::Peripheral,
+        )
+        .unwrap();
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        conn
+    }
+
+    #[test]
+    fn server_is_sync() {
+        // Servers can live in a static shared by several executors
+        fn assert_sync<T: Sync>() {}
+        assert_sync::<AttributeServer<'static, CriticalSectionRawMutex, DefaultPacketPool, 10, 1, 1>>();
+    }
+
+    #[test]
+    fn read_by_type_is_batched() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut stores = [[0u8; 2]; 4];
+        let [s0, s1, s2, s3] = &mut stores;
+        {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            svc.add_characteristic(0x2a19_u16, &[CharacteristicProp::Read], 1u16, s0);
+            svc.add_characteristic(0x2a1a_u16, &[CharacteristicProp::Read], 2u16, s1);
+            svc.add_characteristic(0x2a1b_u16, &[CharacteristicProp::Read], 3u16, s2);
+        }
+        {
+            let mut svc = table.add_service(Service::new(0x180a_u16));
+            svc.add_characteristic(0x2a29_u16, &[CharacteristicProp::Read], 4u16, s3);
+        }
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 1> = AttributeServer::new(table);
+        let conn = connection();
+        let mut buf = [0; 64];
+
+        let req = AttClient::Request(AttReq::ReadByType {
+            start: 1,
+            end: 0xffff,
+            attribute_type: CHARACTERISTIC.into(),
+        });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        // Three declarations of 5 bytes, each prefixed with the handle, fit into the default MTU of 23.
+        assert_eq!(buf[0], att::ATT_READ_BY_TYPE_RSP);
+        assert_eq!(buf[1], 7);
+        assert_eq!(len, 2 + 3 * 7);
+
+        let req = AttClient::Request(AttReq::ReadByGroupType {
+            start: 1,
+            end: 0xffff,
+            group_type: PRIMARY_SERVICE.into(),
+        });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(buf[0], att::ATT_READ_BY_GROUP_TYPE_RSP);
+        assert_eq!(buf[1], 6);
+        assert_eq!(len, 2 + 2 * 6);
+    }
+
+    #[test]
+    fn read_multiple() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut stores = [[0u8; 2]; 2];
+        let [s0, s1] = &mut stores;
+        let (a, b) = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            let a = svc
+                .add_characteristic(0x2a19_u16, &[CharacteristicProp::Read], 0x0201u16, s0)
+                .build();
+            let b = svc
+                .add_characteristic(0x2a1a_u16, &[CharacteristicProp::Write], 0x0403u16, s1)
+                .build();
+            (a.handle, b.handle)
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 1> = AttributeServer::new(table);
+        let conn = connection();
+        let mut buf = [0; 64];
+
+        let handles = [a.to_le_bytes(), a.to_le_bytes()].concat();
+        let req = AttClient::Request(AttReq::ReadMultiple { handles: &handles });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(&buf[..len], &[att::ATT_READ_MULTIPLE_RSP, 1, 2, 1, 2]);
+
+        let req = AttClient::Request(AttReq::ReadMultipleVariable { handles: &handles });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(
+            &buf[..len],
+            &[att::ATT_READ_MULTIPLE_VARIABLE_RSP, 2, 0, 1, 2, 2, 0, 1, 2]
+        );
+
+        // The first handle that cannot be read is reported.
+        let handles = [a.to_le_bytes(), b.to_le_bytes()].concat();
+        let req = AttClient::Request(AttReq::ReadMultiple { handles: &handles });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(buf[0], att::ATT_ERROR_RSP);
+        assert_eq!(buf[1], att::ATT_READ_MULTIPLE_REQ);
+        assert_eq!(u16::from_le_bytes([buf[2], buf[3]]), b);
+    }
+
+    #[test]
+    fn process_pdu() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut store = [0u8; 2];
+        let level = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            svc.add_characteristic(
+                0x2a19_u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                0x0201u16,
+                &mut store,
+            )
+            .build()
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 1> = AttributeServer::new(table);
+        let peer = PeerInfo {
+            handle: ConnHandle::new(1),
+            identity: Identity {
+                bd_addr: BdAddr::new([2; 6]),
+                ..Default::default()
+            },
+            att_mtu: 23,
+            rx_mtu: 247,
+            encrypted: false,
+        };
+        server.connect(&peer).unwrap();
+        let mut buf = [0; 64];
+
+        let len = server.process_pdu(&peer, &[att::ATT_EXCHANGE_MTU_REQ, 0x00, 0x01], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_EXCHANGE_MTU_RSP, 247, 0]);
+
+        let [lo, hi] = level.handle.to_le_bytes();
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 1, 2]);
+
+        let cccd = level.cccd_handle.unwrap();
+        assert!(!server.should_notify(&peer, cccd));
+        let [lo, hi] = cccd.to_le_bytes();
+        let len = server.process_pdu(&peer, &[att::ATT_WRITE_REQ, lo, hi, 1, 0], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_WRITE_RSP]);
+        assert!(server.should_notify(&peer, cccd));
+
+        // Responses are not processed by the server
+        assert_eq!(server.process_pdu(&peer, &[att::ATT_WRITE_RSP], &mut buf), Ok(None));
+
+        // Requests that can't be decoded are answered, commands are ignored
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, 1], &mut buf);
+        assert_eq!(
+            &buf[..len.unwrap().unwrap()],
+            &[att::ATT_ERROR_RSP, att::ATT_READ_REQ, 0, 0, 0x04]
+        );
+        let len = server.process_pdu(&peer, &[0x3e], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_ERROR_RSP, 0x3e, 0, 0, 0x06]);
+        assert_eq!(server.process_pdu(&peer, &[0x7e], &mut buf), Ok(None));
+    }
+
+    #[test]
+    fn permissions() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut store = [0u8; 1];
+        let secret = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            svc.add_characteristic(
+                0x2a19_u16,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::Notify,
+                ],
+                7u8,
+                &mut store,
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::Encrypted,
+                write: AttributeSecurity::None,
+            })
+            .build()
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 1> = AttributeServer::new(table);
+        let mut peer = PeerInfo {
+            handle: ConnHandle::new(1),
+            identity: Identity {
+                bd_addr: BdAddr::new([2; 6]),
+                ..Default::default()
+            },
+            att_mtu: 23,
+            rx_mtu: 23,
+            encrypted: false,
+        };
+        server.connect(&peer).unwrap();
+        let mut buf = [0; 64];
+
+        let [lo, hi] = secret.handle.to_le_bytes();
+        let [cccd_lo, cccd_hi] = secret.cccd_handle.unwrap().to_le_bytes();
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(
+            &buf[..len.unwrap().unwrap()],
+            &[att::ATT_ERROR_RSP, att::ATT_READ_REQ, lo, hi, 0x0f]
+        );
+        let len = server.process_pdu(&peer, &[att::ATT_WRITE_REQ, cccd_lo, cccd_hi, 1, 0], &mut buf);
+        assert_eq!(
+            &buf[..len.unwrap().unwrap()],
+            &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, cccd_lo, cccd_hi, 0x0f]
+        );
+        let len = server.process_pdu(&peer, &[att::ATT_WRITE_REQ, lo, hi, 3], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_WRITE_RSP]);
+
+        peer.encrypted = true;
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 3]);
+        let len = server.process_pdu(&peer, &[att::ATT_WRITE_REQ, cccd_lo, cccd_hi, 1, 0], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_WRITE_RSP]);
+    }
+
+    #[test]
+    fn write_commands() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut stores = [[0u8; 1]; 2];
+        let [request_store, command_store] = &mut stores;
+        let (request_only, secret) = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            let request_only = svc
+                .add_characteristic(
+                    0x2a19_u16,
+                    &[CharacteristicProp::Read, CharacteristicProp::Write],
+                    0u8,
+                    request_store,
+                )
+                .build();
+            let secret = svc
+                .add_characteristic(
+                    0x2a1a_u16,
+                    &[CharacteristicProp::Read, CharacteristicProp::WriteWithoutResponse],
+                    0u8,
+                    command_store,
+                )
+                .permissions(AttributePermissions {
+                    read: AttributeSecurity::None,
+                    write: AttributeSecurity::Encrypted,
+                })
+                .build();
+            (request_only, secret)
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 1> = AttributeServer::new(table);
+        let mut peer = PeerInfo {
+            handle: ConnHandle::new(1),
+            identity: Identity {
+                bd_addr: BdAddr::new([2; 6]),
+                ..Default::default()
+            },
+            att_mtu: 23,
+            rx_mtu: 23,
+            encrypted: false,
+        };
+        server.connect(&peer).unwrap();
+        let mut buf = [0; 64];
+
+        // Characteristics without the write without response property ignore write commands
+        let [lo, hi] = request_only.handle.to_le_bytes();
+        assert!(!server.accepts_write_cmd(&peer, request_only.handle));
+        assert_eq!(
+            server.process_pdu(&peer, &[att::ATT_WRITE_CMD, lo, hi, 3], &mut buf),
+            Ok(None)
+        );
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 0]);
+
+        let [lo, hi] = secret.handle.to_le_bytes();
+        assert!(!server.accepts_write_cmd(&peer, secret.handle));
+        peer.encrypted = true;
+        assert!(server.accepts_write_cmd(&peer, secret.handle));
+        assert_eq!(
+            server.process_pdu(&peer, &[att::ATT_WRITE_CMD, lo, hi, 3], &mut buf),
+            Ok(None)
+        );
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 3]);
+    }
+
+    #[test]
+    fn prepared_writes_per_connection() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut store = [0u8; 4];
+        let handle = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            svc.add_characteristic(
+                0x2a19_u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                [0u8; 4],
+                &mut store,
+            )
+            .build()
+            .handle
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 2> = AttributeServer::new(table);
+        let peers = [1, 2].map(|i| PeerInfo {
+            handle: ConnHandle::new(i),
+            identity: Identity {
+                bd_addr: BdAddr::new([i as u8; 6]),
+                ..Default::default()
+            },
+            att_mtu: 23,
+            rx_mtu: 23,
+            encrypted: false,
+        });
+        let mut buf = [0; 64];
+        let [lo, hi] = handle.to_le_bytes();
+
+        // A long write of one client doesn't block the other
+        for (i, peer) in peers.iter().enumerate() {
+            let v = i as u8 + 1;
+            let len = server.process_pdu(peer, &[att::ATT_PREPARE_WRITE_REQ, lo, hi, 0, 0, v, v], &mut buf);
+            assert_eq!(buf[..len.unwrap().unwrap()][0], att::ATT_PREPARE_WRITE_RSP);
+        }
+        for (i, peer) in peers.iter().enumerate() {
+            let v = i as u8 + 1;
+            let len = server.process_pdu(peer, &[att::ATT_PREPARE_WRITE_REQ, lo, hi, 2, 0, v, v], &mut buf);
+            assert_eq!(buf[..len.unwrap().unwrap()][0], att::ATT_PREPARE_WRITE_RSP);
+        }
+        let len = server.process_pdu(&peers[1], &[att::ATT_EXECUTE_WRITE_REQ, 1], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_EXECUTE_WRITE_RSP]);
+        let len = server.process_pdu(&peers[0], &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 2, 2, 2, 2]);
+
+        let len = server.process_pdu(&peers[0], &[att::ATT_EXECUTE_WRITE_REQ, 1], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_EXECUTE_WRITE_RSP]);
+        let len = server.process_pdu(&peers[0], &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_READ_RSP, 1, 1, 1, 1]);
+    }
+
+    #[cfg(feature = "gatt-last-modified")]
+    #[test]
+    fn last_modified() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut stores = [[0u8; 2]; 2];
+        let [s0, s1] = &mut stores;
+        let (a, b) = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            let a = svc
+                .add_characteristic(0x2a19_u16, &[CharacteristicProp::Write], 1u16, s0)
+                .build();
+            let b = svc
+                .add_characteristic(0x2a1a_u16, &[CharacteristicProp::Read], 2u16, s1)
+                .build();
+            (a, b)
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 1> = AttributeServer::new(table);
+        let conn = connection();
+        let mut buf = [0; 64];
+        assert_eq!(a.last_modified(&server), Ok(None));
+
+        // Changes by the client and locally share the sequence
+        b.set(&server, &3).unwrap();
+        let req = AttClient::Request(AttReq::Write {
+            handle: a.handle,
+            data: &[4, 0],
+        });
+        server.process(&conn, &req, &mut buf).unwrap();
+        let a_modified = a.last_modified(&server).unwrap().unwrap();
+        let b_modified = b.last_modified(&server).unwrap().unwrap();
+        assert_eq!(b_modified.sequence, 1);
+        assert_eq!(a_modified.sequence, 2);
+        assert!(a_modified.at >= b_modified.at);
+
+        // Failed writes leave it unchanged
+        let req = AttClient::Request(AttReq::Write {
+            handle: b.handle,
+            data: &[5, 0],
+        });
+        server.process(&conn, &req, &mut buf).unwrap();
+        assert_eq!(b.last_modified(&server), Ok(Some(b_modified)));
+    }
+
+    #[test]
+    fn write_is_validated() {
+        #[derive(Clone, Copy)]
+        struct Mode(u8);
+
+        impl AsGatt for Mode {
+            const MIN_SIZE: usize = 1;
+            const MAX_SIZE: usize = 1;
+
+            fn as_gatt(&self) -> &[u8] {
+                core::slice::from_ref(&self.0)
+            }
+
+            fn validate(data: &[u8]) -> Result<(), FromGattError> {
+                match data {
+                    [0..=2] => Ok(()),
+                    [_] => Err(FromGattError::InvalidValue),
+                    _ => Err(FromGattError::InvalidLength),
+                }
+            }
+        }
+
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut store = [0u8; 1];
+        let mode = {
+            let mut svc = table.add_service(Service::new(0x180f_u16));
+            svc.add_characteristic(0x2a19_u16, &[CharacteristicProp::Write], Mode(0), &mut store)
+                .build()
+        };
+        let handle = mode.handle;
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 1> = AttributeServer::new(table);
+        let conn = connection();
+        let mut buf = [0; 64];
+
+        let req = AttClient::Request(AttReq::Write { handle, data: &[2] });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(&buf[..len], &[att::ATT_WRITE_RSP]);
+
+        let req = AttClient::Request(AttReq::Write { handle, data: &[3] });
+        let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(buf[0], att::ATT_ERROR_RSP);
+        assert_eq!(buf[len - 1], 0x13); // Value Not Allowed
+
+        // Prepared writes are validated when executed, and leave the value unchanged when rejected
+        let mut prepare_execute = |value: u8, flags: u8| {
+            let value = [value];
+            let req = AttClient::Request(AttReq::PrepareWrite {
+                handle,
+                offset: 0,
+                value: &value,
+            });
+            let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+            assert_eq!(
+                &buf[..len],
+                &[att::ATT_PREPARE_WRITE_RSP, handle as u8, 0, 0, 0, value[0]]
+            );
+            let req = AttClient::Request(AttReq::ExecuteWrite { flags });
+            let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+            (buf[0], buf[len - 1])
+        };
+        assert_eq!(prepare_execute(3, 1), (att::ATT_ERROR_RSP, 0x13));
+        assert_eq!(
+            prepare_execute(1, 0),
+            (att::ATT_EXECUTE_WRITE_RSP, att::ATT_EXECUTE_WRITE_RSP)
+        );
+        assert_eq!(server.table().with_value(&mode, |value| value[0]), Ok(2));
+        assert_eq!(
+            prepare_execute(1, 1),
+            (att::ATT_EXECUTE_WRITE_RSP, att::ATT_EXECUTE_WRITE_RSP)
+        );
+        assert_eq!(server.table().with_value(&mode, |value| value[0]), Ok(1));
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        fn new_server(
+            store: &mut [u8; 2],
+        ) -> (AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 1, 1>, u16, u16) {
+            let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+            let characteristic = {
+                let mut svc = table.add_service(Service::new(0x180f_u16));
+                svc.add_characteristic(
+                    0x2a19_u16,
+                    &[
+                        CharacteristicProp::Read,
+                        CharacteristicProp::Write,
+                        CharacteristicProp::Notify,
+                    ],
+                    0u16,
+                    store,
+                )
+                .build()
+            };
+            (
+                AttributeServer::new(table),
+                characteristic.handle,
+                characteristic.cccd_handle.unwrap(),
+            )
+        }
+
+        let conn = connection();
+        let mut buf = [0; 64];
+        let mut store = [0; 2];
+        let (server, handle, cccd_handle) = new_server(&mut store);
+        server.connect(&conn).unwrap();
+        let req = AttClient::Request(AttReq::Write { handle, data: &[1, 2] });
+        server.process(&conn, &req, &mut buf).unwrap();
+        let req = AttClient::Request(AttReq::Write {
+            handle: cccd_handle,
+            data: &[1, 0],
+        });
+        server.process(&conn, &req, &mut buf).unwrap();
+
+        let mut snapshot = [0; 64];
+        let len = server.snapshot(&mut snapshot).unwrap();
+
+        let mut store = [0; 2];
+        let (restored, _, _) = new_server(&mut store);
+        assert_eq!(restored.restore(&snapshot[..len - 1]), Err(Error::InvalidValue));
+        restored.restore(&snapshot[..len]).unwrap();
+        assert!(restored.should_notify(&conn, cccd_handle));
+        let req = AttClient::Request(AttReq::Read { handle });
+        let n = restored.process(&conn, &req, &mut buf).unwrap().unwrap();
+        assert_eq!(&buf[1..n], &[1, 2]);
+    }
 }
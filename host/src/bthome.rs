@@ -0,0 +1,624 @@
+//! BTHome v2 sensor data.
+//!
+//! BTHome packs sensor measurements as objects into the service data of the `0xFCD2` UUID, and is commonly
+//! used by DIY sensors to report to Home Assistant. Each object is an object ID followed by a value of a
+//! fixed size, which is scaled by a factor defined for the object, see [`object`] for the supported IDs.
+//!
+//! ```rust no_run
+//! use trouble_host::bthome::{self, object, Encoder};
+//! use trouble_host::prelude::*;
+//!
+//! let mut data = [0; 16];
+//! let mut encoder = Encoder::new(&mut data, false).unwrap();
+//! encoder.add(object::TEMPERATURE, 25.06).unwrap();
+//! encoder.add(object::HUMIDITY, 50.55).unwrap();
+//! let data = encoder.finish();
+//!
+//! let mut adv = [0; 31];
+//! AdStructure::encode_slice(&[AdStructure::ServiceData16 { uuid: bthome::UUID, data }], &mut adv).unwrap();
+//! ```
+//!
+//! Encrypted data, with AES-CCM and a key shared with the receiver, requires the `security` feature.
+#[cfg(feature = "security")]
+use aes::cipher::{BlockEncrypt, KeyInit};
+#[cfg(feature = "security")]
+use aes::Aes128;
+#[cfg(feature = "security")]
+use bt_hci::param::BdAddr;
+
+use crate::Error;
+
+/// Service data UUID of BTHome, in the byte order of [`AdStructure::ServiceData16`](crate::advertise::AdStructure::ServiceData16).
+pub const UUID: [u8; 2] = [0xd2, 0xfc];
+
+const VERSION: u8 = 2;
+const ENCRYPTED: u8 = 0x01;
+const TRIGGER_BASED: u8 = 0x04;
+#[cfg(feature = "security")]
+const COUNTER_LEN: usize = 4;
+#[cfg(feature = "security")]
+const MIC_LEN: usize = 4;
+
+/// Object IDs.
+pub mod object {
+    /// Packet ID, to deduplicate packets.
+    pub const PACKET_ID: u8 = 0x00;
+    /// Battery, in %.
+    pub const BATTERY: u8 = 0x01;
+    /// Temperature, in °C with a resolution of 0.01.
+    pub const TEMPERATURE: u8 = 0x02;
+    /// Humidity, in % with a resolution of 0.01.
+    pub const HUMIDITY: u8 = 0x03;
+    /// Pressure, in hPa.
+    pub const PRESSURE: u8 = 0x04;
+    /// Illuminance, in lux.
+    pub const ILLUMINANCE: u8 = 0x05;
+    /// Mass, in kg.
+    pub const MASS_KG: u8 = 0x06;
+    /// Mass, in lb.
+    pub const MASS_LB: u8 = 0x07;
+    /// Dew point, in °C.
+    pub const DEWPOINT: u8 = 0x08;
+    /// Count, 1 byte.
+    pub const COUNT: u8 = 0x09;
+    /// Energy, in kWh.
+    pub const ENERGY: u8 = 0x0a;
+    /// Power, in W.
+    pub const POWER: u8 = 0x0b;
+    /// Voltage, in V with a resolution of 0.001.
+    pub const VOLTAGE: u8 = 0x0c;
+    /// PM2.5, in µg/m³.
+    pub const PM2_5: u8 = 0x0d;
+    /// PM10, in µg/m³.
+    pub const PM10: u8 = 0x0e;
+    /// Generic boolean.
+    pub const GENERIC_BOOLEAN: u8 = 0x0f;
+    /// Power on.
+    pub const POWER_ON: u8 = 0x10;
+    /// Opening.
+    pub const OPENING: u8 = 0x11;
+    /// CO2, in ppm.
+    pub const CO2: u8 = 0x12;
+    /// TVOC, in µg/m³.
+    pub const TVOC: u8 = 0x13;
+    /// Moisture, in % with a resolution of 0.01.
+    pub const MOISTURE: u8 = 0x14;
+    /// Battery low.
+    pub const BATTERY_LOW: u8 = 0x15;
+    /// Battery charging.
+    pub const BATTERY_CHARGING: u8 = 0x16;
+    /// Carbon monoxide detected.
+    pub const CARBON_MONOXIDE: u8 = 0x17;
+    /// Cold.
+    pub const COLD: u8 = 0x18;
+    /// Connectivity.
+    pub const CONNECTIVITY: u8 = 0x19;
+    /// Door open.
+    pub const DOOR: u8 = 0x1a;
+    /// Garage door open.
+    pub const GARAGE_DOOR: u8 = 0x1b;
+    /// Gas detected.
+    pub const GAS_DETECTED: u8 = 0x1c;
+    /// Heat.
+    pub const HEAT: u8 = 0x1d;
+    /// Light.
+    pub const LIGHT: u8 = 0x1e;
+    /// Lock unlocked.
+    pub const LOCK: u8 = 0x1f;
+    /// Moisture detected.
+    pub const MOISTURE_DETECTED: u8 = 0x20;
+    /// Motion detected.
+    pub const MOTION: u8 = 0x21;
+    /// Moving.
+    pub const MOVING: u8 = 0x22;
+    /// Occupancy detected.
+    pub const OCCUPANCY: u8 = 0x23;
+    /// Plug connected.
+    pub const PLUG: u8 = 0x24;
+    /// Presence detected.
+    pub const PRESENCE: u8 = 0x25;
+    /// Problem.
+    pub const PROBLEM: u8 = 0x26;
+    /// Running.
+    pub const RUNNING: u8 = 0x27;
+    /// Unsafe.
+    pub const SAFETY: u8 = 0x28;
+    /// Smoke detected.
+    pub const SMOKE: u8 = 0x29;
+    /// Sound detected.
+    pub const SOUND: u8 = 0x2a;
+    /// Tampered.
+    pub const TAMPER: u8 = 0x2b;
+    /// Vibration detected.
+    pub const VIBRATION: u8 = 0x2c;
+    /// Window open.
+    pub const WINDOW: u8 = 0x2d;
+    /// Humidity, in whole %.
+    pub const HUMIDITY_PERCENT: u8 = 0x2e;
+    /// Moisture, in whole %.
+    pub const MOISTURE_PERCENT: u8 = 0x2f;
+    /// Button event.
+    pub const BUTTON: u8 = 0x3a;
+    /// Dimmer event, the event and the number of steps.
+    pub const DIMMER: u8 = 0x3c;
+    /// Count, 2 bytes.
+    pub const COUNT_U16: u8 = 0x3d;
+    /// Count, 4 bytes.
+    pub const COUNT_U32: u8 = 0x3e;
+    /// Rotation, in °.
+    pub const ROTATION: u8 = 0x3f;
+    /// Distance, in mm.
+    pub const DISTANCE_MM: u8 = 0x40;
+    /// Distance, in m.
+    pub const DISTANCE_M: u8 = 0x41;
+    /// Duration, in s.
+    pub const DURATION: u8 = 0x42;
+    /// Current, in A.
+    pub const CURRENT: u8 = 0x43;
+    /// Speed, in m/s.
+    pub const SPEED: u8 = 0x44;
+    /// Temperature, in °C with a resolution of 0.1.
+    pub const TEMPERATURE_DECI: u8 = 0x45;
+    /// UV index.
+    pub const UV_INDEX: u8 = 0x46;
+    /// Volume, in L.
+    pub const VOLUME_L: u8 = 0x47;
+    /// Volume, in mL.
+    pub const VOLUME_ML: u8 = 0x48;
+    /// Volume flow rate, in m³/h.
+    pub const VOLUME_FLOW_RATE: u8 = 0x49;
+    /// Voltage, in V with a resolution of 0.1.
+    pub const VOLTAGE_DECI: u8 = 0x4a;
+    /// Gas, in m³, 3 bytes.
+    pub const GAS: u8 = 0x4b;
+    /// Gas, in m³, 4 bytes.
+    pub const GAS_U32: u8 = 0x4c;
+    /// Energy, in kWh, 4 bytes.
+    pub const ENERGY_U32: u8 = 0x4d;
+    /// Volume, in L, 4 bytes.
+    pub const VOLUME: u8 = 0x4e;
+    /// Water, in L.
+    pub const WATER: u8 = 0x4f;
+    /// Timestamp, in seconds since the UNIX epoch.
+    pub const TIMESTAMP: u8 = 0x50;
+    /// Acceleration, in m/s².
+    pub const ACCELERATION: u8 = 0x51;
+    /// Gyroscope, in °/s.
+    pub const GYROSCOPE: u8 = 0x52;
+    /// Text, with a length prefix.
+    pub const TEXT: u8 = 0x53;
+    /// Raw bytes, with a length prefix.
+    pub const RAW: u8 = 0x54;
+    /// Device type ID.
+    pub const DEVICE_TYPE: u8 = 0xf0;
+    /// Firmware version, 4 bytes.
+    pub const FIRMWARE_VERSION: u8 = 0xf1;
+    /// Firmware version, 3 bytes.
+    pub const FIRMWARE_VERSION_U24: u8 = 0xf2;
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    // Size in bytes, signed, and the factor applied to the value.
+    Fixed(usize, bool, f32),
+    // Length prefixed bytes.
+    Variable,
+}
+
+fn format(id: u8) -> Option<Format> {
+    use object::*;
+    use Format::*;
+    Some(match id {
+        PACKET_ID | BATTERY | COUNT | HUMIDITY_PERCENT | MOISTURE_PERCENT | BUTTON => Fixed(1, false, 1.0),
+        GENERIC_BOOLEAN..=OPENING | BATTERY_LOW..=WINDOW => Fixed(1, false, 1.0),
+        TEMPERATURE | DEWPOINT => Fixed(2, true, 0.01),
+        HUMIDITY | MASS_KG | MASS_LB | MOISTURE | SPEED => Fixed(2, false, 0.01),
+        PRESSURE | ILLUMINANCE | POWER => Fixed(3, false, 0.01),
+        ENERGY | DURATION | GAS => Fixed(3, false, 0.001),
+        VOLTAGE | CURRENT | VOLUME_FLOW_RATE | ACCELERATION | GYROSCOPE => Fixed(2, false, 0.001),
+        PM2_5 | PM10 | CO2 | TVOC | DIMMER | COUNT_U16 | DISTANCE_MM | VOLUME_ML | DEVICE_TYPE => Fixed(2, false, 1.0),
+        ROTATION | TEMPERATURE_DECI => Fixed(2, true, 0.1),
+        DISTANCE_M | VOLUME_L | VOLTAGE_DECI => Fixed(2, false, 0.1),
+        UV_INDEX => Fixed(1, false, 0.1),
+        COUNT_U32 | TIMESTAMP | FIRMWARE_VERSION => Fixed(4, false, 1.0),
+        GAS_U32 | ENERGY_U32 | VOLUME | WATER => Fixed(4, false, 0.001),
+        FIRMWARE_VERSION_U24 => Fixed(3, false, 1.0),
+        TEXT | RAW => Variable,
+        _ => return None,
+    })
+}
+
+/// Encoder of BTHome service data.
+pub struct Encoder<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> Encoder<'a> {
+    /// Create an encoder writing to `buf`.
+    ///
+    /// A trigger based device only advertises when an event is triggered, rather than at a regular interval.
+    pub fn new(buf: &'a mut [u8], trigger_based: bool) -> Result<Self, Error> {
+        let info = buf.first_mut().ok_or(Error::InsufficientSpace)?;
+        *info = VERSION << 5 | if trigger_based { TRIGGER_BASED } else { 0 };
+        Ok(Self { buf, len: 1 })
+    }
+
+    /// Add a measurement, scaled by the factor of the object.
+    ///
+    /// Objects should be added in order of their IDs. Returns `Error::NotSupported` for unknown and variable
+    /// length objects, and `Error::InvalidValue` if the value does not fit in the object.
+    pub fn add(&mut self, id: u8, value: f32) -> Result<(), Error> {
+        let Some(Format::Fixed(size, signed, factor)) = format(id) else {
+            return Err(Error::NotSupported);
+        };
+        let scaled = value / factor;
+        let raw = if scaled < 0.0 { scaled - 0.5 } else { scaled + 0.5 } as i64;
+        let bits = 8 * size as u32;
+        let (min, max) = if signed {
+            (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+        } else {
+            (0, (1 << bits) - 1)
+        };
+        if raw < min || raw > max {
+            return Err(Error::InvalidValue);
+        }
+        self.append(id, &raw.to_le_bytes()[..size])
+    }
+
+    /// Add a text or raw object.
+    pub fn add_bytes(&mut self, id: u8, data: &[u8]) -> Result<(), Error> {
+        let Some(Format::Variable) = format(id) else {
+            return Err(Error::NotSupported);
+        };
+        let len = u8::try_from(data.len()).map_err(|_| Error::InvalidValue)?;
+        self.append(id, &[len])?;
+        let end = self.len + data.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(Error::InsufficientSpace)?
+            .copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+
+    fn append(&mut self, id: u8, value: &[u8]) -> Result<(), Error> {
+        let end = self.len + 1 + value.len();
+        let dest = self.buf.get_mut(self.len..end).ok_or(Error::InsufficientSpace)?;
+        dest[0] = id;
+        dest[1..].copy_from_slice(value);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Service data with the added objects.
+    pub fn finish(self) -> &'a [u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Service data with the added objects encrypted with `key`.
+    ///
+    /// `address` is the address the device advertises with, and `counter` must increase with every
+    /// advertisement, so that receivers can reject replayed data. The buffer needs 8 more bytes than the
+    /// unencrypted data.
+    #[cfg(feature = "security")]
+    pub fn finish_encrypted(self, key: &[u8; 16], address: &BdAddr, counter: u32) -> Result<&'a [u8], Error> {
+        let end = self.len + COUNTER_LEN + MIC_LEN;
+        if end > self.buf.len() {
+            return Err(Error::InsufficientSpace);
+        }
+        self.buf[0] |= ENCRYPTED;
+        let nonce = nonce(address, self.buf[0], counter);
+        let (objects, rest) = self.buf[1..].split_at_mut(self.len - 1);
+        let mic = ccm(key, &nonce, &[], objects, MIC_LEN, true);
+        rest[..COUNTER_LEN].copy_from_slice(&counter.to_le_bytes());
+        rest[COUNTER_LEN..COUNTER_LEN + MIC_LEN].copy_from_slice(&mic[..MIC_LEN]);
+        Ok(&self.buf[..end])
+    }
+}
+
+/// Decoded BTHome service data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BtHomeData<'a> {
+    info: u8,
+    objects: &'a [u8],
+}
+
+impl<'a> BtHomeData<'a> {
+    /// Parse unencrypted service data.
+    ///
+    /// Returns `Error::NotSupported` for other versions of BTHome and for encrypted data, which is decoded
+    /// with [`BtHomeData::decrypt`].
+    pub fn parse(data: &'a [u8]) -> Result<Self, Error> {
+        let (&info, objects) = data.split_first().ok_or(Error::InvalidValue)?;
+        if info >> 5 != VERSION || info & ENCRYPTED != 0 {
+            return Err(Error::NotSupported);
+        }
+        Ok(Self { info, objects })
+    }
+
+    /// Decrypt service data with `key` into `buf`, returning the data and the counter of the advertisement.
+    ///
+    /// `address` is the address of the advertising device. Returns `Error::InvalidValue` if the data fails
+    /// authentication. Reject counters not greater than the last one received to protect against replays.
+    #[cfg(feature = "security")]
+    pub fn decrypt(data: &[u8], key: &[u8; 16], address: &BdAddr, buf: &'a mut [u8]) -> Result<(Self, u32), Error> {
+        let (&info, rest) = data.split_first().ok_or(Error::InvalidValue)?;
+        if info >> 5 != VERSION || info & ENCRYPTED == 0 {
+            return Err(Error::NotSupported);
+        }
+        let len = rest
+            .len()
+            .checked_sub(COUNTER_LEN + MIC_LEN)
+            .ok_or(Error::InvalidValue)?;
+        let (ciphertext, rest) = rest.split_at(len);
+        let counter = u32::from_le_bytes([rest[0], rest[1], rest[2], rest[3]]);
+        let objects = buf.get_mut(..len).ok_or(Error::InsufficientSpace)?;
+        objects.copy_from_slice(ciphertext);
+        let mic = ccm(key, &nonce(address, info, counter), &[], objects, MIC_LEN, false);
+        if mic[..MIC_LEN] != rest[COUNTER_LEN..] {
+            return Err(Error::InvalidValue);
+        }
+        Ok((Self { info, objects }, counter))
+    }
+
+    /// Check if the device only advertises when an event is triggered.
+    pub fn trigger_based(&self) -> bool {
+        self.info & TRIGGER_BASED != 0
+    }
+
+    /// Iterate over the objects.
+    pub fn objects(&self) -> Objects<'a> {
+        Objects { data: self.objects }
+    }
+}
+
+/// Iterator over the objects of BTHome service data.
+///
+/// Decoding stops with `Error::NotSupported` at an unknown object ID, as its size is unknown.
+#[derive(Debug, Clone)]
+pub struct Objects<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Objects<'a> {
+    type Item = Result<Object<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&id, rest) = self.data.split_first()?;
+        let result = match format(id) {
+            Some(Format::Fixed(size, _, _)) => rest.get(..size).map(|value| (value, size)),
+            Some(Format::Variable) => rest
+                .split_first()
+                .and_then(|(&len, value)| value.get(..usize::from(len)))
+                .map(|value| (value, value.len() + 1)),
+            None => {
+                self.data = &[];
+                return Some(Err(Error::NotSupported));
+            }
+        };
+        match result {
+            Some((data, size)) => {
+                self.data = &rest[size..];
+                Some(Ok(Object { id, data }))
+            }
+            None => {
+                self.data = &[];
+                Some(Err(Error::InvalidValue))
+            }
+        }
+    }
+}
+
+/// A BTHome object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Object<'a> {
+    /// Object ID, see [`object`].
+    pub id: u8,
+    /// Value of the object as encoded, without the length prefix of variable length objects.
+    pub data: &'a [u8],
+}
+
+impl Object<'_> {
+    /// Unscaled value, `None` for variable length objects.
+    pub fn raw(&self) -> Option<i64> {
+        let Some(Format::Fixed(size, signed, _)) = format(self.id) else {
+            return None;
+        };
+        let mut bytes = [0; 8];
+        bytes[..size].copy_from_slice(self.data);
+        let value = i64::from_le_bytes(bytes);
+        let shift = 64 - 8 * size as u32;
+        Some(if signed { value << shift >> shift } else { value })
+    }
+
+    /// Value scaled by the factor of the object, `None` for variable length objects.
+    pub fn value(&self) -> Option<f32> {
+        let Some(Format::Fixed(_, _, factor)) = format(self.id) else {
+            return None;
+        };
+        self.raw().map(|raw| raw as f32 * factor)
+    }
+}
+
+#[cfg(feature = "security")]
+fn nonce(address: &BdAddr, info: u8, counter: u32) -> [u8; 13] {
+    let mut nonce = [0; 13];
+    // The address in the order it is displayed
+    for (dest, src) in nonce[..6].iter_mut().zip(address.raw().iter().rev()) {
+        *dest = *src;
+    }
+    nonce[6..8].copy_from_slice(&UUID);
+    nonce[8] = info;
+    nonce[9..].copy_from_slice(&counter.to_le_bytes());
+    nonce
+}
+
+/// AES-CCM with a 13 byte nonce (RFC 3610), encrypting or decrypting `data` in place.
+///
+/// Returns the encrypted authentication value, of which the first `mic_len` bytes are the MIC.
+#[cfg(feature = "security")]
+fn ccm(key: &[u8; 16], nonce: &[u8; 13], aad: &[u8], data: &mut [u8], mic_len: usize, encrypt: bool) -> [u8; 16] {
+    let cipher = Aes128::new(key.into());
+    let block = |flags: u8, value: u16| {
+        let mut block = [0; 16];
+        block[0] = flags;
+        block[1..14].copy_from_slice(nonce);
+        block[14..].copy_from_slice(&value.to_be_bytes());
+        block
+    };
+    let keystream = |counter: u16| {
+        let mut block = block(0x01, counter);
+        cipher.encrypt_block((&mut block).into());
+        block
+    };
+
+    let adata = if aad.is_empty() { 0 } else { 0x40 };
+    let mut mac = CbcMac {
+        cipher: &cipher,
+        x: block(adata | ((mic_len as u8 - 2) / 2) << 3 | 0x01, data.len() as u16),
+        pos: 16,
+    };
+    if !aad.is_empty() {
+        mac.update(&(aad.len() as u16).to_be_bytes());
+        mac.update(aad);
+        mac.pad();
+    }
+    if encrypt {
+        mac.update(data);
+        mac.pad();
+    }
+    for (i, chunk) in data.chunks_mut(16).enumerate() {
+        let stream = keystream(i as u16 + 1);
+        for (b, s) in chunk.iter_mut().zip(stream.iter()) {
+            *b ^= s;
+        }
+    }
+    if !encrypt {
+        mac.update(data);
+        mac.pad();
+    }
+    let mut tag = mac.finish();
+    for (t, s) in tag.iter_mut().zip(keystream(0).iter()) {
+        *t ^= s;
+    }
+    tag
+}
+
+#[cfg(feature = "security")]
+struct CbcMac<'a> {
+    cipher: &'a Aes128,
+    x: [u8; 16],
+    pos: usize,
+}
+
+#[cfg(feature = "security")]
+impl CbcMac<'_> {
+    // The block in `x` is encrypted once it is full and more data follows, or when finishing
+    fn update(&mut self, data: &[u8]) {
+        for b in data {
+            if self.pos == 16 {
+                self.cipher.encrypt_block((&mut self.x).into());
+                self.pos = 0;
+            }
+            self.x[self.pos] ^= b;
+            self.pos += 1;
+        }
+    }
+
+    // Zero padding to a block boundary
+    fn pad(&mut self) {
+        self.pos = 16;
+    }
+
+    fn finish(mut self) -> [u8; 16] {
+        self.cipher.encrypt_block((&mut self.x).into());
+        self.x
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let mut buf = [0; 24];
+        let mut encoder = Encoder::new(&mut buf, false).unwrap();
+        encoder.add(object::TEMPERATURE, 25.06).unwrap();
+        encoder.add(object::HUMIDITY, 50.55).unwrap();
+        encoder.add_bytes(object::TEXT, b"hi").unwrap();
+        assert_eq!(encoder.add(object::BATTERY, 256.0), Err(Error::InvalidValue));
+        let data = encoder.finish();
+        assert_eq!(data, &[0x40, 0x02, 0xca, 0x09, 0x03, 0xbf, 0x13, 0x53, 2, b'h', b'i']);
+
+        let decoded = BtHomeData::parse(data).unwrap();
+        assert!(!decoded.trigger_based());
+        let mut objects = decoded.objects();
+        let temperature = objects.next().unwrap().unwrap();
+        assert_eq!(temperature.raw(), Some(2506));
+        assert_eq!(objects.next().unwrap().unwrap().raw(), Some(5055));
+        assert_eq!(objects.next().unwrap().unwrap().data, b"hi");
+        assert!(objects.next().is_none());
+
+        let negative = [0x40, 0x02, 0x9c, 0xff, 0x5f, 0x00];
+        let mut objects = BtHomeData::parse(&negative).unwrap().objects();
+        assert_eq!(objects.next().unwrap().unwrap().raw(), Some(-100));
+        assert_eq!(objects.next(), Some(Err(Error::NotSupported)));
+        assert!(objects.next().is_none());
+    }
+
+    #[cfg(feature = "security")]
+    #[test]
+    fn ccm_rfc3610() {
+        // RFC 3610, packet vector #1
+        let key = [
+            0xc0, 0xc1, 0xc2, 0xc3, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcb, 0xcc, 0xcd, 0xce, 0xcf,
+        ];
+        let nonce = [
+            0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5,
+        ];
+        let aad = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07];
+        let mut data: [u8; 23] = core::array::from_fn(|i| i as u8 + 8);
+        let mic = ccm(&key, &nonce, &aad, &mut data, 8, true);
+        assert_eq!(
+            data,
+            [
+                0x58, 0x8c, 0x97, 0x9a, 0x61, 0xc6, 0x63, 0xd2, 0xf0, 0x66, 0xd0, 0xc2, 0xc0, 0xf9, 0x89, 0x80, 0x6d,
+                0x5f, 0x6b, 0x61, 0xda, 0xc3, 0x84
+            ]
+        );
+        assert_eq!(mic[..8], [0x17, 0xe8, 0xd1, 0x2c, 0xfd, 0xf9, 0x26, 0xe0]);
+        assert_eq!(ccm(&key, &nonce, &aad, &mut data, 8, false)[..8], mic[..8]);
+        assert_eq!(data[0], 8);
+    }
+
+    #[cfg(feature = "security")]
+    #[test]
+    fn encrypted() {
+        let key = [0x23; 16];
+        let address = BdAddr::new([0xa5, 0x80, 0x8f, 0xe6, 0x48, 0x54]);
+        let mut buf = [0; 24];
+        let mut encoder = Encoder::new(&mut buf, false).unwrap();
+        encoder.add(object::TEMPERATURE, 25.06).unwrap();
+        let data = encoder.finish_encrypted(&key, &address, 0x0011_2233).unwrap();
+        assert_eq!(data.len(), 12);
+        assert_eq!(data[0], 0x41);
+        assert_eq!(data[4..8], [0x33, 0x22, 0x11, 0x00]);
+
+        let mut plain = [0; 8];
+        let (decoded, counter) = BtHomeData::decrypt(data, &key, &address, &mut plain).unwrap();
+        assert_eq!(counter, 0x0011_2233);
+        assert_eq!(decoded.objects().next().unwrap().unwrap().value(), Some(25.06));
+
+        let mut tampered = [0; 12];
+        tampered.copy_from_slice(data);
+        tampered[1] ^= 1;
+        assert_eq!(
+            BtHomeData::decrypt(&tampered, &key, &address, &mut plain),
+            Err(Error::InvalidValue)
+        );
+    }
+}
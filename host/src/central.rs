@@ -4,6 +4,7 @@ use bt_hci::controller::{Controller, ControllerCmdAsync, ControllerCmdSync};
 use bt_hci::param::{AddrKind, BdAddr, InitiatingPhy, LeConnRole, PhyParams};
 use embassy_futures::select::{select, Either};
 
+use crate::coex::CoexActivity;
 use crate::connection::{ConnectConfig, Connection, PhySet};
 use crate::{BleHostError, Error, PacketPool, Stack};
 
@@ -18,6 +19,9 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
     }
 
     /// Attempt to create a connection with the provided config.
+    ///
+    /// The controller connects to the first device of the filter accept list it discovers. Use
+    /// [`Connection::peer`] to find out which device was connected.
     pub async fn connect(&mut self, config: &ConnectConfig<'_>) -> Result<Connection<'stack, P>, BleHostError<C::Error>>
     where
         C: ControllerCmdSync<LeClearFilterAcceptList>
@@ -27,15 +31,26 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
         if config.scan_config.filter_accept_list.is_empty() {
             return Err(Error::ConfigFilterAcceptListIsEmpty.into());
         }
-
         let host = &self.stack.host;
+        // Scanning and initiating share the filter accept list. Also ensures no other connect is ongoing.
+        if !host
+            .connect_command_state
+            .request_unless(true, &host.scan_command_state)
+            .await
+        {
+            return Err(Error::ScanInProgress.into());
+        }
         let _drop = crate::host::OnDrop::new(|| {
             host.connect_command_state.cancel(true);
         });
-        host.connect_command_state.request().await;
 
         self.set_accept_filter(config.scan_config.filter_accept_list).await?;
 
+        let (min_event_length, max_event_length) = host.coex.event_length(
+            config.connect_params.min_event_length,
+            config.connect_params.max_event_length,
+        );
+        host.coex.notify(CoexActivity::Connecting);
         host.async_command(LeCreateConn::new(
             config.scan_config.interval.into(),
             config.scan_config.window.into(),
@@ -47,8 +62,8 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
             config.connect_params.max_connection_interval.into(),
             config.connect_params.max_latency,
             config.connect_params.supervision_timeout.into(),
-            config.connect_params.min_event_length.into(),
-            config.connect_params.max_event_length.into(),
+            min_event_length.into(),
+            max_event_length.into(),
         ))
         .await?;
         match select(
@@ -80,16 +95,25 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
         if config.scan_config.filter_accept_list.is_empty() {
             return Err(Error::ConfigFilterAcceptListIsEmpty.into());
         }
-
         let host = &self.stack.host;
-        // Ensure no other connect ongoing.
+        // Scanning and initiating share the filter accept list. Also ensures no other connect is ongoing.
+        if !host
+            .connect_command_state
+            .request_unless(true, &host.scan_command_state)
+            .await
+        {
+            return Err(Error::ScanInProgress.into());
+        }
         let _drop = crate::host::OnDrop::new(|| {
             host.connect_command_state.cancel(true);
         });
-        host.connect_command_state.request().await;
 
         self.set_accept_filter(config.scan_config.filter_accept_list).await?;
 
+        let (min_event_length, max_event_length) = host.coex.event_length(
+            config.connect_params.min_event_length,
+            config.connect_params.max_event_length,
+        );
         let initiating = InitiatingPhy {
             scan_interval: config.scan_config.interval.into(),
             scan_window: config.scan_config.window.into(),
@@ -97,11 +121,12 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
             conn_interval_max: config.connect_params.max_connection_interval.into(),
             max_latency: config.connect_params.max_latency,
             supervision_timeout: config.connect_params.supervision_timeout.into(),
-            min_ce_len: config.connect_params.min_event_length.into(),
-            max_ce_len: config.connect_params.max_event_length.into(),
+            min_ce_len: min_event_length.into(),
+            max_ce_len: max_event_length.into(),
         };
         let phy_params = create_phy_params(initiating, config.scan_config.phys);
 
+        host.coex.notify(CoexActivity::Connecting);
         host.async_command(LeExtCreateConn::new(
             true,
             host.address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC),
@@ -134,11 +159,22 @@ impl<'stack, C: Controller, P: PacketPool> Central<'stack, C, P> {
     where
         C: ControllerCmdSync<LeClearFilterAcceptList> + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
+        // Only public and random addresses can be added, anonymous advertisers cannot be connected to
+        let valid = [AddrKind::PUBLIC, AddrKind::RANDOM];
+        if filter_accept_list
+            .iter()
+            .any(|entry| !valid.contains(&crate::identity_addr_kind(entry.0)))
+        {
+            return Err(Error::InvalidValue.into());
+        }
         let host = &self.stack.host;
         host.command(LeClearFilterAcceptList::new()).await?;
         for entry in filter_accept_list {
-            host.command(LeAddDeviceToFilterAcceptList::new(entry.0, *entry.1))
-                .await?;
+            host.command(LeAddDeviceToFilterAcceptList::new(
+                crate::identity_addr_kind(entry.0),
+                *entry.1,
+            ))
+            .await?;
         }
         Ok(())
     }
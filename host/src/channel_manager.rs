@@ -8,6 +8,8 @@ use bt_hci::FromHciBytes;
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::WakerRegistration;
+#[cfg(feature = "channel-metrics")]
+use embassy_time::{Duration, Instant};
 
 use crate::connection_manager::ConnectionManager;
 use crate::cursor::WriteCursor;
@@ -27,6 +29,10 @@ const BASE_ID: u16 = 0x40;
 
 struct State<'d, P> {
     next_req_id: u8,
+    // Maximum number of packets each channel may hold for reassembly and inbound queueing.
+    rx_quota: u16,
+    channels_high_water: usize,
+    rx_packets_high_water: usize,
     channels: &'d mut [ChannelStorage<P>],
     accept_waker: WakerRegistration,
     create_waker: WakerRegistration,
@@ -63,6 +69,10 @@ impl<P, const QLEN: usize> PacketChannel<P, QLEN> {
         self.chan.try_send(Some(pdu)).map_err(|_| Error::OutOfMemory)
     }
 
+    pub fn len(&self) -> usize {
+        self.chan.len()
+    }
+
     pub fn poll_receive(&self, cx: &mut Context<'_>) -> Poll<Option<Pdu<P>>> {
         self.chan.poll_receive(cx)
     }
@@ -90,6 +100,17 @@ impl<P> State<'_, P> {
         next
     }
 
+    fn channels_in_use(&self) -> usize {
+        self.channels
+            .iter()
+            .filter(|c| c.state != ChannelState::Disconnected || c.refcount > 0)
+            .count()
+    }
+
+    fn rx_packets(&self) -> usize {
+        self.channels.iter().map(|c| c.rx_packets()).sum()
+    }
+
     fn inc_ref(&mut self, index: ChannelIndex) {
         let state = &mut self.channels[index.0 as usize];
         state.refcount = unwrap!(state.refcount.checked_add(1), "Too many references to the same channel");
@@ -97,10 +118,13 @@ impl<P> State<'_, P> {
 }
 
 impl<'d, P: PacketPool> ChannelManager<'d, P> {
-    pub fn new(channels: &'d mut [ChannelStorage<P::Packet>]) -> Self {
+    pub fn new(channels: &'d mut [ChannelStorage<P::Packet>], rx_quota: u16) -> Self {
         Self {
             state: RefCell::new(State {
                 next_req_id: 0,
+                rx_quota,
+                channels_high_water: 0,
+                rx_packets_high_water: 0,
                 channels,
                 accept_waker: WakerRegistration::new(),
                 create_waker: WakerRegistration::new(),
@@ -113,6 +137,19 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         self.state.borrow_mut().next_request_id()
     }
 
+    /// Number of packets each channel may hold for reassembly and inbound queueing.
+    pub(crate) fn rx_quota(&self) -> u16 {
+        self.state.borrow().rx_quota
+    }
+
+    // Credits issued to the peer when the channel is established, limited by the channel quota.
+    fn initial_credits(&self, config: &L2capChannelConfig) -> u16 {
+        config
+            .initial_credits
+            .unwrap_or(config::L2CAP_RX_QUEUE_SIZE as u16)
+            .min(self.rx_quota())
+    }
+
     pub(crate) fn psm(&self, index: ChannelIndex) -> u16 {
         self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
@@ -120,6 +157,27 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         })
     }
 
+    pub(crate) fn mtu(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            chan.mtu.min(chan.peer_mtu)
+        })
+    }
+
+    pub(crate) fn rx_mtu(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            chan.mtu
+        })
+    }
+
+    pub(crate) fn tx_mtu(&self, index: ChannelIndex) -> u16 {
+        self.with_mut(|state| {
+            let chan = &mut state.channels[index.0 as usize];
+            chan.peer_mtu
+        })
+    }
+
     pub(crate) fn disconnect(&self, index: ChannelIndex) {
         self.with_mut(|state| {
             let chan = &mut state.channels[index.0 as usize];
@@ -162,6 +220,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 storage.conn = Some(conn);
                 storage.cid = cid;
                 f(storage);
+                state.channels_high_water = state.channels_high_water.max(state.channels_in_use());
                 return Ok(ChannelIndex(idx as u8));
             }
         }
@@ -175,18 +234,9 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         config: &L2capChannelConfig,
         ble: &BleHost<'d, T, P>,
     ) -> Result<L2capChannel<'d, P>, BleHostError<T::Error>> {
-        let L2capChannelConfig {
-            mtu,
-            mps,
-            flow_policy,
-            initial_credits,
-        } = config;
-
-        let mtu = mtu.unwrap_or(P::MTU as u16 - 6);
-        let mps = mps.unwrap_or(P::MTU as u16 - 4);
-        if mps > P::MTU as u16 - 4 {
-            return Err(Error::InsufficientSpace.into());
-        }
+        let (mtu, mps, tx_mtu, tx_mps) = Self::channel_limits(config)?;
+        let flow_policy = config.flow_policy;
+        let initial_credits = self.initial_credits(config);
 
         // Wait until we find a channel for our connection in the connecting state matching our PSM.
         let (channel, req_id, mps, mtu, cid, credits) = poll_fn(|cx| {
@@ -195,15 +245,12 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             for (idx, chan) in state.channels.iter_mut().enumerate() {
                 match chan.state {
                     ChannelState::PeerConnecting(req_id) if chan.conn == Some(conn) && psm.contains(&chan.psm) => {
-                        chan.mtu = chan.mtu.min(mtu);
-                        chan.mps = chan.mps.min(mps);
-                        chan.flow_control = CreditFlowControl::new(
-                            *flow_policy,
-                            initial_credits.unwrap_or(config::L2CAP_RX_QUEUE_SIZE.min(P::capacity()) as u16),
-                        );
+                        chan.mtu = mtu;
+                        chan.mps = mps;
+                        chan.peer_mtu = chan.peer_mtu.min(tx_mtu);
+                        chan.peer_mps = chan.peer_mps.min(tx_mps);
+                        chan.flow_control = CreditFlowControl::new(flow_policy, initial_credits);
                         chan.state = ChannelState::Connected;
-                        let mps = chan.mps;
-                        let mtu = chan.mtu;
                         let cid = chan.cid;
                         let available = chan.flow_control.available();
                         if chan.refcount != 0 {
@@ -248,31 +295,25 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         config: &L2capChannelConfig,
         ble: &BleHost<'_, T, P>,
     ) -> Result<L2capChannel<'d, P>, BleHostError<T::Error>> {
-        let L2capChannelConfig {
-            mtu,
-            mps,
-            flow_policy,
-            initial_credits,
-        } = config;
+        let (mtu, mps, tx_mtu, tx_mps) = Self::channel_limits(config)?;
+        let flow_policy = config.flow_policy;
 
         let req_id = self.next_request_id();
         let mut credits = 0;
         let mut cid: u16 = 0;
 
-        let mtu = mtu.unwrap_or(P::MTU as u16 - 6);
-        let mps = mps.unwrap_or(P::MTU as u16 - 4);
-        if mps > P::MTU as u16 - 4 {
-            return Err(Error::InsufficientSpace.into());
-        }
+        let initial_credits = self.initial_credits(config);
 
         // Allocate space for our new channel.
         let idx = self.alloc(conn, |storage| {
             cid = storage.cid;
-            credits = initial_credits.unwrap_or(config::L2CAP_RX_QUEUE_SIZE.min(P::capacity()) as u16);
+            credits = initial_credits;
             storage.psm = psm;
             storage.mtu = mtu;
             storage.mps = mps;
-            storage.flow_control = CreditFlowControl::new(*flow_policy, credits);
+            storage.peer_mtu = tx_mtu;
+            storage.peer_mps = tx_mps;
+            storage.flow_control = CreditFlowControl::new(flow_policy, credits);
             storage.state = ChannelState::Connecting(req_id);
         })?;
 
@@ -340,9 +381,14 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 return Err(Error::InvalidChannelId);
             }
 
+            let rx_quota = state.rx_quota;
             let storage = &mut state.channels[chan];
             match storage.state {
                 ChannelState::Connected if channel == storage.cid => {
+                    if storage.inbound.len() >= rx_quota as usize {
+                        debug!("[l2cap][cid = {}] rx quota of {} packets exceeded", channel, rx_quota);
+                        return Err(Error::OutOfMemory);
+                    }
                     if storage.flow_control.available() == 0 {
                         #[cfg(feature = "channel-metrics")]
                         storage.metrics.blocked_receive();
@@ -363,6 +409,22 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         })
     }
 
+    /// Check that an SDU announced by the peer fits within the channel MTU.
+    pub(crate) fn check_sdu_len(&self, channel: u16, sdu_len: u16) -> Result<(), Error> {
+        if channel < BASE_ID {
+            return Err(Error::InvalidChannelId);
+        }
+
+        let chan = (channel - BASE_ID) as usize;
+        self.with_mut(|state| match state.channels.get(chan) {
+            Some(storage) if storage.state == ChannelState::Connected && channel == storage.cid => {
+                storage.check_sdu_len(sdu_len)
+            }
+            Some(_) => Err(Error::NotFound),
+            None => Err(Error::InvalidChannelId),
+        })
+    }
+
     pub(crate) fn dispatch(&self, channel: u16, pdu: Pdu<P::Packet>) -> Result<(), Error> {
         if channel < BASE_ID {
             return Err(Error::InvalidChannelId);
@@ -374,6 +436,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                 return Err(Error::InvalidChannelId);
             }
 
+            let rx_quota = state.rx_quota;
             let mut sdu = None;
             let storage = &mut state.channels[chan];
             match storage.state {
@@ -402,6 +465,13 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                             let (first, _) = pdu.as_ref().split_at(2);
                             let sdu_len: u16 = u16::from_le_bytes([first[0], first[1]]);
                             let len = pdu.len() - 2;
+                            storage.check_sdu_len(sdu_len)?;
+
+                            // The SDU being started holds one more packet on top of the queued ones.
+                            if storage.inbound.len() >= rx_quota as usize {
+                                debug!("[l2cap][cid = {}] rx quota of {} packets exceeded", channel, rx_quota);
+                                return Err(Error::OutOfMemory);
+                            }
 
                             let mut packet = pdu.into_inner();
                             packet.as_mut().rotate_left(2);
@@ -422,9 +492,12 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             }
 
             if let Some(sdu) = sdu {
+                #[cfg(feature = "channel-metrics")]
+                storage.metrics.received_sdu(sdu.len());
                 storage.inbound.try_send(sdu)?;
             }
 
+            state.rx_packets_high_water = state.rx_packets_high_water.max(state.rx_packets());
             Ok(())
         })
     }
@@ -457,30 +530,27 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             }
             L2capSignalCode::DisconnectionReq => {
                 let req = DisconnectionReq::from_hci_bytes_complete(data)?;
-                debug!("[l2cap][conn = {:?}, cid = {}] disconnect request", conn, req.dcid);
+                debug!(conn = conn, "[l2cap][cid = {}] disconnect request", req.dcid);
                 self.handle_disconnect_request(req.dcid)?;
             }
             L2capSignalCode::DisconnectionRes => {
                 let res = DisconnectionRes::from_hci_bytes_complete(data)?;
-                debug!("[l2cap][conn = {:?}, cid = {}] disconnect response", conn, res.scid);
+                debug!(conn = conn, "[l2cap][cid = {}] disconnect response", res.scid);
                 self.handle_disconnect_response(res.scid)?;
             }
             L2capSignalCode::ConnParamUpdateReq => {
                 let req = ConnParamUpdateReq::from_hci_bytes_complete(data)?;
                 debug!(
-                    "[l2cap][conn = {:?}] connection param update request: {:?}, ignored",
-                    conn, req
+                    conn = conn,
+                    "[l2cap] connection param update request: {:?}, ignored", req
                 );
             }
             L2capSignalCode::ConnParamUpdateRes => {
                 let res = ConnParamUpdateRes::from_hci_bytes_complete(data)?;
-                debug!(
-                    "[l2cap][conn = {:?}] connection param update response: {}",
-                    conn, res.result,
-                );
+                debug!(conn = conn, "[l2cap] connection param update response: {}", res.result);
             }
             r => {
-                warn!("[l2cap][conn = {:?}] unsupported signal: {:?}", conn, r);
+                warn!(conn = conn, "[l2cap] unsupported signal: {:?}", r);
                 return Err(Error::NotSupported);
             }
         }
@@ -493,8 +563,8 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             storage.psm = req.psm;
             storage.peer_cid = req.scid;
             storage.peer_credits = req.credits;
-            storage.mps = req.mps;
-            storage.mtu = req.mtu;
+            storage.peer_mps = req.mps;
+            storage.peer_mtu = req.mtu;
             storage.state = ChannelState::PeerConnecting(identifier);
         })?;
         self.state.borrow_mut().accept_waker.wake();
@@ -511,8 +581,8 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                         ChannelState::Connecting(req_id) if identifier == req_id && Some(conn) == storage.conn => {
                             storage.peer_cid = res.dcid;
                             storage.peer_credits = res.credits;
-                            storage.mps = storage.mps.min(res.mps);
-                            storage.mtu = storage.mtu.min(res.mtu);
+                            storage.peer_mps = storage.peer_mps.min(res.mps);
+                            storage.peer_mtu = storage.peer_mtu.min(res.mtu);
                             storage.state = ChannelState::Connected;
                             state.create_waker.wake();
                             return Ok(());
@@ -521,14 +591,13 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
                     }
                 }
                 debug!(
-                    "[l2cap][handle_connect_response][link = {}] request with id {} not found",
-                    conn.raw(),
-                    identifier
+                    conn = conn,
+                    "[l2cap][handle_connect_response] request with id {} not found", identifier
                 );
                 Err(Error::NotFound)
             }
             other => {
-                warn!("Channel open request failed: {:?}", other);
+                warn!(conn = conn, "[l2cap] channel open request failed: {:?}", other);
                 Err(Error::NotSupported)
             }
         }
@@ -540,6 +609,7 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             match storage.state {
                 ChannelState::Connected if storage.peer_cid == req.cid && Some(conn) == storage.conn => {
                     trace!(
+                        conn = conn,
                         "[l2cap][handle_credit_flow][cid = {}] {} += {} credits",
                         req.cid,
                         storage.peer_credits,
@@ -657,7 +727,11 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         let n_packets = len.div_ceil(mps);
         // info!("[host] sending {} LE K frames, len {}, mps {}", n_packets, len, mps);
 
+        #[cfg(feature = "channel-metrics")]
+        let start = Instant::now();
         let mut grant = poll_fn(|cx| self.poll_request_to_send(index, n_packets, Some(cx))).await?;
+        #[cfg(feature = "channel-metrics")]
+        let stall = start.elapsed();
 
         // Segment using mps
         let (first, remaining) = buf.split_at(buf.len().min(mps as usize - 2));
@@ -673,12 +747,14 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             ble.l2cap(conn, (len - 4) as u16, 1).await?.send(&p_buf[..len]).await?;
             grant.confirm(1);
         }
+        #[cfg(feature = "channel-metrics")]
+        self.with_mut(|state| state.channels[index.0 as usize].metrics.sent_sdu(buf.len(), stall));
         Ok(())
     }
 
     /// Send the provided buffer over a given l2cap channel.
     ///
-    /// The buffer must be equal to or smaller than the MTU agreed for the channel.
+    /// The buffer must be equal to or smaller than the transmit MTU of the channel.
     ///
     /// If the channel has been closed or the channel id is not valid, an error is returned.
     pub(crate) fn try_send<T: Controller + blocking::Controller>(
@@ -722,6 +798,12 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
             sender.try_send(&p_buf[..len])?;
             grant.confirm(1);
         }
+        #[cfg(feature = "channel-metrics")]
+        self.with_mut(|state| {
+            state.channels[index.0 as usize]
+                .metrics
+                .sent_sdu(buf.len(), Duration::from_ticks(0))
+        });
         Ok(())
     }
 
@@ -736,11 +818,24 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         host.l2cap_signal(handle, identifier, param, &mut tx[..]).await
     }
 
+    // Receive (mtu, mps) advertised to the peer and transmit (mtu, mps) limits for a channel.
+    fn channel_limits(config: &L2capChannelConfig) -> Result<(u16, u16, u16, u16), Error> {
+        let max_mps = P::MTU as u16 - 4;
+        let mtu = config.mtu.unwrap_or(P::MTU as u16 - 6);
+        let mps = config.mps.unwrap_or(max_mps);
+        let tx_mtu = config.tx_mtu.unwrap_or(u16::MAX);
+        let tx_mps = config.tx_mps.unwrap_or(max_mps);
+        if mps > max_mps || tx_mps > max_mps {
+            return Err(Error::InsufficientSpace);
+        }
+        Ok((mtu, mps, tx_mtu, tx_mps))
+    }
+
     fn connected_channel_params(&self, index: ChannelIndex) -> Result<(ConnHandle, u16, u16, u16), Error> {
         let state = self.state.borrow();
         let chan = &state.channels[index.0 as usize];
         if chan.state == ChannelState::Connected {
-            return Ok((chan.conn.unwrap(), chan.mps, chan.mtu, chan.peer_cid));
+            return Ok((chan.conn.unwrap(), chan.peer_mps, chan.peer_mtu, chan.peer_cid));
         }
         //trace!("[l2cap][connected_channel_params] channel {} closed", index);
         Err(Error::ChannelClosed)
@@ -855,11 +950,55 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
         });
     }
 
+    /// Number of channel slots in use, the highest number in use so far, and the total number of slots.
+    pub(crate) fn usage(&self) -> (usize, usize, usize) {
+        let state = self.state.borrow();
+        (state.channels_in_use(), state.channels_high_water, state.channels.len())
+    }
+
+    /// Number of packets held for inbound data by all channels, and the highest number held so far.
+    pub(crate) fn rx_packet_usage(&self) -> (usize, usize) {
+        let state = self.state.borrow();
+        (state.rx_packets(), state.rx_packets_high_water)
+    }
+
     pub(crate) fn log_status(&self, verbose: bool) {
         let state = self.state.borrow();
         state.print(verbose);
     }
 
+    /// Visit a snapshot of each channel in use.
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn debug_state(&self, mut f: impl FnMut(crate::debug_state::ChannelDebugState)) {
+        use crate::debug_state::{ChannelDebugState, L2capState};
+        let state = self.state.borrow();
+        for chan in state.channels.iter() {
+            let Some(conn) = chan.conn else {
+                continue;
+            };
+            let l2cap = match chan.state {
+                ChannelState::Disconnected if chan.refcount == 0 => continue,
+                ChannelState::Disconnected => L2capState::Disconnected,
+                ChannelState::Connecting(_) => L2capState::Connecting,
+                ChannelState::PeerConnecting(_) => L2capState::PeerConnecting,
+                ChannelState::Connected => L2capState::Connected,
+                ChannelState::PeerDisconnecting => L2capState::PeerDisconnecting,
+                ChannelState::Disconnecting => L2capState::Disconnecting,
+            };
+            f(ChannelDebugState {
+                conn,
+                state: l2cap,
+                cid: chan.cid,
+                peer_cid: chan.peer_cid,
+                psm: chan.psm,
+                local_credits: chan.flow_control.available(),
+                peer_credits: chan.peer_credits,
+                rx_queued: chan.inbound.len(),
+                refcount: chan.refcount,
+            });
+        }
+    }
+
     #[cfg(feature = "defmt")]
     pub(crate) fn print(&self, index: ChannelIndex, f: defmt::Formatter) {
         use defmt::Format;
@@ -872,7 +1011,9 @@ impl<'d, P: PacketPool> ChannelManager<'d, P> {
     #[cfg(feature = "channel-metrics")]
     pub(crate) fn metrics<F: FnOnce(&Metrics) -> R, R>(&self, index: ChannelIndex, f: F) -> R {
         self.with_mut(|state| {
-            let state = &state.channels[index.0 as usize];
+            let state = &mut state.channels[index.0 as usize];
+            state.metrics.local_credits = state.flow_control.available();
+            state.metrics.peer_credits = state.peer_credits;
             f(&state.metrics)
         })
     }
@@ -947,6 +1088,8 @@ pub struct ChannelStorage<P> {
     refcount: u8,
 
     peer_cid: u16,
+    peer_mps: u16,
+    peer_mtu: u16,
     peer_credits: u16,
     credit_waker: WakerRegistration,
 
@@ -970,6 +1113,20 @@ pub struct Metrics {
     pub blocked_send: usize,
     /// Number of l2cap packets blocked from receiving.
     pub blocked_receive: usize,
+    /// Number of sent SDUs.
+    pub sdus_sent: usize,
+    /// Number of received SDUs.
+    pub sdus_received: usize,
+    /// Number of payload bytes in sent SDUs.
+    pub bytes_sent: usize,
+    /// Number of payload bytes in received SDUs.
+    pub bytes_received: usize,
+    /// Total time spent waiting for credits from the peer before sending.
+    pub credit_stall: Duration,
+    /// Credits granted to the peer that it has not used yet, when the metrics were read.
+    pub local_credits: u16,
+    /// Credits granted by the peer that have not been used yet, when the metrics were read.
+    pub peer_credits: u16,
 }
 
 #[cfg(feature = "channel-metrics")]
@@ -980,6 +1137,13 @@ impl Metrics {
             num_received: 0,
             blocked_send: 0,
             blocked_receive: 0,
+            sdus_sent: 0,
+            sdus_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            credit_stall: Duration::from_ticks(0),
+            local_credits: 0,
+            peer_credits: 0,
         }
     }
     pub(crate) fn sent(&mut self, num: usize) {
@@ -990,6 +1154,17 @@ impl Metrics {
         self.num_received = self.num_received.wrapping_add(num);
     }
 
+    pub(crate) fn sent_sdu(&mut self, len: usize, stall: Duration) {
+        self.sdus_sent = self.sdus_sent.wrapping_add(1);
+        self.bytes_sent = self.bytes_sent.wrapping_add(len);
+        self.credit_stall += stall;
+    }
+
+    pub(crate) fn received_sdu(&mut self, len: usize) {
+        self.sdus_received = self.sdus_received.wrapping_add(1);
+        self.bytes_received = self.bytes_received.wrapping_add(len);
+    }
+
     pub(crate) fn blocked_send(&mut self) {
         self.blocked_send = self.blocked_send.wrapping_add(1);
     }
@@ -1009,11 +1184,18 @@ impl defmt::Format for Metrics {
     fn format(&self, f: defmt::Formatter<'_>) {
         defmt::write!(
             f,
-            "sent = {}, recvd = {}, blocked send = {}, blocked receive = {}",
+            "sent = {}, recvd = {}, blocked send = {}, blocked receive = {}, sdus sent = {} ({} bytes), sdus recvd = {} ({} bytes), credit stall = {} ms, cred in = {}, cred out = {}",
             self.num_sent,
             self.num_received,
             self.blocked_send,
             self.blocked_receive,
+            self.sdus_sent,
+            self.bytes_sent,
+            self.sdus_received,
+            self.bytes_received,
+            self.credit_stall.as_millis(),
+            self.local_credits,
+            self.peer_credits,
         );
     }
 }
@@ -1028,6 +1210,8 @@ impl<P> core::fmt::Debug for ChannelStorage<P> {
             .field("peer_cid", &self.peer_cid)
             .field("mps", &self.mps)
             .field("mtu", &self.mtu)
+            .field("peer_mps", &self.peer_mps)
+            .field("peer_mtu", &self.peer_mtu)
             .field("peer_credits", &self.peer_credits)
             .field("available", &self.flow_control.available())
             .field("refcount", &self.refcount);
@@ -1042,13 +1226,15 @@ impl<P> defmt::Format for ChannelStorage<P> {
     fn format(&self, f: defmt::Formatter<'_>) {
         defmt::write!(
             f,
-            "state = {}, c = {}, cid = {}, peer = {}, mps = {}, mtu = {}, cred out {}, cred in = {}, ref = {}",
+            "state = {}, c = {}, cid = {}, peer = {}, mps = {}/{}, mtu = {}/{}, cred out {}, cred in = {}, ref = {}",
             self.state,
             self.conn,
             self.cid,
             self.peer_cid,
             self.mps,
+            self.peer_mps,
             self.mtu,
+            self.peer_mtu,
             self.peer_credits,
             self.flow_control.available(),
             self.refcount,
@@ -1070,6 +1256,8 @@ impl<P> ChannelStorage<P> {
 
             flow_control: CreditFlowControl::new(CreditFlowPolicy::Every(1), 0),
             peer_cid: 0,
+            peer_mps: 0,
+            peer_mtu: 0,
             peer_credits: 0,
             credit_waker: WakerRegistration::new(),
             refcount: 0,
@@ -1081,6 +1269,29 @@ impl<P> ChannelStorage<P> {
         }
     }
 
+    // Packets held by the inbound queue and a reassembly in progress.
+    fn rx_packets(&self) -> usize {
+        #[cfg(not(feature = "l2cap-sdu-reassembly-optimization"))]
+        let reassembling = usize::from(self.reassembly.in_progress());
+        #[cfg(feature = "l2cap-sdu-reassembly-optimization")]
+        let reassembling = 0;
+        self.inbound.len() + reassembling
+    }
+
+    fn check_sdu_len(&self, sdu_len: u16) -> Result<(), Error> {
+        if sdu_len > self.mtu {
+            warn!(
+                "[l2cap][cid = {}] sdu of {} bytes exceeds mtu {}",
+                self.cid, sdu_len, self.mtu
+            );
+            return Err(Error::SduTooLarge {
+                max: self.mtu,
+                actual: sdu_len,
+            });
+        }
+        Ok(())
+    }
+
     fn close(&mut self) {
         self.state = ChannelState::Disconnected;
         self.cid = 0;
@@ -1089,6 +1300,8 @@ impl<P> ChannelStorage<P> {
         self.mtu = 0;
         self.psm = 0;
         self.peer_cid = 0;
+        self.peer_mps = 0;
+        self.peer_mtu = 0;
         self.flow_control = CreditFlowControl::new(CreditFlowPolicy::Every(1), 0);
         self.peer_credits = 0;
     }
@@ -1254,4 +1467,155 @@ mod tests {
             Poll::Ready(Err(BleHostError::BleHost(Error::Disconnected)))
         ));
     }
+
+    #[test]
+    fn reject_oversized_sdu() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let mut cid = 0;
+        ble.channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+                storage.mtu = 23;
+                cid = storage.cid;
+            })
+            .unwrap();
+
+        assert!(ble.channels.rx_quota() >= 1);
+        assert!(ble.channels.check_sdu_len(cid, 23).is_ok());
+        assert!(matches!(
+            ble.channels.check_sdu_len(cid, 24),
+            Err(Error::SduTooLarge { max: 23, actual: 24 })
+        ));
+    }
+
+    #[test]
+    fn asymmetric_limits() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new().with_rx_packets(2);
+        let ble = MockController::new();
+
+        assert_eq!(resources.rx_quota(), 1);
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+        assert_eq!(ble.channels.rx_quota(), 1);
+        // Explicit credits are capped to the quota as well
+        let config = L2capChannelConfig {
+            initial_credits: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(ble.channels.initial_credits(&config), 1);
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connecting(7);
+                storage.mtu = 100;
+                storage.mps = 100;
+                storage.peer_mtu = u16::MAX;
+                storage.peer_mps = 40;
+            })
+            .unwrap();
+        let res = LeCreditConnRes {
+            mps: 64,
+            dcid: 0x40,
+            mtu: 23,
+            credits: 1,
+            result: LeCreditConnResultCode::Success,
+        };
+        ble.channels.handle_connect_response(conn, 7, &res).unwrap();
+
+        assert_eq!(ble.channels.rx_mtu(idx), 100);
+        assert_eq!(ble.channels.tx_mtu(idx), 23);
+        assert_eq!(ble.channels.mtu(idx), 23);
+        assert_eq!(ble.channels.connected_channel_params(idx).unwrap().1, 40);
+
+        let config = L2capChannelConfig {
+            tx_mps: Some(DefaultPacketPool::MTU as u16),
+            ..Default::default()
+        };
+        assert!(ChannelManager::<DefaultPacketPool>::channel_limits(&config).is_err());
+    }
+
+    #[cfg(feature = "channel-metrics")]
+    #[test]
+    fn channel_metrics() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 2> = HostResources::new();
+        let ble = MockController::new();
+
+        let builder = crate::new(ble, &mut resources);
+        let ble = builder.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        let mut cid = 0;
+        let idx = ble
+            .channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+                storage.mtu = 23;
+                storage.peer_credits = 5;
+                storage.flow_control = CreditFlowControl::new(CreditFlowPolicy::default(), 4);
+                cid = storage.cid;
+            })
+            .unwrap();
+
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[..5].copy_from_slice(&[3, 0, 1, 2, 3]);
+        ble.channels.dispatch(cid, Pdu::new(packet, 5)).unwrap();
+
+        ble.channels.metrics(idx, |metrics| {
+            assert_eq!(metrics.num_received, 1);
+            assert_eq!(metrics.sdus_received, 1);
+            assert_eq!(metrics.bytes_received, 3);
+            assert_eq!(metrics.local_credits, 3);
+            assert_eq!(metrics.peer_credits, 5);
+        });
+    }
+
+    #[test]
+    fn memory_usage() {
+        type Resources = HostResources<DefaultPacketPool, 2, 2>;
+        let layout = Resources::memory_layout();
+        assert!(layout.total_bytes >= 2 * layout.connection_bytes + 2 * layout.channel_bytes);
+
+        let mut resources: Resources = HostResources::new();
+        let stack = crate::new(MockController::new(), &mut resources);
+        let ble = &stack.host;
+
+        let conn = ConnHandle::new(33);
+        ble.connections
+            .connect(conn, AddrKind::PUBLIC, BdAddr::new([0; 6]), LeConnRole::Central)
+            .unwrap();
+        ble.channels
+            .alloc(conn, |storage| {
+                storage.state = ChannelState::Connected;
+            })
+            .unwrap();
+
+        let stats = stack.memory_stats();
+        assert_eq!(stats.connections_max, 2);
+        assert_eq!(stats.connections_high_water, 1);
+        assert_eq!(stats.channels_max, 2);
+        assert_eq!(stats.channels_in_use, 1);
+        assert_eq!(stats.channels_high_water, 1);
+
+        ble.channels.disconnected(conn).unwrap();
+        let stats = stack.memory_stats();
+        assert_eq!(stats.channels_in_use, 0);
+        assert_eq!(stats.channels_high_water, 1);
+    }
 }
@@ -0,0 +1,203 @@
+//! Radio coexistence.
+//!
+//! A coexistence manager arbitrating the radio between BLE and another protocol, such as Wi-Fi on ESP32 or
+//! Thread on nRF, can ask the host to yield the radio through [`CoexHints`], which [`Stack`](crate::Stack)
+//! implements, and grant BLE priority around the activity reported to a [`CoexObserver`].
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use bt_hci::param::ConnHandle;
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::{Duration, Instant, Timer};
+
+/// Requests of a coexistence manager to the host.
+pub trait CoexHints {
+    /// Limit the connection event length requested for connections created or updated afterwards.
+    ///
+    /// `None` removes the limit.
+    fn bias_event_length(&self, max: Option<Duration>);
+    /// Pause scanning for `window`.
+    ///
+    /// Scanning in progress is stopped and restarted after the window, new scans wait until it ends.
+    fn pause_scanning(&self, window: Duration);
+    /// Deprioritize advertising for `window`.
+    ///
+    /// Advertising started during the window uses the longest interval of its range. Advertising in progress
+    /// is not changed.
+    fn deprioritize_advertising(&self, window: Duration);
+}
+
+/// Upcoming high priority BLE activity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum CoexActivity {
+    /// A connection is being initiated.
+    Connecting,
+    /// A connection was established, and the peers are about to exchange their initial procedures.
+    Connected(ConnHandle),
+    /// The parameters of a connection are being updated.
+    ConnectionUpdate(ConnHandle),
+    /// A connection is being encrypted.
+    Encryption(ConnHandle),
+}
+
+/// Coexistence manager notified of upcoming high priority BLE activity.
+pub trait CoexObserver {
+    /// Activity is about to start, the manager should give BLE priority on the radio.
+    fn upcoming(&self, activity: CoexActivity);
+}
+
+pub(crate) enum ScanCoex {
+    Pause,
+    Resume,
+}
+
+struct CoexInner<'d> {
+    observer: Option<&'d dyn CoexObserver>,
+    max_event_length: Option<Duration>,
+    adv_deprioritized_until: Option<Instant>,
+    scan_pause_until: Option<Instant>,
+    scan_paused: bool,
+    // Scanning stopped by a pause, with whether it is extended
+    scan_restart: Option<bool>,
+    runner: WakerRegistration,
+    scanners: WakerRegistration,
+}
+
+pub(crate) struct CoexState<'d> {
+    state: RefCell<CoexInner<'d>>,
+}
+
+impl<'d> CoexState<'d> {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: RefCell::new(CoexInner {
+                observer: None,
+                max_event_length: None,
+                adv_deprioritized_until: None,
+                scan_pause_until: None,
+                scan_paused: false,
+                scan_restart: None,
+                runner: WakerRegistration::new(),
+                scanners: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    pub(crate) fn set_observer(&self, observer: &'d dyn CoexObserver) {
+        self.state.borrow_mut().observer.replace(observer);
+    }
+
+    pub(crate) fn notify(&self, activity: CoexActivity) {
+        let observer = self.state.borrow().observer;
+        if let Some(observer) = observer {
+            observer.upcoming(activity);
+        }
+    }
+
+    pub(crate) fn set_max_event_length(&self, max: Option<Duration>) {
+        self.state.borrow_mut().max_event_length = max;
+    }
+
+    /// Connection event lengths limited by the coexistence manager.
+    pub(crate) fn event_length(&self, min: Duration, max: Duration) -> (Duration, Duration) {
+        match self.state.borrow().max_event_length {
+            Some(limit) => (min.min(limit), max.min(limit)),
+            None => (min, max),
+        }
+    }
+
+    pub(crate) fn deprioritize_advertising(&self, window: Duration) {
+        let until = Instant::now() + window;
+        let mut state = self.state.borrow_mut();
+        state.adv_deprioritized_until = Some(state.adv_deprioritized_until.map_or(until, |u| u.max(until)));
+    }
+
+    /// Minimum advertising interval, raised to the maximum while advertising is deprioritized.
+    pub(crate) fn adv_interval_min(&self, min: Duration, max: Duration) -> Duration {
+        match self.state.borrow().adv_deprioritized_until {
+            Some(until) if Instant::now() < until => max,
+            _ => min,
+        }
+    }
+
+    pub(crate) fn pause_scanning(&self, window: Duration) {
+        let until = Instant::now() + window;
+        let mut state = self.state.borrow_mut();
+        state.scan_pause_until = Some(state.scan_pause_until.map_or(until, |u| u.max(until)));
+        state.runner.wake();
+    }
+
+    /// Wait until scanning is not paused.
+    pub(crate) async fn scan_allowed(&self) {
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            state.scanners.register(cx.waker());
+            match state.scan_pause_until {
+                Some(_) => Poll::Pending,
+                None => Poll::Ready(()),
+            }
+        })
+        .await
+    }
+
+    /// Wait until scanning should be paused or resumed.
+    pub(crate) async fn scan_due(&self) -> ScanCoex {
+        loop {
+            let until = poll_fn(|cx| {
+                let mut state = self.state.borrow_mut();
+                state.runner.register(cx.waker());
+                match state.scan_pause_until {
+                    Some(until) => Poll::Ready(until),
+                    None => Poll::Pending,
+                }
+            })
+            .await;
+            {
+                let mut state = self.state.borrow_mut();
+                if !state.scan_paused {
+                    state.scan_paused = true;
+                    return ScanCoex::Pause;
+                }
+            }
+            Timer::at(until).await;
+
+            // The window may have been extended in the meantime
+            let mut state = self.state.borrow_mut();
+            if state.scan_pause_until.is_some_and(|until| Instant::now() >= until) {
+                state.scan_pause_until = None;
+                state.scan_paused = false;
+                state.scanners.wake();
+                return ScanCoex::Resume;
+            }
+        }
+    }
+
+    pub(crate) fn set_scan_restart(&self, extended: bool) {
+        self.state.borrow_mut().scan_restart = Some(extended);
+    }
+
+    pub(crate) fn take_scan_restart(&self) -> Option<bool> {
+        self.state.borrow_mut().scan_restart.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_bias_parameters() {
+        let coex = CoexState::new();
+        let (min, max) = (Duration::from_millis(2), Duration::from_millis(10));
+        assert_eq!(coex.event_length(min, max), (min, max));
+        coex.set_max_event_length(Some(Duration::from_millis(5)));
+        assert_eq!(coex.event_length(min, max), (min, Duration::from_millis(5)));
+
+        let (min, max) = (Duration::from_millis(100), Duration::from_millis(500));
+        assert_eq!(coex.adv_interval_min(min, max), min);
+        coex.deprioritize_advertising(Duration::from_secs(60));
+        assert_eq!(coex.adv_interval_min(min, max), max);
+    }
+}
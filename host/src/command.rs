@@ -5,8 +5,9 @@ use core::task::{Context, Poll};
 use embassy_sync::waitqueue::WakerRegistration;
 
 pub enum State<CTX> {
-    Active,
+    Active(CTX),
     Cancel(CTX),
+    Held,
     Idle,
 }
 
@@ -37,14 +38,14 @@ impl<CTX: Clone + Copy> CommandState<CTX> {
         f(&mut inner)
     }
 
-    /// Request a new command
-    pub async fn request(&self) {
+    /// Request a new command, with the context used to cancel it.
+    pub async fn request(&self, ctx: CTX) {
         poll_fn(|cx| {
             self.with_inner(|inner| {
                 inner.host.register(cx.waker());
                 match inner.state {
                     State::Idle => {
-                        inner.state = State::Active;
+                        inner.state = State::Active(ctx);
                         Poll::Ready(())
                     }
                     _ => Poll::Pending,
@@ -54,6 +55,32 @@ impl<CTX: Clone + Copy> CommandState<CTX> {
         .await
     }
 
+    /// Request a new command, unless the `other` command is active.
+    ///
+    /// Waits while the other command is being canceled or held. Both states are checked in the same poll, after
+    /// registering for wake-ups from both, so the other command cannot start between the check and the request.
+    ///
+    /// Returns `false`, without requesting the command, if the other command is active.
+    pub async fn request_unless(&self, ctx: CTX, other: &Self) -> bool {
+        poll_fn(|cx| {
+            other.with_inner(|other| {
+                other.host.register(cx.waker());
+                self.with_inner(|inner| {
+                    inner.host.register(cx.waker());
+                    match (&other.state, &inner.state) {
+                        (State::Active(_), _) => Poll::Ready(false),
+                        (State::Idle, State::Idle) => {
+                            inner.state = State::Active(ctx);
+                            Poll::Ready(true)
+                        }
+                        _ => Poll::Pending,
+                    }
+                })
+            })
+        })
+        .await
+    }
+
     /// Request a new command.
     pub async fn wait_idle(&self) {
         poll_fn(|cx| {
@@ -79,14 +106,58 @@ impl<CTX: Clone + Copy> CommandState<CTX> {
         })
     }
 
+    /// Hold the state once idle, so that no new command can be requested until released.
+    pub async fn hold(&self) {
+        poll_fn(|cx| {
+            self.with_inner(|inner| {
+                inner.host.register(cx.waker());
+                match inner.state {
+                    State::Idle => {
+                        inner.state = State::Held;
+                        Poll::Ready(())
+                    }
+                    _ => Poll::Pending,
+                }
+            })
+        })
+        .await
+    }
+
+    /// Release a held state.
+    pub fn release(&self) {
+        self.with_inner(|inner| {
+            if let State::Held = inner.state {
+                inner.state = State::Idle;
+                inner.host.wake();
+            }
+        })
+    }
+
     /// Request that any pending command be canceled
     pub fn cancel(&self, ctx: CTX) {
         self.with_inner(|inner| {
+            if let State::Held = inner.state {
+                return;
+            }
             inner.state = State::Cancel(ctx);
             inner.controller.wake();
         })
     }
 
+    /// Request that the active command, if any, be canceled with the context it was requested with.
+    ///
+    /// Returns whether a command was active.
+    pub fn cancel_active(&self) -> bool {
+        self.with_inner(|inner| match inner.state {
+            State::Active(ctx) => {
+                inner.state = State::Cancel(ctx);
+                inner.controller.wake();
+                true
+            }
+            _ => false,
+        })
+    }
+
     /// Signal that a command has been canceled.
     pub fn canceled(&self) {
         self.with_inner(|inner| {
@@ -95,9 +166,98 @@ impl<CTX: Clone + Copy> CommandState<CTX> {
         })
     }
 
+    /// Context of the active command, if any.
+    pub fn active(&self) -> Option<CTX> {
+        self.with_inner(|inner| match inner.state {
+            State::Active(ctx) => Some(ctx),
+            _ => None,
+        })
+    }
+
     pub fn done(&self) {
         self.with_inner(|inner| {
             inner.state = State::Idle;
+            inner.host.wake();
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::future::Future;
+    use core::sync::atomic::{AtomicBool, Ordering};
+    use core::task::Waker;
+    use std::sync::Arc;
+
+    use super::*;
+
+    struct Flag(AtomicBool);
+    impl std::task::Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn request_unless_other_active() {
+        let scan: CommandState<bool> = CommandState::new();
+        let connect: CommandState<bool> = CommandState::new();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut request = core::pin::pin!(scan.request_unless(false, &connect));
+        assert!(request.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(scan.active(), Some(false));
+
+        // The other command is rejected while this one is active
+        let mut request = core::pin::pin!(connect.request_unless(true, &scan));
+        assert_eq!(request.as_mut().poll(&mut cx), Poll::Ready(false));
+        assert!(connect.active().is_none());
+    }
+
+    #[test]
+    fn request_unless_waits_for_cancel() {
+        let scan: CommandState<bool> = CommandState::new();
+        let connect: CommandState<bool> = CommandState::new();
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        scan.cancel(false);
+        let mut request = core::pin::pin!(connect.request_unless(true, &scan));
+        assert!(request.as_mut().poll(&mut cx).is_pending());
+
+        // Waker registered before the state was checked, so finishing the cancel wakes the request
+        scan.canceled();
+        assert!(flag.0.load(Ordering::Relaxed));
+        assert_eq!(request.as_mut().poll(&mut cx), Poll::Ready(true));
+        assert_eq!(connect.active(), Some(true));
+    }
+
+    #[test]
+    fn request_unless_waits_for_own_command() {
+        let scan: CommandState<bool> = CommandState::new();
+        let connect: CommandState<bool> = CommandState::new();
+        let noop = futures::task::noop_waker();
+        let mut noop_cx = Context::from_waker(&noop);
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        let waker = Waker::from(flag.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut first = core::pin::pin!(connect.request_unless(true, &scan));
+        assert!(first.as_mut().poll(&mut noop_cx).is_ready());
+        let mut second = core::pin::pin!(connect.request_unless(true, &scan));
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        // A scan cannot start while the second connect is queued
+        let mut request = core::pin::pin!(scan.request_unless(false, &connect));
+        assert_eq!(request.as_mut().poll(&mut cx), Poll::Ready(false));
+
+        flag.0.store(false, Ordering::Relaxed);
+        connect.done();
+        assert!(flag.0.load(Ordering::Relaxed));
+        assert_eq!(second.as_mut().poll(&mut cx), Poll::Ready(true));
+    }
+}
@@ -71,3 +71,19 @@ pub const GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS: usize = raw::GATT_CLIENT_NOT
 ///
 /// Default: 1.
 pub const GATT_CLIENT_NOTIFICATION_QUEUE_SIZE: usize = raw::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
+
+/// GATT write queue size.
+///
+/// Number of bytes of write commands that can be queued per connection for a characteristic
+/// registered with `GattConnection::queue_writes`. A size of 0 disables write queueing.
+///
+/// Default: 0.
+pub const GATT_WRITE_QUEUE_SIZE: usize = raw::GATT_WRITE_QUEUE_SIZE;
+
+/// GATT prepare queue size.
+///
+/// Number of bytes of prepared writes the attribute server holds per connection until they are executed,
+/// bounding the length of long writes. A size of 0 rejects prepared writes.
+///
+/// Default: 512.
+pub const GATT_PREPARE_QUEUE_SIZE: usize = raw::GATT_PREPARE_QUEUE_SIZE;
@@ -1,15 +1,20 @@
 //! BLE connection.
 
+use core::future::{poll_fn, Future};
+
 use bt_hci::cmd::le::{LeConnUpdate, LeReadLocalSupportedFeatures, LeReadPhy, LeSetDataLength, LeSetPhy};
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::controller::{ControllerCmdAsync, ControllerCmdSync};
 use bt_hci::param::{
-    AddrKind, AllPhys, BdAddr, ConnHandle, DisconnectReason, LeConnRole, PhyKind, PhyMask, PhyOptions, Status,
+    AddrKind, AdvHandle, AllPhys, BdAddr, ConnHandle, DisconnectReason, LeConnRole, PhyKind, PhyMask, PhyOptions,
+    Status,
 };
+use embassy_futures::select::{select, Either};
 #[cfg(feature = "gatt")]
 use embassy_sync::blocking_mutex::raw::RawMutex;
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant};
 
+use crate::coex::CoexActivity;
 use crate::connection_manager::ConnectionManager;
 #[cfg(feature = "connection-metrics")]
 pub use crate::connection_manager::Metrics as ConnectionMetrics;
@@ -17,9 +22,11 @@ use crate::pdu::Pdu;
 #[cfg(feature = "gatt")]
 use crate::prelude::{AttributeServer, GattConnection};
 #[cfg(feature = "security")]
-use crate::security_manager::BondInformation;
+use crate::security_manager::{BondInformation, Reason, SecurityMode1Level};
 use crate::types::l2cap::ConnParamUpdateReq;
-use crate::{BleHostError, Error, Identity, PacketPool, Stack};
+use crate::{
+    Address, BleHostError, ControllerWithDle, ControllerWithPhy, Error, Identity, LocalIdentity, PacketPool, Stack,
+};
 
 /// Connection configuration.
 pub struct ConnectConfig<'d> {
@@ -30,10 +37,17 @@ pub struct ConnectConfig<'d> {
 }
 
 /// Scan/connect configuration.
+///
+/// Fields may be added in new releases, so build it from [`ScanConfig::default`] with struct update syntax
+/// (`ScanConfig { active: false, ..Default::default() }`) or the `with_*` methods rather than listing every field.
+/// Literals listing every field no longer compile since `coded` was added.
 pub struct ScanConfig<'d> {
     /// Active scanning.
     pub active: bool,
     /// List of addresses to accept.
+    ///
+    /// The address kind of an advertising report can be used as is, or use [`Address::filter_entry`] with
+    /// the address of the report.
     pub filter_accept_list: &'d [(AddrKind, &'d BdAddr)],
     /// PHYs to scan on.
     pub phys: PhySet,
@@ -41,10 +55,24 @@ pub struct ScanConfig<'d> {
     pub interval: Duration,
     /// Scan window.
     pub window: Duration,
+    /// Scan interval and window on the Coded PHY, if different from `interval` and `window`.
+    ///
+    /// Only used by extended scanning, which scans all PHYs in `phys` simultaneously.
+    pub coded: Option<ScanTiming>,
     /// Scan timeout.
     pub timeout: Duration,
 }
 
+/// Scan interval and window.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub struct ScanTiming {
+    /// Scan interval.
+    pub interval: Duration,
+    /// Scan window.
+    pub window: Duration,
+}
+
 impl Default for ScanConfig<'_> {
     fn default() -> Self {
         Self {
@@ -53,11 +81,20 @@ impl Default for ScanConfig<'_> {
             phys: PhySet::M1,
             interval: Duration::from_secs(1),
             window: Duration::from_secs(1),
+            coded: None,
             timeout: Duration::from_secs(0),
         }
     }
 }
 
+impl ScanConfig<'_> {
+    /// Scan the Coded PHY with its own interval and window, see [`coded`](Self::coded).
+    pub fn with_coded(mut self, interval: Duration, window: Duration) -> Self {
+        self.coded = Some(ScanTiming { interval, window });
+        self
+    }
+}
+
 /// PHYs to scan on.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Eq, PartialEq, Copy, Clone)]
@@ -95,6 +132,80 @@ pub struct ConnectParams {
     pub supervision_timeout: Duration,
 }
 
+/// High level quality of service preference for a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum QosProfile {
+    /// Short connection interval without peripheral latency, for interactive use.
+    LowLatency,
+    /// Fast PHY and large packets, for bulk transfers.
+    Throughput,
+    /// Long connection interval with peripheral latency, for battery powered devices.
+    LowPower,
+}
+
+impl QosProfile {
+    /// Connection parameters for this profile.
+    pub fn connect_params(&self) -> ConnectParams {
+        let (min_interval, max_interval, max_latency, supervision_timeout) = match self {
+            QosProfile::LowLatency => (7_500, 15_000, 0, 2_000),
+            QosProfile::Throughput => (15_000, 30_000, 0, 4_000),
+            QosProfile::LowPower => (100_000, 200_000, 4, 6_000),
+        };
+        ConnectParams {
+            min_connection_interval: Duration::from_micros(min_interval),
+            max_connection_interval: Duration::from_micros(max_interval),
+            max_latency,
+            supervision_timeout: Duration::from_millis(supervision_timeout),
+            ..Default::default()
+        }
+    }
+
+    /// Preferred PHY for this profile.
+    pub fn phy(&self) -> PhyKind {
+        match self {
+            QosProfile::LowLatency | QosProfile::Throughput => PhyKind::Le2M,
+            QosProfile::LowPower => PhyKind::Le1M,
+        }
+    }
+
+    /// Preferred data length (max TX octets and time in microseconds) for this profile, if any.
+    pub fn data_length(&self) -> Option<(u16, u16)> {
+        match self {
+            QosProfile::Throughput => Some((251, 2120)),
+            _ => None,
+        }
+    }
+}
+
+/// Current timing and PHY of a connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionParams {
+    /// Connection interval.
+    pub conn_interval: Duration,
+    /// Peripheral latency.
+    pub peripheral_latency: u16,
+    /// Supervision timeout.
+    pub supervision_timeout: Duration,
+    /// The TX phy.
+    pub tx_phy: PhyKind,
+    /// The RX phy.
+    pub rx_phy: PhyKind,
+}
+
+impl ConnectionParams {
+    pub(crate) const fn new() -> Self {
+        Self {
+            conn_interval: Duration::from_ticks(0),
+            peripheral_latency: 0,
+            supervision_timeout: Duration::from_ticks(0),
+            tx_phy: PhyKind::Le1M,
+            rx_phy: PhyKind::Le1M,
+        }
+    }
+}
+
 /// A connection event.
 #[derive(Debug)]
 pub enum ConnectionEvent {
@@ -136,6 +247,24 @@ pub enum ConnectionEvent {
         /// Bond info for this connection
         bond_info: BondInformation,
     },
+    #[cfg(feature = "security")]
+    /// The encryption state of this connection changed.
+    SecurityChanged {
+        /// The link is encrypted.
+        encrypted: bool,
+    },
+    #[cfg(feature = "security")]
+    /// Pairing failed on this connection.
+    ///
+    /// Pairing can be retried on the same connection, for example with a lower security level.
+    PairingFailed {
+        /// The SMP failure reason.
+        reason: Reason,
+    },
+    /// An ATT transaction on this connection timed out.
+    ///
+    /// No further ATT PDUs are sent or received on the connection. Disconnect and reconnect to use GATT again.
+    AttTransactionTimeout,
 }
 
 impl Default for ConnectParams {
@@ -193,6 +322,39 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.try_send(self.index, pdu)
     }
 
+    pub(crate) fn att_timed_out(&self) -> bool {
+        self.manager.att_timed_out(self.index)
+    }
+
+    pub(crate) fn set_att_timed_out(&self) {
+        self.manager.set_att_timed_out(self.index)
+    }
+
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn set_att_request(&self, opcode: Option<u8>) {
+        self.manager.set_att_request(self.index, opcode)
+    }
+
+    pub(crate) fn indication_sent(&self, deadline: Instant) {
+        self.manager.indication_sent(self.index, deadline)
+    }
+
+    pub(crate) fn indication_deadline(&self) -> Option<Instant> {
+        self.manager.indication_deadline(self.index)
+    }
+
+    pub(crate) async fn send_priority(&self, pdu: Pdu<P::Packet>) {
+        self.manager.send_priority(self.index, pdu).await
+    }
+
+    pub(crate) fn try_send_priority(&self, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        self.manager.try_send_priority(self.index, pdu)
+    }
+
+    pub(crate) async fn send_with_deadline(&self, pdu: Pdu<P::Packet>, deadline: Instant) {
+        self.manager.send_with_deadline(self.index, pdu, deadline).await
+    }
+
     pub(crate) async fn post_event(&self, event: ConnectionEvent) {
         self.manager.post_event(self.index, event).await
     }
@@ -202,11 +364,49 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.next(self.index).await
     }
 
+    /// Run `tasks` until they complete or the connection is disconnected.
+    ///
+    /// The tasks are dropped as soon as the disconnection is observed, so none of them is left
+    /// behind with partially sent state. Several tasks can be combined with `embassy_futures::join`.
+    /// Returns the disconnect reason if the connection was lost before the tasks completed.
+    pub async fn scope<F: Future>(&self, tasks: F) -> Result<F::Output, Status> {
+        let disconnected = poll_fn(|cx| self.manager.poll_disconnected(self.index, cx));
+        match select(tasks, disconnected).await {
+            Either::First(output) => Ok(output),
+            Either::Second(reason) => Err(reason),
+        }
+    }
+
     #[cfg(feature = "gatt")]
     pub(crate) async fn next_gatt(&self) -> Pdu<P::Packet> {
         self.manager.next_gatt(self.index).await
     }
 
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queue_writes(&self, handle: u16, policy: crate::write_queue::OverflowPolicy) -> Result<(), Error> {
+        self.manager.queue_writes(self.index, handle, policy)
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queues_writes(&self, handle: u16) -> bool {
+        self.manager.queues_writes(self.index, handle)
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queue_write(&self, handle: u16, data: &[u8]) -> bool {
+        self.manager.queue_write(self.index, handle, data)
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) async fn read_queued(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.manager.read_queued(self.index, buf).await
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queued_dropped(&self) -> usize {
+        self.manager.queued_dropped(self.index)
+    }
+
     /// Check if still connected
     pub fn is_connected(&self) -> bool {
         self.manager.is_connected(self.index)
@@ -232,16 +432,108 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         self.manager.peer_address(self.index)
     }
 
+    /// The peer address and address kind for this connection.
+    ///
+    /// When the controller resolved the peer address, this is the identity address of the peer. Useful to find out
+    /// which peer was connected when connecting with a filter accept list of several devices.
+    pub fn peer(&self) -> Address {
+        Address {
+            kind: self.manager.peer_addr_kind(self.index),
+            addr: self.peer_address(),
+        }
+    }
+
     /// The peer identity key for this connection.
     pub fn peer_identity(&self) -> Identity {
         self.manager.peer_identity(self.index)
     }
 
+    /// The advertising set this connection was accepted from.
+    ///
+    /// Only known for connections accepted from extended advertising, where it can be used to pick the
+    /// attribute server for the connection when several advertising sets are active.
+    pub fn adv_handle(&self) -> Option<AdvHandle> {
+        self.manager.adv_handle(self.index)
+    }
+
+    /// The local identity the peer connected to.
+    ///
+    /// Only known for connections accepted from an extended advertising set that selected an identity with
+    /// [`AdvertisementParameters::identity`](crate::advertise::AdvertisementParameters::identity), otherwise
+    /// the connection uses the host address.
+    pub fn local_identity(&self) -> Option<LocalIdentity> {
+        self.manager.local_identity(self.index)
+    }
+
+    /// The current connection interval, latency, supervision timeout and PHY.
+    ///
+    /// Kept up to date from connection update and PHY update events of the controller.
+    pub fn params(&self) -> ConnectionParams {
+        self.manager.params(self.index)
+    }
+
+    /// The connection interval, latency and supervision timeout the connection was established with.
+    ///
+    /// Unlike [`Connection::params`], these are not changed by later connection updates.
+    pub fn initial_params(&self) -> ConnectionParams {
+        self.manager.initial_params(self.index)
+    }
+
+    /// The estimated time of the next connection event.
+    ///
+    /// Only available once the anchor point of a connection event has been reported with
+    /// [`Stack::set_connection_anchor`], which requires a controller specific source such as a vendor event.
+    /// Following connection events are predicted from the connection interval, so the estimate is
+    /// discarded when the interval changes.
+    pub fn next_event_anchor(&self) -> Option<Instant> {
+        self.manager.next_anchor(self.index, Instant::now())
+    }
+
     /// Get the encrypted state of the connection
     pub fn encrypted(&self) -> bool {
         self.manager.get_encrypted(self.index)
     }
 
+    /// Get the encryption key size of the connection in octets, if encrypted
+    #[cfg(feature = "security")]
+    pub fn encryption_key_size(&self) -> Option<u8> {
+        self.manager.get_encryption_key_size(self.index)
+    }
+
+    /// Whether the connection is encrypted with a key meeting the minimum key size of the security policy
+    #[cfg(feature = "security")]
+    pub(crate) fn encryption_meets_policy(&self) -> bool {
+        self.encryption_key_size()
+            .is_some_and(|size| size >= self.manager.security_manager.min_encryption_key_size())
+    }
+
+    /// Ask the central to secure this connection with at least the given security level.
+    ///
+    /// Sends an SMP Security Request. A bonded central starts encryption with the stored key,
+    /// otherwise it starts pairing. The outcome is reported as a [`ConnectionEvent::SecurityChanged`]
+    /// event. Only valid for connections in the peripheral role.
+    #[cfg(feature = "security")]
+    pub fn request_security(&self, level: SecurityMode1Level) -> Result<(), Error> {
+        if level == SecurityMode1Level::Level1 {
+            return Ok(());
+        }
+        self.manager.security_manager.request_security(self, level)
+    }
+
+    /// Verify the signature of a signed write from the bonded peer.
+    #[cfg(feature = "security")]
+    pub(crate) fn verify_signature(&self, parts: &[&[u8]], signature: &[u8; 12]) -> bool {
+        self.manager
+            .security_manager
+            .verify_signature(&self.peer_identity(), parts, signature)
+    }
+
+    /// Sign a write to the bonded peer.
+    #[cfg(feature = "security")]
+    pub(crate) fn sign(&self, parts: &[&[u8]]) -> Result<[u8; 12], Error> {
+        self.manager.security_manager.sign(&self.peer_identity(), parts)
+    }
+
     /// Request connection to be disconnected.
     pub fn disconnect(&self) {
         self.manager
@@ -268,10 +560,21 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
     ///
     /// This updates both TX and RX phy of the connection. For more fine grained control,
     /// use the LeSetPhy HCI command directly.
+    ///
+    /// Returns `Error::NotSupported` if the controller does not support the phy.
     pub async fn set_phy<T>(&self, stack: &Stack<'_, T, P>, phy: PhyKind) -> Result<(), BleHostError<T::Error>>
     where
-        T: ControllerCmdAsync<LeSetPhy>,
+        T: ControllerWithPhy,
     {
+        let features = stack.host.command(LeReadLocalSupportedFeatures::new()).await?;
+        let supported = match phy {
+            PhyKind::Le1M => true,
+            PhyKind::Le2M => features.supports_le_2m_phy(),
+            PhyKind::LeCoded | PhyKind::LeCodedS2 => features.supports_le_coded_phy(),
+        };
+        if !supported {
+            return Err(Error::NotSupported.into());
+        }
         let all_phys = AllPhys::new()
             .set_has_no_rx_phy_preference(false)
             .set_has_no_tx_phy_preference(false);
@@ -313,6 +616,10 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
     }
 
     /// Update data length for this connection.
+    ///
+    /// The length and time are limited to the maximum supported by the controller. Returns
+    /// `Error::NotSupported` if `length` exceeds 27 octets and the controller does not support data
+    /// length extension.
     pub async fn update_data_length<T>(
         &self,
         stack: &Stack<'_, T, P>,
@@ -320,12 +627,15 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         time_us: u16,
     ) -> Result<(), BleHostError<T::Error>>
     where
-        T: ControllerCmdSync<LeSetDataLength> + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+        T: ControllerWithDle,
     {
         let handle = self.handle();
         // First, check the local supported features to ensure that the connection update is supported.
         let features = stack.host.command(LeReadLocalSupportedFeatures::new()).await?;
         if length <= 27 || features.supports_le_data_packet_length_extension() {
+            let max = stack.max_data_length().await?;
+            let length = length.min(max.supported_max_tx_octets);
+            let time_us = time_us.min(max.supported_max_tx_time);
             match stack.host.command(LeSetDataLength::new(handle, length, time_us)).await {
                 Ok(_) => Ok(()),
                 Err(BleHostError::BleHost(crate::Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {
@@ -334,8 +644,30 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
                 Err(e) => Err(e),
             }
         } else {
-            Err(BleHostError::BleHost(Error::InvalidValue))
+            Err(BleHostError::BleHost(Error::NotSupported))
+        }
+    }
+
+    /// Tune the connection for a quality of service profile.
+    ///
+    /// Updates the connection parameters, and the PHY and data length where supported by the controller.
+    /// The peer may reject or adjust the requested parameters, check [`Connection::params`] or wait for the
+    /// connection events to see the result.
+    pub async fn set_qos<T>(&self, stack: &Stack<'_, T, P>, profile: QosProfile) -> Result<(), BleHostError<T::Error>>
+    where
+        T: ControllerWithPhy + ControllerWithDle,
+    {
+        let features = stack.host.command(LeReadLocalSupportedFeatures::new()).await?;
+        let phy = profile.phy();
+        if phy != PhyKind::Le2M || features.supports_le_2m_phy() {
+            self.set_phy(stack, phy).await?;
         }
+        if let Some((length, time_us)) = profile.data_length() {
+            if features.supports_le_data_packet_length_extension() {
+                self.update_data_length(stack, length, time_us).await?;
+            }
+        }
+        self.update_connection_params(stack, &profile.connect_params()).await
     }
 
     /// Update connection parameters for this connection.
@@ -351,6 +683,11 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
         // First, check the local supported features to ensure that the connection update is supported.
         let features = stack.host.command(LeReadLocalSupportedFeatures::new()).await?;
         if features.supports_conn_parameters_request_procedure() || self.role() == LeConnRole::Central {
+            let (min_event_length, max_event_length) = stack
+                .host
+                .coex
+                .event_length(params.min_event_length, params.max_event_length);
+            stack.host.coex.notify(CoexActivity::ConnectionUpdate(handle));
             match stack
                 .host
                 .async_command(LeConnUpdate::new(
@@ -359,8 +696,8 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
                     params.max_connection_interval.into(),
                     params.max_latency,
                     params.supervision_timeout.into(),
-                    params.min_event_length.into(),
-                    params.max_event_length.into(),
+                    min_event_length.into(),
+                    max_event_length.into(),
                 ))
                 .await
             {
@@ -389,6 +726,11 @@ impl<'stack, P: PacketPool> Connection<'stack, P> {
     }
 
     /// Transform BLE connection into a `GattConnection`
+    ///
+    /// Attaching an attribute server is optional, and each connection may use a different server, for
+    /// example chosen by [`Connection::adv_handle`] or [`Connection::peer_identity`]. ATT requests on a
+    /// connection without an attribute server are not answered. Use [`GattConnection::detach`] to move
+    /// the connection to a different server.
     #[cfg(feature = "gatt")]
     pub fn with_attribute_server<
         'values,
@@ -4,30 +4,71 @@ use core::future::poll_fn;
 use core::future::Future;
 use core::task::{Context, Poll};
 
-use bt_hci::param::{AddrKind, BdAddr, ConnHandle, DisconnectReason, LeConnRole, Status};
+use bt_hci::param::{AddrKind, AdvHandle, BdAddr, ConnHandle, DisconnectReason, LeConnRole, Status};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::waitqueue::WakerRegistration;
 #[cfg(feature = "security")]
 use embassy_time::TimeoutError;
+use embassy_time::{Duration, Instant};
 
-use crate::connection::{Connection, ConnectionEvent};
+#[cfg(feature = "security")]
+use crate::coex::CoexActivity;
+use crate::connection::{Connection, ConnectionEvent, ConnectionParams};
 use crate::pdu::Pdu;
 use crate::prelude::sar::PacketReassembly;
 #[cfg(feature = "security")]
 use crate::security_manager::{SecurityEventData, SecurityManager};
-use crate::{config, Error, Identity, PacketPool};
+use crate::tx_scheduler::{TxEntry, TxPriority, TxScheduler};
+use crate::types::l2cap::L2CAP_CID_ATT;
+#[cfg(feature = "gatt")]
+use crate::write_queue::{OverflowPolicy, WriteQueue};
+use crate::{att, config, Error, Identity, LocalIdentity, PacketPool};
 
 struct State<'d, P> {
     connections: &'d mut [ConnectionStorage<P>],
     central_waker: WakerRegistration,
     peripheral_waker: WakerRegistration,
     disconnect_waker: WakerRegistration,
-    default_link_credits: usize,
+    idle_waker: WakerRegistration,
+    // Controller ACL buffers not in use by any connection.
+    controller_credits: usize,
+    // Number of ACL buffers of the controller.
+    link_credits: usize,
+    // Maximum number of controller ACL buffers in use by a single connection, if set.
+    tx_window: Option<usize>,
     default_att_mtu: u16,
+    high_water: usize,
+    // Advertising set terminated before the connection it created was reported.
+    early_adv_set: Option<(ConnHandle, AdvHandle, Option<LocalIdentity>)>,
 }
 
 impl<P> State<'_, P> {
+    /// Maximum number of controller ACL buffers in use by a single connection, by default an equal share
+    /// between the connected peers.
+    fn tx_window(&self) -> usize {
+        self.tx_window.unwrap_or_else(|| {
+            let connected = self
+                .connections
+                .iter()
+                .filter(|c| c.state == ConnectionState::Connected)
+                .count();
+            self.link_credits.div_ceil(connected.max(1)).max(1)
+        })
+    }
+
+    /// Return controller buffers to the shared pool, waking connections waiting for them.
+    fn release_credits(&mut self, packets: usize) {
+        if packets == 0 {
+            return;
+        }
+        self.controller_credits += packets;
+        for storage in self.connections.iter_mut() {
+            storage.link_credit_waker.wake();
+            storage.priority_credit_waker.wake();
+        }
+    }
+
     fn print(&self, verbose: bool) {
         for (idx, storage) in self.connections.iter().enumerate() {
             if verbose || storage.state != ConnectionState::Disconnected {
@@ -50,7 +91,7 @@ type GattChannel<P> = Channel<NoopRawMutex, Pdu<P>, { config::L2CAP_RX_QUEUE_SIZ
 
 pub(crate) struct ConnectionManager<'d, P: PacketPool> {
     state: RefCell<State<'d, P::Packet>>,
-    outbound: Channel<NoopRawMutex, (ConnHandle, Pdu<P::Packet>), { config::L2CAP_TX_QUEUE_SIZE }>,
+    outbound: TxScheduler<Pdu<P::Packet>>,
     #[cfg(feature = "security")]
     pub(crate) security_manager: SecurityManager<{ crate::BI_COUNT }>,
 }
@@ -63,10 +104,15 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 central_waker: WakerRegistration::new(),
                 peripheral_waker: WakerRegistration::new(),
                 disconnect_waker: WakerRegistration::new(),
-                default_link_credits: 0,
+                idle_waker: WakerRegistration::new(),
+                controller_credits: 0,
+                link_credits: 0,
+                tx_window: None,
                 default_att_mtu,
+                high_water: 0,
+                early_adv_set: None,
             }),
-            outbound: Channel::new(),
+            outbound: TxScheduler::new(),
             #[cfg(feature = "security")]
             security_manager: SecurityManager::new(),
         }
@@ -93,6 +139,19 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    pub(crate) fn poll_disconnected(&self, index: u8, cx: &mut Context<'_>) -> Poll<Status> {
+        self.with_mut(|state| {
+            let storage = &mut state.connections[index as usize];
+            match storage.disconnect_reason {
+                Some(reason) => Poll::Ready(reason),
+                None => {
+                    storage.disconnect_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+    }
+
     pub(crate) async fn next(&self, index: u8) -> ConnectionEvent {
         poll_fn(|cx| self.with_mut(|state| state.connections[index as usize].events.poll_receive(cx))).await
     }
@@ -132,6 +191,48 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queue_writes(&self, index: u8, handle: u16, policy: OverflowPolicy) -> Result<(), Error> {
+        if config::GATT_WRITE_QUEUE_SIZE == 0 {
+            return Err(Error::InsufficientSpace);
+        }
+        self.with_mut(|state| state.connections[index as usize].write_queue.register(handle, policy));
+        Ok(())
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queues_writes(&self, index: u8, attribute: u16) -> bool {
+        self.with_mut(|state| state.connections[index as usize].write_queue.queues(attribute))
+    }
+
+    /// Queue a write command if writes to the attribute are queued, returns `false` otherwise.
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queue_write(&self, index: u8, attribute: u16, data: &[u8]) -> bool {
+        self.with_mut(|state| state.connections[index as usize].write_queue.push(attribute, data))
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) async fn read_queued(&self, index: u8, buf: &mut [u8]) -> Result<usize, Error> {
+        poll_fn(|cx| {
+            self.with_mut(|state| {
+                let storage = &mut state.connections[index as usize];
+                match storage.write_queue.poll_read(Some(cx), buf) {
+                    Poll::Ready(len) => Poll::Ready(Ok(len)),
+                    Poll::Pending if storage.state != ConnectionState::Connected => {
+                        Poll::Ready(Err(Error::Disconnected))
+                    }
+                    Poll::Pending => Poll::Pending,
+                }
+            })
+        })
+        .await
+    }
+
+    #[cfg(feature = "gatt")]
+    pub(crate) fn queued_dropped(&self, index: u8) -> usize {
+        self.with_mut(|state| state.connections[index as usize].write_queue.dropped())
+    }
+
     pub(crate) fn peer_address(&self, index: u8) -> BdAddr {
         self.with_mut(|state| {
             let state = &mut state.connections[index as usize];
@@ -142,6 +243,13 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    pub(crate) fn peer_addr_kind(&self, index: u8) -> AddrKind {
+        self.with_mut(|state| {
+            let state = &mut state.connections[index as usize];
+            state.peer_addr_kind.unwrap_or(AddrKind::PUBLIC)
+        })
+    }
+
     pub(crate) fn peer_identity(&self, index: u8) -> Identity {
         self.with_mut(|state| {
             let state = &mut state.connections[index as usize];
@@ -149,6 +257,29 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    pub(crate) fn adv_handle(&self, index: u8) -> Option<AdvHandle> {
+        self.with_mut(|state| state.connections[index as usize].adv_handle)
+    }
+
+    pub(crate) fn local_identity(&self, index: u8) -> Option<LocalIdentity> {
+        self.with_mut(|state| state.connections[index as usize].local_identity)
+    }
+
+    /// Record the advertising set a connection was created from, and the local identity it used.
+    ///
+    /// Controllers may report the advertising set before the connection, in which case it is kept until the
+    /// connection is created.
+    pub(crate) fn set_adv_handle(&self, handle: ConnHandle, adv_handle: AdvHandle, identity: Option<LocalIdentity>) {
+        let recorded = self.with_connected_handle(handle, |storage| {
+            storage.adv_handle = Some(adv_handle);
+            storage.local_identity = identity;
+            Ok(())
+        });
+        if recorded.is_err() {
+            self.state.borrow_mut().early_adv_set = Some((handle, adv_handle, identity));
+        }
+    }
+
     pub(crate) fn set_att_mtu(&self, index: u8, mtu: u16) {
         self.with_mut(|state| {
             state.connections[index as usize].att_mtu = mtu;
@@ -177,6 +308,44 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         })
     }
 
+    /// Check if any link is open or being opened or closed.
+    pub(crate) fn has_links(&self) -> bool {
+        self.with_mut(|state| {
+            state
+                .connections
+                .iter()
+                .any(|c| c.state != ConnectionState::Disconnected)
+        })
+    }
+
+    /// Request disconnecting all open links.
+    pub(crate) fn request_disconnect_all(&self, reason: DisconnectReason) {
+        self.with_mut(|state| {
+            for entry in state.connections.iter_mut() {
+                if entry.state == ConnectionState::Connected {
+                    entry.state = ConnectionState::DisconnectRequest(reason);
+                }
+            }
+            state.disconnect_waker.wake();
+        })
+    }
+
+    /// Poll until all links are disconnected.
+    pub(crate) fn poll_all_disconnected(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.with_mut(|state| {
+            state.idle_waker.register(cx.waker());
+            if state
+                .connections
+                .iter()
+                .all(|c| c.state == ConnectionState::Disconnected)
+            {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+    }
+
     pub(crate) fn poll_disconnecting<'m>(
         &'m self,
         cx: Option<&mut Context<'_>>,
@@ -258,20 +427,30 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
             if Some(h) == storage.handle && storage.state != ConnectionState::Disconnected {
                 storage.state = ConnectionState::Disconnected;
                 storage.reassembly.clear();
+                // The controller flushes the packets of the connection
+                let released = core::mem::take(&mut storage.tx_in_flight);
                 let _ = storage.events.try_send(ConnectionEvent::Disconnected { reason });
+                storage.disconnect_reason = Some(reason);
+                storage.disconnect_waker.wake();
                 #[cfg(feature = "gatt")]
-                storage.gatt.clear();
+                {
+                    storage.gatt.clear();
+                    storage.write_queue.wake();
+                }
                 #[cfg(feature = "connection-metrics")]
                 storage.metrics.reset();
                 #[cfg(feature = "security")]
                 {
                     storage.encrypted = false;
+                    storage.key_size = 0;
                     let _ = self.security_manager.disconnect(h);
                 }
+                state.idle_waker.wake();
+                state.release_credits(released);
                 return Ok(());
             }
         }
-        warn!("[link][disconnect] connection handle {:?} not found", h);
+        warn!(conn = h, "[link][disconnect] connection handle not found");
         Err(Error::NotFound)
     }
 
@@ -282,17 +461,39 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         peer_addr: BdAddr,
         role: LeConnRole,
     ) -> Result<(), Error> {
+        let peer_addr_kind = crate::identity_addr_kind(peer_addr_kind);
         let mut state = self.state.borrow_mut();
-        let default_credits = state.default_link_credits;
         let default_att_mtu = state.default_att_mtu;
+        let early_adv_set = state.early_adv_set.take_if(|(h, _, _)| *h == handle);
         for (idx, storage) in state.connections.iter_mut().enumerate() {
             if ConnectionState::Disconnected == storage.state && storage.refcount == 0 {
                 storage.events.clear();
                 storage.reassembly.clear();
+                #[cfg(feature = "gatt")]
+                storage.write_queue.reset();
                 storage.state = ConnectionState::Connecting;
-                storage.link_credits = default_credits;
+                storage.tx_in_flight = 0;
+                storage.priority_waiting = false;
+                storage.disconnect_reason = None;
                 // Default ATT MTU is 23
                 storage.att_mtu = 23;
+                storage.params = ConnectionParams::new();
+                storage.initial_params = ConnectionParams::new();
+                storage.anchor = None;
+                storage.att_timed_out = false;
+                storage.indication_deadline = None;
+                storage.att_peer_request = false;
+                storage.att_local_request = false;
+                #[cfg(feature = "debug-state")]
+                {
+                    storage.att_request = None;
+                }
+                storage.adv_handle = None;
+                storage.local_identity = None;
+                if let Some((_, adv_handle, identity)) = early_adv_set {
+                    storage.adv_handle = Some(adv_handle);
+                    storage.local_identity = identity;
+                }
                 storage.handle.replace(handle);
                 storage.peer_addr_kind.replace(peer_addr_kind);
                 storage.peer_identity.replace(Identity {
@@ -302,6 +503,13 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 });
                 storage.role.replace(role);
 
+                let in_use = state
+                    .connections
+                    .iter()
+                    .filter(|c| c.state != ConnectionState::Disconnected)
+                    .count();
+                state.high_water = state.high_water.max(in_use);
+
                 match role {
                     LeConnRole::Central => {
                         state.central_waker.wake();
@@ -313,7 +521,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 return Ok(());
             }
         }
-        warn!("[link][connect] no available slot found for handle {:?}", handle);
+        warn!(conn = handle, "[link][connect] no available slot found");
         Err(Error::NotFound)
     }
 
@@ -342,7 +550,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                     if !peers.is_empty() {
                         for peer in peers.iter() {
                             // TODO: Accept advertsing peers which use IRK
-                            if storage.peer_addr_kind.unwrap() == peer.0
+                            if storage.peer_addr_kind.unwrap() == crate::identity_addr_kind(peer.0)
                                 && storage.peer_identity.unwrap().bd_addr == *peer.1
                             {
                                 storage.state = ConnectionState::Connected;
@@ -372,11 +580,55 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         f(&mut state)
     }
 
+    /// Number of connection slots in use, the highest number in use so far, and the total number of slots.
+    pub(crate) fn usage(&self) -> (usize, usize, usize) {
+        let state = self.state.borrow();
+        let in_use = state
+            .connections
+            .iter()
+            .filter(|c| c.state != ConnectionState::Disconnected || c.refcount > 0)
+            .count();
+        (in_use, state.high_water, state.connections.len())
+    }
+
     pub(crate) fn log_status(&self, verbose: bool) {
         let state = self.state.borrow();
         state.print(verbose);
     }
 
+    /// Visit a snapshot of each link in use.
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn debug_state(&self, mut f: impl FnMut(crate::debug_state::ConnectionDebugState)) {
+        use crate::debug_state::{ConnectionDebugState, LinkState};
+        let state = self.state.borrow();
+        for storage in state.connections.iter() {
+            let (Some(handle), Some(role)) = (storage.handle, storage.role) else {
+                continue;
+            };
+            let link = match storage.state {
+                ConnectionState::Disconnected if storage.refcount == 0 => continue,
+                ConnectionState::Disconnected => LinkState::Disconnected,
+                ConnectionState::Connecting => LinkState::Connecting,
+                ConnectionState::Connected => LinkState::Connected,
+                ConnectionState::DisconnectRequest(reason) => LinkState::DisconnectRequested(reason),
+                ConnectionState::Disconnecting(reason) => LinkState::Disconnecting(reason),
+            };
+            f(ConnectionDebugState {
+                handle,
+                role,
+                state: link,
+                att_mtu: storage.att_mtu,
+                att_request: storage.att_request,
+                indication_pending: storage.indication_deadline.is_some(),
+                att_timed_out: storage.att_timed_out,
+                #[cfg(feature = "security")]
+                encrypted: storage.encrypted,
+                tx_in_flight: storage.tx_in_flight,
+                refcount: storage.refcount,
+            });
+        }
+    }
+
     pub(crate) fn inc_ref(&self, index: u8) {
         self.with_mut(|state| {
             state.inc_ref(index);
@@ -401,12 +653,20 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         poll_fn(|cx| self.poll_accept(role, peers, Some(cx))).await
     }
 
+    /// Set the number of ACL buffers of the controller, shared by all connections.
+    ///
+    /// Unless set explicitly, each connection may use an equal share of the buffers, so a connection with a slow
+    /// peer cannot stall the others.
     pub(crate) fn set_link_credits(&self, credits: usize) {
         let mut state = self.state.borrow_mut();
-        state.default_link_credits = credits;
-        for storage in state.connections.iter_mut() {
-            storage.link_credits = credits;
-        }
+        state.controller_credits = credits;
+        state.link_credits = credits;
+    }
+
+    /// Set the maximum number of ACL buffers of the controller used by a single connection.
+    pub(crate) fn set_tx_window(&self, packets: usize) {
+        let mut state = self.state.borrow_mut();
+        state.tx_window = Some(packets.max(1));
     }
 
     pub(crate) fn set_default_att_mtu(&self, att_mtu: u16) {
@@ -414,37 +674,63 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         state.default_att_mtu = att_mtu;
     }
 
-    pub(crate) fn confirm_sent(&self, handle: ConnHandle, packets: usize) -> Result<(), Error> {
+    /// Return the controller buffers of packets completed by the controller, as reported for each connection
+    /// by a Number Of Completed Packets event.
+    pub(crate) fn confirm_sent(&self, completed: impl Iterator<Item = (ConnHandle, usize)>) {
         let mut state = self.state.borrow_mut();
-        for storage in state.connections.iter_mut() {
-            match storage.state {
-                ConnectionState::Connected if handle == storage.handle.unwrap() => {
-                    storage.link_credits += packets;
-                    storage.link_credit_waker.wake();
-                    return Ok(());
+        let mut released = 0;
+        for (handle, packets) in completed {
+            match state
+                .connections
+                .iter_mut()
+                .find(|s| s.state == ConnectionState::Connected && s.handle == Some(handle))
+            {
+                Some(storage) => {
+                    let packets = packets.min(storage.tx_in_flight);
+                    storage.tx_in_flight -= packets;
+                    released += packets;
                 }
-                _ => {}
+                None => warn!(conn = handle, "[link] completed packets for unknown connection"),
             }
         }
-        Err(Error::NotFound)
+        state.release_credits(released);
     }
 
+    /// Request link credits for sending packets.
+    ///
+    /// While a priority request is waiting for credits, other requests are held back so that the
+    /// credits released next go to the priority request.
     pub(crate) fn poll_request_to_send(
         &self,
         handle: ConnHandle,
         packets: usize,
+        priority: bool,
         cx: Option<&mut Context<'_>>,
     ) -> Poll<Result<PacketGrant<'_, 'd, P::Packet>, Error>> {
         let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let tx_window = state.tx_window();
         for storage in state.connections.iter_mut() {
             match storage.state {
                 ConnectionState::Connected if storage.handle.unwrap() == handle => {
-                    if packets <= storage.link_credits {
-                        storage.link_credits -= packets;
+                    // A request larger than the window is granted once nothing else is in flight
+                    let in_window = storage.tx_in_flight == 0 || storage.tx_in_flight + packets <= tx_window;
+                    if packets <= state.controller_credits && in_window && (priority || !storage.priority_waiting) {
+                        state.controller_credits -= packets;
+                        storage.tx_in_flight += packets;
+                        if priority {
+                            storage.priority_waiting = false;
+                            storage.link_credit_waker.wake();
+                        }
 
                         return Poll::Ready(Ok(PacketGrant::new(&self.state, handle, packets)));
                     } else {
-                        if let Some(cx) = cx {
+                        if priority {
+                            storage.priority_waiting = true;
+                            if let Some(cx) = cx {
+                                storage.priority_credit_waker.register(cx.waker());
+                            }
+                        } else if let Some(cx) = cx {
                             storage.link_credit_waker.register(cx.waker());
                         }
                         #[cfg(feature = "connection-metrics")]
@@ -456,7 +742,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 _ => {}
             }
         }
-        warn!("[link][pool_request_to_send] connection {:?} not found", handle);
+        warn!(conn = handle, "[link][pool_request_to_send] connection not found");
         Poll::Ready(Err(Error::NotFound))
     }
 
@@ -466,20 +752,57 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
 
     pub(crate) async fn send(&self, index: u8, pdu: Pdu<P::Packet>) {
         let handle = self.with_mut(|state| state.connections[index as usize].handle.unwrap());
-        self.outbound.send((handle, pdu)).await
+        self.outbound.push(handle, pdu, TxPriority::Normal, None).await
     }
 
     pub(crate) fn try_send(&self, index: u8, pdu: Pdu<P::Packet>) -> Result<(), Error> {
         let handle = self.with_mut(|state| state.connections[index as usize].handle.unwrap());
-        self.outbound.try_send((handle, pdu)).map_err(|_| Error::OutOfMemory)
+        self.outbound.try_push(handle, pdu, TxPriority::Normal, None)
+    }
+
+    pub(crate) async fn send_priority(&self, index: u8, pdu: Pdu<P::Packet>) {
+        let handle = self.with_mut(|state| state.connections[index as usize].handle.unwrap());
+        self.outbound.push(handle, pdu, TxPriority::High, None).await
+    }
+
+    pub(crate) fn try_send_priority(&self, index: u8, pdu: Pdu<P::Packet>) -> Result<(), Error> {
+        let handle = self.with_mut(|state| state.connections[index as usize].handle.unwrap());
+        self.outbound.try_push(handle, pdu, TxPriority::High, None)
+    }
+
+    /// Queue a PDU that must be sent by `deadline`, ahead of connections without a deadline or with a later one.
+    pub(crate) async fn send_with_deadline(&self, index: u8, pdu: Pdu<P::Packet>, deadline: Instant) {
+        let handle = self.with_mut(|state| state.connections[index as usize].handle.unwrap());
+        self.outbound
+            .push(handle, pdu, TxPriority::Normal, Some(deadline))
+            .await
     }
 
     pub(crate) fn try_outbound(&self, handle: ConnHandle, pdu: Pdu<P::Packet>) -> Result<(), Error> {
-        self.outbound.try_send((handle, pdu)).map_err(|_| Error::OutOfMemory)
+        self.outbound.try_push(handle, pdu, TxPriority::Normal, None)
+    }
+
+    /// Next outbound PDU. PDUs of a connection keep their order, connections with latency critical PDUs are
+    /// served first, then connections by earliest deadline.
+    pub(crate) async fn outbound(&self) -> TxEntry<Pdu<P::Packet>> {
+        let entry = self.outbound.pop().await;
+        self.att_sent(entry.handle, entry.item.as_ref());
+        entry
+    }
+
+    /// Number of PDUs waiting to be sent.
+    pub(crate) fn outbound_len(&self) -> usize {
+        self.outbound.len()
+    }
+
+    /// Number of ACL packets sent to the controller and not yet reported as completed.
+    pub(crate) fn packets_in_flight(&self) -> usize {
+        self.state.borrow().connections.iter().map(|c| c.tx_in_flight).sum()
     }
 
-    pub(crate) async fn outbound(&self) -> (ConnHandle, Pdu<P::Packet>) {
-        self.outbound.receive().await
+    /// Check if any PDU is waiting to be sent.
+    pub(crate) fn has_outbound(&self) -> bool {
+        !self.outbound.is_empty()
     }
 
     pub(crate) fn get_att_mtu_handle(&self, conn: ConnHandle) -> u16 {
@@ -511,6 +834,207 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         mtu
     }
 
+    pub(crate) fn params(&self, index: u8) -> ConnectionParams {
+        self.state.borrow().connections[index as usize].params
+    }
+
+    pub(crate) fn initial_params(&self, index: u8) -> ConnectionParams {
+        self.state.borrow().connections[index as usize].initial_params
+    }
+
+    /// Record the parameters reported when the connection was established.
+    pub(crate) fn set_initial_params(
+        &self,
+        handle: ConnHandle,
+        conn_interval: Duration,
+        peripheral_latency: u16,
+        supervision_timeout: Duration,
+    ) {
+        let _ = self.with_connected_handle(handle, |storage| {
+            storage.params.conn_interval = conn_interval;
+            storage.params.peripheral_latency = peripheral_latency;
+            storage.params.supervision_timeout = supervision_timeout;
+            storage.initial_params = storage.params;
+            Ok(())
+        });
+    }
+
+    pub(crate) fn update_params<F: FnOnce(&mut ConnectionParams)>(&self, handle: ConnHandle, f: F) {
+        let _ = self.with_connected_handle(handle, |storage| {
+            let interval = storage.params.conn_interval;
+            f(&mut storage.params);
+            // A known anchor point no longer predicts the connection events after an interval change
+            if storage.params.conn_interval != interval {
+                storage.anchor = None;
+            }
+            Ok(())
+        });
+    }
+
+    /// Check if an ATT transaction timed out on the connection, after which no further ATT PDUs may be exchanged.
+    pub(crate) fn att_timed_out(&self, index: u8) -> bool {
+        self.with_mut(|state| state.connections[index as usize].att_timed_out)
+    }
+
+    pub(crate) fn att_timed_out_handle(&self, handle: ConnHandle) -> bool {
+        self.with_connected_handle(handle, |storage| Ok(storage.att_timed_out))
+            .unwrap_or(false)
+    }
+
+    /// Record the opcode of the GATT client request waiting for a response, for debug snapshots.
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn set_att_request(&self, index: u8, opcode: Option<u8>) {
+        self.with_mut(|state| state.connections[index as usize].att_request = opcode)
+    }
+
+    /// Abandon the ATT bearer of the connection after a transaction timeout.
+    pub(crate) fn set_att_timed_out(&self, index: u8) {
+        self.with_mut(|state| {
+            let storage = &mut state.connections[index as usize];
+            if !storage.att_timed_out {
+                storage.att_timed_out = true;
+                storage.indication_deadline = None;
+                if storage.events.try_send(ConnectionEvent::AttTransactionTimeout).is_err() {
+                    warn!("[link] unable to post ATT transaction timeout event");
+                }
+            }
+        })
+    }
+
+    /// Start the transaction for an indication, which ends when the confirmation is received.
+    pub(crate) fn indication_sent(&self, index: u8, deadline: Instant) {
+        self.with_mut(|state| state.connections[index as usize].indication_deadline = Some(deadline))
+    }
+
+    pub(crate) fn indication_confirmed(&self, handle: ConnHandle) {
+        let _ = self.with_connected_handle(handle, |storage| {
+            storage.indication_deadline = None;
+            Ok(())
+        });
+    }
+
+    /// Record a request received from the peer, returning false if one is already waiting for its response.
+    pub(crate) fn att_request_received(&self, handle: ConnHandle) -> bool {
+        self.with_connected_handle(handle, |storage| {
+            Ok(!core::mem::replace(&mut storage.att_peer_request, true))
+        })
+        .unwrap_or(true)
+    }
+
+    /// Record a response received from the peer, returning false if no request is waiting for it.
+    pub(crate) fn att_response_received(&self, handle: ConnHandle) -> bool {
+        self.with_connected_handle(handle, |storage| {
+            Ok(core::mem::replace(&mut storage.att_local_request, false))
+        })
+        .unwrap_or(true)
+    }
+
+    // Track the ATT transactions opened and closed by an outbound PDU.
+    fn att_sent(&self, handle: ConnHandle, pdu: &[u8]) {
+        let [_, _, lo, hi, opcode, ..] = *pdu else {
+            return;
+        };
+        if u16::from_le_bytes([lo, hi]) != L2CAP_CID_ATT {
+            return;
+        }
+        let _ = self.with_connected_handle(handle, |storage| {
+            if att::is_request(opcode) {
+                storage.att_local_request = true;
+            } else if att::is_response(opcode) {
+                storage.att_peer_request = false;
+            }
+            Ok(())
+        });
+    }
+
+    pub(crate) fn indication_deadline(&self, index: u8) -> Option<Instant> {
+        self.with_mut(|state| state.connections[index as usize].indication_deadline)
+    }
+
+    pub(crate) fn set_anchor(&self, handle: ConnHandle, anchor: Instant) -> Result<(), Error> {
+        self.with_connected_handle(handle, |storage| {
+            storage.anchor = Some(anchor);
+            Ok(())
+        })
+    }
+
+    pub(crate) fn next_anchor(&self, index: u8, now: Instant) -> Option<Instant> {
+        let state = self.state.borrow();
+        let storage = &state.connections[index as usize];
+        let anchor = storage.anchor?;
+        let interval = storage.params.conn_interval.as_ticks();
+        if anchor > now {
+            return Some(anchor);
+        } else if interval == 0 {
+            return None;
+        }
+        let elapsed = (now - anchor).as_ticks();
+        Some(anchor + Duration::from_ticks((elapsed / interval + 1) * interval))
+    }
+
+    /// The earliest connection event anchor after `now` of all connections.
+    pub(crate) fn next_anchor_any(&self, now: Instant) -> Option<Instant> {
+        let len = self.state.borrow().connections.len();
+        (0..len as u8)
+            .filter(|index| self.is_connected(*index))
+            .filter_map(|index| self.next_anchor(index, now))
+            .min()
+    }
+
+    /// The start of the next window of `duration` between connection events, or `None` if it is open at `now`.
+    ///
+    /// Connection events are assumed to last at most `guard`, and only connections with a known anchor
+    /// are taken into account. Returns `Error::InvalidValue` if the window can not fit in a connection interval,
+    /// or if no window common to all connections opens within the longest connection interval.
+    pub(crate) fn flash_window(
+        &self,
+        now: Instant,
+        duration: Duration,
+        guard: Duration,
+    ) -> Result<Option<Instant>, Error> {
+        let len = self.state.borrow().connections.len();
+        let longest = (0..len as u8)
+            .filter(|index| self.is_connected(*index) && self.next_anchor(*index, now).is_some())
+            .map(|index| self.state.borrow().connections[index as usize].params.conn_interval)
+            .max()
+            .unwrap_or(Duration::from_ticks(0));
+        let mut at = now;
+        while let Some(start) = self.blocked_until(at, duration, guard)? {
+            if start > now + longest {
+                return Err(Error::InvalidValue);
+            }
+            at = start;
+        }
+        Ok((at != now).then_some(at))
+    }
+
+    // The end of the connection events overlapping a window of `duration` starting at `now`.
+    fn blocked_until(&self, now: Instant, duration: Duration, guard: Duration) -> Result<Option<Instant>, Error> {
+        let len = self.state.borrow().connections.len();
+        let mut start = None;
+        for index in (0..len as u8).filter(|index| self.is_connected(*index)) {
+            let Some(next) = self.next_anchor(index, now) else {
+                continue;
+            };
+            let interval = self.state.borrow().connections[index as usize].params.conn_interval;
+            let previous = if interval.as_ticks() > 0 {
+                if interval < duration + guard * 2 {
+                    return Err(Error::InvalidValue);
+                }
+                Some(next - interval)
+            } else {
+                None
+            };
+            let open = match previous {
+                Some(previous) if now < previous + guard => Some(previous + guard),
+                _ if now + duration + guard > next => Some(next + guard),
+                _ => None,
+            };
+            start = start.max(open);
+        }
+        Ok(start)
+    }
+
     pub(crate) fn get_encrypted(&self, index: u8) -> bool {
         #[cfg(feature = "security")]
         {
@@ -520,6 +1044,13 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
         false
     }
 
+    #[cfg(feature = "security")]
+    pub(crate) fn get_encryption_key_size(&self, index: u8) -> Option<u8> {
+        let state = self.state.borrow();
+        let storage = &state.connections[index as usize];
+        storage.encrypted.then_some(storage.key_size)
+    }
+
     pub(crate) fn handle_security_channel(&self, handle: ConnHandle, pdu: Pdu<P::Packet>) -> Result<(), Error> {
         #[cfg(feature = "security")]
         {
@@ -528,7 +1059,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 match storage.state {
                     ConnectionState::Connected if storage.handle.unwrap() == handle => {
                         if let Err(error) = self.security_manager.handle(pdu, self, storage) {
-                            error!("Failed to handle security manager packet, {:?}", error);
+                            error!(conn = handle, "Failed to handle security manager packet, {:?}", error);
                             return Err(error);
                         }
                         break;
@@ -543,11 +1074,25 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
     pub(crate) fn handle_security_hci_event(&self, event: bt_hci::event::Event) -> Result<(), Error> {
         #[cfg(feature = "security")]
         {
-            self.security_manager.handle_event(&event)?;
+            // Key size of the link, before the pairing data is cleared by the security manager
+            let key_size = match &event {
+                bt_hci::event::Event::EncryptionChangeV1(event_data) => self
+                    .with_connected_handle(event_data.handle, |storage| Ok(storage.peer_identity))
+                    .ok()
+                    .flatten()
+                    .map(|identity| self.security_manager.encryption_key_size(event_data.handle, &identity)),
+                _ => None,
+            };
+
+            self.security_manager.handle_event(&event, self)?;
 
             if let bt_hci::event::Event::EncryptionChangeV1(event_data) = event {
                 self.with_connected_handle(event_data.handle, |storage| {
-                    storage.encrypted = event_data.enabled;
+                    storage.encrypted = event_data.status.to_result().is_ok() && event_data.enabled;
+                    storage.key_size = if storage.encrypted { key_size.unwrap_or(0) } else { 0 };
+                    let _ = storage.events.try_send(ConnectionEvent::SecurityChanged {
+                        encrypted: storage.encrypted,
+                    });
                     Ok(())
                 })?;
             }
@@ -586,18 +1131,22 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
 
                 if let Some((conn, identity)) = conn_info {
                     if let Some(ltk) = self.security_manager.get_peer_long_term_key(&identity) {
+                        host.coex.notify(CoexActivity::Encryption(handle));
                         let _ = host
                             .command(LeLongTermKeyRequestReply::new(handle, ltk.to_le_bytes()))
                             .await?;
                     } else {
-                        warn!("[host] Long term key request reply failed, no long term key");
+                        warn!(
+                            conn = handle,
+                            "[host] Long term key request reply failed, no long term key"
+                        );
                         // Send disconnect event to the controller
                         host.command(Disconnect::new(conn, DisconnectReason::AuthenticationFailure))
                             .await?;
                         unwrap!(self.disconnected(conn, Status::AUTHENTICATION_FAILURE));
                     }
                 } else {
-                    warn!("[host] Long term key request reply failed, unknown peer")
+                    warn!(conn = handle, "[host] Long term key request reply failed, unknown peer")
                 }
             }
             crate::security_manager::SecurityEventData::EnableEncryption(handle, bond_info) => {
@@ -622,6 +1171,7 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                 if let Some((index, role, identity)) = connection_data {
                     if let Some(ltk) = self.security_manager.get_peer_long_term_key(&identity) {
                         if let Some(LeConnRole::Central) = role {
+                            host.coex.notify(CoexActivity::Encryption(handle));
                             host.async_command(LeEnableEncryption::new(handle, [0; 8], 0, ltk.to_le_bytes()))
                                 .await?;
                         }
@@ -629,10 +1179,10 @@ impl<'d, P: PacketPool> ConnectionManager<'d, P> {
                         self.post_event(index as u8, ConnectionEvent::Bonded { bond_info })
                             .await;
                     } else {
-                        warn!("[host] Enable encryption failed, no long term key")
+                        warn!(conn = handle, "[host] Enable encryption failed, no long term key")
                     }
                 } else {
-                    warn!("[host] Enable encryption failed, unknown peer")
+                    warn!(conn = handle, "[host] Enable encryption failed, unknown peer")
                 }
             }
             crate::security_manager::SecurityEventData::Timeout => {
@@ -687,18 +1237,39 @@ pub struct ConnectionStorage<P> {
     pub role: Option<LeConnRole>,
     pub peer_addr_kind: Option<AddrKind>,
     pub peer_identity: Option<Identity>,
+    pub adv_handle: Option<AdvHandle>,
+    pub local_identity: Option<LocalIdentity>,
     pub att_mtu: u16,
-    pub link_credits: usize,
+    pub params: ConnectionParams,
+    pub initial_params: ConnectionParams,
+    pub anchor: Option<Instant>,
+    pub att_timed_out: bool,
+    pub indication_deadline: Option<Instant>,
+    /// A request of the peer waits for the response of the local server.
+    pub att_peer_request: bool,
+    /// A request of the local client waits for the response of the peer.
+    pub att_local_request: bool,
+    #[cfg(feature = "debug-state")]
+    pub att_request: Option<u8>,
+    pub tx_in_flight: usize,
     pub link_credit_waker: WakerRegistration,
+    pub priority_credit_waker: WakerRegistration,
+    pub priority_waiting: bool,
+    pub disconnect_reason: Option<Status>,
+    pub disconnect_waker: WakerRegistration,
     pub refcount: u8,
     #[cfg(feature = "connection-metrics")]
     pub metrics: Metrics,
     #[cfg(feature = "security")]
     pub encrypted: bool,
+    #[cfg(feature = "security")]
+    pub key_size: u8,
     pub events: EventChannel,
     pub reassembly: PacketReassembly<P>,
     #[cfg(feature = "gatt")]
     pub gatt: GattChannel<P>,
+    #[cfg(feature = "gatt")]
+    pub write_queue: WriteQueue<{ config::GATT_WRITE_QUEUE_SIZE }>,
 }
 
 /// Connection metrics
@@ -771,17 +1342,36 @@ impl<P> ConnectionStorage<P> {
             role: None,
             peer_addr_kind: None,
             peer_identity: None,
+            adv_handle: None,
+            local_identity: None,
             att_mtu: 23,
-            link_credits: 0,
+            params: ConnectionParams::new(),
+            initial_params: ConnectionParams::new(),
+            anchor: None,
+            att_timed_out: false,
+            indication_deadline: None,
+            att_peer_request: false,
+            att_local_request: false,
+            #[cfg(feature = "debug-state")]
+            att_request: None,
+            tx_in_flight: 0,
             link_credit_waker: WakerRegistration::new(),
+            priority_credit_waker: WakerRegistration::new(),
+            priority_waiting: false,
+            disconnect_reason: None,
+            disconnect_waker: WakerRegistration::new(),
             refcount: 0,
             #[cfg(feature = "connection-metrics")]
             metrics: Metrics::new(),
             #[cfg(feature = "security")]
             encrypted: false,
+            #[cfg(feature = "security")]
+            key_size: 0,
             events: EventChannel::new(),
             #[cfg(feature = "gatt")]
             gatt: GattChannel::new(),
+            #[cfg(feature = "gatt")]
+            write_queue: WriteQueue::new(),
             reassembly: PacketReassembly::new(),
         }
     }
@@ -810,7 +1400,7 @@ impl<P> defmt::Format for ConnectionStorage<P> {
             "state = {}, conn = {}, flow = {}",
             self.state,
             self.handle,
-            self.link_credits,
+            self.tx_in_flight,
         );
 
         defmt::write!(
@@ -870,18 +1460,19 @@ impl<P> Drop for PacketGrant<'_, '_, P> {
     fn drop(&mut self) {
         if self.packets > 0 {
             let mut state = self.state.borrow_mut();
-            for storage in state.connections.iter_mut() {
-                match storage.state {
-                    ConnectionState::Connected if self.handle == storage.handle.unwrap() => {
-                        storage.link_credits += self.packets;
-                        storage.link_credit_waker.wake();
-                        return;
-                    }
-                    _ => {}
+            let storage = state
+                .connections
+                .iter_mut()
+                .find(|s| s.state == ConnectionState::Connected && s.handle == Some(self.handle));
+            match storage {
+                Some(storage) => {
+                    let packets = self.packets.min(storage.tx_in_flight);
+                    storage.tx_in_flight -= packets;
+                    state.release_credits(packets);
                 }
+                // Released when the connection was disconnected
+                None => warn!(conn = self.handle, "[link] connection not found"),
             }
-            // make it an assert?
-            warn!("[link] connection {:?} not found", self.handle);
         }
     }
 }
@@ -927,6 +1518,153 @@ mod tests {
         handle.disconnect();
     }
 
+    #[test]
+    fn connection_params_updated() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        mgr.set_initial_params(
+            ConnHandle::new(3),
+            Duration::from_micros(30_000),
+            0,
+            Duration::from_millis(4_000),
+        );
+        mgr.update_params(ConnHandle::new(3), |params| {
+            params.conn_interval = Duration::from_micros(7_500);
+            params.tx_phy = bt_hci::param::PhyKind::Le2M;
+        });
+
+        let Poll::Ready(handle) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let params = handle.params();
+        assert_eq!(params.conn_interval, Duration::from_micros(7_500));
+        assert_eq!(params.tx_phy, bt_hci::param::PhyKind::Le2M);
+        assert_eq!(params.supervision_timeout, Duration::from_millis(4_000));
+        assert_eq!(handle.initial_params().conn_interval, Duration::from_micros(30_000));
+        assert_eq!(params.rx_phy, bt_hci::param::PhyKind::Le1M);
+    }
+
+    #[test]
+    fn next_event_anchor() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        mgr.update_params(ConnHandle::new(3), |params| {
+            params.conn_interval = Duration::from_millis(10);
+        });
+        let now = Instant::from_millis(1000);
+        assert_eq!(mgr.next_anchor(0, now), None);
+
+        unwrap!(mgr.set_anchor(ConnHandle::new(3), Instant::from_millis(975)));
+        assert_eq!(mgr.next_anchor(0, now), Some(Instant::from_millis(1005)));
+        assert_eq!(
+            mgr.next_anchor(0, Instant::from_millis(970)),
+            Some(Instant::from_millis(975))
+        );
+
+        mgr.update_params(ConnHandle::new(3), |params| {
+            params.conn_interval = Duration::from_millis(30);
+        });
+        assert_eq!(mgr.next_anchor(0, now), None);
+    }
+
+    #[test]
+    fn flash_window_between_events() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(handle) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        mgr.update_params(ConnHandle::new(3), |params| {
+            params.conn_interval = Duration::from_millis(10);
+        });
+        let guard = Duration::from_millis(2);
+        let duration = Duration::from_millis(5);
+        let window = |now| unwrap!(mgr.flash_window(Instant::from_millis(now), duration, guard));
+        // Unknown anchor
+        assert_eq!(window(1001), None);
+
+        unwrap!(mgr.set_anchor(ConnHandle::new(3), Instant::from_millis(1000)));
+        assert_eq!(window(1001), Some(Instant::from_millis(1002)));
+        assert_eq!(window(1003), None);
+        assert_eq!(window(1004), Some(Instant::from_millis(1012)));
+        assert!(mgr
+            .flash_window(Instant::from_millis(1003), Duration::from_millis(7), guard)
+            .is_err());
+
+        // The gaps between the events of both connections never overlap
+        unwrap!(mgr.connect(
+            ConnHandle::new(4),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_2),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(other) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        mgr.update_params(ConnHandle::new(4), |params| {
+            params.conn_interval = Duration::from_millis(10);
+        });
+        unwrap!(mgr.set_anchor(ConnHandle::new(4), Instant::from_millis(1005)));
+        assert_eq!(
+            mgr.flash_window(Instant::from_millis(1001), duration, guard),
+            Err(Error::InvalidValue)
+        );
+        drop(other);
+        drop(handle);
+    }
+
+    #[test]
+    fn local_identity_of_adv_set() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(handle) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert_eq!(handle.local_identity(), None);
+
+        let identity = LocalIdentity::new(Address::random(ADDR_2));
+        mgr.set_adv_handle(ConnHandle::new(3), AdvHandle::new(1), Some(identity));
+        assert_eq!(handle.adv_handle(), Some(AdvHandle::new(1)));
+        assert_eq!(handle.local_identity(), Some(identity));
+
+        // Advertising set reported before the connection
+        mgr.set_adv_handle(ConnHandle::new(4), AdvHandle::new(2), None);
+        unwrap!(mgr.connect(
+            ConnHandle::new(4),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_2),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(handle) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert_eq!(handle.adv_handle(), Some(AdvHandle::new(2)));
+    }
+
     #[test]
     fn central_connection_established() {
         let mgr = setup();
@@ -1058,6 +1796,179 @@ mod tests {
         assert!(mgr.poll_disconnecting(None).is_pending());
     }
 
+    #[test]
+    fn scope_ends_on_disconnect() {
+        let mgr = setup();
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        assert_eq!(block_on(conn.scope(async { 7 })), Ok(7));
+
+        unwrap!(mgr.disconnected(ConnHandle::new(3), Status::UNSPECIFIED));
+        let pending = core::future::pending::<()>();
+        assert_eq!(block_on(conn.scope(pending)), Err(Status::UNSPECIFIED));
+    }
+
+    #[test]
+    fn att_transaction_timeout() {
+        let mgr = setup();
+
+        let handle = ConnHandle::new(3);
+        unwrap!(mgr.connect(handle, AddrKind::RANDOM, BdAddr::new(ADDR_1), LeConnRole::Peripheral));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        conn.indication_sent(Instant::now() + Duration::from_secs(30));
+        mgr.indication_confirmed(handle);
+        assert_eq!(conn.indication_deadline(), None);
+        assert!(!mgr.att_timed_out_handle(handle));
+
+        conn.set_att_timed_out();
+        conn.set_att_timed_out();
+        assert!(mgr.att_timed_out_handle(handle));
+        assert!(matches!(
+            block_on(conn.next()),
+            crate::connection::ConnectionEvent::AttTransactionTimeout
+        ));
+        assert!(mgr.with_mut(|state| state.connections[0].events.is_empty()));
+
+        // The bearer is usable again after reconnecting
+        unwrap!(mgr.disconnected(handle, Status::UNSPECIFIED));
+        drop(conn);
+        unwrap!(mgr.connect(handle, AddrKind::RANDOM, BdAddr::new(ADDR_1), LeConnRole::Peripheral));
+        assert!(!mgr.att_timed_out_handle(handle));
+    }
+
+    #[test]
+    fn priority_request_reserves_link_credits() {
+        let mgr = setup();
+        mgr.set_link_credits(1);
+
+        let handle = ConnHandle::new(3);
+        unwrap!(mgr.connect(handle, AddrKind::RANDOM, BdAddr::new(ADDR_1), LeConnRole::Peripheral));
+        let Poll::Ready(_conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        let Poll::Ready(Ok(bulk)) = mgr.poll_request_to_send(handle, 1, false, None) else {
+            panic!("expected grant");
+        };
+        assert!(mgr.poll_request_to_send(handle, 1, true, None).is_pending());
+        drop(bulk);
+
+        // Credits released while a priority request waits are kept for it
+        assert!(mgr.poll_request_to_send(handle, 1, false, None).is_pending());
+        let Poll::Ready(Ok(priority)) = mgr.poll_request_to_send(handle, 1, true, None) else {
+            panic!("expected priority grant");
+        };
+        drop(priority);
+        assert!(matches!(
+            mgr.poll_request_to_send(handle, 1, false, None),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn tx_window_per_connection() {
+        let mgr = setup();
+        // Two connected peers share four controller buffers, two per connection
+        mgr.set_link_credits(4);
+
+        let slow = ConnHandle::new(3);
+        let other = ConnHandle::new(4);
+        unwrap!(mgr.connect(slow, AddrKind::RANDOM, BdAddr::new(ADDR_1), LeConnRole::Peripheral));
+        unwrap!(mgr.connect(other, AddrKind::RANDOM, BdAddr::new(ADDR_2), LeConnRole::Peripheral));
+        let (Poll::Ready(_a), Poll::Ready(_b)) = (
+            mgr.poll_accept(LeConnRole::Peripheral, &[], None),
+            mgr.poll_accept(LeConnRole::Peripheral, &[], None),
+        ) else {
+            panic!("expected connections to be accepted");
+        };
+
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(slow, 2, false, None) else {
+            panic!("expected grant");
+        };
+        grant.confirm(2);
+        drop(grant);
+        assert!(mgr.poll_request_to_send(slow, 1, false, None).is_pending());
+
+        let Poll::Ready(Ok(mut grant)) = mgr.poll_request_to_send(other, 2, false, None) else {
+            panic!("expected grant");
+        };
+        grant.confirm(2);
+        drop(grant);
+        assert!(mgr.poll_request_to_send(other, 1, false, None).is_pending());
+
+        // Completed packets and disconnections return buffers to the shared pool
+        mgr.confirm_sent([(slow, 1)].into_iter());
+        assert!(matches!(
+            mgr.poll_request_to_send(slow, 1, false, None),
+            Poll::Ready(Ok(_))
+        ));
+        unwrap!(mgr.disconnected(other, Status::UNSPECIFIED));
+        assert_eq!(mgr.with_mut(|state| state.controller_credits), 3);
+
+        // A single connection may use all the buffers
+        assert!(matches!(
+            mgr.poll_request_to_send(slow, 2, false, None),
+            Poll::Ready(Ok(_))
+        ));
+    }
+
+    #[test]
+    fn accept_resolved_peer_from_filter_list() {
+        let mgr = setup();
+
+        let peers = [
+            (AddrKind::PUBLIC, &BdAddr::new(ADDR_2)),
+            (AddrKind::RANDOM, &BdAddr::new(ADDR_1)),
+        ];
+        assert!(mgr.poll_accept(LeConnRole::Central, &peers, None).is_pending());
+
+        // Peer address resolved by the controller
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Central, &peers, None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert_eq!(conn.peer(), crate::Address::random(ADDR_1));
+    }
+
+    #[test]
+    fn accept_peer_with_kind_from_report() {
+        let mgr = setup();
+
+        // Address kind as reported by an advertising report resolved by the controller
+        let peers = [(AddrKind::RESOLVABLE_PRIVATE_OR_PUBLIC, &BdAddr::new(ADDR_1))];
+        assert!(mgr.poll_accept(LeConnRole::Central, &peers, None).is_pending());
+
+        unwrap!(mgr.connect(
+            ConnHandle::new(3),
+            AddrKind::PUBLIC,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Central, &peers, None) else {
+            panic!("expected connection to be accepted");
+        };
+        assert_eq!(conn.peer(), crate::Address::public(ADDR_1));
+    }
+
     #[test]
     fn referenced_handle_not_reused() {
         let mgr = setup();
@@ -1191,4 +2102,40 @@ mod tests {
 
         assert!(!mgr.is_handle_connected(ConnHandle::new(3)));
     }
+
+    #[cfg(feature = "debug-state")]
+    #[test]
+    fn debug_state() {
+        use crate::debug_state::LinkState;
+
+        let mgr = setup();
+        unwrap!(mgr.connect(
+            ConnHandle::new(2),
+            AddrKind::RANDOM,
+            BdAddr::new(ADDR_1),
+            LeConnRole::Central
+        ));
+        let Poll::Ready(handle) = mgr.poll_accept(LeConnRole::Central, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        handle.set_att_request(Some(0x0a));
+
+        let mut links = std::vec::Vec::new();
+        mgr.debug_state(|c| links.push(c));
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].handle, ConnHandle::new(2));
+        assert_eq!(links[0].state, LinkState::Connected);
+        assert_eq!(links[0].att_request, Some(0x0a));
+        assert!(!links[0].att_timed_out);
+
+        handle.set_att_timed_out();
+        handle.disconnect();
+        links.clear();
+        mgr.debug_state(|c| links.push(c));
+        assert!(links[0].att_timed_out);
+        assert_eq!(
+            links[0].state,
+            LinkState::DisconnectRequested(DisconnectReason::RemoteUserTerminatedConn)
+        );
+    }
 }
@@ -0,0 +1,170 @@
+//! Snapshots of the host state machines, for debugging.
+//!
+//! When a connection or a channel stalls, [`Stack::debug_state`](crate::Stack::debug_state) shows what the host is
+//! waiting for: the state of each link and L2CAP channel, the GATT client request awaiting a response, and the phase
+//! of an ongoing pairing. A snapshot is plain data, so it can be logged, sent over a debug channel or compared
+//! between runs.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//!
+//! fn dump<C: Controller>(stack: &Stack<'_, C, DefaultPacketPool>) {
+//!     let state = stack.debug_state::<4, 2>();
+//!     for conn in state.connections.iter() {
+//!         if let Some(opcode) = conn.att_request {
+//!             println!("{:?}: waiting for a response to ATT request {:02x}", conn.handle, opcode);
+//!         }
+//!     }
+//! }
+//! ```
+use bt_hci::param::{ConnHandle, DisconnectReason, LeConnRole};
+use heapless::Vec;
+
+#[cfg(feature = "security")]
+pub use crate::security_manager::PairingState;
+
+/// Snapshot of the host state machines.
+///
+/// Holds up to `CONNS` links and `CHANNELS` L2CAP channels, which are normally the sizes given to
+/// [`HostResources`](crate::HostResources). Slots that are not in use are left out, and links and channels that
+/// don't fit are counted in `truncated_connections` and `truncated_channels`.
+#[derive(Debug, Clone)]
+pub struct DebugState<const CONNS: usize, const CHANNELS: usize> {
+    /// Links in use, including those being established or torn down.
+    pub connections: Vec<ConnectionDebugState, CONNS>,
+    /// Number of links in use left out of `connections`.
+    pub truncated_connections: usize,
+    /// L2CAP connection oriented channels in use.
+    pub channels: Vec<ChannelDebugState, CHANNELS>,
+    /// Number of channels in use left out of `channels`.
+    pub truncated_channels: usize,
+    /// Pairing in progress or last completed, if any.
+    #[cfg(feature = "security")]
+    pub pairing: Option<PairingDebugState>,
+    /// Number of PDUs waiting in the transmit queues.
+    pub outbound_pending: usize,
+    /// Number of ACL packets sent to the controller and not yet reported as completed.
+    pub packets_in_flight: usize,
+}
+
+#[cfg(feature = "defmt")]
+impl<const CONNS: usize, const CHANNELS: usize> defmt::Format for DebugState<CONNS, CHANNELS> {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(
+            fmt,
+            "DebugState {{ connections: {}, truncated_connections: {}, channels: {}, truncated_channels: {}, ",
+            &self.connections[..],
+            self.truncated_connections,
+            &self.channels[..],
+            self.truncated_channels,
+        );
+        #[cfg(feature = "security")]
+        defmt::write!(fmt, "pairing: {}, ", self.pairing);
+        defmt::write!(
+            fmt,
+            "outbound_pending: {}, packets_in_flight: {} }}",
+            self.outbound_pending,
+            self.packets_in_flight
+        );
+    }
+}
+
+/// State of a link.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum LinkState {
+    /// Connection established by the controller, not yet accepted by the application.
+    Connecting,
+    /// Connected.
+    Connected,
+    /// Disconnection requested, not yet sent to the controller.
+    DisconnectRequested(DisconnectReason),
+    /// Disconnection sent to the controller, waiting for the disconnection complete event.
+    Disconnecting(DisconnectReason),
+    /// Disconnected, with handles to the connection still held by the application.
+    Disconnected,
+}
+
+/// Snapshot of a link.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ConnectionDebugState {
+    /// Connection handle.
+    pub handle: ConnHandle,
+    /// Role of the local device.
+    pub role: LeConnRole,
+    /// State of the link.
+    pub state: LinkState,
+    /// Negotiated ATT MTU.
+    pub att_mtu: u16,
+    /// Opcode of the GATT client request waiting for a response.
+    pub att_request: Option<u8>,
+    /// Whether an indication is waiting for its confirmation.
+    pub indication_pending: bool,
+    /// Whether an ATT transaction timed out, after which no ATT PDUs are exchanged until reconnected.
+    pub att_timed_out: bool,
+    /// Whether the link is encrypted.
+    #[cfg(feature = "security")]
+    pub encrypted: bool,
+    /// Number of ACL packets sent to the controller and not yet reported as completed.
+    pub tx_in_flight: usize,
+    /// Number of handles to the connection held by the application and the host.
+    pub refcount: u8,
+}
+
+/// State of an L2CAP connection oriented channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum L2capState {
+    /// Connection request sent, waiting for the response.
+    Connecting,
+    /// Connection request received, not yet accepted.
+    PeerConnecting,
+    /// Connected.
+    Connected,
+    /// Disconnection requested by the peer.
+    PeerDisconnecting,
+    /// Disconnection request sent, waiting for the response.
+    Disconnecting,
+    /// Disconnected, with handles to the channel still held by the application.
+    Disconnected,
+}
+
+/// Snapshot of an L2CAP connection oriented channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ChannelDebugState {
+    /// Handle of the connection the channel belongs to.
+    pub conn: ConnHandle,
+    /// State of the channel.
+    pub state: L2capState,
+    /// Local channel identifier.
+    pub cid: u16,
+    /// Channel identifier of the peer.
+    pub peer_cid: u16,
+    /// Protocol/service multiplexer of the channel.
+    pub psm: u16,
+    /// Credits given to the peer and not yet used.
+    pub local_credits: u16,
+    /// Credits given by the peer and not yet used. No data is sent while zero.
+    pub peer_credits: u16,
+    /// Number of inbound packets waiting to be read by the application.
+    pub rx_queued: usize,
+    /// Number of handles to the channel held by the application and the host.
+    pub refcount: u8,
+}
+
+/// Snapshot of the pairing handled by the security manager.
+#[cfg(feature = "security")]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PairingDebugState {
+    /// Handle of the connection being paired.
+    pub handle: ConnHandle,
+    /// Role of the local device.
+    pub role: LeConnRole,
+    /// Phase of the pairing.
+    pub state: PairingState,
+    /// Whether the pairing completed successfully.
+    pub complete: bool,
+}
@@ -0,0 +1,219 @@
+//! Finder network advertisements.
+//!
+//! Trackers of finder networks, such as Apple Find My and Google Find My Device, advertise a key that rotates
+//! on a fixed schedule, so that only the owner can link the locations reported by finders to the tracker.
+//! This module builds the advertisements and tracks the rotation schedule. Deriving and provisioning the
+//! keys is left to the application, by implementing [`KeyProvider`].
+//!
+//! ```rust no_run
+//! use embassy_time::Instant;
+//! use trouble_host::finder::{KeyProvider, OfflineFinding, RotationSchedule, OFFLINE_FINDING_INTERVAL};
+//! use trouble_host::prelude::*;
+//!
+//! struct Keys;
+//! impl KeyProvider<28> for Keys {
+//!     fn key(&self, period: u32) -> [u8; 28] {
+//!         // Derive the public key of the period, or read it from provisioned storage
+//!         [0; 28]
+//!     }
+//! }
+//!
+//! let schedule = RotationSchedule::new(Instant::now(), OFFLINE_FINDING_INTERVAL);
+//! let finding = OfflineFinding::new(Keys.key(schedule.period(Instant::now())), 0);
+//! let mut payload = [0; 27];
+//! let mut adv = [0; 31];
+//! AdStructure::encode_slice(&[finding.ad_structure(&mut payload)], &mut adv).unwrap();
+//! // Advertise from `finding.address()` until `schedule.next_rotation(Instant::now())`
+//! ```
+use embassy_time::{Duration, Instant};
+
+use crate::advertise::AdStructure;
+use crate::Address;
+
+/// Rotation interval of Apple offline finding keys.
+pub const OFFLINE_FINDING_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Rotation interval of Google Find My Device ephemeral identifiers.
+pub const FIND_MY_DEVICE_INTERVAL: Duration = Duration::from_secs(1024);
+
+/// Provider of the key advertised in each rotation period.
+///
+/// Keeps the key material and the cryptography needed to derive keys outside of the host.
+pub trait KeyProvider<const N: usize> {
+    /// Key to advertise during `period`, counted from the epoch of the [`RotationSchedule`].
+    fn key(&self, period: u32) -> [u8; N];
+}
+
+/// Schedule of the key rotations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RotationSchedule {
+    epoch: Instant,
+    interval: Duration,
+}
+
+impl RotationSchedule {
+    /// Create a schedule rotating every `interval` since `epoch`, the start of period 0.
+    ///
+    /// The epoch is the time the keys were provisioned at, which must be restored after a reset for the
+    /// tracker to keep advertising the keys the owner expects.
+    pub const fn new(epoch: Instant, interval: Duration) -> Self {
+        Self { epoch, interval }
+    }
+
+    /// Rotation period at `now`.
+    pub fn period(&self, now: Instant) -> u32 {
+        let elapsed = now
+            .checked_duration_since(self.epoch)
+            .unwrap_or(Duration::from_ticks(0));
+        (elapsed.as_ticks() / self.interval.as_ticks()) as u32
+    }
+
+    /// Start of the rotation period after the one at `now`.
+    pub fn next_rotation(&self, now: Instant) -> Instant {
+        self.epoch + self.interval * (self.period(now) + 1)
+    }
+}
+
+/// Apple offline finding advertisement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OfflineFinding {
+    key: [u8; 28],
+    status: u8,
+}
+
+impl OfflineFinding {
+    /// Company identifier of the manufacturer specific data.
+    pub const COMPANY_IDENTIFIER: u16 = 0x004c;
+
+    /// Advertise the 28 byte public key of the current period, with the status byte, e.g. the battery level.
+    pub const fn new(key: [u8; 28], status: u8) -> Self {
+        Self { key, status }
+    }
+
+    /// Static random address to advertise with, made of the first 6 bytes of the key.
+    ///
+    /// The address changes with the key, so it must be set when the key rotates.
+    pub fn address(&self) -> Address {
+        let mut addr = [0; 6];
+        for (dest, src) in addr.iter_mut().zip(self.key[..6].iter().rev()) {
+            *dest = *src;
+        }
+        addr[5] |= 0xc0;
+        Address::random(addr)
+    }
+
+    /// Manufacturer specific data holding the rest of the key, with the payload written to `buf`.
+    pub fn ad_structure<'a>(&self, buf: &'a mut [u8; 27]) -> AdStructure<'a> {
+        buf[0] = 0x12;
+        buf[1] = 25;
+        buf[2] = self.status;
+        buf[3..25].copy_from_slice(&self.key[6..]);
+        // The two bits of the key replaced in the address
+        buf[25] = self.key[0] >> 6;
+        buf[26] = 0;
+        AdStructure::ManufacturerSpecificData {
+            company_identifier: Self::COMPANY_IDENTIFIER,
+            payload: buf,
+        }
+    }
+}
+
+/// Google Find My Device advertisement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct FindMyDevice {
+    eid: [u8; 20],
+    hashed_flags: Option<u8>,
+    unwanted_tracking_protection: bool,
+}
+
+impl FindMyDevice {
+    /// Service data UUID, in the byte order of [`AdStructure::ServiceData16`].
+    pub const UUID: [u8; 2] = [0xaa, 0xfe];
+
+    /// Advertise the 20 byte ephemeral identifier of the current period.
+    ///
+    /// `hashed_flags` are the flags already hashed with the ephemeral identity key, if advertised.
+    pub const fn new(eid: [u8; 20], hashed_flags: Option<u8>) -> Self {
+        Self {
+            eid,
+            hashed_flags,
+            unwanted_tracking_protection: false,
+        }
+    }
+
+    /// Advertise in unwanted tracking protection mode, when separated from the owner.
+    pub const fn unwanted_tracking_protection(mut self, enabled: bool) -> Self {
+        self.unwanted_tracking_protection = enabled;
+        self
+    }
+
+    /// Service data holding the frame, with the data written to `buf`.
+    pub fn ad_structure<'a>(&self, buf: &'a mut [u8; 22]) -> AdStructure<'a> {
+        buf[0] = if self.unwanted_tracking_protection { 0x41 } else { 0x40 };
+        buf[1..21].copy_from_slice(&self.eid);
+        let len = match self.hashed_flags {
+            Some(flags) => {
+                buf[21] = flags;
+                22
+            }
+            None => 21,
+        };
+        AdStructure::ServiceData16 {
+            uuid: Self::UUID,
+            data: &buf[..len],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AddressType;
+
+    #[test]
+    fn offline_finding() {
+        let key: [u8; 28] = core::array::from_fn(|i| i as u8 + 0x10);
+        let finding = OfflineFinding::new(key, 0x20);
+        let address = finding.address();
+        assert_eq!(address.address_type(), AddressType::StaticRandom);
+        assert_eq!(address.addr.raw(), &[0x15, 0x14, 0x13, 0x12, 0x11, 0xd0]);
+        let mut payload = [0; 27];
+        let mut adv = [0; 31];
+        let len = AdStructure::encode_slice(&[finding.ad_structure(&mut payload)], &mut adv).unwrap();
+        assert_eq!(len, 31);
+        assert_eq!(&adv[..8], &[30, 0xff, 0x4c, 0x00, 0x12, 25, 0x20, 0x16]);
+        assert_eq!(&adv[28..], &[0x2b, 0, 0]);
+    }
+
+    #[test]
+    fn find_my_device() {
+        let mut buf = [0; 22];
+        let frame = FindMyDevice::new([0x55; 20], None).unwanted_tracking_protection(true);
+        let mut adv = [0; 31];
+        let len = AdStructure::encode_slice(&[frame.ad_structure(&mut buf)], &mut adv).unwrap();
+        assert_eq!(len, 25);
+        assert_eq!(&adv[..6], &[24, 0x16, 0xaa, 0xfe, 0x41, 0x55]);
+
+        let frame = FindMyDevice::new([0x55; 20], Some(0x99));
+        let len = AdStructure::encode_slice(&[frame.ad_structure(&mut buf)], &mut adv).unwrap();
+        assert_eq!(len, 26);
+        assert_eq!(&adv[..5], &[25, 0x16, 0xaa, 0xfe, 0x40]);
+        assert_eq!(adv[25], 0x99);
+    }
+
+    #[test]
+    fn rotation_schedule() {
+        let epoch = Instant::from_secs(100);
+        let schedule = RotationSchedule::new(epoch, FIND_MY_DEVICE_INTERVAL);
+        assert_eq!(schedule.period(Instant::from_secs(50)), 0);
+        assert_eq!(schedule.period(epoch + Duration::from_secs(1023)), 0);
+        assert_eq!(schedule.period(epoch + Duration::from_secs(2048)), 2);
+        assert_eq!(
+            schedule.next_rotation(epoch + Duration::from_secs(2048)),
+            epoch + Duration::from_secs(3072)
+        );
+    }
+}
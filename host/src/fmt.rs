@@ -109,12 +109,34 @@ macro_rules! panic {
 }
 
 macro_rules! trace {
+    (conn = $conn:expr, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[allow(unused)]
+            const ENABLED: bool = $crate::fmt::enabled(::core::module_path!());
+            #[cfg(feature = "log")]
+            if ENABLED {
+                ::log::trace!("[conn {}] {}", $conn.raw(), ::core::format_args!($s $(, $x)*));
+            }
+            #[cfg(feature = "defmt")]
+            if ENABLED {
+                ::defmt::trace!("[conn {}] {}", $conn.raw(), $crate::fmt::FormatFn(|fmt| ::defmt::write!(fmt, $s $(, $x)*)));
+            }
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = (&$conn, $( & $x ),*);
+        }
+    };
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            #[allow(unused)]
+            const ENABLED: bool = $crate::fmt::enabled(::core::module_path!());
             #[cfg(feature = "log")]
-            ::log::trace!($s $(, $x)*);
+            if ENABLED {
+                ::log::trace!($s $(, $x)*);
+            }
             #[cfg(feature = "defmt")]
-            ::defmt::trace!($s $(, $x)*);
+            if ENABLED {
+                ::defmt::trace!($s $(, $x)*);
+            }
             #[cfg(not(any(feature = "log", feature="defmt")))]
             let _ = ($( & $x ),*);
         }
@@ -122,12 +144,34 @@ macro_rules! trace {
 }
 
 macro_rules! debug {
+    (conn = $conn:expr, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[allow(unused)]
+            const ENABLED: bool = $crate::fmt::enabled(::core::module_path!());
+            #[cfg(feature = "log")]
+            if ENABLED {
+                ::log::debug!("[conn {}] {}", $conn.raw(), ::core::format_args!($s $(, $x)*));
+            }
+            #[cfg(feature = "defmt")]
+            if ENABLED {
+                ::defmt::debug!("[conn {}] {}", $conn.raw(), $crate::fmt::FormatFn(|fmt| ::defmt::write!(fmt, $s $(, $x)*)));
+            }
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = (&$conn, $( & $x ),*);
+        }
+    };
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
+            #[allow(unused)]
+            const ENABLED: bool = $crate::fmt::enabled(::core::module_path!());
             #[cfg(feature = "log")]
-            ::log::debug!($s $(, $x)*);
+            if ENABLED {
+                ::log::debug!($s $(, $x)*);
+            }
             #[cfg(feature = "defmt")]
-            ::defmt::debug!($s $(, $x)*);
+            if ENABLED {
+                ::defmt::debug!($s $(, $x)*);
+            }
             #[cfg(not(any(feature = "log", feature="defmt")))]
             let _ = ($( & $x ),*);
         }
@@ -135,6 +179,16 @@ macro_rules! debug {
 }
 
 macro_rules! info {
+    (conn = $conn:expr, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(feature = "log")]
+            ::log::info!("[conn {}] {}", $conn.raw(), ::core::format_args!($s $(, $x)*));
+            #[cfg(feature = "defmt")]
+            ::defmt::info!("[conn {}] {}", $conn.raw(), $crate::fmt::FormatFn(|fmt| ::defmt::write!(fmt, $s $(, $x)*)));
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = (&$conn, $( & $x ),*);
+        }
+    };
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
             #[cfg(feature = "log")]
@@ -148,6 +202,16 @@ macro_rules! info {
 }
 
 macro_rules! warn {
+    (conn = $conn:expr, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(feature = "log")]
+            ::log::warn!("[conn {}] {}", $conn.raw(), ::core::format_args!($s $(, $x)*));
+            #[cfg(feature = "defmt")]
+            ::defmt::warn!("[conn {}] {}", $conn.raw(), $crate::fmt::FormatFn(|fmt| ::defmt::write!(fmt, $s $(, $x)*)));
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = (&$conn, $( & $x ),*);
+        }
+    };
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
             #[cfg(feature = "log")]
@@ -161,6 +225,16 @@ macro_rules! warn {
 }
 
 macro_rules! error {
+    (conn = $conn:expr, $s:literal $(, $x:expr)* $(,)?) => {
+        {
+            #[cfg(feature = "log")]
+            ::log::error!("[conn {}] {}", $conn.raw(), ::core::format_args!($s $(, $x)*));
+            #[cfg(feature = "defmt")]
+            ::defmt::error!("[conn {}] {}", $conn.raw(), $crate::fmt::FormatFn(|fmt| ::defmt::write!(fmt, $s $(, $x)*)));
+            #[cfg(not(any(feature = "log", feature="defmt")))]
+            let _ = (&$conn, $( & $x ),*);
+        }
+    };
     ($s:literal $(, $x:expr)* $(,)?) => {
         {
             #[cfg(feature = "log")]
@@ -200,6 +274,90 @@ macro_rules! unwrap {
     }
 }
 
+/// Modules of the ATT and GATT layer.
+const ATT_MODULES: &[&str] = &[
+    "trouble_host::att",
+    "trouble_host::attribute",
+    "trouble_host::attribute_server",
+    "trouble_host::gap",
+    "trouble_host::gatt",
+    "trouble_host::services",
+    "trouble_host::types::gatt_traits",
+    "trouble_host::write_queue",
+];
+
+/// Modules of the L2CAP layer.
+const L2CAP_MODULES: &[&str] = &[
+    "trouble_host::channel_manager",
+    "trouble_host::l2cap",
+    "trouble_host::pdu",
+    "trouble_host::types::l2cap",
+];
+
+/// Modules of the security manager.
+const SECURITY_MODULES: &[&str] = &["trouble_host::security_manager"];
+
+/// Modules of the HCI and link layer, i.e. the runners, connections, advertising and scanning.
+const HOST_MODULES: &[&str] = &[
+    "trouble_host",
+    "trouble_host::advertise",
+    "trouble_host::central",
+    "trouble_host::coex",
+    "trouble_host::command",
+    "trouble_host::connection",
+    "trouble_host::connection_manager",
+    "trouble_host::host",
+    "trouble_host::packet_pool",
+    "trouble_host::peripheral",
+    "trouble_host::scan",
+    "trouble_host::split",
+    "trouble_host::transport",
+    "trouble_host::tx_scheduler",
+];
+
+/// Are trace and debug logs of the module enabled?
+///
+/// The logs of a layer are compiled out with the `log-filter-att`, `log-filter-l2cap`,
+/// `log-filter-security` and `log-filter-host` features. Info, warning and error logs are always kept.
+pub(crate) const fn enabled(module: &str) -> bool {
+    !((cfg!(feature = "log-filter-att") && in_modules(module, ATT_MODULES))
+        || (cfg!(feature = "log-filter-l2cap") && in_modules(module, L2CAP_MODULES))
+        || (cfg!(feature = "log-filter-security") && in_modules(module, SECURITY_MODULES))
+        || (cfg!(feature = "log-filter-host") && is_module(module, HOST_MODULES)))
+}
+
+/// Is the module path exactly one of the modules?
+const fn is_module(module: &str, modules: &[&str]) -> bool {
+    let mut i = 0;
+    while i < modules.len() {
+        if modules[i].len() == module.len() && in_modules(module, &[modules[i]]) {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Is the module path one of the modules, or a submodule of one of them?
+const fn in_modules(module: &str, modules: &[&str]) -> bool {
+    let module = module.as_bytes();
+    let mut i = 0;
+    while i < modules.len() {
+        let prefix = modules[i].as_bytes();
+        if module.len() >= prefix.len() && (module.len() == prefix.len() || module[prefix.len()] == b':') {
+            let mut j = 0;
+            while j < prefix.len() && module[j] == prefix[j] {
+                j += 1;
+            }
+            if j == prefix.len() {
+                return true;
+            }
+        }
+        i += 1;
+    }
+    false
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub struct NoneError;
 
@@ -229,6 +387,17 @@ impl<T, E> Try for Result<T, E> {
     }
 }
 
+/// Message of a log line prefixed with a connection handle, formatted by defmt.
+#[cfg(feature = "defmt")]
+pub(crate) struct FormatFn<F: Fn(defmt::Formatter<'_>)>(pub F);
+
+#[cfg(feature = "defmt")]
+impl<F: Fn(defmt::Formatter<'_>)> defmt::Format for FormatFn<F> {
+    fn format(&self, fmt: defmt::Formatter) {
+        (self.0)(fmt)
+    }
+}
+
 #[allow(unused)]
 pub(crate) struct Bytes<'a>(pub &'a [u8]);
 
@@ -256,3 +425,66 @@ impl<'a> defmt::Format for Bytes<'a> {
         defmt::write!(fmt, "{:02x}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_filter() {
+        assert!(in_modules("trouble_host::l2cap", L2CAP_MODULES));
+        assert!(in_modules("trouble_host::l2cap::sar", L2CAP_MODULES));
+        assert!(!in_modules("trouble_host::l2cap_extra", L2CAP_MODULES));
+        assert!(in_modules("trouble_host::attribute_server", ATT_MODULES));
+        assert!(!in_modules("trouble_host::host", ATT_MODULES));
+        assert!(is_module("trouble_host", HOST_MODULES));
+        assert!(!is_module("trouble_host::attribute", HOST_MODULES));
+    }
+
+    #[test]
+    fn every_module_has_a_layer() {
+        extern crate std;
+        use std::string::{String, ToString};
+
+        // Modules without trace or debug logs of their own
+        const OTHER: &[&str] = &[
+            "trouble_host::assigned_numbers",
+            "trouble_host::bthome",
+            "trouble_host::codec",
+            "trouble_host::config",
+            "trouble_host::cursor",
+            "trouble_host::debug_state",
+            "trouble_host::finder",
+            "trouble_host::fmt",
+            "trouble_host::mock_controller",
+            "trouble_host::ranging",
+            "trouble_host::test_mode",
+            "trouble_host::types",
+            "trouble_host::types::primitives",
+            "trouble_host::types::uuid",
+        ];
+        fn visit(dir: &std::path::Path, module: &str) {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let name = path.file_stem().unwrap().to_str().unwrap().to_string();
+                let module: String = match name.as_str() {
+                    "lib" | "mod" => module.to_string(),
+                    _ => std::format!("{module}::{name}"),
+                };
+                if path.is_dir() {
+                    visit(&path, &module);
+                    continue;
+                }
+                let layers = [ATT_MODULES, L2CAP_MODULES, SECURITY_MODULES];
+                let covered = layers.iter().any(|layer| in_modules(&module, layer))
+                    || is_module(&module, HOST_MODULES)
+                    || in_modules(&module, OTHER);
+                assert!(covered, "module {} is not in a log filter layer", module);
+            }
+        }
+        visit(
+            &std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            "trouble_host",
+        );
+    }
+}
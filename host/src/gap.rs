@@ -7,14 +7,21 @@
 //! In addition, this profile includes common format requirements for
 //! parameters accessible on the user interface level.
 
+use core::marker::PhantomData;
+
 use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Duration;
 use heapless::String;
 use static_cell::StaticCell;
 
+use crate::advertise::{
+    AdStructure, AdvertisementParameters, AD_FLAG_LE_LIMITED_DISCOVERABLE, BR_EDR_NOT_SUPPORTED,
+    LE_GENERAL_DISCOVERABLE,
+};
 use crate::prelude::*;
 
 /// Advertising packet is limited to 31 bytes. 9 of these are used by other GAP data, leaving 22 bytes for the Device Name characteristic
-const DEVICE_NAME_MAX_LENGTH: usize = 22;
+pub const DEVICE_NAME_MAX_LENGTH: usize = 22;
 
 /// The number of attributes added by the GAP and GATT services
 /// GAP_SERVICE:       1
@@ -25,6 +32,47 @@ const DEVICE_NAME_MAX_LENGTH: usize = 22;
 ///                  = 6
 pub const GAP_SERVICE_ATTRIBUTE_COUNT: usize = 6;
 
+/// The first handle available to services added after the GAP and GATT services.
+pub const GAP_SERVICE_HANDLE_END: u16 = 0x20;
+
+/// Maximum time to remain advertising in the limited discoverable mode.
+pub const LIMITED_DISCOVERABLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Discoverable mode of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum DiscoverableMode {
+    /// The device is not discoverable.
+    NonDiscoverable,
+    /// The device is discoverable for a limited time, typically after a user action.
+    ///
+    /// The stack stops advertising after [`LIMITED_DISCOVERABLE_TIMEOUT`].
+    Limited,
+    /// The device is discoverable until advertising is stopped.
+    #[default]
+    General,
+}
+
+impl DiscoverableMode {
+    /// The value of the Flags AD type for this mode on an LE only device.
+    pub fn flags(&self) -> u8 {
+        let discoverable = match self {
+            DiscoverableMode::NonDiscoverable => 0,
+            DiscoverableMode::Limited => AD_FLAG_LE_LIMITED_DISCOVERABLE,
+            DiscoverableMode::General => LE_GENERAL_DISCOVERABLE,
+        };
+        discoverable | BR_EDR_NOT_SUPPORTED
+    }
+
+    /// The advertising timeout for this mode.
+    pub fn timeout(&self) -> Option<Duration> {
+        match self {
+            DiscoverableMode::Limited => Some(LIMITED_DISCOVERABLE_TIMEOUT),
+            _ => None,
+        }
+    }
+}
+
 /// Configuration for the GAP Service.
 pub enum GapConfig<'a> {
     /// Peripheral device configuration.
@@ -41,10 +89,31 @@ pub struct PeripheralConfig<'a> {
     ///
     /// Example: `&appearance::sensor::GENERIC_SENSOR.`
     pub appearance: &'a BluetoothUuid16,
+    /// Allow clients to write the device name, e.g. to let users rename a speaker from a companion app.
+    pub name_write: Option<DeviceNameWrite<'a>>,
+    /// The discoverable mode used when advertising.
+    pub discoverable: DiscoverableMode,
     // TODO: Add more GAP parameters
     // pub preferred_connection_parameters: Option<ConnectionParameters>,
 }
 
+/// Permission for clients to write the device name of a peripheral.
+#[derive(Clone, Copy)]
+pub struct DeviceNameWrite<'a> {
+    /// Security required to write the name, writes on links that don't meet it are rejected.
+    pub security: AttributeSecurity,
+    /// Store of the names written by clients.
+    pub store: &'a (dyn DeviceNameStore + Sync),
+}
+
+/// Store of the device name written by a client.
+pub trait DeviceNameStore {
+    /// Persist the new device name, e.g. to flash, so that it is configured again after a reset.
+    ///
+    /// Called by the attribute server once the name is written, which must not be accessed from here.
+    fn store(&self, name: &str);
+}
+
 /// Configuration for a central device GAP Service.
 pub struct CentralConfig<'a> {
     /// The name of the central device.
@@ -61,10 +130,32 @@ impl<'a> GapConfig<'a> {
     ///
     /// This configuration will use the `UNKNOWN` appearance.
     pub fn default(name: &'a str) -> Self {
-        GapConfig::Peripheral(PeripheralConfig {
-            name,
-            appearance: &appearance::UNKNOWN,
-        })
+        GapConfig::Peripheral(PeripheralConfig::new(name, &appearance::UNKNOWN))
+    }
+
+    /// The discoverable mode of the device.
+    ///
+    /// A central is never discoverable.
+    pub fn discoverable(&self) -> DiscoverableMode {
+        match self {
+            GapConfig::Peripheral(config) => config.discoverable,
+            GapConfig::Central(_) => DiscoverableMode::NonDiscoverable,
+        }
+    }
+
+    /// The Flags AD structure to include in the advertising data.
+    pub fn ad_flags(&self) -> AdStructure<'static> {
+        AdStructure::Flags(self.discoverable().flags())
+    }
+
+    /// Apply the discoverable mode to the advertising parameters.
+    ///
+    /// In the limited discoverable mode the advertising timeout is capped to [`LIMITED_DISCOVERABLE_TIMEOUT`].
+    pub fn advertisement_parameters(&self, mut params: AdvertisementParameters) -> AdvertisementParameters {
+        if let Some(max) = self.discoverable().timeout() {
+            params.timeout = Some(params.timeout.map_or(max, |timeout| timeout.min(max)));
+        }
+        params
     }
 
     /// Add the GAP config to the attribute table
@@ -80,41 +171,318 @@ impl<'a> GapConfig<'a> {
 }
 
 impl<'a> PeripheralConfig<'a> {
+    /// Create a peripheral configuration, discoverable in the general mode.
+    ///
+    /// The other fields are set to their defaults, and can be changed before building the GAP service.
+    pub fn new(name: &'a str, appearance: &'a BluetoothUuid16) -> Self {
+        Self {
+            name,
+            appearance,
+            name_write: None,
+            discoverable: DiscoverableMode::General,
+        }
+    }
+
     /// Add the peripheral GAP config to the attribute table
     fn build<M: RawMutex, const MAX: usize>(self, table: &mut AttributeTable<'a, M, MAX>) -> Result<(), &'static str> {
-        static PERIPHERAL_NAME: StaticCell<String<DEVICE_NAME_MAX_LENGTH>> = StaticCell::new();
-        let peripheral_name = PERIPHERAL_NAME.init(String::new());
-        peripheral_name
-            .push_str(self.name)
-            .map_err(|_| "Device name is too long. Max length is 22 bytes")?;
-
-        let mut gap_builder = table.add_service(Service::new(service::GAP));
-        gap_builder.add_characteristic_ro(characteristic::DEVICE_NAME, peripheral_name);
-        gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
-        gap_builder.build();
-
-        table.add_service(Service::new(service::GATT));
-
-        Ok(())
+        static PERIPHERAL_NAME: StaticCell<[u8; DEVICE_NAME_MAX_LENGTH]> = StaticCell::new();
+        static PERIPHERAL_APPEARANCE: StaticCell<[u8; 2]> = StaticCell::new();
+        build_gap_service(
+            table,
+            self.name,
+            self.appearance,
+            self.name_write,
+            PERIPHERAL_NAME.init([0; DEVICE_NAME_MAX_LENGTH]),
+            PERIPHERAL_APPEARANCE.init([0; 2]),
+        )
     }
 }
 
 impl<'a> CentralConfig<'a> {
     /// Add the peripheral GAP config to the attribute table
     fn build<M: RawMutex, const MAX: usize>(self, table: &mut AttributeTable<'a, M, MAX>) -> Result<(), &'static str> {
-        static CENTRAL_NAME: StaticCell<String<DEVICE_NAME_MAX_LENGTH>> = StaticCell::new();
-        let central_name = CENTRAL_NAME.init(String::new());
-        central_name
-            .push_str(self.name)
-            .map_err(|_| "Device name is too long. Max length is 22 bytes")?;
+        static CENTRAL_NAME: StaticCell<[u8; DEVICE_NAME_MAX_LENGTH]> = StaticCell::new();
+        static CENTRAL_APPEARANCE: StaticCell<[u8; 2]> = StaticCell::new();
+        build_gap_service(
+            table,
+            self.name,
+            self.appearance,
+            None,
+            CENTRAL_NAME.init([0; DEVICE_NAME_MAX_LENGTH]),
+            CENTRAL_APPEARANCE.init([0; 2]),
+        )
+    }
+}
+
+/// Add the GAP and GATT services to the attribute table.
+///
+/// The device name and appearance are backed by mutable storage so that they can be
+/// updated at runtime using [`AttributeServer::set_device_name`] and [`AttributeServer::set_appearance`].
+fn build_gap_service<'a, M: RawMutex, const MAX: usize>(
+    table: &mut AttributeTable<'a, M, MAX>,
+    name: &str,
+    appearance: &BluetoothUuid16,
+    name_write: Option<DeviceNameWrite<'a>>,
+    name_store: &'a mut [u8; DEVICE_NAME_MAX_LENGTH],
+    appearance_store: &'a mut [u8; 2],
+) -> Result<(), &'static str> {
+    let mut device_name: String<DEVICE_NAME_MAX_LENGTH> = String::new();
+    device_name
+        .push_str(name)
+        .map_err(|_| "Device name is too long. Max length is 22 bytes")?;
+
+    let name_props: &[CharacteristicProp] = match name_write {
+        Some(_) => &[CharacteristicProp::Read, CharacteristicProp::Write],
+        None => &[CharacteristicProp::Read],
+    };
+    let mut gap_builder = table.add_service(Service::new(service::GAP));
+    let name = gap_builder
+        .add_characteristic(characteristic::DEVICE_NAME, name_props, device_name, name_store)
+        .permissions(AttributePermissions {
+            read: AttributeSecurity::None,
+            write: name_write.map_or(AttributeSecurity::None, |write| write.security),
+        })
+        .build();
+    gap_builder.add_characteristic(
+        characteristic::APPEARANCE,
+        &[CharacteristicProp::Read],
+        *appearance,
+        appearance_store,
+    );
+    gap_builder.build();
+    table.device_name_write = name_write.map(|write| (name.handle, write));
+
+    table.add_service(Service::new(service::GATT));
+
+    Ok(())
+}
+
+impl<M: RawMutex, P: PacketPool, const ATT_MAX: usize, const CCCD_MAX: usize, const CONN_MAX: usize>
+    AttributeServer<'_, M, P, ATT_MAX, CCCD_MAX, CONN_MAX>
+{
+    /// Update the device name exposed by the GAP service.
+    ///
+    /// The name can be at most [`DEVICE_NAME_MAX_LENGTH`] bytes long. If the attribute table
+    /// was created without a GAP service, `Error::NotFound` is returned.
+    pub fn set_device_name(&self, name: &str) -> Result<(), Error> {
+        let handle = self.gap_value_handle(characteristic::DEVICE_NAME)?;
+        self.table().set_raw(handle, name.as_bytes())
+    }
+
+    /// Read the device name currently exposed by the GAP service.
+    pub fn device_name(&self) -> Result<String<DEVICE_NAME_MAX_LENGTH>, Error> {
+        let handle = self.gap_value_handle(characteristic::DEVICE_NAME)?;
+        self.table().get(&Characteristic::<String<DEVICE_NAME_MAX_LENGTH>> {
+            handle,
+            cccd_handle: None,
+            latency_critical: false,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Update the appearance exposed by the GAP service.
+    ///
+    /// Example: `server.set_appearance(appearance::sensor::GENERIC_SENSOR)`.
+    pub fn set_appearance(&self, appearance: BluetoothUuid16) -> Result<(), Error> {
+        let handle = self.gap_value_handle(characteristic::APPEARANCE)?;
+        self.table().set_raw(handle, AsGatt::as_gatt(&appearance))
+    }
 
-        let mut gap_builder = table.add_service(Service::new(service::GAP));
-        gap_builder.add_characteristic_ro(characteristic::DEVICE_NAME, central_name);
-        gap_builder.add_characteristic_ro(characteristic::APPEARANCE, self.appearance);
-        gap_builder.build();
+    /// Read the appearance currently exposed by the GAP service.
+    pub fn appearance(&self) -> Result<BluetoothUuid16, Error> {
+        let handle = self.gap_value_handle(characteristic::APPEARANCE)?;
+        self.table().get(&Characteristic::<BluetoothUuid16> {
+            handle,
+            cccd_handle: None,
+            latency_critical: false,
+            phantom: PhantomData,
+        })
+    }
 
-        table.add_service(Service::new(service::GATT));
+    /// Encode an advertising payload reflecting the current GAP device name and appearance.
+    ///
+    /// The structures in `data` (flags, service UUIDs, ...) are encoded first, followed by the
+    /// appearance (omitted if `UNKNOWN`) and the device name. If the complete name does not fit
+    /// in `dest`, it is truncated and advertised as a shortened local name instead.
+    ///
+    /// Call this again after [`AttributeServer::set_device_name`] or [`AttributeServer::set_appearance`]
+    /// and restart advertising with the new payload.
+    pub fn encode_advertising_data(&self, data: &[AdStructure<'_>], dest: &mut [u8]) -> Result<usize, Error> {
+        let name = self.device_name()?;
+        let appearance = self.appearance()?;
 
-        Ok(())
+        let mut len = AdStructure::encode_slice(data, dest)?;
+        if appearance != appearance::UNKNOWN {
+            let value: [u8; 2] = appearance.into();
+            len += AdStructure::encode_slice(&[AdStructure::Unknown { ty: 0x19, data: &value }], &mut dest[len..])?;
+        }
+
+        // Length and type octets precede the name.
+        let available = dest.len().saturating_sub(len + 2);
+        let name = if name.len() <= available {
+            AdStructure::CompleteLocalName(name.as_bytes())
+        } else if available > 0 {
+            AdStructure::ShortenedLocalName(&name.as_bytes()[..available])
+        } else {
+            return Err(Error::InsufficientSpace);
+        };
+        len += AdStructure::encode_slice(&[name], &mut dest[len..])?;
+        Ok(len)
+    }
+
+    fn gap_value_handle(&self, uuid: BluetoothUuid16) -> Result<u16, Error> {
+        self.table().find_value_handle(&uuid.into()).ok_or(Error::NotFound)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::Poll;
+    use std::boxed::Box;
+    use std::sync::Mutex;
+
+    use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::att::{self, AttClient, AttReq};
+    use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+
+    #[test]
+    fn discoverable_mode() {
+        let general = GapConfig::default("general");
+        assert!(matches!(general.ad_flags(), AdStructure::Flags(0x06)));
+        assert_eq!(general.advertisement_parameters(Default::default()).timeout, None);
+
+        let limited = GapConfig::Peripheral(PeripheralConfig {
+            discoverable: DiscoverableMode::Limited,
+            ..PeripheralConfig::new("limited", &appearance::UNKNOWN)
+        });
+        assert!(matches!(limited.ad_flags(), AdStructure::Flags(0x05)));
+        let params = limited.advertisement_parameters(AdvertisementParameters {
+            timeout: Some(Duration::from_secs(60)),
+            ..Default::default()
+        });
+        assert_eq!(params.timeout, Some(LIMITED_DISCOVERABLE_TIMEOUT));
+    }
+
+    #[test]
+    fn fixed_service_handles() {
+        let mut table: AttributeTable<'_, NoopRawMutex, 3> = AttributeTable::new();
+        assert_eq!(table.add_service(Service::new(0x1234u16)).build(), 1);
+        assert!(table.add_service_at(Service::new(0x1235u16), 0x08).is_err());
+        assert_eq!(crate::attribute::next_service_handle(1, None, 1), 0x10);
+        assert_eq!(table.add_service(Service::new(0x1236u16)).build(), 0x10);
+        assert_eq!(
+            table.add_service_at(Service::new(0x1237u16), 0x100).unwrap().build(),
+            0x100
+        );
+    }
+
+    #[test]
+    fn update_device_name_and_appearance() {
+        let mut table: AttributeTable<'_, NoopRawMutex, GAP_SERVICE_ATTRIBUTE_COUNT> = AttributeTable::new();
+        GapConfig::default("initial").build(&mut table).unwrap();
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, GAP_SERVICE_ATTRIBUTE_COUNT, 0, 1> =
+            AttributeServer::new(table);
+
+        assert_eq!(server.device_name().unwrap().as_str(), "initial");
+        assert_eq!(server.appearance().unwrap(), appearance::UNKNOWN);
+
+        server.set_device_name("renamed device").unwrap();
+        server.set_appearance(appearance::sensor::GENERIC_SENSOR).unwrap();
+        assert_eq!(server.device_name().unwrap().as_str(), "renamed device");
+        assert_eq!(server.appearance().unwrap(), appearance::sensor::GENERIC_SENSOR);
+        assert!(server.set_device_name("a name that is far too long").is_err());
+
+        let mut adv = [0; 31];
+        let len = server
+            .encode_advertising_data(&[AdStructure::Flags(0x06)], &mut adv[..])
+            .unwrap();
+        let mut it = AdStructure::decode(&adv[..len]);
+        assert!(matches!(it.next(), Some(Ok(AdStructure::Flags(0x06)))));
+        assert!(matches!(it.next(), Some(Ok(AdStructure::Unknown { ty: 0x19, .. }))));
+        assert!(matches!(
+            it.next(),
+            Some(Ok(AdStructure::CompleteLocalName(b"renamed device")))
+        ));
+
+        let mut adv = [0; 12];
+        let len = server.encode_advertising_data(&[], &mut adv[..]).unwrap();
+        let mut it = AdStructure::decode(&adv[..len]);
+        assert!(matches!(it.next(), Some(Ok(AdStructure::Unknown { ty: 0x19, .. }))));
+        assert!(matches!(
+            it.next(),
+            Some(Ok(AdStructure::ShortenedLocalName(b"rename")))
+        ));
+    }
+
+    #[test]
+    fn writable_device_name() {
+        struct Store(Mutex<String<DEVICE_NAME_MAX_LENGTH>>);
+        impl DeviceNameStore for Store {
+            fn store(&self, name: &str) {
+                *self.0.lock().unwrap() = name.try_into().unwrap();
+            }
+        }
+
+        let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 1]));
+        let mgr = Box::leak(Box::new(ConnectionManager::<DefaultPacketPool>::new(
+            &mut storage[..],
+            23,
+        )));
+        mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new([1; 6]),
+            LeConnRole::Peripheral,
+        )
+        .unwrap();
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+
+        let store = Store(Mutex::new(String::new()));
+        let (mut name, mut appearance) = ([0; DEVICE_NAME_MAX_LENGTH], [0; 2]);
+        let mut buf = [0; 23];
+        for security in [AttributeSecurity::Encrypted, AttributeSecurity::None] {
+            let mut table: AttributeTable<'_, NoopRawMutex, GAP_SERVICE_ATTRIBUTE_COUNT> = AttributeTable::new();
+            let write = DeviceNameWrite {
+                security,
+                store: &store,
+            };
+            build_gap_service(
+                &mut table,
+                "speaker",
+                &appearance::UNKNOWN,
+                Some(write),
+                &mut name,
+                &mut appearance,
+            )
+            .unwrap();
+            let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, GAP_SERVICE_ATTRIBUTE_COUNT, 0, 1> =
+                AttributeServer::new(table);
+            let handle = server.gap_value_handle(characteristic::DEVICE_NAME).unwrap();
+            let req = AttClient::Request(AttReq::Write {
+                handle,
+                data: b"kitchen",
+            });
+            let len = server.process(&conn, &req, &mut buf).unwrap().unwrap();
+            if security == AttributeSecurity::Encrypted {
+                // The link is not encrypted
+                assert_eq!(
+                    &buf[..len],
+                    &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, handle as u8, 0, 0x0f]
+                );
+                assert_eq!(server.device_name().unwrap().as_str(), "speaker");
+                assert!(store.0.lock().unwrap().is_empty());
+            } else {
+                assert_eq!(&buf[..len], &[att::ATT_WRITE_RSP]);
+                assert_eq!(server.device_name().unwrap().as_str(), "kitchen");
+                assert_eq!(store.0.lock().unwrap().as_str(), "kitchen");
+            }
+        }
     }
 }
@@ -1,20 +1,27 @@
 //! GATT server and client implementation.
-use core::cell::RefCell;
-use core::future::Future;
+use core::cell::{Cell, RefCell};
+use core::future::{poll_fn, Future};
 use core::marker::PhantomData;
+use core::task::Poll;
 
 use bt_hci::controller::Controller;
 use bt_hci::param::{ConnHandle, PhyKind, Status};
-use bt_hci::uuid::declarations::{CHARACTERISTIC, PRIMARY_SERVICE};
+use bt_hci::uuid::characteristic::SERVICE_CHANGED;
+use bt_hci::uuid::declarations::{CHARACTERISTIC, INCLUDE, PRIMARY_SERVICE, SECONDARY_SERVICE};
 use bt_hci::uuid::descriptors::CLIENT_CHARACTERISTIC_CONFIGURATION;
-use embassy_futures::select::{select, Either};
+use bt_hci::uuid::service::GATT;
+use embassy_futures::select::{select3, Either3};
 use embassy_sync::blocking_mutex::raw::{NoopRawMutex, RawMutex};
 use embassy_sync::channel::{Channel, DynamicReceiver};
-use embassy_sync::pubsub::{self, PubSubChannel, WaitResult};
-use embassy_time::Duration;
+use embassy_sync::mutex::Mutex;
+use embassy_sync::signal::Signal;
+use embassy_time::{with_deadline, with_timeout, Duration, Instant, Timer};
 use heapless::Vec;
 
-use crate::att::{self, Att, AttClient, AttCmd, AttErrorCode, AttReq, AttRsp, AttServer, AttUns, ATT_HANDLE_VALUE_NTF};
+use crate::att::{
+    self, Att, AttBearer, AttCfm, AttClient, AttCmd, AttErrorCode, AttReq, AttRsp, AttServer, AttUns,
+    ATT_HANDLE_VALUE_IND, ATT_HANDLE_VALUE_NTF,
+};
 use crate::attribute::{AttributeData, Characteristic, CharacteristicProp, Uuid, CCCD};
 use crate::attribute_server::{AttributeServer, DynamicAttributeServer};
 use crate::connection::Connection;
@@ -22,9 +29,9 @@ use crate::cursor::{ReadCursor, WriteCursor};
 use crate::pdu::Pdu;
 use crate::prelude::ConnectionEvent;
 #[cfg(feature = "security")]
-use crate::security_manager::BondInformation;
+use crate::security_manager::{BondInformation, Reason};
 use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
-use crate::types::l2cap::L2capHeader;
+pub use crate::write_queue::OverflowPolicy;
 use crate::{config, BleHostError, Error, PacketPool, Stack};
 
 /// A GATT connection event.
@@ -67,6 +74,22 @@ pub enum GattConnectionEvent<'stack, 'server, P: PacketPool> {
         /// Bond info for this connection
         bond_info: BondInformation,
     },
+    #[cfg(feature = "security")]
+    /// The encryption state of this connection changed.
+    SecurityChanged {
+        /// The link is encrypted.
+        encrypted: bool,
+    },
+    #[cfg(feature = "security")]
+    /// Pairing failed on this connection.
+    PairingFailed {
+        /// The SMP failure reason.
+        reason: Reason,
+    },
+    /// An ATT transaction on this connection timed out.
+    ///
+    /// No further ATT PDUs are sent or received on the connection. Disconnect and reconnect to use GATT again.
+    AttTransactionTimeout,
     /// GATT event.
     Gatt {
         /// The event that was returned
@@ -82,7 +105,7 @@ pub struct GattConnection<'stack, 'server, P: PacketPool> {
 
 impl<P: PacketPool> Drop for GattConnection<'_, '_, P> {
     fn drop(&mut self) {
-        trace!("[gatt {}] disconnecting from server", self.connection.handle().raw());
+        trace!(conn = self.connection.handle(), "[gatt] disconnecting from server");
         self.server.disconnect(&self.connection);
     }
 }
@@ -94,7 +117,7 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
         connection: Connection<'stack, P>,
         server: &'server AttributeServer<'values, M, P, AT, CT, CN>,
     ) -> Result<Self, Error> {
-        trace!("[gatt {}] connecting to server", connection.handle().raw());
+        trace!(conn = connection.handle(), "[gatt] connecting to server");
         server.connect(&connection)?;
         Ok(Self { connection, server })
     }
@@ -103,49 +126,157 @@ impl<'stack, 'server, P: PacketPool> GattConnection<'stack, 'server, P> {
     ///
     /// Uses the attribute server to handle the protocol.
     pub async fn next(&self) -> GattConnectionEvent<'stack, 'server, P> {
-        match select(self.connection.next(), self.connection.next_gatt()).await {
-            Either::First(event) => match event {
-                ConnectionEvent::Disconnected { reason } => GattConnectionEvent::Disconnected { reason },
-                ConnectionEvent::ConnectionParamsUpdated {
-                    conn_interval,
-                    peripheral_latency,
-                    supervision_timeout,
-                } => GattConnectionEvent::ConnectionParamsUpdated {
-                    conn_interval,
-                    peripheral_latency,
-                    supervision_timeout,
-                },
-                ConnectionEvent::PhyUpdated { tx_phy, rx_phy } => GattConnectionEvent::PhyUpdated { tx_phy, rx_phy },
-                ConnectionEvent::DataLengthUpdated {
-                    max_tx_octets,
-                    max_tx_time,
-                    max_rx_octets,
-                    max_rx_time,
-                } => GattConnectionEvent::DataLengthUpdated {
-                    max_tx_octets,
-                    max_tx_time,
-                    max_rx_octets,
-                    max_rx_time,
-                },
-                #[cfg(feature = "security")]
-                ConnectionEvent::Bonded { bond_info } => {
-                    // Update the identity of the connection
-                    if let Err(e) = self.server.update_identity(bond_info.identity) {
-                        error!("Failed to update identity in att server: {:?}", e);
+        loop {
+            let deadline = self.connection.indication_deadline();
+            let indication_timeout = async {
+                match deadline {
+                    Some(deadline) => Timer::at(deadline).await,
+                    None => core::future::pending().await,
+                }
+            };
+            match select3(self.connection.next(), self.connection.next_gatt(), indication_timeout).await {
+                Either3::First(event) => return self.map_event(event),
+                Either3::Second(data) => {
+                    let mut data = GattData::new(data, self.connection.clone());
+                    if let Err(code) = self.server.filter_incoming(&self.connection, data.raw()) {
+                        // Only requests expect a response
+                        if matches!(data.incoming(), AttClient::Request(_)) {
+                            match process(&mut data, self.server, Err(code)) {
+                                Ok(reply) => reply.send().await,
+                                Err(e) => warn!(
+                                    conn = self.connection.handle(),
+                                    "[gatt] error rejecting request: {:?}", e
+                                ),
+                            }
+                        }
+                        continue;
+                    }
+                    if let AttClient::Command(AttCmd::Write { handle, data: value }) = data.incoming() {
+                        if self.connection.queues_writes(handle) {
+                            // Write commands can't respond with an error, so rejected writes are dropped.
+                            if self.server.accepts_write_cmd(&self.connection, handle) {
+                                self.connection.queue_write(handle, value);
+                            }
+                            continue;
+                        }
+                    }
+                    return GattConnectionEvent::Gatt {
+                        event: GattEvent::new(data, self.server),
+                    };
+                }
+                Either3::Third(()) => {
+                    if self.connection.indication_deadline() == deadline {
+                        warn!(
+                            conn = self.connection.handle(),
+                            "[gatt] indication not confirmed, abandoning ATT bearer"
+                        );
+                        // Reported by the next connection event
+                        self.connection.set_att_timed_out();
                     }
-                    GattConnectionEvent::Bonded { bond_info }
                 }
+            }
+        }
+    }
+
+    fn map_event(&self, event: ConnectionEvent) -> GattConnectionEvent<'stack, 'server, P> {
+        match event {
+            ConnectionEvent::Disconnected { reason } => GattConnectionEvent::Disconnected { reason },
+            ConnectionEvent::ConnectionParamsUpdated {
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout,
+            } => GattConnectionEvent::ConnectionParamsUpdated {
+                conn_interval,
+                peripheral_latency,
+                supervision_timeout,
             },
-            Either::Second(data) => GattConnectionEvent::Gatt {
-                event: GattEvent::new(GattData::new(data, self.connection.clone()), self.server),
+            ConnectionEvent::PhyUpdated { tx_phy, rx_phy } => GattConnectionEvent::PhyUpdated { tx_phy, rx_phy },
+            ConnectionEvent::DataLengthUpdated {
+                max_tx_octets,
+                max_tx_time,
+                max_rx_octets,
+                max_rx_time,
+            } => GattConnectionEvent::DataLengthUpdated {
+                max_tx_octets,
+                max_tx_time,
+                max_rx_octets,
+                max_rx_time,
             },
+            #[cfg(feature = "security")]
+            ConnectionEvent::Bonded { bond_info } => {
+                // Update the identity of the connection
+                if let Err(e) = self.server.update_identity(bond_info.identity) {
+                    error!(
+                        conn = self.connection.handle(),
+                        "[gatt] failed to update identity in att server: {:?}", e
+                    );
+                }
+                GattConnectionEvent::Bonded { bond_info }
+            }
+            #[cfg(feature = "security")]
+            ConnectionEvent::SecurityChanged { encrypted } => GattConnectionEvent::SecurityChanged { encrypted },
+            #[cfg(feature = "security")]
+            ConnectionEvent::PairingFailed { reason } => GattConnectionEvent::PairingFailed { reason },
+            ConnectionEvent::AttTransactionTimeout => GattConnectionEvent::AttTransactionTimeout,
         }
     }
 
+    /// Run `tasks` until they complete or the connection is disconnected.
+    ///
+    /// See [`Connection::scope`].
+    pub async fn scope<F: Future>(&self, tasks: F) -> Result<F::Output, Status> {
+        self.connection.scope(tasks).await
+    }
+
     /// Get a reference to the underlying BLE connection.
     pub fn raw(&self) -> &Connection<'stack, P> {
         &self.connection
     }
+
+    /// Detach the connection from the attribute server, returning the underlying BLE connection.
+    ///
+    /// The connection stays open, and can be attached to another attribute server with
+    /// [`Connection::with_attribute_server`], i.e. to expose the full database once the peer has bonded.
+    /// Clients that cached the previous database are expected to discover services again.
+    pub fn detach(self) -> Connection<'stack, P> {
+        let connection = self.connection.clone();
+        drop(self);
+        connection
+    }
+
+    /// Queue the data of write commands to `characteristic` instead of delivering one event per write.
+    ///
+    /// This suits a client streaming data at full rate (e.g. the RX characteristic of a UART service), where
+    /// the event queue would fill up and drop writes while the application is busy. The data is read with
+    /// [`GattConnection::read_queued`], and bytes that do not fit in the queue are handled according to `policy`.
+    ///
+    /// Queued writes are taken from the event queue by [`GattConnection::next`], which must keep being polled.
+    /// They pass the middleware and the permissions of the characteristic like any other write, and write
+    /// commands the characteristic does not accept are dropped. The stored value of the characteristic is not
+    /// updated. Write requests to the characteristic are still delivered as events.
+    ///
+    /// The queue size is set with the `gatt-write-queue-size-*` features, and this returns
+    /// [`Error::InsufficientSpace`] when it is 0.
+    pub fn queue_writes<T: AsGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        policy: OverflowPolicy,
+    ) -> Result<(), Error> {
+        self.connection.queue_writes(characteristic.handle, policy)
+    }
+
+    /// Read data written to the characteristic registered with [`GattConnection::queue_writes`].
+    ///
+    /// Waits until data is available, and returns the number of bytes copied into `buf`. Returns
+    /// [`Error::Disconnected`] once the connection is closed and the queue is empty.
+    pub async fn read_queued(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        self.connection.read_queued(buf).await
+    }
+
+    /// Number of written bytes dropped because the write queue was full.
+    pub fn queued_dropped(&self) -> usize {
+        self.connection.queued_dropped()
+    }
 }
 
 /// A GATT payload ready for processing.
@@ -169,12 +300,21 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
         match self.incoming() {
             AttClient::Request(AttReq::Write { handle, .. }) => Some(handle),
             AttClient::Command(AttCmd::Write { handle, .. }) => Some(handle),
+            AttClient::Command(AttCmd::SignedWrite { handle, .. }) => Some(handle),
             AttClient::Request(AttReq::Read { handle }) => Some(handle),
             AttClient::Request(AttReq::ReadBlob { handle, .. }) => Some(handle),
             _ => None,
         }
     }
 
+    fn raw(&self) -> &[u8] {
+        self.pdu.as_ref().unwrap().as_ref()
+    }
+
+    pub(crate) fn connection(&self) -> &Connection<'stack, P> {
+        &self.connection
+    }
+
     /// Get the raw incoming ATT PDU.
     pub fn incoming(&self) -> AttClient<'_> {
         // We know that:
@@ -196,9 +336,20 @@ impl<'stack, P: PacketPool> GattData<'stack, P> {
     }
 
     /// Send an unsolicited ATT PDU without having a request (e.g. notification or indication)
+    ///
+    /// If an indication is not confirmed within the ATT transaction timeout, the ATT bearer is abandoned and
+    /// [`GattConnectionEvent::AttTransactionTimeout`] is returned by [`GattConnection::next`].
     pub async fn send_unsolicited(connection: &Connection<'_, P>, uns: AttUns<'_>) -> Result<(), Error> {
+        let indication = matches!(uns, AttUns::Indicate { .. });
         let pdu = send(connection, AttServer::Unsolicited(uns))?;
-        connection.send(pdu).await;
+        if indication {
+            // The confirmation timer runs from queueing, so the indication is sent ahead of other connections
+            let deadline = Instant::now() + ATT_TRANSACTION_TIMEOUT;
+            connection.indication_sent(deadline);
+            connection.send_with_deadline(pdu, deadline).await;
+        } else {
+            connection.send(pdu).await;
+        }
         Ok(())
     }
 }
@@ -218,9 +369,9 @@ impl<'stack, 'server, P: PacketPool> GattEvent<'stack, 'server, P> {
     pub fn new(data: GattData<'stack, P>, server: &'server dyn DynamicAttributeServer<P>) -> Self {
         let att = data.incoming();
         match att {
-            AttClient::Request(AttReq::Write { .. }) | AttClient::Command(AttCmd::Write { .. }) => {
-                GattEvent::Write(WriteEvent { data, server })
-            }
+            AttClient::Request(AttReq::Write { .. })
+            | AttClient::Command(AttCmd::Write { .. })
+            | AttClient::Command(AttCmd::SignedWrite { .. }) => GattEvent::Write(WriteEvent { data, server }),
             AttClient::Request(AttReq::Read { .. }) | AttClient::Request(AttReq::ReadBlob { .. }) => {
                 GattEvent::Read(ReadEvent { data, server })
             }
@@ -296,6 +447,20 @@ impl<'stack, P: PacketPool> ReadEvent<'stack, '_, P> {
         process(&mut self.data, self.server, Err(err))
     }
 
+    /// Respond to the event with the provided value, instead of the value stored in the attribute table.
+    ///
+    /// The value is not stored, so values computed on each read never need to be written to the table.
+    /// For a read blob request, the response starts at the requested offset. The response is truncated
+    /// to fit the ATT MTU, so longer values are read by the client using read blob requests.
+    pub fn respond_with(mut self, value: &[u8]) -> Result<Reply<'stack, P>, Error> {
+        if let Some(pdu) = self.data.pdu.take() {
+            let reply = process_respond(&pdu, &self.data.connection, value)?;
+            reply.filter(self.server, &pdu)
+        } else {
+            Ok(Reply::new(self.data.connection.clone(), None))
+        }
+    }
+
     /// Get a reference to the underlying `GattData` payload that this event is enclosing
     pub fn payload(&self) -> &GattData<'stack, P> {
         &self.data
@@ -338,7 +503,12 @@ impl<'stack, P: PacketPool> WriteEvent<'stack, '_, P> {
     /// Raw data to be written
     pub fn data(&self) -> &[u8] {
         // Note: write event data is always at offset 3, right?
-        &self.data.pdu.as_ref().unwrap().as_ref()[3..]
+        let pdu = self.data.pdu.as_ref().unwrap().as_ref();
+        // The signature of a signed write follows the data
+        match pdu[0] {
+            att::ATT_SIGNED_WRITE_CMD => &pdu[3..pdu.len() - 12],
+            _ => &pdu[3..],
+        }
     }
 
     /// Characteristic data to be written
@@ -436,11 +606,11 @@ where
     P: PacketPool,
 {
     if let Some(pdu) = data.pdu.take() {
-        let res = match result {
-            Ok(_) => process_accept(&pdu, &data.connection, server),
-            Err(code) => process_reject(&pdu, &data.connection, code),
+        let reply = match result {
+            Ok(_) => process_accept(&pdu, &data.connection, server)?,
+            Err(code) => process_reject(&pdu, &data.connection, code)?,
         };
-        res
+        reply.filter(server, &pdu)
     } else {
         Ok(Reply::new(data.connection.clone(), None))
     }
@@ -460,21 +630,9 @@ where
     let Att::Client(att) = att else {
         unreachable!("Expected Att::Client, got {:?}", att)
     };
-    let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
-    let mut w = WriteCursor::new(tx.as_mut());
-    let (mut header, mut data) = w.split(4)?;
-    if let Some(written) = server.process(connection, &att, data.write_buf())? {
-        let mtu = connection.get_att_mtu();
-        data.commit(written)?;
-        data.truncate(mtu as usize);
-        header.write(data.len() as u16)?;
-        header.write(4_u16)?;
-        let len = header.len() + data.len();
-        let pdu = Pdu::new(tx, len);
-        Ok(Reply::new(connection.clone(), Some(pdu)))
-    } else {
-        Ok(Reply::new(connection.clone(), None))
-    }
+    let tx = P::allocate().ok_or(Error::OutOfMemory)?;
+    let pdu = connection.frame(tx, |buf| server.process(connection, &att, buf))?;
+    Ok(Reply::new(connection.clone(), pdu))
 }
 
 fn process_reject<'stack, P: PacketPool>(
@@ -491,6 +649,7 @@ fn process_reject<'stack, P: PacketPool>(
     let handle = match att {
         AttClient::Request(AttReq::Write { handle, .. }) => handle,
         AttClient::Command(AttCmd::Write { handle, .. }) => handle,
+        AttClient::Command(AttCmd::SignedWrite { handle, .. }) => handle,
         AttClient::Request(AttReq::Read { handle }) => handle,
         AttClient::Request(AttReq::ReadBlob { handle, .. }) => handle,
         _ => 0, // As per spec, if the incoming ATT does not have an ATT handle, we should report with handle 0
@@ -502,18 +661,36 @@ fn process_reject<'stack, P: PacketPool>(
     Ok(Reply::new(connection.clone(), Some(pdu)))
 }
 
-fn send<'stack, P: PacketPool>(conn: &Connection<'stack, P>, att: AttServer<'_>) -> Result<Pdu<P::Packet>, Error> {
-    let mut tx = P::allocate().ok_or(Error::OutOfMemory)?;
-    let mut w = WriteCursor::new(tx.as_mut());
-    let (mut header, mut data) = w.split(4)?;
-    data.write(Att::Server(att))?;
-
-    let mtu = conn.get_att_mtu();
-    data.truncate(mtu as usize);
-    header.write(data.len() as u16)?;
-    header.write(4_u16)?;
-    let len = header.len() + data.len();
-    Ok(Pdu::new(tx, len))
+fn process_respond<'stack, P: PacketPool>(
+    pdu: &Pdu<P::Packet>,
+    connection: &Connection<'stack, P>,
+    value: &[u8],
+) -> Result<Reply<'stack, P>, Error> {
+    // - The PDU is decodable, as it was already decoded once before adding it to the connection queue
+    // - The PDU is a read or read blob request, because only those are wrapped in a `ReadEvent`
+    let att = unwrap!(Att::decode(pdu.as_ref()));
+    let (opcode, offset) = match att {
+        Att::Client(AttClient::Request(AttReq::Read { .. })) => (att::ATT_READ_RSP, 0),
+        Att::Client(AttClient::Request(AttReq::ReadBlob { offset, .. })) => (att::ATT_READ_BLOB_RSP, offset as usize),
+        _ => unreachable!("Expected read request, got {:?}", att),
+    };
+    let Some(value) = value.get(offset..) else {
+        return process_reject(pdu, connection, AttErrorCode::INVALID_OFFSET);
+    };
+    let tx = P::allocate().ok_or(Error::OutOfMemory)?;
+    let pdu = connection.frame(tx, |buf| {
+        let mut w = WriteCursor::new(buf);
+        w.write(opcode)?;
+        let len = value.len().min(w.available());
+        w.append(&value[..len])?;
+        Ok(Some(w.len()))
+    })?;
+    Ok(Reply::new(connection.clone(), pdu))
+}
+
+fn send<P: PacketPool, B: AttBearer<P>>(bearer: &B, att: AttServer<'_>) -> Result<Pdu<P::Packet>, Error> {
+    let tx = P::allocate().ok_or(Error::OutOfMemory)?;
+    bearer.encode(tx, Att::Server(att))
 }
 
 /// A reply to a gatt request.
@@ -530,6 +707,19 @@ impl<'stack, P: PacketPool> Reply<'stack, P> {
         Self { connection, pdu }
     }
 
+    /// Replace the reply to `request` with an error response if the middleware of the server vetoes it.
+    ///
+    /// The client would otherwise wait for a response until the ATT transaction times out.
+    fn filter(mut self, server: &dyn DynamicAttributeServer<P>, request: &Pdu<P::Packet>) -> Result<Self, Error> {
+        if let Some(pdu) = &self.pdu {
+            if !server.filter_outgoing(&self.connection, &pdu.as_ref()[self.connection.header_len()..]) {
+                self.pdu = None;
+                return process_reject(request, &self.connection, AttErrorCode::UNLIKELY_ERROR);
+            }
+        }
+        Ok(self)
+    }
+
     /// Send the reply.
     ///
     /// May fail if the outbound queue is full.
@@ -553,32 +743,149 @@ impl<P: PacketPool> Drop for Reply<'_, P> {
     fn drop(&mut self) {
         if let Some(pdu) = self.pdu.take() {
             if self.connection.try_send(pdu).is_err() {
-                warn!("[gatt] error sending reply (outbound buffer full)");
+                warn!(
+                    conn = self.connection.handle(),
+                    "[gatt] error sending reply (outbound buffer full)"
+                );
             }
         }
     }
 }
 
+/// How notifications are handled when a [`NotificationListener`] does not keep up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum NotificationPolicy {
+    /// Discard the oldest queued notification to make room for the new one.
+    DropOldest,
+    /// Discard the new notification.
+    DropNewest,
+    /// Wait until the listener has room, delaying the processing of further ATT PDUs on the
+    /// connection, including responses and the confirmation of indications.
+    Backpressure,
+}
+
 /// Notification listener for GATT client.
 pub struct NotificationListener<'lst, const MTU: usize> {
-    handle: u16,
-    listener: pubsub::DynSubscriber<'lst, Notification<MTU>>,
+    slot: &'lst NotificationSlot<MTU>,
 }
 
 impl<'lst, const MTU: usize> NotificationListener<'lst, MTU> {
     #[allow(clippy::should_implement_trait)]
     /// Get the next (len: u16, Packet) tuple from the rx queue
     pub async fn next(&mut self) -> Notification<MTU> {
-        loop {
-            if let WaitResult::Message(m) = self.listener.next_message().await {
-                if m.handle == self.handle {
-                    return m;
+        self.slot.queue.receive().await
+    }
+
+    /// Number of notifications dropped because of the [`NotificationPolicy`] of the subscription.
+    pub fn dropped(&self) -> usize {
+        self.slot.dropped.get()
+    }
+}
+
+impl<const MTU: usize> Drop for NotificationListener<'_, MTU> {
+    fn drop(&mut self) {
+        self.slot.subscription.set(None);
+        // Also wakes a notification waiting for room with backpressure
+        self.slot.queue.clear();
+    }
+}
+
+/// Notifications queued for a single subscription.
+struct NotificationSlot<const MTU: usize> {
+    subscription: Cell<Option<(u16, NotificationPolicy)>>,
+    dropped: Cell<usize>,
+    queue: Channel<NoopRawMutex, Notification<MTU>, NOTIF_QSIZE>,
+}
+
+impl<const MTU: usize> NotificationSlot<MTU> {
+    const fn new() -> Self {
+        Self {
+            subscription: Cell::new(None),
+            dropped: Cell::new(0),
+            queue: Channel::new(),
+        }
+    }
+
+    /// Queue a notification according to the policy of the subscription.
+    async fn deliver(&self, n: &Notification<MTU>) {
+        let Some((handle, policy)) = self.subscription.get() else {
+            return;
+        };
+        if handle != n.handle {
+            return;
+        }
+        match policy {
+            NotificationPolicy::DropOldest => {
+                if self.queue.is_full() {
+                    let _ = self.queue.try_receive();
+                    self.dropped.set(self.dropped.get().wrapping_add(1));
+                }
+                let _ = self.queue.try_send(n.clone());
+            }
+            NotificationPolicy::DropNewest => {
+                if self.queue.try_send(n.clone()).is_err() {
+                    self.dropped.set(self.dropped.get().wrapping_add(1));
+                }
+            }
+            NotificationPolicy::Backpressure => {
+                // Stop waiting if the listener goes away
+                poll_fn(|cx| match self.subscription.get() {
+                    Some((h, _)) if h == handle => self.queue.poll_ready_to_send(cx),
+                    _ => Poll::Ready(()),
+                })
+                .await;
+                if self.subscription.get().is_some() {
+                    let _ = self.queue.try_send(n.clone());
                 }
             }
         }
     }
 }
 
+/// Time to wait for the response to an ATT request, as defined by the ATT transaction timeout.
+const ATT_TRANSACTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time to wait before sending a request again, multiplied by the number of attempts so far.
+const REQUEST_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Maximum number of handles in a Read Multiple request.
+const READ_MULTIPLE_MAX_HANDLES: usize = 16;
+
+/// Encode handles for a Read Multiple request.
+fn encode_handles<'a>(handles: &[u16], raw: &'a mut [u8]) -> Result<&'a [u8], Error> {
+    if handles.len() < 2 {
+        return Err(Error::InvalidValue);
+    }
+    if 2 * handles.len() > raw.len() {
+        return Err(Error::InsufficientSpace);
+    }
+    for (dest, handle) in raw.chunks_exact_mut(2).zip(handles) {
+        dest.copy_from_slice(&handle.to_le_bytes());
+    }
+    Ok(&raw[..2 * handles.len()])
+}
+
+/// Check if a response reports that the server lacks the resources to handle the request for now.
+fn is_retryable(pdu: &[u8]) -> bool {
+    matches!(
+        Att::decode(pdu),
+        Ok(Att::Server(AttServer::Response(AttRsp::Error {
+            code: AttErrorCode::INSUFFICIENT_RESOURCES,
+            ..
+        })))
+    )
+}
+
+/// Check if an ATT PDU is the response to a request with the given opcode.
+fn is_response_to(request: u8, pdu: &[u8]) -> bool {
+    match pdu {
+        [att::ATT_ERROR_RSP, req, ..] => *req == request,
+        [opcode, ..] => *opcode == request + 1,
+        [] => false,
+    }
+}
+
 const MAX_NOTIF: usize = config::GATT_CLIENT_NOTIFICATION_MAX_SUBSCRIBERS;
 const NOTIF_QSIZE: usize = config::GATT_CLIENT_NOTIFICATION_QUEUE_SIZE;
 
@@ -589,9 +896,18 @@ pub struct GattClient<'reference, T: Controller, P: PacketPool, const MAX_SERVIC
     stack: &'reference Stack<'reference, T, P>,
     connection: Connection<'reference, P>,
     response_channel: Channel<NoopRawMutex, (ConnHandle, Pdu<P::Packet>), 1>,
+    // Only one request may be outstanding on the bearer at a time.
+    request_lock: Mutex<NoopRawMutex, ()>,
+    request_timeout: Cell<Duration>,
+    request_retries: Cell<u8>,
+    // Opcode of the request awaiting its response, and the end of its ATT transaction.
+    pending: Cell<Option<(u8, Instant)>>,
+    // Value handle of the Service Changed characteristic of the peer, once subscribed.
+    service_changed: Cell<Option<u16>>,
+    services_changed: Signal<NoopRawMutex, (u16, u16)>,
 
     // TODO: Wait for something like https://github.com/rust-lang/rust/issues/132980 (min_generic_const_args) to allow using P::MTU
-    notifications: PubSubChannel<NoopRawMutex, Notification<512>, NOTIF_QSIZE, MAX_NOTIF, 1>,
+    notifications: [NotificationSlot<512>; MAX_NOTIF],
 }
 
 /// A notification payload.
@@ -609,6 +925,21 @@ impl<const MTU: usize> AsRef<[u8]> for Notification<MTU> {
     }
 }
 
+/// An event from the GATT server a client is connected to.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GattClientEvent {
+    /// The services of the server within the handle range have changed.
+    ///
+    /// Services and characteristics previously discovered in the range must be discovered again.
+    ServicesChanged {
+        /// First affected attribute handle.
+        start: u16,
+        /// Last affected attribute handle.
+        end: u16,
+    },
+}
+
 /// Handle for a GATT service.
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, PartialEq, Clone)]
@@ -618,6 +949,16 @@ pub struct ServiceHandle {
     uuid: Uuid,
 }
 
+/// Handle for a descriptor of a characteristic, found by [`GattClient::descriptors`].
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct DescriptorHandle {
+    /// Handle of the descriptor.
+    pub handle: u16,
+    /// Type of the descriptor.
+    pub uuid: Uuid,
+}
+
 pub(crate) struct Response<P> {
     pdu: Pdu<P>,
     handle: ConnHandle,
@@ -634,14 +975,21 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> Client
     for GattClient<'reference, T, P, MAX_SERVICES>
 {
     async fn request(&self, req: AttReq<'_>) -> Result<Response<P::Packet>, BleHostError<T::Error>> {
-        let data = Att::Client(AttClient::Request(req));
-
-        self.send_att_data(data).await?;
-
-        let (h, pdu) = self.response_channel.receive().await;
-
-        assert_eq!(h, self.connection.handle());
-        Ok(Response { handle: h, pdu })
+        let retries = self.request_retries.get();
+        let mut attempt = 0;
+        loop {
+            let response = self.request_once(req.clone()).await?;
+            if attempt == retries || !is_retryable(response.pdu.as_ref()) {
+                return Ok(response);
+            }
+            attempt += 1;
+            debug!(
+                conn = self.connection.handle(),
+                "[gatt] server lacks resources, sending request again ({}/{})", attempt, retries
+            );
+            drop(response);
+            Timer::after(REQUEST_RETRY_DELAY * u32::from(attempt)).await;
+        }
     }
 
     async fn command(&self, cmd: AttCmd<'_>) -> Result<(), BleHostError<T::Error>> {
@@ -654,19 +1002,75 @@ impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> Client
 }
 
 impl<'reference, T: Controller, P: PacketPool, const MAX_SERVICES: usize> GattClient<'reference, T, P, MAX_SERVICES> {
-    async fn send_att_data(&self, data: Att<'_>) -> Result<(), BleHostError<T::Error>> {
-        let header = L2capHeader {
-            channel: crate::types::l2cap::L2CAP_CID_ATT,
-            length: data.size() as u16,
+    async fn request_once(&self, req: AttReq<'_>) -> Result<Response<P::Packet>, BleHostError<T::Error>> {
+        let _guard = self.request_lock.lock().await;
+        if self.connection.att_timed_out() {
+            // No further requests may be sent on a bearer after a transaction timeout.
+            return Err(Error::Timeout.into());
+        }
+        // A request abandoned by a timeout or cancellation is still outstanding until answered.
+        if let Some((opcode, deadline)) = self.pending.get() {
+            if with_deadline(deadline, self.receive_response(opcode)).await.is_err() {
+                warn!(
+                    conn = self.connection.handle(),
+                    "[gatt] request {:02x} not answered within the transaction timeout", opcode
+                );
+                self.connection.set_att_timed_out();
+                return Err(Error::Timeout.into());
+            }
+            self.pending.set(None);
+        }
+
+        let buf = P::allocate_async().await;
+        let pdu = self.connection.encode(buf, Att::Client(AttClient::Request(req)))?;
+        let opcode = pdu.as_ref()[self.connection.header_len()];
+        // Drop any response not matching a request.
+        self.response_channel.clear();
+        #[cfg(feature = "debug-state")]
+        let _pending = {
+            self.connection.set_att_request(Some(opcode));
+            crate::host::OnDrop::new(|| self.connection.set_att_request(None))
         };
+        self.connection.send(pdu).await;
+        let deadline = Instant::now() + ATT_TRANSACTION_TIMEOUT;
+        self.pending.set(Some((opcode, deadline)));
+
+        match with_timeout(self.request_timeout.get(), self.receive_response(opcode)).await {
+            Ok(response) => {
+                self.pending.set(None);
+                Ok(response)
+            }
+            Err(_) => {
+                warn!(
+                    conn = self.connection.handle(),
+                    "[gatt] request {:02x} timed out", opcode
+                );
+                // Only the ATT transaction timeout abandons the bearer
+                if Instant::now() >= deadline {
+                    self.connection.set_att_timed_out();
+                }
+                Err(Error::Timeout.into())
+            }
+        }
+    }
 
-        let mut buf = P::allocate().ok_or(Error::OutOfMemory)?;
-        let mut w = WriteCursor::new(buf.as_mut());
-        w.write_hci(&header)?;
-        w.write(data)?;
-        let len = w.len();
+    async fn receive_response(&self, opcode: u8) -> Response<P::Packet> {
+        loop {
+            let (h, pdu) = self.response_channel.receive().await;
+            if h == self.connection.handle() && is_response_to(opcode, pdu.as_ref()) {
+                return Response { handle: h, pdu };
+            }
+            warn!(
+                conn = h,
+                "[gatt] discarding response not matching request {:02x}", opcode
+            );
+        }
+    }
 
-        self.connection.send(Pdu::new(buf, len)).await;
+    async fn send_att_data(&self, data: Att<'_>) -> Result<(), BleHostError<T::Error>> {
+        let buf = P::allocate_async().await;
+        let pdu = self.connection.encode(buf, data)?;
+        self.connection.send(pdu).await;
         Ok(())
     }
 }
@@ -677,16 +1081,14 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         stack: &'reference Stack<'reference, C, P>,
         connection: &Connection<'reference, P>,
     ) -> Result<GattClient<'reference, C, P, MAX_SERVICES>, BleHostError<C::Error>> {
-        let l2cap = L2capHeader { channel: 4, length: 3 };
-        let mut buf = P::allocate().ok_or(Error::OutOfMemory)?;
-        let mut w = WriteCursor::new(buf.as_mut());
-        w.write_hci(&l2cap)?;
-        w.write(att::Att::Client(att::AttClient::Request(att::AttReq::ExchangeMtu {
-            mtu: P::MTU as u16 - 4,
-        })))?;
-
-        let len = w.len();
-        connection.send(Pdu::new(buf, len)).await;
+        let buf = P::allocate_async().await;
+        let pdu = connection.encode(
+            buf,
+            att::Att::Client(att::AttClient::Request(att::AttReq::ExchangeMtu {
+                mtu: P::MTU as u16 - 4,
+            })),
+        )?;
+        connection.send(pdu).await;
         Ok(Self {
             known_services: RefCell::new(heapless::Vec::new()),
             rx: stack.host.att_client.receiver().into(),
@@ -694,15 +1096,76 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
             connection: connection.clone(),
 
             response_channel: Channel::new(),
-
-            notifications: PubSubChannel::new(),
+            request_lock: Mutex::new(()),
+            request_timeout: Cell::new(ATT_TRANSACTION_TIMEOUT),
+            request_retries: Cell::new(0),
+            pending: Cell::new(None),
+            service_changed: Cell::new(None),
+            services_changed: Signal::new(),
+
+            notifications: [const { NotificationSlot::new() }; MAX_NOTIF],
         })
     }
 
+    pub(crate) fn connection(&self) -> &Connection<'reference, P> {
+        &self.connection
+    }
+
+    /// Set the time to wait for the response to a request.
+    ///
+    /// Requests from concurrent tasks are queued, and only one is outstanding at a time. If no
+    /// response is received within the timeout, the request fails with `Error::Timeout` and the
+    /// client stays usable: the next request is sent once the late response arrives. Only if it
+    /// does not arrive within the ATT transaction timeout of 30 seconds, all further requests on
+    /// this client fail, the connection reports a [`ConnectionEvent::AttTransactionTimeout`], and
+    /// no further ATT PDUs are exchanged on it until reconnected.
+    /// Defaults to the ATT transaction timeout.
+    pub fn set_request_timeout(&self, timeout: Duration) {
+        self.request_timeout.set(timeout);
+    }
+
+    /// Set how many times a request is sent again when the server answers with the ATT error
+    /// `Insufficient Resources`, waiting 100 ms longer before each attempt.
+    ///
+    /// Other errors and timeouts are returned without retrying. Defaults to 0.
+    pub fn set_request_retries(&self, retries: u8) {
+        self.request_retries.set(retries);
+    }
+
     /// Discover primary services associated with a UUID.
     pub async fn services_by_uuid(
         &self,
         uuid: &Uuid,
+    ) -> Result<Vec<ServiceHandle, MAX_SERVICES>, BleHostError<C::Error>> {
+        self.discover_services(uuid, true).await
+    }
+
+    /// Subscribe to the Service Changed characteristic of the server, so that changes are reported by
+    /// [`GattClient::next_event`] and known services in the affected range are forgotten.
+    ///
+    /// Returns `false` if the server has no Service Changed characteristic.
+    pub async fn subscribe_service_changed(&self) -> Result<bool, BleHostError<C::Error>> {
+        if self.service_changed.get().is_some() {
+            return Ok(true);
+        }
+        let handle = self.try_subscribe_service_changed().await?;
+        self.service_changed.set(handle);
+        Ok(handle.is_some())
+    }
+
+    /// Wait for the next event from the server.
+    ///
+    /// Requires [`GattClient::task`] to be running, and [`GattClient::subscribe_service_changed`] to report
+    /// changes of the services.
+    pub async fn next_event(&self) -> GattClientEvent {
+        let (start, end) = self.services_changed.wait().await;
+        GattClientEvent::ServicesChanged { start, end }
+    }
+
+    async fn discover_services(
+        &self,
+        uuid: &Uuid,
+        remember: bool,
     ) -> Result<Vec<ServiceHandle, MAX_SERVICES>, BleHostError<C::Error>> {
         let mut start: u16 = 0x0001;
         let mut result = Vec::new();
@@ -735,10 +1198,12 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                             uuid: uuid.clone(),
                         };
                         result.push(svc.clone()).map_err(|_| Error::InsufficientSpace)?;
-                        self.known_services
-                            .borrow_mut()
-                            .push(svc)
-                            .map_err(|_| Error::InsufficientSpace)?;
+                        if remember {
+                            self.known_services
+                                .borrow_mut()
+                                .push(svc)
+                                .map_err(|_| Error::InsufficientSpace)?;
+                        }
                     }
                     if end == 0xFFFF {
                         break;
@@ -746,7 +1211,7 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                     start = end + 1;
                 }
                 res => {
-                    trace!("[gatt client] response: {:?}", res);
+                    trace!(conn = self.connection.handle(), "[gatt client] response: {:?}", res);
                     return Err(Error::UnexpectedGattResponse.into());
                 }
             }
@@ -801,6 +1266,7 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
                                 return Ok(Characteristic {
                                     handle,
                                     cccd_handle,
+                                    latency_critical: false,
                                     phantom: PhantomData,
                                 });
                             }
@@ -822,6 +1288,278 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
+    /// Discover all characteristics with a UUID in a given service, for services with several instances of a
+    /// characteristic.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the service has more than `N` of them.
+    pub async fn characteristics_by_uuid<T: AsGatt, const N: usize>(
+        &self,
+        service: &ServiceHandle,
+        uuid: &Uuid,
+    ) -> Result<Vec<Characteristic<T>, N>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        let mut start: u16 = service.start;
+        while start <= service.end {
+            let data = att::AttReq::ReadByType {
+                start,
+                end: service.end,
+                attribute_type: CHARACTERISTIC.into(),
+            };
+            let response = self.request(data).await?;
+
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::ReadByType { mut it } => {
+                    let first = start;
+                    while let Some(Ok((_, item))) = it.next() {
+                        if item.len() < 5 {
+                            return Err(Error::MalformedCharacteristicDeclaration {
+                                expected: 5,
+                                actual: item.len(),
+                            }
+                            .into());
+                        }
+                        let AttributeData::Declaration {
+                            props,
+                            handle,
+                            uuid: decl_uuid,
+                        } = AttributeData::decode_declaration(item)?
+                        else {
+                            return Err(Error::InvalidCharacteristicDeclarationData.into());
+                        };
+                        if *uuid == decl_uuid {
+                            let cccd_handle = if props.any(&[CharacteristicProp::Indicate, CharacteristicProp::Notify])
+                            {
+                                Some(self.get_characteristic_cccd(handle).await?.0)
+                            } else {
+                                None
+                            };
+                            result
+                                .push(Characteristic {
+                                    handle,
+                                    cccd_handle,
+                                    latency_critical: false,
+                                    phantom: PhantomData,
+                                })
+                                .map_err(|_| Error::InsufficientSpace)?;
+                        }
+                        if handle == 0xFFFF {
+                            return Ok(result);
+                        }
+                        start = handle + 1;
+                    }
+                    if start == first {
+                        return Err(Error::UnexpectedGattResponse.into());
+                    }
+                }
+                AttRsp::Error {
+                    code: AttErrorCode::ATTRIBUTE_NOT_FOUND,
+                    ..
+                } => break,
+                AttRsp::Error { code, .. } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(result)
+    }
+
+    /// Discover a characteristic by UUID within the first service with the given UUID.
+    ///
+    /// Unlike [`GattClient::services_by_uuid`] followed by [`GattClient::characteristic_by_uuid`],
+    /// this stops at the first matching service, which takes fewer round trips when only a
+    /// single characteristic is needed.
+    pub async fn characteristic_by_service_uuid<T: AsGatt>(
+        &self,
+        service: &Uuid,
+        uuid: &Uuid,
+    ) -> Result<Characteristic<T>, BleHostError<C::Error>> {
+        let data = att::AttReq::FindByTypeValue {
+            start_handle: 0x0001,
+            end_handle: 0xffff,
+            att_type: PRIMARY_SERVICE.into(),
+            att_value: service.as_raw(),
+        };
+        let response = self.request(data).await?;
+        let svc = match Self::response(response.pdu.as_ref())? {
+            AttRsp::FindByTypeValue { mut it } => match it.next() {
+                Some(res) => {
+                    let (start, end) = res?;
+                    ServiceHandle {
+                        start,
+                        end,
+                        uuid: service.clone(),
+                    }
+                }
+                None => return Err(Error::NotFound.into()),
+            },
+            AttRsp::Error {
+                code: AttErrorCode::ATTRIBUTE_NOT_FOUND,
+                ..
+            } => return Err(Error::NotFound.into()),
+            AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+            _ => return Err(Error::UnexpectedGattResponse.into()),
+        };
+
+        {
+            let mut known = self.known_services.borrow_mut();
+            if !known.contains(&svc) {
+                known.push(svc.clone()).map_err(|_| Error::InsufficientSpace)?;
+            }
+        }
+
+        self.characteristic_by_uuid(&svc, uuid).await
+    }
+
+    /// Discover the descriptors of a characteristic in a given service, such as its Client Characteristic
+    /// Configuration, Characteristic Extended Properties or Characteristic User Description.
+    pub async fn descriptors<T: AsGatt, const N: usize>(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &Characteristic<T>,
+    ) -> Result<Vec<DescriptorHandle, N>, BleHostError<C::Error>> {
+        let mut result = Vec::new();
+        self.find_descriptors(service, characteristic, |descriptor| {
+            result.push(descriptor).map_err(|_| Error::InsufficientSpace)?;
+            Ok(true)
+        })
+        .await?;
+        Ok(result)
+    }
+
+    /// Discover a descriptor of a characteristic in a given service using a UUID.
+    pub async fn descriptor_by_uuid<T: AsGatt>(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &Characteristic<T>,
+        uuid: &Uuid,
+    ) -> Result<DescriptorHandle, BleHostError<C::Error>> {
+        let mut found = None;
+        self.find_descriptors(service, characteristic, |descriptor| {
+            if descriptor.uuid == *uuid {
+                found = Some(descriptor);
+            }
+            Ok(found.is_none())
+        })
+        .await?;
+        found.ok_or(Error::NotFound.into())
+    }
+
+    // Calls `f` for each descriptor of the characteristic until it returns false. The descriptors
+    // follow the characteristic value, up to the next declaration or the end of the service.
+    async fn find_descriptors<T: AsGatt>(
+        &self,
+        service: &ServiceHandle,
+        characteristic: &Characteristic<T>,
+        mut f: impl FnMut(DescriptorHandle) -> Result<bool, Error>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let declarations: [Uuid; 4] = [
+            PRIMARY_SERVICE.into(),
+            SECONDARY_SERVICE.into(),
+            INCLUDE.into(),
+            CHARACTERISTIC.into(),
+        ];
+        let mut start = characteristic.handle;
+        while start < service.end {
+            let data = att::AttReq::FindInformation {
+                start_handle: start + 1,
+                end_handle: service.end,
+            };
+            let response = self.request(data).await?;
+            match Self::response(response.pdu.as_ref())? {
+                AttRsp::FindInformation { mut it } => {
+                    let mut last = None;
+                    while let Some(res) = it.next() {
+                        let (handle, uuid) = res?;
+                        if declarations.contains(&uuid) || !f(DescriptorHandle { handle, uuid })? {
+                            return Ok(());
+                        }
+                        last = Some(handle);
+                    }
+                    match last {
+                        Some(handle) if handle > start => start = handle,
+                        _ => return Err(Error::UnexpectedGattResponse.into()),
+                    }
+                }
+                AttRsp::Error {
+                    code: AttErrorCode::ATTRIBUTE_NOT_FOUND,
+                    ..
+                } => return Ok(()),
+                AttRsp::Error { request, handle, code } => return Err(Error::Att(code).into()),
+                _ => return Err(Error::UnexpectedGattResponse.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a descriptor.
+    ///
+    /// The number of bytes copied into the provided buffer is returned.
+    pub async fn read_descriptor(
+        &self,
+        descriptor: &DescriptorHandle,
+        dest: &mut [u8],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let data = att::AttReq::Read {
+            handle: descriptor.handle,
+        };
+
+        let response = self.request(data).await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::Read { data } => {
+                let to_copy = data.len().min(dest.len());
+                dest[..to_copy].copy_from_slice(&data[..to_copy]);
+                Ok(to_copy)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Write a descriptor.
+    ///
+    /// Writing the Client Characteristic Configuration this way does not register a listener, use
+    /// [`GattClient::subscribe`] to receive notifications.
+    pub async fn write_descriptor(
+        &self,
+        descriptor: &DescriptorHandle,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let data = att::AttReq::Write {
+            handle: descriptor.handle,
+            data: buf,
+        };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::Write => Ok(()),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    async fn try_subscribe_service_changed(&self) -> Result<Option<u16>, BleHostError<C::Error>> {
+        let services = self.discover_services(&GATT.into(), false).await?;
+        let Some(service) = services.first() else {
+            return Ok(None);
+        };
+        let characteristic: Characteristic<[u8; 4]> =
+            match self.characteristic_by_uuid(service, &SERVICE_CHANGED.into()).await {
+                Ok(c) => c,
+                Err(BleHostError::BleHost(Error::Att(_) | Error::NotFound)) => return Ok(None),
+                Err(e) => return Err(e),
+            };
+        let data = att::AttReq::Write {
+            handle: characteristic.cccd_handle.ok_or(Error::NotSupported)?,
+            data: &0x02u16.to_le_bytes(),
+        };
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::Write => Ok(Some(characteristic.handle)),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
     async fn get_characteristic_cccd(&self, char_handle: u16) -> Result<(u16, CCCD), BleHostError<C::Error>> {
         let data = att::AttReq::ReadByType {
             start: char_handle,
@@ -914,6 +1652,68 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 
+    /// Read the values of multiple characteristics, described by their handles, in a single request.
+    ///
+    /// The values are concatenated into the provided buffer, so all but the last characteristic
+    /// must have a fixed length. The response is limited by the ATT MTU.
+    ///
+    /// The number of bytes copied into the provided buffer is returned.
+    pub async fn read_multiple(&self, handles: &[u16], dest: &mut [u8]) -> Result<usize, BleHostError<C::Error>> {
+        let mut raw = [0; 2 * READ_MULTIPLE_MAX_HANDLES];
+        let handles = encode_handles(handles, &mut raw)?;
+        let response = self.request(att::AttReq::ReadMultiple { handles }).await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultiple { data } => {
+                let to_copy = data.len().min(dest.len());
+                dest[..to_copy].copy_from_slice(&data[..to_copy]);
+                Ok(to_copy)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Read the values of multiple variable length characteristics, described by their handles,
+    /// in a single request.
+    ///
+    /// The values are copied one after another into `dest`, and the number of bytes copied for
+    /// each value is written to `lens`. A value may be truncated if the response does not fit the
+    /// ATT MTU.
+    ///
+    /// The number of values read is returned.
+    pub async fn read_multiple_variable(
+        &self,
+        handles: &[u16],
+        dest: &mut [u8],
+        lens: &mut [usize],
+    ) -> Result<usize, BleHostError<C::Error>> {
+        let mut raw = [0; 2 * READ_MULTIPLE_MAX_HANDLES];
+        let handles = encode_handles(handles, &mut raw)?;
+        let response = self.request(att::AttReq::ReadMultipleVariable { handles }).await?;
+
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ReadMultipleVariable { mut it } => {
+                let mut pos = 0;
+                let mut count = 0;
+                while let Some(item) = it.next() {
+                    let (_len, value) = item?;
+                    let Some(len) = lens.get_mut(count) else {
+                        break;
+                    };
+                    let to_copy = value.len().min(dest.len() - pos);
+                    dest[pos..pos + to_copy].copy_from_slice(&value[..to_copy]);
+                    *len = to_copy;
+                    pos += to_copy;
+                    count += 1;
+                }
+                Ok(count)
+            }
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
     /// Write to a characteristic described by a handle.
     pub async fn write_characteristic<T: FromGatt>(
         &self,
@@ -949,13 +1749,107 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         Ok(())
     }
 
+    /// Write a value longer than the ATT MTU allows to a characteristic described by a handle.
+    ///
+    /// The value is queued on the server in prepare write requests and written at once when all parts
+    /// have been queued. The queue is cancelled on any error, and [`Error::UnexpectedGattResponse`] is
+    /// returned if the server does not echo a part back unchanged.
+    pub async fn write_characteristic_long<T: FromGatt>(
+        &self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let mut write = self.reliable_write();
+        write.prepare(handle, buf).await?;
+        write.execute().await
+    }
+
+    /// Start a reliable write, queueing values for one or more characteristics on the server before they
+    /// are written at once with [`ReliableWrite::execute`].
+    ///
+    /// Only one reliable write may be in progress on a connection.
+    pub fn reliable_write(&self) -> ReliableWrite<'_, 'reference, C, P, MAX_SERVICES> {
+        ReliableWrite {
+            client: self,
+            pending: false,
+        }
+    }
+
+    async fn prepare_write(&self, handle: u16, offset: u16, value: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        let data = att::AttReq::PrepareWrite { handle, offset, value };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            // The server echoes the queued part, so it can be checked for corruption
+            AttRsp::PrepareWrite {
+                handle: h,
+                offset: o,
+                value: v,
+            } if h == handle && o == offset && v == value => Ok(()),
+            AttRsp::PrepareWrite { .. } => Err(Error::UnexpectedGattResponse.into()),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    async fn execute_write(&self, commit: bool) -> Result<(), BleHostError<C::Error>> {
+        let data = att::AttReq::ExecuteWrite { flags: commit.into() };
+
+        let response = self.request(data).await?;
+        match Self::response(response.pdu.as_ref())? {
+            AttRsp::ExecuteWrite => Ok(()),
+            AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
+            _ => Err(Error::UnexpectedGattResponse.into()),
+        }
+    }
+
+    /// Write a signed command, without waiting for a response, to a characteristic described by a handle.
+    ///
+    /// The write is signed with the signing key distributed to the peer when bonding, so it can be used
+    /// without encryption. Returns [`Error::NotFound`] if the bond has no local signing key.
+    #[cfg(feature = "security")]
+    pub async fn write_characteristic_signed<T: FromGatt>(
+        &self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        let signature = self
+            .connection
+            .sign(&[&[att::ATT_SIGNED_WRITE_CMD], &handle.handle.to_le_bytes(), buf])?;
+        let data = att::AttCmd::SignedWrite {
+            handle: handle.handle,
+            data: buf,
+            signature,
+        };
+
+        self.command(data).await?;
+
+        Ok(())
+    }
+
     /// Subscribe to indication/notification of a given Characteristic
     ///
-    /// A listener is returned, which has a `next()` method
+    /// A listener is returned, which has a `next()` method. Notifications the listener does not
+    /// keep up with are dropped, oldest first.
     pub async fn subscribe<T: AsGatt>(
         &self,
         characteristic: &Characteristic<T>,
         indication: bool,
+    ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
+        self.subscribe_with_policy(characteristic, indication, NotificationPolicy::DropOldest)
+            .await
+    }
+
+    /// Subscribe to indication/notification of a given Characteristic, choosing how notifications
+    /// the listener does not keep up with are handled.
+    ///
+    /// With [`NotificationPolicy::Backpressure`] nothing is lost, but a listener that is not polled
+    /// stalls every request of the client.
+    pub async fn subscribe_with_policy<T: AsGatt>(
+        &self,
+        characteristic: &Characteristic<T>,
+        indication: bool,
+        policy: NotificationPolicy,
     ) -> Result<NotificationListener<'_, 512>, BleHostError<C::Error>> {
         let properties = u16::to_le_bytes(if indication { 0x02 } else { 0x01 });
 
@@ -968,16 +1862,17 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         let response = self.request(data).await?;
 
         match Self::response(response.pdu.as_ref())? {
-            AttRsp::Write => match self.notifications.dyn_subscriber() {
-                Ok(listener) => Ok(NotificationListener {
-                    listener,
-                    handle: characteristic.handle,
-                }),
-                Err(embassy_sync::pubsub::Error::MaximumSubscribersReached) => {
-                    Err(Error::GattSubscriberLimitReached.into())
-                }
-                Err(_) => Err(Error::Other.into()),
-            },
+            AttRsp::Write => {
+                let slot = self
+                    .notifications
+                    .iter()
+                    .find(|slot| slot.subscription.get().is_none())
+                    .ok_or(Error::GattSubscriberLimitReached)?;
+                slot.queue.clear();
+                slot.dropped.set(0);
+                slot.subscription.set(Some((characteristic.handle, policy)));
+                Ok(NotificationListener { slot })
+            }
             AttRsp::Error { request, handle, code } => Err(Error::Att(code).into()),
             _ => Err(Error::UnexpectedGattResponse.into()),
         }
@@ -1021,10 +1916,38 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
             data,
             len: to_copy,
         };
-        self.notifications.immediate_publisher().publish_immediate(n);
+        for slot in self.notifications.iter() {
+            slot.deliver(&n).await;
+        }
         Ok(())
     }
 
+    async fn handle_indication_packet(&self, data: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        let mut r = ReadCursor::new(data);
+        let handle: u16 = r.read()?;
+        if Some(handle) == self.service_changed.get() {
+            let start: u16 = r.read()?;
+            let end: u16 = r.read()?;
+            info!(
+                conn = self.connection.handle(),
+                "[gatt client] services changed in range {}..={}", start, end
+            );
+            self.known_services
+                .borrow_mut()
+                .retain(|s| s.end < start || s.start > end);
+            // Merge with a change not yet picked up by the application.
+            let (start, end) = match self.services_changed.try_take() {
+                Some((s, e)) => (s.min(start), e.max(end)),
+                None => (start, end),
+            };
+            self.services_changed.signal((start, end));
+        } else {
+            self.handle_notification_packet(data).await?;
+        }
+        self.send_att_data(Att::Client(AttClient::Confirmation(AttCfm::ConfirmIndication)))
+            .await
+    }
+
     /// Task which handles GATT rx data (needed for notifications to work)
     pub async fn task(&self) -> Result<(), BleHostError<C::Error>> {
         loop {
@@ -1033,8 +1956,10 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
             // handle notifications
             if pdu.as_ref()[0] == ATT_HANDLE_VALUE_NTF {
                 self.handle_notification_packet(&pdu.as_ref()[1..]).await?;
-            } else {
-                self.response_channel.send((handle, pdu)).await;
+            } else if pdu.as_ref()[0] == ATT_HANDLE_VALUE_IND {
+                self.handle_indication_packet(&pdu.as_ref()[1..]).await?;
+            } else if self.response_channel.try_send((handle, pdu)).is_err() {
+                warn!(conn = handle, "[gatt] discarding unexpected response");
             }
         }
     }
@@ -1047,3 +1972,275 @@ impl<'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> GattCl
         }
     }
 }
+
+/// A reliable write in progress, created with [`GattClient::reliable_write`].
+///
+/// Values are queued on the server with [`Self::prepare`], and every queued part is verified against
+/// the copy echoed by the server. Nothing is written until [`Self::execute`] is called. A write that is
+/// dropped without being executed is not cancelled on the server, call [`Self::cancel`] instead.
+pub struct ReliableWrite<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    pending: bool,
+}
+
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize> ReliableWrite<'_, '_, C, P, MAX_SERVICES> {
+    /// Queue a value for a characteristic, split into as many parts as the ATT MTU requires.
+    ///
+    /// If a part cannot be queued the queue is cancelled, and [`Error::UnexpectedGattResponse`] is
+    /// returned if a part is not echoed back unchanged.
+    pub async fn prepare<T: FromGatt>(
+        &mut self,
+        handle: &Characteristic<T>,
+        buf: &[u8],
+    ) -> Result<(), BleHostError<C::Error>> {
+        self.prepare_handle(handle.handle, buf).await
+    }
+
+    /// Queue a value for an attribute, such as a descriptor, given its handle.
+    pub async fn prepare_handle(&mut self, handle: u16, buf: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        if let Err(e) = self.prepare_parts(handle, buf).await {
+            // Report the original error, cancelling also fails e.g. once disconnected
+            let _ = self.cancel().await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    async fn prepare_parts(&mut self, handle: u16, buf: &[u8]) -> Result<(), BleHostError<C::Error>> {
+        // Each prepare write request carries a 5 byte opcode, handle and offset
+        let chunk_len = usize::from(self.client.connection.att_mtu()) - 5;
+        let mut offset = 0;
+        loop {
+            let chunk = &buf[offset..buf.len().min(offset + chunk_len)];
+            let offset_field = u16::try_from(offset).map_err(|_| Error::InvalidValue)?;
+            self.pending = true;
+            self.client.prepare_write(handle, offset_field, chunk).await?;
+            offset += chunk.len();
+            if offset >= buf.len() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Write all queued values.
+    ///
+    /// If the values cannot be written, the queue is cancelled.
+    pub async fn execute(&mut self) -> Result<(), BleHostError<C::Error>> {
+        let result = self.client.execute_write(true).await;
+        if result.is_err() {
+            let _ = self.client.execute_write(false).await;
+        }
+        self.pending = false;
+        result
+    }
+
+    /// Discard all queued values without writing them.
+    pub async fn cancel(&mut self) -> Result<(), BleHostError<C::Error>> {
+        if !self.pending {
+            return Ok(());
+        }
+        self.pending = false;
+        self.client.execute_write(false).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use std::boxed::Box;
+
+    use bt_hci::param::{AddrKind, BdAddr, LeConnRole};
+
+    use super::*;
+    use crate::attribute::AttributeTable;
+    use crate::attribute_server::AttMiddleware;
+    use crate::connection_manager::{ConnectionManager, ConnectionStorage};
+    use crate::prelude::DefaultPacketPool;
+
+    fn connection() -> Connection<'static, DefaultPacketPool> {
+        let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 1]));
+        let mgr = Box::leak(Box::new(ConnectionManager::new(&mut storage[..], 23)));
+        unwrap!(mgr.connect(
+            ConnHandle::new(0),
+            AddrKind::RANDOM,
+            BdAddr::new([1; 6]),
+            LeConnRole::Peripheral,
+        ));
+        let Poll::Ready(conn) = mgr.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        conn
+    }
+
+    fn respond(conn: &Connection<'static, DefaultPacketPool>, request: &[u8], value: &[u8]) -> std::vec::Vec<u8> {
+        let mut packet = unwrap!(DefaultPacketPool::allocate());
+        packet.as_mut()[..request.len()].copy_from_slice(request);
+        let pdu = Pdu::new(packet, request.len());
+        let mut reply = unwrap!(process_respond(&pdu, conn, value));
+        // Skip the L2CAP header
+        unwrap!(reply.pdu.take()).as_ref()[4..].to_vec()
+    }
+
+    #[test]
+    fn retryable_responses() {
+        assert!(is_retryable(&[
+            att::ATT_ERROR_RSP,
+            att::ATT_WRITE_REQ,
+            0x01,
+            0x00,
+            0x11
+        ]));
+        assert!(!is_retryable(&[
+            att::ATT_ERROR_RSP,
+            att::ATT_WRITE_REQ,
+            0x01,
+            0x00,
+            0x0e
+        ]));
+        assert!(!is_retryable(&[att::ATT_WRITE_RSP]));
+    }
+
+    #[test]
+    fn read_respond_with() {
+        let conn = connection();
+        let value: [u8; 30] = core::array::from_fn(|i| i as u8);
+
+        // A read response is truncated to the ATT MTU
+        let rsp = respond(&conn, &[att::ATT_READ_REQ, 3, 0], &value);
+        assert_eq!(rsp[0], att::ATT_READ_RSP);
+        assert_eq!(&rsp[1..], &value[..22]);
+
+        // A read blob response continues from the offset
+        let rsp = respond(&conn, &[att::ATT_READ_BLOB_REQ, 3, 0, 22, 0], &value);
+        assert_eq!(rsp[0], att::ATT_READ_BLOB_RSP);
+        assert_eq!(&rsp[1..], &value[22..]);
+
+        let rsp = respond(&conn, &[att::ATT_READ_BLOB_REQ, 3, 0, 31, 0], &value);
+        assert_eq!(rsp, [att::ATT_ERROR_RSP, att::ATT_READ_BLOB_REQ, 3, 0, 0x07]);
+    }
+
+    #[test]
+    fn middleware_vetoes_outgoing() {
+        struct ReadOnly(AtomicUsize);
+        impl AttMiddleware for ReadOnly {
+            fn outgoing(&self, _: ConnHandle, pdu: &[u8]) -> bool {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                pdu[0] != att::ATT_WRITE_RSP
+            }
+        }
+
+        let middleware = ReadOnly(AtomicUsize::new(0));
+        let mut table: AttributeTable<'_, NoopRawMutex, 10> = AttributeTable::new();
+        let mut store = [0u8; 1];
+        let handle = {
+            let mut svc = table.add_service(crate::attribute::Service::new(0x180f_u16));
+            svc.add_characteristic(
+                0x2a19_u16,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                0u8,
+                &mut store,
+            )
+            .build()
+            .handle
+        };
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, 10, 0, 1> = AttributeServer::new(table);
+        server.set_middleware(&middleware);
+        let conn = connection();
+
+        let request = |pdu: &[u8]| {
+            let mut packet = unwrap!(DefaultPacketPool::allocate());
+            packet.as_mut()[..pdu.len()].copy_from_slice(pdu);
+            GattData::new(Pdu::new(packet, pdu.len()), conn.clone())
+        };
+        let [lo, hi] = handle.to_le_bytes();
+        let mut reply = unwrap!(process(&mut request(&[att::ATT_READ_REQ, lo, hi]), &server, Ok(())));
+        assert!(reply.pdu.take().is_some());
+        // The client still gets a response to its request
+        let mut reply = unwrap!(process(&mut request(&[att::ATT_WRITE_REQ, lo, hi, 1]), &server, Ok(())));
+        let pdu = unwrap!(reply.pdu.take());
+        assert_eq!(
+            &pdu.as_ref()[conn.header_len()..],
+            &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, lo, hi, 0x0e]
+        );
+        assert_eq!(middleware.0.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn match_response_to_request() {
+        assert!(is_response_to(att::ATT_READ_REQ, &[att::ATT_READ_RSP, 1, 2]));
+        assert!(is_response_to(
+            att::ATT_READ_REQ,
+            &[att::ATT_ERROR_RSP, att::ATT_READ_REQ, 0x01, 0x00, 0x02]
+        ));
+        assert!(!is_response_to(att::ATT_READ_REQ, &[att::ATT_WRITE_RSP]));
+        assert!(!is_response_to(
+            att::ATT_READ_REQ,
+            &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, 0x01, 0x00, 0x02]
+        ));
+        assert!(!is_response_to(att::ATT_READ_REQ, &[]));
+    }
+
+    #[test]
+    fn find_information_response() {
+        let pdu = [att::ATT_FIND_INFORMATION_RSP, 0x01, 4, 0, 0x02, 0x29, 5, 0, 0x01, 0x29];
+        let Ok(Att::Server(AttServer::Response(AttRsp::FindInformation { mut it }))) = Att::decode(&pdu) else {
+            panic!("expected find information response");
+        };
+        assert_eq!(
+            unwrap!(unwrap!(it.next())),
+            (4, CLIENT_CHARACTERISTIC_CONFIGURATION.into())
+        );
+        assert_eq!(unwrap!(unwrap!(it.next())), (5, Uuid::new_short(0x2901)));
+        assert!(it.next().is_none());
+    }
+
+    #[test]
+    fn prepare_write_codec() {
+        let value = [1, 2, 3];
+        let req = Att::Client(AttClient::Request(AttReq::PrepareWrite {
+            handle: 3,
+            offset: 18,
+            value: &value,
+        }));
+        let mut buf = [0; 8];
+        unwrap!(req.encode(&mut buf[..req.size()]));
+        assert_eq!(&buf[..req.size()], [att::ATT_PREPARE_WRITE_REQ, 3, 0, 18, 0, 1, 2, 3]);
+
+        // The response echoes the request
+        buf[0] = att::ATT_PREPARE_WRITE_RSP;
+        assert!(matches!(
+            Att::decode(&buf),
+            Ok(Att::Server(AttServer::Response(AttRsp::PrepareWrite {
+                handle: 3,
+                offset: 18,
+                value: [1, 2, 3],
+            })))
+        ));
+        assert!(matches!(
+            Att::decode(&[att::ATT_EXECUTE_WRITE_RSP]),
+            Ok(Att::Server(AttServer::Response(AttRsp::ExecuteWrite)))
+        ));
+    }
+
+    #[test]
+    fn notification_policies() {
+        let n = |b: u8| Notification::<4> {
+            handle: 3,
+            data: [b; 4],
+            len: 4,
+        };
+        for (policy, first) in [(NotificationPolicy::DropNewest, 0), (NotificationPolicy::DropOldest, 1)] {
+            let slot = NotificationSlot::<4>::new();
+            slot.subscription.set(Some((3, policy)));
+            for i in 0..=NOTIF_QSIZE as u8 {
+                embassy_futures::block_on(slot.deliver(&n(i)));
+            }
+            // Other handles are not queued
+            embassy_futures::block_on(slot.deliver(&Notification { handle: 4, ..n(9) }));
+            assert_eq!(slot.dropped.get(), 1);
+            assert_eq!(unwrap!(slot.queue.try_receive()), n(first));
+        }
+    }
+}
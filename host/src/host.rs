@@ -1,10 +1,10 @@
 //! BleHost
 //!
 //! The host module contains the main entry point for the TrouBLE host.
-use core::cell::RefCell;
-use core::future::poll_fn;
+use core::cell::{Cell, RefCell};
+use core::future::{poll_fn, Future};
 use core::mem::MaybeUninit;
-use core::task::Poll;
+use core::task::{Context, Poll, Waker};
 
 use bt_hci::cmd::controller_baseband::{
     HostBufferSize, HostNumberOfCompletedPackets, Reset, SetControllerToHostFlowControl, SetEventMask,
@@ -12,9 +12,9 @@ use bt_hci::cmd::controller_baseband::{
 };
 use bt_hci::cmd::info::ReadBdAddr;
 use bt_hci::cmd::le::{
-    LeConnUpdate, LeCreateConnCancel, LeEnableEncryption, LeLongTermKeyRequestReply, LeReadBufferSize,
-    LeReadFilterAcceptListSize, LeSetAdvEnable, LeSetEventMask, LeSetExtAdvEnable, LeSetExtScanEnable, LeSetRandomAddr,
-    LeSetScanEnable,
+    LeConnUpdate, LeCreateConnCancel, LeEnableEncryption, LeLongTermKeyRequestReply, LeRand, LeReadBufferSize,
+    LeReadFilterAcceptListSize, LeSetAdvEnable, LeSetAdvSetRandomAddr, LeSetEventMask, LeSetExtAdvEnable,
+    LeSetExtScanEnable, LeSetRandomAddr, LeSetScanEnable,
 };
 use bt_hci::cmd::link_control::Disconnect;
 use bt_hci::cmd::{AsyncCmd, SyncCmd};
@@ -27,16 +27,16 @@ use bt_hci::param::{
     LeConnRole, LeEventMask, Status,
 };
 use bt_hci::{ControllerToHostPacket, FromHciBytes, WriteHci};
-use embassy_futures::select::{select3, select4, Either3, Either4};
-use embassy_sync::once_lock::OnceLock;
-use embassy_sync::waitqueue::WakerRegistration;
+use embassy_futures::select::{select, select3, select4, Either, Either3, Either4};
+use embassy_sync::waitqueue::{MultiWakerRegistration, WakerRegistration};
 #[cfg(feature = "gatt")]
 use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant, Timer};
 use futures::pin_mut;
 
 use crate::att::{AttClient, AttServer};
 use crate::channel_manager::{ChannelManager, ChannelStorage};
+use crate::coex::{CoexActivity, CoexState, ScanCoex};
 use crate::command::CommandState;
 use crate::connection::ConnectionEvent;
 use crate::connection_manager::{ConnectionManager, ConnectionStorage, PacketGrant};
@@ -48,7 +48,10 @@ use crate::types::l2cap::{
     ConnParamUpdateReq, L2capHeader, L2capSignal, L2capSignalHeader, L2CAP_CID_ATT, L2CAP_CID_DYN_START,
     L2CAP_CID_LE_U_SECURITY_MANAGER, L2CAP_CID_LE_U_SIGNAL,
 };
-use crate::{att, Address, BleHostError, Error, PacketPool, Stack};
+use crate::{att, Address, BleHostError, Error, LocalIdentity, PacketPool, ProtocolLayer, Stack};
+
+/// Default interval between changes of private addresses, TGAP(private_addr_int) ([Vol 3] Part C, Appendix A).
+const PRIVATE_ADDRESS_INTERVAL: Duration = Duration::from_secs(15 * 60);
 
 /// A BLE Host.
 ///
@@ -58,9 +61,13 @@ use crate::{att, Address, BleHostError, Error, PacketPool, Stack};
 /// The host performs connection management, l2cap channel management, and
 /// multiplexes events and data across connections and l2cap channels.
 pub(crate) struct BleHost<'d, T, P: PacketPool> {
-    initialized: OnceLock<InitialState>,
+    initialized: Cell<Option<InitialState>>,
     metrics: RefCell<HostMetrics>,
+    last_rx: Cell<Option<Instant>>,
+    rx_packets: Cell<u32>,
+    completed_packets: Cell<u32>,
     pub(crate) address: Option<Address>,
+    pub(crate) identities: &'d [LocalIdentity],
     pub(crate) controller: T,
     pub(crate) connections: ConnectionManager<'d, P>,
     pub(crate) channels: ChannelManager<'d, P>,
@@ -70,24 +77,98 @@ pub(crate) struct BleHost<'d, T, P: PacketPool> {
     pub(crate) advertise_command_state: CommandState<bool>,
     pub(crate) connect_command_state: CommandState<bool>,
     pub(crate) scan_command_state: CommandState<bool>,
+    pub(crate) coex: CoexState<'d>,
+    pub(crate) controller_sleep: ControllerSleep<'d>,
+    pub(crate) strict: Cell<bool>,
+    power: RefCell<PowerState>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PowerMode {
+    Running,
+    Suspending,
+    Suspended,
+}
+
+struct PowerState {
+    mode: PowerMode,
+    /// Number of runner loops that have not stopped yet.
+    runners: u8,
+    /// The receive, transmit and control loops.
+    runner: MultiWakerRegistration<3>,
+    host: WakerRegistration,
+}
+
+/// Counts a runner loop as running until it is dropped.
+struct RunningGuard<'a, 'd, T, P: PacketPool> {
+    host: &'a BleHost<'d, T, P>,
+}
+
+impl<'a, 'd, T, P: PacketPool> RunningGuard<'a, 'd, T, P> {
+    fn new(host: &'a BleHost<'d, T, P>) -> Self {
+        host.power.borrow_mut().runners += 1;
+        Self { host }
+    }
+}
+
+impl<T, P: PacketPool> Drop for RunningGuard<'_, '_, T, P> {
+    fn drop(&mut self) {
+        let mut power = self.host.power.borrow_mut();
+        power.runners -= 1;
+        power.host.wake();
+    }
 }
 
 #[derive(Clone, Copy)]
 pub(crate) struct InitialState {
     acl_max: usize,
+    capabilities: ControllerCapabilities,
+}
+
+/// Optional controller capabilities, detected when the host starts.
+///
+/// Optional commands that the controller reports as unknown are skipped, and the capability is
+/// marked absent instead of failing the start of the host.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ControllerCapabilities {
+    /// The second page of the event mask is supported, which enables the Encryption Change v2 event.
+    pub event_mask_page2: bool,
+    /// Size of the filter accept list, if the controller reports it.
+    pub filter_accept_list_size: Option<u8>,
+    /// The host buffer size is supported, as required for controller to host flow control.
+    pub host_buffer_size: bool,
+    /// The controller reports its public device address.
+    pub read_bd_addr: bool,
+}
+
+/// Treat an unknown command as an absent optional capability.
+pub(crate) fn optional<T, E>(result: Result<T, impl Into<BleHostError<E>>>) -> Result<Option<T>, BleHostError<E>> {
+    match result.map_err(Into::into) {
+        Ok(value) => Ok(Some(value)),
+        Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CMD))) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Clone, Copy, Debug)]
 pub(crate) enum AdvHandleState {
     None,
-    Advertising(AdvHandle),
+    Advertising(AdvHandle, Option<LocalIdentity>),
     Terminated(AdvHandle),
 }
 
 pub(crate) struct AdvInnerState<'d> {
     handles: &'d mut [AdvHandleState],
     waker: WakerRegistration,
+    /// Advertising sets using a non-resolvable private address, as a bit per advertising handle.
+    private: u64,
+    /// Interval between changes of the private addresses.
+    private_interval: Duration,
+    /// Time of the next change of the private addresses.
+    rotate_at: Instant,
+    rotation_waker: WakerRegistration,
 }
 
 pub(crate) struct AdvState<'d> {
@@ -100,6 +181,10 @@ impl<'d> AdvState<'d> {
             state: RefCell::new(AdvInnerState {
                 handles,
                 waker: WakerRegistration::new(),
+                private: 0,
+                private_interval: PRIVATE_ADDRESS_INTERVAL,
+                rotate_at: Instant::MAX,
+                rotation_waker: WakerRegistration::new(),
             }),
         }
     }
@@ -109,21 +194,95 @@ impl<'d> AdvState<'d> {
         for entry in state.handles.iter_mut() {
             *entry = AdvHandleState::None;
         }
+        state.private = 0;
         state.waker.wake();
     }
 
-    // Terminate handle
-    pub(crate) fn terminate(&self, handle: AdvHandle) {
+    pub(crate) fn set_private_interval(&self, interval: Duration) {
+        self.state.borrow_mut().private_interval = interval;
+    }
+
+    /// Give an advertising set a private address right away, and change it periodically.
+    pub(crate) fn set_private(&self, handle: AdvHandle) {
         let mut state = self.state.borrow_mut();
+        state.private |= 1 << handle.as_raw();
+        state.rotate_at = Instant::now();
+        state.rotation_waker.wake();
+    }
+
+    /// Stop changing private addresses, once advertising is disabled.
+    pub(crate) fn clear_private(&self) {
+        self.state.borrow_mut().private = 0;
+    }
+
+    /// Wait until the private addresses should change, returning the advertising sets using them.
+    pub(crate) async fn rotation_due(&self) -> u64 {
+        loop {
+            let deadline = poll_fn(|cx| {
+                let mut state = self.state.borrow_mut();
+                state.rotation_waker.register(cx.waker());
+                if state.private == 0 {
+                    Poll::Pending
+                } else {
+                    Poll::Ready(state.rotate_at)
+                }
+            })
+            .await;
+            // New sets are due right away, don't keep sleeping until the previous deadline
+            let changed = poll_fn(|cx| {
+                let mut state = self.state.borrow_mut();
+                state.rotation_waker.register(cx.waker());
+                if state.rotate_at < deadline {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            });
+            select(Timer::at(deadline), changed).await;
+
+            // Advertising may have been stopped or restarted in the meantime
+            let mut state = self.state.borrow_mut();
+            let now = Instant::now();
+            if state.private != 0 && now >= state.rotate_at {
+                state.rotate_at = now + state.private_interval;
+                return state.private;
+            }
+        }
+    }
+
+    // Terminate handle, returning the local identity the set advertised with
+    pub(crate) fn terminate(&self, handle: AdvHandle) -> Option<LocalIdentity> {
+        let mut state = self.state.borrow_mut();
+        let mut identity = None;
         for entry in state.handles.iter_mut() {
             match entry {
-                AdvHandleState::Advertising(h) if *h == handle => {
+                AdvHandleState::Advertising(h, i) if *h == handle => {
+                    identity = *i;
                     *entry = AdvHandleState::Terminated(handle);
                 }
                 _ => {}
             }
         }
+        state.private &= !(1 << handle.as_raw());
         state.waker.wake();
+        identity
+    }
+
+    /// Record the local identity an advertising set uses.
+    pub(crate) fn set_identity(&self, handle: AdvHandle, identity: LocalIdentity) {
+        let mut state = self.state.borrow_mut();
+        for entry in state.handles.iter_mut() {
+            match entry {
+                AdvHandleState::Advertising(h, i) if *h == handle => {
+                    *i = Some(identity);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub(crate) fn register(&self, waker: &Waker) {
+        self.state.borrow_mut().waker.register(waker);
     }
 
     pub(crate) fn len(&self) -> usize {
@@ -139,7 +298,7 @@ impl<'d> AdvState<'d> {
         }
 
         for (idx, entry) in sets.iter().enumerate() {
-            state.handles[idx] = AdvHandleState::Advertising(entry.adv_handle);
+            state.handles[idx] = AdvHandleState::Advertising(entry.adv_handle, None);
         }
     }
 
@@ -179,6 +338,65 @@ pub struct HostMetrics {
     pub disconnect_events: u32,
     /// How many errors processing received data.
     pub rx_errors: u32,
+    /// How many HCI packets have been dispatched by the receive loop.
+    #[cfg(feature = "runner-metrics")]
+    pub rx_dispatched: u32,
+    /// Total time spent dispatching HCI packets, from reading them from the controller to handling them.
+    #[cfg(feature = "runner-metrics")]
+    pub rx_dispatch_time: Duration,
+    /// Longest time spent dispatching a single HCI packet.
+    #[cfg(feature = "runner-metrics")]
+    pub rx_dispatch_max: Duration,
+    /// Number of PDUs waiting in the transmit queues, when the metrics were read.
+    #[cfg(feature = "runner-metrics")]
+    pub tx_queue_depth: usize,
+    /// Highest number of PDUs waiting in the transmit queues.
+    #[cfg(feature = "runner-metrics")]
+    pub tx_queue_high_water: usize,
+    /// Total time the transmit loop spent waiting for controller buffers and writing ACL data.
+    #[cfg(feature = "runner-metrics")]
+    pub tx_controller_wait: Duration,
+}
+
+#[cfg(feature = "runner-metrics")]
+impl HostMetrics {
+    fn dispatched(&mut self, elapsed: Duration) {
+        self.rx_dispatched = self.rx_dispatched.wrapping_add(1);
+        self.rx_dispatch_time += elapsed;
+        self.rx_dispatch_max = self.rx_dispatch_max.max(elapsed);
+    }
+
+    fn transmitted(&mut self, queue_depth: usize, controller_wait: Duration) {
+        self.tx_queue_high_water = self.tx_queue_high_water.max(queue_depth);
+        self.tx_controller_wait += controller_wait;
+    }
+}
+
+/// Runtime memory usage of the host resources.
+///
+/// High-water marks are kept for the lifetime of the stack and can be compared against the
+/// const generics of [`HostResources`](crate::HostResources) and the packet pool size.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MemoryStats {
+    /// Number of connection slots available.
+    pub connections_max: usize,
+    /// Number of connection slots currently in use.
+    pub connections_in_use: usize,
+    /// Highest number of connection slots in use at the same time.
+    pub connections_high_water: usize,
+    /// Number of L2CAP channel slots available.
+    pub channels_max: usize,
+    /// Number of L2CAP channel slots currently in use.
+    pub channels_in_use: usize,
+    /// Highest number of L2CAP channel slots in use at the same time.
+    pub channels_high_water: usize,
+    /// Number of packets in the packet pool.
+    pub pool_capacity: usize,
+    /// Number of packets currently held by L2CAP channels for inbound data.
+    pub rx_packets_in_use: usize,
+    /// Highest number of packets held by L2CAP channels for inbound data at the same time.
+    pub rx_packets_high_water: usize,
 }
 
 impl<'d, T, P> BleHost<'d, T, P>
@@ -195,21 +413,110 @@ where
         controller: T,
         connections: &'d mut [ConnectionStorage<P::Packet>],
         channels: &'d mut [ChannelStorage<P::Packet>],
+        rx_quota: u16,
         advertise_handles: &'d mut [AdvHandleState],
     ) -> Self {
         Self {
             address: None,
-            initialized: OnceLock::new(),
+            identities: &[],
+            initialized: Cell::new(None),
             metrics: RefCell::new(HostMetrics::default()),
+            last_rx: Cell::new(None),
+            rx_packets: Cell::new(0),
+            completed_packets: Cell::new(0),
             controller,
             connections: ConnectionManager::new(connections, P::MTU as u16 - 4),
-            channels: ChannelManager::new(channels),
+            channels: ChannelManager::new(channels, rx_quota),
             #[cfg(feature = "gatt")]
             att_client: Channel::new(),
             advertise_state: AdvState::new(advertise_handles),
             advertise_command_state: CommandState::new(),
             scan_command_state: CommandState::new(),
             connect_command_state: CommandState::new(),
+            coex: CoexState::new(),
+            controller_sleep: ControllerSleep::new(),
+            strict: Cell::new(false),
+            power: RefCell::new(PowerState {
+                mode: PowerMode::Running,
+                runners: 0,
+                runner: MultiWakerRegistration::new(),
+                host: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Request the runners to stop, so the controller can be powered down.
+    pub(crate) fn request_suspend(&self) {
+        let mut power = self.power.borrow_mut();
+        power.mode = PowerMode::Suspending;
+        power.runner.wake();
+    }
+
+    /// Run a runner loop until it fails or the stack is suspended.
+    async fn until_suspended<E>(&self, run: impl Future<Output = Result<(), E>>) -> Result<(), E> {
+        let _running = RunningGuard::new(self);
+        match select(run, poll_fn(|cx| self.poll_suspend(cx))).await {
+            Either::First(result) => result,
+            Either::Second(_) => Ok(()),
+        }
+    }
+
+    /// Poll if the runners should stop.
+    fn poll_suspend(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut power = self.power.borrow_mut();
+        if power.mode == PowerMode::Suspending {
+            Poll::Ready(())
+        } else {
+            power.runner.register(cx.waker());
+            Poll::Pending
+        }
+    }
+
+    /// Wait until the runner has initialized the controller.
+    async fn initialized(&self) -> InitialState {
+        poll_fn(|cx| match self.initialized.get() {
+            Some(state) => Poll::Ready(state),
+            None => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Mark the controller as initialized after a suspend.
+    fn set_running(&self) {
+        let mut power = self.power.borrow_mut();
+        power.mode = PowerMode::Running;
+        power.host.wake();
+    }
+
+    /// Poll until all runners have stopped.
+    ///
+    /// The controller must be initialized again once the stack resumes, as it may lose its state while powered down.
+    pub(crate) fn poll_suspended(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut power = self.power.borrow_mut();
+        power.host.register(cx.waker());
+        match power.mode {
+            PowerMode::Suspending if power.runners == 0 => {
+                power.mode = PowerMode::Suspended;
+                self.initialized.set(None);
+                info!("[host] suspended");
+                Poll::Ready(())
+            }
+            PowerMode::Suspended => Poll::Ready(()),
+            _ => Poll::Pending,
+        }
+    }
+
+    /// Poll until the controller is available, which it is not while suspended.
+    pub(crate) fn poll_resumed(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut power = self.power.borrow_mut();
+        power.host.register(cx.waker());
+        if power.mode == PowerMode::Suspended {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
         }
     }
 
@@ -219,18 +526,81 @@ where
         C: SyncCmd,
         T: ControllerCmdSync<C>,
     {
-        let _ = self.initialized.get().await;
+        self.initialized().await;
+        poll_fn(|cx| self.poll_resumed(cx)).await;
+        self.controller_sleep.wake();
         let ret = cmd.exec(&self.controller).await?;
         Ok(ret)
     }
 
+    /// Generate a new non-resolvable private address with the controller random number generator.
+    pub(crate) async fn private_address(&self) -> Result<Address, BleHostError<T::Error>>
+    where
+        T: ControllerCmdSync<LeRand>,
+    {
+        let random = self.command(LeRand::new()).await?;
+        let mut val = [0; 6];
+        val.copy_from_slice(&random[..6]);
+        let address = Address::non_resolvable_private(val);
+        // A private address must not match the public address.
+        if self.address.is_some_and(|a| a.addr == address.addr) {
+            val[0] ^= 1;
+            return Ok(Address::non_resolvable_private(val));
+        }
+        Ok(address)
+    }
+
+    /// Change the private addresses of advertising sets whenever they are due.
+    pub(crate) async fn rotate_private_addresses(&self)
+    where
+        T: ControllerCmdSync<LeRand>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>,
+    {
+        loop {
+            let sets = self.advertise_state.rotation_due().await;
+            trace!("[host] changing private advertising addresses");
+            for handle in (0..64u8).filter(|h| sets & (1 << h) != 0).map(AdvHandle::new) {
+                match self.rotate_private_address(handle).await {
+                    Ok(()) => {}
+                    Err(BleHostError::BleHost(e)) => {
+                        warn!("[host] error changing private address of advertising set: {:?}", e);
+                    }
+                    Err(BleHostError::Controller(_)) => {
+                        warn!("[host] controller error changing private address of advertising set");
+                    }
+                }
+            }
+        }
+    }
+
+    async fn rotate_private_address(&self, handle: AdvHandle) -> Result<(), BleHostError<T::Error>>
+    where
+        T: ControllerCmdSync<LeRand>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>,
+    {
+        let address = self.private_address().await?;
+        self.command(LeSetAdvSetRandomAddr::new(handle, address.addr)).await?;
+        // The controller may only use the new address once the set is enabled again
+        let set = [AdvSet {
+            adv_handle: handle,
+            duration: bt_hci::param::Duration::from_u16(0),
+            max_ext_adv_events: 0,
+        }];
+        self.command(LeSetExtAdvEnable::new(true, &set)).await?;
+        Ok(())
+    }
+
     /// Run an async HCI command where the response will generate an event later.
     pub(crate) async fn async_command<C>(&self, cmd: C) -> Result<(), BleHostError<T::Error>>
     where
         C: AsyncCmd,
         T: ControllerCmdAsync<C>,
     {
-        let _ = self.initialized.get().await;
+        self.initialized().await;
+        poll_fn(|cx| self.poll_resumed(cx)).await;
+        self.controller_sleep.wake();
         cmd.exec(&self.controller).await?;
         Ok(())
     }
@@ -246,20 +616,15 @@ where
         match status.to_result() {
             Ok(_) => {
                 if let Err(err) = self.connections.connect(handle, peer_addr_kind, peer_addr, role) {
-                    warn!("Error establishing connection: {:?}", err);
+                    warn!(conn = handle, "[host] error establishing connection: {:?}", err);
                     return false;
                 } else {
                     #[cfg(feature = "defmt")]
-                    debug!(
-                        "[host] connection with handle {:?} established to {:02x}",
-                        handle, peer_addr
-                    );
+                    debug!(conn = handle, "[host] connection established to {:02x}", peer_addr);
 
                     #[cfg(feature = "log")]
-                    debug!(
-                        "[host] connection with handle {:?} established to {:02x?}",
-                        handle, peer_addr
-                    );
+                    debug!(conn = handle, "[host] connection established to {:02x?}", peer_addr);
+                    self.coex.notify(CoexActivity::Connected(handle));
                     let mut m = self.metrics.borrow_mut();
                     m.connect_events = m.connect_events.wrapping_add(1);
                 }
@@ -279,6 +644,15 @@ where
         true
     }
 
+    /// Check peer behaviour that is only tolerated outside of the strict mode.
+    fn check_peer(&self, handle: ConnHandle, valid: bool, layer: ProtocolLayer, violation: &str) -> Result<(), Error> {
+        if !valid && self.strict.get() {
+            error!(conn = handle, "[host] protocol violation: {}", violation);
+            return Err(Error::ProtocolViolation(layer));
+        }
+        Ok(())
+    }
+
     fn handle_acl(&self, acl: AclPacket<'_>) -> Result<(), Error> {
         self.connections.received(acl.handle())?;
         let handle = acl.handle();
@@ -291,18 +665,32 @@ where
                     && !(&[L2CAP_CID_LE_U_SIGNAL, L2CAP_CID_ATT, L2CAP_CID_LE_U_SECURITY_MANAGER]
                         .contains(&header.channel))
                 {
-                    warn!("[host] unsupported l2cap channel id {}", header.channel);
+                    self.check_peer(handle, false, ProtocolLayer::L2cap, "unsupported l2cap channel")?;
+                    warn!(conn = handle, "[host] unsupported l2cap channel id {}", header.channel);
                     return Err(Error::NotSupported);
                 }
+                self.check_peer(
+                    handle,
+                    data.len() <= header.length as usize,
+                    ProtocolLayer::L2cap,
+                    "l2cap fragment exceeds length",
+                )?;
 
                 // Avoids using the packet buffer for signalling packets
                 if header.channel == L2CAP_CID_LE_U_SIGNAL {
+                    self.check_peer(
+                        handle,
+                        data.len() == header.length as usize,
+                        ProtocolLayer::L2cap,
+                        "fragmented signal",
+                    )?;
                     assert!(data.len() == header.length as usize);
                     self.channels.signal(acl.handle(), data)?;
                     return Ok(());
                 }
 
                 trace!(
+                    conn = handle,
                     "[host] inbound l2cap header channel = {}, fragment len = {}, total = {}",
                     header.channel,
                     data.len(),
@@ -322,8 +710,12 @@ where
                                 // Init the new assembly assuming the length of the SDU.
                                 let (first, payload) = data.split_at(2);
                                 let len: u16 = u16::from_le_bytes([first[0], first[1]]);
+                                self.channels.check_sdu_len(header.channel, len)?;
                                 let Some(packet) = P::allocate() else {
-                                    warn!("[host] no memory for packets on channel {}", header.channel);
+                                    warn!(
+                                        conn = handle,
+                                        "[host] no memory for packets on channel {}", header.channel
+                                    );
                                     return Err(Error::OutOfMemory);
                                 };
                                 p.init(header.channel, len, packet)?;
@@ -342,7 +734,10 @@ where
                     }
 
                     let Some(packet) = P::allocate() else {
-                        warn!("[host] no memory for packets on channel {}", header.channel);
+                        warn!(
+                            conn = handle,
+                            "[host] no memory for packets on channel {}", header.channel
+                        );
                         return Err(Error::OutOfMemory);
                     };
                     self.connections.reassembly(acl.handle(), |p| {
@@ -368,9 +763,13 @@ where
                             if !p.in_progress() {
                                 let (first, payload) = data.split_at(2);
                                 let len: u16 = u16::from_le_bytes([first[0], first[1]]);
+                                self.channels.check_sdu_len(header.channel, len)?;
 
                                 let Some(packet) = P::allocate() else {
-                                    warn!("[host] no memory for packets on channel {}", header.channel);
+                                    warn!(
+                                        conn = handle,
+                                        "[host] no memory for packets on channel {}", header.channel
+                                    );
                                     return Err(Error::OutOfMemory);
                                 };
                                 p.init(header.channel, len, packet)?;
@@ -389,7 +788,10 @@ where
                         (state, pdu)
                     } else {
                         let Some(packet) = P::allocate() else {
-                            warn!("[host] no memory for packets on channel {}", header.channel);
+                            warn!(
+                                conn = handle,
+                                "[host] no memory for packets on channel {}", header.channel
+                            );
                             return Err(Error::OutOfMemory);
                         };
                         let result = self.connections.reassembly(acl.handle(), |p| {
@@ -405,14 +807,14 @@ where
             }
             // Next (potentially last) in a fragment
             AclPacketBoundary::Continuing => {
-                trace!("[host] inbound l2cap len = {}", acl.data().len(),);
+                trace!(conn = handle, "[host] inbound l2cap len = {}", acl.data().len());
                 // Get the existing fragment
                 if let Some((header, p)) = self.connections.reassembly(acl.handle(), |p| {
                     if !p.in_progress() {
                         warn!(
-                            "[host] unexpected continuation fragment of length {} for handle {}: {:?}",
+                            conn = handle,
+                            "[host] unexpected continuation fragment of length {}: {:?}",
                             acl.data().len(),
-                            acl.handle().raw(),
                             p
                         );
                         return Err(Error::InvalidState);
@@ -426,16 +828,35 @@ where
                 }
             }
             other => {
-                warn!("Unexpected boundary flag: {:?}!", other);
+                warn!(conn = handle, "[host] unexpected boundary flag: {:?}", other);
                 return Err(Error::NotSupported);
             }
         };
 
         match header.channel {
             L2CAP_CID_ATT => {
+                if self.connections.att_timed_out_handle(acl.handle()) {
+                    warn!(conn = handle, "[host] dropping ATT PDU after transaction timeout");
+                    return Ok(());
+                }
                 // Handle ATT MTU exchange here since it doesn't strictly require
                 // gatt to be enabled.
                 let a = att::Att::decode(pdu.as_ref());
+                match a {
+                    Ok(att::Att::Client(AttClient::Request(_))) => self.check_peer(
+                        handle,
+                        self.connections.att_request_received(handle),
+                        ProtocolLayer::Att,
+                        "ATT request while another is pending",
+                    )?,
+                    Ok(att::Att::Server(AttServer::Response(_))) => self.check_peer(
+                        handle,
+                        self.connections.att_response_received(handle),
+                        ProtocolLayer::Att,
+                        "ATT response without a request",
+                    )?,
+                    _ => {}
+                }
                 if let Ok(att::Att::Client(AttClient::Request(att::AttReq::ExchangeMtu { mtu }))) = a {
                     let mtu = self.connections.exchange_att_mtu(acl.handle(), mtu);
 
@@ -450,16 +871,19 @@ where
                     w.write_hci(&l2cap)?;
                     w.write(rsp)?;
 
-                    info!("[host] agreed att MTU of {}", mtu);
+                    info!(conn = handle, "[host] agreed att MTU of {}", mtu);
                     let len = w.len();
                     self.connections.try_outbound(acl.handle(), Pdu::new(packet, len))?;
                 } else if let Ok(att::Att::Server(AttServer::Response(att::AttRsp::ExchangeMtu { mtu }))) = a {
-                    info!("[host] remote agreed att MTU of {}", mtu);
+                    info!(conn = handle, "[host] remote agreed att MTU of {}", mtu);
                     self.connections.exchange_att_mtu(acl.handle(), mtu);
                 } else {
                     #[cfg(feature = "gatt")]
                     match a {
-                        Ok(att::Att::Client(_)) => {
+                        Ok(att::Att::Client(client)) => {
+                            if let AttClient::Confirmation(_) = client {
+                                self.connections.indication_confirmed(acl.handle());
+                            }
                             self.connections.post_gatt(acl.handle(), pdu)?;
                         }
                         Ok(att::Att::Server(_)) => {
@@ -468,7 +892,8 @@ where
                             }
                         }
                         Err(e) => {
-                            warn!("Error decoding attribute payload: {:?}", e);
+                            self.check_peer(handle, false, ProtocolLayer::Att, "undecodable ATT PDU")?;
+                            warn!(conn = handle, "[host] error decoding attribute payload: {:?}", e);
                         }
                     }
                     #[cfg(not(feature = "gatt"))]
@@ -479,21 +904,27 @@ where
                 panic!("le signalling channel was fragmented, impossible!");
             }
             L2CAP_CID_LE_U_SECURITY_MANAGER => {
+                #[cfg(feature = "security")]
+                self.check_peer(
+                    handle,
+                    !self.connections.security_manager.after_pairing(handle, pdu.as_ref()),
+                    ProtocolLayer::Smp,
+                    "pairing PDU after pairing completed",
+                )?;
                 self.connections.handle_security_channel(acl.handle(), pdu)?;
             }
             other if other >= L2CAP_CID_DYN_START => match self.channels.dispatch(header.channel, pdu) {
                 Ok(_) => {}
                 Err(e) => {
-                    warn!("Error dispatching l2cap packet to channel: {:?}", e);
+                    warn!(
+                        conn = handle,
+                        "[host] error dispatching l2cap packet to channel: {:?}", e
+                    );
                     return Err(e);
                 }
             },
             chan => {
-                debug!(
-                    "[host] conn {:?} attempted to use unsupported l2cap channel {}, ignoring",
-                    acl.handle(),
-                    chan
-                );
+                debug!(conn = handle, "[host] unsupported l2cap channel {}, ignoring", chan);
                 return Ok(());
             }
         }
@@ -543,15 +974,36 @@ where
         handle: ConnHandle,
         len: u16,
         n_packets: u16,
+    ) -> Result<L2capSender<'_, 'd, T, P::Packet>, BleHostError<T::Error>> {
+        self.l2cap_with_priority(handle, len, n_packets, false).await
+    }
+
+    // Same as l2cap, but a priority request is granted link credits ahead of other requests.
+    pub(crate) async fn l2cap_with_priority(
+        &self,
+        handle: ConnHandle,
+        len: u16,
+        n_packets: u16,
+        priority: bool,
     ) -> Result<L2capSender<'_, 'd, T, P::Packet>, BleHostError<T::Error>> {
         // Take into account l2cap header.
-        let acl_max = self.initialized.get().await.acl_max as u16;
+        let acl_max = self.initialized().await.acl_max as u16;
         let len = len + (4 * n_packets);
         let n_acl = len.div_ceil(acl_max);
-        let grant = poll_fn(|cx| self.connections.poll_request_to_send(handle, n_acl as usize, Some(cx))).await?;
-        trace!("[host] granted send packets = {}, len = {}", n_packets, len);
+        let grant = poll_fn(|cx| {
+            self.connections
+                .poll_request_to_send(handle, n_acl as usize, priority, Some(cx))
+        })
+        .await?;
+        trace!(
+            conn = handle,
+            "[host] granted send packets = {}, len = {}",
+            n_packets,
+            len
+        );
         Ok(L2capSender {
             controller: &self.controller,
+            sleep: &self.controller_sleep,
             handle,
             grant,
             fragment_size: acl_max,
@@ -568,10 +1020,13 @@ where
         len: u16,
         n_packets: u16,
     ) -> Result<L2capSender<'_, 'd, T, P::Packet>, BleHostError<T::Error>> {
-        let acl_max = self.initialized.try_get().map(|i| i.acl_max).unwrap_or(27) as u16;
+        let acl_max = self.initialized.get().map(|i| i.acl_max).unwrap_or(27) as u16;
         let len = len + (4 * n_packets);
         let n_acl = len.div_ceil(acl_max);
-        let grant = match self.connections.poll_request_to_send(handle, n_acl as usize, None) {
+        let grant = match self
+            .connections
+            .poll_request_to_send(handle, n_acl as usize, false, None)
+        {
             Poll::Ready(res) => res?,
             Poll::Pending => {
                 return Err(Error::Busy.into());
@@ -579,6 +1034,7 @@ where
         };
         Ok(L2capSender {
             controller: &self.controller,
+            sleep: &self.controller_sleep,
             handle,
             grant,
             fragment_size: acl_max,
@@ -595,16 +1051,99 @@ where
 
     /// Read current host metrics
     pub(crate) fn metrics<F: FnOnce(&HostMetrics) -> R, R>(&self, f: F) -> R {
+        #[cfg(feature = "runner-metrics")]
+        {
+            self.metrics.borrow_mut().tx_queue_depth = self.connections.outbound_len();
+        }
         let m = self.metrics.borrow();
         f(&m)
     }
 
+    /// Optional capabilities of the controller, once the host is initialized.
+    pub(crate) fn capabilities(&self) -> Option<ControllerCapabilities> {
+        self.initialized.get().map(|i| i.capabilities)
+    }
+
+    /// Read the liveness of the host
+    pub(crate) fn liveness(&self) -> Liveness {
+        Liveness {
+            last_rx: self.last_rx.get(),
+            rx_packets: self.rx_packets.get(),
+            completed_packets: self.completed_packets.get(),
+            packets_in_flight: self.connections.packets_in_flight(),
+            outbound_pending: self.connections.outbound_len(),
+        }
+    }
+
+    /// Read current memory usage
+    pub(crate) fn memory_stats(&self) -> MemoryStats {
+        let (connections_in_use, connections_high_water, connections_max) = self.connections.usage();
+        let (channels_in_use, channels_high_water, channels_max) = self.channels.usage();
+        let (rx_packets_in_use, rx_packets_high_water) = self.channels.rx_packet_usage();
+        MemoryStats {
+            connections_max,
+            connections_in_use,
+            connections_high_water,
+            channels_max,
+            channels_in_use,
+            channels_high_water,
+            pool_capacity: P::capacity(),
+            rx_packets_in_use,
+            rx_packets_high_water,
+        }
+    }
+
+    /// Snapshot of the host state machines
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn debug_state<const CONNS: usize, const CHANNELS: usize>(
+        &self,
+    ) -> crate::debug_state::DebugState<CONNS, CHANNELS> {
+        let mut connections = heapless::Vec::new();
+        let mut truncated_connections = 0;
+        self.connections.debug_state(|c| {
+            if connections.push(c).is_err() {
+                truncated_connections += 1;
+            }
+        });
+        let mut channels = heapless::Vec::new();
+        let mut truncated_channels = 0;
+        self.channels.debug_state(|c| {
+            if channels.push(c).is_err() {
+                truncated_channels += 1;
+            }
+        });
+        crate::debug_state::DebugState {
+            connections,
+            truncated_connections,
+            channels,
+            truncated_channels,
+            #[cfg(feature = "security")]
+            pairing: self.connections.security_manager.debug_state(),
+            outbound_pending: self.connections.outbound_len(),
+            packets_in_flight: self.connections.packets_in_flight(),
+        }
+    }
+
     /// Log status information of the host
     pub(crate) fn log_status(&self, verbose: bool) {
         let m = self.metrics.borrow();
         debug!("[host] connect events: {}", m.connect_events);
         debug!("[host] disconnect events: {}", m.disconnect_events);
         debug!("[host] rx errors: {}", m.rx_errors);
+        #[cfg(feature = "runner-metrics")]
+        {
+            debug!(
+                "[host] rx dispatched: {}, dispatch time: {} us, max: {} us",
+                m.rx_dispatched,
+                m.rx_dispatch_time.as_micros(),
+                m.rx_dispatch_max.as_micros()
+            );
+            debug!(
+                "[host] tx queue high water: {}, controller wait: {} us",
+                m.tx_queue_high_water,
+                m.tx_controller_wait.as_micros()
+            );
+        }
         self.connections.log_status(verbose);
         self.channels.log_status(verbose);
     }
@@ -636,17 +1175,154 @@ pub struct TxRunner<'d, C, P: PacketPool> {
 pub trait EventHandler {
     /// Handle vendor events
     fn on_vendor(&self, vendor: &Vendor) {}
+    /// A connection was closed, with the reason reported by the controller.
+    ///
+    /// Called for every connection, including those the application no longer holds, e.g. to raise a
+    /// link loss alert.
+    fn on_disconnected(&self, handle: ConnHandle, reason: Status) {}
     /// Handle advertising reports
     #[cfg(feature = "scan")]
     fn on_adv_reports(&self, reports: bt_hci::param::LeAdvReportsIter) {}
     /// Handle extended advertising reports
+    ///
+    /// Each report contains the primary and secondary PHY it was received on.
     #[cfg(feature = "scan")]
     fn on_ext_adv_reports(&self, reports: bt_hci::param::LeExtAdvReportsIter) {}
+    /// Handle advertising reports with the time they were received from the controller.
+    ///
+    /// The data of each report is the advertising payload as received, e.g. to decode sensor data and estimate
+    /// the advertising rate of a peer. Calls [`EventHandler::on_adv_reports`] by default.
+    #[cfg(feature = "scan")]
+    fn on_adv_reports_at(&self, reports: bt_hci::param::LeAdvReportsIter, received: Instant) {
+        self.on_adv_reports(reports)
+    }
+    /// Handle extended advertising reports with the time they were received from the controller.
+    ///
+    /// Calls [`EventHandler::on_ext_adv_reports`] by default.
+    #[cfg(feature = "scan")]
+    fn on_ext_adv_reports_at(&self, reports: bt_hci::param::LeExtAdvReportsIter, received: Instant) {
+        self.on_ext_adv_reports(reports)
+    }
+}
+
+/// Power control of the transport to the controller, set with `Stack::set_controller_power`.
+///
+/// Transports with sleep signaling, such as H5 or vendor UART sleep protocols, can suspend the link while
+/// the host is idle. Waking up when the controller has an event to report is left to the transport.
+pub trait ControllerPower {
+    /// Nothing is waiting to be sent to the controller.
+    ///
+    /// `next_event` is the next known connection event anchor, before which no connection activity is
+    /// expected. It is `None` when no anchor is known, see `Stack::set_connection_anchor`.
+    fn sleep(&self, next_event: Option<Instant>) {}
+    /// A command or data is about to be written to the controller after [`ControllerPower::sleep`] was called.
+    fn wake(&self) {}
+}
+
+/// Sleep state of the transport, so that it is woken before anything is written to the controller.
+pub(crate) struct ControllerSleep<'d> {
+    power: Cell<Option<&'d dyn ControllerPower>>,
+    asleep: Cell<bool>,
+}
+
+impl<'d> ControllerSleep<'d> {
+    pub(crate) fn new() -> Self {
+        Self {
+            power: Cell::new(None),
+            asleep: Cell::new(false),
+        }
+    }
+
+    pub(crate) fn set_power(&self, power: &'d dyn ControllerPower) {
+        self.power.set(Some(power));
+    }
+
+    fn sleep(&self, next_event: Option<Instant>) {
+        if let Some(power) = self.power.get() {
+            if !self.asleep.replace(true) {
+                power.sleep(next_event);
+            }
+        }
+    }
+
+    /// Wake the transport if it was put to sleep, called before every write to the controller.
+    pub(crate) fn wake(&self) {
+        if self.asleep.replace(false) {
+            if let Some(power) = self.power.get() {
+                power.wake();
+            }
+        }
+    }
+}
+
+/// Liveness of the host, passed to a [`HealthCheck`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Liveness {
+    /// Time the last packet was received from the controller, if any.
+    pub last_rx: Option<Instant>,
+    /// Number of packets received from the controller, wrapping around.
+    pub rx_packets: u32,
+    /// Number of ACL packets reported as completed by the controller, wrapping around.
+    pub completed_packets: u32,
+    /// Number of ACL packets sent to the controller and not yet reported as completed.
+    pub packets_in_flight: usize,
+    /// Number of PDUs waiting to be sent to the controller.
+    pub outbound_pending: usize,
+}
+
+impl Liveness {
+    /// Check if the host is waiting on the controller, but nothing was received from it for longer than `timeout`.
+    ///
+    /// A controller that is working reports completed packets, so this indicates a controller lockup.
+    /// An idle host is never stalled.
+    pub fn is_stalled(&self, timeout: Duration) -> bool {
+        if self.packets_in_flight == 0 && self.outbound_pending == 0 {
+            return false;
+        }
+        match self.last_rx {
+            Some(last_rx) => last_rx.elapsed() > timeout,
+            None => true,
+        }
+    }
+}
+
+/// Liveness hook called by the runner on every iteration of its receive and transmit loops.
+///
+/// Used to feed an external watchdog only while the stack is making progress, so that a controller
+/// that silently stops responding resets the device. The progress counters of [`Liveness`] tell whether
+/// the controller did anything since the previous call. The hook is not called while the runner is blocked
+/// or not running.
+pub trait HealthCheck {
+    /// Longest time between two calls to [`HealthCheck::check`] while there is nothing to send.
+    fn interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+    /// Called with the current liveness of the host, e.g. to feed a watchdog unless
+    /// [`Liveness::is_stalled`].
+    fn check(&self, liveness: &Liveness);
 }
 
 struct DummyHandler;
 impl EventHandler for DummyHandler {}
 
+/// Configuration of [`Runner::run_with_config`].
+pub struct RunnerConfig<'a> {
+    /// Handler of controller events not handled by the host, such as vendor events.
+    pub event_handler: &'a dyn EventHandler,
+    /// Liveness hook, called from the receive and transmit loops.
+    pub health: Option<&'a dyn HealthCheck>,
+}
+
+impl Default for RunnerConfig<'_> {
+    fn default() -> Self {
+        Self {
+            event_handler: &DummyHandler,
+            health: None,
+        }
+    }
+}
+
 impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
     pub(crate) fn new(stack: &'d Stack<'d, C, P>) -> Self {
         Self {
@@ -662,6 +1338,8 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
     }
 
     /// Run the host.
+    ///
+    /// Returns `Ok(())` once the stack is suspended with `Stack::suspend`. Run it again to resume.
     pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>>
     where
         C: ControllerCmdSync<Disconnect>
@@ -691,6 +1369,37 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
 
     /// Run the host with a vendor event handler for custom events.
     pub async fn run_with_handler<E: EventHandler>(&mut self, event_handler: &E) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<Disconnect>
+            + ControllerCmdSync<SetEventMask>
+            + ControllerCmdSync<SetEventMaskPage2>
+            + ControllerCmdSync<LeSetEventMask>
+            + ControllerCmdSync<LeSetRandomAddr>
+            + ControllerCmdSync<LeReadFilterAcceptListSize>
+            + ControllerCmdSync<HostBufferSize>
+            + ControllerCmdAsync<LeConnUpdate>
+            + ControllerCmdSync<SetControllerToHostFlowControl>
+            + for<'t> ControllerCmdSync<LeSetAdvEnable>
+            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
+            + for<'t> ControllerCmdSync<HostNumberOfCompletedPackets<'t>>
+            + ControllerCmdSync<LeSetScanEnable>
+            + ControllerCmdSync<LeSetExtScanEnable>
+            + ControllerCmdSync<Reset>
+            + ControllerCmdSync<LeCreateConnCancel>
+            + ControllerCmdSync<LeReadBufferSize>
+            + ControllerCmdSync<LeLongTermKeyRequestReply>
+            + ControllerCmdAsync<LeEnableEncryption>
+            + ControllerCmdSync<ReadBdAddr>,
+    {
+        self.run_with_config(RunnerConfig {
+            event_handler,
+            ..Default::default()
+        })
+        .await
+    }
+
+    /// Run the host with the given configuration.
+    pub async fn run_with_config(&mut self, config: RunnerConfig<'_>) -> Result<(), BleHostError<C::Error>>
     where
         C: ControllerCmdSync<Disconnect>
             + ControllerCmdSync<SetEventMask>
@@ -714,8 +1423,8 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
             + ControllerCmdSync<ReadBdAddr>,
     {
         let control_fut = self.control.run();
-        let rx_fut = self.rx.run_with_handler(event_handler);
-        let tx_fut = self.tx.run();
+        let rx_fut = self.rx.run_inner(config.event_handler, config.health);
+        let tx_fut = self.tx.run_inner(config.health);
         pin_mut!(control_fut, rx_fut, tx_fut);
         match select3(&mut tx_fut, &mut rx_fut, &mut control_fut).await {
             Either3::First(result) => {
@@ -736,6 +1445,8 @@ impl<'d, C: Controller, P: PacketPool> Runner<'d, C, P> {
 
 impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
     /// Run the receive loop that polls the controller for events.
+    ///
+    /// Returns `Ok(())` once the stack is suspended with `Stack::suspend`.
     pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>>
     where
         C: ControllerCmdSync<Disconnect>,
@@ -746,200 +1457,266 @@ impl<'d, C: Controller, P: PacketPool> RxRunner<'d, C, P> {
 
     /// Runs the receive loop that pools the controller for events, dispatching
     /// vendor events to the provided closure.
-    pub async fn run_with_handler<E: EventHandler>(&mut self, event_handler: &E) -> Result<(), BleHostError<C::Error>>
+    pub async fn run_with_handler<E: EventHandler + ?Sized>(
+        &mut self,
+        event_handler: &E,
+    ) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<Disconnect>,
+    {
+        self.run_inner(event_handler, None).await
+    }
+
+    async fn run_inner<E: EventHandler + ?Sized>(
+        &mut self,
+        event_handler: &E,
+        health: Option<&dyn HealthCheck>,
+    ) -> Result<(), BleHostError<C::Error>>
     where
         C: ControllerCmdSync<Disconnect>,
     {
         const MAX_HCI_PACKET_LEN: usize = 259;
         let host = &self.stack.host;
-        // use embassy_time::Instant;
-        // let mut last = Instant::now();
-        loop {
-            // Task handling receiving data from the controller.
-            let mut rx = [0u8; MAX_HCI_PACKET_LEN];
-            // let now = Instant::now();
-            // let elapsed = (now - last).as_millis();
-            // if elapsed >= 1 {
-            //     trace!("[host] time since last poll was {} us", elapsed);
-            // }
-            let result = host.controller.read(&mut rx).await;
-            // last = Instant::now();
-            //        trace!("[host] polling took {} ms", (polled - started).as_millis());
-            match result {
-                Ok(ControllerToHostPacket::Acl(acl)) => match host.handle_acl(acl) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        warn!(
-                            "[host] encountered error processing ACL data for {:?}: {:?}",
-                            acl.handle(),
-                            e
-                        );
-
-                        match e {
-                            Error::InvalidState | Error::Disconnected => {
-                                warn!("[host] requesting {:?} to be disconnected", acl.handle());
-                                host.connections.log_status(true);
-                                host.connections.request_handle_disconnect(
-                                    acl.handle(),
-                                    DisconnectReason::RemoteUserTerminatedConn,
-                                );
+        host.until_suspended(async {
+            // use embassy_time::Instant;
+            // let mut last = Instant::now();
+            loop {
+                if let Some(health) = health {
+                    health.check(&host.liveness());
+                }
+                // Task handling receiving data from the controller.
+                let mut rx = [0u8; MAX_HCI_PACKET_LEN];
+                // let now = Instant::now();
+                // let elapsed = (now - last).as_millis();
+                // if elapsed >= 1 {
+                //     trace!("[host] time since last poll was {} us", elapsed);
+                // }
+                let result = host.controller.read(&mut rx).await;
+                if result.is_ok() {
+                    host.last_rx.set(Some(Instant::now()));
+                    host.rx_packets.set(host.rx_packets.get().wrapping_add(1));
+                }
+                #[cfg(feature = "runner-metrics")]
+                let received = Instant::now();
+                // last = Instant::now();
+                //        trace!("[host] polling took {} ms", (polled - started).as_millis());
+                match result {
+                    Ok(ControllerToHostPacket::Acl(acl)) => match host.handle_acl(acl) {
+                        Ok(_) => {}
+                        Err(e) => {
+                            warn!(
+                                conn = acl.handle(),
+                                "[host] encountered error processing ACL data: {:?}", e
+                            );
+
+                            match e {
+                                Error::InvalidState | Error::Disconnected | Error::ProtocolViolation(_) => {
+                                    warn!(conn = acl.handle(), "[host] requesting to be disconnected");
+                                    host.connections.log_status(true);
+                                    host.connections.request_handle_disconnect(
+                                        acl.handle(),
+                                        DisconnectReason::RemoteUserTerminatedConn,
+                                    );
+                                }
+                                _ => {}
                             }
-                            _ => {}
-                        }
 
-                        let mut m = host.metrics.borrow_mut();
-                        m.rx_errors = m.rx_errors.wrapping_add(1);
-                    }
-                },
-                Ok(ControllerToHostPacket::Event(event)) => {
-                    match event {
-                        Event::Le(ref le_event) => match le_event {
-                            LeEvent::LeConnectionComplete(e) => {
-                                if !host.handle_connection(e.status, e.handle, e.peer_addr_kind, e.peer_addr, e.role) {
-                                    let _ = host
-                                        .command(Disconnect::new(
+                            let mut m = host.metrics.borrow_mut();
+                            m.rx_errors = m.rx_errors.wrapping_add(1);
+                        }
+                    },
+                    Ok(ControllerToHostPacket::Event(event)) => {
+                        match event {
+                            Event::Le(ref le_event) => match le_event {
+                                LeEvent::LeConnectionComplete(e) => {
+                                    if host.handle_connection(e.status, e.handle, e.peer_addr_kind, e.peer_addr, e.role)
+                                    {
+                                        host.connections.set_initial_params(
                                             e.handle,
-                                            DisconnectReason::RemoteDeviceTerminatedConnLowResources,
-                                        ))
-                                        .await;
-                                    host.connect_command_state.canceled();
+                                            Duration::from_micros(e.conn_interval.as_micros()),
+                                            e.peripheral_latency,
+                                            Duration::from_micros(e.supervision_timeout.as_micros()),
+                                        );
+                                    } else {
+                                        let _ = host
+                                            .command(Disconnect::new(
+                                                e.handle,
+                                                DisconnectReason::RemoteDeviceTerminatedConnLowResources,
+                                            ))
+                                            .await;
+                                        host.connect_command_state.canceled();
+                                    }
                                 }
-                            }
-                            LeEvent::LeEnhancedConnectionComplete(e) => {
-                                if !host.handle_connection(e.status, e.handle, e.peer_addr_kind, e.peer_addr, e.role) {
-                                    let _ = host
-                                        .command(Disconnect::new(
+                                LeEvent::LeEnhancedConnectionComplete(e) => {
+                                    if host.handle_connection(e.status, e.handle, e.peer_addr_kind, e.peer_addr, e.role)
+                                    {
+                                        host.connections.set_initial_params(
                                             e.handle,
-                                            DisconnectReason::RemoteDeviceTerminatedConnLowResources,
-                                        ))
-                                        .await;
-                                    host.connect_command_state.canceled();
+                                            Duration::from_micros(e.conn_interval.as_micros()),
+                                            e.peripheral_latency,
+                                            Duration::from_micros(e.supervision_timeout.as_micros()),
+                                        );
+                                    } else {
+                                        let _ = host
+                                            .command(Disconnect::new(
+                                                e.handle,
+                                                DisconnectReason::RemoteDeviceTerminatedConnLowResources,
+                                            ))
+                                            .await;
+                                        host.connect_command_state.canceled();
+                                    }
                                 }
-                            }
-                            LeEvent::LeScanTimeout(_) => {}
-                            LeEvent::LeAdvertisingSetTerminated(set) => {
-                                host.advertise_state.terminate(set.adv_handle);
-                            }
-                            LeEvent::LeExtendedAdvertisingReport(data) => {
-                                #[cfg(feature = "scan")]
-                                {
-                                    event_handler.on_ext_adv_reports(data.reports.iter());
+                                LeEvent::LeScanTimeout(_) => {}
+                                LeEvent::LeAdvertisingSetTerminated(set) => {
+                                    let identity = host.advertise_state.terminate(set.adv_handle);
+                                    if set.status == Status::SUCCESS {
+                                        host.connections.set_adv_handle(set.handle, set.adv_handle, identity);
+                                    }
                                 }
-                            }
-                            LeEvent::LeAdvertisingReport(data) => {
-                                #[cfg(feature = "scan")]
-                                {
-                                    event_handler.on_adv_reports(data.reports.iter());
+                                LeEvent::LeExtendedAdvertisingReport(data) => {
+                                    #[cfg(feature = "scan")]
+                                    {
+                                        let received = host.last_rx.get().unwrap_or_else(Instant::now);
+                                        event_handler.on_ext_adv_reports_at(data.reports.iter(), received);
+                                    }
                                 }
-                            }
-                            LeEvent::LeLongTermKeyRequest(_) => {
-                                host.connections.handle_security_hci_event(event)?;
-                            }
-                            LeEvent::LePhyUpdateComplete(event) => {
-                                if let Err(e) = event.status.to_result() {
-                                    warn!("[host] error updating phy for {:?}: {:?}", event.handle, e);
-                                } else {
+                                LeEvent::LeAdvertisingReport(data) => {
+                                    #[cfg(feature = "scan")]
+                                    {
+                                        let received = host.last_rx.get().unwrap_or_else(Instant::now);
+                                        event_handler.on_adv_reports_at(data.reports.iter(), received);
+                                    }
+                                }
+                                LeEvent::LeLongTermKeyRequest(_) => {
+                                    host.connections.handle_security_hci_event(event)?;
+                                }
+                                LeEvent::LePhyUpdateComplete(event) => {
+                                    if let Err(e) = event.status.to_result() {
+                                        warn!(conn = event.handle, "[host] error updating phy: {:?}", e);
+                                    } else {
+                                        host.connections.update_params(event.handle, |params| {
+                                            params.tx_phy = event.tx_phy;
+                                            params.rx_phy = event.rx_phy;
+                                        });
+                                        let _ = host.connections.post_handle_event(
+                                            event.handle,
+                                            ConnectionEvent::PhyUpdated {
+                                                tx_phy: event.tx_phy,
+                                                rx_phy: event.rx_phy,
+                                            },
+                                        );
+                                    }
+                                }
+                                LeEvent::LeConnectionUpdateComplete(event) => {
+                                    if let Err(e) = event.status.to_result() {
+                                        warn!(
+                                            conn = event.handle,
+                                            "[host] error updating connection parameters: {:?}", e
+                                        );
+                                    } else {
+                                        host.connections.update_params(event.handle, |params| {
+                                            params.conn_interval =
+                                                Duration::from_micros(event.conn_interval.as_micros());
+                                            params.peripheral_latency = event.peripheral_latency;
+                                            params.supervision_timeout =
+                                                Duration::from_micros(event.supervision_timeout.as_micros());
+                                        });
+                                        let _ = host.connections.post_handle_event(
+                                            event.handle,
+                                            ConnectionEvent::ConnectionParamsUpdated {
+                                                conn_interval: Duration::from_micros(event.conn_interval.as_micros()),
+                                                peripheral_latency: event.peripheral_latency,
+                                                supervision_timeout: Duration::from_micros(
+                                                    event.supervision_timeout.as_micros(),
+                                                ),
+                                            },
+                                        );
+                                    }
+                                }
+                                LeEvent::LeDataLengthChange(event) => {
                                     let _ = host.connections.post_handle_event(
                                         event.handle,
-                                        ConnectionEvent::PhyUpdated {
-                                            tx_phy: event.tx_phy,
-                                            rx_phy: event.rx_phy,
+                                        ConnectionEvent::DataLengthUpdated {
+                                            max_tx_octets: event.max_tx_octets,
+                                            max_tx_time: event.max_tx_time,
+                                            max_rx_octets: event.max_rx_octets,
+                                            max_rx_time: event.max_rx_time,
                                         },
                                     );
                                 }
-                            }
-                            LeEvent::LeConnectionUpdateComplete(event) => {
-                                if let Err(e) = event.status.to_result() {
-                                    warn!(
-                                        "[host] error updating connection parameters for {:?}: {:?}",
-                                        event.handle, e
-                                    );
+                                _ => {
+                                    warn!("Unknown LE event!");
+                                }
+                            },
+                            Event::DisconnectionComplete(e) => {
+                                let handle = e.handle;
+                                let reason = if let Err(e) = e.status.to_result() {
+                                    info!(conn = handle, "[host] disconnection event, status: {:?}", e);
+                                    None
+                                } else if let Err(err) = e.reason.to_result() {
+                                    info!(conn = handle, "[host] disconnection event, reason: {:?}", err);
+                                    Some(e.reason)
                                 } else {
-                                    let _ = host.connections.post_handle_event(
-                                        event.handle,
-                                        ConnectionEvent::ConnectionParamsUpdated {
-                                            conn_interval: Duration::from_micros(event.conn_interval.as_micros()),
-                                            peripheral_latency: event.peripheral_latency,
-                                            supervision_timeout: Duration::from_micros(
-                                                event.supervision_timeout.as_micros(),
-                                            ),
-                                        },
-                                    );
+                                    info!(conn = handle, "[host] disconnection event");
+                                    None
                                 }
+                                .unwrap_or(Status::UNSPECIFIED);
+                                let _ = host.connections.disconnected(handle, reason);
+                                let _ = host.channels.disconnected(handle);
+                                event_handler.on_disconnected(handle, reason);
+                                let mut m = host.metrics.borrow_mut();
+                                m.disconnect_events = m.disconnect_events.wrapping_add(1);
                             }
-                            LeEvent::LeDataLengthChange(event) => {
-                                let _ = host.connections.post_handle_event(
-                                    event.handle,
-                                    ConnectionEvent::DataLengthUpdated {
-                                        max_tx_octets: event.max_tx_octets,
-                                        max_tx_time: event.max_tx_time,
-                                        max_rx_octets: event.max_rx_octets,
-                                        max_rx_time: event.max_rx_time,
-                                    },
-                                );
+                            Event::NumberOfCompletedPackets(c) => {
+                                // All connections of the event are credited at once, waking waiting senders once
+                                host.connections
+                                    .confirm_sent(c.completed_packets.iter().filter_map(|entry| {
+                                        match (entry.handle(), entry.num_completed_packets()) {
+                                            (Ok(handle), Ok(completed)) => {
+                                                host.completed_packets
+                                                    .set(host.completed_packets.get().wrapping_add(completed.into()));
+                                                Some((handle, completed as usize))
+                                            }
+                                            (Ok(handle), Err(e)) => {
+                                                warn!(
+                                                    conn = handle,
+                                                    "[host] error processing completed packets: {:?}", e
+                                                );
+                                                None
+                                            }
+                                            _ => None,
+                                        }
+                                    }));
                             }
-                            _ => {
-                                warn!("Unknown LE event!");
+                            Event::Vendor(vendor) => {
+                                event_handler.on_vendor(&vendor);
                             }
-                        },
-                        Event::DisconnectionComplete(e) => {
-                            let handle = e.handle;
-                            let reason = if let Err(e) = e.status.to_result() {
-                                info!("[host] disconnection event on handle {}, status: {:?}", handle.raw(), e);
-                                None
-                            } else if let Err(err) = e.reason.to_result() {
-                                info!(
-                                    "[host] disconnection event on handle {}, reason: {:?}",
-                                    handle.raw(),
-                                    err
-                                );
-                                Some(e.reason)
-                            } else {
-                                info!("[host] disconnection event on handle {}", handle.raw());
-                                None
-                            }
-                            .unwrap_or(Status::UNSPECIFIED);
-                            let _ = host.connections.disconnected(handle, reason);
-                            let _ = host.channels.disconnected(handle);
-                            let mut m = host.metrics.borrow_mut();
-                            m.disconnect_events = m.disconnect_events.wrapping_add(1);
-                        }
-                        Event::NumberOfCompletedPackets(c) => {
-                            // Explicitly ignoring for now
-                            for entry in c.completed_packets.iter() {
-                                match (entry.handle(), entry.num_completed_packets()) {
-                                    (Ok(handle), Ok(completed)) => {
-                                        let _ = host.connections.confirm_sent(handle, completed as usize);
-                                    }
-                                    (Ok(handle), Err(e)) => {
-                                        warn!("[host] error processing completed packets for {:?}: {:?}", handle, e);
-                                    }
-                                    _ => {}
-                                }
+                            Event::EncryptionChangeV1(_) => {
+                                host.connections.handle_security_hci_event(event)?;
                             }
+                            // Ignore
+                            _ => {}
                         }
-                        Event::Vendor(vendor) => {
-                            event_handler.on_vendor(&vendor);
-                        }
-                        Event::EncryptionChangeV1(_) => {
-                            host.connections.handle_security_hci_event(event)?;
-                        }
-                        // Ignore
-                        _ => {}
+                    }
+                    // Ignore
+                    Ok(_) => {}
+                    Err(e) => {
+                        return Err(BleHostError::Controller(e));
                     }
                 }
-                // Ignore
-                Ok(_) => {}
-                Err(e) => {
-                    return Err(BleHostError::Controller(e));
-                }
+                #[cfg(feature = "runner-metrics")]
+                host.metrics.borrow_mut().dispatched(received.elapsed());
             }
-        }
+        })
+        .await
     }
 }
 
 impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
     /// Run the control loop for the host
+    ///
+    /// Returns `Ok(())` once the stack is suspended with `Stack::suspend`. Run it again to initialize the
+    /// controller when resuming.
     pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>>
     where
         C: ControllerCmdSync<Disconnect>
@@ -964,222 +1741,324 @@ impl<'d, C: Controller, P: PacketPool> ControlRunner<'d, C, P> {
             + ControllerCmdSync<ReadBdAddr>,
     {
         let host = &self.stack.host;
-        Reset::new().exec(&host.controller).await?;
-
-        if let Some(addr) = host.address {
-            LeSetRandomAddr::new(addr.addr).exec(&host.controller).await?;
-        }
+        host.until_suspended(async {
+            host.controller_sleep.wake();
+            Reset::new().exec(&host.controller).await?;
 
-        SetEventMask::new(
-            EventMask::new()
-                .enable_le_meta(true)
-                .enable_conn_request(true)
-                .enable_conn_complete(true)
-                .enable_hardware_error(true)
-                .enable_disconnection_complete(true)
-                .enable_encryption_change_v1(true),
-        )
-        .exec(&host.controller)
-        .await?;
+            if let Some(addr) = host.address.filter(|a| a.kind == AddrKind::RANDOM) {
+                LeSetRandomAddr::new(addr.addr).exec(&host.controller).await?;
+            }
 
-        SetEventMaskPage2::new(EventMaskPage2::new().enable_encryption_change_v2(true))
+            SetEventMask::new(
+                EventMask::new()
+                    .enable_le_meta(true)
+                    .enable_conn_request(true)
+                    .enable_conn_complete(true)
+                    .enable_hardware_error(true)
+                    .enable_disconnection_complete(true)
+                    .enable_encryption_change_v1(true),
+            )
             .exec(&host.controller)
             .await?;
 
-        LeSetEventMask::new(
-            LeEventMask::new()
-                .enable_le_conn_complete(true)
-                .enable_le_enhanced_conn_complete(true)
-                .enable_le_conn_update_complete(true)
-                .enable_le_adv_set_terminated(true)
-                .enable_le_adv_report(true)
-                .enable_le_scan_timeout(true)
-                .enable_le_ext_adv_report(true)
-                .enable_le_long_term_key_request(true)
-                .enable_le_phy_update_complete(true)
-                .enable_le_data_length_change(true),
-        )
-        .exec(&host.controller)
-        .await?;
-
-        info!(
-            "[host] using packet pool with MTU {} capacity {}",
-            P::MTU,
-            P::capacity(),
-        );
-
-        let ret = LeReadFilterAcceptListSize::new().exec(&host.controller).await?;
-        info!("[host] filter accept list size: {}", ret);
-
-        let ret = LeReadBufferSize::new().exec(&host.controller).await?;
-        info!(
-            "[host] setting txq to {}, fragmenting at {}",
-            ret.total_num_le_acl_data_packets as usize, ret.le_acl_data_packet_length as usize
-        );
-        host.connections
-            .set_link_credits(ret.total_num_le_acl_data_packets as usize);
-
-        const ACL_LEN: u16 = 255;
-        const ACL_N: u16 = 1;
-        info!(
-            "[host] configuring host buffers ({} packets of size {})",
-            ACL_N, ACL_LEN,
-        );
-        HostBufferSize::new(ACL_LEN, 0, ACL_N, 0).exec(&host.controller).await?;
-
-        /*
-                #[cfg(feature = "controller-host-flow-control")]
-                {
-                    info!("[host] enabling flow control");
-                    SetControllerToHostFlowControl::new(ControllerToHostFlowControl::AclOnSyncOff)
-                        .exec(&host.controller)
-                        .await?;
-                }
-        */
-
-        let _ = host.initialized.init(InitialState {
-            acl_max: ret.le_acl_data_packet_length as usize,
-        });
-        info!("[host] initialized");
+            let event_mask_page2 = optional(
+                SetEventMaskPage2::new(EventMaskPage2::new().enable_encryption_change_v2(true))
+                    .exec(&host.controller)
+                    .await,
+            )?
+            .is_some();
+
+            LeSetEventMask::new(
+                LeEventMask::new()
+                    .enable_le_conn_complete(true)
+                    .enable_le_enhanced_conn_complete(true)
+                    .enable_le_conn_update_complete(true)
+                    .enable_le_adv_set_terminated(true)
+                    .enable_le_adv_report(true)
+                    .enable_le_scan_timeout(true)
+                    .enable_le_ext_adv_report(true)
+                    .enable_le_long_term_key_request(true)
+                    .enable_le_phy_update_complete(true)
+                    .enable_le_data_length_change(true),
+            )
+            .exec(&host.controller)
+            .await?;
 
-        let device_address = host.command(ReadBdAddr::new()).await?;
-        if *device_address.raw() != [0, 0, 0, 0, 0, 0] {
-            let device_address = Address {
-                kind: AddrKind::PUBLIC,
-                addr: device_address,
+            info!(
+                "[host] using packet pool with MTU {} capacity {}",
+                P::MTU,
+                P::capacity(),
+            );
+
+            let filter_accept_list_size = optional(LeReadFilterAcceptListSize::new().exec(&host.controller).await)?;
+            info!("[host] filter accept list size: {:?}", filter_accept_list_size);
+
+            let ret = LeReadBufferSize::new().exec(&host.controller).await?;
+            info!(
+                "[host] setting txq to {}, fragmenting at {}",
+                ret.total_num_le_acl_data_packets as usize, ret.le_acl_data_packet_length as usize
+            );
+            host.connections
+                .set_link_credits(ret.total_num_le_acl_data_packets as usize);
+
+            const ACL_LEN: u16 = 255;
+            const ACL_N: u16 = 1;
+            info!(
+                "[host] configuring host buffers ({} packets of size {})",
+                ACL_N, ACL_LEN,
+            );
+            let host_buffer_size =
+                optional(HostBufferSize::new(ACL_LEN, 0, ACL_N, 0).exec(&host.controller).await)?.is_some();
+
+            /*
+                    #[cfg(feature = "controller-host-flow-control")]
+                    {
+                        info!("[host] enabling flow control");
+                        SetControllerToHostFlowControl::new(ControllerToHostFlowControl::AclOnSyncOff)
+                            .exec(&host.controller)
+                            .await?;
+                    }
+            */
+
+            let device_address = optional(ReadBdAddr::new().exec(&host.controller).await)?;
+            let capabilities = ControllerCapabilities {
+                event_mask_page2,
+                filter_accept_list_size,
+                host_buffer_size,
+                read_bd_addr: device_address.is_some(),
             };
-            info!("[host] Device Address {}", device_address);
-            if host.address.is_none() {
-                #[cfg(feature = "security")]
-                host.connections.security_manager.set_local_address(device_address);
+            info!("[host] controller capabilities: {:?}", capabilities);
+
+            host.initialized.set(Some(InitialState {
+                acl_max: ret.le_acl_data_packet_length as usize,
+                capabilities,
+            }));
+            info!("[host] initialized");
+            host.set_running();
+
+            if let Some(device_address) = device_address.filter(|a| *a.raw() != [0, 0, 0, 0, 0, 0]) {
+                let device_address = Address {
+                    kind: AddrKind::PUBLIC,
+                    addr: device_address,
+                };
+                info!("[host] Device Address {}", device_address);
+                if let Some(address) = host.address.filter(|a| a.kind == AddrKind::PUBLIC) {
+                    if address.addr != device_address.addr {
+                        warn!(
+                            "[host] Configured public address {} does not match controller address",
+                            address
+                        );
+                    }
+                }
+                if host.address.is_none() {
+                    #[cfg(feature = "security")]
+                    host.connections.security_manager.set_local_address(device_address);
+                }
             }
-        }
 
-        loop {
-            match select3(
-                poll_fn(|cx| host.connections.poll_disconnecting(Some(cx))),
-                poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
-                select4(
-                    poll_fn(|cx| host.connect_command_state.poll_cancelled(cx)),
-                    poll_fn(|cx| host.advertise_command_state.poll_cancelled(cx)),
-                    poll_fn(|cx| host.scan_command_state.poll_cancelled(cx)),
-                    #[cfg(feature = "security")]
-                    {
-                        host.connections.poll_security_events()
-                    },
-                    #[cfg(not(feature = "security"))]
-                    {
-                        poll_fn(|cx| Poll::<()>::Pending)
-                    },
-                ),
-            )
-            .await
-            {
-                Either3::First(request) => {
-                    trace!("[host] poll disconnecting links");
-                    match host.command(Disconnect::new(request.handle(), request.reason())).await {
-                        Ok(_) => {}
-                        Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
-                        Err(e) => {
-                            return Err(e);
+            loop {
+                match select4(
+                    poll_fn(|cx| host.connections.poll_disconnecting(Some(cx))),
+                    poll_fn(|cx| host.channels.poll_disconnecting(Some(cx))),
+                    select4(
+                        poll_fn(|cx| host.connect_command_state.poll_cancelled(cx)),
+                        poll_fn(|cx| host.advertise_command_state.poll_cancelled(cx)),
+                        poll_fn(|cx| host.scan_command_state.poll_cancelled(cx)),
+                        #[cfg(feature = "security")]
+                        {
+                            host.connections.poll_security_events()
+                        },
+                        #[cfg(not(feature = "security"))]
+                        {
+                            poll_fn(|cx| Poll::<()>::Pending)
+                        },
+                    ),
+                    host.coex.scan_due(),
+                )
+                .await
+                {
+                    Either4::First(request) => {
+                        trace!("[host] poll disconnecting links");
+                        match host.command(Disconnect::new(request.handle(), request.reason())).await {
+                            Ok(_) => {}
+                            Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
+                            Err(e) => {
+                                return Err(e);
+                            }
                         }
+                        request.confirm();
                     }
-                    request.confirm();
-                }
-                Either3::Second(request) => {
-                    trace!("[host] poll disconnecting channels");
-                    match request.send(host).await {
-                        Ok(_) => {}
-                        Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
-                        Err(BleHostError::BleHost(Error::NotFound)) => {}
-                        Err(e) => {
-                            return Err(e);
+                    Either4::Second(request) => {
+                        trace!("[host] poll disconnecting channels");
+                        match request.send(host).await {
+                            Ok(_) => {}
+                            Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => {}
+                            Err(BleHostError::BleHost(Error::NotFound)) => {}
+                            Err(e) => {
+                                return Err(e);
+                            }
                         }
+                        request.confirm();
                     }
-                    request.confirm();
-                }
-                Either3::Third(states) => match states {
-                    Either4::First(_) => {
-                        trace!("[host] cancel connection create");
-                        // trace!("[host] cancelling create connection");
-                        if host.command(LeCreateConnCancel::new()).await.is_err() {
-                            warn!("[host] error cancelling connection");
+                    Either4::Third(states) => match states {
+                        Either4::First(_) => {
+                            trace!("[host] cancel connection create");
+                            // trace!("[host] cancelling create connection");
+                            if host.command(LeCreateConnCancel::new()).await.is_err() {
+                                warn!("[host] error cancelling connection");
+                            }
+                            // Signal to ensure no one is stuck
+                            host.connect_command_state.canceled();
                         }
-                        // Signal to ensure no one is stuck
-                        host.connect_command_state.canceled();
-                    }
-                    Either4::Second(ext) => {
-                        trace!("[host] disabling advertising");
-                        if ext {
-                            host.command(LeSetExtAdvEnable::new(false, &[])).await?
-                        } else {
-                            host.command(LeSetAdvEnable::new(false)).await?
+                        Either4::Second(ext) => {
+                            trace!("[host] disabling advertising");
+                            if ext {
+                                host.command(LeSetExtAdvEnable::new(false, &[])).await?
+                            } else {
+                                host.command(LeSetAdvEnable::new(false)).await?
+                            }
+                            host.advertise_state.clear_private();
+                            host.advertise_command_state.canceled();
                         }
-                        host.advertise_command_state.canceled();
-                    }
-                    Either4::Third(ext) => {
-                        trace!("[host] disabling scanning");
-                        if ext {
-                            // TODO: A bit opinionated but not more than before
-                            host.command(LeSetExtScanEnable::new(
-                                false,
-                                FilterDuplicates::Disabled,
-                                bt_hci::param::Duration::from_secs(0),
-                                bt_hci::param::Duration::from_secs(0),
-                            ))
-                            .await?;
-                        } else {
-                            host.command(LeSetScanEnable::new(false, false)).await?;
+                        Either4::Third(ext) => {
+                            trace!("[host] disabling scanning");
+                            if ext {
+                                // TODO: A bit opinionated but not more than before
+                                host.command(LeSetExtScanEnable::new(
+                                    false,
+                                    FilterDuplicates::Disabled,
+                                    bt_hci::param::Duration::from_secs(0),
+                                    bt_hci::param::Duration::from_secs(0),
+                                ))
+                                .await?;
+                            } else {
+                                host.command(LeSetScanEnable::new(false, false)).await?;
+                            }
+                            host.scan_command_state.canceled();
                         }
-                        host.scan_command_state.canceled();
-                    }
-                    Either4::Fourth(request) => {
-                        #[cfg(feature = "security")]
-                        {
-                            let event_data = match request {
-                                Ok(e) => e,
-                                Err(_) => SecurityEventData::Timeout,
+                        Either4::Fourth(request) => {
+                            #[cfg(feature = "security")]
+                            {
+                                let event_data = match request {
+                                    Ok(e) => e,
+                                    Err(_) => SecurityEventData::Timeout,
+                                };
+                                host.connections.handle_security_event(host, event_data).await?;
+                            }
+                        }
+                    },
+                    Either4::Fourth(ScanCoex::Pause) => {
+                        if let Some(ext) = host.scan_command_state.active() {
+                            trace!("[host] pausing scanning");
+                            host.coex.set_scan_restart(ext);
+                            let result = if ext {
+                                host.command(LeSetExtScanEnable::new(
+                                    false,
+                                    FilterDuplicates::Disabled,
+                                    bt_hci::param::Duration::from_secs(0),
+                                    bt_hci::param::Duration::from_secs(0),
+                                ))
+                                .await
+                            } else {
+                                host.command(LeSetScanEnable::new(false, false)).await
                             };
-                            host.connections.handle_security_event(host, event_data).await?;
+                            if result.is_err() {
+                                warn!("[host] error pausing scanning");
+                            }
+                        }
+                    }
+                    Either4::Fourth(ScanCoex::Resume) => {
+                        // Only restart the scan that was paused, it may have been stopped during the pause
+                        if let Some(ext) = host.coex.take_scan_restart() {
+                            if host.scan_command_state.active() == Some(ext) {
+                                trace!("[host] resuming scanning");
+                                let result = if ext {
+                                    host.command(LeSetExtScanEnable::new(
+                                        true,
+                                        FilterDuplicates::Disabled,
+                                        bt_hci::param::Duration::from_secs(0),
+                                        bt_hci::param::Duration::from_secs(0),
+                                    ))
+                                    .await
+                                } else {
+                                    host.command(LeSetScanEnable::new(true, true)).await
+                                };
+                                if result.is_err() {
+                                    warn!("[host] error resuming scanning");
+                                }
+                            }
                         }
                     }
-                },
+                }
             }
-        }
+        })
+        .await
     }
 }
 
 impl<'d, C: Controller, P: PacketPool> TxRunner<'d, C, P> {
     /// Run the transmit loop for the host.
+    ///
+    /// Returns `Ok(())` once the stack is suspended with `Stack::suspend`.
+    ///
+    /// The [`ControllerPower`] set with `Stack::set_controller_power` is put to sleep while there is nothing to send.
     pub async fn run(&mut self) -> Result<(), BleHostError<C::Error>> {
+        self.run_inner(None).await
+    }
+
+    async fn run_inner(&mut self, health: Option<&dyn HealthCheck>) -> Result<(), BleHostError<C::Error>> {
         let host = &self.stack.host;
-        let params = host.initialized.get().await;
-        loop {
-            let (conn, pdu) = host.connections.outbound().await;
-            match host.l2cap(conn, pdu.len() as u16, 1).await {
-                Ok(mut sender) => {
-                    if let Err(e) = sender.send(pdu.as_ref()).await {
-                        warn!("[host] error sending outbound pdu");
-                        return Err(e);
-                    }
-                }
-                Err(BleHostError::BleHost(Error::NotFound)) => {
-                    warn!("[host] unable to send data to disconnected host (ignored)");
+        host.until_suspended(async {
+            let params = host.initialized().await;
+            loop {
+                if let Some(health) = health {
+                    health.check(&host.liveness());
                 }
-                Err(BleHostError::BleHost(Error::Disconnected)) => {
-                    warn!("[host] unable to send data to disconnected host (ignored)");
+                if !host.connections.has_outbound() {
+                    host.controller_sleep
+                        .sleep(host.connections.next_anchor_any(Instant::now()));
                 }
-                Err(e) => {
-                    warn!("[host] error requesting sending outbound pdu");
-                    return Err(e);
+                let entry = match health {
+                    Some(health) => match select(host.connections.outbound(), Timer::after(health.interval())).await {
+                        Either::First(entry) => entry,
+                        // Nothing to send, report the idle host
+                        Either::Second(_) => continue,
+                    },
+                    None => host.connections.outbound().await,
+                };
+                let priority = entry.urgent();
+                let (conn, pdu) = (entry.handle, entry.item);
+                #[cfg(feature = "runner-metrics")]
+                let (queue_depth, start) = (host.connections.outbound_len() + 1, Instant::now());
+                match host.l2cap_with_priority(conn, pdu.len() as u16, 1, priority).await {
+                    Ok(mut sender) => {
+                        if let Err(e) = sender.send(pdu.as_ref()).await {
+                            warn!(conn = conn, "[host] error sending outbound pdu");
+                            return Err(e);
+                        }
+                    }
+                    Err(BleHostError::BleHost(Error::NotFound)) => {
+                        warn!(conn = conn, "[host] unable to send data to disconnected host (ignored)");
+                    }
+                    Err(BleHostError::BleHost(Error::Disconnected)) => {
+                        warn!(conn = conn, "[host] unable to send data to disconnected host (ignored)");
+                    }
+                    Err(e) => {
+                        warn!(conn = conn, "[host] error requesting sending outbound pdu");
+                        return Err(e);
+                    }
                 }
+                #[cfg(feature = "runner-metrics")]
+                host.metrics.borrow_mut().transmitted(queue_depth, start.elapsed());
             }
-        }
+        })
+        .await
     }
 }
 
 pub struct L2capSender<'a, 'd, T: Controller, P> {
     pub(crate) controller: &'a T,
+    pub(crate) sleep: &'a ControllerSleep<'d>,
     pub(crate) handle: ConnHandle,
     pub(crate) grant: PacketGrant<'a, 'd, P>,
     pub(crate) fragment_size: u16,
@@ -1196,15 +2075,16 @@ impl<'a, 'd, T: Controller, P> L2capSender<'a, 'd, T, P> {
         //    pdu.len(),
         //    self.fragment_size
         //);
+        self.sleep.wake();
         for chunk in pdu.chunks(self.fragment_size as usize) {
             let acl = AclPacket::new(self.handle, pbf, AclBroadcastFlag::PointToPoint, chunk);
             match self.controller.try_write_acl_data(&acl) {
                 Ok(result) => {
                     self.grant.confirm(1);
-                    trace!("[host] sent acl packet len = {}", chunk.len());
+                    trace!(conn = self.handle, "[host] sent acl packet len = {}", chunk.len());
                 }
                 Err(blocking::TryError::Busy) => {
-                    warn!("hci: acl data send busy");
+                    warn!(conn = self.handle, "[host] acl data send busy");
                     return Err(Error::Busy.into());
                 }
                 Err(blocking::TryError::Error(e)) => return Err(BleHostError::Controller(e)),
@@ -1221,6 +2101,7 @@ impl<'a, 'd, T: Controller, P> L2capSender<'a, 'd, T, P> {
         //    self.fragment_size
         //);
         let mut pbf = AclPacketBoundary::FirstNonFlushable;
+        self.sleep.wake();
         for chunk in pdu.chunks(self.fragment_size as usize) {
             let acl = AclPacket::new(self.handle, pbf, AclBroadcastFlag::PointToPoint, chunk);
             self.controller
@@ -1229,7 +2110,7 @@ impl<'a, 'd, T: Controller, P> L2capSender<'a, 'd, T, P> {
                 .map_err(BleHostError::Controller)?;
             self.grant.confirm(1);
             pbf = AclPacketBoundary::Continuing;
-            trace!("[host] sent acl packet len = {}", chunk.len());
+            trace!(conn = self.handle, "[host] sent acl packet len = {}", chunk.len());
         }
         Ok(())
     }
@@ -1258,3 +2139,155 @@ impl<F: FnOnce()> Drop for OnDrop<F> {
         unsafe { self.f.as_ptr().read()() }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::mock_controller::MockController;
+    use crate::prelude::DefaultPacketPool;
+    use crate::{new, HostResources};
+
+    type Host<'d> = BleHost<'d, MockController, DefaultPacketPool>;
+
+    /// Receive an L2CAP frame announcing `length` bytes on `channel`.
+    fn receive(host: &Host<'_>, channel: u16, length: u16, payload: &[u8]) -> Result<(), Error> {
+        let mut data = std::vec::Vec::new();
+        data.extend_from_slice(&length.to_le_bytes());
+        data.extend_from_slice(&channel.to_le_bytes());
+        data.extend_from_slice(payload);
+        host.handle_acl(AclPacket::new(
+            ConnHandle::new(1),
+            AclPacketBoundary::FirstFlushable,
+            AclBroadcastFlag::PointToPoint,
+            &data,
+        ))
+    }
+
+    fn receive_att(host: &Host<'_>, payload: &[u8]) -> Result<(), Error> {
+        receive(host, L2CAP_CID_ATT, payload.len() as u16, payload)
+    }
+
+    /// Send an ATT PDU through the transmit queue.
+    fn send_att(host: &Host<'_>, payload: &[u8]) {
+        let mut packet = unwrap!(DefaultPacketPool::allocate());
+        packet.as_mut()[..2].copy_from_slice(&(payload.len() as u16).to_le_bytes());
+        packet.as_mut()[2..4].copy_from_slice(&L2CAP_CID_ATT.to_le_bytes());
+        packet.as_mut()[4..4 + payload.len()].copy_from_slice(payload);
+        unwrap!(host
+            .connections
+            .try_outbound(ConnHandle::new(1), Pdu::new(packet, 4 + payload.len())));
+        block_on(host.connections.outbound());
+    }
+
+    #[test]
+    fn strict_mode() {
+        let mut resources: HostResources<DefaultPacketPool, 1, 1> = HostResources::new();
+        let stack = new(MockController::new(), &mut resources).set_strict_mode(true);
+        let host = &stack.host;
+        unwrap!(host.connections.connect(
+            ConnHandle::new(1),
+            AddrKind::RANDOM,
+            BdAddr::new([1; 6]),
+            LeConnRole::Peripheral
+        ));
+        let Poll::Ready(_conn) = host.connections.poll_accept(LeConnRole::Peripheral, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        let read = [att::ATT_READ_REQ, 0x01, 0x00];
+
+        let l2cap = Err(Error::ProtocolViolation(ProtocolLayer::L2cap));
+        assert_eq!(receive(host, L2CAP_CID_ATT, 2, &read), l2cap);
+        assert_eq!(receive(host, 0x0003, 1, &[0]), l2cap);
+
+        let att = Err(Error::ProtocolViolation(ProtocolLayer::Att));
+        assert_eq!(receive_att(host, &read[..2]), att);
+        // Only one request may wait for its response
+        assert_eq!(receive_att(host, &read), Ok(()));
+        assert_eq!(receive_att(host, &read), att);
+        send_att(host, &[att::ATT_READ_RSP, 0]);
+        assert_eq!(receive_att(host, &read), Ok(()));
+        // Responses answer a request of the local client
+        assert_eq!(receive_att(host, &[att::ATT_READ_RSP, 0]), att);
+        send_att(host, &read);
+        assert_eq!(
+            receive_att(host, &[att::ATT_ERROR_RSP, att::ATT_READ_REQ, 0x01, 0x00, 0x0a]),
+            Ok(())
+        );
+        assert_eq!(
+            Error::ProtocolViolation(ProtocolLayer::Att).kind(),
+            crate::ErrorKind::Gatt(crate::GattError::ProtocolViolation)
+        );
+
+        // Violations are tolerated outside of the strict mode
+        host.strict.set(false);
+        assert_eq!(receive_att(host, &read), Ok(()));
+        assert_eq!(receive_att(host, &[att::ATT_READ_RSP, 0]), Ok(()));
+    }
+
+    #[test]
+    fn suspend_resume() {
+        let mut resources: HostResources<DefaultPacketPool, 1, 1> = HostResources::new();
+        let stack = new(MockController::new(), &mut resources);
+        let host = &stack.host;
+        let initial = InitialState {
+            acl_max: 27,
+            capabilities: ControllerCapabilities::default(),
+        };
+        host.initialized.set(Some(initial));
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Split runners, which run until the stack is suspended
+        let rx = host.until_suspended(core::future::pending::<Result<(), Error>>());
+        let tx = host.until_suspended(core::future::pending::<Result<(), Error>>());
+        let suspend = stack.suspend(false);
+        pin_mut!(rx, tx, suspend);
+        assert!(rx.as_mut().poll(&mut cx).is_pending());
+        assert!(tx.as_mut().poll(&mut cx).is_pending());
+
+        // Suspending waits for every runner to stop
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(rx.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert!(suspend.as_mut().poll(&mut cx).is_pending());
+        assert_eq!(tx.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(suspend.as_mut().poll(&mut cx), Poll::Ready(Ok(())));
+
+        // The controller is initialized again before commands are sent after resuming
+        assert!(host.capabilities().is_none());
+        let resume = stack.resume();
+        pin_mut!(resume);
+        assert!(resume.as_mut().poll(&mut cx).is_pending());
+        host.initialized.set(Some(initial));
+        host.set_running();
+        assert!(resume.as_mut().poll(&mut cx).is_ready());
+        assert_eq!(host.capabilities(), Some(ControllerCapabilities::default()));
+    }
+
+    #[cfg(feature = "debug-state")]
+    #[test]
+    fn debug_state_truncated() {
+        let mut resources: HostResources<DefaultPacketPool, 2, 1> = HostResources::new();
+        let stack = new(MockController::new(), &mut resources);
+        let host = &stack.host;
+        for handle in [1, 2] {
+            unwrap!(host.connections.connect(
+                ConnHandle::new(handle),
+                AddrKind::RANDOM,
+                BdAddr::new([handle as u8; 6]),
+                LeConnRole::Peripheral
+            ));
+        }
+
+        let state = host.debug_state::<1, 1>();
+        assert_eq!(state.connections.len(), 1);
+        assert_eq!(state.truncated_connections, 1);
+        assert_eq!(state.truncated_channels, 0);
+        let state = host.debug_state::<2, 1>();
+        assert_eq!(state.connections.len(), 2);
+        assert_eq!(state.truncated_connections, 0);
+    }
+}
@@ -84,18 +84,42 @@ impl<P: PacketPool> Drop for L2capChannelReader<'_, P> {
 }
 
 /// Configuration for an L2CAP channel.
+///
+/// Fields may be added in new releases, so build it from [`L2capChannelConfig::default`] with struct update
+/// syntax or the `with_*` methods rather than listing every field. Literals listing every field no longer compile
+/// since `tx_mtu` and `tx_mps` were added.
 #[derive(Default)]
 pub struct L2capChannelConfig {
-    /// Size of Service Data Unit. Defaults to packet allocator MTU-6.
+    /// Size of Service Data Unit received on the channel. Defaults to packet allocator MTU-6.
     pub mtu: Option<u16>,
-    /// Frame size (1 frame == 1 credit). Defaults to packet allocator MTU-4.
+    /// Frame size received on the channel (1 frame == 1 credit). Defaults to packet allocator MTU-4.
     pub mps: Option<u16>,
+    /// Largest Service Data Unit sent on the channel, further limited by the MTU of the peer. Defaults to no limit.
+    pub tx_mtu: Option<u16>,
+    /// Largest frame sent on the channel, further limited by the MPS of the peer. Defaults to packet allocator MTU-4.
+    pub tx_mps: Option<u16>,
     /// Flow control policy for connection oriented channels.
     pub flow_policy: CreditFlowPolicy,
     /// Initial credits for connection oriented channels.
+    ///
+    /// Capped to the packets a channel may hold, see `HostResources::rx_quota`.
     pub initial_credits: Option<u16>,
 }
 
+impl L2capChannelConfig {
+    /// Limit the SDUs sent on the channel, see [`tx_mtu`](Self::tx_mtu).
+    pub fn with_tx_mtu(mut self, mtu: u16) -> Self {
+        self.tx_mtu = Some(mtu);
+        self
+    }
+
+    /// Limit the frames sent on the channel, see [`tx_mps`](Self::tx_mps).
+    pub fn with_tx_mps(mut self, mps: u16) -> Self {
+        self.tx_mps = Some(mps);
+        self
+    }
+}
+
 impl<'d, P: PacketPool> L2capChannel<'d, P> {
     pub(crate) fn new(index: ChannelIndex, manager: &'d ChannelManager<'d, P>) -> Self {
         Self { index, manager }
@@ -111,18 +135,34 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
         self.manager.psm(self.index)
     }
 
+    /// Get the MTU agreed for this channel, the smaller of the receive and transmit MTU.
+    pub fn mtu(&self) -> u16 {
+        self.manager.mtu(self.index)
+    }
+
+    /// Get the largest SDU this channel accepts from the peer.
+    pub fn rx_mtu(&self) -> u16 {
+        self.manager.rx_mtu(self.index)
+    }
+
+    /// Get the largest SDU that can be sent on this channel.
+    pub fn tx_mtu(&self) -> u16 {
+        self.manager.tx_mtu(self.index)
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
-    /// The buffer must be equal to or smaller than the MTU agreed for the channel.
+    /// The buffer must be equal to or smaller than the transmit MTU of the channel.
     ///
     /// If the channel has been closed or the channel id is not valid, an error is returned.
     /// If there are no available credits to send, waits until more credits are available.
+    /// If the packet pool is exhausted, waits until a packet is available.
     pub async fn send<T: Controller>(
         &mut self,
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut p_buf = P::allocate_async().await;
         stack
             .host
             .channels
@@ -132,10 +172,11 @@ impl<'d, P: PacketPool> L2capChannel<'d, P> {
 
     /// Send the provided buffer over this l2cap channel.
     ///
-    /// The buffer must be equal to or smaller than the MTU agreed for the channel.
+    /// The buffer must be equal to or smaller than the transmit MTU of the channel.
     ///
     /// If the channel has been closed or the channel id is not valid, an error is returned.
     /// If there are no available credits to send, returns Error::Busy.
+    /// If the packet pool is exhausted, returns Error::OutOfMemory.
     pub fn try_send<T: Controller + blocking::Controller>(
         &mut self,
         stack: &Stack<'_, T, P>,
@@ -239,6 +280,11 @@ impl<'d, P: PacketPool> L2capChannelReader<'d, P> {
         self.manager.disconnect(self.index);
     }
 
+    /// Get the largest SDU this channel accepts from the peer.
+    pub fn rx_mtu(&self) -> u16 {
+        self.manager.rx_mtu(self.index)
+    }
+
     /// Receive data on this channel and copy it into the buffer.
     ///
     /// The length provided buffer slice must be equal or greater to the agreed MTU.
@@ -290,18 +336,24 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
         self.manager.disconnect(self.index);
     }
 
+    /// Get the largest SDU that can be sent on this channel.
+    pub fn tx_mtu(&self) -> u16 {
+        self.manager.tx_mtu(self.index)
+    }
+
     /// Send the provided buffer over this l2cap channel.
     ///
-    /// The buffer must be equal to or smaller than the MTU agreed for the channel.
+    /// The buffer must be equal to or smaller than the transmit MTU of the channel.
     ///
     /// If the channel has been closed or the channel id is not valid, an error is returned.
     /// If there are no available credits to send, waits until more credits are available.
+    /// If the packet pool is exhausted, waits until a packet is available.
     pub async fn send<T: Controller>(
         &mut self,
         stack: &Stack<'_, T, P>,
         buf: &[u8],
     ) -> Result<(), BleHostError<T::Error>> {
-        let mut p_buf = P::allocate().ok_or(Error::OutOfMemory)?;
+        let mut p_buf = P::allocate_async().await;
         stack
             .host
             .channels
@@ -311,10 +363,11 @@ impl<'d, P: PacketPool> L2capChannelWriter<'d, P> {
 
     /// Send the provided buffer over this l2cap channel.
     ///
-    /// The buffer must be equal to or smaller than the MTU agreed for the channel.
+    /// The buffer must be equal to or smaller than the transmit MTU of the channel.
     ///
     /// If the channel has been closed or the channel id is not valid, an error is returned.
     /// If there are no available credits to send, returns Error::Busy.
+    /// If the packet pool is exhausted, returns Error::OutOfMemory.
     pub fn try_send<T: Controller + blocking::Controller>(
         &mut self,
         stack: &Stack<'_, T, P>,
@@ -5,12 +5,13 @@
 #![doc = include_str!(concat!("../", env!("CARGO_PKG_README")))]
 #![warn(missing_docs)]
 
+use core::future::poll_fn;
 use core::mem::MaybeUninit;
 
 use advertise::AdvertisementDataError;
 use bt_hci::cmd::status::ReadRssi;
 use bt_hci::cmd::{AsyncCmd, SyncCmd};
-use bt_hci::param::{AddrKind, BdAddr};
+use bt_hci::param::{AddrKind, BdAddr, ConnHandle, DisconnectReason, LeAdvReport, LeExtAdvReport};
 use bt_hci::FromHciBytesError;
 #[cfg(feature = "security")]
 use heapless::Vec;
@@ -18,34 +19,51 @@ use rand_core::{CryptoRng, RngCore};
 
 use crate::att::AttErrorCode;
 use crate::channel_manager::ChannelStorage;
+use crate::coex::{CoexHints, CoexObserver};
 use crate::connection_manager::ConnectionStorage;
 #[cfg(feature = "security")]
-pub use crate::security_manager::{BondInformation, IdentityResolvingKey, LongTermKey};
+pub use crate::security_manager::{
+    BondInformation, ConnectionSignatureResolvingKey, IdentityResolvingKey, LongTermKey, Reason, SecurityMode1Level,
+    SecurityPolicy, SigningKey,
+};
 
 /// Number of bonding information stored
 pub(crate) const BI_COUNT: usize = 10; // Should be configurable
 
+/// Version of the snapshot format written by [`Stack::snapshot`].
+const STACK_SNAPSHOT_VERSION: u8 = 3;
+
 mod fmt;
 
 #[cfg(not(any(feature = "central", feature = "peripheral")))]
 compile_error!("Must enable at least one of the `central` or `peripheral` features");
 
+pub mod assigned_numbers;
 pub mod att;
+pub mod bthome;
 #[cfg(feature = "central")]
 pub mod central;
 mod channel_manager;
 mod codec;
+pub mod coex;
 mod command;
 pub mod config;
 mod connection_manager;
 mod cursor;
+#[cfg(feature = "debug-state")]
+pub mod debug_state;
+pub mod finder;
 #[cfg(feature = "default-packet-pool")]
 mod packet_pool;
 mod pdu;
 #[cfg(feature = "peripheral")]
 pub mod peripheral;
+pub mod ranging;
 #[cfg(feature = "security")]
 mod security_manager;
+pub mod split;
+pub mod test_mode;
+mod tx_scheduler;
 pub mod types;
 
 #[cfg(feature = "central")]
@@ -60,12 +78,15 @@ pub mod gap;
 pub mod l2cap;
 #[cfg(feature = "scan")]
 pub mod scan;
+pub mod transport;
 
 #[cfg(test)]
 pub(crate) mod mock_controller;
 
 pub(crate) mod host;
-use host::{AdvHandleState, BleHost, HostMetrics, Runner};
+use host::{
+    AdvHandleState, BleHost, ControllerCapabilities, ControllerPower, HostMetrics, Liveness, MemoryStats, Runner,
+};
 
 pub mod prelude {
     //! Convenience include of most commonly used types.
@@ -79,7 +100,13 @@ pub mod prelude {
     pub use trouble_host_macros::*;
 
     pub use super::att::AttErrorCode;
-    pub use super::{BleHostError, Controller, Error, Host, HostResources, Packet, PacketPool, Stack};
+    #[cfg(feature = "security")]
+    pub use super::SecurityError;
+    pub use super::{
+        AdvertiseError, BleHostError, ConnectError, Controller, ControllerWithDle, ControllerWithExtAdv,
+        ControllerWithPhy, DeviceAddressSource, Error, ErrorKind, FlashGate, GattError, Host, HostResources,
+        L2capError, MemoryLayout, Packet, PacketPool, Stack,
+    };
     #[cfg(feature = "peripheral")]
     pub use crate::advertise::*;
     #[cfg(feature = "gatt")]
@@ -88,12 +115,16 @@ pub mod prelude {
     pub use crate::attribute_server::*;
     #[cfg(feature = "central")]
     pub use crate::central::*;
+    pub use crate::coex::{CoexActivity, CoexHints, CoexObserver};
     pub use crate::connection::*;
     #[cfg(feature = "gatt")]
     pub use crate::gap::*;
     #[cfg(feature = "gatt")]
     pub use crate::gatt::*;
-    pub use crate::host::{ControlRunner, EventHandler, HostMetrics, Runner, RxRunner, TxRunner};
+    pub use crate::host::{
+        ControlRunner, ControllerCapabilities, ControllerPower, EventHandler, HealthCheck, HostMetrics, Liveness,
+        MemoryStats, Runner, RunnerConfig, RxRunner, TxRunner,
+    };
     pub use crate::l2cap::*;
     #[cfg(feature = "default-packet-pool")]
     pub use crate::packet_pool::DefaultPacketPool;
@@ -104,7 +135,7 @@ pub mod prelude {
     pub use crate::scan::*;
     #[cfg(feature = "gatt")]
     pub use crate::types::gatt_traits::{AsGatt, FixedGattValue, FromGatt};
-    pub use crate::{Address, Identity};
+    pub use crate::{Address, AddressType, Identity, LocalIdentity};
 }
 
 #[cfg(feature = "gatt")]
@@ -113,6 +144,10 @@ pub mod attribute;
 mod attribute_server;
 #[cfg(feature = "gatt")]
 pub mod gatt;
+#[cfg(feature = "gatt")]
+pub mod services;
+#[cfg(feature = "gatt")]
+mod write_queue;
 
 /// A BLE address.
 /// Every BLE device is identified by a unique *Bluetooth Device Address*, which is a 48-bit identifier similar to a MAC address. BLE addresses are categorized into two main types: *Public* and *Random*.
@@ -125,7 +160,7 @@ pub mod gatt;
 /// - *Private Random Address*: Changes periodically for privacy purposes. It can be *Resolvable* (can be linked to the original device using an Identity Resolving Key) or *Non-Resolvable* (completely anonymous).
 ///
 /// Random addresses enhance privacy by preventing device tracking.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Address {
     /// Address type.
     pub kind: AddrKind,
@@ -142,6 +177,83 @@ impl Address {
         }
     }
 
+    /// Create a new public address.
+    pub fn public(val: [u8; 6]) -> Self {
+        Self {
+            kind: AddrKind::PUBLIC,
+            addr: BdAddr::new(val),
+        }
+    }
+
+    /// Create a static random address derived from a unique device identifier.
+    ///
+    /// The two most significant bits are set as required for a static random address, so boards
+    /// flashed with the same firmware advertise with different addresses. The random part must not be
+    /// all zeros or all ones, e.g. for an unprogrammed identifier.
+    pub fn from_device_id<S: DeviceAddressSource + ?Sized>(source: &S) -> Self {
+        let mut val = source.device_id();
+        val[5] |= 0xC0;
+        if val == [0, 0, 0, 0, 0, 0xC0] {
+            val[0] = 0x01;
+        } else if val == [0xFF; 6] {
+            val[0] = 0xFE;
+        }
+        Self::random(val)
+    }
+
+    /// Create a non-resolvable private address from random bits.
+    ///
+    /// The two most significant bits are cleared, and the random part must not be all zeros or all ones.
+    pub fn non_resolvable_private(mut val: [u8; 6]) -> Self {
+        val[5] &= 0x3F;
+        if val == [0; 6] {
+            val[0] = 0x01;
+        } else if val == [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x3F] {
+            val[0] = 0xFE;
+        }
+        Self::random(val)
+    }
+
+    /// Parse an address of the given kind from the colon notation `AA:BB:CC:DD:EE:FF`, most significant byte first.
+    pub fn parse(kind: AddrKind, s: &str) -> Result<Self, Error> {
+        let mut val = [0; 6];
+        let mut parts = s.split(':');
+        for byte in val.iter_mut().rev() {
+            let part = parts.next().ok_or(Error::InvalidValue)?;
+            // `from_str_radix` also accepts a sign, as in `+F`
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Error::InvalidValue);
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| Error::InvalidValue)?;
+        }
+        if parts.next().is_some() {
+            return Err(Error::InvalidValue);
+        }
+        Ok(Self {
+            kind,
+            addr: BdAddr::new(val),
+        })
+    }
+
+    /// The type of address, based on the address kind and the sub-type bits of random addresses.
+    pub fn address_type(&self) -> AddressType {
+        if self.kind != AddrKind::RANDOM {
+            return AddressType::Public;
+        }
+        match self.addr.raw()[5] >> 6 {
+            0b11 => AddressType::StaticRandom,
+            0b01 => AddressType::ResolvablePrivate,
+            0b00 => AddressType::NonResolvablePrivate,
+            _ => AddressType::Reserved,
+        }
+    }
+
+    /// Check whether this is a resolvable private address generated from the given identity resolving key.
+    #[cfg(feature = "security")]
+    pub fn resolves_with(&self, irk: &IdentityResolvingKey) -> bool {
+        self.address_type() == AddressType::ResolvablePrivate && irk.resolve_address(&self.addr)
+    }
+
     /// To bytes
     pub fn to_bytes(&self) -> [u8; 7] {
         let mut bytes = [0; 7];
@@ -151,6 +263,107 @@ impl Address {
         bytes[1..].copy_from_slice(&addr_bytes);
         bytes
     }
+
+    /// The entry to add to the filter accept list of a
+    /// [`ScanConfig`](crate::connection::ScanConfig) to connect to this address.
+    pub fn filter_entry(&self) -> (AddrKind, &BdAddr) {
+        (self.kind, &self.addr)
+    }
+}
+
+/// The address kind to use in the filter accept list for an address of the given kind.
+///
+/// Addresses resolved by the controller are reported with the kind of the identity address, which is the
+/// kind the filter accept list and connection events use.
+pub(crate) fn identity_addr_kind(kind: AddrKind) -> AddrKind {
+    if kind == AddrKind::RESOLVABLE_PRIVATE_OR_PUBLIC {
+        AddrKind::PUBLIC
+    } else if kind == AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM {
+        AddrKind::RANDOM
+    } else {
+        kind
+    }
+}
+
+/// The address of the advertiser of a report, which can be connected to through
+/// [`Address::filter_entry`].
+impl From<&LeAdvReport<'_>> for Address {
+    fn from(report: &LeAdvReport<'_>) -> Self {
+        Self {
+            kind: identity_addr_kind(report.addr_kind),
+            addr: report.addr,
+        }
+    }
+}
+
+/// The address of the advertiser of an extended report, which can be connected to through
+/// [`Address::filter_entry`].
+///
+/// Returns [`Error::InvalidValue`] for anonymous advertising, which has no address.
+impl TryFrom<&LeExtAdvReport<'_>> for Address {
+    type Error = Error;
+
+    fn try_from(report: &LeExtAdvReport<'_>) -> Result<Self, Error> {
+        if report.addr_kind == AddrKind::ANONYMOUS_ADV {
+            return Err(Error::InvalidValue);
+        }
+        Ok(Self {
+            kind: identity_addr_kind(report.addr_kind),
+            addr: report.addr,
+        })
+    }
+}
+
+/// A source of a unique, stable device identifier used to derive the device address.
+///
+/// Implement this for the chip specific identifier, such as the `DEVICEADDR` registers in the nRF FICR
+/// or the factory MAC in the ESP32 efuse, and pass it to [`Stack::set_device_address`].
+pub trait DeviceAddressSource {
+    /// The device identifier, least significant byte first.
+    fn device_id(&self) -> [u8; 6];
+}
+
+/// An identifier obtained by other means, such as a MAC address read from the radio firmware.
+impl DeviceAddressSource for [u8; 6] {
+    fn device_id(&self) -> [u8; 6] {
+        *self
+    }
+}
+
+/// Schedules flash operations around radio activity.
+///
+/// Erasing or writing internal flash stalls the CPU, and on controllers sharing it with the host, such as
+/// the nRF SoftDevice Controller, this breaks the timing of connection events and drops connections.
+/// Wait on a gate before persisting bonds (e.g. a [`Stack::snapshot`]), and perform the flash operation
+/// right after it returns, without awaiting in between.
+///
+/// [`Stack`] implements this from the connection event anchors reported with
+/// [`Stack::set_connection_anchor`]. On nRF, an implementation requesting an MPSL timeslot is more robust.
+pub trait FlashGate {
+    /// Wait until a flash operation taking at most `duration` can run without disturbing radio activity.
+    fn acquire(&self, duration: embassy_time::Duration) -> impl core::future::Future<Output = Result<(), Error>>;
+}
+
+/// Type of a Bluetooth device address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressType {
+    /// Public address assigned by the IEEE.
+    Public,
+    /// Static random address, fixed until the device restarts.
+    StaticRandom,
+    /// Resolvable private address, which can be resolved with an identity resolving key.
+    ResolvablePrivate,
+    /// Non-resolvable private address.
+    NonResolvablePrivate,
+    /// Random address with the reserved sub-type bits.
+    Reserved,
+}
+
+impl core::fmt::Debug for Address {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Address({:?}, {})", self.address_type(), self)
+    }
 }
 
 impl core::fmt::Display for Address {
@@ -181,6 +394,33 @@ impl defmt::Format for Address {
     }
 }
 
+/// Local identity of the device, as the identity address and the key to resolve its private addresses.
+///
+/// A device can have several identities, set with [`Stack::set_identities`] and selected per advertising set
+/// with [`AdvertisementParameters::identity`](crate::advertise::AdvertisementParameters::identity), to appear
+/// as different products to different peers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LocalIdentity {
+    /// Public or random static identity address
+    pub address: Address,
+
+    /// Identity Resolving Key, distributed to peers that bond with this identity
+    #[cfg(feature = "security")]
+    pub irk: Option<IdentityResolvingKey>,
+}
+
+impl LocalIdentity {
+    /// Create a local identity without an Identity Resolving Key.
+    pub fn new(address: Address) -> Self {
+        Self {
+            address,
+            #[cfg(feature = "security")]
+            irk: None,
+        }
+    }
+}
+
 /// Identity of a peer device
 ///
 /// Sometimes we have to save both the address and the IRK.
@@ -234,6 +474,40 @@ impl Identity {
         }
         false
     }
+
+    /// Write the identity to a snapshot, as the address followed by an optional IRK.
+    pub(crate) fn write_snapshot(&self, w: &mut cursor::WriteCursor<'_>) -> Result<(), Error> {
+        w.append(self.bd_addr.raw())?;
+        #[cfg(feature = "security")]
+        if let Some(irk) = self.irk {
+            w.write(1u8)?;
+            w.append(&irk.0.to_le_bytes())?;
+            return Ok(());
+        }
+        w.write(0u8)?;
+        Ok(())
+    }
+
+    /// Read an identity written by [`Identity::write_snapshot`].
+    pub(crate) fn read_snapshot(r: &mut cursor::ReadCursor<'_>) -> Result<Self, Error> {
+        let bd_addr = BdAddr::new(unwrap!(r.slice(6)?.try_into()));
+        let has_irk: u8 = r.read()?;
+        let irk = if has_irk != 0 {
+            Some(u128::from_le_bytes(unwrap!(r.slice(16)?.try_into())))
+        } else {
+            None
+        };
+        #[cfg(feature = "security")]
+        return Ok(Self {
+            bd_addr,
+            irk: irk.map(IdentityResolvingKey::new),
+        });
+        #[cfg(not(feature = "security"))]
+        match irk {
+            Some(_) => Err(Error::InvalidValue),
+            None => Ok(Self { bd_addr }),
+        }
+    }
 }
 
 /// Errors returned by the host.
@@ -252,6 +526,7 @@ pub const MAX_INVALID_DATA_LEN: usize = 16;
 /// Errors related to Host.
 #[derive(Debug, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 pub enum Error {
     /// Error encoding parameters for HCI commands.
     Hci(bt_hci::param::Error),
@@ -335,6 +610,13 @@ pub enum Error {
     NotSupported,
     /// L2cap channel closed.
     ChannelClosed,
+    /// An SDU announced by the peer exceeds the MTU configured for the L2CAP channel.
+    SduTooLarge {
+        /// Maximum SDU size of the channel.
+        max: u16,
+        /// Announced SDU size.
+        actual: u16,
+    },
     /// Operation timed out.
     Timeout,
     /// Controller is busy.
@@ -349,10 +631,255 @@ pub enum Error {
     ///
     /// The limit can be modified using the `gatt-client-notification-max-subscribers-N` features.
     GattSubscriberLimitReached,
+    /// A scan is in progress, which conflicts with initiating a connection.
+    ScanInProgress,
+    /// A connection is being initiated, which conflicts with scanning.
+    InitiatingInProgress,
+    /// The peer violated the protocol, and is disconnected in the strict mode.
+    ProtocolViolation(ProtocolLayer),
     /// Other error.
     Other,
 }
 
+/// Protocol layer in which a peer violated the protocol, see [`Error::ProtocolViolation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ProtocolLayer {
+    /// L2CAP framing or fixed channels.
+    L2cap,
+    /// Attribute protocol.
+    Att,
+    /// Security manager protocol.
+    #[cfg(feature = "security")]
+    Smp,
+}
+
+/// The subsystem and actionable cause of an [`Error`].
+///
+/// Obtained with [`Error::kind`] or [`BleHostError::kind`], so that applications can match on the cause
+/// of an error without knowing which [`Error`] variants each subsystem produces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The controller rejected a command with an HCI error code not specific to a subsystem.
+    Hci(bt_hci::param::Error),
+    /// Advertising error.
+    Advertise(AdvertiseError),
+    /// Connection establishment or link error.
+    Connect(ConnectError),
+    /// GATT or ATT error.
+    Gatt(GattError),
+    /// L2CAP channel error.
+    L2cap(L2capError),
+    /// Security manager error.
+    #[cfg(feature = "security")]
+    Security(SecurityError),
+    /// Host resources such as packets or buffers are exhausted.
+    Resources,
+    /// Operation timed out.
+    Timeout,
+    /// Invalid argument, state or data not specific to a subsystem.
+    Other,
+}
+
+/// Advertising errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum AdvertiseError {
+    /// Extended advertising is not supported by the controller.
+    ExtendedNotSupported,
+    /// The advertisement data is invalid.
+    InvalidData(AdvertisementDataError),
+    /// Advertising failed with an HCI error code, such as an unknown advertising set.
+    Hci(bt_hci::param::Error),
+}
+
+/// Connection errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum ConnectError {
+    /// No more connections can be established.
+    LimitReached,
+    /// Connecting with a filter accept list requires at least one entry.
+    EmptyFilterAcceptList,
+    /// The connection is disconnected.
+    Disconnected,
+    /// A connection could not be initiated because a scan is in progress.
+    ScanInProgress,
+    /// A scan could not be started because a connection is being initiated.
+    InitiatingInProgress,
+    /// The connection failed with an HCI error code, such as an unknown connection or a timeout.
+    Hci(bt_hci::param::Error),
+}
+
+/// GATT errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum GattError {
+    /// The peer or the local attribute server responded with an ATT error code.
+    Att(AttErrorCode),
+    /// The peer sent an unexpected or malformed response.
+    InvalidResponse,
+    /// The value does not match the characteristic type.
+    InvalidValue,
+    /// No more notification subscribers can be registered.
+    SubscriberLimitReached,
+    /// The peer violated the attribute protocol, and was disconnected.
+    ProtocolViolation,
+}
+
+/// L2CAP channel errors.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum L2capError {
+    /// The channel id is not valid.
+    InvalidChannelId,
+    /// No channel is available.
+    NoChannelAvailable,
+    /// The channel is closed.
+    Closed,
+    /// The peer announced an SDU larger than the channel MTU.
+    SduTooLarge {
+        /// Maximum SDU size of the channel.
+        max: u16,
+        /// Announced SDU size.
+        actual: u16,
+    },
+    /// The peer has not granted credits to send.
+    NoCredits,
+    /// The peer violated the L2CAP framing, and was disconnected.
+    ProtocolViolation,
+}
+
+/// Security manager errors.
+#[cfg(feature = "security")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
+pub enum SecurityError {
+    /// Pairing failed with an SMP reason code.
+    Pairing(crate::security_manager::Reason),
+    /// The peer violated the security manager protocol, and was disconnected.
+    ProtocolViolation,
+    /// Link security failed with an HCI error code, such as a missing key.
+    Hci(bt_hci::param::Error),
+}
+
+impl Error {
+    /// The subsystem and actionable cause of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Hci(e) => hci_kind(*e),
+            Error::Att(code) => ErrorKind::Gatt(GattError::Att(*code)),
+            #[cfg(feature = "security")]
+            Error::Security(reason) => ErrorKind::Security(SecurityError::Pairing(*reason)),
+            Error::ExtendedAdvertisingNotSupported => ErrorKind::Advertise(AdvertiseError::ExtendedNotSupported),
+            Error::Advertisement(e) => ErrorKind::Advertise(AdvertiseError::InvalidData(*e)),
+            Error::ConnectionLimitReached => ErrorKind::Connect(ConnectError::LimitReached),
+            Error::ScanInProgress => ErrorKind::Connect(ConnectError::ScanInProgress),
+            Error::InitiatingInProgress => ErrorKind::Connect(ConnectError::InitiatingInProgress),
+            Error::ProtocolViolation(ProtocolLayer::L2cap) => ErrorKind::L2cap(L2capError::ProtocolViolation),
+            Error::ProtocolViolation(ProtocolLayer::Att) => ErrorKind::Gatt(GattError::ProtocolViolation),
+            #[cfg(feature = "security")]
+            Error::ProtocolViolation(ProtocolLayer::Smp) => ErrorKind::Security(SecurityError::ProtocolViolation),
+            Error::ConfigFilterAcceptListIsEmpty => ErrorKind::Connect(ConnectError::EmptyFilterAcceptList),
+            Error::Disconnected => ErrorKind::Connect(ConnectError::Disconnected),
+            Error::UnexpectedGattResponse
+            | Error::MalformedCharacteristicDeclaration { .. }
+            | Error::InvalidCharacteristicDeclarationData
+            | Error::InvalidCccdHandleLength(_) => ErrorKind::Gatt(GattError::InvalidResponse),
+            Error::UnexpectedDataLength { .. } | Error::CannotConstructGattValue(_) => {
+                ErrorKind::Gatt(GattError::InvalidValue)
+            }
+            Error::GattSubscriberLimitReached => ErrorKind::Gatt(GattError::SubscriberLimitReached),
+            Error::InvalidChannelId => ErrorKind::L2cap(L2capError::InvalidChannelId),
+            Error::NoChannelAvailable => ErrorKind::L2cap(L2capError::NoChannelAvailable),
+            Error::ChannelClosed => ErrorKind::L2cap(L2capError::Closed),
+            Error::SduTooLarge { max, actual } => ErrorKind::L2cap(L2capError::SduTooLarge {
+                max: *max,
+                actual: *actual,
+            }),
+            Error::NoPermits => ErrorKind::L2cap(L2capError::NoCredits),
+            Error::OutOfMemory | Error::InsufficientSpace | Error::Busy => ErrorKind::Resources,
+            Error::Timeout => ErrorKind::Timeout,
+            Error::HciDecode(_)
+            | Error::InvalidValue
+            | Error::FailedToFinalize { .. }
+            | Error::CodecError(_)
+            | Error::InvalidUuidLength(_)
+            | Error::NotFound
+            | Error::InvalidState
+            | Error::NotSupported
+            | Error::Other => ErrorKind::Other,
+        }
+    }
+}
+
+// Attribute HCI error codes that only occur in one subsystem to it.
+fn hci_kind(error: bt_hci::param::Error) -> ErrorKind {
+    use bt_hci::param::Error as Hci;
+    match error {
+        Hci::ADV_TIMEOUT | Hci::UNKNOWN_ADV_IDENTIFIER | Hci::LIMIT_REACHED => {
+            ErrorKind::Advertise(AdvertiseError::Hci(error))
+        }
+        Hci::UNKNOWN_CONN_IDENTIFIER
+        | Hci::CONN_TIMEOUT
+        | Hci::CONN_LIMIT_EXCEEDED
+        | Hci::CONN_REJECTED_LIMITED_RESOURCES
+        | Hci::CONN_REJECTED_UNACCEPTABLE_BD_ADDR
+        | Hci::CONN_ACCEPT_TIMEOUT_EXCEEDED
+        | Hci::CONN_FAILED_SYNCHRONIZATION_TIMEOUT
+        | Hci::CONN_REJECTED_NO_SUITABLE_CHANNEL_FOUND => ErrorKind::Connect(ConnectError::Hci(error)),
+        #[cfg(feature = "security")]
+        Hci::AUTHENTICATION_FAILURE
+        | Hci::PIN_OR_KEY_MISSING
+        | Hci::CONN_REJECTED_SECURITY_REASONS
+        | Hci::PAIRING_NOT_ALLOWED
+        | Hci::ENCRYPTION_MODE_NOT_ACCEPTABLE
+        | Hci::INSUFFICIENT_SECURITY => ErrorKind::Security(SecurityError::Hci(error)),
+        _ => ErrorKind::Hci(error),
+    }
+}
+
+impl<E> BleHostError<E> {
+    /// The subsystem and actionable cause of this error, or `None` for controller transport errors.
+    pub fn kind(&self) -> Option<ErrorKind> {
+        match self {
+            BleHostError::Controller(_) => None,
+            BleHostError::BleHost(e) => Some(e.kind()),
+        }
+    }
+}
+
+impl embedded_io::Error for Error {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Error::Disconnected => embedded_io::ErrorKind::NotConnected,
+            Error::ChannelClosed => embedded_io::ErrorKind::BrokenPipe,
+            Error::Timeout => embedded_io::ErrorKind::TimedOut,
+            Error::OutOfMemory | Error::InsufficientSpace => embedded_io::ErrorKind::OutOfMemory,
+            Error::InvalidValue => embedded_io::ErrorKind::InvalidInput,
+            Error::NotFound => embedded_io::ErrorKind::NotFound,
+            Error::NotSupported => embedded_io::ErrorKind::Unsupported,
+            _ => embedded_io::ErrorKind::Other,
+        }
+    }
+}
+
+impl<E: embedded_io::Error> embedded_io::Error for BleHostError<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            BleHostError::Controller(e) => e.kind(),
+            BleHostError::BleHost(e) => embedded_io::Error::kind(e),
+        }
+    }
+}
+
 impl<E> From<Error> for BleHostError<E> {
     fn from(value: Error) -> Self {
         Self::BleHost(value)
@@ -484,6 +1011,63 @@ impl<
 {
 }
 
+/// Controller that can change the PHY of connections, see [`Connection::set_phy`](crate::prelude::Connection::set_phy).
+pub trait ControllerWithPhy:
+    Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>
+{
+}
+
+impl<C: Controller + ControllerCmdAsync<LeSetPhy> + ControllerCmdSync<LeReadLocalSupportedFeatures>> ControllerWithPhy
+    for C
+{
+}
+
+/// Controller that can change the data length of connections, see
+/// [`Connection::update_data_length`](crate::prelude::Connection::update_data_length).
+pub trait ControllerWithDle:
+    Controller
+    + ControllerCmdSync<LeSetDataLength>
+    + ControllerCmdSync<LeReadMaxDataLength>
+    + ControllerCmdSync<LeReadLocalSupportedFeatures>
+{
+}
+
+impl<
+        C: Controller
+            + ControllerCmdSync<LeSetDataLength>
+            + ControllerCmdSync<LeReadMaxDataLength>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    > ControllerWithDle for C
+{
+}
+
+/// Controller supporting extended advertising, see
+/// [`Peripheral::advertise_ext`](crate::prelude::Peripheral::advertise_ext).
+pub trait ControllerWithExtAdv:
+    Controller
+    + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+    + ControllerCmdSync<LeClearAdvSets>
+    + ControllerCmdSync<LeSetExtAdvParams>
+    + ControllerCmdSync<LeSetAdvSetRandomAddr>
+    + ControllerCmdSync<LeReadNumberOfSupportedAdvSets>
+    + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+    + ControllerCmdSync<LeReadLocalSupportedFeatures>
+{
+}
+
+impl<
+        C: Controller
+            + for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
+            + ControllerCmdSync<LeClearAdvSets>
+            + ControllerCmdSync<LeSetExtAdvParams>
+            + ControllerCmdSync<LeSetAdvSetRandomAddr>
+            + ControllerCmdSync<LeReadNumberOfSupportedAdvSets>
+            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>
+            + ControllerCmdSync<LeReadLocalSupportedFeatures>,
+    > ControllerWithExtAdv for C
+{
+}
+
 /// A Packet is a byte buffer for packet data.
 /// Similar to a `Vec<u8>` it has a length and a capacity.
 pub trait Packet: Sized + AsRef<[u8]> + AsMut<[u8]> {}
@@ -507,6 +1091,22 @@ pub trait PacketPool: 'static {
     /// amount of bytes it has received.
     fn allocate() -> Option<Self::Packet>;
 
+    /// Allocate a new buffer with space for `MTU` bytes, waiting until one is available.
+    ///
+    /// The default implementation tries [`allocate`](Self::allocate) each time it is polled, and
+    /// wakes itself to be polled again after yielding to other tasks. Pools that can, should
+    /// instead register the waker of a waiting task and wake it when a buffer is freed, serving
+    /// waiters in the order they started waiting so that a single task cannot monopolize freed buffers.
+    fn allocate_async() -> impl core::future::Future<Output = Self::Packet> {
+        poll_fn(|cx| match Self::allocate() {
+            Some(packet) => core::task::Poll::Ready(packet),
+            None => {
+                cx.waker().wake_by_ref();
+                core::task::Poll::Pending
+            }
+        })
+    }
+
     /// Capacity of this pool in the number of packets.
     fn capacity() -> usize;
 }
@@ -515,10 +1115,23 @@ pub trait PacketPool: 'static {
 ///
 /// The l2cap packet pool is used by the host to handle inbound data, by allocating space for
 /// incoming packets and dispatching to the appropriate connection and channel.
+///
+/// The const generics determine how the packet pool is shared:
+///
+/// - `CONNS`: every connection may hold one packet for reassembling fragmented ACL data.
+/// - `CHANNELS`: every L2CAP channel may hold at most [`HostResources::rx_quota`] packets, by default
+///   `PacketPool::capacity() / CHANNELS` (at least 1), for SDU reassembly and its inbound queue. The
+///   credits granted to the peer are capped to this quota and to the `l2cap-rx-queue-size-N` setting, so
+///   a single peer cannot exhaust the pool and deny service to other channels. SDUs larger than the
+///   MTU of the channel are rejected with [`Error::SduTooLarge`].
+///
+/// Inbound and outbound data share the packet pool. Use [`HostResources::with_rx_packets`] to limit
+/// the packets held by inbound channel data, so that the remainder stays available for sending.
 pub struct HostResources<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: usize = 1> {
     connections: MaybeUninit<[ConnectionStorage<P::Packet>; CONNS]>,
     channels: MaybeUninit<[ChannelStorage<P::Packet>; CHANNELS]>,
     advertise_handles: MaybeUninit<[AdvHandleState; ADV_SETS]>,
+    rx_packets: usize,
 }
 
 impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: usize> Default
@@ -529,17 +1142,75 @@ impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: u
     }
 }
 
+/// RAM layout of [`HostResources`] for a given set of const generics.
+///
+/// The packet pool is allocated separately from the host resources and is not included in
+/// `total_bytes`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MemoryLayout {
+    /// Bytes used by each connection slot.
+    pub connection_bytes: usize,
+    /// Bytes used by each L2CAP channel slot.
+    pub channel_bytes: usize,
+    /// Bytes used by each advertising set slot.
+    pub adv_set_bytes: usize,
+    /// Bytes of payload in each packet of the packet pool.
+    pub packet_bytes: usize,
+    /// Total bytes used by the host resources.
+    pub total_bytes: usize,
+}
+
 impl<P: PacketPool, const CONNS: usize, const CHANNELS: usize, const ADV_SETS: usize>
     HostResources<P, CONNS, CHANNELS, ADV_SETS>
 {
+    /// Bytes used by each connection slot.
+    pub const CONNECTION_BYTES: usize = core::mem::size_of::<ConnectionStorage<P::Packet>>();
+    /// Bytes used by each L2CAP channel slot.
+    pub const CHANNEL_BYTES: usize = core::mem::size_of::<ChannelStorage<P::Packet>>();
+    /// Bytes used by each advertising set slot.
+    pub const ADV_SET_BYTES: usize = core::mem::size_of::<AdvHandleState>();
+    /// Total bytes used by the host resources, excluding the packet pool.
+    pub const TOTAL_BYTES: usize = core::mem::size_of::<Self>();
+
+    /// RAM layout of the host resources.
+    pub const fn memory_layout() -> MemoryLayout {
+        MemoryLayout {
+            connection_bytes: Self::CONNECTION_BYTES,
+            channel_bytes: Self::CHANNEL_BYTES,
+            adv_set_bytes: Self::ADV_SET_BYTES,
+            packet_bytes: P::MTU,
+            total_bytes: Self::TOTAL_BYTES,
+        }
+    }
+
     /// Create a new instance of host resources.
     pub const fn new() -> Self {
         Self {
             connections: MaybeUninit::uninit(),
             channels: MaybeUninit::uninit(),
             advertise_handles: MaybeUninit::uninit(),
+            rx_packets: usize::MAX,
         }
     }
+
+    /// Limit the packets of the packet pool used to receive data on L2CAP channels.
+    ///
+    /// The limit is split evenly among the `CHANNELS` channels, see [`rx_quota`](Self::rx_quota).
+    /// Defaults to the capacity of the packet pool.
+    pub const fn with_rx_packets(mut self, packets: usize) -> Self {
+        self.rx_packets = packets;
+        self
+    }
+
+    /// Packets each L2CAP channel may hold for SDU reassembly and its inbound queue.
+    ///
+    /// The packets set with [`with_rx_packets`](Self::with_rx_packets), at most the capacity of the packet pool,
+    /// split evenly among the `CHANNELS` channels, with at least 1 packet per channel. The credits granted to the
+    /// peer of a channel, including `L2capChannelConfig::initial_credits`, are capped to this quota.
+    pub fn rx_quota(&self) -> u16 {
+        (P::capacity().min(self.rx_packets) / CHANNELS.max(1)).clamp(1, u16::MAX as usize) as u16
+    }
 }
 
 /// Create a new instance of the BLE host using the provided controller implementation and
@@ -565,6 +1236,7 @@ pub fn new<
     // - This _should_ be OK, because there are no references held to the resources
     //   when the stack is shut down.
 
+    let rx_quota = resources.rx_quota();
     let connections: &mut [ConnectionStorage<P::Packet>] =
         &mut *resources.connections.write([const { ConnectionStorage::new() }; CONNS]);
     let connections: &'resources mut [ConnectionStorage<P::Packet>] = unsafe { transmute_slice(connections) };
@@ -574,7 +1246,7 @@ pub fn new<
 
     let advertise_handles = &mut *resources.advertise_handles.write([AdvHandleState::None; ADV_SETS]);
     let advertise_handles: &'static mut [AdvHandleState] = unsafe { transmute_slice(advertise_handles) };
-    let host: BleHost<'_, C, P> = BleHost::new(controller, connections, channels, advertise_handles);
+    let host: BleHost<'_, C, P> = BleHost::new(controller, connections, channels, rx_quota, advertise_handles);
 
     Stack { host }
 }
@@ -597,14 +1269,136 @@ pub struct Host<'stack, C, P: PacketPool> {
     pub runner: Runner<'stack, C, P>,
 }
 
+/// Time reserved for a connection event when scheduling flash operations.
+const FLASH_GATE_GUARD: embassy_time::Duration = embassy_time::Duration::from_millis(2);
+
+impl<C: Controller, P: PacketPool> FlashGate for Stack<'_, C, P> {
+    /// Wait for a gap between the connection events of all connections with a known anchor.
+    ///
+    /// Connections without a reported anchor are not taken into account. Returns `Error::InvalidValue`
+    /// if `duration` does not fit in a connection interval, or if the connection events leave no common gap
+    /// within the longest connection interval.
+    async fn acquire(&self, duration: embassy_time::Duration) -> Result<(), Error> {
+        while let Some(start) =
+            self.host
+                .connections
+                .flash_window(embassy_time::Instant::now(), duration, FLASH_GATE_GUARD)?
+        {
+            embassy_time::Timer::at(start).await;
+        }
+        Ok(())
+    }
+}
+
+impl<C: Controller, P: PacketPool> CoexHints for Stack<'_, C, P> {
+    fn bias_event_length(&self, max: Option<embassy_time::Duration>) {
+        self.host.coex.set_max_event_length(max);
+    }
+
+    fn pause_scanning(&self, window: embassy_time::Duration) {
+        self.host.coex.pause_scanning(window);
+    }
+
+    fn deprioritize_advertising(&self, window: embassy_time::Duration) {
+        self.host.coex.deprioritize_advertising(window);
+    }
+}
+
 impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
     /// Set the random address used by this host.
-    pub fn set_random_address(mut self, address: Address) -> Self {
+    ///
+    /// Takes an [`Address`] as built by [`Address::random`] or [`Address::from_device_id`], and behaves like
+    /// [`set_address`](Self::set_address): the kind of the address is used as given.
+    pub fn set_random_address(self, address: Address) -> Self {
+        self.set_address(address)
+    }
+
+    /// Set the public address of the controller used by this host.
+    ///
+    /// Unlike [`set_random_address`](Self::set_random_address), this takes the bare [`BdAddr`], as the kind is
+    /// always [`AddrKind::PUBLIC`]. The address must match the public address of the controller, it is not written
+    /// to the controller.
+    pub fn set_public_address(self, addr: BdAddr) -> Self {
+        self.set_address(Address {
+            kind: AddrKind::PUBLIC,
+            addr,
+        })
+    }
+
+    /// Set the address used by this host.
+    ///
+    /// The address kind determines the own address type used when advertising, scanning and connecting. A random
+    /// address is written to the controller when the host starts.
+    pub fn set_address(mut self, address: Address) -> Self {
         self.host.address.replace(address);
         #[cfg(feature = "security")]
         self.host.connections.security_manager.set_local_address(address);
         self
     }
+    /// Set the random address used by this host to a static address derived from the device identifier.
+    pub fn set_device_address<S: DeviceAddressSource + ?Sized>(self, source: &S) -> Self {
+        self.set_random_address(Address::from_device_id(source))
+    }
+
+    /// Set the local identities that advertising sets can use instead of the host address.
+    pub fn set_identities(mut self, identities: &'stack [LocalIdentity]) -> Self {
+        self.host.identities = identities;
+        self
+    }
+
+    /// Set the interval between changes of the private address of advertising sets, 15 minutes by default.
+    ///
+    /// Used by advertising sets with [`AdvertisementParameters::private_address`](crate::advertise::AdvertisementParameters::private_address).
+    pub fn set_private_address_interval(self, interval: embassy_time::Duration) -> Self {
+        self.host.advertise_state.set_private_interval(interval);
+        self
+    }
+
+    /// Generate and change the private addresses of advertising sets, never returns.
+    ///
+    /// Advertising sets with [`AdvertisementParameters::private_address`](crate::advertise::AdvertisementParameters::private_address)
+    /// are only enabled once this has given them an address, so it must run alongside the runner. Errors are
+    /// logged and the address is changed again at the next interval.
+    pub async fn rotate_private_addresses(&self)
+    where
+        C: ControllerWithExtAdv + ControllerCmdSync<LeRand>,
+    {
+        self.host.rotate_private_addresses().await
+    }
+
+    /// Disconnect peers on clearly invalid behaviour instead of tolerating it.
+    ///
+    /// In the strict mode L2CAP frames with bad lengths or on unsupported fixed channels, undecodable ATT PDUs,
+    /// ATT requests sent while another is pending, ATT responses without a request and pairing PDUs received
+    /// once pairing has completed are logged and the connection is terminated, as required by security
+    /// hardening guidelines for exposed devices. Disabled by default.
+    pub fn set_strict_mode(self, enabled: bool) -> Self {
+        self.host.strict.set(enabled);
+        self
+    }
+
+    /// Set the power control of the transport, put to sleep by the runner while idle and woken before
+    /// anything is written to the controller.
+    pub fn set_controller_power(self, power: &'stack dyn ControllerPower) -> Self {
+        self.host.controller_sleep.set_power(power);
+        self
+    }
+
+    /// Set the coexistence manager notified of upcoming high priority activity.
+    pub fn set_coex_observer(self, observer: &'stack dyn CoexObserver) -> Self {
+        self.host.coex.set_observer(observer);
+        self
+    }
+
+    /// Set the maximum number of controller ACL buffers that a single connection may use at a time.
+    ///
+    /// By default, the buffers are shared equally between the connected peers, so a connection with a slow peer
+    /// cannot stall the others.
+    pub fn set_tx_window(self, packets: usize) -> Self {
+        self.host.connections.set_tx_window(packets);
+        self
+    }
+
     /// Set the random generator seed for random generator used by security manager
     pub fn set_random_generator_seed<RNG: RngCore + CryptoRng>(self, _random_generator: &mut RNG) -> Self {
         #[cfg(feature = "security")]
@@ -619,6 +1413,39 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
         self
     }
 
+    #[cfg(feature = "security")]
+    /// Set the security requirements enforced by the security manager when pairing
+    pub fn set_security_policy(self, policy: SecurityPolicy) -> Self {
+        self.host.connections.security_manager.set_policy(policy);
+        self
+    }
+
+    #[cfg(feature = "insecure-fixed-pairing")]
+    /// Pair using passkey entry with a fixed passkey, for closed systems provisioned at manufacturing.
+    ///
+    /// The peripheral reports the passkey as displayed and the central as entered, so two devices configured
+    /// with the same passkey pair without user interaction. A fixed passkey is easily recovered by an
+    /// eavesdropper of a single pairing, so it only protects against peers that never learned it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the passkey is larger than 999999.
+    pub fn set_fixed_passkey(self, passkey: u32) -> Self {
+        assert!(passkey <= 999_999, "passkey must have at most 6 digits");
+        self.host.connections.security_manager.set_fixed_passkey(passkey);
+        self
+    }
+
+    #[cfg(feature = "insecure-fixed-pairing")]
+    /// Encrypt connections to peers without a bond using a pre-shared long term key, instead of pairing.
+    ///
+    /// Every device of a closed system is provisioned with the same key, and anyone who extracts it can
+    /// decrypt the connections of all devices.
+    pub fn set_preshared_ltk(self, ltk: LongTermKey) -> Self {
+        self.host.connections.security_manager.set_preshared_ltk(ltk);
+        self
+    }
+
     /// Build the stack.
     pub fn build(&'stack self) -> Host<'stack, C, P> {
         #[cfg(all(feature = "security", not(feature = "dev-disable-csprng-seed-requirement")))]
@@ -661,6 +1488,215 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
         self.host.metrics(f)
     }
 
+    /// Optional capabilities of the controller, once the runner has initialized it.
+    pub fn capabilities(&self) -> Option<ControllerCapabilities> {
+        self.host.capabilities()
+    }
+
+    /// Read the maximum data length supported by the controller.
+    ///
+    /// A controller without the LE Read Maximum Data Length command is assumed not to support data
+    /// length extension, and the spec defined 27 octets and 328 µs are returned for both directions.
+    pub async fn max_data_length(&self) -> Result<LeReadMaxDataLengthReturn, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeReadMaxDataLength>,
+    {
+        Ok(
+            host::optional(self.host.command(LeReadMaxDataLength::new()).await)?.unwrap_or(LeReadMaxDataLengthReturn {
+                supported_max_tx_octets: 27,
+                supported_max_tx_time: 328,
+                supported_max_rx_octets: 27,
+                supported_max_rx_time: 328,
+            }),
+        )
+    }
+
+    /// Read the liveness of the host, as reported to a [`HealthCheck`](crate::prelude::HealthCheck).
+    pub fn liveness(&self) -> Liveness {
+        self.host.liveness()
+    }
+
+    /// Report the time of a connection event anchor point.
+    ///
+    /// Controllers that expose connection event timing, typically through vendor events delivered to
+    /// [`EventHandler::on_vendor`](crate::prelude::EventHandler::on_vendor), can report it here to make
+    /// [`Connection::next_event_anchor`](crate::prelude::Connection::next_event_anchor) available.
+    pub fn set_connection_anchor(&self, handle: ConnHandle, anchor: embassy_time::Instant) -> Result<(), Error> {
+        self.host.connections.set_anchor(handle, anchor)
+    }
+
+    /// Serialize the state of the stack into `buf` before a RAM-off sleep, returning the number of bytes written.
+    ///
+    /// The snapshot holds the bonded devices, so links can be encrypted again after [`restore`](Self::restore)
+    /// without pairing. Signing keys are included with their sign counters, take a new snapshot after signed
+    /// writes so that counters are not reused. The GATT server state is saved separately, with `AttributeServer::snapshot`.
+    ///
+    /// Returns `Error::InvalidState` if a connection is still in use.
+    pub fn snapshot(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.host.connections.has_links() {
+            return Err(Error::InvalidState);
+        }
+        let mut w = cursor::WriteCursor::new(buf);
+        w.write(STACK_SNAPSHOT_VERSION)?;
+        #[cfg(feature = "security")]
+        {
+            let bonds = self.host.connections.security_manager.get_bond_information();
+            w.write(bonds.len() as u8)?;
+            for bond in bonds.iter() {
+                bond.identity.write_snapshot(&mut w)?;
+                w.append(&bond.ltk.0.to_le_bytes())?;
+                w.write(bond.key_size)?;
+                // Signing keys, with their sign counters
+                w.write(u8::from(bond.peer_csrk.is_some()) | (u8::from(bond.local_csrk.is_some()) << 1))?;
+                for key in [bond.peer_csrk, bond.local_csrk].iter().flatten() {
+                    w.append(&key.csrk.to_le_bytes())?;
+                    w.write(key.counter)?;
+                }
+            }
+        }
+        #[cfg(not(feature = "security"))]
+        w.write(0u8)?;
+        Ok(w.len())
+    }
+
+    /// Restore the state serialized by [`snapshot`](Self::snapshot), before connecting.
+    ///
+    /// The bonds of the snapshot are added to the known bonds. If the snapshot is corrupt or truncated,
+    /// `Error::InvalidValue` is returned, and if the bonds do not fit, `Error::OutOfMemory`. In both cases
+    /// the stack is left unchanged.
+    pub fn restore(&self, buf: &[u8]) -> Result<(), Error> {
+        let bonds = Self::read_snapshot(buf).map_err(|_| Error::InvalidValue)?;
+        #[cfg(feature = "security")]
+        self.host.connections.security_manager.add_bonds(&bonds)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "security")]
+    fn read_snapshot(buf: &[u8]) -> Result<Vec<BondInformation, BI_COUNT>, Error> {
+        let mut r = cursor::ReadCursor::new(buf);
+        let version: u8 = r.read()?;
+        if version != STACK_SNAPSHOT_VERSION {
+            return Err(Error::InvalidValue);
+        }
+        let count: u8 = r.read()?;
+        let mut bonds = Vec::new();
+        for _ in 0..count {
+            let identity = Identity::read_snapshot(&mut r)?;
+            let ltk = u128::from_le_bytes(unwrap!(r.slice(16)?.try_into()));
+            let mut bond = BondInformation::new(identity, LongTermKey::new(ltk));
+            bond.key_size = r.read()?;
+            let keys: u8 = r.read()?;
+            if keys & !0x03 != 0 {
+                return Err(Error::InvalidValue);
+            }
+            let mut read_key = |present: bool| -> Result<Option<SigningKey>, Error> {
+                if !present {
+                    return Ok(None);
+                }
+                let csrk = ConnectionSignatureResolvingKey::from_le_bytes(unwrap!(r.slice(16)?.try_into()));
+                Ok(Some(SigningKey {
+                    csrk,
+                    counter: r.read()?,
+                }))
+            };
+            bond.peer_csrk = read_key(keys & 0x01 != 0)?;
+            bond.local_csrk = read_key(keys & 0x02 != 0)?;
+            bonds.push(bond).map_err(|_| Error::InvalidValue)?;
+        }
+        if r.available() != 0 {
+            return Err(Error::InvalidValue);
+        }
+        Ok(bonds)
+    }
+
+    #[cfg(not(feature = "security"))]
+    fn read_snapshot(buf: &[u8]) -> Result<(), Error> {
+        match buf {
+            [STACK_SNAPSHOT_VERSION, 0] => Ok(()),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    /// Suspend the stack, so the controller can be powered down.
+    ///
+    /// Stops advertising, scanning and connecting. Open connections are disconnected if `disconnect` is set,
+    /// otherwise `Error::InvalidState` is returned while a connection is open. Returns once the [`Runner`], or
+    /// each of the runners returned by [`Runner::split`], has returned `Ok(())`. HCI commands then wait until
+    /// the stack is resumed and the controller initialized again.
+    pub async fn suspend(&self, disconnect: bool) -> Result<(), Error> {
+        let host = &self.host;
+        if host.connections.has_links() {
+            if !disconnect {
+                return Err(Error::InvalidState);
+            }
+            host.connections
+                .request_disconnect_all(DisconnectReason::RemoteDeviceTerminatedConnPowerOff);
+            poll_fn(|cx| host.connections.poll_all_disconnected(cx)).await;
+        }
+        for state in [
+            &host.advertise_command_state,
+            &host.scan_command_state,
+            &host.connect_command_state,
+        ] {
+            if state.cancel_active() {
+                state.wait_idle().await;
+            }
+        }
+        host.request_suspend();
+        poll_fn(|cx| host.poll_suspended(cx)).await;
+        Ok(())
+    }
+
+    /// Resume the stack after [`suspend`](Self::suspend), once the controller is powered up again.
+    ///
+    /// Run the [`Runner`] again, which resets and initializes the controller, and await this to wait until
+    /// the controller is ready for commands.
+    pub async fn resume(&self) {
+        self.host.advertise_state.reset();
+        poll_fn(|cx| self.host.poll_resumed(cx)).await;
+    }
+
+    /// Enter Direct Test Mode, for RF qualification and production testing.
+    ///
+    /// Stops advertising, scanning and connecting, which wait until the returned [`TestMode`](test_mode::TestMode)
+    /// is dropped. Returns `Error::InvalidState` while a connection is open.
+    pub async fn test_mode(&'stack self) -> Result<test_mode::TestMode<'stack, C, P>, Error> {
+        let host = &self.host;
+        if host.connections.has_links() {
+            return Err(Error::InvalidState);
+        }
+        for state in [
+            &host.advertise_command_state,
+            &host.scan_command_state,
+            &host.connect_command_state,
+        ] {
+            state.cancel_active();
+        }
+        // Released by the test mode handle, also when a connection is found below.
+        let test_mode = test_mode::TestMode::new(self);
+        host.advertise_command_state.hold().await;
+        host.scan_command_state.hold().await;
+        host.connect_command_state.hold().await;
+        if host.connections.has_links() {
+            return Err(Error::InvalidState);
+        }
+        Ok(test_mode)
+    }
+
+    /// Read current memory usage, including high-water marks.
+    pub fn memory_stats(&self) -> MemoryStats {
+        self.host.memory_stats()
+    }
+
+    /// Snapshot of the link, L2CAP channel and pairing state machines.
+    ///
+    /// `CONNS` and `CHANNELS` bound the number of links and channels in the snapshot, and are normally those of
+    /// the [`HostResources`]. See [`debug_state`] for details.
+    #[cfg(feature = "debug-state")]
+    pub fn debug_state<const CONNS: usize, const CHANNELS: usize>(&self) -> debug_state::DebugState<CONNS, CHANNELS> {
+        self.host.debug_state()
+    }
+
     /// Log status information of the host
     pub fn log_status(&self, verbose: bool) {
         self.host.log_status(verbose);
@@ -686,4 +1722,201 @@ impl<'stack, C: Controller, P: PacketPool> Stack<'stack, C, P> {
     pub fn get_bond_information(&self) -> Vec<BondInformation, BI_COUNT> {
         self.host.connections.security_manager.get_bond_information()
     }
+
+    #[cfg(feature = "security")]
+    /// Forget a bonded device
+    ///
+    /// Removes the stored keys and any entry for the device in the controller filter accept list and resolving
+    /// list, so that the device is no longer accepted or resolved by the controller.
+    pub async fn delete_bond(&self, identity: Identity) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeRemoveDeviceFromFilterAcceptList> + ControllerCmdSync<LeRemoveDeviceFromResolvingList>,
+    {
+        self.host
+            .connections
+            .security_manager
+            .remove_bond_information(identity)?;
+        self.forget_device(&identity.bd_addr).await
+    }
+
+    #[cfg(feature = "security")]
+    /// Forget all bonded devices
+    ///
+    /// Removes all stored keys and the controller state of the bonded devices.
+    pub async fn wipe_bonds(&self) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeRemoveDeviceFromFilterAcceptList> + ControllerCmdSync<LeRemoveDeviceFromResolvingList>,
+    {
+        let bonds = self.host.connections.security_manager.get_bond_information();
+        self.host.connections.security_manager.clear_bond_information();
+        for bond in bonds.iter() {
+            self.forget_device(&bond.identity.bd_addr).await?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "security")]
+    async fn forget_device(&self, addr: &BdAddr) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeRemoveDeviceFromFilterAcceptList> + ControllerCmdSync<LeRemoveDeviceFromResolvingList>,
+    {
+        // The identity address kind is not known, so both kinds are removed. A device that is not in a
+        // list is reported as an unknown connection identifier.
+        for kind in [AddrKind::PUBLIC, AddrKind::RANDOM] {
+            match self
+                .host
+                .command(LeRemoveDeviceFromFilterAcceptList::new(kind, *addr))
+                .await
+            {
+                Ok(()) | Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => (),
+                Err(e) => return Err(e),
+            }
+            match self
+                .host
+                .command(LeRemoveDeviceFromResolvingList::new(kind, *addr))
+                .await
+            {
+                Ok(()) | Err(BleHostError::BleHost(Error::Hci(bt_hci::param::Error::UNKNOWN_CONN_IDENTIFIER))) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::mock_controller::MockController;
+    use crate::prelude::DefaultPacketPool;
+
+    #[test]
+    fn error_kind() {
+        use bt_hci::param::Error as Hci;
+
+        assert_eq!(
+            Error::Hci(Hci::UNKNOWN_ADV_IDENTIFIER).kind(),
+            ErrorKind::Advertise(AdvertiseError::Hci(Hci::UNKNOWN_ADV_IDENTIFIER))
+        );
+        assert_eq!(
+            Error::Hci(Hci::CONN_TIMEOUT).kind(),
+            ErrorKind::Connect(ConnectError::Hci(Hci::CONN_TIMEOUT))
+        );
+        #[cfg(feature = "security")]
+        assert_eq!(
+            Error::Hci(Hci::PIN_OR_KEY_MISSING).kind(),
+            ErrorKind::Security(SecurityError::Hci(Hci::PIN_OR_KEY_MISSING))
+        );
+        assert_eq!(
+            Error::Hci(Hci::INVALID_HCI_PARAMETERS).kind(),
+            ErrorKind::Hci(Hci::INVALID_HCI_PARAMETERS)
+        );
+        assert_eq!(
+            Error::Att(AttErrorCode::INSUFFICIENT_ENCRYPTION).kind(),
+            ErrorKind::Gatt(GattError::Att(AttErrorCode::INSUFFICIENT_ENCRYPTION))
+        );
+        assert_eq!(
+            Error::ExtendedAdvertisingNotSupported.kind(),
+            ErrorKind::Advertise(AdvertiseError::ExtendedNotSupported)
+        );
+        assert_eq!(
+            Error::ConnectionLimitReached.kind(),
+            ErrorKind::Connect(ConnectError::LimitReached)
+        );
+        assert_eq!(Error::NoPermits.kind(), ErrorKind::L2cap(L2capError::NoCredits));
+        assert_eq!(Error::OutOfMemory.kind(), ErrorKind::Resources);
+        assert_eq!(Error::Timeout.kind(), ErrorKind::Timeout);
+        assert_eq!(Error::InvalidValue.kind(), ErrorKind::Other);
+        assert_eq!(BleHostError::<()>::Controller(()).kind(), None);
+        assert_eq!(
+            BleHostError::<()>::BleHost(Error::Disconnected).kind(),
+            Some(ErrorKind::Connect(ConnectError::Disconnected))
+        );
+    }
+
+    #[test]
+    fn address_parse_and_format() {
+        let address = unwrap!(Address::parse(AddrKind::RANDOM, "C1:02:03:04:05:0a"));
+        assert_eq!(address.addr.raw(), &[0x0a, 0x05, 0x04, 0x03, 0x02, 0xc1]);
+        assert_eq!(address.address_type(), AddressType::StaticRandom);
+
+        assert_eq!(std::format!("{}", address), "C1:02:03:04:05:0A");
+        assert_eq!(
+            std::format!("{:?}", address),
+            "Address(StaticRandom, C1:02:03:04:05:0A)"
+        );
+
+        assert!(Address::parse(AddrKind::PUBLIC, "C1:02:03:04:05").is_err());
+        assert!(Address::parse(AddrKind::PUBLIC, "C1:02:03:04:05:06:07").is_err());
+        assert!(Address::parse(AddrKind::PUBLIC, "C1:02:03:04:05:G6").is_err());
+        assert!(Address::parse(AddrKind::PUBLIC, "C1:02:03:04:05:+6").is_err());
+        assert_eq!(
+            Address::from_device_id(&[0; 6]).addr.raw(),
+            &[0x01, 0x00, 0x00, 0x00, 0x00, 0xc0]
+        );
+        assert_eq!(
+            Address::from_device_id(&[0xff; 6]).addr.raw(),
+            &[0xfe, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(Address::public([1, 2, 3, 4, 5, 6]).address_type(), AddressType::Public);
+        assert_eq!(
+            Address::random([1, 2, 3, 4, 5, 0x46]).address_type(),
+            AddressType::ResolvablePrivate
+        );
+        assert_eq!(
+            Address::random([1, 2, 3, 4, 5, 0x06]).address_type(),
+            AddressType::NonResolvablePrivate
+        );
+        for val in [[1, 2, 3, 4, 5, 0xC6], [0; 6], [0xFF; 6]] {
+            let address = Address::non_resolvable_private(val);
+            assert_eq!(address.address_type(), AddressType::NonResolvablePrivate);
+            assert_ne!(address.addr.raw(), &[0; 6]);
+            assert_ne!(address.addr.raw(), &[0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x3F]);
+        }
+    }
+
+    #[cfg(feature = "security")]
+    #[test]
+    fn snapshot_restore() {
+        let mut resources: HostResources<DefaultPacketPool, 1, 1> = HostResources::new();
+        let stack = new(MockController::new(), &mut resources);
+        let mut signed = BondInformation::new(
+            Identity {
+                bd_addr: BdAddr::new([1; 6]),
+                irk: Some(IdentityResolvingKey::new(7)),
+            },
+            LongTermKey::new(0x1234),
+        );
+        signed.key_size = 12;
+        signed.peer_csrk = Some(SigningKey {
+            csrk: ConnectionSignatureResolvingKey::from_le_bytes([3; 16]),
+            counter: 5,
+        });
+        let plain = BondInformation::new(
+            Identity {
+                bd_addr: BdAddr::new([2; 6]),
+                irk: None,
+            },
+            LongTermKey::new(0x5678),
+        );
+        unwrap!(stack.add_bond_information(signed.clone()));
+        unwrap!(stack.add_bond_information(plain.clone()));
+        let mut buf = [0; 128];
+        let len = unwrap!(stack.snapshot(&mut buf));
+
+        let mut resources: HostResources<DefaultPacketPool, 1, 1> = HostResources::new();
+        let restored = new(MockController::new(), &mut resources);
+        // Corrupt snapshots are rejected without adding any bond
+        let mut version = buf;
+        version[0] += 1;
+        assert_eq!(restored.restore(&version[..len]), Err(Error::InvalidValue));
+        assert_eq!(restored.restore(&buf[..len - 1]), Err(Error::InvalidValue));
+        assert_eq!(restored.restore(&buf[..len + 1]), Err(Error::InvalidValue));
+        assert!(restored.get_bond_information().is_empty());
+
+        unwrap!(restored.restore(&buf[..len]));
+        assert_eq!(restored.get_bond_information().as_slice(), &[signed, plain]);
+    }
 }
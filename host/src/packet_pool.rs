@@ -1,8 +1,13 @@
 //! A packet pool for allocating and freeing packet buffers with quality of service policy.
 use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::{Context, Poll, Waker};
 
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
 use embassy_sync::blocking_mutex::Mutex;
+use embassy_sync::waitqueue::MultiWakerRegistration;
 
 use crate::{config, Packet, PacketPool};
 
@@ -24,12 +29,20 @@ impl<const MTU: usize> PacketBuf<MTU> {
 
 struct State<const MTU: usize, const N: usize> {
     packets: [PacketBuf<MTU>; N],
+    // Tasks waiting for a packet, served in the order they started waiting.
+    waiters: heapless::Vec<(u32, Waker), N>,
+    next_waiter: u32,
+    // Tasks waiting for room in the list of waiters.
+    overflow: MultiWakerRegistration<N>,
 }
 
 impl<const MTU: usize, const N: usize> State<MTU, N> {
     pub(crate) const fn new() -> Self {
         Self {
             packets: [PacketBuf::NEW; N],
+            waiters: heapless::Vec::new(),
+            next_waiter: 0,
+            overflow: MultiWakerRegistration::new(),
         }
     }
 
@@ -51,6 +64,64 @@ impl<const MTU: usize, const N: usize> State<MTU, N> {
     fn free(&mut self, p_ref: &PacketRef<MTU>) {
         // info!("[{}] free {}", id.0, p_ref.idx);
         self.packets[p_ref.idx].free = true;
+        self.wake_next();
+    }
+
+    fn wake_next(&mut self) {
+        if let Some((_, waker)) = self.waiters.first() {
+            waker.wake_by_ref();
+        }
+    }
+
+    fn poll_alloc(&mut self, waiter: &mut Option<u32>, cx: &mut Context<'_>) -> Poll<PacketRef<MTU>> {
+        match *waiter {
+            None if self.waiters.is_empty() => {
+                if let Some(p) = self.alloc() {
+                    return Poll::Ready(p);
+                }
+            }
+            Some(id) if self.waiters.first().map(|(w, _)| *w) == Some(id) => {
+                if let Some(p) = self.alloc() {
+                    self.waiters.remove(0);
+                    self.overflow.wake();
+                    *waiter = None;
+                    if self.available() > 0 {
+                        self.wake_next();
+                    }
+                    return Poll::Ready(p);
+                }
+            }
+            _ => {}
+        }
+
+        match *waiter {
+            Some(id) => {
+                if let Some((_, waker)) = self.waiters.iter_mut().find(|(w, _)| *w == id) {
+                    waker.clone_from(cx.waker());
+                }
+            }
+            None => {
+                let id = self.next_waiter;
+                if self.waiters.push((id, cx.waker().clone())).is_ok() {
+                    self.next_waiter = self.next_waiter.wrapping_add(1);
+                    waiter.replace(id);
+                } else {
+                    // Wait queue is full, retry once a waiter leaves it.
+                    self.overflow.register(cx.waker());
+                }
+            }
+        }
+        Poll::Pending
+    }
+
+    fn cancel(&mut self, id: u32) {
+        if let Some(pos) = self.waiters.iter().position(|(w, _)| *w == id) {
+            self.waiters.remove(pos);
+            self.overflow.wake();
+            if pos == 0 && self.available() > 0 {
+                self.wake_next();
+            }
+        }
     }
 
     fn available(&mut self) -> usize {
@@ -98,6 +169,44 @@ impl<M: RawMutex, const MTU: usize, const N: usize> StaticPacketPool<M, MTU, N>
             state.available()
         })
     }
+
+    fn poll_alloc(&self, waiter: &mut Option<u32>, cx: &mut Context<'_>) -> Poll<PacketRef<MTU>> {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            state.poll_alloc(waiter, cx)
+        })
+    }
+
+    fn cancel(&self, id: u32) {
+        self.state.lock(|state| {
+            let mut state = state.borrow_mut();
+            state.cancel(id);
+        });
+    }
+}
+
+/// Future waiting for a packet to become available in a pool.
+///
+/// Waiters are served in the order they started waiting.
+struct Allocate<'d, M: RawMutex, const MTU: usize, const N: usize> {
+    pool: &'d StaticPacketPool<M, MTU, N>,
+    waiter: Option<u32>,
+}
+
+impl<M: RawMutex, const MTU: usize, const N: usize> Future for Allocate<'_, M, MTU, N> {
+    type Output = PacketRef<MTU>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        this.pool.poll_alloc(&mut this.waiter, cx)
+    }
+}
+
+impl<M: RawMutex, const MTU: usize, const N: usize> Drop for Allocate<'_, M, MTU, N> {
+    fn drop(&mut self) {
+        if let Some(id) = self.waiter.take() {
+            self.pool.cancel(id);
+        }
+    }
 }
 
 /// Represents a reference to a packet.
@@ -107,18 +216,49 @@ pub struct PacketRef<const MTU: usize> {
     buf: *mut u8,
 }
 
+// Set once the application promised that the default pool is only used from a single executor.
+static SINGLE_EXECUTOR: AtomicBool = AtomicBool::new(false);
+
+/// Raw mutex of the default packet pool.
+///
+/// Locks with a critical section, unless [`DefaultPacketPool::assume_single_executor`] was called.
+pub struct DefaultRawMutex(CriticalSectionRawMutex);
+
+// SAFETY: a critical section is taken unless the caller of `assume_single_executor` guaranteed that no two
+// locks can overlap.
+unsafe impl RawMutex for DefaultRawMutex {
+    const INIT: Self = Self(CriticalSectionRawMutex::INIT);
+
+    fn lock<R>(&self, f: impl FnOnce() -> R) -> R {
+        if SINGLE_EXECUTOR.load(Ordering::Relaxed) {
+            f()
+        } else {
+            self.0.lock(f)
+        }
+    }
+}
+
 /// Global default packet pool.
-pub type DefaultPacketPool = StaticPacketPool<
-    CriticalSectionRawMutex,
-    { config::DEFAULT_PACKET_POOL_MTU },
-    { config::DEFAULT_PACKET_POOL_SIZE },
->;
-
-static DEFAULT_POOL: StaticPacketPool<
-    CriticalSectionRawMutex,
-    { config::DEFAULT_PACKET_POOL_MTU },
-    { config::DEFAULT_PACKET_POOL_SIZE },
-> = StaticPacketPool::new();
+pub type DefaultPacketPool =
+    StaticPacketPool<DefaultRawMutex, { config::DEFAULT_PACKET_POOL_MTU }, { config::DEFAULT_PACKET_POOL_SIZE }>;
+
+static DEFAULT_POOL: DefaultPacketPool = StaticPacketPool::new();
+
+impl DefaultPacketPool {
+    /// Lock the default packet pool without a critical section from now on.
+    ///
+    /// The rest of the host synchronizes without critical sections, so this saves the only critical section
+    /// taken for each packet allocated and freed.
+    ///
+    /// # Safety
+    ///
+    /// The host, and every task allocating or dropping packets of the default pool, must run on a single
+    /// executor, and never from an interrupt, another thread or another core. Call this before the stack is
+    /// built.
+    pub unsafe fn assume_single_executor() {
+        SINGLE_EXECUTOR.store(true, Ordering::Relaxed);
+    }
+}
 
 impl PacketPool for DefaultPacketPool {
     type Packet = DefaultPacket;
@@ -133,6 +273,18 @@ impl PacketPool for DefaultPacketPool {
             pool: &DEFAULT_POOL,
         })
     }
+
+    async fn allocate_async() -> DefaultPacket {
+        let p = Allocate {
+            pool: &DEFAULT_POOL,
+            waiter: None,
+        }
+        .await;
+        DefaultPacket {
+            p_ref: p,
+            pool: &DEFAULT_POOL,
+        }
+    }
 }
 
 /// Type representing the packet from the default packet pool.
@@ -162,6 +314,8 @@ impl Drop for DefaultPacket {
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
+
     use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 
     use super::*;
@@ -191,4 +345,93 @@ mod tests {
         let b2 = pool.alloc();
         assert!(b2.is_none());
     }
+
+    #[test]
+    fn test_waiters_served_in_order() {
+        let pool: StaticPacketPool<NoopRawMutex, 27, 1> = StaticPacketPool::new();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let a = pool.alloc().unwrap();
+
+        let mut first = core::pin::pin!(Allocate {
+            pool: &pool,
+            waiter: None
+        });
+        let mut second = core::pin::pin!(Allocate {
+            pool: &pool,
+            waiter: None
+        });
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+
+        pool.free(&a);
+        // The second waiter may not take the packet the first is waiting for.
+        assert!(second.as_mut().poll(&mut cx).is_pending());
+        let Poll::Ready(b) = first.as_mut().poll(&mut cx) else {
+            panic!("expected packet for first waiter");
+        };
+
+        pool.free(&b);
+        assert!(second.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn test_cancelled_waiter() {
+        let pool: StaticPacketPool<NoopRawMutex, 27, 1> = StaticPacketPool::new();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        let a = pool.alloc().unwrap();
+        {
+            let mut first = core::pin::pin!(Allocate {
+                pool: &pool,
+                waiter: None
+            });
+            assert!(first.as_mut().poll(&mut cx).is_pending());
+        }
+        pool.free(&a);
+
+        let mut second = core::pin::pin!(Allocate {
+            pool: &pool,
+            waiter: None
+        });
+        assert!(second.as_mut().poll(&mut cx).is_ready());
+    }
+
+    #[test]
+    fn test_full_wait_queue_parks() {
+        struct Flag(AtomicBool);
+        impl std::task::Wake for Flag {
+            fn wake(self: std::sync::Arc<Self>) {
+                self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let pool: StaticPacketPool<NoopRawMutex, 27, 1> = StaticPacketPool::new();
+        let waker = futures::task::noop_waker();
+        let mut cx = Context::from_waker(&waker);
+        let flag = std::sync::Arc::new(Flag(AtomicBool::new(false)));
+        let overflow_waker = Waker::from(flag.clone());
+        let mut overflow_cx = Context::from_waker(&overflow_waker);
+
+        let a = pool.alloc().unwrap();
+        let mut first = core::pin::pin!(Allocate {
+            pool: &pool,
+            waiter: None
+        });
+        let mut second = core::pin::pin!(Allocate {
+            pool: &pool,
+            waiter: None
+        });
+        assert!(first.as_mut().poll(&mut cx).is_pending());
+        // No room to wait in line, the task sleeps instead of being polled again right away
+        assert!(second.as_mut().poll(&mut overflow_cx).is_pending());
+        assert!(!flag.0.load(Ordering::Relaxed));
+
+        pool.free(&a);
+        assert!(!flag.0.load(Ordering::Relaxed));
+        assert!(first.as_mut().poll(&mut cx).is_ready());
+        assert!(flag.0.load(Ordering::Relaxed));
+    }
 }
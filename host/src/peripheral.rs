@@ -1,18 +1,23 @@
 //! Functionality for the BLE peripheral role.
+use core::future::{poll_fn, Future};
 use core::task::Poll;
 
 use bt_hci::cmd::le::{
-    LeClearAdvSets, LeReadNumberOfSupportedAdvSets, LeSetAdvData, LeSetAdvEnable, LeSetAdvParams,
+    LeReadLocalSupportedFeatures, LeReadNumberOfSupportedAdvSets, LeSetAdvData, LeSetAdvEnable, LeSetAdvParams,
     LeSetAdvSetRandomAddr, LeSetExtAdvData, LeSetExtAdvEnable, LeSetExtAdvParams, LeSetExtScanResponseData,
     LeSetScanResponseData,
 };
 use bt_hci::controller::{Controller, ControllerCmdSync};
 use bt_hci::param::{AddrKind, AdvChannelMap, AdvHandle, AdvKind, AdvSet, BdAddr, LeConnRole, Operation};
 use embassy_futures::select::{select, Either};
+use embassy_time::{Instant, Timer};
 
 use crate::advertise::{Advertisement, AdvertisementParameters, AdvertisementSet, RawAdvertisement};
 use crate::connection::Connection;
-use crate::{Address, BleHostError, Error, PacketPool, Stack};
+use crate::{Address, BleHostError, ControllerWithExtAdv, Error, PacketPool, Stack};
+
+/// Time to wait for the advertising set of an accepted connection to be reported.
+const ADV_SET_REPORT_TIMEOUT: embassy_time::Duration = embassy_time::Duration::from_millis(100);
 
 /// Type which implements the BLE peripheral role.
 pub struct Peripheral<'d, C, P: PacketPool> {
@@ -42,7 +47,7 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         let drop = crate::host::OnDrop::new(|| {
             host.advertise_command_state.cancel(false);
         });
-        host.advertise_command_state.request().await;
+        host.advertise_command_state.request(false).await;
 
         // Clear current advertising terminations
         host.advertise_state.reset();
@@ -51,6 +56,9 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         if !data.props.legacy_adv() {
             return Err(Error::ExtendedAdvertisingNotSupported.into());
         }
+        if params.private_address || params.identity.is_some() {
+            return Err(Error::InvalidValue.into());
+        }
 
         let kind = match (data.props.connectable_adv(), data.props.scannable_adv()) {
             (true, true) => AdvKind::AdvInd,
@@ -64,7 +72,9 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         });
 
         host.command(LeSetAdvParams::new(
-            params.interval_min.into(),
+            host.coex
+                .adv_interval_min(params.interval_min, params.interval_max)
+                .into(),
             params.interval_max.into(),
             kind,
             host.address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC),
@@ -103,9 +113,40 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
             stack: self.stack,
             extended: false,
             done: false,
+            // Legacy advertising has no duration, so the timeout is handled by the stack
+            deadline: params.timeout.map(|timeout| Instant::now() + timeout),
         })
     }
 
+    /// Advertise and pass every accepted connection to `handler`, restarting advertising when it returns.
+    ///
+    /// The handler should return once it is done with the connection, typically when it is disconnected.
+    /// Advertising is restarted as well when it times out. Returns when advertising fails or the
+    /// handler returns an error.
+    pub async fn advertise_loop<'k, F, Fut>(
+        &mut self,
+        params: &AdvertisementParameters,
+        data: Advertisement<'k>,
+        mut handler: F,
+    ) -> Result<(), BleHostError<C::Error>>
+    where
+        C: for<'t> ControllerCmdSync<LeSetAdvData>
+            + ControllerCmdSync<LeSetAdvParams>
+            + for<'t> ControllerCmdSync<LeSetAdvEnable>
+            + for<'t> ControllerCmdSync<LeSetScanResponseData>,
+        F: FnMut(Connection<'d, P>) -> Fut,
+        Fut: Future<Output = Result<(), BleHostError<C::Error>>>,
+    {
+        loop {
+            let advertiser = self.advertise(params, data).await?;
+            match advertiser.accept().await {
+                Ok(conn) => handler(conn).await?,
+                Err(Error::Timeout) => trace!("[host] advertising stopped, restarting"),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
     /// Update the advertisment adv_data and/or scan_data. Does not change any
     /// other advertising parameters. If no advertising is active, this will not
     /// produce any observable effect. This is typically useful when
@@ -150,16 +191,36 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         handles: &mut [AdvSet],
     ) -> Result<Advertiser<'d, C, P>, BleHostError<C::Error>>
     where
-        C: for<'t> ControllerCmdSync<LeSetExtAdvData<'t>>
-            + ControllerCmdSync<LeClearAdvSets>
-            + ControllerCmdSync<LeSetExtAdvParams>
-            + ControllerCmdSync<LeSetAdvSetRandomAddr>
-            + ControllerCmdSync<LeReadNumberOfSupportedAdvSets>
-            + for<'t> ControllerCmdSync<LeSetExtAdvEnable<'t>>
-            + for<'t> ControllerCmdSync<LeSetExtScanResponseData<'t>>,
+        C: ControllerWithExtAdv,
     {
         assert_eq!(sets.len(), handles.len());
         let host = &self.stack.host;
+        for (i, set) in sets.iter().enumerate().filter(|(_, set)| set.params.private_address) {
+            let props = RawAdvertisement::from(set.data).props;
+            // Private addresses are tracked with a bit per advertising handle
+            if i >= 64
+                || props.connectable_adv()
+                || set.params.timeout.is_some()
+                || set.params.max_events.is_some()
+                || set.params.identity.is_some()
+            {
+                return Err(Error::InvalidValue.into());
+            }
+        }
+        if sets
+            .iter()
+            .filter_map(|set| set.params.identity)
+            .any(|index| usize::from(index) >= host.identities.len())
+        {
+            return Err(Error::InvalidValue.into());
+        }
+        if !host
+            .command(LeReadLocalSupportedFeatures::new())
+            .await?
+            .supports_le_ext_adv()
+        {
+            return Err(Error::ExtendedAdvertisingNotSupported.into());
+        }
         // Check host supports the required advertisement sets
         {
             let result = host.command(LeReadNumberOfSupportedAdvSets::new()).await?;
@@ -172,7 +233,7 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
         let drop = crate::host::OnDrop::new(|| {
             host.advertise_command_state.cancel(true);
         });
-        host.advertise_command_state.request().await;
+        host.advertise_command_state.request(true).await;
 
         // Clear current advertising terminations
         host.advertise_state.reset();
@@ -185,13 +246,26 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
                 kind: AddrKind::PUBLIC,
                 addr: BdAddr::default(),
             });
+            let address = if let Some(index) = params.identity {
+                Some(host.identities[usize::from(index)].address)
+            } else {
+                host.address
+            };
+            // Private addresses are set by the rotation, see Stack::rotate_private_addresses
+            let kind = if params.private_address {
+                AddrKind::RANDOM
+            } else {
+                address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC)
+            };
             host.command(LeSetExtAdvParams::new(
                 handle,
                 data.props,
-                params.interval_min.into(),
+                host.coex
+                    .adv_interval_min(params.interval_min, params.interval_max)
+                    .into(),
                 params.interval_max.into(),
                 params.channel_map.unwrap_or(AdvChannelMap::ALL),
-                host.address.map(|a| a.kind).unwrap_or(AddrKind::PUBLIC),
+                kind,
                 peer.kind,
                 peer.addr,
                 params.filter_policy,
@@ -204,7 +278,10 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
             ))
             .await?;
 
-            if let Some(address) = host.address.as_ref() {
+            if let Some(address) = address
+                .as_ref()
+                .filter(|a| a.kind == AddrKind::RANDOM && !params.private_address)
+            {
                 host.command(LeSetAdvSetRandomAddr::new(handle, address.addr)).await?;
             }
 
@@ -238,12 +315,30 @@ impl<'d, C: Controller, P: PacketPool> Peripheral<'d, C, P> {
 
         trace!("[host] enabling extended advertising");
         host.advertise_state.start(handles);
-        host.command(LeSetExtAdvEnable::new(true, handles)).await?;
+        for (set, handle) in sets.iter().zip(handles.iter()) {
+            if let Some(index) = set.params.identity {
+                host.advertise_state
+                    .set_identity(handle.adv_handle, host.identities[usize::from(index)]);
+            }
+        }
+        if sets.iter().any(|set| set.params.private_address) {
+            for (set, handle) in sets.iter().zip(handles.iter()) {
+                if set.params.private_address {
+                    host.advertise_state.set_private(handle.adv_handle);
+                } else {
+                    host.command(LeSetExtAdvEnable::new(true, core::slice::from_ref(handle)))
+                        .await?;
+                }
+            }
+        } else {
+            host.command(LeSetExtAdvEnable::new(true, handles)).await?;
+        }
         drop.defuse();
         Ok(Advertiser {
             stack: self.stack,
             extended: true,
             done: false,
+            deadline: None,
         })
     }
 
@@ -309,6 +404,7 @@ pub struct Advertiser<'d, C, P: PacketPool> {
     stack: &'d Stack<'d, C, P>,
     extended: bool,
     done: bool,
+    deadline: Option<Instant>,
 }
 
 impl<'d, C: Controller, P: PacketPool> Advertiser<'d, C, P> {
@@ -316,17 +412,41 @@ impl<'d, C: Controller, P: PacketPool> Advertiser<'d, C, P> {
     ///
     /// Returns Error::Timeout if advertiser stopped.
     pub async fn accept(mut self) -> Result<Connection<'d, P>, Error> {
-        let result = match select(
-            self.stack.host.connections.accept(LeConnRole::Peripheral, &[]),
-            self.stack.host.advertise_state.wait(),
-        )
-        .await
-        {
-            Either::First(conn) => Ok(conn),
-            Either::Second(_) => Err(Error::Timeout),
+        let deadline = self.deadline;
+        let stopped = async {
+            match deadline {
+                Some(deadline) => matches!(
+                    select(self.stack.host.advertise_state.wait(), Timer::at(deadline)).await,
+                    Either::Second(_)
+                ),
+                None => {
+                    self.stack.host.advertise_state.wait().await;
+                    false
+                }
+            }
         };
-        self.done = true;
-        result
+        let (result, expired) =
+            match select(self.stack.host.connections.accept(LeConnRole::Peripheral, &[]), stopped).await {
+                Either::First(conn) => (Ok(conn), false),
+                Either::Second(expired) => (Err(Error::Timeout), expired),
+            };
+        // Advertising is still enabled in the controller when the timeout expired, so let drop cancel it
+        self.done = !expired;
+        let conn = result?;
+        if self.extended {
+            // The advertising set is reported by the controller right after the connection, don't hold
+            // the connection back if the controller never reports it
+            let reported = poll_fn(|cx| {
+                self.stack.host.advertise_state.register(cx.waker());
+                if conn.adv_handle().is_some() || !conn.is_connected() {
+                    Poll::Ready(())
+                } else {
+                    Poll::Pending
+                }
+            });
+            let _ = select(reported, Timer::after(ADV_SET_REPORT_TIMEOUT)).await;
+        }
+        Ok(conn)
     }
 }
 
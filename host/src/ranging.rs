@@ -0,0 +1,349 @@
+//! Approximate ranging based on RSSI.
+//!
+//! RSSI measurements from scan reports or connections are noisy, so they are smoothed per peer before
+//! estimating the distance with a log-distance path loss model. The estimate is approximate and depends
+//! on the environment, calibrate the model for better results.
+use bt_hci::param::{LeAdvReport, LeExtAdvReport};
+use heapless::Vec;
+
+use crate::Address;
+
+/// RSSI value reported by the controller when no RSSI is available.
+const RSSI_NOT_AVAILABLE: i8 = 127;
+
+/// Log-distance path loss model.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct PathLossModel {
+    /// RSSI measured at a distance of 1 meter, in dBm.
+    pub measured_power: i8,
+    /// Path loss exponent, 2.0 in free space and typically 2.5 to 4.0 indoors.
+    pub exponent: f32,
+}
+
+impl Default for PathLossModel {
+    fn default() -> Self {
+        Self::new(-59, 2.0)
+    }
+}
+
+impl PathLossModel {
+    /// Create a new path loss model.
+    pub const fn new(measured_power: i8, exponent: f32) -> Self {
+        Self {
+            measured_power,
+            exponent,
+        }
+    }
+
+    /// Create a model calibrated from the RSSI measured at a known distance, in meters.
+    pub fn calibrate(rssi: f32, distance: f32, exponent: f32) -> Self {
+        let measured_power = rssi + 10.0 * exponent * log10(distance);
+        // Round to the nearest dBm, `as` alone truncates towards zero.
+        let measured_power = if measured_power < 0.0 {
+            measured_power - 0.5
+        } else {
+            measured_power + 0.5
+        };
+        Self::new(measured_power as i8, exponent)
+    }
+
+    /// Estimated distance in meters for the given RSSI.
+    pub fn distance(&self, rssi: f32) -> f32 {
+        pow10((self.measured_power as f32 - rssi) / (10.0 * self.exponent))
+    }
+}
+
+/// Smoothing applied to RSSI measurements.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Smoothing {
+    /// Exponentially weighted moving average, with the weight of new measurements between 0 and 1.
+    Ewma {
+        /// Weight of new measurements.
+        alpha: f32,
+    },
+    /// One-dimensional Kalman filter.
+    Kalman {
+        /// Variance of the change of the RSSI between measurements.
+        process_noise: f32,
+        /// Variance of the RSSI measurements.
+        measurement_noise: f32,
+    },
+}
+
+impl Default for Smoothing {
+    fn default() -> Self {
+        Self::Kalman {
+            process_noise: 0.1,
+            measurement_noise: 4.0,
+        }
+    }
+}
+
+/// Smoothed RSSI of a single peer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RssiFilter {
+    smoothing: Smoothing,
+    estimate: Option<f32>,
+    variance: f32,
+}
+
+impl RssiFilter {
+    /// Create a new filter.
+    pub const fn new(smoothing: Smoothing) -> Self {
+        Self {
+            smoothing,
+            estimate: None,
+            variance: 0.0,
+        }
+    }
+
+    /// Add a measurement and return the smoothed RSSI.
+    pub fn update(&mut self, rssi: i8) -> f32 {
+        let rssi = rssi as f32;
+        let estimate = match (self.estimate, self.smoothing) {
+            (None, Smoothing::Ewma { .. }) => rssi,
+            (None, Smoothing::Kalman { measurement_noise, .. }) => {
+                self.variance = measurement_noise;
+                rssi
+            }
+            (Some(estimate), Smoothing::Ewma { alpha }) => estimate + alpha * (rssi - estimate),
+            (
+                Some(estimate),
+                Smoothing::Kalman {
+                    process_noise,
+                    measurement_noise,
+                },
+            ) => {
+                let variance = self.variance + process_noise;
+                let gain = variance / (variance + measurement_noise);
+                self.variance = (1.0 - gain) * variance;
+                estimate + gain * (rssi - estimate)
+            }
+        };
+        self.estimate = Some(estimate);
+        estimate
+    }
+
+    /// The smoothed RSSI, if any measurement was added.
+    pub fn rssi(&self) -> Option<f32> {
+        self.estimate
+    }
+}
+
+struct Peer {
+    address: Address,
+    filter: RssiFilter,
+    last_update: u32,
+}
+
+/// Smoothed RSSI and distance estimates for up to `N` peers.
+///
+/// When the table is full, the peer updated least recently is replaced. `N` must not be zero.
+pub struct Ranging<const N: usize> {
+    model: PathLossModel,
+    smoothing: Smoothing,
+    peers: Vec<Peer, N>,
+    updates: u32,
+}
+
+impl<const N: usize> Default for Ranging<N> {
+    fn default() -> Self {
+        Self::new(PathLossModel::default(), Smoothing::default())
+    }
+}
+
+impl<const N: usize> Ranging<N> {
+    /// Create a new ranging table.
+    pub const fn new(model: PathLossModel, smoothing: Smoothing) -> Self {
+        const { core::assert!(N > 0, "a ranging table must hold at least one peer") };
+        Self {
+            model,
+            smoothing,
+            peers: Vec::new(),
+            updates: 0,
+        }
+    }
+
+    /// Add an RSSI measurement for a peer, for example from [`Connection::rssi`](crate::connection::Connection::rssi),
+    /// and return the smoothed RSSI.
+    pub fn update(&mut self, address: Address, rssi: i8) -> f32 {
+        self.updates = self.updates.wrapping_add(1);
+        let index = match self.peers.iter().position(|peer| peer.address == address) {
+            Some(index) => index,
+            None => {
+                let peer = Peer {
+                    address,
+                    filter: RssiFilter::new(self.smoothing),
+                    last_update: self.updates,
+                };
+                match self.peers.push(peer) {
+                    Ok(()) => self.peers.len() - 1,
+                    Err(peer) => {
+                        let updates = self.updates;
+                        let (index, _) = unwrap!(self
+                            .peers
+                            .iter()
+                            .enumerate()
+                            .max_by_key(|(_, p)| updates.wrapping_sub(p.last_update)));
+                        self.peers[index] = peer;
+                        index
+                    }
+                }
+            }
+        };
+        let peer = &mut self.peers[index];
+        peer.last_update = self.updates;
+        peer.filter.update(rssi)
+    }
+
+    /// Add the RSSI of a legacy advertising report.
+    pub fn update_report(&mut self, report: &LeAdvReport<'_>) -> Option<f32> {
+        (report.rssi != RSSI_NOT_AVAILABLE).then(|| {
+            self.update(
+                Address {
+                    kind: report.addr_kind,
+                    addr: report.addr,
+                },
+                report.rssi,
+            )
+        })
+    }
+
+    /// Add the RSSI of an extended advertising report.
+    pub fn update_ext_report(&mut self, report: &LeExtAdvReport<'_>) -> Option<f32> {
+        (report.rssi != RSSI_NOT_AVAILABLE).then(|| {
+            self.update(
+                Address {
+                    kind: report.addr_kind,
+                    addr: report.addr,
+                },
+                report.rssi,
+            )
+        })
+    }
+
+    /// The smoothed RSSI of a peer.
+    pub fn rssi(&self, address: &Address) -> Option<f32> {
+        self.peers
+            .iter()
+            .find(|peer| peer.address == *address)
+            .and_then(|peer| peer.filter.rssi())
+    }
+
+    /// The estimated distance to a peer, in meters.
+    pub fn distance(&self, address: &Address) -> Option<f32> {
+        self.rssi(address).map(|rssi| self.model.distance(rssi))
+    }
+
+    /// Forget a peer.
+    pub fn remove(&mut self, address: &Address) {
+        self.peers.retain(|peer| peer.address != *address);
+    }
+}
+
+/// 10 to the power of `x`, without depending on a math library.
+fn pow10(x: f32) -> f32 {
+    // 10^x = 2^(x * log2(10)), split into an integer and a fractional power of two.
+    let y = (x * core::f32::consts::LOG2_10).clamp(-126.0, 127.0);
+    let mut int = y as i32;
+    if y < int as f32 {
+        int -= 1;
+    }
+    let frac = y - int as f32;
+    // Polynomial approximation of 2^frac for frac in [0, 1).
+    let p = 1.0
+        + frac
+            * (core::f32::consts::LN_2
+                + frac * (0.240_226_5 + frac * (0.055_504_1 + frac * (0.009_618_1 + frac * 0.001_333_6))));
+    f32::from_bits(((int + 127) as u32) << 23) * p
+}
+
+/// Base 10 logarithm of `x`, without depending on a math library.
+fn log10(x: f32) -> f32 {
+    if x <= 0.0 {
+        return f32::NEG_INFINITY;
+    }
+    // x = m * 2^e with m in [1, 2)
+    let bits = x.to_bits();
+    let e = ((bits >> 23) & 0xff) as i32 - 127;
+    let m = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+    // ln(m) = 2 * atanh((m - 1) / (m + 1))
+    let t = (m - 1.0) / (m + 1.0);
+    let t2 = t * t;
+    let ln_m = 2.0 * t * (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 * (1.0 / 7.0 + t2 / 9.0))));
+    (ln_m + e as f32 * core::f32::consts::LN_2) * core::f32::consts::LOG10_E
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= b.abs() * 1e-3
+    }
+
+    #[test]
+    fn math() {
+        for (x, expected) in [
+            (0.0, 1.0),
+            (1.0, 10.0),
+            (-1.0, 0.1),
+            (0.5, 3.162_277_7),
+            (2.3, 199.526_23),
+        ] {
+            assert!(close(pow10(x), expected), "pow10({}) = {}", x, pow10(x));
+            assert!(
+                (log10(expected) - x).abs() < 1e-3,
+                "log10({}) = {}",
+                expected,
+                log10(expected)
+            );
+        }
+    }
+
+    #[test]
+    fn distance_estimate() {
+        let model = PathLossModel::new(-59, 2.0);
+        assert!(close(model.distance(-59.0), 1.0));
+        assert!(close(model.distance(-79.0), 10.0));
+
+        // -65 dBm + 6.02 dB
+        let model = PathLossModel::calibrate(-65.0, 2.0, 2.0);
+        assert_eq!(model.measured_power, -59);
+    }
+
+    #[test]
+    fn smoothing() {
+        let mut ewma = RssiFilter::new(Smoothing::Ewma { alpha: 0.5 });
+        assert_eq!(ewma.update(-60), -60.0);
+        assert_eq!(ewma.update(-70), -65.0);
+
+        let mut kalman = RssiFilter::new(Smoothing::default());
+        for rssi in [-60, -70, -60, -70, -60, -70, -60, -70] {
+            kalman.update(rssi);
+        }
+        let rssi = unwrap!(kalman.rssi());
+        assert!(rssi > -67.0 && rssi < -63.0);
+    }
+
+    #[test]
+    fn replace_least_recently_updated() {
+        let a = Address::random([1, 0, 0, 0, 0, 0xc0]);
+        let b = Address::random([2, 0, 0, 0, 0, 0xc0]);
+        let c = Address::random([3, 0, 0, 0, 0, 0xc0]);
+        let mut ranging: Ranging<2> = Ranging::default();
+        ranging.update(a, -50);
+        ranging.update(b, -60);
+        ranging.update(a, -50);
+        ranging.update(c, -70);
+        assert!(ranging.rssi(&a).is_some());
+        assert!(ranging.rssi(&b).is_none());
+        assert!(ranging.distance(&c).is_some());
+
+        ranging.remove(&c);
+        assert!(ranging.rssi(&c).is_none());
+    }
+}
@@ -10,7 +10,7 @@ use embassy_time::Instant;
 
 use crate::command::CommandState;
 use crate::connection::ScanConfig;
-use crate::{BleHostError, Central, PacketPool};
+use crate::{BleHostError, Central, Error, PacketPool};
 
 /// A scanner that wraps a central to provide additional functionality
 /// around BLE scanning.
@@ -34,6 +34,9 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
 
     /// Performs an extended BLE scan, return a report for discovering peripherals.
     ///
+    /// All PHYs in the config are scanned simultaneously. Reports are tagged with the primary and secondary PHY
+    /// they were received on.
+    ///
     /// Scan is stopped when a report is received. Call this method repeatedly to continue scanning.
     pub async fn scan_ext(&mut self, config: &ScanConfig<'_>) -> Result<ScanSession<'_, true>, BleHostError<C::Error>>
     where
@@ -43,10 +46,18 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
         let host = &self.central.stack.host;
+        host.coex.scan_allowed().await;
+        // Scanning and initiating share the filter accept list.
+        if !host
+            .scan_command_state
+            .request_unless(true, &host.connect_command_state)
+            .await
+        {
+            return Err(Error::InitiatingInProgress.into());
+        }
         let drop = crate::host::OnDrop::new(|| {
             host.scan_command_state.cancel(false);
         });
-        host.scan_command_state.request().await;
         self.central.set_accept_filter(config.filter_accept_list).await?;
 
         let scanning = ScanningPhy {
@@ -54,7 +65,11 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             scan_interval: config.interval.into(),
             scan_window: config.window.into(),
         };
-        let phy_params = crate::central::create_phy_params(scanning, config.phys);
+        let mut phy_params = crate::central::create_phy_params(scanning, config.phys);
+        if let (Some(coded), Some(phy)) = (config.coded, phy_params.le_coded_phy.as_mut()) {
+            phy.scan_interval = coded.interval.into();
+            phy.scan_window = coded.window.into();
+        }
         let host = &self.central.stack.host;
         host.command(LeSetExtScanParams::new(
             host.address.map(|s| s.kind).unwrap_or(AddrKind::PUBLIC),
@@ -97,10 +112,18 @@ impl<'d, C: Controller, P: PacketPool> Scanner<'d, C, P> {
             + ControllerCmdSync<LeAddDeviceToFilterAcceptList>,
     {
         let host = &self.central.stack.host;
+        host.coex.scan_allowed().await;
+        // Scanning and initiating share the filter accept list.
+        if !host
+            .scan_command_state
+            .request_unless(false, &host.connect_command_state)
+            .await
+        {
+            return Err(Error::InitiatingInProgress.into());
+        }
         let drop = crate::host::OnDrop::new(|| {
             host.scan_command_state.cancel(false);
         });
-        host.scan_command_state.request().await;
 
         self.central.set_accept_filter(config.filter_accept_list).await?;
 
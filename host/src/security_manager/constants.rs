@@ -1,2 +1,4 @@
 /// 128-bit encryption key size
 pub(crate) const ENCRYPTION_KEY_SIZE_128_BITS: u8 = 128 / 8;
+/// Number of rounds of passkey entry, one for each bit of the 20-bit passkey
+pub(crate) const PASSKEY_ROUNDS: u8 = 20;
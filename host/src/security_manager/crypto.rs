@@ -32,6 +32,28 @@ impl LongTermKey {
     pub const fn to_le_bytes(self) -> [u8; 16] {
         self.0.to_le_bytes()
     }
+
+    /// SIRK encryption function `sef` of the Coordinated Set Identification Service, which is also the
+    /// decryption function `sdf`: the SIRK is XORed with `k1(K, s1("SIRKenc"), "csis")`.
+    pub(crate) fn sef(&self, sirk: u128) -> u128 {
+        sirk ^ k1(self.0, s1(b"SIRKenc"), b"csis")
+    }
+}
+
+/// Salt generation function `s1` of the Coordinated Set Identification Service.
+fn s1(m: &[u8]) -> u128 {
+    let mut cmac = AesCmac::new(&Key::new(0));
+    cmac.update(m);
+    cmac.finalize()
+}
+
+/// Key derivation function `k1` of the Coordinated Set Identification Service.
+fn k1(n: u128, salt: u128, p: &[u8]) -> u128 {
+    let mut cmac = AesCmac::new(&Key::new(salt));
+    cmac.update(n.to_be_bytes());
+    let mut cmac = AesCmac::new(&cmac.finalize_key());
+    cmac.update(p);
+    cmac.finalize()
 }
 
 impl From<&LongTermKey> for u128 {
@@ -54,6 +76,73 @@ impl defmt::Format for LongTermKey {
     }
 }
 
+/// Connection Signature Resolving Key, used to sign data on an unencrypted connection
+/// ([Vol 3] Part H, Section 2.4.5).
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[must_use]
+#[repr(transparent)]
+pub struct ConnectionSignatureResolvingKey(pub u128);
+
+impl ConnectionSignatureResolvingKey {
+    /// Creates a Connection Signature Resolving Key from a `u128` value.
+    #[inline(always)]
+    pub const fn new(k: u128) -> Self {
+        Self(k)
+    }
+
+    /// Creates a Connection Signature Resolving Key from a `[u8; 16]` value in little endian.
+    #[inline(always)]
+    pub const fn from_le_bytes(k: [u8; 16]) -> Self {
+        Self(u128::from_le_bytes(k))
+    }
+
+    /// Returns the Connection Signature Resolving Key as `[u8; 16]` value in little endian.
+    #[inline(always)]
+    pub const fn to_le_bytes(self) -> [u8; 16] {
+        self.0.to_le_bytes()
+    }
+
+    /// Generates a random Connection Signature Resolving Key.
+    pub(crate) fn generate<T: RngCore + CryptoRng>(rng: &mut T) -> Self {
+        let mut k = [0; 16];
+        rng.fill_bytes(&mut k);
+        Self::from_le_bytes(k)
+    }
+
+    /// Computes the signature of a message, given as the concatenation of `parts`, with the sign counter.
+    ///
+    /// The signature is the counter followed by the MAC. The message and counter are signed most
+    /// significant octet first, and the MAC is the most significant 64 bits of the AES-CMAC output.
+    pub(crate) fn sign(&self, parts: &[&[u8]], counter: u32) -> [u8; 12] {
+        let mut m = AesCmac::new(&Key::new(self.0));
+        m.update(counter.to_be_bytes());
+        for part in parts.iter().rev() {
+            for b in part.iter().rev() {
+                m.update([*b]);
+            }
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let mac = (m.finalize() >> 64) as u64;
+        let mut signature = [0; 12];
+        signature[..4].copy_from_slice(&counter.to_le_bytes());
+        signature[4..].copy_from_slice(&mac.to_le_bytes());
+        signature
+    }
+}
+
+impl core::fmt::Display for ConnectionSignatureResolvingKey {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for ConnectionSignatureResolvingKey {
+    fn format(&self, fmt: defmt::Formatter) {
+        defmt::write!(fmt, "{:016x}", self.0)
+    }
+}
+
 /// Identity Resolving Key.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
 #[must_use]
@@ -498,6 +587,38 @@ mod tests {
     extern crate std;
     use bt_hci::param::{AddrKind, BdAddr};
 
+    #[test]
+    fn csrk_sign() {
+        // AES-CMAC example 2 of RFC 4493, with the message and counter in reverse octet order
+        let csrk = ConnectionSignatureResolvingKey::new(0x2b7e1516_28aed2a6_abf71588_09cf4f3c);
+        let message = [0x2a, 0x17, 0x93, 0x73, 0x11, 0x7e, 0x3d, 0xe9, 0x96, 0x9f, 0x40, 0x2e];
+        let signature = csrk.sign(&[&message[..5], &message[5..]], 0x6bc1bee2);
+        assert_eq!(&signature[..4], &0x6bc1bee2_u32.to_le_bytes());
+        assert_eq!(&signature[4..], &0x070a16b4_6b4d4144_u64.to_le_bytes());
+    }
+
+    #[test]
+    fn csis_sef() {
+        // Sample data of the CSIS specification for s1, and of the Mesh Profile specification, which defines the
+        // same s1 and k1 functions
+        assert_eq!(s1(b"SIRKenc"), 0x6901983f_18149e82_3c7d133a_7d774572);
+        assert_eq!(s1(b"test"), 0xb73cefbd_641ef2ea_598c2b6e_fb62f79c);
+        let p = 0x5a09d607_97eeb447_8aada59d_b3352a0d_u128.to_be_bytes();
+        assert_eq!(
+            k1(
+                0x3216d150_9884b533_24854179_2b877f98,
+                0x2ba14ffa_0df84a28_31938d57_d276cab4,
+                &p
+            ),
+            0xf6ed15a8_934afbe7_d83e8dcb_57fcf5d7
+        );
+
+        // Sample K and SIRK of the CSIS specification
+        let k = LongTermKey(0x676e1b9b_d448696f_061ec622_8e7549f4);
+        let sirk = 0x457d7d09_21a1fd22_cecd8c86_dd72cccd;
+        assert_eq!(k.sef(sirk), 0x562c33aa_23fda377_7060099f_fc6b07f4);
+    }
+
     #[test]
     fn sizes() {
         assert_eq!(core::mem::size_of::<Coord>(), 32);
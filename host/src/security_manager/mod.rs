@@ -13,9 +13,9 @@ use core::ops::DerefMut;
 use bt_hci::event::le::LeEvent;
 use bt_hci::event::Event;
 use bt_hci::param::{AddrKind, BdAddr, ConnHandle, LeConnRole};
-use constants::ENCRYPTION_KEY_SIZE_128_BITS;
+use constants::{ENCRYPTION_KEY_SIZE_128_BITS, PASSKEY_ROUNDS};
 use crypto::{Check, Confirm, DHKey, MacKey, Nonce, PublicKey, SecretKey};
-pub use crypto::{IdentityResolvingKey, LongTermKey};
+pub use crypto::{ConnectionSignatureResolvingKey, IdentityResolvingKey, LongTermKey};
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use embassy_sync::channel::Channel;
 use embassy_sync::signal::Signal;
@@ -23,16 +23,16 @@ use embassy_time::{Duration, Instant, TimeoutError, WithTimeout};
 use heapless::Vec;
 use rand_chacha::ChaCha12Rng;
 use rand_core::SeedableRng;
-pub use types::Reason;
-use types::{AuthReq, BondingFlag, Command, IoCapabilities, PairingFeatures};
+use types::{AuthReq, BondingFlag, Command, IoCapabilities, KeyDistributionFlags, PairingFeatures};
+pub use types::{Reason, SecurityMode1Level};
 
 use crate::codec::{Decode, Encode};
 use crate::connection_manager::{ConnectionManager, ConnectionStorage};
 use crate::pdu::Pdu;
-use crate::prelude::Connection;
+use crate::prelude::{Connection, ConnectionEvent};
 use crate::security_manager::types::UseOutOfBand;
 use crate::types::l2cap::L2CAP_CID_LE_U_SECURITY_MANAGER;
-use crate::{Address, Error, Identity, PacketPool};
+use crate::{Address, Error, Identity, LocalIdentity, PacketPool};
 
 /// Events of interest to the security manager
 pub(crate) enum SecurityEventData {
@@ -53,13 +53,49 @@ pub struct BondInformation {
     pub ltk: LongTermKey,
     /// Peer identity
     pub identity: Identity,
-    // Connection Signature Resolving Key (CSRK)?
+    /// Signing key distributed by the peer, used to verify its signed writes
+    pub peer_csrk: Option<SigningKey>,
+    /// Signing key distributed to the peer, used to sign writes
+    pub local_csrk: Option<SigningKey>,
+    /// Encryption key size of the LTK in octets
+    pub key_size: u8,
 }
 
 impl BondInformation {
-    /// Create a BondInformation
+    /// Create a BondInformation with a full size (16 octets) key
     pub fn new(identity: Identity, ltk: LongTermKey) -> Self {
-        Self { ltk, identity }
+        Self {
+            ltk,
+            identity,
+            peer_csrk: None,
+            local_csrk: None,
+            key_size: ENCRYPTION_KEY_SIZE_128_BITS,
+        }
+    }
+}
+
+/// Connection Signature Resolving Key (CSRK) with its sign counter
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SigningKey {
+    /// Connection Signature Resolving Key
+    pub csrk: ConnectionSignatureResolvingKey,
+    /// Sign counter, the counter of the next signed write
+    pub counter: u32,
+}
+
+impl SigningKey {
+    /// Create a SigningKey with a zero sign counter
+    pub fn new(csrk: ConnectionSignatureResolvingKey) -> Self {
+        Self { csrk, counter: 0 }
+    }
+}
+
+/// Signing key of a bond after pairing, the sign counter restarts when the key changes
+fn renew_signing_key(key: Option<SigningKey>, csrk: Option<ConnectionSignatureResolvingKey>) -> Option<SigningKey> {
+    match (key, csrk) {
+        (Some(key), Some(csrk)) if key.csrk == csrk => Some(key),
+        (_, csrk) => csrk.map(SigningKey::new),
     }
 }
 
@@ -76,6 +112,66 @@ impl defmt::Format for BondInformation {
     }
 }
 
+/// Security requirements enforced when pairing and when accessing attributes
+///
+/// LE legacy pairing is not supported, so a peer without LE Secure Connections support can never pair.
+/// [`SecurityPolicy::secure_connections_only`] selects how such a peer is rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SecurityPolicy {
+    /// Minimum encryption key size in octets (7 to 16).
+    ///
+    /// Pairing with a smaller key size is rejected, and links encrypted with a smaller key, e.g. with the key of a
+    /// bond restored from an older policy, can't access attributes requiring encryption.
+    pub min_encryption_key_size: u8,
+    /// Secure Connections Only mode ([Vol 3] Part C, Section 10.2.4).
+    ///
+    /// When set, the default, a peer without LE Secure Connections support is rejected with
+    /// [`Reason::AuthenticationRequirements`] as required by the mode. Otherwise it is rejected with
+    /// [`Reason::PairingNotSupported`], since LE legacy pairing is not implemented.
+    pub secure_connections_only: bool,
+}
+
+impl Default for SecurityPolicy {
+    fn default() -> Self {
+        Self {
+            min_encryption_key_size: ENCRYPTION_KEY_SIZE_128_BITS,
+            secure_connections_only: true,
+        }
+    }
+}
+
+impl SecurityPolicy {
+    /// Check the pairing features of the peer against the policy and return the negotiated key size
+    fn check(&self, local: &PairingFeatures, peer: &PairingFeatures) -> Result<u8, Error> {
+        let key_size = local.maximum_encryption_key_size.min(peer.maximum_encryption_key_size);
+        if key_size < self.min_encryption_key_size {
+            return Err(Error::Security(Reason::EncryptionKeySize));
+        }
+        if !peer.security_properties.secure_connection() {
+            return Err(Error::Security(if self.secure_connections_only {
+                Reason::AuthenticationRequirements
+            } else {
+                Reason::PairingNotSupported
+            }));
+        }
+        Ok(key_size)
+    }
+}
+
+/// Failed pairing attempts of a peer
+///
+/// ([Vol 3] Part H, Section 2.3.6)
+#[derive(Clone, Copy)]
+struct PairingAttempts {
+    /// Peer device address
+    address: BdAddr,
+    /// Number of consecutive failed attempts
+    failures: u8,
+    /// New pairing attempts are rejected until this instant
+    blocked_until: Instant,
+}
+
 /// Security manager data
 struct SecurityManagerData<const BOND_COUNT: usize> {
     /// Local device address
@@ -84,17 +180,77 @@ struct SecurityManagerData<const BOND_COUNT: usize> {
     bond: Vec<BondInformation, BOND_COUNT>,
     /// Random generator seeded
     random_generator_seeded: bool,
+    /// Peers with recently failed pairing attempts
+    attempts: Vec<PairingAttempts, BOND_COUNT>,
+    /// Security requirements
+    policy: SecurityPolicy,
+    /// Fixed passkey used for passkey entry pairing
+    passkey: Option<u32>,
+    /// Long term key used for peers without a bond
+    preshared_ltk: Option<LongTermKey>,
 }
 
 impl<const BOND_COUNT: usize> SecurityManagerData<BOND_COUNT> {
+    /// Wait interval after the first failed pairing attempt
+    const REPEATED_ATTEMPTS_INTERVAL: Duration = Duration::from_secs(2);
+    /// Maximum number of times the wait interval is doubled
+    const REPEATED_ATTEMPTS_MAX_DOUBLINGS: u8 = 6;
+
     /// Create a new security manager data structure
     pub(crate) fn new() -> Self {
         Self {
             local_address: None,
             bond: Vec::new(),
             random_generator_seeded: false,
+            attempts: Vec::new(),
+            policy: SecurityPolicy::default(),
+            passkey: None,
+            preshared_ltk: None,
         }
     }
+
+    /// Is pairing with the peer currently blocked because of repeated failed attempts?
+    fn pairing_blocked(&self, address: &BdAddr, now: Instant) -> bool {
+        self.attempts
+            .iter()
+            .any(|attempts| attempts.address == *address && now < attempts.blocked_until)
+    }
+
+    /// Record a failed pairing attempt, doubling the wait interval for every consecutive failure
+    fn pairing_failed(&mut self, address: BdAddr, now: Instant) {
+        let index = match self.attempts.iter().position(|attempts| attempts.address == address) {
+            Some(index) => index,
+            None => {
+                let entry = PairingAttempts {
+                    address,
+                    failures: 0,
+                    blocked_until: now,
+                };
+                if self.attempts.is_full() {
+                    // Replace the entry that is blocked the shortest
+                    let (index, _) = unwrap!(self
+                        .attempts
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, attempts)| attempts.blocked_until));
+                    self.attempts[index] = entry;
+                    index
+                } else {
+                    unwrap!(self.attempts.push(entry).ok());
+                    self.attempts.len() - 1
+                }
+            }
+        };
+        let attempts = &mut self.attempts[index];
+        let doublings = attempts.failures.min(Self::REPEATED_ATTEMPTS_MAX_DOUBLINGS);
+        attempts.failures = attempts.failures.saturating_add(1);
+        attempts.blocked_until = now + Self::REPEATED_ATTEMPTS_INTERVAL * (1u32 << doublings);
+    }
+
+    /// Forget failed pairing attempts after a successful pairing
+    fn pairing_succeeded(&mut self, address: &BdAddr) {
+        self.attempts.retain(|attempts| attempts.address != *address);
+    }
 }
 
 /// Packet structure for sending security manager protocol (SMP) commands
@@ -158,7 +314,7 @@ enum PairingMethod {
 /// Pairing states
 #[derive(Debug, Clone, Copy, PartialEq)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-enum PairingState {
+pub enum PairingState {
     /// No pairing initialized
     Idle,
     /// Security request from peripheral
@@ -227,6 +383,14 @@ struct PairingData {
     peer_address: Option<Address>,
     /// Identity Resolving Key
     irk: Option<IdentityResolvingKey>,
+    /// Signing key distributed by the peer
+    peer_csrk: Option<ConnectionSignatureResolvingKey>,
+    /// Signing key distributed to the peer
+    local_csrk: Option<ConnectionSignatureResolvingKey>,
+    /// Current round of passkey entry
+    passkey_round: u8,
+    /// Pairing completed successfully
+    complete: bool,
 }
 
 impl PairingData {
@@ -251,11 +415,16 @@ impl PairingData {
             ltk: None,
             peer_address: None,
             irk: None,
+            peer_csrk: None,
+            local_csrk: None,
+            passkey_round: 0,
+            complete: false,
         }
     }
     /// Clear pairing data
     pub(crate) fn clear(&mut self) {
         self.state = PairingState::Idle;
+        self.complete = false;
         self.method = PairingMethod::None;
         self.role = LeConnRole::Peripheral;
         self.handle = None;
@@ -272,6 +441,31 @@ impl PairingData {
         self.local_check = None;
         self.ltk = None;
         self.peer_address = None;
+        self.peer_csrk = None;
+        self.local_csrk = None;
+        self.passkey_round = 0;
+    }
+
+    /// Negotiated encryption key size, once the pairing features are exchanged
+    fn key_size(&self) -> Option<u8> {
+        match (&self.local_features, &self.peer_features) {
+            (Some(local), Some(peer)) => Some(local.maximum_encryption_key_size.min(peer.maximum_encryption_key_size)),
+            _ => None,
+        }
+    }
+
+    /// Negotiated key distribution, as the keys distributed by the local device and by the peer
+    fn key_distribution(&self) -> Option<(KeyDistributionFlags, KeyDistributionFlags)> {
+        match self.role {
+            LeConnRole::Central => {
+                let features = self.peer_features?;
+                Some((features.initiator_key_distribution, features.responder_key_distribution))
+            }
+            LeConnRole::Peripheral => {
+                let features = self.local_features?;
+                Some((features.responder_key_distribution, features.initiator_key_distribution))
+            }
+        }
     }
 }
 
@@ -345,21 +539,103 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         self.state.borrow_mut().random_generator_seeded = true;
     }
 
+    /// Set the security requirements enforced when pairing
+    pub(crate) fn set_policy(&self, mut policy: SecurityPolicy) {
+        policy.min_encryption_key_size = policy.min_encryption_key_size.clamp(7, ENCRYPTION_KEY_SIZE_128_BITS);
+        self.state.borrow_mut().policy = policy;
+    }
+
+    /// Minimum encryption key size of the security policy
+    pub(crate) fn min_encryption_key_size(&self) -> u8 {
+        self.state.borrow().policy.min_encryption_key_size
+    }
+
+    /// Use a fixed passkey for passkey entry pairing
+    #[cfg(feature = "insecure-fixed-pairing")]
+    pub(crate) fn set_fixed_passkey(&self, passkey: u32) {
+        self.state.borrow_mut().passkey = Some(passkey);
+    }
+
+    /// Use a pre-shared long term key for peers without a bond
+    #[cfg(feature = "insecure-fixed-pairing")]
+    pub(crate) fn set_preshared_ltk(&self, ltk: LongTermKey) {
+        self.state.borrow_mut().preshared_ltk = Some(ltk);
+    }
+
     /// Set the current local address
     pub(crate) fn set_local_address(&self, address: Address) {
         self.state.borrow_mut().local_address = Some(address);
     }
 
+    /// Encryption key size of a link encrypted after pairing on the connection, or with the key of the peer
+    pub(crate) fn encryption_key_size(&self, handle: ConnHandle, identity: &Identity) -> u8 {
+        let pairing_state = self.pairing_state.borrow();
+        if let (Some(key_size), true) = (pairing_state.key_size(), pairing_state.handle == Some(handle)) {
+            return key_size;
+        }
+        self.state
+            .borrow()
+            .bond
+            .iter()
+            .find(|bond| bond.identity.match_identity(identity))
+            // A preshared key has the full size
+            .map_or(ENCRYPTION_KEY_SIZE_128_BITS, |bond| bond.key_size)
+    }
+
     /// Get the long term key for peer
     pub(crate) fn get_peer_long_term_key(&self, identity: &Identity) -> Option<LongTermKey> {
         trace!("[security manager] Find long term key for {:?}", identity);
-        self.state.borrow().bond.iter().find_map(|bond| {
-            if bond.identity.match_identity(identity) {
-                Some(bond.ltk)
-            } else {
-                None
-            }
-        })
+        let state = self.state.borrow();
+        state
+            .bond
+            .iter()
+            .find_map(|bond| {
+                if bond.identity.match_identity(identity) {
+                    Some(bond.ltk)
+                } else {
+                    None
+                }
+            })
+            .or(state.preshared_ltk)
+    }
+
+    /// Verify the signature of a signed write from a bonded peer, and advance the peer sign counter
+    ///
+    /// The signature is rejected if the peer has no signing key or reuses a sign counter.
+    pub(crate) fn verify_signature(&self, identity: &Identity, parts: &[&[u8]], signature: &[u8; 12]) -> bool {
+        let counter = u32::from_le_bytes([signature[0], signature[1], signature[2], signature[3]]);
+        let mut state = self.state.borrow_mut();
+        let Some(key) = state
+            .bond
+            .iter_mut()
+            .find(|bond| bond.identity.match_identity(identity))
+            .and_then(|bond| bond.peer_csrk.as_mut())
+        else {
+            return false;
+        };
+        if counter < key.counter || key.csrk.sign(parts, counter) != *signature {
+            return false;
+        }
+        // The last counter value cannot be followed by a fresh one, so it would be accepted again
+        let Some(next) = counter.checked_add(1) else {
+            return false;
+        };
+        key.counter = next;
+        true
+    }
+
+    /// Sign a message with the local signing key of a bond, and advance the local sign counter
+    pub(crate) fn sign(&self, identity: &Identity, parts: &[&[u8]]) -> Result<[u8; 12], Error> {
+        let mut state = self.state.borrow_mut();
+        let key = state
+            .bond
+            .iter_mut()
+            .find(|bond| bond.identity.match_identity(identity))
+            .and_then(|bond| bond.local_csrk.as_mut())
+            .ok_or(Error::NotFound)?;
+        let signature = key.csrk.sign(parts, key.counter);
+        key.counter = key.counter.checked_add(1).ok_or(Error::InvalidState)?;
+        Ok(signature)
     }
 
     /// Get the result of the pairing
@@ -396,6 +672,28 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         }
     }
 
+    /// Add several bonds, or none of them if they do not all fit.
+    pub(crate) fn add_bonds(&self, bonds: &[BondInformation]) -> Result<(), Error> {
+        {
+            let state = self.state.borrow();
+            let added = bonds
+                .iter()
+                .enumerate()
+                .filter(|(i, bond)| {
+                    !state.bond.iter().any(|b| bond.identity.match_identity(&b.identity))
+                        && !bonds[..*i].iter().any(|b| bond.identity.match_identity(&b.identity))
+                })
+                .count();
+            if state.bond.len() + added > state.bond.capacity() {
+                return Err(Error::OutOfMemory);
+            }
+        }
+        for bond in bonds {
+            self.add_bond_information(bond.clone())?;
+        }
+        Ok(())
+    }
+
     /// Remove a bonded device
     pub(crate) fn remove_bond_information(&self, identity: Identity) -> Result<(), Error> {
         trace!("[security manager] Remove bond for {:?}", identity);
@@ -414,6 +712,12 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         }
     }
 
+    /// Remove all bonded devices
+    pub(crate) fn clear_bond_information(&self) {
+        trace!("[security manager] Remove all bonds");
+        self.state.borrow_mut().bond.clear();
+    }
+
     /// Get bonded devices
     pub(crate) fn get_bond_information(&self) -> Vec<BondInformation, BOND_COUNT> {
         Vec::from_slice(self.state.borrow().bond.as_slice()).unwrap()
@@ -444,7 +748,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 size
             };
             if size < 2 {
-                error!("[security manager] Payload size too small {}", size);
+                error!(conn = handle, "[security manager] Payload size too small {}", size);
                 return Err(Error::Security(Reason::InvalidParameters));
             }
             let payload = &buffer[1..size];
@@ -453,7 +757,10 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             let command = match Command::try_from(command) {
                 Ok(command) => {
                     if usize::from(command.payload_size()) != payload.len() {
-                        error!("[security manager] Payload size mismatch for command {}", command);
+                        error!(
+                            conn = handle,
+                            "[security manager] Payload size mismatch for command {}", command
+                        );
                         return Err(Error::Security(Reason::InvalidParameters));
                     }
                     command
@@ -469,9 +776,9 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 if let Some(pairing_handle) = pairing_state.handle {
                     if pairing_handle != handle {
                         error!(
-                            "Mismatching connection handle {} != {}",
-                            pairing_handle.raw(),
-                            handle.raw()
+                            conn = handle,
+                            "Mismatching connection handle, pairing on {}",
+                            pairing_handle.raw()
                         );
                         return Err(Error::InvalidValue);
                     }
@@ -488,6 +795,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 self.pairing_state.borrow_mut().peer_address = Some(peer_address);
             }
 
+            if matches!(command, Command::PairingRequest | Command::SecurityRequest)
+                && self.state.borrow().pairing_blocked(&peer_address.addr, Instant::now())
+            {
+                warn!(conn = handle, "[security manager] Pairing rejected, repeated attempts");
+                return Err(Error::Security(Reason::RepeatedAttempts));
+            }
+
             {
                 match command {
                     Command::PairingRequest
@@ -502,20 +816,21 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 }
             }
 
-            trace!("Security Manager Protocol command {}", command);
+            trace!(conn = handle, "Security Manager Protocol command {}", command);
 
             match command {
-                Command::PairingRequest => self.handle_pairing_request(payload, connections, handle),
+                Command::PairingRequest => self.handle_pairing_request(payload, connections, handle, storage),
                 Command::PairingResponse => self.handle_pairing_response(payload, connections, handle),
                 Command::PairingPublicKey => self.handle_pairing_public_key(payload, connections, handle),
                 Command::PairingConfirm => self.handle_pairing_confirm(payload, connections, handle),
                 Command::PairingRandom => self.handle_pairing_random(payload, connections, handle, storage),
                 Command::PairingDhKeyCheck => self.handle_pairing_dhkey_check(payload, connections, handle, storage),
-                Command::PairingFailed => self.handle_pairing_failed(payload),
+                Command::PairingFailed => self.handle_pairing_failed(payload, storage),
                 Command::IdentityInformation => self.handle_identity_information(payload, handle),
                 Command::IdentityAddressInformation => self.handle_identity_address_information(payload),
+                Command::SigningInformation => self.handle_signing_information(payload, handle),
                 _ => {
-                    warn!("Unhandled Security Manager Protocol command {}", command);
+                    warn!(conn = handle, "Unhandled Security Manager Protocol command {}", command);
                     Ok(())
                 }
             }
@@ -527,7 +842,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 Reason::UnspecifiedReason
             };
 
-            error!("Handling of command failed {:?}", error);
+            error!(conn = handle, "Handling of command failed {:?}", error);
 
             // Cease sending security manager messages on timeout
             if *error != Error::Timeout {
@@ -538,16 +853,47 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 match self.try_send_packet(packet, connections, handle) {
                     Ok(()) => (),
                     Err(error) => {
-                        error!("[security manager] Failed to send pairing failed {:?}", error);
+                        error!(
+                            conn = handle,
+                            "[security manager] Failed to send pairing failed {:?}", error
+                        );
                         return Err(error);
                     }
                 }
             }
-            self.pairing_result(reason)?;
+            self.pairing_failed(reason, storage)?;
         }
         result
     }
 
+    /// Whether an SMP PDU of the pairing phase is received on a connection that completed pairing.
+    pub(crate) fn after_pairing(&self, handle: ConnHandle, pdu: &[u8]) -> bool {
+        let pairing_state = self.pairing_state.borrow();
+        let pairing_phase = matches!(
+            pdu.first().map(|&command| Command::try_from(command)),
+            Some(Ok(Command::PairingResponse
+                | Command::PairingConfirm
+                | Command::PairingRandom
+                | Command::PairingPublicKey
+                | Command::PairingDhKeyCheck))
+        );
+        pairing_state.complete && pairing_state.handle == Some(handle) && pairing_phase
+    }
+
+    /// Snapshot of the pairing in progress or last completed.
+    #[cfg(feature = "debug-state")]
+    pub(crate) fn debug_state(&self) -> Option<crate::debug_state::PairingDebugState> {
+        let pairing_state = self.pairing_state.borrow();
+        pairing_state
+            .handle
+            .map(|handle| crate::debug_state::PairingDebugState {
+                handle,
+                role: pairing_state.role,
+                state: pairing_state.state,
+                complete: pairing_state.complete,
+            })
+    }
+
     /// Initiate pairing
     pub fn initiate<P: PacketPool>(&self, connection: &Connection<P>) -> Result<(), Error> {
         if connection.role() == LeConnRole::Central {
@@ -566,11 +912,13 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 self.timer_reset()?;
             } else {
                 // Send pairing request
-                let local_features = PairingFeatures {
-                    io_capabilities: IoCapabilities::NoInputNoOutput,
+                let mut local_features = PairingFeatures {
+                    io_capabilities: self.local_io_capabilities(LeConnRole::Central),
                     security_properties: AuthReq::new(BondingFlag::Bonding),
                     ..Default::default()
                 };
+                // Distribute a signing key, so that the peer can verify signed writes
+                local_features.initiator_key_distribution.set_signing_key();
 
                 let mut packet: TxPacket<P> =
                     TxPacket::new(P::allocate().ok_or(Error::OutOfMemory)?, Command::PairingRequest)?;
@@ -592,6 +940,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                     pairing_state.role = connection.role();
                     pairing_state.handle = Some(connection.handle());
                     pairing_state.state = PairingState::Request;
+                    pairing_state.complete = false;
                     pairing_state.local_features = Some(local_features);
                     pairing_state.method =
                         self.choose_pairing_method(&pairing_state.local_features, &pairing_state.peer_features);
@@ -599,31 +948,46 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 }
             }
         } else {
-            // Send sequrity request to central
-            let auth_req = AuthReq::new(BondingFlag::Bonding);
+            self.request_security(connection, SecurityMode1Level::Level4)?;
+        }
 
-            let mut packet: TxPacket<P> =
-                TxPacket::new(P::allocate().ok_or(Error::OutOfMemory)?, Command::SecurityRequest)?;
+        Ok(())
+    }
 
-            let response = packet.payload_mut();
+    /// Send a security request to the central of a peripheral connection
+    pub(crate) fn request_security<P: PacketPool>(
+        &self,
+        connection: &Connection<P>,
+        level: SecurityMode1Level,
+    ) -> Result<(), Error> {
+        if connection.role() != LeConnRole::Peripheral {
+            return Err(Error::InvalidValue);
+        }
+        let auth_req = AuthReq::with_level(BondingFlag::Bonding, level);
 
-            response[0] = auth_req.into();
+        let mut packet: TxPacket<P> =
+            TxPacket::new(P::allocate().ok_or(Error::OutOfMemory)?, Command::SecurityRequest)?;
 
-            match connection.try_send(packet.into_pdu()) {
-                Ok(()) => (),
-                Err(error) => {
-                    error!("[security manager] Failed to send security request {:?}", error);
-                    return Err(error);
-                }
-            }
+        let response = packet.payload_mut();
 
-            {
-                let mut pairing_state = self.pairing_state.borrow_mut();
-                pairing_state.state = PairingState::SecurityRequest;
-                self.timer_reset()?;
+        response[0] = auth_req.into();
+
+        match connection.try_send(packet.into_pdu()) {
+            Ok(()) => (),
+            Err(error) => {
+                error!("[security manager] Failed to send security request {:?}", error);
+                return Err(error);
             }
         }
 
+        {
+            let mut pairing_state = self.pairing_state.borrow_mut();
+            pairing_state.role = connection.role();
+            pairing_state.handle = Some(connection.handle());
+            pairing_state.state = PairingState::SecurityRequest;
+            self.timer_reset()?;
+        }
+
         Ok(())
     }
 
@@ -645,9 +1009,9 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         if let Some(pairing_handle) = pairing_state.handle {
             if pairing_handle != handle {
                 error!(
-                    "Mismatching connection handle {} != {}",
-                    pairing_handle.raw(),
-                    handle.raw()
+                    conn = handle,
+                    "Mismatching connection handle, pairing on {}",
+                    pairing_handle.raw()
                 );
                 return Err(Error::InvalidValue);
             } else {
@@ -659,14 +1023,33 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         Ok(())
     }
 
-    /// Handle pairing response command
-    fn handle_pairing_failed(&self, payload: &[u8]) -> Result<(), Error> {
+    /// Handle pairing failed command
+    fn handle_pairing_failed<P>(&self, payload: &[u8], storage: &ConnectionStorage<P>) -> Result<(), Error> {
         let reason = if let Ok(r) = Reason::try_from(payload[0]) {
             r
         } else {
             Reason::UnspecifiedReason
         };
         error!("[security manager] Pairing failed {}", reason);
+        self.pairing_failed(reason, storage)
+    }
+
+    /// Abort pairing on a connection and report the reason to the application
+    ///
+    /// The pairing state is reset so that the application can retry, possibly with lower requirements.
+    fn pairing_failed<P>(&self, reason: Reason, storage: &ConnectionStorage<P>) -> Result<(), Error> {
+        {
+            let mut pairing_state = self.pairing_state.borrow_mut();
+            if pairing_state.handle.is_none() || pairing_state.handle == storage.handle {
+                pairing_state.clear();
+            }
+        }
+        if reason != Reason::RepeatedAttempts {
+            if let Some(identity) = storage.peer_identity {
+                self.state.borrow_mut().pairing_failed(identity.bd_addr, Instant::now());
+            }
+        }
+        let _ = storage.events.try_send(ConnectionEvent::PairingFailed { reason });
         self.pairing_result(reason)
     }
 
@@ -676,6 +1059,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         payload: &[u8],
         connections: &ConnectionManager<P>,
         handle: ConnHandle,
+        storage: &ConnectionStorage<P::Packet>,
     ) -> Result<(), Error> {
         let peer_features = PairingFeatures::decode(payload).map_err(|_| Error::Security(Reason::InvalidParameters))?;
         {
@@ -684,23 +1068,33 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 return Err(Error::Security(Reason::CommandNotSupported));
             }
         }
-        if peer_features.maximum_encryption_key_size < ENCRYPTION_KEY_SIZE_128_BITS {
-            return Err(Error::Security(Reason::EncryptionKeySize));
-        }
-        if !peer_features.security_properties.secure_connection() {
-            return Err(Error::Security(Reason::UnspecifiedReason));
-        }
         let mut local_features = PairingFeatures {
-            io_capabilities: IoCapabilities::NoInputNoOutput,
+            io_capabilities: self.local_io_capabilities(LeConnRole::Peripheral),
             security_properties: AuthReq::new(BondingFlag::Bonding),
             ..Default::default()
         };
+        self.state.borrow().policy.check(&local_features, &peer_features)?;
 
         // Set identity key flag
         if peer_features.initiator_key_distribution.identity_key() {
             local_features.initiator_key_distribution.set_identity_key();
         }
 
+        // Distribute the identity the peer connected to, if it can be resolved
+        if peer_features.responder_key_distribution.identity_key()
+            && storage.local_identity.is_some_and(|identity| identity.irk.is_some())
+        {
+            local_features.responder_key_distribution.set_identity_key();
+        }
+
+        // Set signing key flags
+        if peer_features.initiator_key_distribution.signing_key() {
+            local_features.initiator_key_distribution.set_signing_key();
+        }
+        if peer_features.responder_key_distribution.signing_key() {
+            local_features.responder_key_distribution.set_signing_key();
+        }
+
         {
             let pairing_state = self.pairing_state.borrow();
 
@@ -748,6 +1142,8 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             if pairing_state.state != PairingState::Request {
                 return Err(Error::InvalidState);
             }
+            let local_features = pairing_state.local_features.as_ref().ok_or(Error::InvalidValue)?;
+            self.state.borrow().policy.check(local_features, &peer_features)?;
         }
 
         let mut rng_borrow = self.rng.borrow_mut();
@@ -781,6 +1177,8 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         {
             let mut pairing_state = self.pairing_state.borrow_mut();
             pairing_state.peer_features = Some(peer_features);
+            pairing_state.method =
+                self.choose_pairing_method(&pairing_state.local_features, &pairing_state.peer_features);
             pairing_state.public_key = Some(public_key);
             pairing_state.secret_key = Some(secret_key);
             pairing_state.state = PairingState::CentralPublicKey;
@@ -828,20 +1226,40 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 let local_nonce = Nonce::new(rng);
                 (dh_key, local_nonce, pairing_state.method)
             };
-            if method != PairingMethod::LeSecureConnectionNumericComparison {
-                return Err(Error::InvalidValue);
+            match method {
+                PairingMethod::LeSecureConnectionNumericComparison => {}
+                PairingMethod::LeSecureConnectionPasskey => {
+                    // The central sends the first confirm of each passkey entry round
+                    let local_public_key = self.pairing_state.borrow().public_key.ok_or(Error::InvalidValue)?;
+                    self.send_passkey_confirm(
+                        connections,
+                        handle,
+                        &local_nonce,
+                        &local_public_key,
+                        &peer_public_key,
+                        0,
+                    )?;
+                }
+                _ => return Err(Error::InvalidValue),
             }
             {
                 let mut pairing_state = self.pairing_state.borrow_mut();
                 pairing_state.public_key_peer = Some(peer_public_key);
                 pairing_state.local_nonce = Some(local_nonce);
                 pairing_state.dh_key = Some(dh_key);
+                pairing_state.passkey_round = 0;
                 pairing_state.state = PairingState::PeripheralPublicKey;
             }
         } else {
             let secret_key = SecretKey::new(rng);
             let public_key = secret_key.public_key();
 
+            // Validate the key of the peer before revealing our own
+            let dh_key = match secret_key.dh_key(peer_public_key) {
+                Some(dh_key) => Ok(dh_key),
+                None => Err(Error::Security(Reason::InvalidParameters)),
+            }?;
+
             let mut x = [0u8; 32];
             let mut y = [0u8; 32];
             x.copy_from_slice(public_key.x.as_be_bytes());
@@ -864,27 +1282,25 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 }
             }
 
-            let dh_key = match secret_key.dh_key(peer_public_key) {
-                Some(dh_key) => Ok(dh_key),
-                None => Err(Error::Security(Reason::InvalidParameters)),
-            }?;
-
             // SUBTLE: The order of these send/recv ops is important. See last
             // paragraph of Section 2.3.5.6.2.
             let local_nonce = Nonce::new(rng);
-            let confirm = local_nonce.f4(public_key.x(), peer_public_key.x(), 0);
+            // With passkey entry the peripheral waits for the confirm of the central instead
+            if self.pairing_state.borrow().method != PairingMethod::LeSecureConnectionPasskey {
+                let confirm = local_nonce.f4(public_key.x(), peer_public_key.x(), 0);
 
-            let mut packet = self.prepare_packet(Command::PairingConfirm, connections)?;
+                let mut packet = self.prepare_packet(Command::PairingConfirm, connections)?;
 
-            let response = packet.payload_mut();
+                let response = packet.payload_mut();
 
-            response.copy_from_slice(&confirm.0.to_le_bytes());
+                response.copy_from_slice(&confirm.0.to_le_bytes());
 
-            match self.try_send_packet(packet, connections, handle) {
-                Ok(()) => (),
-                Err(error) => {
-                    error!("[security manager] Failed to send confirm {:?}", error);
-                    return Err(error);
+                match self.try_send_packet(packet, connections, handle) {
+                    Ok(()) => (),
+                    Err(error) => {
+                        error!("[security manager] Failed to send confirm {:?}", error);
+                        return Err(error);
+                    }
                 }
             }
             {
@@ -911,6 +1327,46 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         let confirm = Confirm(u128::from_le_bytes(
             payload.try_into().map_err(|_| Error::InvalidValue)?,
         ));
+        let (role, method) = {
+            let pairing_state = self.pairing_state.borrow();
+            // A confirm is only expected once per round, after the public keys were exchanged
+            let expected = match pairing_state.role {
+                LeConnRole::Central => PairingState::PeripheralPublicKey,
+                _ => PairingState::PeripheralConfirm,
+            };
+            if pairing_state.state != expected || pairing_state.confirm.is_some() {
+                return Err(Error::InvalidState);
+            }
+            (pairing_state.role, pairing_state.method)
+        };
+        if role == LeConnRole::Peripheral {
+            // Only passkey entry has the central send a confirm, answered with a confirm for the round
+            if method != PairingMethod::LeSecureConnectionPasskey {
+                return Err(Error::InvalidState);
+            }
+            let (local_public_key, peer_public_key, round) = {
+                let pairing_state = self.pairing_state.borrow();
+                (
+                    pairing_state.public_key.ok_or(Error::InvalidValue)?,
+                    pairing_state.public_key_peer.ok_or(Error::InvalidValue)?,
+                    pairing_state.passkey_round,
+                )
+            };
+            let local_nonce = Nonce::new(self.rng.borrow_mut().deref_mut());
+            self.send_passkey_confirm(
+                connections,
+                handle,
+                &local_nonce,
+                &local_public_key,
+                &peer_public_key,
+                round,
+            )?;
+            let mut pairing_state = self.pairing_state.borrow_mut();
+            pairing_state.local_nonce = Some(local_nonce);
+            pairing_state.confirm = Some(confirm);
+            pairing_state.state = PairingState::CentralRandom;
+            return Ok(());
+        }
         {
             let pairing_state = self.pairing_state.borrow();
 
@@ -959,22 +1415,51 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 .try_into()
                 .map_err(|_| Error::Security(Reason::InvalidParameters))?,
         ));
-        let (role, local_nonce, local_public_key, peer_public_key) = {
+        let (role, method, round, local_nonce, local_public_key, peer_public_key) = {
             let pairing_state = self.pairing_state.borrow();
+            // The random follows the confirm of the round, only the peripheral of numeric comparison sends none
+            let expected = match (pairing_state.role, pairing_state.method) {
+                (LeConnRole::Peripheral, PairingMethod::LeSecureConnectionNumericComparison) => {
+                    PairingState::PeripheralConfirm
+                }
+                _ => PairingState::CentralRandom,
+            };
+            if pairing_state.state != expected {
+                return Err(Error::InvalidState);
+            }
             let local_nonce = pairing_state.local_nonce.ok_or(Error::InvalidValue)?;
             let local_public_key = pairing_state.public_key.ok_or(Error::InvalidValue)?;
             let peer_public_key = pairing_state.public_key_peer.ok_or(Error::InvalidValue)?;
-            (pairing_state.role, local_nonce, local_public_key, peer_public_key)
+            (
+                pairing_state.role,
+                pairing_state.method,
+                pairing_state.passkey_round,
+                local_nonce,
+                local_public_key,
+                peer_public_key,
+            )
         };
-        if role == LeConnRole::Central {
-            let pairing_state = self.pairing_state.borrow();
-            let peer_confirm = pairing_state.confirm.ok_or(Error::InvalidValue)?;
+        // A peer reflecting our own random value is attempting to impersonate us
+        if peer_nonce == local_nonce {
+            return Err(Error::Security(Reason::ConfirmValueFailed));
+        }
+        let passkey_entry = method == PairingMethod::LeSecureConnectionPasskey;
+        if role == LeConnRole::Central || passkey_entry {
+            // Each confirm is checked once, the next round starts with a new one
+            let peer_confirm = self
+                .pairing_state
+                .borrow_mut()
+                .confirm
+                .take()
+                .ok_or(Error::InvalidValue)?;
             // Calculate and check confirm
-            let local_confirm = peer_nonce.f4(peer_public_key.x(), local_public_key.x(), 0);
+            let z = if passkey_entry { self.passkey_bit(round)? } else { 0 };
+            let local_confirm = peer_nonce.f4(peer_public_key.x(), local_public_key.x(), z);
             if local_confirm != peer_confirm {
                 return Err(Error::Security(Reason::ConfirmValueFailed));
             }
-        } else {
+        }
+        if role == LeConnRole::Peripheral {
             let mut packet = self.prepare_packet(Command::PairingRandom, connections)?;
 
             let response = packet.payload_mut();
@@ -989,6 +1474,27 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 }
             }
         }
+        if passkey_entry && round + 1 < PASSKEY_ROUNDS {
+            // Continue with the next bit of the passkey, started by a new confirm from the central
+            if role == LeConnRole::Central {
+                let local_nonce = Nonce::new(self.rng.borrow_mut().deref_mut());
+                self.send_passkey_confirm(
+                    connections,
+                    handle,
+                    &local_nonce,
+                    &local_public_key,
+                    &peer_public_key,
+                    round + 1,
+                )?;
+                let mut pairing_state = self.pairing_state.borrow_mut();
+                pairing_state.local_nonce = Some(local_nonce);
+                pairing_state.state = PairingState::PeripheralPublicKey;
+            } else {
+                self.pairing_state.borrow_mut().state = PairingState::PeripheralConfirm;
+            }
+            self.pairing_state.borrow_mut().passkey_round = round + 1;
+            return Ok(());
+        }
         let (peer_nonce, mac_key, ltk, local_check) = {
             let pairing_state = self.pairing_state.borrow();
             let peer_address_kind = storage.peer_addr_kind.ok_or(Error::InvalidValue)?;
@@ -997,24 +1503,26 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 kind: peer_address_kind,
                 addr: peer_identity.bd_addr,
             };
-            let local_address = self.state.borrow().local_address.ok_or(Error::InvalidValue)?;
+            let local_address = self.local_address(storage)?;
             let dh_key = pairing_state.dh_key.as_ref().ok_or(Error::InvalidValue)?;
             let local_features = pairing_state.local_features.ok_or(Error::InvalidValue)?;
 
-            let vb = if role == LeConnRole::Peripheral {
-                peer_nonce.g2(peer_public_key.x(), local_public_key.x(), &local_nonce)
-            } else {
-                local_nonce.g2(local_public_key.x(), peer_public_key.x(), &peer_nonce)
-            };
+            if !passkey_entry {
+                let vb = if role == LeConnRole::Peripheral {
+                    peer_nonce.g2(peer_public_key.x(), local_public_key.x(), &local_nonce)
+                } else {
+                    local_nonce.g2(local_public_key.x(), peer_public_key.x(), &peer_nonce)
+                };
 
-            // should display the code and get confirmation from user (pin ok or not) - if not okay send a pairing-failed
-            // assume it's correct or the user will cancel on central
-            info!("Display code is ** {} **", vb.0);
+                // should display the code and get confirmation from user (pin ok or not) - if not okay send a pairing-failed
+                // assume it's correct or the user will cancel on central
+                info!("Display code is ** {} **", vb.0);
+            }
 
             // Authentication stage 2 and long term key calculation
             // ([Vol 3] Part H, Section 2.3.5.6.5 and C.2.2.4).
 
-            let ra = 0;
+            let ra = self.check_r(method)?;
 
             let (mac_key, ltk, local_check) = if role == LeConnRole::Peripheral {
                 let (mac_key, ltk) = dh_key.f5(peer_nonce, local_nonce, peer_address, local_address);
@@ -1045,7 +1553,9 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             let mut pairing_state = self.pairing_state.borrow_mut();
             pairing_state.peer_nonce = Some(peer_nonce);
             pairing_state.mac_key = Some(mac_key);
-            pairing_state.ltk = Some(ltk.0);
+            // Shorten the key to the negotiated encryption key size ([Vol 3] Part H, Section 2.3.4)
+            let key_size = pairing_state.key_size().unwrap_or(ENCRYPTION_KEY_SIZE_128_BITS);
+            pairing_state.ltk = Some(ltk.0 & (u128::MAX >> (8 * u32::from(ENCRYPTION_KEY_SIZE_128_BITS - key_size))));
             pairing_state.local_check = Some(local_check);
             pairing_state.state = if role == LeConnRole::Central {
                 PairingState::CentralKeyCheck
@@ -1094,16 +1604,17 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                 kind: peer_address_kind,
                 addr: peer_identity.bd_addr,
             };
-            let local_address = self.state.borrow().local_address.ok_or(Error::InvalidValue)?;
+            let local_address = self.local_address(storage)?;
             let mac_key = pairing_state.mac_key.as_ref().ok_or(Error::InvalidValue)?;
             let peer_features = pairing_state.peer_features.ok_or(Error::InvalidValue)?;
             let local_check = pairing_state.local_check.ok_or(Error::InvalidValue)?;
+            let rb = self.check_r(pairing_state.method)?;
 
             let expected_check = mac_key
                 .f6(
                     peer_nonce,
                     local_nonce,
-                    0,
+                    rb,
                     peer_features.as_io_cap(),
                     peer_address,
                     local_address,
@@ -1188,8 +1699,114 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         Ok(())
     }
 
+    fn handle_signing_information(&self, payload: &[u8], handle: ConnHandle) -> Result<(), Error> {
+        let csrk = ConnectionSignatureResolvingKey::from_le_bytes(payload.try_into().map_err(|_| Error::InvalidValue)?);
+        {
+            let mut pairing_state = self.pairing_state.borrow_mut();
+            if !pairing_state
+                .key_distribution()
+                .is_some_and(|(_, peer)| peer.signing_key())
+            {
+                return Err(Error::Security(Reason::CommandNotSupported));
+            }
+            pairing_state.peer_csrk = Some(csrk);
+        }
+        let bond_info = self.store_pairing()?;
+        self.try_send_event(SecurityEventData::EnableEncryption(handle, bond_info))?;
+        debug!("Signing information: CSRK: {:?}", csrk);
+        Ok(())
+    }
+
+    /// Distribute the local keys once the link is encrypted, if they were negotiated
+    fn distribute_keys<P: PacketPool>(
+        &self,
+        connections: &ConnectionManager<P>,
+        handle: ConnHandle,
+    ) -> Result<(), Error> {
+        let Some((keys, _)) = self.pairing_state.borrow().key_distribution() else {
+            return Ok(());
+        };
+        if keys.identity_key() {
+            self.distribute_identity(connections, handle)?;
+        }
+        if keys.signing_key() {
+            self.distribute_signing_key(connections, handle)?;
+        }
+        Ok(())
+    }
+
+    /// Distribute the local identity the peer connected to
+    fn distribute_identity<P: PacketPool>(
+        &self,
+        connections: &ConnectionManager<P>,
+        handle: ConnHandle,
+    ) -> Result<(), Error> {
+        let identity = connections.with_connected_handle(handle, |storage| Ok(storage.local_identity))?;
+        let (address, irk) = match identity {
+            Some(LocalIdentity {
+                address,
+                irk: Some(irk),
+                ..
+            }) => (address, irk),
+            _ => return Err(Error::InvalidState),
+        };
+
+        let mut packet = self.prepare_packet(Command::IdentityInformation, connections)?;
+        packet.payload_mut().copy_from_slice(&irk.0.to_le_bytes());
+        match self.try_send_packet(packet, connections, handle) {
+            Ok(()) => (),
+            Err(error) => {
+                error!("[security manager] Failed to send identity information {:?}", error);
+                return Err(error);
+            }
+        }
+
+        let mut packet = self.prepare_packet(Command::IdentityAddressInformation, connections)?;
+        let payload = packet.payload_mut();
+        payload[0] = u8::from(address.kind == AddrKind::RANDOM);
+        payload[1..].copy_from_slice(address.addr.raw());
+        match self.try_send_packet(packet, connections, handle) {
+            Ok(()) => (),
+            Err(error) => {
+                error!(
+                    "[security manager] Failed to send identity address information {:?}",
+                    error
+                );
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Distribute the local signing key
+    fn distribute_signing_key<P: PacketPool>(
+        &self,
+        connections: &ConnectionManager<P>,
+        handle: ConnHandle,
+    ) -> Result<(), Error> {
+        let csrk = ConnectionSignatureResolvingKey::generate(self.rng.borrow_mut().deref_mut());
+
+        let mut packet = self.prepare_packet(Command::SigningInformation, connections)?;
+        packet.payload_mut().copy_from_slice(&csrk.to_le_bytes());
+        match self.try_send_packet(packet, connections, handle) {
+            Ok(()) => (),
+            Err(error) => {
+                error!("[security manager] Failed to send signing information {:?}", error);
+                return Err(error);
+            }
+        }
+
+        self.pairing_state.borrow_mut().local_csrk = Some(csrk);
+        let bond_info = self.store_pairing()?;
+        connections.post_handle_event(handle, ConnectionEvent::Bonded { bond_info })
+    }
+
     /// Handle recevied events from HCI
-    pub(crate) fn handle_event(&self, event: &Event) -> Result<(), Error> {
+    pub(crate) fn handle_event<P: PacketPool>(
+        &self,
+        event: &Event,
+        connections: &ConnectionManager<P>,
+    ) -> Result<(), Error> {
         match event {
             Event::EncryptionChangeV1(event_data) => match event_data.status.to_result() {
                 Ok(()) => {
@@ -1201,7 +1818,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                                 pairing_state.role == LeConnRole::Central
                                     && pairing_state.handle == Some(event_data.handle)
                             }
-                            PairingState::PeripheralKeyCheck => {
+                            PairingState::SecurityRequest | PairingState::PeripheralKeyCheck => {
                                 pairing_state.role == LeConnRole::Peripheral
                                     && pairing_state.handle == Some(event_data.handle)
                             }
@@ -1210,6 +1827,7 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
                     };
                     if checks_ok {
                         if event_data.enabled {
+                            self.distribute_keys(connections, event_data.handle)?;
                             self.pairing_result(Reason::Success)?;
                         }
                     } else {
@@ -1234,20 +1852,29 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
         if let (Some(ltk), Some(peer_address)) = (pairing_state.ltk, pairing_state.peer_address) {
             let ltk = LongTermKey(ltk);
             // Use IRK in bond information if available
-            let bond = BondInformation {
+            let mut bond = BondInformation {
                 ltk,
                 identity: Identity {
                     bd_addr: peer_address.addr,
                     irk,
                 },
+                peer_csrk: pairing_state.peer_csrk.map(SigningKey::new),
+                local_csrk: pairing_state.local_csrk.map(SigningKey::new),
+                key_size: pairing_state.key_size().unwrap_or(ENCRYPTION_KEY_SIZE_128_BITS),
             };
 
             let bonds = &mut self.state.borrow_mut().bond;
 
             let mut replaced = false;
-            for bond in bonds.iter_mut() {
-                if bond.identity.match_address(&peer_address.addr) {
-                    bond.ltk = ltk;
+            for stored in bonds.iter_mut() {
+                if stored.identity.match_address(&peer_address.addr) {
+                    stored.ltk = ltk;
+                    stored.key_size = bond.key_size;
+                    // Keep the sign counters of keys that did not change
+                    stored.peer_csrk = renew_signing_key(stored.peer_csrk, pairing_state.peer_csrk);
+                    stored.local_csrk = renew_signing_key(stored.local_csrk, pairing_state.local_csrk);
+                    bond.peer_csrk = stored.peer_csrk;
+                    bond.local_csrk = stored.local_csrk;
                     replaced = true;
                     trace!("[security manager] Replaced bond for {}", peer_address);
                     break;
@@ -1334,10 +1961,79 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
     /// Update pairing result
     fn pairing_result(&self, reason: Reason) -> Result<(), Error> {
         self.timer_disable()?;
+        if reason == Reason::Success {
+            self.pairing_state.borrow_mut().complete = true;
+            if let Some(address) = self.pairing_state.borrow().peer_address {
+                self.state.borrow_mut().pairing_succeeded(&address.addr);
+            }
+        }
         self.result_signal.signal(reason);
         Ok(())
     }
 
+    /// IO capabilities of the local device
+    ///
+    /// With a fixed passkey, the peripheral acts as the display of the passkey and the central as the keyboard,
+    /// so passkey entry is used between two devices of the same fleet.
+    fn local_io_capabilities(&self, role: LeConnRole) -> IoCapabilities {
+        match (self.state.borrow().passkey, role) {
+            (None, _) => IoCapabilities::NoInputNoOutput,
+            (Some(_), LeConnRole::Central) => IoCapabilities::KeyboardOnly,
+            (Some(_), _) => IoCapabilities::DisplayOnly,
+        }
+    }
+
+    /// Local address of a connection, the identity the peer connected to or the host address
+    fn local_address<P>(&self, storage: &ConnectionStorage<P>) -> Result<Address, Error> {
+        match storage.local_identity {
+            Some(identity) => Ok(identity.address),
+            None => self.state.borrow().local_address.ok_or(Error::InvalidValue),
+        }
+    }
+
+    /// Passkey bit used in the confirm values of a passkey entry round ([Vol 3] Part H, Section 2.3.5.6.3)
+    fn passkey_bit(&self, round: u8) -> Result<u8, Error> {
+        let passkey = self.state.borrow().passkey.ok_or(Error::InvalidValue)?;
+        Ok(0x80 | ((passkey >> round) & 1) as u8)
+    }
+
+    /// Value of `r` used in the DH key check values ([Vol 3] Part H, Section 2.3.5.6.5)
+    fn check_r(&self, method: PairingMethod) -> Result<u128, Error> {
+        if method == PairingMethod::LeSecureConnectionPasskey {
+            let passkey = self.state.borrow().passkey.ok_or(Error::InvalidValue)?;
+            Ok(u128::from(passkey))
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Send the confirm value of a passkey entry round
+    fn send_passkey_confirm<P: PacketPool>(
+        &self,
+        connections: &ConnectionManager<P>,
+        handle: ConnHandle,
+        local_nonce: &Nonce,
+        local_public_key: &PublicKey,
+        peer_public_key: &PublicKey,
+        round: u8,
+    ) -> Result<(), Error> {
+        let confirm = local_nonce.f4(local_public_key.x(), peer_public_key.x(), self.passkey_bit(round)?);
+
+        let mut packet = self.prepare_packet(Command::PairingConfirm, connections)?;
+
+        let response = packet.payload_mut();
+
+        response.copy_from_slice(&confirm.0.to_le_bytes());
+
+        match self.try_send_packet(packet, connections, handle) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                error!("[security manager] Failed to send confirm {:?}", error);
+                Err(error)
+            }
+        }
+    }
+
     /// Choose pairing method
     ///
     /// https://www.bluetooth.com/wp-content/uploads/Files/Specification/HTML/Core-60/out/en/host/generic-access-profile.html#UUID-9bec8715-4a79-31bd-f551-37336e9ff099_N1680553287676
@@ -1367,10 +2063,387 @@ impl<const BOND_COUNT: usize> SecurityManager<BOND_COUNT> {
             (IoCapabilities::KeyboardOnly, IoCapabilities::DisplayOnly)
             | (IoCapabilities::KeyboardOnly, IoCapabilities::DisplayYesNo)
             | (IoCapabilities::KeyboardOnly, IoCapabilities::KeyboardOnly)
+            | (IoCapabilities::KeyboardOnly, IoCapabilities::KeyboardDisplay)
             | (IoCapabilities::DisplayOnly, IoCapabilities::KeyboardOnly)
-            | (IoCapabilities::DisplayYesNo, IoCapabilities::KeyboardOnly) => PairingMethod::LeSecureConnectionPasskey,
+            | (IoCapabilities::DisplayOnly, IoCapabilities::KeyboardDisplay)
+            | (IoCapabilities::DisplayYesNo, IoCapabilities::KeyboardOnly)
+            | (IoCapabilities::KeyboardDisplay, IoCapabilities::KeyboardOnly)
+            | (IoCapabilities::KeyboardDisplay, IoCapabilities::DisplayOnly) => {
+                PairingMethod::LeSecureConnectionPasskey
+            }
 
             _ => PairingMethod::LeSecureConnectionNumericComparison,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::task::Poll;
+    use std::boxed::Box;
+
+    use embassy_futures::block_on;
+
+    use super::*;
+    use crate::prelude::DefaultPacketPool;
+
+    type Manager = ConnectionManager<'static, DefaultPacketPool>;
+
+    fn connect(role: LeConnRole) -> (&'static Manager, Connection<'static, DefaultPacketPool>) {
+        let storage = Box::leak(Box::new([const { ConnectionStorage::new() }; 1]));
+        let mgr: &'static Manager = Box::leak(Box::new(ConnectionManager::new(&mut storage[..], 23)));
+        mgr.connect(
+            ConnHandle::new(1),
+            AddrKind::RANDOM,
+            BdAddr::new([1, 2, 3, 4, 5, 6]),
+            role,
+        )
+        .unwrap();
+        let Poll::Ready(conn) = mgr.poll_accept(role, &[], None) else {
+            panic!("expected connection to be accepted");
+        };
+        (mgr, conn)
+    }
+
+    /// Deliver a command from the peer.
+    fn receive(mgr: &Manager, command: Command, payload: &[u8]) -> Result<(), Error> {
+        // Timer changes are handled by the runner
+        while mgr.security_manager.events.try_receive().is_ok() {}
+        let mut packet = DefaultPacketPool::allocate().unwrap();
+        packet.as_mut()[0] = command.into();
+        packet.as_mut()[1..=payload.len()].copy_from_slice(payload);
+        mgr.handle_security_channel(ConnHandle::new(1), Pdu::new(packet, payload.len() + 1))
+    }
+
+    /// Next command sent to the peer.
+    fn sent(mgr: &Manager) -> (Command, Vec<u8, 64>) {
+        let entry = block_on(mgr.outbound());
+        let data = entry.item.as_ref();
+        (
+            Command::try_from(data[4]).unwrap(),
+            Vec::from_slice(&data[5..]).unwrap(),
+        )
+    }
+
+    fn features(security_properties: AuthReq, key_size: u8) -> [u8; 6] {
+        let features = PairingFeatures {
+            security_properties,
+            maximum_encryption_key_size: key_size,
+            ..Default::default()
+        };
+        let mut buf = [0; 6];
+        features.encode(&mut buf).unwrap();
+        buf
+    }
+
+    fn public_key_bytes(key: &PublicKey) -> [u8; 64] {
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(key.x.as_be_bytes());
+        bytes[32..].copy_from_slice(key.y.as_be_bytes());
+        bytes[..32].reverse();
+        bytes[32..].reverse();
+        bytes
+    }
+
+    fn assert_failed(mgr: &Manager, result: Result<(), Error>, reason: Reason) {
+        assert_eq!(result, Err(Error::Security(reason)));
+        assert_eq!(
+            sent(mgr),
+            (Command::PairingFailed, Vec::from_slice(&[reason.into()]).unwrap())
+        );
+    }
+
+    #[test]
+    fn reject_downgrade() {
+        // Legacy pairing
+        let (mgr, _conn) = connect(LeConnRole::Peripheral);
+        let request = features(AuthReq::from(BondingFlag::Bonding as u8), 16);
+        let result = receive(mgr, Command::PairingRequest, &request);
+        assert_failed(mgr, result, Reason::AuthenticationRequirements);
+
+        // Weakened encryption key
+        let (mgr, _conn) = connect(LeConnRole::Peripheral);
+        let request = features(AuthReq::new(BondingFlag::Bonding), 7);
+        let result = receive(mgr, Command::PairingRequest, &request);
+        assert_failed(mgr, result, Reason::EncryptionKeySize);
+    }
+
+    #[test]
+    fn reject_invalid_public_key() {
+        let (mgr, _conn) = connect(LeConnRole::Peripheral);
+        let request = features(AuthReq::new(BondingFlag::Bonding), 16);
+        receive(mgr, Command::PairingRequest, &request).unwrap();
+        assert_eq!(sent(mgr).0, Command::PairingResponse);
+
+        // A point that is not on the curve fails pairing before the local public key is sent
+        let mut key = [0; 64];
+        key[0] = 1;
+        key[32] = 1;
+        let result = receive(mgr, Command::PairingPublicKey, &key);
+        assert_failed(mgr, result, Reason::InvalidParameters);
+    }
+
+    #[test]
+    fn reject_reflection() {
+        // The peer returns the public key of the central
+        let (mgr, conn) = connect(LeConnRole::Central);
+        mgr.security_manager.initiate(&conn).unwrap();
+        assert_eq!(sent(mgr).0, Command::PairingRequest);
+        let response = features(AuthReq::new(BondingFlag::Bonding), 16);
+        receive(mgr, Command::PairingResponse, &response).unwrap();
+        let (command, key) = sent(mgr);
+        assert_eq!(command, Command::PairingPublicKey);
+        let result = receive(mgr, Command::PairingPublicKey, &key);
+        assert_failed(mgr, result, Reason::InvalidParameters);
+
+        // The peer returns the random value of the central
+        let (mgr, conn) = connect(LeConnRole::Central);
+        mgr.security_manager.initiate(&conn).unwrap();
+        sent(mgr);
+        receive(mgr, Command::PairingResponse, &response).unwrap();
+        sent(mgr);
+        let peer_key = SecretKey::new(&mut ChaCha12Rng::from_seed([7; 32])).public_key();
+        receive(mgr, Command::PairingPublicKey, &public_key_bytes(&peer_key)).unwrap();
+        receive(mgr, Command::PairingConfirm, &[0; 16]).unwrap();
+        let (command, random) = sent(mgr);
+        assert_eq!(command, Command::PairingRandom);
+        let result = receive(mgr, Command::PairingRandom, &random);
+        assert_failed(mgr, result, Reason::ConfirmValueFailed);
+    }
+
+    #[test]
+    fn repeated_attempts_backoff() {
+        let mut data: SecurityManagerData<2> = SecurityManagerData::new();
+        let peer = BdAddr::new([1, 2, 3, 4, 5, 6]);
+        let now = Instant::from_secs(100);
+        assert!(!data.pairing_blocked(&peer, now));
+
+        data.pairing_failed(peer, now);
+        assert!(data.pairing_blocked(&peer, now + Duration::from_secs(1)));
+        assert!(!data.pairing_blocked(&peer, now + Duration::from_secs(2)));
+
+        // The wait interval doubles for every consecutive failure
+        data.pairing_failed(peer, now);
+        assert!(data.pairing_blocked(&peer, now + Duration::from_secs(3)));
+        assert!(!data.pairing_blocked(&peer, now + Duration::from_secs(4)));
+
+        data.pairing_succeeded(&peer);
+        assert!(!data.pairing_blocked(&peer, now));
+
+        // Other peers are not affected, and the oldest entry is replaced when full
+        let others = [BdAddr::new([1; 6]), BdAddr::new([2; 6]), BdAddr::new([3; 6])];
+        for (i, other) in others.iter().enumerate() {
+            data.pairing_failed(*other, now + Duration::from_secs(i as u64));
+        }
+        assert!(!data.pairing_blocked(&peer, now));
+        assert!(!data.pairing_blocked(&others[0], now));
+        assert!(data.pairing_blocked(&others[2], now + Duration::from_secs(2)));
+    }
+
+    /// Start passkey entry with a local peripheral, returning the public keys of the central and the peripheral.
+    fn start_passkey_entry(
+        passkey: u32,
+    ) -> (
+        &'static Manager,
+        Connection<'static, DefaultPacketPool>,
+        PublicKey,
+        PublicKey,
+    ) {
+        let (mgr, conn) = connect(LeConnRole::Peripheral);
+        mgr.security_manager.state.borrow_mut().passkey = Some(passkey);
+        mgr.security_manager.set_local_address(Address::random([7; 6]));
+        let request = PairingFeatures {
+            io_capabilities: IoCapabilities::KeyboardOnly,
+            security_properties: AuthReq::new(BondingFlag::Bonding),
+            maximum_encryption_key_size: 16,
+            ..Default::default()
+        };
+        let mut buf = [0; 6];
+        request.encode(&mut buf).unwrap();
+        receive(mgr, Command::PairingRequest, &buf).unwrap();
+        assert_eq!(sent(mgr).0, Command::PairingResponse);
+        let central = SecretKey::new(&mut ChaCha12Rng::from_seed([7; 32])).public_key();
+        receive(mgr, Command::PairingPublicKey, &public_key_bytes(&central)).unwrap();
+        let (command, peripheral) = sent(mgr);
+        assert_eq!(command, Command::PairingPublicKey);
+        (mgr, conn, central, PublicKey::from_bytes(&peripheral))
+    }
+
+    /// Run a passkey entry round as the central, returning the random value it sent.
+    fn passkey_round(mgr: &Manager, central: &PublicKey, peripheral: &PublicKey, passkey: u32, round: u8) -> Nonce {
+        let z = 0x80 | ((passkey >> round) & 1) as u8;
+        let nonce = Nonce(u128::from(round) + 1);
+        let confirm = nonce.f4(central.x(), peripheral.x(), z);
+        receive(mgr, Command::PairingConfirm, &confirm.0.to_le_bytes()).unwrap();
+        let (command, peer_confirm) = sent(mgr);
+        assert_eq!(command, Command::PairingConfirm);
+        receive(mgr, Command::PairingRandom, &nonce.0.to_le_bytes()).unwrap();
+        let (command, peer_nonce) = sent(mgr);
+        assert_eq!(command, Command::PairingRandom);
+        let peer_nonce = Nonce(u128::from_le_bytes(peer_nonce[..].try_into().unwrap()));
+        let expected = peer_nonce.f4(peripheral.x(), central.x(), z);
+        assert_eq!(&peer_confirm[..], &expected.0.to_le_bytes());
+        nonce
+    }
+
+    #[test]
+    fn passkey_entry_rounds() {
+        let passkey = 0b1010_0110_1100_1011_0101;
+        let (mgr, _conn, central, peripheral) = start_passkey_entry(passkey);
+        for round in 0..PASSKEY_ROUNDS {
+            passkey_round(mgr, &central, &peripheral, passkey, round);
+        }
+        // Waiting for the DH key check of the central
+        assert_eq!(
+            mgr.security_manager.pairing_state.borrow().state,
+            PairingState::PeripheralRandom
+        );
+    }
+
+    #[test]
+    fn passkey_entry_replayed_confirm() {
+        let passkey = 123456;
+
+        // The random of the next round is checked against a fresh confirm only
+        let (mgr, _conn, central, peripheral) = start_passkey_entry(passkey);
+        let nonce = passkey_round(mgr, &central, &peripheral, passkey, 0);
+        assert!(receive(mgr, Command::PairingRandom, &nonce.0.to_le_bytes()).is_err());
+
+        // A second confirm in the same round is rejected
+        let (mgr, _conn, central, peripheral) = start_passkey_entry(passkey);
+        let confirm = Nonce(1).f4(central.x(), peripheral.x(), 0x80);
+        receive(mgr, Command::PairingConfirm, &confirm.0.to_le_bytes()).unwrap();
+        assert_eq!(sent(mgr).0, Command::PairingConfirm);
+        assert!(receive(mgr, Command::PairingConfirm, &confirm.0.to_le_bytes()).is_err());
+    }
+
+    #[test]
+    fn passkey_entry() {
+        let sm: SecurityManager<1> = SecurityManager::new();
+        assert_eq!(
+            sm.local_io_capabilities(LeConnRole::Central),
+            IoCapabilities::NoInputNoOutput
+        );
+        assert_eq!(
+            sm.check_r(PairingMethod::LeSecureConnectionPasskey),
+            Err(Error::InvalidValue)
+        );
+
+        sm.state.borrow_mut().passkey = Some(0b101);
+        let features = |role| {
+            Some(PairingFeatures {
+                io_capabilities: sm.local_io_capabilities(role),
+                security_properties: AuthReq::new(BondingFlag::Bonding),
+                ..Default::default()
+            })
+        };
+        let central = features(LeConnRole::Central);
+        let peripheral = features(LeConnRole::Peripheral);
+        assert_eq!(
+            sm.choose_pairing_method(&central, &peripheral),
+            PairingMethod::LeSecureConnectionPasskey
+        );
+        assert_eq!(
+            sm.choose_pairing_method(&peripheral, &central),
+            PairingMethod::LeSecureConnectionPasskey
+        );
+
+        // One bit of the passkey is used in each round, and the whole passkey in the DH key check
+        assert_eq!(sm.passkey_bit(0), Ok(0x81));
+        assert_eq!(sm.passkey_bit(1), Ok(0x80));
+        assert_eq!(sm.passkey_bit(2), Ok(0x81));
+        assert_eq!(sm.passkey_bit(PASSKEY_ROUNDS - 1), Ok(0x80));
+        assert_eq!(sm.check_r(PairingMethod::LeSecureConnectionPasskey), Ok(0b101));
+        assert_eq!(sm.check_r(PairingMethod::LeSecureConnectionNumericComparison), Ok(0));
+    }
+
+    #[test]
+    fn pairing_pdus_after_pairing() {
+        let (mgr, _conn) = connect(LeConnRole::Peripheral);
+        let sm = &mgr.security_manager;
+        let handle = ConnHandle::new(1);
+        let confirm = [Command::PairingConfirm.into(), 0];
+        sm.pairing_state.borrow_mut().handle = Some(handle);
+        assert!(!sm.after_pairing(handle, &confirm));
+
+        unwrap!(sm.pairing_result(Reason::Success));
+        assert!(sm.after_pairing(handle, &confirm));
+        // Pairing may be started again, and other connections are not affected
+        assert!(!sm.after_pairing(handle, &[Command::PairingRequest.into()]));
+        assert!(!sm.after_pairing(ConnHandle::new(2), &confirm));
+        assert_eq!(
+            Error::ProtocolViolation(crate::ProtocolLayer::Smp).kind(),
+            crate::ErrorKind::Security(crate::SecurityError::ProtocolViolation)
+        );
+    }
+
+    #[test]
+    fn signed_write() {
+        let sm: SecurityManager<1> = SecurityManager::new();
+        let identity = Identity {
+            bd_addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+            irk: None,
+        };
+        let message: [&[u8]; 2] = [&[0xd2, 0x03, 0x00], &[1, 2, 3]];
+        assert_eq!(sm.sign(&identity, &message), Err(Error::NotFound));
+
+        // Both ends of the link share the key, as the local key of one bond and the peer key of the other
+        let key = SigningKey::new(ConnectionSignatureResolvingKey::new(0x1234));
+        let mut bond = BondInformation::new(identity, LongTermKey::new(0));
+        bond.local_csrk = Some(key);
+        bond.peer_csrk = Some(key);
+        sm.add_bond_information(bond).unwrap();
+
+        let first = sm.sign(&identity, &message).unwrap();
+        let second = sm.sign(&identity, &message).unwrap();
+        assert_eq!(&first[..4], &[0, 0, 0, 0]);
+        assert_eq!(&second[..4], &[1, 0, 0, 0]);
+        assert!(sm.verify_signature(&identity, &message, &first));
+        assert!(!sm.verify_signature(&identity, &[&[0xd2, 0x03, 0x00], &[1, 2, 4]], &second));
+        assert!(sm.verify_signature(&identity, &message, &second));
+
+        // Replayed sign counters are rejected
+        assert!(!sm.verify_signature(&identity, &message, &first));
+        assert!(!sm.verify_signature(&identity, &message, &second));
+
+        // The last counter value could be replayed forever
+        let last = key.csrk.sign(&message, u32::MAX);
+        assert!(!sm.verify_signature(&identity, &message, &last));
+    }
+
+    #[test]
+    fn security_policy() {
+        let local = PairingFeatures::default();
+        let mut peer = PairingFeatures {
+            security_properties: AuthReq::new(BondingFlag::Bonding),
+            maximum_encryption_key_size: 12,
+            ..Default::default()
+        };
+
+        let policy = SecurityPolicy::default();
+        assert_eq!(
+            policy.check(&local, &peer),
+            Err(Error::Security(Reason::EncryptionKeySize))
+        );
+
+        let mut policy = SecurityPolicy {
+            min_encryption_key_size: 10,
+            ..Default::default()
+        };
+        assert_eq!(policy.check(&local, &peer), Ok(12));
+
+        peer.security_properties = AuthReq::from(BondingFlag::Bonding as u8);
+        assert_eq!(
+            policy.check(&local, &peer),
+            Err(Error::Security(Reason::AuthenticationRequirements))
+        );
+
+        policy.secure_connections_only = false;
+        assert_eq!(
+            policy.check(&local, &peer),
+            Err(Error::Security(Reason::PairingNotSupported))
+        );
+    }
+}
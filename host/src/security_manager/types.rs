@@ -458,6 +458,14 @@ impl AuthReq {
     pub fn new(bonding: BondingFlag) -> Self {
         AuthReq((bonding as u8) | AUTH_REQ_MITM | AUTH_REQ_SECURE_CONNECTION | AUTH_REQ_CT2)
     }
+    /// Build a AuthReq octet requesting at least the given security level
+    pub fn with_level(bonding: BondingFlag, level: SecurityMode1Level) -> Self {
+        let mitm = match level {
+            SecurityMode1Level::Level1 | SecurityMode1Level::Level2 => 0,
+            SecurityMode1Level::Level3 | SecurityMode1Level::Level4 => AUTH_REQ_MITM,
+        };
+        AuthReq((bonding as u8) | mitm | AUTH_REQ_SECURE_CONNECTION | AUTH_REQ_CT2)
+    }
     /// Bond requested
     pub fn bond(&self) -> BondingFlag {
         if let Ok(v) = BondingFlag::try_from(self.0) {
@@ -781,6 +789,16 @@ pub enum SecurityLevel {
 mod tests {
     use super::*;
 
+    #[test]
+    fn auth_req_with_level() {
+        let level2 = AuthReq::with_level(BondingFlag::Bonding, SecurityMode1Level::Level2);
+        assert!(!level2.man_in_the_middle());
+        assert!(level2.secure_connection());
+
+        let level4 = AuthReq::with_level(BondingFlag::Bonding, SecurityMode1Level::Level4);
+        assert!(u8::from(level4) == u8::from(AuthReq::new(BondingFlag::Bonding)));
+    }
+
     #[test]
     fn reason_variant() {
         assert!(u8::from(Reason::PasskeyEntryFailed) == 1);
@@ -0,0 +1,33 @@
+//! Typed values for standard GATT services.
+//!
+//! The types in this module implement [`AsGatt`](crate::types::gatt_traits::AsGatt) and
+//! [`FromGatt`](crate::types::gatt_traits::FromGatt), so they can be used as characteristic and descriptor
+//! values in a `#[gatt_service]`, or to decode values read from a peer with a GATT client.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::heart_rate::{BodySensorLocation, HeartRateMeasurement};
+//!
+//! #[gatt_service(uuid = service::HEART_RATE)]
+//! struct HeartRateService {
+//!     #[characteristic(uuid = characteristic::HEART_RATE_MEASUREMENT, notify)]
+//!     measurement: HeartRateMeasurement,
+//!     #[characteristic(uuid = characteristic::BODY_SENSOR_LOCATION, read, value = BodySensorLocation::Wrist)]
+//!     location: BodySensorLocation,
+//! }
+//! ```
+pub mod broadcast_audio_scan;
+#[cfg(feature = "security")]
+pub mod csis;
+pub mod cycling;
+pub mod environmental;
+pub mod esl;
+pub mod heart_rate;
+pub mod ipsp;
+pub mod media_control;
+pub mod mesh;
+pub mod provisioning;
+pub mod proximity;
+pub mod scan_parameters;
+pub mod tmap;
+pub mod volume_control;
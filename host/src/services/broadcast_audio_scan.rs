@@ -0,0 +1,473 @@
+//! Broadcast Audio Scan service client.
+//!
+//! A broadcast assistant, such as a phone or a remote, uses the Broadcast Audio Scan service of a scan delegator,
+//! such as a hearing aid or earbud, to tell it which LE Audio broadcasts to synchronize to. The assistant scans for
+//! broadcasts on behalf of the delegator, adds the sources it found, and provides the broadcast code of encrypted
+//! broadcasts. The delegator reports what it is synchronized to in its Broadcast Receive State characteristics.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::broadcast_audio_scan::{BassOperation, BroadcastAudioScanClient, BroadcastSource, PaSync};
+//!
+//! async fn add<C: Controller, P: PacketPool>(client: &GattClient<'_, C, P, 4>, source: Address) -> Result<(), BleHostError<C::Error>> {
+//!     let bass = BroadcastAudioScanClient::discover(client).await?;
+//!     bass.write(client, &BassOperation::RemoteScanStarted).await?;
+//!     // Scan for the broadcast source
+//!     bass.write(client, &BassOperation::AddSource(BroadcastSource {
+//!         address: source,
+//!         adv_sid: 0,
+//!         broadcast_id: 0x123456,
+//!         pa_sync: PaSync::SyncPastNotAvailable,
+//!         pa_interval: 0xffff,
+//!         subgroups: &[],
+//!     })).await?;
+//!     bass.write(client, &BassOperation::RemoteScanStopped).await
+//! }
+//! ```
+use bt_hci::controller::Controller;
+use bt_hci::param::{AddrKind, BdAddr};
+use bt_hci::uuid::{characteristic, service};
+use heapless::Vec;
+
+use crate::attribute::Characteristic;
+use crate::gatt::GattClient;
+use crate::types::gatt_traits::FromGattError;
+use crate::{Address, BleHostError, Error, PacketPool};
+
+/// Maximum number of Broadcast Receive State characteristics discovered on a delegator.
+pub const MAX_RECEIVE_STATES: usize = 4;
+
+/// Maximum size of a Broadcast Receive State value read from a delegator.
+pub const MAX_RECEIVE_STATE_LEN: usize = 256;
+
+/// Maximum size of an operation written to the Broadcast Audio Scan Control Point.
+pub const MAX_CONTROL_POINT_LEN: usize = 256;
+
+/// Value of `pa_interval` when the periodic advertising interval is unknown.
+pub const PA_INTERVAL_UNKNOWN: u16 = 0xffff;
+
+/// Value of `bis_sync` when the delegator may synchronize to any BIS of the subgroup.
+pub const BIS_SYNC_NO_PREFERENCE: u32 = 0xffff_ffff;
+
+/// Whether the delegator should synchronize to the periodic advertising of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum PaSync {
+    /// Do not synchronize, or stop synchronizing.
+    DoNotSync = 0x00,
+    /// Synchronize, the assistant can transfer the synchronization with PAST.
+    SyncPastAvailable = 0x01,
+    /// Synchronize by scanning, PAST is not available.
+    SyncPastNotAvailable = 0x02,
+}
+
+/// Periodic advertising synchronization state of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum PaSyncState {
+    /// Not synchronized.
+    NotSynchronized,
+    /// The delegator waits for the assistant to transfer the synchronization with PAST.
+    SyncInfoRequest,
+    /// Synchronized.
+    Synchronized,
+    /// Synchronization failed.
+    Failed,
+    /// The delegator needs PAST, which the assistant did not offer.
+    NoPast,
+}
+
+/// Encryption state of the broadcast isochronous group of a source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BigEncryption {
+    /// The broadcast is not encrypted.
+    NotEncrypted,
+    /// The delegator needs the broadcast code, written with [`BassOperation::SetBroadcastCode`].
+    BroadcastCodeRequired,
+    /// The delegator is decrypting the broadcast.
+    Decrypting,
+    /// The broadcast code is wrong.
+    BadCode([u8; 16]),
+}
+
+/// Subgroup of a broadcast, with the BISes to synchronize to and its metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Subgroup<'a> {
+    /// Bitfield of the BIS indices, starting at bit 0 for BIS index 1, or [`BIS_SYNC_NO_PREFERENCE`].
+    pub bis_sync: u32,
+    /// LTV encoded metadata of the subgroup.
+    pub metadata: &'a [u8],
+}
+
+/// Iterator over the subgroups of a [`BroadcastReceiveState`].
+#[derive(Debug, Clone)]
+pub struct Subgroups<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Subgroups<'a> {
+    type Item = Subgroup<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (subgroup, rest) = split_subgroup(self.data).ok()?;
+        self.data = rest;
+        Some(subgroup)
+    }
+}
+
+fn split_subgroup(data: &[u8]) -> Result<(Subgroup<'_>, &[u8]), FromGattError> {
+    let [b0, b1, b2, b3, len, rest @ ..] = data else {
+        return Err(FromGattError::InvalidLength);
+    };
+    let len = usize::from(*len);
+    if rest.len() < len {
+        return Err(FromGattError::InvalidLength);
+    }
+    let subgroup = Subgroup {
+        bis_sync: u32::from_le_bytes([*b0, *b1, *b2, *b3]),
+        metadata: &rest[..len],
+    };
+    Ok((subgroup, &rest[len..]))
+}
+
+/// A broadcast the delegator is synchronized to, or trying to synchronize to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BroadcastReceiveState<'a> {
+    /// ID assigned to the source by the delegator.
+    pub source_id: u8,
+    /// Address of the broadcast source.
+    pub address: Address,
+    /// Advertising set of the broadcast source.
+    pub adv_sid: u8,
+    /// Broadcast ID, 24 bits.
+    pub broadcast_id: u32,
+    /// Periodic advertising synchronization state.
+    pub pa_sync_state: PaSyncState,
+    /// Encryption state of the broadcast.
+    pub big_encryption: BigEncryption,
+    num_subgroups: u8,
+    subgroups: &'a [u8],
+}
+
+impl<'a> BroadcastReceiveState<'a> {
+    /// Decode a Broadcast Receive State value, which is empty while the characteristic holds no source.
+    pub fn decode(data: &'a [u8]) -> Result<Option<Self>, FromGattError> {
+        if data.is_empty() {
+            return Ok(None);
+        }
+        let [source_id, kind, a0, a1, a2, a3, a4, a5, adv_sid, i0, i1, i2, pa_sync_state, big_encryption, rest @ ..] =
+            data
+        else {
+            return Err(FromGattError::InvalidLength);
+        };
+        let pa_sync_state = match pa_sync_state {
+            0x00 => PaSyncState::NotSynchronized,
+            0x01 => PaSyncState::SyncInfoRequest,
+            0x02 => PaSyncState::Synchronized,
+            0x03 => PaSyncState::Failed,
+            0x04 => PaSyncState::NoPast,
+            _ => return Err(FromGattError::InvalidValue),
+        };
+        let (big_encryption, rest) = match (big_encryption, rest) {
+            (0x00, rest) => (BigEncryption::NotEncrypted, rest),
+            (0x01, rest) => (BigEncryption::BroadcastCodeRequired, rest),
+            (0x02, rest) => (BigEncryption::Decrypting, rest),
+            (0x03, rest) if rest.len() >= 16 => {
+                let (code, rest) = rest.split_at(16);
+                (BigEncryption::BadCode(code.try_into().unwrap()), rest)
+            }
+            (0x03, _) => return Err(FromGattError::InvalidLength),
+            _ => return Err(FromGattError::InvalidValue),
+        };
+        let [num_subgroups, subgroups @ ..] = rest else {
+            return Err(FromGattError::InvalidLength);
+        };
+        let mut remaining = subgroups;
+        for _ in 0..*num_subgroups {
+            remaining = split_subgroup(remaining)?.1;
+        }
+        if !remaining.is_empty() {
+            return Err(FromGattError::InvalidLength);
+        }
+        Ok(Some(Self {
+            source_id: *source_id,
+            address: Address {
+                kind: match kind {
+                    0x00 => AddrKind::PUBLIC,
+                    0x01 => AddrKind::RANDOM,
+                    _ => return Err(FromGattError::InvalidValue),
+                },
+                addr: BdAddr::new([*a0, *a1, *a2, *a3, *a4, *a5]),
+            },
+            adv_sid: *adv_sid,
+            broadcast_id: u32::from_le_bytes([*i0, *i1, *i2, 0]),
+            pa_sync_state,
+            big_encryption,
+            num_subgroups: *num_subgroups,
+            subgroups,
+        }))
+    }
+
+    /// Number of subgroups of the broadcast.
+    pub fn num_subgroups(&self) -> u8 {
+        self.num_subgroups
+    }
+
+    /// The subgroups of the broadcast, with the BISes the delegator is synchronized to.
+    pub fn subgroups(&self) -> Subgroups<'a> {
+        Subgroups { data: self.subgroups }
+    }
+}
+
+/// A broadcast found by the assistant, added to the delegator with [`BassOperation::AddSource`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BroadcastSource<'a> {
+    /// Address of the broadcast source, public or random.
+    pub address: Address,
+    /// Advertising set of the broadcast source.
+    pub adv_sid: u8,
+    /// Broadcast ID, 24 bits.
+    pub broadcast_id: u32,
+    /// Whether to synchronize to the periodic advertising.
+    pub pa_sync: PaSync,
+    /// Periodic advertising interval in units of 1.25 ms, or [`PA_INTERVAL_UNKNOWN`].
+    pub pa_interval: u16,
+    /// Subgroups of the broadcast.
+    pub subgroups: &'a [Subgroup<'a>],
+}
+
+/// An operation written to the Broadcast Audio Scan Control Point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum BassOperation<'a> {
+    /// The assistant stopped scanning for broadcasts on behalf of the delegator.
+    RemoteScanStopped,
+    /// The assistant started scanning for broadcasts on behalf of the delegator.
+    RemoteScanStarted,
+    /// Add a broadcast source.
+    AddSource(BroadcastSource<'a>),
+    /// Change the synchronization to a source.
+    ModifySource {
+        /// ID of the source.
+        source_id: u8,
+        /// Whether to synchronize to the periodic advertising.
+        pa_sync: PaSync,
+        /// Periodic advertising interval in units of 1.25 ms, or [`PA_INTERVAL_UNKNOWN`].
+        pa_interval: u16,
+        /// Subgroups of the broadcast.
+        subgroups: &'a [Subgroup<'a>],
+    },
+    /// Provide the broadcast code of an encrypted source.
+    SetBroadcastCode {
+        /// ID of the source.
+        source_id: u8,
+        /// Broadcast code.
+        code: [u8; 16],
+    },
+    /// Remove a source the delegator is not synchronized to.
+    RemoveSource {
+        /// ID of the source.
+        source_id: u8,
+    },
+}
+
+impl BassOperation<'_> {
+    /// Encode the operation.
+    pub fn encode(&self) -> Result<Vec<u8, MAX_CONTROL_POINT_LEN>, Error> {
+        let mut out = Vec::new();
+        match self {
+            Self::RemoteScanStopped => push(&mut out, &[0x00])?,
+            Self::RemoteScanStarted => push(&mut out, &[0x01])?,
+            Self::AddSource(source) => {
+                let kind = match source.address.kind {
+                    AddrKind::PUBLIC => 0x00,
+                    AddrKind::RANDOM => 0x01,
+                    _ => return Err(Error::InvalidValue),
+                };
+                if source.broadcast_id > 0xff_ffff {
+                    return Err(Error::InvalidValue);
+                }
+                push(&mut out, &[0x02, kind])?;
+                push(&mut out, source.address.addr.raw())?;
+                push(&mut out, &[source.adv_sid])?;
+                push(&mut out, &source.broadcast_id.to_le_bytes()[..3])?;
+                push_sync(&mut out, source.pa_sync, source.pa_interval, source.subgroups)?;
+            }
+            Self::ModifySource {
+                source_id,
+                pa_sync,
+                pa_interval,
+                subgroups,
+            } => {
+                push(&mut out, &[0x03, *source_id])?;
+                push_sync(&mut out, *pa_sync, *pa_interval, subgroups)?;
+            }
+            Self::SetBroadcastCode { source_id, code } => {
+                push(&mut out, &[0x04, *source_id])?;
+                push(&mut out, code)?;
+            }
+            Self::RemoveSource { source_id } => push(&mut out, &[0x05, *source_id])?,
+        }
+        Ok(out)
+    }
+}
+
+fn push(out: &mut Vec<u8, MAX_CONTROL_POINT_LEN>, data: &[u8]) -> Result<(), Error> {
+    out.extend_from_slice(data).map_err(|_| Error::InsufficientSpace)
+}
+
+fn push_sync(
+    out: &mut Vec<u8, MAX_CONTROL_POINT_LEN>,
+    pa_sync: PaSync,
+    pa_interval: u16,
+    subgroups: &[Subgroup<'_>],
+) -> Result<(), Error> {
+    let num_subgroups = u8::try_from(subgroups.len()).map_err(|_| Error::InvalidValue)?;
+    push(out, &[pa_sync as u8])?;
+    push(out, &pa_interval.to_le_bytes())?;
+    push(out, &[num_subgroups])?;
+    for subgroup in subgroups {
+        let len = u8::try_from(subgroup.metadata.len()).map_err(|_| Error::InvalidValue)?;
+        push(out, &subgroup.bis_sync.to_le_bytes())?;
+        push(out, &[len])?;
+        push(out, subgroup.metadata)?;
+    }
+    Ok(())
+}
+
+/// Client of the Broadcast Audio Scan service of a scan delegator.
+pub struct BroadcastAudioScanClient {
+    /// Broadcast Audio Scan Control Point of the delegator.
+    pub control_point: Characteristic<Vec<u8, MAX_CONTROL_POINT_LEN>>,
+    /// Broadcast Receive State characteristics of the delegator, one per source it can hold. Subscribe to them to
+    /// follow the synchronization of the delegator.
+    pub receive_states: Vec<Characteristic<Vec<u8, MAX_RECEIVE_STATE_LEN>>, MAX_RECEIVE_STATES>,
+}
+
+impl BroadcastAudioScanClient {
+    /// Discover the Broadcast Audio Scan service of the delegator.
+    pub async fn discover<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<Self, BleHostError<C::Error>> {
+        let services = client.services_by_uuid(&service::BROADCAST_AUDIO_SCAN.into()).await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        let control_point = client
+            .characteristic_by_uuid(service, &characteristic::BROADCAST_AUDIO_SCAN_CONTROL_POINT.into())
+            .await?;
+        let receive_states = client
+            .characteristics_by_uuid(service, &characteristic::BROADCAST_RECEIVE_STATE.into())
+            .await?;
+        Ok(Self {
+            control_point,
+            receive_states,
+        })
+    }
+
+    /// Write an operation to the control point.
+    ///
+    /// The delegator rejects unsupported operations with the application error 0x80, and operations on an unknown
+    /// source with 0x81, which are returned as [`Error::Att`]. Operations that don't fit in a write request with
+    /// the negotiated ATT MTU are written with a long write.
+    pub async fn write<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+        operation: &BassOperation<'_>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let value = operation.encode()?;
+        if value.len() <= usize::from(client.connection().att_mtu()) - 3 {
+            client.write_characteristic(&self.control_point, &value).await
+        } else {
+            client.write_characteristic_long(&self.control_point, &value).await
+        }
+    }
+
+    /// Read a Broadcast Receive State characteristic into the buffer, returning the source it holds if any.
+    ///
+    /// Only the part of the value fitting in a read response is read, which holds the source and its first
+    /// subgroups with the default ATT MTU.
+    pub async fn read_receive_state<'b, C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+        receive_state: &Characteristic<Vec<u8, MAX_RECEIVE_STATE_LEN>>,
+        buf: &'b mut [u8],
+    ) -> Result<Option<BroadcastReceiveState<'b>>, BleHostError<C::Error>> {
+        let len = client.read_characteristic(receive_state, buf).await?;
+        Ok(BroadcastReceiveState::decode(&buf[..len]).map_err(|_| Error::InvalidValue)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_source() {
+        let metadata = [0x03, 0x02, 0x04, 0x00];
+        let subgroups = [Subgroup {
+            bis_sync: BIS_SYNC_NO_PREFERENCE,
+            metadata: &metadata,
+        }];
+        let operation = BassOperation::AddSource(BroadcastSource {
+            address: Address::random([1, 2, 3, 4, 5, 6]),
+            adv_sid: 2,
+            broadcast_id: 0x123456,
+            pa_sync: PaSync::SyncPastAvailable,
+            pa_interval: PA_INTERVAL_UNKNOWN,
+            subgroups: &subgroups,
+        });
+        assert_eq!(
+            operation.encode().unwrap(),
+            [
+                0x02, 0x01, 1, 2, 3, 4, 5, 6, 2, 0x56, 0x34, 0x12, 0x01, 0xff, 0xff, 1, 0xff, 0xff, 0xff, 0xff, 4,
+                0x03, 0x02, 0x04, 0x00
+            ]
+        );
+        let operation = BassOperation::SetBroadcastCode {
+            source_id: 1,
+            code: [7; 16],
+        };
+        assert_eq!(operation.encode().unwrap()[..3], [0x04, 1, 7]);
+    }
+
+    #[test]
+    fn receive_state() {
+        assert_eq!(BroadcastReceiveState::decode(&[]), Ok(None));
+
+        let data = [
+            1, 0x00, 1, 2, 3, 4, 5, 6, 2, 0x56, 0x34, 0x12, 0x02, 0x01, 2, 0x01, 0, 0, 0, 0, 0x02, 0, 0, 0, 1, 0xaa,
+        ];
+        let state = BroadcastReceiveState::decode(&data).unwrap().unwrap();
+        assert_eq!(state.source_id, 1);
+        assert_eq!(state.address.kind, AddrKind::PUBLIC);
+        assert_eq!(state.broadcast_id, 0x123456);
+        assert_eq!(state.pa_sync_state, PaSyncState::Synchronized);
+        assert_eq!(state.big_encryption, BigEncryption::BroadcastCodeRequired);
+        let mut subgroups = state.subgroups();
+        assert_eq!(
+            subgroups.next(),
+            Some(Subgroup {
+                bis_sync: 1,
+                metadata: &[]
+            })
+        );
+        assert_eq!(
+            subgroups.next(),
+            Some(Subgroup {
+                bis_sync: 2,
+                metadata: &[0xaa]
+            })
+        );
+        assert_eq!(subgroups.next(), None);
+
+        // A subgroup longer than the value
+        assert_eq!(
+            BroadcastReceiveState::decode(&data[..data.len() - 1]),
+            Err(FromGattError::InvalidLength)
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Coordinated Set Identification service client.
+//!
+//! Devices that work together, such as a pair of earbuds, are members of a coordinated set. Each member holds the
+//! Set Identity Resolving Key (SIRK) of the set, which the client reads from the first member it connects to. The
+//! other members advertise a Resolvable Set Identifier (RSI) generated from the SIRK, so the client finds them by
+//! resolving the RSIs of the devices it scans with [`Sirk::is_member`].
+//!
+//! Before a procedure on all members, the client takes the lock of each member in rank order with [`lock_set`], so
+//! that other clients do not change the set at the same time.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::csis::{CoordinatedSetClient, Sirk};
+//! use trouble_host::LongTermKey;
+//!
+//! async fn first_member<C: Controller, P: PacketPool>(
+//!     client: &GattClient<'_, C, P, 4>,
+//!     ltk: &LongTermKey,
+//! ) -> Result<Sirk, BleHostError<C::Error>> {
+//!     let set = CoordinatedSetClient::discover(client).await?;
+//!     let sirk = set.read_sirk(client, ltk).await?;
+//!     let size = set.read_size(client).await?;
+//!     // Scan for the other size - 1 members, which `sirk.is_member(report.data)` finds
+//!     Ok(sirk)
+//! }
+//! ```
+use bt_hci::controller::Controller;
+use bt_hci::param::BdAddr;
+use bt_hci::uuid::{characteristic, service};
+
+use crate::advertise::AdStructure;
+use crate::att::AttErrorCode;
+use crate::attribute::Characteristic;
+use crate::gatt::GattClient;
+use crate::{BleHostError, Error, IdentityResolvingKey, LongTermKey, PacketPool};
+
+/// Advertising data type of the Resolvable Set Identifier.
+pub const AD_TYPE_RSI: u8 = 0x2e;
+
+/// Set Identity Resolving Key, shared by the members of a coordinated set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Sirk(pub u128);
+
+impl Sirk {
+    /// Creates a SIRK from a `[u8; 16]` value in little endian.
+    pub const fn from_le_bytes(k: [u8; 16]) -> Self {
+        Self(u128::from_le_bytes(k))
+    }
+
+    /// Decode the value of the SIRK characteristic, decrypting an encrypted SIRK with the LTK of the link it was
+    /// read over.
+    pub fn decode(data: &[u8], ltk: &LongTermKey) -> Result<Self, Error> {
+        let [ty, value @ ..] = data else {
+            return Err(Error::InvalidValue);
+        };
+        let value: [u8; 16] = value.try_into().map_err(|_| Error::InvalidValue)?;
+        match ty {
+            0x00 => Ok(Self(ltk.sef(u128::from_le_bytes(value)))),
+            0x01 => Ok(Self::from_le_bytes(value)),
+            _ => Err(Error::InvalidValue),
+        }
+    }
+
+    /// Whether a Resolvable Set Identifier, in the byte order of an address, was generated from this SIRK.
+    ///
+    /// RSIs are generated like resolvable private addresses, with the SIRK in place of an IRK.
+    pub fn resolve(&self, rsi: &[u8; 6]) -> bool {
+        IdentityResolvingKey::new(self.0).resolve_address(&BdAddr::new(*rsi))
+    }
+
+    /// Whether advertising data holds a Resolvable Set Identifier of this set.
+    pub fn is_member(&self, data: &[u8]) -> bool {
+        AdStructure::decode(data).any(|ad| match ad {
+            Ok(AdStructure::Unknown { ty: AD_TYPE_RSI, data }) => {
+                data.try_into().is_ok_and(|rsi: [u8; 6]| self.resolve(&rsi))
+            }
+            _ => false,
+        })
+    }
+}
+
+/// Client of the Coordinated Set Identification service of a set member.
+pub struct CoordinatedSetClient {
+    /// SIRK characteristic of the member.
+    pub sirk: Characteristic<[u8; 17]>,
+    /// Size of the set, which is optional.
+    pub size: Option<Characteristic<u8>>,
+    /// Lock of the member, which is optional.
+    pub lock: Option<Characteristic<u8>>,
+    /// Rank of the member in the set, which is present if the lock is.
+    pub rank: Option<Characteristic<u8>>,
+}
+
+impl CoordinatedSetClient {
+    /// Discover the Coordinated Set Identification service of the member.
+    pub async fn discover<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<Self, BleHostError<C::Error>> {
+        let services = client
+            .services_by_uuid(&service::COORDINATED_SET_IDENTIFICATION.into())
+            .await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        let sirk = client
+            .characteristic_by_uuid(service, &characteristic::SET_IDENTITY_RESOLVING_KEY.into())
+            .await?;
+        let mut optional = [
+            characteristic::COORDINATED_SET_SIZE,
+            characteristic::SET_MEMBER_LOCK,
+            characteristic::SET_MEMBER_RANK,
+        ]
+        .map(|uuid| (uuid, None));
+        for (uuid, found) in optional.iter_mut() {
+            *found = match client.characteristic_by_uuid(service, &(*uuid).into()).await {
+                Ok(characteristic) => Some(characteristic),
+                Err(BleHostError::BleHost(Error::NotFound | Error::Att(AttErrorCode::ATTRIBUTE_NOT_FOUND))) => None,
+                Err(e) => return Err(e),
+            };
+        }
+        let [(_, size), (_, lock), (_, rank)] = optional;
+        Ok(Self { sirk, size, lock, rank })
+    }
+
+    /// Read the SIRK of the set, decrypting it with the LTK of the link if the member encrypted it.
+    ///
+    /// Members only expose the SIRK over an encrypted link, and some only share it out of band.
+    pub async fn read_sirk<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+        ltk: &LongTermKey,
+    ) -> Result<Sirk, BleHostError<C::Error>> {
+        let mut buf = [0; 17];
+        let len = client.read_characteristic(&self.sirk, &mut buf).await?;
+        Ok(Sirk::decode(&buf[..len], ltk)?)
+    }
+
+    /// Read the number of members of the set.
+    pub async fn read_size<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<u8, BleHostError<C::Error>> {
+        read_u8(client, self.size.as_ref()).await
+    }
+
+    /// Read the rank of the member, the order in which the lock of the members is taken starting at 1.
+    pub async fn read_rank<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<u8, BleHostError<C::Error>> {
+        read_u8(client, self.rank.as_ref()).await
+    }
+
+    /// Take the lock of the member.
+    ///
+    /// The member rejects the request with the application error 0x80 if another client holds the lock, and 0x84
+    /// if this client already does, which are returned as [`Error::Att`].
+    pub async fn lock<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let lock = self.lock.as_ref().ok_or(Error::NotSupported)?;
+        client.write_characteristic(lock, &[0x02]).await
+    }
+
+    /// Release the lock of the member.
+    pub async fn unlock<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let lock = self.lock.as_ref().ok_or(Error::NotSupported)?;
+        client.write_characteristic(lock, &[0x01]).await
+    }
+}
+
+async fn read_u8<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+    client: &GattClient<'_, C, P, MAX_SERVICES>,
+    characteristic: Option<&Characteristic<u8>>,
+) -> Result<u8, BleHostError<C::Error>> {
+    let characteristic = characteristic.ok_or(Error::NotSupported)?;
+    let mut buf = [0; 1];
+    match client.read_characteristic(characteristic, &mut buf).await? {
+        1 => Ok(buf[0]),
+        _ => Err(Error::InvalidValue.into()),
+    }
+}
+
+/// Take the lock of all members of a set, given in ascending order of rank.
+///
+/// If a member denies the lock, the locks taken so far are released in reverse order and the error is returned.
+pub async fn lock_set<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+    members: &[(&CoordinatedSetClient, &GattClient<'_, C, P, MAX_SERVICES>)],
+) -> Result<(), BleHostError<C::Error>> {
+    for (locked, (member, client)) in members.iter().enumerate() {
+        if let Err(e) = member.lock(client).await {
+            let _ = unlock_set(&members[..locked]).await;
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// Release the lock of all members of a set, given in ascending order of rank, in descending order of rank.
+///
+/// All locks are released even if some fail, returning the first error.
+pub async fn unlock_set<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+    members: &[(&CoordinatedSetClient, &GattClient<'_, C, P, MAX_SERVICES>)],
+) -> Result<(), BleHostError<C::Error>> {
+    let mut result = Ok(());
+    for (member, client) in members.iter().rev() {
+        if let Err(e) = member.unlock(client).await {
+            if result.is_ok() {
+                result = Err(e);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_rsi() {
+        // Sample data of the sih function in the CSIS specification
+        let sirk = Sirk(0x457d7d09_21a1fd22_cecd8c86_dd72cccd);
+        let rsi = [0xda, 0x48, 0x19, 0x63, 0xf5, 0x69];
+        assert!(sirk.resolve(&rsi));
+        assert!(!Sirk(1).resolve(&rsi));
+
+        let mut data = [0x02, 0x01, 0x06, 0x07, AD_TYPE_RSI, 0, 0, 0, 0, 0, 0];
+        data[5..].copy_from_slice(&rsi);
+        assert!(sirk.is_member(&data));
+        assert!(!sirk.is_member(&data[..3]));
+    }
+
+    #[test]
+    fn decode_sirk() {
+        let ltk = LongTermKey(0x676e1b9b_d448696f_061ec622_8e7549f4);
+        let sirk = Sirk(0x457d7d09_21a1fd22_cecd8c86_dd72cccd);
+
+        let mut value = [0x01; 17];
+        value[1..].copy_from_slice(&sirk.0.to_le_bytes());
+        assert_eq!(Sirk::decode(&value, &ltk), Ok(sirk));
+
+        // Encrypted with the sef function, see the `csis_sef` test of the security manager
+        value[0] = 0x00;
+        value[1..].copy_from_slice(&0x562c33aa_23fda377_7060099f_fc6b07f4_u128.to_le_bytes());
+        assert_eq!(Sirk::decode(&value, &ltk), Ok(sirk));
+        assert_eq!(Sirk::decode(&value[..16], &ltk), Err(Error::InvalidValue));
+    }
+}
@@ -0,0 +1,196 @@
+//! Cycling Speed and Cadence Service values.
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+
+const FLAG_WHEEL: u8 = 0x01;
+const FLAG_CRANK: u8 = 0x02;
+
+const MAX_LEN: usize = 11;
+
+/// Cumulative wheel revolutions and the time of the last wheel event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct WheelRevolutions {
+    /// Cumulative number of wheel revolutions.
+    pub revolutions: u32,
+    /// Time of the last wheel event, in units of 1/1024 seconds.
+    pub last_event_time: u16,
+}
+
+/// Cumulative crank revolutions and the time of the last crank event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CrankRevolutions {
+    /// Cumulative number of crank revolutions.
+    pub revolutions: u16,
+    /// Time of the last crank event, in units of 1/1024 seconds.
+    pub last_event_time: u16,
+}
+
+/// CSC Measurement characteristic value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CscMeasurement {
+    wheel: Option<WheelRevolutions>,
+    crank: Option<CrankRevolutions>,
+    buf: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl Default for CscMeasurement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CscMeasurement {
+    /// Create a measurement without wheel or crank data.
+    pub fn new() -> Self {
+        let mut m = Self {
+            wheel: None,
+            crank: None,
+            buf: [0; MAX_LEN],
+            len: 0,
+        };
+        m.encode();
+        m
+    }
+
+    /// Add wheel revolution data.
+    pub fn wheel(mut self, revolutions: u32, last_event_time: u16) -> Self {
+        self.wheel = Some(WheelRevolutions {
+            revolutions,
+            last_event_time,
+        });
+        self.encode();
+        self
+    }
+
+    /// Add crank revolution data.
+    pub fn crank(mut self, revolutions: u16, last_event_time: u16) -> Self {
+        self.crank = Some(CrankRevolutions {
+            revolutions,
+            last_event_time,
+        });
+        self.encode();
+        self
+    }
+
+    /// Wheel revolution data, if present.
+    pub fn wheel_revolutions(&self) -> Option<WheelRevolutions> {
+        self.wheel
+    }
+
+    /// Crank revolution data, if present.
+    pub fn crank_revolutions(&self) -> Option<CrankRevolutions> {
+        self.crank
+    }
+
+    fn encode(&mut self) {
+        let mut flags = 0;
+        let mut len = 1;
+        if let Some(wheel) = self.wheel {
+            flags |= FLAG_WHEEL;
+            self.buf[len..len + 4].copy_from_slice(&wheel.revolutions.to_le_bytes());
+            self.buf[len + 4..len + 6].copy_from_slice(&wheel.last_event_time.to_le_bytes());
+            len += 6;
+        }
+        if let Some(crank) = self.crank {
+            flags |= FLAG_CRANK;
+            self.buf[len..len + 2].copy_from_slice(&crank.revolutions.to_le_bytes());
+            self.buf[len + 2..len + 4].copy_from_slice(&crank.last_event_time.to_le_bytes());
+            len += 4;
+        }
+        self.buf[0] = flags;
+        self.len = len;
+    }
+}
+
+impl AsGatt for CscMeasurement {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = MAX_LEN;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl FromGatt for CscMeasurement {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let (&flags, data) = data.split_first().ok_or(FromGattError::InvalidLength)?;
+        let expected = if flags & FLAG_WHEEL != 0 { 6 } else { 0 } + if flags & FLAG_CRANK != 0 { 4 } else { 0 };
+        if data.len() != expected {
+            return Err(FromGattError::InvalidLength);
+        }
+        let mut m = Self::new();
+        let data = if flags & FLAG_WHEEL != 0 {
+            m = m.wheel(
+                u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                u16::from_le_bytes([data[4], data[5]]),
+            );
+            &data[6..]
+        } else {
+            data
+        };
+        if flags & FLAG_CRANK != 0 {
+            m = m.crank(
+                u16::from_le_bytes([data[0], data[1]]),
+                u16::from_le_bytes([data[2], data[3]]),
+            );
+        }
+        Ok(m)
+    }
+}
+
+/// CSC Feature characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct CscFeature(u16);
+
+impl CscFeature {
+    /// Wheel revolution data is supported.
+    pub const WHEEL_REVOLUTION_DATA: Self = Self(0x0001);
+    /// Crank revolution data is supported.
+    pub const CRANK_REVOLUTION_DATA: Self = Self(0x0002);
+    /// Multiple sensor locations are supported.
+    pub const MULTIPLE_SENSOR_LOCATIONS: Self = Self(0x0004);
+
+    /// Combine two sets of features.
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Check if all features of `other` are supported.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl AsGatt for CscFeature {
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: usize = 2;
+
+    fn as_gatt(&self) -> &[u8] {
+        self.0.as_gatt()
+    }
+}
+
+impl FromGatt for CscFeature {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        u16::from_gatt(data).map(Self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csc_measurement() {
+        let m = CscMeasurement::new().crank(3, 1024);
+        assert_eq!(m.as_gatt(), &[0x02, 3, 0, 0, 4]);
+
+        let m = CscMeasurement::new().wheel(0x01020304, 0x0506).crank(7, 8);
+        assert_eq!(m.as_gatt(), &[0x03, 4, 3, 2, 1, 6, 5, 7, 0, 8, 0]);
+        assert_eq!(CscMeasurement::from_gatt(m.as_gatt()), Ok(m));
+        assert_eq!(CscMeasurement::from_gatt(&[0x01, 0]), Err(FromGattError::InvalidLength));
+    }
+}
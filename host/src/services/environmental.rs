@@ -0,0 +1,262 @@
+//! Environmental Sensing Service values.
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+
+/// Temperature characteristic value, in units of 0.01 degrees Celsius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Temperature(pub i16);
+
+impl Temperature {
+    /// Value reported when the temperature is not known.
+    pub const UNKNOWN: Self = Self(i16::MIN);
+
+    /// Temperature from degrees Celsius, rounded to 0.01 degrees.
+    pub fn from_celsius(celsius: f32) -> Self {
+        Self(round(celsius * 100.0) as i16)
+    }
+
+    /// Temperature in degrees Celsius.
+    pub fn celsius(&self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+}
+
+/// Humidity characteristic value, in units of 0.01 percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Humidity(pub u16);
+
+impl Humidity {
+    /// Value reported when the humidity is not known.
+    pub const UNKNOWN: Self = Self(u16::MAX);
+
+    /// Humidity from percent, rounded to 0.01 percent.
+    pub fn from_percent(percent: f32) -> Self {
+        Self(round(percent * 100.0) as u16)
+    }
+
+    /// Humidity in percent.
+    pub fn percent(&self) -> f32 {
+        self.0 as f32 / 100.0
+    }
+}
+
+/// Pressure characteristic value, in units of 0.1 pascal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Pressure(pub u32);
+
+impl Pressure {
+    /// Pressure from pascal, rounded to 0.1 pascal.
+    pub fn from_pascal(pascal: f32) -> Self {
+        Self(round(pascal * 10.0) as u32)
+    }
+
+    /// Pressure in pascal.
+    pub fn pascal(&self) -> f32 {
+        self.0 as f32 / 10.0
+    }
+}
+
+macro_rules! newtype_gatt_value {
+    ($name:ident, $inner:ty) => {
+        impl AsGatt for $name {
+            const MIN_SIZE: usize = core::mem::size_of::<$inner>();
+            const MAX_SIZE: usize = core::mem::size_of::<$inner>();
+
+            fn as_gatt(&self) -> &[u8] {
+                self.0.as_gatt()
+            }
+        }
+
+        impl FromGatt for $name {
+            fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+                <$inner>::from_gatt(data).map(Self)
+            }
+        }
+    };
+}
+
+newtype_gatt_value!(Temperature, i16);
+newtype_gatt_value!(Humidity, u16);
+newtype_gatt_value!(Pressure, u32);
+
+fn round(value: f32) -> f32 {
+    if value < 0.0 {
+        value - 0.5
+    } else {
+        value + 0.5
+    }
+}
+
+/// Condition comparing the characteristic value with an operand, for an [`EsTriggerSetting`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ValueCondition {
+    /// Notify while the value is less than the operand.
+    LessThan = 0x04,
+    /// Notify while the value is less than or equal to the operand.
+    LessThanOrEqual = 0x05,
+    /// Notify while the value is greater than the operand.
+    GreaterThan = 0x06,
+    /// Notify while the value is greater than or equal to the operand.
+    GreaterThanOrEqual = 0x07,
+    /// Notify while the value is equal to the operand.
+    Equal = 0x08,
+    /// Notify while the value is not equal to the operand.
+    NotEqual = 0x09,
+}
+
+const CONDITION_INACTIVE: u8 = 0x00;
+const CONDITION_FIXED_INTERVAL: u8 = 0x01;
+const CONDITION_MINIMUM_INTERVAL: u8 = 0x02;
+const CONDITION_VALUE_CHANGED: u8 = 0x03;
+
+/// Maximum size of the operand of an [`EsTriggerSetting`].
+pub const MAX_OPERAND_LEN: usize = 4;
+
+/// Environmental Sensing Trigger Setting descriptor value.
+///
+/// The constructors are `const`, so the setting can be used as the value of a `#[descriptor]`:
+///
+/// ```rust no_run
+/// use trouble_host::prelude::*;
+/// use trouble_host::services::environmental::{EsTriggerSetting, Temperature};
+///
+/// #[gatt_service(uuid = service::ENVIRONMENTAL_SENSING)]
+/// struct EnvironmentalSensingService {
+///     #[descriptor(uuid = descriptors::ENVIRONMENTAL_SENSING_TRIGGER_SETTING, read, value = EsTriggerSetting::fixed_interval(60))]
+///     #[characteristic(uuid = characteristic::TEMPERATURE, read, notify)]
+///     temperature: Temperature,
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EsTriggerSetting {
+    buf: [u8; 1 + MAX_OPERAND_LEN],
+    len: usize,
+}
+
+impl Default for EsTriggerSetting {
+    fn default() -> Self {
+        Self::inactive()
+    }
+}
+
+impl EsTriggerSetting {
+    const fn with_operand(condition: u8, operand: &[u8]) -> Self {
+        core::assert!(operand.len() <= MAX_OPERAND_LEN, "trigger operand too large");
+        let mut buf = [0; 1 + MAX_OPERAND_LEN];
+        buf[0] = condition;
+        let mut i = 0;
+        while i < operand.len() {
+            buf[1 + i] = operand[i];
+            i += 1;
+        }
+        Self {
+            buf,
+            len: 1 + operand.len(),
+        }
+    }
+
+    const fn interval(condition: u8, seconds: u32) -> Self {
+        let b = seconds.to_le_bytes();
+        Self::with_operand(condition, &[b[0], b[1], b[2]])
+    }
+
+    /// No notifications are sent.
+    pub const fn inactive() -> Self {
+        Self::with_operand(CONDITION_INACTIVE, &[])
+    }
+
+    /// Notify at a fixed interval, in seconds up to 24 bits.
+    pub const fn fixed_interval(seconds: u32) -> Self {
+        Self::interval(CONDITION_FIXED_INTERVAL, seconds)
+    }
+
+    /// Notify on changes, no more often than the interval in seconds up to 24 bits.
+    pub const fn minimum_interval(seconds: u32) -> Self {
+        Self::interval(CONDITION_MINIMUM_INTERVAL, seconds)
+    }
+
+    /// Notify whenever the value changes.
+    pub const fn value_changed() -> Self {
+        Self::with_operand(CONDITION_VALUE_CHANGED, &[])
+    }
+
+    /// Notify when the value compared to the operand matches the condition.
+    ///
+    /// The operand is encoded like the characteristic value, and is at most [`MAX_OPERAND_LEN`] bytes.
+    pub const fn value(condition: ValueCondition, operand: &[u8]) -> Self {
+        Self::with_operand(condition as u8, operand)
+    }
+
+    /// Encoded length of the setting.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the setting is empty, which it never is.
+    pub const fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// The condition of the trigger, and its operand.
+    pub fn condition(&self) -> (u8, &[u8]) {
+        (self.buf[0], &self.buf[1..self.len])
+    }
+}
+
+impl AsGatt for EsTriggerSetting {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1 + MAX_OPERAND_LEN;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl FromGatt for EsTriggerSetting {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let (&condition, operand) = data.split_first().ok_or(FromGattError::InvalidLength)?;
+        let expected = match condition {
+            CONDITION_INACTIVE | CONDITION_VALUE_CHANGED => Some(0),
+            CONDITION_FIXED_INTERVAL | CONDITION_MINIMUM_INTERVAL => Some(3),
+            0x04..=0x09 => None,
+            _ => return Err(FromGattError::InvalidValue),
+        };
+        match expected {
+            Some(len) if operand.len() != len => Err(FromGattError::InvalidLength),
+            None if operand.is_empty() || operand.len() > MAX_OPERAND_LEN => Err(FromGattError::InvalidLength),
+            _ => Ok(Self::with_operand(condition, operand)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn es_values() {
+        assert_eq!(Temperature::from_celsius(21.456).as_gatt(), &2146i16.to_le_bytes());
+        assert_eq!(Temperature::from_celsius(-5.0), Temperature(-500));
+        assert_eq!(Humidity::from_gatt(&[0x10, 0x27]), Ok(Humidity(10000)));
+        assert_eq!(Pressure::from_pascal(101325.0).as_gatt(), &1013250u32.to_le_bytes());
+
+        const SETTING: EsTriggerSetting = EsTriggerSetting::fixed_interval(60);
+        const LEN: usize = SETTING.len();
+        assert_eq!(SETTING.as_gatt(), &[0x01, 60, 0, 0]);
+        assert_eq!(LEN, 4);
+
+        let setting = EsTriggerSetting::value(ValueCondition::GreaterThan, &Temperature(3000).0.to_le_bytes());
+        assert_eq!(setting.as_gatt(), &[0x06, 0xb8, 0x0b]);
+        assert_eq!(EsTriggerSetting::from_gatt(setting.as_gatt()), Ok(setting));
+        assert_eq!(
+            EsTriggerSetting::from_gatt(&[0x01, 1]),
+            Err(FromGattError::InvalidLength)
+        );
+        assert_eq!(EsTriggerSetting::from_gatt(&[0x0a]), Err(FromGattError::InvalidValue));
+    }
+}
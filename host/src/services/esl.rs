@@ -0,0 +1,636 @@
+//! Electronic Shelf Label service.
+//!
+//! Implements the parts of the ESL profile that do not depend on the transport: the ESL service used by an
+//! access point to configure a tag over a connection, and the encoding of ESL commands and responses.
+//!
+//! In the profile, commands are also sent to synchronized tags in periodic advertising with responses (PAwR)
+//! subevents. PAwR is not supported by the host yet, so tags can only be controlled through the ESL Control
+//! Point while connected. The command and response encoding is the same for both transports.
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+use static_cell::StaticCell;
+
+use crate::attribute::{
+    AttributePermissions, AttributeSecurity, AttributeTable, Characteristic, CharacteristicProp, GattService, Service,
+};
+use crate::gatt::GattConnection;
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{Error, PacketPool};
+
+/// ESL ID addressing all tags of a group.
+pub const BROADCAST_ESL_ID: u8 = 0xff;
+
+/// Maximum size of a command or response, the opcode and up to 16 bytes of parameters.
+pub const MAX_MESSAGE_LEN: usize = 17;
+
+/// Maximum size of the value written to the ESL Control Point.
+pub const MAX_CONTROL_POINT_LEN: usize = 48;
+
+/// Address of a tag, assigned by the access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct EslAddress([u8; 2]);
+
+impl EslAddress {
+    /// Create an address from the group and ESL IDs.
+    ///
+    /// The group ID is 7 bits, and the ESL ID must not be the broadcast ID.
+    pub fn new(group_id: u8, esl_id: u8) -> Result<Self, Error> {
+        if group_id > 0x7f || esl_id == BROADCAST_ESL_ID {
+            return Err(Error::InvalidValue);
+        }
+        Ok(Self([esl_id, group_id]))
+    }
+
+    /// The group of the tag.
+    pub fn group_id(&self) -> u8 {
+        self.0[1] & 0x7f
+    }
+
+    /// The ID of the tag within its group.
+    pub fn esl_id(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Whether a command for the ESL ID is addressed to this tag.
+    pub fn matches(&self, esl_id: u8) -> bool {
+        esl_id == self.esl_id() || esl_id == BROADCAST_ESL_ID
+    }
+}
+
+impl AsGatt for EslAddress {
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: usize = 2;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for EslAddress {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [esl_id, group_id] => Self::new(*group_id, *esl_id).map_err(|_| FromGattError::InvalidValue),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+}
+
+/// Session key and IV, for the AP Sync and ESL Response key materials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct KeyMaterial([u8; 24]);
+
+impl KeyMaterial {
+    /// Create key material from a session key and IV.
+    pub fn new(session_key: [u8; 16], iv: [u8; 8]) -> Self {
+        let mut data = [0; 24];
+        data[..16].copy_from_slice(&session_key);
+        data[16..].copy_from_slice(&iv);
+        Self(data)
+    }
+
+    /// The session key.
+    pub fn session_key(&self) -> &[u8] {
+        &self.0[..16]
+    }
+
+    /// The initialization vector.
+    pub fn iv(&self) -> &[u8] {
+        &self.0[16..]
+    }
+}
+
+impl AsGatt for KeyMaterial {
+    const MIN_SIZE: usize = 24;
+    const MAX_SIZE: usize = 24;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for KeyMaterial {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        data.try_into().map(Self).map_err(|_| FromGattError::InvalidLength)
+    }
+}
+
+/// Error codes reported in an error response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum EslError {
+    /// Unspecified error.
+    Unspecified = 0x01,
+    /// The opcode is not supported.
+    InvalidOpcode = 0x02,
+    /// The command is not allowed in the current state.
+    InvalidState = 0x03,
+    /// The image index is out of range.
+    InvalidImageIndex = 0x04,
+    /// The image is not available.
+    ImageNotAvailable = 0x05,
+    /// The parameters are invalid.
+    InvalidParameters = 0x06,
+    /// The command exceeds a capacity limit.
+    CapacityLimit = 0x07,
+    /// The battery level is too low.
+    InsufficientBattery = 0x08,
+    /// Not enough resources to execute the command.
+    InsufficientResources = 0x09,
+    /// The command should be retried.
+    Retry = 0x0a,
+    /// The queue of timed commands is full.
+    QueueFull = 0x0b,
+    /// The absolute time is too far in the future.
+    ImplausibleAbsoluteTime = 0x0c,
+}
+
+/// Parameters of an LED control command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct LedControl {
+    /// Index of the LED.
+    pub led: u8,
+    /// Color and brightness.
+    pub color: u8,
+    /// Flashing pattern, 40 bits sent least significant bit first.
+    pub pattern: [u8; 5],
+    /// Duration of an off bit of the pattern, in units of 2 milliseconds.
+    pub off_period: u8,
+    /// Duration of an on bit of the pattern, in units of 2 milliseconds.
+    pub on_period: u8,
+    /// Repeat type in the least significant bit and the repeat count or duration.
+    pub repeat: u16,
+}
+
+impl LedControl {
+    fn decode(p: &[u8]) -> Self {
+        Self {
+            led: p[0],
+            color: p[1],
+            pattern: [p[2], p[3], p[4], p[5], p[6]],
+            off_period: p[7],
+            on_period: p[8],
+            repeat: u16::from_le_bytes([p[9], p[10]]),
+        }
+    }
+}
+
+/// A command sent by the access point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EslCommand<'a> {
+    /// Request the basic state.
+    Ping,
+    /// Forget the ESL address and key material.
+    Unassociate,
+    /// Clear the service needed flag.
+    ServiceReset,
+    /// Return to the unassociated state and clear all configuration.
+    FactoryReset,
+    /// The access point finished configuring the tag.
+    UpdateComplete,
+    /// Read a sensor.
+    ReadSensorData {
+        /// Index of the sensor.
+        sensor: u8,
+    },
+    /// Redraw the current image of a display.
+    RefreshDisplay {
+        /// Index of the display.
+        display: u8,
+    },
+    /// Show an image on a display.
+    DisplayImage {
+        /// Index of the display.
+        display: u8,
+        /// Index of the image.
+        image: u8,
+    },
+    /// Show an image on a display at an absolute time.
+    DisplayTimedImage {
+        /// Index of the display.
+        display: u8,
+        /// Index of the image.
+        image: u8,
+        /// Absolute time in milliseconds.
+        absolute_time: u32,
+    },
+    /// Control an LED.
+    LedControl(LedControl),
+    /// Control an LED at an absolute time.
+    LedTimedControl {
+        /// LED control parameters.
+        control: LedControl,
+        /// Absolute time in milliseconds.
+        absolute_time: u32,
+    },
+    /// Vendor specific command.
+    Vendor {
+        /// Opcode of the command.
+        opcode: u8,
+        /// Parameters following the ESL ID.
+        params: &'a [u8],
+    },
+}
+
+impl<'a> EslCommand<'a> {
+    fn decode(opcode: u8, p: &'a [u8]) -> Result<Self, EslError> {
+        let time = |i: usize| u32::from_le_bytes([p[i], p[i + 1], p[i + 2], p[i + 3]]);
+        Ok(match opcode {
+            0x00 => Self::Ping,
+            0x01 => Self::Unassociate,
+            0x02 => Self::ServiceReset,
+            0x03 => Self::FactoryReset,
+            0x04 => Self::UpdateComplete,
+            0x10 => Self::ReadSensorData { sensor: p[0] },
+            0x11 => Self::RefreshDisplay { display: p[0] },
+            0x20 => Self::DisplayImage {
+                display: p[0],
+                image: p[1],
+            },
+            0x60 => Self::DisplayTimedImage {
+                display: p[0],
+                image: p[1],
+                absolute_time: time(2),
+            },
+            0xb0 => Self::LedControl(LedControl::decode(p)),
+            0xf0 => Self::LedTimedControl {
+                control: LedControl::decode(p),
+                absolute_time: time(11),
+            },
+            opcode if opcode & 0x0f == 0x0f => Self::Vendor { opcode, params: p },
+            _ => return Err(EslError::InvalidOpcode),
+        })
+    }
+}
+
+/// Iterator over the commands in a payload, yielding the ESL ID each command is addressed to.
+///
+/// Iteration stops at the end of the payload, or at a truncated command.
+pub struct EslCommands<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> EslCommands<'a> {
+    /// Iterate over the commands in a payload.
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl<'a> Iterator for EslCommands<'a> {
+    type Item = (u8, Result<EslCommand<'a>, EslError>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&opcode, rest) = self.data.split_first()?;
+        // The length of the parameters, including the ESL ID, is encoded in the opcode.
+        let len = (opcode >> 4) as usize + 1;
+        if rest.len() < len {
+            self.data = &[];
+            return None;
+        }
+        let (params, rest) = rest.split_at(len);
+        self.data = rest;
+        Some((params[0], EslCommand::decode(opcode, &params[1..])))
+    }
+}
+
+/// Basic state of a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct BasicState(pub u16);
+
+impl BasicState {
+    /// The tag needs service, e.g. its battery is low.
+    pub const SERVICE_NEEDED: u16 = 0x0001;
+    /// The tag is synchronized to the access point.
+    pub const SYNCHRONIZED: u16 = 0x0002;
+    /// An LED is active.
+    pub const ACTIVE_LED: u16 = 0x0004;
+    /// A timed LED command is pending.
+    pub const PENDING_LED_UPDATE: u16 = 0x0008;
+    /// A timed display command is pending.
+    pub const PENDING_DISPLAY_UPDATE: u16 = 0x0010;
+}
+
+/// A response sent by a tag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum EslResponse<'a> {
+    /// The command failed.
+    Error(EslError),
+    /// An LED command was executed.
+    LedState {
+        /// Index of the LED.
+        led: u8,
+    },
+    /// The basic state of the tag.
+    BasicState(BasicState),
+    /// A display command was executed.
+    DisplayState {
+        /// Index of the display.
+        display: u8,
+        /// Index of the image.
+        image: u8,
+    },
+    /// A sensor value, of 1 to 15 bytes.
+    SensorValue {
+        /// Index of the sensor.
+        sensor: u8,
+        /// Sensor data.
+        data: &'a [u8],
+    },
+    /// Vendor specific response, of 1 to 16 bytes.
+    Vendor {
+        /// Vendor data.
+        data: &'a [u8],
+    },
+}
+
+impl EslResponse<'_> {
+    /// Encode the response.
+    pub fn encode(&self) -> Result<Vec<u8, MAX_MESSAGE_LEN>, Error> {
+        let mut out = Vec::new();
+        let mut push = |tag: u8, params: &[&[u8]]| -> Result<(), Error> {
+            let len: usize = params.iter().map(|p| p.len()).sum();
+            if !(1..=16).contains(&len) {
+                return Err(Error::InvalidValue);
+            }
+            out.push(((len as u8 - 1) << 4) | tag)
+                .map_err(|_| Error::InsufficientSpace)?;
+            for p in params {
+                out.extend_from_slice(p).map_err(|_| Error::InsufficientSpace)?;
+            }
+            Ok(())
+        };
+        match self {
+            Self::Error(code) => push(0x00, &[&[*code as u8]])?,
+            Self::LedState { led } => push(0x01, &[&[*led]])?,
+            Self::BasicState(state) => push(0x00, &[&state.0.to_le_bytes()])?,
+            Self::DisplayState { display, image } => push(0x01, &[&[*display, *image]])?,
+            Self::SensorValue { sensor, data } => push(0x0e, &[&[*sensor], data])?,
+            Self::Vendor { data } => push(0x0f, &[data])?,
+        }
+        Ok(out)
+    }
+}
+
+/// Electronic Shelf Label service.
+///
+/// All characteristics are only written over an encrypted link, and the key material is only read over one.
+/// Can be added to a `#[gatt_server]` like a `#[gatt_service]`. The optional Display, Image, Sensor
+/// and LED Information characteristics can be added as a separate service by the application.
+pub struct EslService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// ESL address, written by the access point.
+    pub address: Characteristic<EslAddress>,
+    /// Key material used to decrypt PAwR commands, written by the access point.
+    pub ap_sync_key_material: Characteristic<KeyMaterial>,
+    /// Key material used to encrypt PAwR responses, written by the access point.
+    pub response_key_material: Characteristic<KeyMaterial>,
+    /// Current absolute time in milliseconds, written by the access point.
+    pub current_absolute_time: Characteristic<u32>,
+    /// Control point receiving commands, responses are notified.
+    pub control_point: Characteristic<Vec<u8, MAX_CONTROL_POINT_LEN>>,
+}
+
+impl EslService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 12;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 1;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static ADDRESS_STORE: StaticCell<[u8; 2]> = StaticCell::new();
+        static AP_SYNC_KEY_STORE: StaticCell<[u8; 24]> = StaticCell::new();
+        static RESPONSE_KEY_STORE: StaticCell<[u8; 24]> = StaticCell::new();
+        static TIME_STORE: StaticCell<[u8; 4]> = StaticCell::new();
+        static CONTROL_POINT_STORE: StaticCell<[u8; MAX_CONTROL_POINT_LEN]> = StaticCell::new();
+
+        use bt_hci::uuid::{characteristic, service};
+        let write = [CharacteristicProp::Write];
+        let mut builder = table.add_service(Service::new(service::ELECTRONIC_SHELF_LABEL));
+        let address = builder
+            .add_characteristic(
+                characteristic::ESL_ADDRESS,
+                &write,
+                EslAddress::default(),
+                ADDRESS_STORE.init([0; 2]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::None,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        let ap_sync_key_material = builder
+            .add_characteristic(
+                characteristic::AP_SYNC_KEY_MATERIAL,
+                &write,
+                KeyMaterial::default(),
+                AP_SYNC_KEY_STORE.init([0; 24]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::Encrypted,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        let response_key_material = builder
+            .add_characteristic(
+                characteristic::ESL_RESPONSE_KEY_MATERIAL,
+                &write,
+                KeyMaterial::default(),
+                RESPONSE_KEY_STORE.init([0; 24]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::Encrypted,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        let current_absolute_time = builder
+            .add_characteristic(
+                characteristic::ESL_CURRENT_ABSOLUTE_TIME,
+                &write,
+                0u32,
+                TIME_STORE.init([0; 4]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::None,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        let control_point = builder
+            .add_characteristic(
+                characteristic::ESL_CONTROL_POINT,
+                &[
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                Vec::<u8, MAX_CONTROL_POINT_LEN>::new(),
+                CONTROL_POINT_STORE.init([0; MAX_CONTROL_POINT_LEN]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::None,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        Self {
+            handle: builder.build(),
+            address,
+            ap_sync_key_material,
+            response_key_material,
+            current_absolute_time,
+            control_point,
+        }
+    }
+
+    /// Notify a response to a command received on the control point.
+    pub async fn respond<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        response: &EslResponse<'_>,
+    ) -> Result<(), Error> {
+        let data = response.encode()?;
+        let value = Vec::from_slice(&data).map_err(|_| Error::InsufficientSpace)?;
+        self.control_point.notify(connection, &value).await
+    }
+}
+
+impl GattService for EslService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bt_hci::param::ConnHandle;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::attribute_server::{AttributeServer, PeerInfo};
+    use crate::prelude::DefaultPacketPool;
+    use crate::{att, Identity};
+
+    #[test]
+    fn esl_address() {
+        let address = EslAddress::new(3, 7).unwrap();
+        assert_eq!(address.as_gatt(), &[7, 3]);
+        assert!(address.matches(7) && address.matches(BROADCAST_ESL_ID) && !address.matches(8));
+        assert_eq!(EslAddress::from_gatt(&[0xff, 0]), Err(FromGattError::InvalidValue));
+    }
+
+    #[test]
+    fn esl_commands() {
+        let payload = [
+            0x00, 0x05, // Ping ESL 5
+            0x20, 0x05, 0x00, 0x02, // Display image 2 on display 0
+            0x10, 0xff, 0x01, // Read sensor 1, broadcast
+            0x12, 0x05, 0x00, // Unknown opcode with 2 bytes of parameters
+            0xb0, 0x05, 0x01, 0x03, 1, 2, 3, 4, 5, 10, 20, 0x02, 0x00, // LED control
+            0x20, 0x05, // Truncated
+        ];
+        let mut commands = EslCommands::new(&payload);
+        assert_eq!(commands.next(), Some((5, Ok(EslCommand::Ping))));
+        assert_eq!(
+            commands.next(),
+            Some((5, Ok(EslCommand::DisplayImage { display: 0, image: 2 })))
+        );
+        assert_eq!(
+            commands.next(),
+            Some((0xff, Ok(EslCommand::ReadSensorData { sensor: 1 })))
+        );
+        assert_eq!(commands.next(), Some((5, Err(EslError::InvalidOpcode))));
+        assert_eq!(
+            commands.next(),
+            Some((
+                5,
+                Ok(EslCommand::LedControl(LedControl {
+                    led: 1,
+                    color: 3,
+                    pattern: [1, 2, 3, 4, 5],
+                    off_period: 10,
+                    on_period: 20,
+                    repeat: 2,
+                }))
+            ))
+        );
+        assert_eq!(commands.next(), None);
+    }
+
+    #[test]
+    fn esl_responses() {
+        let response = EslResponse::BasicState(BasicState(BasicState::SYNCHRONIZED));
+        assert_eq!(&response.encode().unwrap()[..], &[0x10, 0x02, 0x00]);
+        let response = EslResponse::Error(EslError::InvalidOpcode);
+        assert_eq!(&response.encode().unwrap()[..], &[0x00, 0x02]);
+        let response = EslResponse::SensorValue {
+            sensor: 1,
+            data: &[0xaa, 0xbb],
+        };
+        assert_eq!(&response.encode().unwrap()[..], &[0x2e, 0x01, 0xaa, 0xbb]);
+        let response = EslResponse::Vendor { data: &[] };
+        assert!(response.encode().is_err());
+    }
+
+    #[test]
+    fn esl_writes_require_encryption() {
+        let mut table: AttributeTable<'_, NoopRawMutex, { EslService::ATTRIBUTE_COUNT }> = AttributeTable::new();
+        let esl = EslService::new(&mut table);
+        let server: AttributeServer<'_, NoopRawMutex, DefaultPacketPool, { EslService::ATTRIBUTE_COUNT }, 1, 1> =
+            AttributeServer::new(table);
+        let mut peer = PeerInfo {
+            handle: ConnHandle::new(1),
+            identity: Identity::default(),
+            att_mtu: 23,
+            rx_mtu: 23,
+            encrypted: false,
+        };
+        server.connect(&peer).unwrap();
+        let mut buf = [0; 64];
+
+        let [lo, hi] = esl.ap_sync_key_material.handle.to_le_bytes();
+        for handle in [
+            esl.address.handle,
+            esl.ap_sync_key_material.handle,
+            esl.response_key_material.handle,
+            esl.current_absolute_time.handle,
+            esl.control_point.handle,
+        ] {
+            let [lo, hi] = handle.to_le_bytes();
+            let len = server.process_pdu(&peer, &[att::ATT_WRITE_REQ, lo, hi, 1, 0], &mut buf);
+            assert_eq!(
+                &buf[..len.unwrap().unwrap()],
+                &[att::ATT_ERROR_RSP, att::ATT_WRITE_REQ, lo, hi, 0x0f]
+            );
+        }
+        let len = server.process_pdu(&peer, &[att::ATT_READ_REQ, lo, hi], &mut buf);
+        assert_eq!(
+            &buf[..len.unwrap().unwrap()],
+            &[att::ATT_ERROR_RSP, att::ATT_READ_REQ, lo, hi, 0x0f]
+        );
+
+        // Key material is 24 bytes
+        peer.encrypted = true;
+        let mut write = [1; 27];
+        write[..3].copy_from_slice(&[att::ATT_WRITE_REQ, lo, hi]);
+        let len = server.process_pdu(&peer, &write, &mut buf);
+        assert_eq!(&buf[..len.unwrap().unwrap()], &[att::ATT_WRITE_RSP]);
+    }
+}
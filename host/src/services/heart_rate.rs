@@ -0,0 +1,281 @@
+//! Heart Rate Service values.
+use heapless::Vec;
+
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+
+const FLAG_VALUE_U16: u8 = 0x01;
+const FLAG_CONTACT_DETECTED: u8 = 0x02;
+const FLAG_CONTACT_SUPPORTED: u8 = 0x04;
+const FLAG_ENERGY_EXPENDED: u8 = 0x08;
+const FLAG_RR_INTERVALS: u8 = 0x10;
+
+/// Size of a measurement filling a notification at the default ATT MTU.
+const MAX_LEN: usize = 20;
+
+/// Maximum number of RR intervals in a measurement.
+pub const MAX_RR_INTERVALS: usize = 9;
+
+/// Heart Rate Measurement characteristic value.
+///
+/// The value is encoded when it is built, RR intervals that do not fit in a notification
+/// at the default ATT MTU are dropped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartRateMeasurement {
+    bpm: u16,
+    sensor_contact: Option<bool>,
+    energy_expended: Option<u16>,
+    rr_intervals: Vec<u16, MAX_RR_INTERVALS>,
+    buf: [u8; MAX_LEN],
+    len: usize,
+}
+
+impl Default for HeartRateMeasurement {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl HeartRateMeasurement {
+    /// Create a measurement of the heart rate in beats per minute.
+    pub fn new(bpm: u16) -> Self {
+        let mut m = Self {
+            bpm,
+            sensor_contact: None,
+            energy_expended: None,
+            rr_intervals: Vec::new(),
+            buf: [0; MAX_LEN],
+            len: 0,
+        };
+        m.encode();
+        m
+    }
+
+    /// Report whether skin contact is detected, for sensors supporting contact detection.
+    pub fn sensor_contact(mut self, detected: bool) -> Self {
+        self.sensor_contact = Some(detected);
+        self.encode();
+        self
+    }
+
+    /// Report the accumulated energy expended in kilojoules.
+    pub fn energy_expended(mut self, kilojoules: u16) -> Self {
+        self.energy_expended = Some(kilojoules);
+        self.encode();
+        self
+    }
+
+    /// Add an RR interval in units of 1/1024 seconds.
+    pub fn rr_interval(mut self, interval: u16) -> Self {
+        if self.rr_intervals.push(interval).is_ok() {
+            self.encode();
+        }
+        self
+    }
+
+    /// Heart rate in beats per minute.
+    pub fn bpm(&self) -> u16 {
+        self.bpm
+    }
+
+    /// Whether skin contact is detected, if the sensor supports contact detection.
+    pub fn contact(&self) -> Option<bool> {
+        self.sensor_contact
+    }
+
+    /// Accumulated energy expended in kilojoules, if present.
+    pub fn energy(&self) -> Option<u16> {
+        self.energy_expended
+    }
+
+    /// RR intervals in units of 1/1024 seconds, oldest first.
+    pub fn rr_intervals(&self) -> &[u16] {
+        &self.rr_intervals
+    }
+
+    fn encode(&mut self) {
+        let mut flags = 0;
+        let mut len = 1;
+        if self.bpm > u8::MAX as u16 {
+            flags |= FLAG_VALUE_U16;
+            self.buf[len..len + 2].copy_from_slice(&self.bpm.to_le_bytes());
+            len += 2;
+        } else {
+            self.buf[len] = self.bpm as u8;
+            len += 1;
+        }
+        if let Some(detected) = self.sensor_contact {
+            flags |= FLAG_CONTACT_SUPPORTED;
+            if detected {
+                flags |= FLAG_CONTACT_DETECTED;
+            }
+        }
+        if let Some(energy) = self.energy_expended {
+            flags |= FLAG_ENERGY_EXPENDED;
+            self.buf[len..len + 2].copy_from_slice(&energy.to_le_bytes());
+            len += 2;
+        }
+        // Drop the intervals that no longer fit, i.e. after energy expended was added
+        self.rr_intervals.truncate((MAX_LEN - len) / 2);
+        if !self.rr_intervals.is_empty() {
+            flags |= FLAG_RR_INTERVALS;
+            for interval in self.rr_intervals.iter() {
+                self.buf[len..len + 2].copy_from_slice(&interval.to_le_bytes());
+                len += 2;
+            }
+        }
+        self.buf[0] = flags;
+        self.len = len;
+    }
+}
+
+impl AsGatt for HeartRateMeasurement {
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: usize = MAX_LEN;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl FromGatt for HeartRateMeasurement {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let (&flags, mut data) = data.split_first().ok_or(FromGattError::InvalidLength)?;
+        let mut take = |n: usize| -> Result<&[u8], FromGattError> {
+            if data.len() < n {
+                return Err(FromGattError::InvalidLength);
+            }
+            let (value, rest) = data.split_at(n);
+            data = rest;
+            Ok(value)
+        };
+        let bpm = if flags & FLAG_VALUE_U16 != 0 {
+            let v = take(2)?;
+            u16::from_le_bytes([v[0], v[1]])
+        } else {
+            take(1)?[0] as u16
+        };
+        let mut m = Self::new(bpm);
+        if flags & FLAG_CONTACT_SUPPORTED != 0 {
+            m.sensor_contact = Some(flags & FLAG_CONTACT_DETECTED != 0);
+        }
+        if flags & FLAG_ENERGY_EXPENDED != 0 {
+            let v = take(2)?;
+            m.energy_expended = Some(u16::from_le_bytes([v[0], v[1]]));
+        }
+        if flags & FLAG_RR_INTERVALS != 0 {
+            let rest = data;
+            if rest.len() % 2 != 0 || rest.len() / 2 > MAX_RR_INTERVALS {
+                return Err(FromGattError::InvalidLength);
+            }
+            for v in rest.chunks_exact(2) {
+                let _ = m.rr_intervals.push(u16::from_le_bytes([v[0], v[1]]));
+            }
+        }
+        m.encode();
+        Ok(m)
+    }
+}
+
+/// Body Sensor Location characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum BodySensorLocation {
+    /// Other location.
+    #[default]
+    Other = 0,
+    /// Chest.
+    Chest = 1,
+    /// Wrist.
+    Wrist = 2,
+    /// Finger.
+    Finger = 3,
+    /// Hand.
+    Hand = 4,
+    /// Ear lobe.
+    EarLobe = 5,
+    /// Foot.
+    Foot = 6,
+}
+
+impl TryFrom<u8> for BodySensorLocation {
+    type Error = FromGattError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Other,
+            1 => Self::Chest,
+            2 => Self::Wrist,
+            3 => Self::Finger,
+            4 => Self::Hand,
+            5 => Self::EarLobe,
+            6 => Self::Foot,
+            _ => return Err(FromGattError::InvalidValue),
+        })
+    }
+}
+
+impl AsGatt for BodySensorLocation {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1;
+
+    fn as_gatt(&self) -> &[u8] {
+        match self {
+            Self::Other => &[0],
+            Self::Chest => &[1],
+            Self::Wrist => &[2],
+            Self::Finger => &[3],
+            Self::Hand => &[4],
+            Self::EarLobe => &[5],
+            Self::Foot => &[6],
+        }
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for BodySensorLocation {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [value] => Self::try_from(*value),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+}
+
+/// Value written to the Heart Rate Control Point to reset the energy expended.
+pub const RESET_ENERGY_EXPENDED: u8 = 0x01;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heart_rate_measurement() {
+        let m = HeartRateMeasurement::new(72).sensor_contact(true);
+        assert_eq!(m.as_gatt(), &[0x06, 72]);
+
+        let m = HeartRateMeasurement::new(300)
+            .energy_expended(0x0102)
+            .rr_interval(1024)
+            .rr_interval(980);
+        assert_eq!(m.as_gatt(), &[0x19, 0x2c, 0x01, 0x02, 0x01, 0x00, 0x04, 0xd4, 0x03]);
+        assert_eq!(HeartRateMeasurement::from_gatt(m.as_gatt()), Ok(m));
+
+        let mut m = HeartRateMeasurement::new(60);
+        for _ in 0..20 {
+            m = m.rr_interval(1000);
+        }
+        assert_eq!(m.rr_intervals().len(), MAX_RR_INTERVALS);
+        let m = m.energy_expended(10);
+        assert_eq!(m.rr_intervals().len(), 8);
+        assert_eq!(m.as_gatt().len(), 20);
+
+        assert_eq!(
+            HeartRateMeasurement::from_gatt(&[0x01, 72]),
+            Err(FromGattError::InvalidLength)
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Internet Protocol Support Profile.
+//!
+//! IPv6 packets, compressed with 6LoWPAN as defined in RFC 7668, are exchanged over an L2CAP channel on the
+//! IPSP PSM. The node, usually the peripheral, exposes the Internet Protocol Support service so that routers
+//! can discover it, and accepts the channel. The router connects the channel. Each SDU carries one
+//! compressed packet, and the channel MTU is at least the IPv6 minimum link MTU of 1280 bytes, so packets
+//! are never fragmented at the 6LoWPAN layer.
+//!
+//! [`IpspChannel`] sends and receives whole frames, which fits the device interface of network stacks such
+//! as `smoltcp` or embassy-net. The 6LoWPAN compression itself is left to the network stack.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::ipsp::{InternetProtocolSupportService, IpspChannel, IPSP_MTU};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     ipss: InternetProtocolSupportService,
+//! }
+//!
+//! async fn run<C: Controller, P: PacketPool>(
+//!     stack: &'static Stack<'static, C, P>,
+//!     conn: &Connection<'static, P>,
+//! ) -> Result<(), BleHostError<C::Error>> {
+//!     let mut channel = IpspChannel::accept(stack, conn).await?;
+//!     let mut frame = [0; IPSP_MTU as usize];
+//!     loop {
+//!         let len = channel.receive(stack, &mut frame).await?;
+//!         // Pass &frame[..len] to the network stack, and send its frames with channel.send(stack, frame)
+//!     }
+//! }
+//! ```
+use bt_hci::controller::Controller;
+use bt_hci::uuid::service;
+use embassy_sync::blocking_mutex::raw::RawMutex;
+
+use crate::attribute::{AttributeTable, GattService, Service};
+use crate::connection::Connection;
+use crate::l2cap::{L2capChannel, L2capChannelConfig};
+use crate::pdu::Sdu;
+use crate::{BleHostError, Error, PacketPool, Stack};
+
+/// PSM of the IPSP L2CAP channel.
+pub const IPSP_PSM: u16 = 0x0023;
+
+/// Minimum MTU of the IPSP L2CAP channel, the IPv6 minimum link MTU.
+pub const IPSP_MTU: u16 = 1280;
+
+/// Internet Protocol Support service.
+///
+/// The service has no characteristics, its presence tells routers that the node supports IPSP.
+pub struct InternetProtocolSupportService {
+    /// Handle of the service.
+    pub handle: u16,
+}
+
+impl InternetProtocolSupportService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 1;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 0;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        let service = table.add_service(Service::new(service::INTERNET_PROTOCOL_SUPPORT));
+        Self {
+            handle: service.build(),
+        }
+    }
+}
+
+impl GattService for InternetProtocolSupportService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+/// An IPSP L2CAP channel, carrying one 6LoWPAN compressed IPv6 packet per frame.
+pub struct IpspChannel<'d, P: PacketPool> {
+    channel: L2capChannel<'d, P>,
+}
+
+impl<'d, P: PacketPool> IpspChannel<'d, P> {
+    /// Accept an IPSP channel from a router, as the node.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the packets of the pool cannot hold an [`IPSP_MTU`] SDU.
+    pub async fn accept<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+    ) -> Result<Self, BleHostError<T::Error>> {
+        let channel = L2capChannel::accept(stack, connection, &[IPSP_PSM], &config::<P>()?).await?;
+        Self::new(channel)
+    }
+
+    /// Connect an IPSP channel to a node, as the router.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the packets of the pool cannot hold an [`IPSP_MTU`] SDU.
+    pub async fn connect<T: Controller>(
+        stack: &'d Stack<'d, T, P>,
+        connection: &Connection<'_, P>,
+    ) -> Result<Self, BleHostError<T::Error>> {
+        let channel = L2capChannel::create(stack, connection, IPSP_PSM, &config::<P>()?).await?;
+        Self::new(channel)
+    }
+
+    fn new<E>(mut channel: L2capChannel<'d, P>) -> Result<Self, BleHostError<E>> {
+        // The peer must support the IPv6 minimum link MTU as well
+        if channel.mtu() < IPSP_MTU {
+            channel.disconnect();
+            return Err(Error::InvalidValue.into());
+        }
+        Ok(Self { channel })
+    }
+
+    /// The largest frame that can be sent or received on the channel.
+    pub fn mtu(&self) -> u16 {
+        self.channel.mtu()
+    }
+
+    /// Send a frame.
+    ///
+    /// Returns [`Error::InsufficientSpace`] if the frame is larger than [`Self::mtu`].
+    pub async fn send<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        frame: &[u8],
+    ) -> Result<(), BleHostError<T::Error>> {
+        if frame.len() > usize::from(self.mtu()) {
+            return Err(Error::InsufficientSpace.into());
+        }
+        self.channel.send(stack, frame).await
+    }
+
+    /// Receive a frame into the buffer, returning its length.
+    ///
+    /// The buffer must be at least [`Self::mtu`] bytes long.
+    pub async fn receive<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+        buf: &mut [u8],
+    ) -> Result<usize, BleHostError<T::Error>> {
+        self.channel.receive(stack, buf).await
+    }
+
+    /// Receive a frame without copying it.
+    pub async fn receive_frame<T: Controller>(
+        &mut self,
+        stack: &Stack<'_, T, P>,
+    ) -> Result<Sdu<P::Packet>, BleHostError<T::Error>> {
+        self.channel.receive_sdu(stack).await
+    }
+
+    /// Disconnect the channel.
+    pub fn disconnect(&mut self) {
+        self.channel.disconnect();
+    }
+
+    /// Retrieve the underlying L2CAP channel.
+    pub fn into_inner(self) -> L2capChannel<'d, P> {
+        self.channel
+    }
+}
+
+/// Channel configuration for an [`IPSP_MTU`] SDU, if the packets of the pool can hold one.
+fn config<P: PacketPool>() -> Result<L2capChannelConfig, Error> {
+    // The SDU is reassembled in a single packet, after its 4 byte L2CAP header and 2 byte SDU length
+    if P::MTU < usize::from(IPSP_MTU) + 6 {
+        return Err(Error::InsufficientSpace);
+    }
+    Ok(L2capChannelConfig {
+        mtu: Some(IPSP_MTU),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::DefaultPacketPool;
+
+    #[test]
+    fn ipsp_config() {
+        let config = config::<DefaultPacketPool>();
+        if DefaultPacketPool::MTU >= usize::from(IPSP_MTU) + 6 {
+            assert_eq!(unwrap!(config).mtu, Some(IPSP_MTU));
+        } else {
+            assert_eq!(config.err(), Some(Error::InsufficientSpace));
+        }
+    }
+}
@@ -0,0 +1,618 @@
+//! Generic Media Control service.
+//!
+//! An audio accessory, such as headphones, controls the media player of a phone through its Media Control service.
+//! This module provides the server side, for devices that play media themselves, such as a speaker streaming from
+//! its own source. Operations written to the Media Control Point are decoded with
+//! [`MediaControlService::operation`], and their result notified back with [`MediaControlService::respond`].
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::media_control::{MediaControlOperation, MediaControlService, MediaState};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     media: MediaControlService,
+//! }
+//!
+//! async fn run(server: &Server<'_>, conn: &GattConnection<'_, '_, DefaultPacketPool>) -> Result<(), Error> {
+//!     loop {
+//!         if let GattConnectionEvent::Gatt { event } = conn.next().await {
+//!             let operation = server.media.operation(server, &event);
+//!             event.accept()?.send().await;
+//!             let response = match operation {
+//!                 Some(Ok(operation @ MediaControlOperation::Play)) => {
+//!                     server.media.media_state.set(server, &MediaState::Playing)?;
+//!                     server.media.media_state.notify(conn, &MediaState::Playing).await?;
+//!                     operation.success()
+//!                 }
+//!                 Some(Ok(operation @ MediaControlOperation::FirstTrack)) => {
+//!                     // Selecting a track activates an inactive player
+//!                     if server.media.media_state.get(server)? == MediaState::Inactive {
+//!                         server.media.media_state.set(server, &MediaState::Paused)?;
+//!                         server.media.media_state.notify(conn, &MediaState::Paused).await?;
+//!                     }
+//!                     operation.success()
+//!                 }
+//!                 Some(Ok(operation)) => operation.success(),
+//!                 Some(Err(response)) => response,
+//!                 None => continue,
+//!             };
+//!             server.media.respond(conn, &response).await?;
+//!         }
+//!     }
+//! }
+//! ```
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::{String, Vec};
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttCmd, AttReq};
+use crate::attribute::{
+    AttributePermissions, AttributeSecurity, AttributeTable, Characteristic, CharacteristicProp, GattService, Service,
+};
+use crate::attribute_server::AttributeServer;
+use crate::gatt::{GattConnection, GattEvent};
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{Error, PacketPool};
+
+/// Track duration or position when it is unknown.
+pub const TRACK_POSITION_UNAVAILABLE: i32 = -1;
+
+/// LE Audio requires an encrypted link to access the characteristics of the service.
+const ENCRYPTED: AttributePermissions = AttributePermissions {
+    read: AttributeSecurity::Encrypted,
+    write: AttributeSecurity::Encrypted,
+};
+
+/// State of the media player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MediaState {
+    /// No track is selected.
+    #[default]
+    Inactive = 0,
+    /// The current track is playing.
+    Playing = 1,
+    /// The current track is paused.
+    Paused = 2,
+    /// The current track is fast rewinding or fast forwarding.
+    Seeking = 3,
+}
+
+impl TryFrom<u8> for MediaState {
+    type Error = FromGattError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0 => Self::Inactive,
+            1 => Self::Playing,
+            2 => Self::Paused,
+            3 => Self::Seeking,
+            _ => return Err(FromGattError::InvalidValue),
+        })
+    }
+}
+
+impl AsGatt for MediaState {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1;
+
+    fn as_gatt(&self) -> &[u8] {
+        match self {
+            Self::Inactive => &[0],
+            Self::Playing => &[1],
+            Self::Paused => &[2],
+            Self::Seeking => &[3],
+        }
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for MediaState {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [value] => Self::try_from(*value),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+}
+
+/// An operation written to the Media Control Point.
+///
+/// Offsets and positions are in hundredths of a second, segment, track and group numbers start at 1 from the first
+/// and at -1 from the last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MediaControlOperation {
+    /// Start playing the current track.
+    Play,
+    /// Pause the current track.
+    Pause,
+    /// Start fast rewinding the current track.
+    FastRewind,
+    /// Start fast forwarding the current track.
+    FastForward,
+    /// Stop the current track and move to its start.
+    Stop,
+    /// Move the position in the current track by an offset.
+    MoveRelative(i32),
+    /// Move to the previous segment of the current track.
+    PreviousSegment,
+    /// Move to the next segment of the current track.
+    NextSegment,
+    /// Move to the first segment of the current track.
+    FirstSegment,
+    /// Move to the last segment of the current track.
+    LastSegment,
+    /// Move to a segment of the current track.
+    GotoSegment(i32),
+    /// Move to the previous track.
+    PreviousTrack,
+    /// Move to the next track.
+    NextTrack,
+    /// Move to the first track.
+    FirstTrack,
+    /// Move to the last track.
+    LastTrack,
+    /// Move to a track of the current group.
+    GotoTrack(i32),
+    /// Move to the previous group.
+    PreviousGroup,
+    /// Move to the next group.
+    NextGroup,
+    /// Move to the first group.
+    FirstGroup,
+    /// Move to the last group.
+    LastGroup,
+    /// Move to a group.
+    GotoGroup(i32),
+}
+
+impl MediaControlOperation {
+    /// Decode an operation, returning the response to notify if the opcode is unknown or its operand invalid.
+    pub fn decode(data: &[u8]) -> Result<Self, MediaControlResponse> {
+        let [opcode, operand @ ..] = data else {
+            return Err(MediaControlResponse::new(0, MediaControlResult::OpcodeNotSupported));
+        };
+        let operand = || {
+            let operand: [u8; 4] = operand
+                .try_into()
+                .map_err(|_| MediaControlResponse::new(*opcode, MediaControlResult::CannotBeCompleted))?;
+            Ok(i32::from_le_bytes(operand))
+        };
+        let operation = match opcode {
+            0x01 => Self::Play,
+            0x02 => Self::Pause,
+            0x03 => Self::FastRewind,
+            0x04 => Self::FastForward,
+            0x05 => Self::Stop,
+            0x10 => Self::MoveRelative(operand()?),
+            0x20 => Self::PreviousSegment,
+            0x21 => Self::NextSegment,
+            0x22 => Self::FirstSegment,
+            0x23 => Self::LastSegment,
+            0x24 => Self::GotoSegment(operand()?),
+            0x30 => Self::PreviousTrack,
+            0x31 => Self::NextTrack,
+            0x32 => Self::FirstTrack,
+            0x33 => Self::LastTrack,
+            0x34 => Self::GotoTrack(operand()?),
+            0x40 => Self::PreviousGroup,
+            0x41 => Self::NextGroup,
+            0x42 => Self::FirstGroup,
+            0x43 => Self::LastGroup,
+            0x44 => Self::GotoGroup(operand()?),
+            _ => {
+                return Err(MediaControlResponse::new(
+                    *opcode,
+                    MediaControlResult::OpcodeNotSupported,
+                ))
+            }
+        };
+        Ok(operation)
+    }
+
+    /// Opcode of the operation.
+    pub fn opcode(&self) -> u8 {
+        match self {
+            Self::Play => 0x01,
+            Self::Pause => 0x02,
+            Self::FastRewind => 0x03,
+            Self::FastForward => 0x04,
+            Self::Stop => 0x05,
+            Self::MoveRelative(_) => 0x10,
+            Self::PreviousSegment => 0x20,
+            Self::NextSegment => 0x21,
+            Self::FirstSegment => 0x22,
+            Self::LastSegment => 0x23,
+            Self::GotoSegment(_) => 0x24,
+            Self::PreviousTrack => 0x30,
+            Self::NextTrack => 0x31,
+            Self::FirstTrack => 0x32,
+            Self::LastTrack => 0x33,
+            Self::GotoTrack(_) => 0x34,
+            Self::PreviousGroup => 0x40,
+            Self::NextGroup => 0x41,
+            Self::FirstGroup => 0x42,
+            Self::LastGroup => 0x43,
+            Self::GotoGroup(_) => 0x44,
+        }
+    }
+
+    /// Bit of the operation in the Media Control Point Opcodes Supported characteristic.
+    pub fn supported_bit(&self) -> u32 {
+        let bit = match self.opcode() {
+            opcode @ 0x01..=0x05 => opcode - 0x01,
+            0x10 => 5,
+            opcode @ 0x20..=0x24 => opcode - 0x20 + 6,
+            opcode @ 0x30..=0x34 => opcode - 0x30 + 11,
+            opcode => opcode - 0x40 + 16,
+        };
+        1 << bit
+    }
+
+    /// Whether the operation is accepted while the media player is inactive.
+    ///
+    /// Track and group operations select a track, after which the player is paused. Other operations act on the
+    /// current track, so fail with [`MediaControlResult::MediaPlayerInactive`] while there is none.
+    pub fn allowed_while_inactive(&self) -> bool {
+        self.opcode() >= 0x30
+    }
+
+    /// The response to notify once the operation succeeded.
+    pub fn success(&self) -> MediaControlResponse {
+        MediaControlResponse::new(self.opcode(), MediaControlResult::Success)
+    }
+}
+
+/// Result of an operation written to the Media Control Point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum MediaControlResult {
+    /// The operation succeeded.
+    Success = 1,
+    /// The opcode is unknown or not supported by the player.
+    OpcodeNotSupported = 2,
+    /// The media player is inactive.
+    MediaPlayerInactive = 3,
+    /// The operation could not be completed.
+    CannotBeCompleted = 4,
+}
+
+/// Response notified by the Media Control Point after an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct MediaControlResponse {
+    /// Opcode of the operation.
+    pub opcode: u8,
+    /// Result of the operation.
+    pub result: MediaControlResult,
+}
+
+impl MediaControlResponse {
+    /// Response to an operation.
+    pub fn new(opcode: u8, result: MediaControlResult) -> Self {
+        Self { opcode, result }
+    }
+
+    /// Encode the response as notified by the Media Control Point.
+    pub fn encode(&self) -> [u8; 2] {
+        [self.opcode, self.result as u8]
+    }
+}
+
+/// Generic Media Control service.
+///
+/// Can be added to a `#[gatt_server]` like a `#[gatt_service]`. The player starts inactive with no operation
+/// supported, set the supported operations with [`MediaControlService::set_supported`]. The characteristics are
+/// only accessible over an encrypted link.
+pub struct MediaControlService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Name of the media player.
+    pub player_name: Characteristic<String<32>>,
+    /// Track changed, notified when the current track changes.
+    pub track_changed: Characteristic<[u8; 0]>,
+    /// Title of the current track.
+    pub track_title: Characteristic<String<64>>,
+    /// Duration of the current track in hundredths of a second, or [`TRACK_POSITION_UNAVAILABLE`].
+    pub track_duration: Characteristic<i32>,
+    /// Position in the current track in hundredths of a second, or [`TRACK_POSITION_UNAVAILABLE`].
+    pub track_position: Characteristic<i32>,
+    /// State of the media player.
+    pub media_state: Characteristic<MediaState>,
+    /// Media control point, written by the client and notifying the result of operations.
+    pub control_point: Characteristic<Vec<u8, 5>>,
+    /// Operations supported by the media control point, a bit for each.
+    pub opcodes_supported: Characteristic<u32>,
+    /// Content control ID, identifying the service to the client among other content control services.
+    pub content_control_id: Characteristic<u8>,
+}
+
+impl MediaControlService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 26;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 7;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static PLAYER_NAME_STORE: StaticCell<[u8; 32]> = StaticCell::new();
+        static TRACK_CHANGED_STORE: StaticCell<[u8; 0]> = StaticCell::new();
+        static TRACK_TITLE_STORE: StaticCell<[u8; 64]> = StaticCell::new();
+        static TRACK_DURATION_STORE: StaticCell<[u8; 4]> = StaticCell::new();
+        static TRACK_POSITION_STORE: StaticCell<[u8; 4]> = StaticCell::new();
+        static MEDIA_STATE_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+        static CONTROL_POINT_STORE: StaticCell<[u8; 5]> = StaticCell::new();
+        static OPCODES_SUPPORTED_STORE: StaticCell<[u8; 4]> = StaticCell::new();
+        static CONTENT_CONTROL_ID_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::GENERIC_MEDIA_CONTROL));
+        let player_name = service
+            .add_characteristic(
+                characteristic::MEDIA_PLAYER_NAME,
+                &[CharacteristicProp::Read],
+                String::<32>::new(),
+                PLAYER_NAME_STORE.init([0; 32]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let track_changed = service
+            .add_characteristic(
+                characteristic::TRACK_CHANGED,
+                &[CharacteristicProp::Notify],
+                [],
+                TRACK_CHANGED_STORE.init([]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let track_title = service
+            .add_characteristic(
+                characteristic::TRACK_TITLE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                String::<64>::new(),
+                TRACK_TITLE_STORE.init([0; 64]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let track_duration = service
+            .add_characteristic(
+                characteristic::TRACK_DURATION,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                TRACK_POSITION_UNAVAILABLE,
+                TRACK_DURATION_STORE.init([0; 4]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let track_position = service
+            .add_characteristic(
+                characteristic::TRACK_POSITION,
+                &[
+                    CharacteristicProp::Read,
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                TRACK_POSITION_UNAVAILABLE,
+                TRACK_POSITION_STORE.init([0; 4]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let media_state = service
+            .add_characteristic(
+                characteristic::MEDIA_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                MediaState::Inactive,
+                MEDIA_STATE_STORE.init([0; 1]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let control_point = service
+            .add_characteristic(
+                characteristic::MEDIA_CONTROL_POINT,
+                &[
+                    CharacteristicProp::Write,
+                    CharacteristicProp::WriteWithoutResponse,
+                    CharacteristicProp::Notify,
+                ],
+                Vec::<u8, 5>::new(),
+                CONTROL_POINT_STORE.init([0; 5]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let opcodes_supported = service
+            .add_characteristic(
+                characteristic::MEDIA_CONTROL_POINT_OPCODES_SUPPORTED,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                0u32,
+                OPCODES_SUPPORTED_STORE.init([0; 4]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let content_control_id = service
+            .add_characteristic(
+                characteristic::CONTENT_CONTROL_ID,
+                &[CharacteristicProp::Read],
+                0u8,
+                CONTENT_CONTROL_ID_STORE.init([0; 1]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        Self {
+            handle: service.build(),
+            player_name,
+            track_changed,
+            track_title,
+            track_duration,
+            track_position,
+            media_state,
+            control_point,
+            opcodes_supported,
+            content_control_id,
+        }
+    }
+
+    /// Set the operations supported by the media player.
+    pub fn set_supported<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        operations: &[MediaControlOperation],
+    ) -> Result<(), Error> {
+        let supported = operations.iter().fold(0, |bits, op| bits | op.supported_bit());
+        self.opcodes_supported.set(server, &supported)
+    }
+
+    /// The operation written by a GATT event to the media control point.
+    ///
+    /// Returns the response to notify instead if the operation is unknown, not supported, or needs a current track
+    /// while the media player is inactive. The event is accepted either way, and the result notified with
+    /// [`MediaControlService::respond`] once the operation is done.
+    pub fn operation<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        event: &GattEvent<'_, '_, P>,
+    ) -> Option<Result<MediaControlOperation, MediaControlResponse>> {
+        let data = match event.payload().incoming() {
+            AttClient::Command(AttCmd::Write { handle, data }) | AttClient::Request(AttReq::Write { handle, data })
+                if handle == self.control_point.handle =>
+            {
+                data
+            }
+            _ => return None,
+        };
+        Some(self.check_operation(server, data))
+    }
+
+    fn check_operation<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        data: &[u8],
+    ) -> Result<MediaControlOperation, MediaControlResponse> {
+        let operation = MediaControlOperation::decode(data)?;
+        let supported = self.opcodes_supported.get(server).unwrap_or(0);
+        let result = if supported & operation.supported_bit() == 0 {
+            Err(MediaControlResult::OpcodeNotSupported)
+        } else if self.media_state.get(server) == Ok(MediaState::Inactive) && !operation.allowed_while_inactive() {
+            Err(MediaControlResult::MediaPlayerInactive)
+        } else {
+            Ok(operation)
+        };
+        result.map_err(|result| MediaControlResponse::new(operation.opcode(), result))
+    }
+
+    /// Notify the result of an operation to the client, if it subscribed to the media control point.
+    pub async fn respond<P: PacketPool>(
+        &self,
+        connection: &GattConnection<'_, '_, P>,
+        response: &MediaControlResponse,
+    ) -> Result<(), Error> {
+        let value = unwrap!(Vec::from_slice(&response.encode()));
+        self.control_point.notify(connection, &value).await
+    }
+
+    /// The track position written by a GATT event, if it writes the track position characteristic.
+    ///
+    /// The written position is stored once the event is accepted. A player that cannot move to it should set and
+    /// notify the position it moved to instead.
+    pub fn requested_position<P: PacketPool>(&self, event: &GattEvent<'_, '_, P>) -> Option<i32> {
+        match event.payload().incoming() {
+            AttClient::Command(AttCmd::Write { handle, data }) | AttClient::Request(AttReq::Write { handle, data })
+                if handle == self.track_position.handle =>
+            {
+                i32::from_gatt(data).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl GattService for MediaControlService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    use crate::prelude::DefaultPacketPool;
+
+    #[test]
+    fn media_control_operations() {
+        assert_eq!(MediaControlOperation::decode(&[0x01]), Ok(MediaControlOperation::Play));
+        assert_eq!(
+            MediaControlOperation::decode(&[0x34, 0xff, 0xff, 0xff, 0xff]),
+            Ok(MediaControlOperation::GotoTrack(-1))
+        );
+        assert_eq!(
+            MediaControlOperation::decode(&[0x10, 0x01]),
+            Err(MediaControlResponse::new(0x10, MediaControlResult::CannotBeCompleted))
+        );
+        assert_eq!(
+            MediaControlOperation::decode(&[0x06]).map_err(|r| r.encode()),
+            Err([0x06, 0x02])
+        );
+
+        assert_eq!(MediaControlOperation::Play.supported_bit(), 1 << 0);
+        assert_eq!(MediaControlOperation::MoveRelative(0).supported_bit(), 1 << 5);
+        assert_eq!(MediaControlOperation::GotoSegment(1).supported_bit(), 1 << 10);
+        assert_eq!(MediaControlOperation::NextTrack.supported_bit(), 1 << 12);
+        assert_eq!(MediaControlOperation::GotoGroup(1).supported_bit(), 1 << 20);
+        assert_eq!(MediaControlOperation::Pause.success().encode(), [0x02, 0x01]);
+    }
+
+    #[test]
+    fn inactive_player_selects_tracks() {
+        let mut table: AttributeTable<'_, NoopRawMutex, { MediaControlService::ATTRIBUTE_COUNT }> =
+            AttributeTable::new();
+        let media = MediaControlService::new(&mut table);
+        let server: AttributeServer<
+            '_,
+            NoopRawMutex,
+            DefaultPacketPool,
+            { MediaControlService::ATTRIBUTE_COUNT },
+            7,
+            1,
+        > = AttributeServer::new(table);
+        let operations = [
+            MediaControlOperation::Play,
+            MediaControlOperation::NextSegment,
+            MediaControlOperation::FirstTrack,
+            MediaControlOperation::NextGroup,
+        ];
+        media.set_supported(&server, &operations).unwrap();
+
+        assert_eq!(
+            media.check_operation(&server, &[0x01]),
+            Err(MediaControlResponse::new(0x01, MediaControlResult::MediaPlayerInactive))
+        );
+        assert_eq!(
+            media.check_operation(&server, &[0x21]),
+            Err(MediaControlResponse::new(0x21, MediaControlResult::MediaPlayerInactive))
+        );
+        assert_eq!(
+            media.check_operation(&server, &[0x32]),
+            Ok(MediaControlOperation::FirstTrack)
+        );
+        assert_eq!(
+            media.check_operation(&server, &[0x41]),
+            Ok(MediaControlOperation::NextGroup)
+        );
+        assert_eq!(
+            media.check_operation(&server, &[0x31]),
+            Err(MediaControlResponse::new(0x31, MediaControlResult::OpcodeNotSupported))
+        );
+
+        media.media_state.set(&server, &MediaState::Paused).unwrap();
+        assert_eq!(media.check_operation(&server, &[0x01]), Ok(MediaControlOperation::Play));
+    }
+}
@@ -0,0 +1,353 @@
+//! Mesh Proxy and Provisioning services.
+//!
+//! Implements the GATT bearer of Bluetooth Mesh: the proxy PDU segmentation and reassembly, and the Mesh
+//! Provisioning (PB-GATT) and Mesh Proxy services carrying proxy PDUs. The PDUs themselves, provisioning,
+//! network and proxy configuration messages, are left to the mesh stack layered on top.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::mesh::{MeshProvisioningService, ProxyReassembler};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     provisioning: MeshProvisioningService,
+//! }
+//!
+//! async fn run<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+//!     let mut reassembler = ProxyReassembler::<66>::new();
+//!     loop {
+//!         if let GattConnectionEvent::Gatt { event } = conn.next().await {
+//!             if let Some(data) = server.provisioning.data_in(&event) {
+//!                 if let Some((message_type, pdu)) = reassembler.push(data)? {
+//!                     // Pass the provisioning PDU to the mesh stack, and reply with
+//!                     // server.provisioning.send(conn, message_type, &response).await?
+//!                 }
+//!             }
+//!             event.accept()?.send().await;
+//!         }
+//!     }
+//! }
+//! ```
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttCmd};
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, GattService, Service};
+use crate::gatt::{GattConnection, GattEvent};
+use crate::{Error, PacketPool};
+
+/// Maximum size of a proxy PDU written to or notified from the data characteristics.
+pub const MAX_PDU_LEN: usize = 66;
+
+const SAR_COMPLETE: u8 = 0b00;
+const SAR_FIRST: u8 = 0b01;
+const SAR_CONTINUATION: u8 = 0b10;
+const SAR_LAST: u8 = 0b11;
+
+/// Type of the message carried by a proxy PDU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ProxyMessageType {
+    /// Network PDU.
+    NetworkPdu = 0x00,
+    /// Mesh beacon.
+    MeshBeacon = 0x01,
+    /// Proxy configuration message.
+    ProxyConfiguration = 0x02,
+    /// Provisioning PDU.
+    ProvisioningPdu = 0x03,
+}
+
+impl TryFrom<u8> for ProxyMessageType {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self, Error> {
+        Ok(match value {
+            0x00 => Self::NetworkPdu,
+            0x01 => Self::MeshBeacon,
+            0x02 => Self::ProxyConfiguration,
+            0x03 => Self::ProvisioningPdu,
+            _ => return Err(Error::InvalidValue),
+        })
+    }
+}
+
+/// Reassembles messages from segmented proxy PDUs.
+///
+/// Messages of up to `N` bytes can be reassembled. Per the specification, the connection should be
+/// terminated when a PDU is rejected.
+pub struct ProxyReassembler<const N: usize> {
+    buf: Vec<u8, N>,
+    message_type: Option<ProxyMessageType>,
+    complete: bool,
+}
+
+impl<const N: usize> Default for ProxyReassembler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> ProxyReassembler<N> {
+    /// Create an empty reassembler.
+    pub fn new() -> Self {
+        Self {
+            buf: Vec::new(),
+            message_type: None,
+            complete: false,
+        }
+    }
+
+    /// Add a proxy PDU, returning the message once it is complete.
+    ///
+    /// Returns an error if the PDU is out of sequence, has an unknown message type, or the message does not
+    /// fit. The partially reassembled message is discarded in that case.
+    pub fn push(&mut self, pdu: &[u8]) -> Result<Option<(ProxyMessageType, &[u8])>, Error> {
+        if self.complete {
+            self.reset();
+        }
+        let result = self.append(pdu);
+        if result.is_err() {
+            self.reset();
+        }
+        if result? {
+            self.complete = true;
+            // Message type is always set when a message is complete.
+            Ok(self.message_type.map(|t| (t, &self.buf[..])))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn append(&mut self, pdu: &[u8]) -> Result<bool, Error> {
+        let (&header, data) = pdu.split_first().ok_or(Error::InvalidValue)?;
+        let message_type = ProxyMessageType::try_from(header & 0x3f)?;
+        let sar = header >> 6;
+        match (sar, self.message_type) {
+            (SAR_COMPLETE | SAR_FIRST, None) => self.message_type = Some(message_type),
+            (SAR_CONTINUATION | SAR_LAST, Some(t)) if t == message_type => {}
+            _ => return Err(Error::InvalidValue),
+        }
+        self.buf.extend_from_slice(data).map_err(|_| Error::InsufficientSpace)?;
+        Ok(sar == SAR_COMPLETE || sar == SAR_LAST)
+    }
+
+    fn reset(&mut self) {
+        self.buf.clear();
+        self.message_type = None;
+        self.complete = false;
+    }
+}
+
+/// A segment of a message, as produced by [`segments`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ProxySegment<'a> {
+    /// The proxy PDU header, with the SAR and message type.
+    pub header: u8,
+    /// The part of the message carried by this segment.
+    pub data: &'a [u8],
+}
+
+impl ProxySegment<'_> {
+    /// Encode the proxy PDU.
+    pub fn to_vec<const N: usize>(&self) -> Result<Vec<u8, N>, Error> {
+        let mut pdu = Vec::new();
+        pdu.push(self.header).map_err(|_| Error::InsufficientSpace)?;
+        pdu.extend_from_slice(self.data).map_err(|_| Error::InsufficientSpace)?;
+        Ok(pdu)
+    }
+}
+
+/// Iterator over the segments of a message, created by [`segments`].
+pub struct ProxySegments<'a> {
+    message_type: ProxyMessageType,
+    data: &'a [u8],
+    chunk: usize,
+    first: bool,
+}
+
+/// Split a message into proxy PDUs of at most `max_pdu_len` bytes.
+///
+/// `max_pdu_len` is typically the ATT MTU minus 3, and must be at least 2.
+pub fn segments(message_type: ProxyMessageType, data: &[u8], max_pdu_len: usize) -> ProxySegments<'_> {
+    ProxySegments {
+        message_type,
+        data,
+        chunk: max_pdu_len.max(2) - 1,
+        first: true,
+    }
+}
+
+impl<'a> Iterator for ProxySegments<'a> {
+    type Item = ProxySegment<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() && !self.first {
+            return None;
+        }
+        let len = self.data.len().min(self.chunk);
+        let (data, rest) = self.data.split_at(len);
+        let sar = match (self.first, rest.is_empty()) {
+            (true, true) => SAR_COMPLETE,
+            (true, false) => SAR_FIRST,
+            (false, false) => SAR_CONTINUATION,
+            (false, true) => SAR_LAST,
+        };
+        self.first = false;
+        self.data = rest;
+        Some(ProxySegment {
+            header: (sar << 6) | self.message_type as u8,
+            data,
+        })
+    }
+}
+
+macro_rules! mesh_service {
+    ($(#[$attr:meta])* $name:ident, $service:expr, $data_in:expr, $data_out:expr) => {
+        $(#[$attr])*
+        pub struct $name {
+            /// Handle of the service.
+            pub handle: u16,
+            /// Proxy PDUs written by the client.
+            pub data_in: Characteristic<Vec<u8, MAX_PDU_LEN>>,
+            /// Proxy PDUs notified to the client.
+            pub data_out: Characteristic<Vec<u8, MAX_PDU_LEN>>,
+        }
+
+        impl $name {
+            /// Number of attributes added to the table.
+            pub const ATTRIBUTE_COUNT: usize = 6;
+            /// Number of CCCDs added to the table.
+            pub const CCCD_COUNT: usize = 1;
+
+            /// Add the service to the attribute table.
+            pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+                static DATA_IN_STORE: StaticCell<[u8; MAX_PDU_LEN]> = StaticCell::new();
+                static DATA_OUT_STORE: StaticCell<[u8; MAX_PDU_LEN]> = StaticCell::new();
+
+                let mut service = table.add_service(Service::new($service));
+                let data_in = service
+                    .add_characteristic(
+                        $data_in,
+                        &[CharacteristicProp::WriteWithoutResponse],
+                        Vec::<u8, MAX_PDU_LEN>::new(),
+                        DATA_IN_STORE.init([0; MAX_PDU_LEN]),
+                    )
+                    .build();
+                let data_out = service
+                    .add_characteristic(
+                        $data_out,
+                        &[CharacteristicProp::Notify],
+                        Vec::<u8, MAX_PDU_LEN>::new(),
+                        DATA_OUT_STORE.init([0; MAX_PDU_LEN]),
+                    )
+                    .build();
+                Self {
+                    handle: service.build(),
+                    data_in,
+                    data_out,
+                }
+            }
+
+            /// The proxy PDU written by a GATT event, if it writes the data in characteristic.
+            pub fn data_in<'a, P: PacketPool>(&self, event: &'a GattEvent<'_, '_, P>) -> Option<&'a [u8]> {
+                match event.payload().incoming() {
+                    AttClient::Command(AttCmd::Write { handle, data }) if handle == self.data_in.handle => Some(data),
+                    _ => None,
+                }
+            }
+
+            /// Send a message to the client, segmented to fit the ATT MTU of the connection.
+            pub async fn send<P: PacketPool>(
+                &self,
+                connection: &GattConnection<'_, '_, P>,
+                message_type: ProxyMessageType,
+                data: &[u8],
+            ) -> Result<(), Error> {
+                let max_pdu_len = (connection.raw().att_mtu() as usize - 3).min(MAX_PDU_LEN);
+                for segment in segments(message_type, data, max_pdu_len) {
+                    self.data_out.notify(connection, &segment.to_vec()?).await?;
+                }
+                Ok(())
+            }
+        }
+
+        impl GattService for $name {
+            const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+            const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+            fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+                Self::new(table)
+            }
+        }
+    };
+}
+
+mesh_service!(
+    /// Mesh Provisioning service, the PB-GATT provisioning bearer.
+    ///
+    /// Can be added to a `#[gatt_server]` like a `#[gatt_service]`.
+    MeshProvisioningService,
+    service::MESH_PROVISIONING,
+    characteristic::MESH_PROVISIONING_DATA_IN,
+    characteristic::MESH_PROVISIONING_DATA_OUT
+);
+
+mesh_service!(
+    /// Mesh Proxy service, relaying network PDUs, beacons and proxy configuration messages.
+    ///
+    /// Can be added to a `#[gatt_server]` like a `#[gatt_service]`.
+    MeshProxyService,
+    service::MESH_PROXY,
+    characteristic::MESH_PROXY_DATA_IN,
+    characteristic::MESH_PROXY_DATA_OUT
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proxy_sar() {
+        let message = [1, 2, 3, 4, 5, 6, 7];
+        let pdus: Vec<Vec<u8, 4>, 4> = segments(ProxyMessageType::ProvisioningPdu, &message, 4)
+            .map(|s| s.to_vec::<4>().unwrap())
+            .collect();
+        assert_eq!(&pdus[0][..], &[0x43, 1, 2, 3]);
+        assert_eq!(&pdus[1][..], &[0x83, 4, 5, 6]);
+        assert_eq!(&pdus[2][..], &[0xc3, 7]);
+        assert_eq!(pdus.len(), 3);
+
+        let mut reassembler = ProxyReassembler::<16>::new();
+        assert_eq!(reassembler.push(&pdus[0]), Ok(None));
+        assert_eq!(reassembler.push(&pdus[1]), Ok(None));
+        assert_eq!(
+            reassembler.push(&pdus[2]),
+            Ok(Some((ProxyMessageType::ProvisioningPdu, &message[..])))
+        );
+        assert_eq!(
+            reassembler.push(&[0x00, 9]),
+            Ok(Some((ProxyMessageType::NetworkPdu, &[9][..])))
+        );
+
+        // Continuation without a first segment
+        assert_eq!(reassembler.push(&pdus[1]), Err(Error::InvalidValue));
+        // Message type changes between segments
+        assert_eq!(reassembler.push(&pdus[0]), Ok(None));
+        assert_eq!(reassembler.push(&[0xc0, 1]), Err(Error::InvalidValue));
+
+        let mut segments = segments(ProxyMessageType::MeshBeacon, &[], 20);
+        assert_eq!(
+            segments.next(),
+            Some(ProxySegment {
+                header: 0x01,
+                data: &[]
+            })
+        );
+        assert_eq!(segments.next(), None);
+    }
+}
@@ -0,0 +1,342 @@
+//! Wi-Fi provisioning service.
+//!
+//! Commissions Wi-Fi credentials over BLE. The client writes the credentials as a TLV payload to the
+//! credentials characteristic, and follows the progress of the connection on the status characteristic.
+//!
+//! The credentials are encoded as a sequence of `type, length, value` entries:
+//!
+//! | Type   | Value                               |
+//! |--------|-------------------------------------|
+//! | `0x01` | SSID, 1 to 32 bytes                 |
+//! | `0x02` | Passphrase, 8 to 63 bytes           |
+//! | `0x03` | Pre-shared key, 32 bytes            |
+//!
+//! The credentials do not fit a single write at the default ATT MTU, so clients either exchange a larger
+//! MTU or use a long write. The credentials characteristic requires an encrypted link, writes on other
+//! links are rejected by the attribute server with `Insufficient Encryption`.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::provisioning::{ProvisioningService, ProvisioningStatus};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     provisioning: ProvisioningService,
+//! }
+//!
+//! async fn run<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+//!     loop {
+//!         if let GattConnectionEvent::Gatt { event } = conn.next().await {
+//!             let written = server.provisioning.written(&event);
+//!             event.accept()?.send().await;
+//!             if written {
+//!                 match server.provisioning.credentials(server) {
+//!                     Ok(credentials) => { /* connect to credentials.ssid() */ }
+//!                     Err(_) => {
+//!                         let status = ProvisioningStatus::InvalidCredentials;
+//!                         server.provisioning.status.notify(conn, &status).await?;
+//!                     }
+//!                 }
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+use core::cell::Cell;
+
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::{String, Vec};
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttReq};
+use crate::attribute::{
+    AttributePermissions, AttributeSecurity, AttributeTable, Characteristic, CharacteristicProp, GattService, Service,
+    Uuid,
+};
+use crate::attribute_server::{AttPeer, AttributeServer};
+use crate::gatt::GattEvent;
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{Error, PacketPool};
+
+/// UUID of the provisioning service.
+pub const SERVICE: Uuid = Uuid::new_long(0x7472_6f75_0001_4000_8000_0000_7769_6669u128.to_le_bytes());
+/// UUID of the credentials characteristic.
+pub const CREDENTIALS: Uuid = Uuid::new_long(0x7472_6f75_0002_4000_8000_0000_7769_6669u128.to_le_bytes());
+/// UUID of the status characteristic.
+pub const STATUS: Uuid = Uuid::new_long(0x7472_6f75_0003_4000_8000_0000_7769_6669u128.to_le_bytes());
+
+/// Maximum size of the credentials payload.
+pub const MAX_CREDENTIALS_LEN: usize = 128;
+/// Maximum length of an SSID.
+pub const MAX_SSID_LEN: usize = 32;
+/// Maximum length of a passphrase.
+pub const MAX_PASSPHRASE_LEN: usize = 63;
+
+const TYPE_SSID: u8 = 0x01;
+const TYPE_PASSPHRASE: u8 = 0x02;
+const TYPE_PSK: u8 = 0x03;
+
+/// Secret used to join a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WifiSecret {
+    /// The network is open.
+    None,
+    /// WPA passphrase.
+    Passphrase(String<MAX_PASSPHRASE_LEN>),
+    /// WPA pre-shared key.
+    Psk([u8; 32]),
+}
+
+/// Wi-Fi credentials written by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WifiCredentials {
+    ssid: Vec<u8, MAX_SSID_LEN>,
+    secret: WifiSecret,
+}
+
+impl WifiCredentials {
+    /// Create credentials for a network, the SSID is 1 to 32 bytes.
+    pub fn new(ssid: &[u8], secret: WifiSecret) -> Result<Self, Error> {
+        if ssid.is_empty() {
+            return Err(Error::InvalidValue);
+        }
+        if let WifiSecret::Passphrase(passphrase) = &secret {
+            if passphrase.len() < 8 {
+                return Err(Error::InvalidValue);
+            }
+        }
+        Ok(Self {
+            ssid: Vec::from_slice(ssid).map_err(|_| Error::InvalidValue)?,
+            secret,
+        })
+    }
+
+    /// SSID of the network, usually but not necessarily UTF-8.
+    pub fn ssid(&self) -> &[u8] {
+        &self.ssid
+    }
+
+    /// Secret used to join the network.
+    pub fn secret(&self) -> &WifiSecret {
+        &self.secret
+    }
+
+    /// Decode credentials from a TLV payload.
+    pub fn from_tlv(mut data: &[u8]) -> Result<Self, Error> {
+        let mut ssid: Option<&[u8]> = None;
+        let mut secret = WifiSecret::None;
+        while let [kind, len, rest @ ..] = data {
+            let len = *len as usize;
+            if rest.len() < len {
+                return Err(Error::InvalidValue);
+            }
+            let (value, rest) = rest.split_at(len);
+            match *kind {
+                TYPE_SSID => ssid = Some(value),
+                TYPE_PASSPHRASE => {
+                    let passphrase = core::str::from_utf8(value).map_err(|_| Error::InvalidValue)?;
+                    secret = WifiSecret::Passphrase(String::try_from(passphrase).map_err(|_| Error::InvalidValue)?);
+                }
+                TYPE_PSK => secret = WifiSecret::Psk(value.try_into().map_err(|_| Error::InvalidValue)?),
+                // Unknown entries are skipped, so the format can be extended.
+                _ => {}
+            }
+            data = rest;
+        }
+        if !data.is_empty() {
+            return Err(Error::InvalidValue);
+        }
+        Self::new(ssid.ok_or(Error::InvalidValue)?, secret)
+    }
+
+    /// Encode the credentials as a TLV payload.
+    pub fn to_tlv(&self) -> Vec<u8, MAX_CREDENTIALS_LEN> {
+        let mut tlv = Vec::new();
+        let mut push = |kind: u8, value: &[u8]| {
+            // Cannot fail, the entries are bounded to fit
+            let _ = tlv.extend_from_slice(&[kind, value.len() as u8]);
+            let _ = tlv.extend_from_slice(value);
+        };
+        push(TYPE_SSID, &self.ssid);
+        match &self.secret {
+            WifiSecret::None => {}
+            WifiSecret::Passphrase(passphrase) => push(TYPE_PASSPHRASE, passphrase.as_bytes()),
+            WifiSecret::Psk(psk) => push(TYPE_PSK, psk),
+        }
+        tlv
+    }
+}
+
+/// Status of the provisioning, notified to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum ProvisioningStatus {
+    /// Waiting for credentials.
+    #[default]
+    Idle = 0,
+    /// Connecting to the network.
+    Connecting = 1,
+    /// Connected to the network.
+    Connected = 2,
+    /// The credentials could not be decoded.
+    InvalidCredentials = 3,
+    /// The network was not found.
+    NetworkNotFound = 4,
+    /// The network rejected the credentials.
+    AuthenticationFailed = 5,
+    /// Connecting failed for another reason.
+    Failed = 6,
+}
+
+impl AsGatt for ProvisioningStatus {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1;
+
+    fn as_gatt(&self) -> &[u8] {
+        match self {
+            Self::Idle => &[0],
+            Self::Connecting => &[1],
+            Self::Connected => &[2],
+            Self::InvalidCredentials => &[3],
+            Self::NetworkNotFound => &[4],
+            Self::AuthenticationFailed => &[5],
+            Self::Failed => &[6],
+        }
+    }
+}
+
+impl FromGatt for ProvisioningStatus {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Ok(match data {
+            [0] => Self::Idle,
+            [1] => Self::Connecting,
+            [2] => Self::Connected,
+            [3] => Self::InvalidCredentials,
+            [4] => Self::NetworkNotFound,
+            [5] => Self::AuthenticationFailed,
+            [6] => Self::Failed,
+            [_] => return Err(FromGattError::InvalidValue),
+            _ => return Err(FromGattError::InvalidLength),
+        })
+    }
+}
+
+/// Wi-Fi provisioning service.
+///
+/// Can be added to a `#[gatt_server]` like a `#[gatt_service]`.
+pub struct ProvisioningService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Credentials, written by the client as a TLV payload.
+    pub credentials: Characteristic<Vec<u8, MAX_CREDENTIALS_LEN>>,
+    /// Status of the provisioning.
+    pub status: Characteristic<ProvisioningStatus>,
+    long_write: Cell<bool>,
+}
+
+impl ProvisioningService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 6;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 1;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static CREDENTIALS_STORE: StaticCell<[u8; MAX_CREDENTIALS_LEN]> = StaticCell::new();
+        static STATUS_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(SERVICE));
+        let credentials = service
+            .add_characteristic(
+                CREDENTIALS,
+                &[CharacteristicProp::Write],
+                Vec::<u8, MAX_CREDENTIALS_LEN>::new(),
+                CREDENTIALS_STORE.init([0; MAX_CREDENTIALS_LEN]),
+            )
+            .permissions(AttributePermissions {
+                read: AttributeSecurity::None,
+                write: AttributeSecurity::Encrypted,
+            })
+            .build();
+        let status = service
+            .add_characteristic(
+                STATUS,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                ProvisioningStatus::Idle,
+                STATUS_STORE.init([0; 1]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            credentials,
+            status,
+            long_write: Cell::new(false),
+        }
+    }
+
+    /// Whether accepting a GATT event completes a write of the credentials, after which they can be read
+    /// with [`ProvisioningService::credentials`].
+    ///
+    /// Call before accepting the event. Writes on links without sufficient encryption are rejected by the
+    /// attribute server and never complete.
+    pub fn written<P: PacketPool>(&self, event: &GattEvent<'_, '_, P>) -> bool {
+        let handle = self.credentials.handle;
+        let encrypted = event.payload().connection().encryption_sufficient();
+        match event.payload().incoming() {
+            AttClient::Request(AttReq::Write { handle: h, .. }) if h == handle => encrypted,
+            AttClient::Request(AttReq::PrepareWrite { handle: h, .. }) if h == handle => {
+                if encrypted {
+                    self.long_write.set(true);
+                }
+                false
+            }
+            AttClient::Request(AttReq::ExecuteWrite { flags }) => self.long_write.replace(false) && flags == 1,
+            _ => false,
+        }
+    }
+
+    /// Decode the credentials written by the client.
+    pub fn credentials<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+    ) -> Result<WifiCredentials, Error> {
+        let data = self.credentials.get(server)?;
+        WifiCredentials::from_tlv(&data)
+    }
+}
+
+impl GattService for ProvisioningService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credentials_tlv() {
+        let credentials = WifiCredentials::new(
+            b"home",
+            WifiSecret::Passphrase(String::try_from("correct horse").unwrap()),
+        )
+        .unwrap();
+        let tlv = credentials.to_tlv();
+        assert_eq!(&tlv[..6], &[0x01, 4, b'h', b'o', b'm', b'e']);
+        assert_eq!(WifiCredentials::from_tlv(&tlv), Ok(credentials));
+
+        // Unknown entries are skipped
+        let tlv = [0x01, 1, b'x', 0x7f, 2, 0, 0];
+        let credentials = WifiCredentials::from_tlv(&tlv).unwrap();
+        assert_eq!(credentials.secret(), &WifiSecret::None);
+
+        assert!(WifiCredentials::from_tlv(&[0x01, 4, b'x']).is_err());
+        assert!(WifiCredentials::from_tlv(&[0x02, 8, b'p', b'a', b's', b's', b'w', b'o', b'r', b'd']).is_err());
+        assert!(WifiCredentials::from_tlv(&[0x01, 1, b'x', 0x02, 3, b'a', b'b', b'c']).is_err());
+    }
+}
@@ -0,0 +1,312 @@
+//! Proximity profile services.
+//!
+//! The Immediate Alert, Link Loss and Tx Power services, used by the Find Me and Proximity profiles.
+//! Each service can be added to a `#[gatt_server]` like a `#[gatt_service]`.
+//!
+//! The Link Loss alert level is kept in the attribute table, so it persists across connections until the
+//! client writes a new level. The alert is raised by the runner when a connection times out, through the
+//! handler returned by [`LinkLossService::handler`].
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::proximity::{AlertLevel, ImmediateAlertService, LinkLossService, TxPowerService};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     immediate_alert: ImmediateAlertService,
+//!     link_loss: LinkLossService,
+//!     tx_power: TxPowerService,
+//! }
+//!
+//! fn alert(level: AlertLevel) { /* beep */ }
+//!
+//! async fn ble_task<C: Controller, P: PacketPool>(server: &Server<'_>, mut runner: Runner<'_, C, P>) {
+//!     let link_loss = server.link_loss.handler(server, |_, level| alert(level));
+//!     let _ = runner.run_with_handler(&link_loss).await;
+//! }
+//!
+//! async fn run<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+//!     loop {
+//!         match conn.next().await {
+//!             GattConnectionEvent::Disconnected { .. } => return Ok(()),
+//!             GattConnectionEvent::Gatt { event } => {
+//!                 if let Some(level) = server.immediate_alert.alert_level(&event) {
+//!                     alert(level);
+//!                 }
+//!                 event.accept()?.send().await;
+//!             }
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! ```
+use bt_hci::param::{ConnHandle, Status};
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttCmd, AttReq};
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, GattService, Service};
+use crate::attribute_server::AttributeServer;
+use crate::gatt::GattEvent;
+use crate::host::EventHandler;
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::PacketPool;
+
+/// Alert Level characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[repr(u8)]
+pub enum AlertLevel {
+    /// No alert.
+    #[default]
+    NoAlert = 0,
+    /// Mild alert.
+    MildAlert = 1,
+    /// High alert.
+    HighAlert = 2,
+}
+
+impl AsGatt for AlertLevel {
+    const MIN_SIZE: usize = 1;
+    const MAX_SIZE: usize = 1;
+
+    fn as_gatt(&self) -> &[u8] {
+        match self {
+            Self::NoAlert => &[0],
+            Self::MildAlert => &[1],
+            Self::HighAlert => &[2],
+        }
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for AlertLevel {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        Ok(match data {
+            [0] => Self::NoAlert,
+            [1] => Self::MildAlert,
+            [2] => Self::HighAlert,
+            [_] => return Err(FromGattError::InvalidValue),
+            _ => return Err(FromGattError::InvalidLength),
+        })
+    }
+}
+
+/// Whether a disconnect reason is a link loss, the connection timing out rather than being terminated by either side.
+pub fn is_link_loss(reason: Status) -> bool {
+    reason == Status::CONN_TIMEOUT || reason == Status::LMP_LL_RESPONSE_TIMEOUT
+}
+
+/// Immediate Alert service.
+pub struct ImmediateAlertService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Alert level, written by the client without response.
+    pub alert_level: Characteristic<AlertLevel>,
+}
+
+impl ImmediateAlertService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 3;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 0;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static ALERT_LEVEL_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::IMMEDIATE_ALERT));
+        let alert_level = service
+            .add_characteristic(
+                characteristic::ALERT_LEVEL,
+                &[CharacteristicProp::WriteWithoutResponse],
+                AlertLevel::NoAlert,
+                ALERT_LEVEL_STORE.init([0; 1]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            alert_level,
+        }
+    }
+
+    /// The alert level written by a GATT event, if it writes a valid level to the alert level characteristic.
+    pub fn alert_level<P: PacketPool>(&self, event: &GattEvent<'_, '_, P>) -> Option<AlertLevel> {
+        match event.payload().incoming() {
+            AttClient::Command(AttCmd::Write { handle, data }) | AttClient::Request(AttReq::Write { handle, data })
+                if handle == self.alert_level.handle =>
+            {
+                AlertLevel::from_gatt(data).ok()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl GattService for ImmediateAlertService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+/// Link Loss service.
+pub struct LinkLossService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Alert level to raise when the link is lost.
+    pub alert_level: Characteristic<AlertLevel>,
+}
+
+impl LinkLossService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 3;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 0;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static ALERT_LEVEL_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::LINK_LOSS));
+        let alert_level = service
+            .add_characteristic(
+                characteristic::ALERT_LEVEL,
+                &[CharacteristicProp::Read, CharacteristicProp::Write],
+                AlertLevel::NoAlert,
+                ALERT_LEVEL_STORE.init([0; 1]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            alert_level,
+        }
+    }
+
+    /// Handle a disconnect, calling `alert` with the configured level if the link was lost.
+    ///
+    /// Nothing is raised when the connection did not time out, see [`is_link_loss`], or the level is
+    /// [`AlertLevel::NoAlert`].
+    pub fn disconnected<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        reason: Status,
+        alert: impl FnOnce(AlertLevel),
+    ) {
+        if !is_link_loss(reason) {
+            return;
+        }
+        match self.alert_level.get(server) {
+            Ok(AlertLevel::NoAlert) | Err(_) => {}
+            Ok(level) => alert(level),
+        }
+    }
+
+    /// Event handler raising the alert when the link of a connection is lost.
+    ///
+    /// Run the stack with it, e.g. with [`Runner::run_with_handler`](crate::host::Runner::run_with_handler),
+    /// so the alert is raised on every disconnect reported by the controller.
+    pub fn handler<'a, 'values, M, P, const AT: usize, const CT: usize, const CN: usize, F>(
+        &'a self,
+        server: &'a AttributeServer<'values, M, P, AT, CT, CN>,
+        alert: F,
+    ) -> impl EventHandler + use<'a, 'values, M, P, AT, CT, CN, F>
+    where
+        M: RawMutex,
+        P: PacketPool,
+        F: Fn(ConnHandle, AlertLevel),
+    {
+        LinkLossHandler {
+            service: self,
+            server,
+            alert,
+        }
+    }
+}
+
+struct LinkLossHandler<'a, 'values, M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize, F> {
+    service: &'a LinkLossService,
+    server: &'a AttributeServer<'values, M, P, AT, CT, CN>,
+    alert: F,
+}
+
+impl<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize, F: Fn(ConnHandle, AlertLevel)>
+    EventHandler for LinkLossHandler<'_, '_, M, P, AT, CT, CN, F>
+{
+    fn on_disconnected(&self, handle: ConnHandle, reason: Status) {
+        self.service
+            .disconnected(self.server, reason, |level| (self.alert)(handle, level));
+    }
+}
+
+impl GattService for LinkLossService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+/// Tx Power service.
+pub struct TxPowerService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Transmit power level in dBm, set by the application.
+    pub tx_power_level: Characteristic<i8>,
+}
+
+impl TxPowerService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 3;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 0;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static TX_POWER_LEVEL_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::TX_POWER));
+        let tx_power_level = service
+            .add_characteristic(
+                characteristic::TX_POWER_LEVEL,
+                &[CharacteristicProp::Read],
+                0i8,
+                TX_POWER_LEVEL_STORE.init([0; 1]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            tx_power_level,
+        }
+    }
+}
+
+impl GattService for TxPowerService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alert_level() {
+        assert_eq!(AlertLevel::from_gatt(&[2]), Ok(AlertLevel::HighAlert));
+        assert_eq!(AlertLevel::validate(&[3]), Err(FromGattError::InvalidValue));
+        assert!(is_link_loss(Status::CONN_TIMEOUT));
+        assert!(!is_link_loss(Status::REMOTE_USER_TERMINATED_CONN));
+        assert!(!is_link_loss(Status::REMOTE_DEVICE_TERMINATED_CONN_POWER_OFF));
+    }
+}
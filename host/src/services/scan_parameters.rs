@@ -0,0 +1,274 @@
+//! Scan Parameters service.
+//!
+//! A HID host writes the scan interval and window it uses to the Scan Parameters service of a HID device, so
+//! that the device can tune how it advertises when reconnecting, e.g. a keyboard advertising more often while the
+//! host only scans at a low duty cycle. The device can ask the host to write them again with a scan refresh.
+//!
+//! The service is added to a `#[gatt_server]` like a `#[gatt_service]`, and [`ScanParametersClient`] writes the
+//! parameters of a host.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::scan_parameters::ScanParametersService;
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     scan_parameters: ScanParametersService,
+//! }
+//!
+//! async fn run<P: PacketPool>(server: &Server<'_>, conn: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+//!     loop {
+//!         if let GattConnectionEvent::Gatt { event } = conn.next().await {
+//!             if let Some(timing) = server.scan_parameters.scan_interval_window(&event) {
+//!                 // Adjust the advertising interval used to reconnect to the host
+//!             }
+//!             event.accept()?.send().await;
+//!         }
+//!     }
+//! }
+//! ```
+use bt_hci::controller::Controller;
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_time::Duration;
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttCmd, AttErrorCode, AttReq};
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, GattService, Service};
+use crate::connection::ScanTiming;
+use crate::gatt::{GattClient, GattConnection, GattEvent};
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{BleHostError, Error, PacketPool};
+
+/// Value of the scan refresh characteristic asking the client to write its scan parameters again.
+pub const SERVER_REQUIRES_REFRESH: u8 = 0;
+
+/// Scan Interval Window characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ScanIntervalWindow {
+    buf: [u8; 4],
+}
+
+impl ScanIntervalWindow {
+    /// Interval and window in units of 0.625 ms, the window being at most the interval.
+    pub fn new(interval: u16, window: u16) -> Self {
+        let [i0, i1] = interval.to_le_bytes();
+        let [w0, w1] = window.to_le_bytes();
+        Self { buf: [i0, i1, w0, w1] }
+    }
+
+    /// Scan interval in units of 0.625 ms.
+    pub fn interval(&self) -> u16 {
+        u16::from_le_bytes([self.buf[0], self.buf[1]])
+    }
+
+    /// Scan window in units of 0.625 ms.
+    pub fn window(&self) -> u16 {
+        u16::from_le_bytes([self.buf[2], self.buf[3]])
+    }
+
+    /// Interval and window as durations.
+    pub fn timing(&self) -> ScanTiming {
+        ScanTiming {
+            interval: Duration::from_micros(u64::from(self.interval()) * 625),
+            window: Duration::from_micros(u64::from(self.window()) * 625),
+        }
+    }
+}
+
+impl From<ScanTiming> for ScanIntervalWindow {
+    fn from(timing: ScanTiming) -> Self {
+        let units = |d: Duration| (d.as_micros() / 625).clamp(0x0004, 0x4000) as u16;
+        Self::new(units(timing.interval), units(timing.window))
+    }
+}
+
+impl AsGatt for ScanIntervalWindow {
+    const MIN_SIZE: usize = 4;
+    const MAX_SIZE: usize = 4;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.buf
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for ScanIntervalWindow {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        let buf: [u8; 4] = data.try_into().map_err(|_| FromGattError::InvalidLength)?;
+        let value = Self { buf };
+        let range = 0x0004..=0x4000;
+        if !range.contains(&value.interval()) || !range.contains(&value.window()) || value.window() > value.interval() {
+            return Err(FromGattError::InvalidValue);
+        }
+        Ok(value)
+    }
+}
+
+/// Scan Parameters service.
+pub struct ScanParametersService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Scan interval and window of the client, written without response.
+    pub scan_interval_window: Characteristic<ScanIntervalWindow>,
+    /// Scan refresh, notified to ask the client to write its parameters again.
+    pub scan_refresh: Characteristic<u8>,
+}
+
+impl ScanParametersService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 6;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 1;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static SCAN_INTERVAL_WINDOW_STORE: StaticCell<[u8; 4]> = StaticCell::new();
+        static SCAN_REFRESH_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::SCAN_PARAMETERS));
+        let scan_interval_window = service
+            .add_characteristic(
+                characteristic::SCAN_INTERVAL_WINDOW,
+                &[CharacteristicProp::WriteWithoutResponse],
+                ScanIntervalWindow::new(0x0010, 0x0010),
+                SCAN_INTERVAL_WINDOW_STORE.init([0; 4]),
+            )
+            .build();
+        let scan_refresh = service
+            .add_characteristic(
+                characteristic::SCAN_REFRESH,
+                &[CharacteristicProp::Notify],
+                SERVER_REQUIRES_REFRESH,
+                SCAN_REFRESH_STORE.init([0; 1]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            scan_interval_window,
+            scan_refresh,
+        }
+    }
+
+    /// The scan parameters written by a GATT event, if it writes valid parameters to the scan interval window
+    /// characteristic.
+    pub fn scan_interval_window<P: PacketPool>(&self, event: &GattEvent<'_, '_, P>) -> Option<ScanIntervalWindow> {
+        match event.payload().incoming() {
+            AttClient::Command(AttCmd::Write { handle, data }) | AttClient::Request(AttReq::Write { handle, data })
+                if handle == self.scan_interval_window.handle =>
+            {
+                ScanIntervalWindow::from_gatt(data).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Ask the client to write its scan parameters again, if it subscribed to the scan refresh.
+    pub async fn request_refresh<P: PacketPool>(&self, connection: &GattConnection<'_, '_, P>) -> Result<(), Error> {
+        self.scan_refresh.notify(connection, &SERVER_REQUIRES_REFRESH).await
+    }
+}
+
+impl GattService for ScanParametersService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+/// Client writing the scan parameters of a HID host to the Scan Parameters service of a device.
+pub struct ScanParametersClient {
+    /// Scan interval window characteristic of the server.
+    pub scan_interval_window: Characteristic<ScanIntervalWindow>,
+    /// Scan refresh characteristic of the server, which is optional.
+    pub scan_refresh: Option<Characteristic<u8>>,
+}
+
+impl ScanParametersClient {
+    /// Discover the Scan Parameters service of the server.
+    pub async fn discover<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+    ) -> Result<Self, BleHostError<C::Error>> {
+        let services = client.services_by_uuid(&service::SCAN_PARAMETERS.into()).await?;
+        let service = services.first().ok_or(Error::NotFound)?;
+        let scan_interval_window = client
+            .characteristic_by_uuid(service, &characteristic::SCAN_INTERVAL_WINDOW.into())
+            .await?;
+        let scan_refresh = match client
+            .characteristic_by_uuid(service, &characteristic::SCAN_REFRESH.into())
+            .await
+        {
+            Ok(characteristic) => Some(characteristic),
+            Err(BleHostError::BleHost(Error::NotFound | Error::Att(AttErrorCode::ATTRIBUTE_NOT_FOUND))) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            scan_interval_window,
+            scan_refresh,
+        })
+    }
+
+    /// Write the scan interval and window used to scan for the server.
+    pub async fn write<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+        timing: ScanTiming,
+    ) -> Result<(), BleHostError<C::Error>> {
+        let value = ScanIntervalWindow::from(timing);
+        client
+            .write_characteristic_without_response(&self.scan_interval_window, value.as_gatt())
+            .await
+    }
+
+    /// Write the scan interval and window, and write them again each time the server asks for a refresh.
+    ///
+    /// Only returns on a failed request, so run it alongside the other tasks of the connection. Returns once
+    /// written if the server does not support scan refresh.
+    pub async fn maintain<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+        &self,
+        client: &GattClient<'_, C, P, MAX_SERVICES>,
+        timing: ScanTiming,
+    ) -> Result<(), BleHostError<C::Error>> {
+        self.write(client, timing).await?;
+        let Some(scan_refresh) = &self.scan_refresh else {
+            return Ok(());
+        };
+        let mut listener = client.subscribe(scan_refresh, false).await?;
+        loop {
+            if listener.next().await.as_ref() == [SERVER_REQUIRES_REFRESH] {
+                self.write(client, timing).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_interval_window() {
+        let timing = ScanTiming {
+            interval: Duration::from_millis(60),
+            window: Duration::from_millis(30),
+        };
+        let value = ScanIntervalWindow::from(timing);
+        assert_eq!(value.as_gatt(), &[0x60, 0x00, 0x30, 0x00]);
+        assert_eq!(value.timing(), timing);
+        assert_eq!(ScanIntervalWindow::from_gatt(&[0x60, 0x00, 0x30, 0x00]), Ok(value));
+        assert_eq!(
+            ScanIntervalWindow::validate(&[0x30, 0x00, 0x60, 0x00]),
+            Err(FromGattError::InvalidValue)
+        );
+        assert_eq!(
+            ScanIntervalWindow::validate(&[0x30, 0x00]),
+            Err(FromGattError::InvalidLength)
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Telephony and Media Audio service.
+//!
+//! LE Audio devices declare the Telephony and Media Audio Profile (TMAP) roles they support, such as a phone being
+//! a call gateway and unicast media sender, or earbuds being a call terminal and unicast media receiver. The roles
+//! are exposed by the TMAP Role characteristic of the Telephony and Media Audio service, and advertised as service
+//! data so that peers can find devices with a matching role before connecting.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::tmap::{TmapRoles, TmapService};
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     tmap: TmapService,
+//! }
+//!
+//! fn setup(server: &Server<'_>, adv: &mut [u8; 31]) -> Result<usize, Error> {
+//!     let roles = TmapRoles::CALL_TERMINAL.with(TmapRoles::UNICAST_MEDIA_RECEIVER);
+//!     server.tmap.roles.set(server, &roles)?;
+//!     AdStructure::encode_slice(
+//!         &[
+//!             AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+//!             roles.ad_structure(),
+//!         ],
+//!         adv,
+//!     )
+//!     .map_err(Error::from)
+//! }
+//! ```
+use bt_hci::controller::Controller;
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use static_cell::StaticCell;
+
+use crate::advertise::AdStructure;
+use crate::attribute::{AttributeTable, Characteristic, CharacteristicProp, GattService, Service};
+use crate::gatt::GattClient;
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{BleHostError, Error, PacketPool};
+
+/// TMAP Role characteristic value, the set of TMAP roles of a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TmapRoles(u16);
+
+impl TmapRoles {
+    /// Call Gateway, such as a phone placing calls.
+    pub const CALL_GATEWAY: Self = Self(0x0001);
+    /// Call Terminal, such as a headset taking part in calls.
+    pub const CALL_TERMINAL: Self = Self(0x0002);
+    /// Unicast Media Sender, such as a phone streaming music.
+    pub const UNICAST_MEDIA_SENDER: Self = Self(0x0004);
+    /// Unicast Media Receiver, such as earbuds playing music.
+    pub const UNICAST_MEDIA_RECEIVER: Self = Self(0x0008);
+    /// Broadcast Media Sender, such as a TV broadcasting its audio.
+    pub const BROADCAST_MEDIA_SENDER: Self = Self(0x0010);
+    /// Broadcast Media Receiver, such as a speaker playing a broadcast.
+    pub const BROADCAST_MEDIA_RECEIVER: Self = Self(0x0020);
+
+    /// Combine two sets of roles.
+    pub const fn with(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Check if all roles of `other` are supported.
+    pub const fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Service data advertising the roles.
+    pub fn ad_structure(&self) -> AdStructure<'_> {
+        AdStructure::ServiceData16 {
+            uuid: service::TELEPHONY_AND_MEDIA_AUDIO.to_le_bytes(),
+            data: self.as_gatt(),
+        }
+    }
+
+    /// The roles advertised by a device, if its advertising data holds them.
+    pub fn from_advertising(data: &[u8]) -> Option<Self> {
+        AdStructure::decode(data).find_map(|ad| match ad {
+            Ok(AdStructure::ServiceData16 { uuid, data })
+                if uuid == service::TELEPHONY_AND_MEDIA_AUDIO.to_le_bytes() =>
+            {
+                Self::from_gatt(data).ok()
+            }
+            _ => None,
+        })
+    }
+}
+
+impl AsGatt for TmapRoles {
+    const MIN_SIZE: usize = 2;
+    const MAX_SIZE: usize = 2;
+
+    fn as_gatt(&self) -> &[u8] {
+        self.0.as_gatt()
+    }
+}
+
+impl FromGatt for TmapRoles {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        u16::from_gatt(data).map(Self)
+    }
+}
+
+/// Telephony and Media Audio service.
+///
+/// Can be added to a `#[gatt_server]` like a `#[gatt_service]`. No role is declared until they are set.
+pub struct TmapService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// TMAP roles of the device.
+    pub roles: Characteristic<TmapRoles>,
+}
+
+impl TmapService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 3;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 0;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static ROLES_STORE: StaticCell<[u8; 2]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::TELEPHONY_AND_MEDIA_AUDIO));
+        let roles = service
+            .add_characteristic(
+                characteristic::TMAP_ROLE,
+                &[CharacteristicProp::Read],
+                TmapRoles::default(),
+                ROLES_STORE.init([0; 2]),
+            )
+            .build();
+        Self {
+            handle: service.build(),
+            roles,
+        }
+    }
+}
+
+impl GattService for TmapService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+/// Read the TMAP roles of a connected device.
+pub async fn read_roles<C: Controller, P: PacketPool, const MAX_SERVICES: usize>(
+    client: &GattClient<'_, C, P, MAX_SERVICES>,
+) -> Result<TmapRoles, BleHostError<C::Error>> {
+    let services = client
+        .services_by_uuid(&service::TELEPHONY_AND_MEDIA_AUDIO.into())
+        .await?;
+    let service = services.first().ok_or(Error::NotFound)?;
+    let roles: Characteristic<TmapRoles> = client
+        .characteristic_by_uuid(service, &characteristic::TMAP_ROLE.into())
+        .await?;
+    let mut buf = [0; 2];
+    let len = client.read_characteristic(&roles, &mut buf).await?;
+    Ok(TmapRoles::from_gatt(&buf[..len]).map_err(|_| Error::InvalidValue)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmap_roles() {
+        let roles = TmapRoles::CALL_TERMINAL.with(TmapRoles::UNICAST_MEDIA_RECEIVER);
+        assert_eq!(roles.as_gatt(), &[0x0a, 0x00]);
+        assert!(roles.contains(TmapRoles::CALL_TERMINAL));
+        assert!(!roles.contains(TmapRoles::CALL_GATEWAY.with(TmapRoles::CALL_TERMINAL)));
+
+        let mut adv = [0; 31];
+        let len = AdStructure::encode_slice(&[roles.ad_structure()], &mut adv).unwrap();
+        assert_eq!(&adv[..len], &[0x05, 0x16, 0x55, 0x18, 0x0a, 0x00]);
+        assert_eq!(TmapRoles::from_advertising(&adv[..len]), Some(roles));
+        assert_eq!(TmapRoles::from_advertising(&[0x02, 0x01, 0x06]), None);
+    }
+}
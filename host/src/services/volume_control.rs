@@ -0,0 +1,337 @@
+//! Volume Control service.
+//!
+//! A phone or remote controls the volume of an audio accessory, such as a hearing aid or a speaker, through its
+//! Volume Control service. Each operation written to the Volume Control Point carries the change counter of the
+//! volume state the client last saw, so that operations based on a stale state are rejected.
+//!
+//! ```rust no_run
+//! use trouble_host::prelude::*;
+//! use trouble_host::services::volume_control::VolumeControlService;
+//!
+//! #[gatt_server]
+//! struct Server {
+//!     volume: VolumeControlService,
+//! }
+//!
+//! async fn run(server: &Server<'_>, conn: &GattConnection<'_, '_, DefaultPacketPool>) -> Result<(), Error> {
+//!     loop {
+//!         if let GattConnectionEvent::Gatt { event } = conn.next().await {
+//!             match server.volume.process(server, &event) {
+//!                 Ok(changed) => {
+//!                     event.accept()?.send().await;
+//!                     if let Some(state) = changed {
+//!                         // Apply state.volume() to the audio output
+//!                         server.volume.volume_state.notify(conn, &state).await?;
+//!                     }
+//!                 }
+//!                 Err(code) => event.reject(code)?.send().await,
+//!             }
+//!         }
+//!     }
+//! }
+//! ```
+use core::cell::Cell;
+
+use bt_hci::uuid::{characteristic, service};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use heapless::Vec;
+use static_cell::StaticCell;
+
+use crate::att::{AttClient, AttErrorCode, AttReq};
+use crate::attribute::{
+    AttributePermissions, AttributeSecurity, AttributeTable, Characteristic, CharacteristicProp, GattService, Service,
+};
+use crate::attribute_server::AttributeServer;
+use crate::gatt::GattEvent;
+use crate::types::gatt_traits::{AsGatt, FromGatt, FromGattError};
+use crate::{Error, PacketPool};
+
+/// Error returned for an operation carrying a stale change counter.
+pub const INVALID_CHANGE_COUNTER: AttErrorCode = AttErrorCode::application(0x80);
+
+/// Error returned for an unknown operation.
+pub const OPCODE_NOT_SUPPORTED: AttErrorCode = AttErrorCode::application(0x81);
+
+/// Volume flag set once the volume has been changed from its default.
+pub const VOLUME_SETTING_PERSISTED: u8 = 0x01;
+
+/// LE Audio requires an encrypted link to access the characteristics of the service.
+const ENCRYPTED: AttributePermissions = AttributePermissions {
+    read: AttributeSecurity::Encrypted,
+    write: AttributeSecurity::Encrypted,
+};
+
+/// Volume State characteristic value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct VolumeState {
+    buf: [u8; 3],
+}
+
+impl VolumeState {
+    /// Volume from 0 to 255, whether the output is muted, and the change counter.
+    pub fn new(volume: u8, muted: bool, change_counter: u8) -> Self {
+        Self {
+            buf: [volume, muted.into(), change_counter],
+        }
+    }
+
+    /// Volume from 0 to 255.
+    pub fn volume(&self) -> u8 {
+        self.buf[0]
+    }
+
+    /// Whether the output is muted.
+    pub fn muted(&self) -> bool {
+        self.buf[1] != 0
+    }
+
+    /// Change counter, incremented with each change to the volume or mute.
+    pub fn change_counter(&self) -> u8 {
+        self.buf[2]
+    }
+
+    /// The state after changing the volume and mute, with the change counter incremented if either changed.
+    fn changed(&self, volume: u8, muted: bool) -> Option<Self> {
+        (volume != self.volume() || muted != self.muted())
+            .then(|| Self::new(volume, muted, self.change_counter().wrapping_add(1)))
+    }
+}
+
+impl AsGatt for VolumeState {
+    const MIN_SIZE: usize = 3;
+    const MAX_SIZE: usize = 3;
+
+    fn as_gatt(&self) -> &[u8] {
+        &self.buf
+    }
+
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        Self::from_gatt(data).map(|_| ())
+    }
+}
+
+impl FromGatt for VolumeState {
+    fn from_gatt(data: &[u8]) -> Result<Self, FromGattError> {
+        match data {
+            [volume, muted @ (0 | 1), change_counter] => Ok(Self::new(*volume, *muted == 1, *change_counter)),
+            [_, _, _] => Err(FromGattError::InvalidValue),
+            _ => Err(FromGattError::InvalidLength),
+        }
+    }
+}
+
+/// An operation written to the Volume Control Point.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum VolumeOperation {
+    /// Lower the volume by one step.
+    RelativeVolumeDown,
+    /// Raise the volume by one step.
+    RelativeVolumeUp,
+    /// Unmute and lower the volume by one step.
+    UnmuteRelativeVolumeDown,
+    /// Unmute and raise the volume by one step.
+    UnmuteRelativeVolumeUp,
+    /// Set the volume.
+    SetAbsoluteVolume(u8),
+    /// Unmute.
+    Unmute,
+    /// Mute.
+    Mute,
+}
+
+impl VolumeOperation {
+    /// Decode an operation and the change counter it carries.
+    pub fn decode(data: &[u8]) -> Result<(Self, u8), AttErrorCode> {
+        let operation = match data {
+            [0x00, _] => Self::RelativeVolumeDown,
+            [0x01, _] => Self::RelativeVolumeUp,
+            [0x02, _] => Self::UnmuteRelativeVolumeDown,
+            [0x03, _] => Self::UnmuteRelativeVolumeUp,
+            [0x04, _, volume] => Self::SetAbsoluteVolume(*volume),
+            [0x05, _] => Self::Unmute,
+            [0x06, _] => Self::Mute,
+            [0x00..=0x06, ..] => return Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH),
+            _ => return Err(OPCODE_NOT_SUPPORTED),
+        };
+        Ok((operation, data[1]))
+    }
+
+    /// The state after applying the operation, changing the volume by `step` for relative operations.
+    pub fn apply(&self, state: &VolumeState, step: u8) -> (u8, bool) {
+        let (volume, muted) = (state.volume(), state.muted());
+        match self {
+            Self::RelativeVolumeDown => (volume.saturating_sub(step), muted),
+            Self::RelativeVolumeUp => (volume.saturating_add(step), muted),
+            Self::UnmuteRelativeVolumeDown => (volume.saturating_sub(step), false),
+            Self::UnmuteRelativeVolumeUp => (volume.saturating_add(step), false),
+            Self::SetAbsoluteVolume(volume) => (*volume, muted),
+            Self::Unmute => (volume, false),
+            Self::Mute => (volume, true),
+        }
+    }
+}
+
+/// Volume Control service.
+///
+/// Can be added to a `#[gatt_server]` like a `#[gatt_service]`. The volume starts at 0 and unmuted, set the
+/// starting volume with [`VolumeControlService::set_volume`]. Clients access it over an encrypted link only.
+pub struct VolumeControlService {
+    /// Handle of the service.
+    pub handle: u16,
+    /// Volume state, notified to the client after each change.
+    pub volume_state: Characteristic<VolumeState>,
+    /// Volume control point, written by the client.
+    pub control_point: Characteristic<Vec<u8, 3>>,
+    /// Volume flags.
+    pub volume_flags: Characteristic<u8>,
+    step: Cell<u8>,
+}
+
+impl VolumeControlService {
+    /// Number of attributes added to the table.
+    pub const ATTRIBUTE_COUNT: usize = 8;
+    /// Number of CCCDs added to the table.
+    pub const CCCD_COUNT: usize = 1;
+
+    /// Add the service to the attribute table.
+    pub fn new<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        static VOLUME_STATE_STORE: StaticCell<[u8; 3]> = StaticCell::new();
+        static CONTROL_POINT_STORE: StaticCell<[u8; 3]> = StaticCell::new();
+        static VOLUME_FLAGS_STORE: StaticCell<[u8; 1]> = StaticCell::new();
+
+        let mut service = table.add_service(Service::new(service::VOLUME_CONTROL));
+        let volume_state = service
+            .add_characteristic(
+                characteristic::VOLUME_STATE,
+                &[CharacteristicProp::Read, CharacteristicProp::Notify],
+                VolumeState::new(0, false, 0),
+                VOLUME_STATE_STORE.init([0; 3]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let control_point = service
+            .add_characteristic(
+                characteristic::VOLUME_CONTROL_POINT,
+                &[CharacteristicProp::Write],
+                Vec::<u8, 3>::new(),
+                CONTROL_POINT_STORE.init([0; 3]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        let volume_flags = service
+            .add_characteristic(
+                characteristic::VOLUME_FLAGS,
+                &[CharacteristicProp::Read],
+                0u8,
+                VOLUME_FLAGS_STORE.init([0; 1]),
+            )
+            .permissions(ENCRYPTED)
+            .build();
+        Self {
+            handle: service.build(),
+            volume_state,
+            control_point,
+            volume_flags,
+            step: Cell::new(1),
+        }
+    }
+
+    /// Set the amount the relative operations change the volume by, 1 by default.
+    pub fn set_step(&self, step: u8) {
+        self.step.set(step);
+    }
+
+    /// Process a GATT event before it is accepted.
+    ///
+    /// Applies an operation written to the control point, returning the new volume state if it changed, which
+    /// should be notified to the client once the event is accepted. Returns the error code to reject the event
+    /// with if the operation is unknown or carries a stale change counter.
+    pub fn process<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        event: &GattEvent<'_, '_, P>,
+    ) -> Result<Option<VolumeState>, AttErrorCode> {
+        let data = match event.payload().incoming() {
+            AttClient::Request(AttReq::Write { handle, data }) if handle == self.control_point.handle => data,
+            _ => return Ok(None),
+        };
+        let (operation, change_counter) = VolumeOperation::decode(data)?;
+        let state = self
+            .volume_state
+            .get(server)
+            .map_err(|_| AttErrorCode::UNLIKELY_ERROR)?;
+        if change_counter != state.change_counter() {
+            return Err(INVALID_CHANGE_COUNTER);
+        }
+        let (volume, muted) = operation.apply(&state, self.step.get());
+        self.update(server, &state, volume, muted)
+            .map_err(|_| AttErrorCode::UNLIKELY_ERROR)
+    }
+
+    /// Change the volume locally, i.e. from the buttons of the device, returning the new volume state if it
+    /// changed, which should be notified to the client.
+    pub fn set_volume<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        volume: u8,
+        muted: bool,
+    ) -> Result<Option<VolumeState>, Error> {
+        let state = self.volume_state.get(server)?;
+        self.update(server, &state, volume, muted)
+    }
+
+    fn update<M: RawMutex, P: PacketPool, const AT: usize, const CT: usize, const CN: usize>(
+        &self,
+        server: &AttributeServer<'_, M, P, AT, CT, CN>,
+        state: &VolumeState,
+        volume: u8,
+        muted: bool,
+    ) -> Result<Option<VolumeState>, Error> {
+        let Some(state) = state.changed(volume, muted) else {
+            return Ok(None);
+        };
+        self.volume_state.set(server, &state)?;
+        self.volume_flags.set(server, &VOLUME_SETTING_PERSISTED)?;
+        Ok(Some(state))
+    }
+}
+
+impl GattService for VolumeControlService {
+    const ATTRIBUTE_COUNT: usize = Self::ATTRIBUTE_COUNT;
+    const CCCD_COUNT: usize = Self::CCCD_COUNT;
+
+    fn register<M: RawMutex, const MAX: usize>(table: &mut AttributeTable<'_, M, MAX>) -> Self {
+        Self::new(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_operations() {
+        let state = VolumeState::new(250, true, 7);
+        assert_eq!(state.as_gatt(), &[250, 1, 7]);
+        assert_eq!(VolumeState::from_gatt(&[250, 2, 7]), Err(FromGattError::InvalidValue));
+
+        let (operation, counter) = VolumeOperation::decode(&[0x03, 7]).unwrap();
+        assert_eq!((operation, counter), (VolumeOperation::UnmuteRelativeVolumeUp, 7));
+        let (volume, muted) = operation.apply(&state, 10);
+        assert_eq!((volume, muted), (255, false));
+        assert_eq!(state.changed(volume, muted), Some(VolumeState::new(255, false, 8)));
+        assert_eq!(state.changed(250, true), None);
+
+        assert_eq!(
+            VolumeOperation::decode(&[0x04, 7, 42]),
+            Ok((VolumeOperation::SetAbsoluteVolume(42), 7))
+        );
+        assert_eq!(
+            VolumeOperation::decode(&[0x04, 7]),
+            Err(AttErrorCode::INVALID_ATTRIBUTE_VALUE_LENGTH)
+        );
+        assert_eq!(VolumeOperation::decode(&[0x07, 7]), Err(OPCODE_NOT_SUPPORTED));
+    }
+}
@@ -0,0 +1,316 @@
+//! Running the host and the controller on different cores.
+//!
+//! On dual core chips such as the RP2040 or the ESP32-S3, the controller driver and its radio interrupts can be
+//! kept on one core while the host runs on the other. The cores exchange HCI packets as [`HciMessage`]s over an
+//! [`HciLink`], such as a [`ChannelLink`] backed by channels shared between the cores.
+//!
+//! On the host core, [`SplitTransport`] implements the bt-hci [`Transport`], so it is wrapped in an
+//! `ExternalController` like a serial transport. On the controller core, [`run_controller`] moves the packets
+//! between the link and the byte stream of the controller driver.
+//!
+//! The `ble_bas_peripheral_split` examples for the RP2040 (`examples/rp-pico-w`) and the ESP32-S3
+//! (`examples/esp32`) run a controller attached to a UART on one core and the host on the other.
+//!
+//! ```rust no_run
+//! use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+//! use embassy_sync::channel::Channel;
+//! use trouble_host::prelude::*;
+//! use trouble_host::split::{self, ChannelLink, HciMessage, SplitTransport};
+//!
+//! const N: usize = 259;
+//! type Queue = Channel<CriticalSectionRawMutex, HciMessage<N>, 4>;
+//! static TO_CONTROLLER: Queue = Channel::new();
+//! static TO_HOST: Queue = Channel::new();
+//!
+//! // Core 1
+//! async fn host() {
+//!     let (link, _) = ChannelLink::pair(&TO_CONTROLLER, &TO_HOST);
+//!     let controller: ExternalController<_, 10> = ExternalController::new(SplitTransport::new(link));
+//!     // Build the stack on the controller
+//! }
+//!
+//! // Core 0
+//! async fn controller<R, W>(reader: R, writer: W)
+//! where
+//!     R: embedded_io_async::Read,
+//!     W: embedded_io_async::Write<Error = R::Error>,
+//! {
+//!     let (_, link) = ChannelLink::pair(&TO_CONTROLLER, &TO_HOST);
+//!     let _ = split::run_controller(&link, reader, writer).await;
+//! }
+//! ```
+use bt_hci::transport::Transport;
+use bt_hci::{ControllerToHostPacket, FromHciBytes, HostToControllerPacket, PacketKind, ReadHciError};
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+use embassy_sync::channel::Channel;
+use embedded_io_async::{Read, Write};
+
+use crate::Error;
+
+/// An HCI packet passed between the cores, holding up to `N` bytes after the packet indicator.
+#[derive(Debug, Clone)]
+pub struct HciMessage<const N: usize> {
+    kind: PacketKind,
+    len: usize,
+    buf: [u8; N],
+}
+
+impl<const N: usize> HciMessage<N> {
+    /// Create a message from the bytes of a packet of the given kind.
+    pub fn new(kind: PacketKind, data: &[u8]) -> Result<Self, Error> {
+        let mut buf = [0; N];
+        buf.get_mut(..data.len())
+            .ok_or(Error::InsufficientSpace)?
+            .copy_from_slice(data);
+        Ok(Self {
+            kind,
+            len: data.len(),
+            buf,
+        })
+    }
+
+    /// Create a message from a packet sent by the host.
+    pub fn encode<T: HostToControllerPacket>(packet: &T) -> Result<Self, Error> {
+        let len = packet.size();
+        let mut buf = [0; N];
+        let dest = buf.get_mut(..len).ok_or(Error::InsufficientSpace)?;
+        packet.write_hci(dest).map_err(|_| Error::InsufficientSpace)?;
+        Ok(Self {
+            kind: T::KIND,
+            len,
+            buf,
+        })
+    }
+
+    /// Kind of the packet.
+    pub fn kind(&self) -> PacketKind {
+        self.kind
+    }
+
+    /// Bytes of the packet, after the packet indicator.
+    pub fn data(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Message passing between the host core and the controller core.
+///
+/// Each side holds one end of the link, sending to and receiving from the other end.
+pub trait HciLink<const N: usize> {
+    /// Send a message to the other end.
+    fn send(&self, message: HciMessage<N>) -> impl core::future::Future<Output = ()>;
+
+    /// Receive a message from the other end.
+    fn receive(&self) -> impl core::future::Future<Output = HciMessage<N>>;
+}
+
+/// One end of an [`HciLink`] backed by a channel in each direction.
+///
+/// Sharing the channels between cores requires a raw mutex that is safe across cores, such as
+/// `CriticalSectionRawMutex` with a multicore critical section implementation.
+pub struct ChannelLink<'a, M: RawMutex, const N: usize, const Q: usize> {
+    tx: &'a Channel<M, HciMessage<N>, Q>,
+    rx: &'a Channel<M, HciMessage<N>, Q>,
+}
+
+impl<'a, M: RawMutex, const N: usize, const Q: usize> ChannelLink<'a, M, N, Q> {
+    /// Both ends of a link, for the host and for the controller.
+    pub fn pair(
+        to_controller: &'a Channel<M, HciMessage<N>, Q>,
+        to_host: &'a Channel<M, HciMessage<N>, Q>,
+    ) -> (Self, Self) {
+        let host = Self {
+            tx: to_controller,
+            rx: to_host,
+        };
+        let controller = Self {
+            tx: to_host,
+            rx: to_controller,
+        };
+        (host, controller)
+    }
+}
+
+impl<M: RawMutex, const N: usize, const Q: usize> HciLink<N> for ChannelLink<'_, M, N, Q> {
+    async fn send(&self, message: HciMessage<N>) {
+        self.tx.send(message).await
+    }
+
+    async fn receive(&self) -> HciMessage<N> {
+        self.rx.receive().await
+    }
+}
+
+/// Host end of a link, used as the transport of an `ExternalController`.
+pub struct SplitTransport<L: HciLink<N>, const N: usize> {
+    link: L,
+}
+
+impl<L: HciLink<N>, const N: usize> SplitTransport<L, N> {
+    /// Create a transport over the host end of a link.
+    pub fn new(link: L) -> Self {
+        Self { link }
+    }
+}
+
+impl<L: HciLink<N>, const N: usize> embedded_io::ErrorType for SplitTransport<L, N> {
+    type Error = Error;
+}
+
+impl<L: HciLink<N>, const N: usize> Transport for SplitTransport<L, N> {
+    async fn read<'a>(&self, rx: &'a mut [u8]) -> Result<ControllerToHostPacket<'a>, Self::Error> {
+        let message = self.link.receive().await;
+        let data = message.data();
+        let rx = rx.get_mut(..data.len()).ok_or(Error::InsufficientSpace)?;
+        rx.copy_from_slice(data);
+        let (packet, _) = ControllerToHostPacket::from_hci_bytes_with_kind(message.kind(), rx)?;
+        Ok(packet)
+    }
+
+    async fn write<T: HostToControllerPacket>(&self, val: &T) -> Result<(), Self::Error> {
+        self.link.send(HciMessage::encode(val)?).await;
+        Ok(())
+    }
+}
+
+/// Error moving packets in [`run_controller`].
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ControllerError<R: embedded_io::Error, W: embedded_io::Error> {
+    /// Reading from the controller failed or returned an invalid packet.
+    Read(ReadHciError<R>),
+    /// Writing to the controller failed.
+    Write(W),
+}
+
+/// Move packets between the controller end of a link and the HCI byte stream of a controller driver, using the
+/// packet indicators of the UART transport layer.
+///
+/// Packets from the controller that do not fit in an [`HciMessage`] are skipped with a warning. Only returns if
+/// the byte stream fails or carries an invalid packet, after which the stream is no longer aligned on packets.
+pub async fn run_controller<L, R, W, const N: usize>(
+    link: &L,
+    mut reader: R,
+    mut writer: W,
+) -> Result<(), ControllerError<R::Error, W::Error>>
+where
+    L: HciLink<N>,
+    R: Read,
+    W: Write,
+{
+    let to_host = async {
+        loop {
+            match read_message(&mut reader).await {
+                Ok(Some(message)) => link.send(message).await,
+                Ok(None) => warn!("[split] dropped HCI packet larger than {} bytes", N),
+                Err(e) => break e,
+            }
+        }
+    };
+    let to_controller = async {
+        loop {
+            let message = link.receive().await;
+            if let Err(e) = write_message(&mut writer, &message).await {
+                break e;
+            }
+        }
+    };
+    match select(to_host, to_controller).await {
+        Either::First(e) => Err(ControllerError::Read(e)),
+        Either::Second(e) => Err(ControllerError::Write(e)),
+    }
+}
+
+/// Read the next packet from the byte stream, or consume it and return `None` if it does not fit in a message.
+async fn read_message<R: Read, const N: usize>(
+    reader: &mut R,
+) -> Result<Option<HciMessage<N>>, ReadHciError<R::Error>> {
+    let mut indicator = [0];
+    reader.read_exact(&mut indicator).await?;
+    let (kind, _) = PacketKind::from_hci_bytes(&indicator)?;
+    let mut header = [0; 4];
+    let header = match kind {
+        PacketKind::Event => &mut header[..2],
+        PacketKind::SyncData => &mut header[..3],
+        PacketKind::AclData | PacketKind::IsoData => &mut header[..],
+        PacketKind::Cmd => return Err(ReadHciError::InvalidValue),
+    };
+    reader.read_exact(header).await?;
+    let payload_len = match kind {
+        PacketKind::Event => usize::from(header[1]),
+        PacketKind::SyncData => usize::from(header[2]),
+        PacketKind::AclData => usize::from(u16::from_le_bytes([header[2], header[3]])),
+        _ => usize::from(u16::from_le_bytes([header[2], header[3]]) & 0x3fff),
+    };
+    let len = header.len() + payload_len;
+    let mut message = HciMessage { kind, len, buf: [0; N] };
+    let Some(buf) = message.buf.get_mut(..len) else {
+        // Consume the payload so that the stream stays aligned on the next packet
+        let mut scratch = [0; 32];
+        let mut remaining = payload_len;
+        while remaining > 0 {
+            let chunk = remaining.min(scratch.len());
+            reader.read_exact(&mut scratch[..chunk]).await?;
+            remaining -= chunk;
+        }
+        return Ok(None);
+    };
+    let (head, payload) = buf.split_at_mut(header.len());
+    head.copy_from_slice(header);
+    reader.read_exact(payload).await?;
+    Ok(Some(message))
+}
+
+async fn write_message<W: Write, const N: usize>(writer: &mut W, message: &HciMessage<N>) -> Result<(), W::Error> {
+    writer.write_all(&[message.kind() as u8]).await?;
+    writer.write_all(message.data()).await?;
+    writer.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use bt_hci::cmd::controller_baseband::Reset;
+    use bt_hci::event::Event;
+    use embassy_futures::block_on;
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+    extern crate std;
+
+    #[test]
+    fn split_round_trip() {
+        let to_controller: Channel<NoopRawMutex, HciMessage<64>, 2> = Channel::new();
+        let to_host = Channel::new();
+        let (host, controller) = ChannelLink::pair(&to_controller, &to_host);
+        let transport = SplitTransport::new(host);
+
+        // The host command reaches the controller byte stream with its packet indicator
+        block_on(transport.write(&Reset::new())).unwrap();
+        let mut stream = [0u8; 8];
+        block_on(write_message(&mut &mut stream[..], &block_on(controller.receive()))).unwrap();
+        assert_eq!(&stream[..4], &[0x01, 0x03, 0x0c, 0x00]);
+
+        // A Command Complete event from the controller byte stream reaches the host
+        let bytes = [0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00];
+        let message: HciMessage<64> = block_on(read_message(&mut &bytes[..])).unwrap().unwrap();
+        assert_eq!(message.kind(), PacketKind::Event);
+        block_on(controller.send(message));
+        let mut rx = [0; 64];
+        let packet = block_on(transport.read(&mut rx)).unwrap();
+        assert!(matches!(
+            packet,
+            ControllerToHostPacket::Event(Event::CommandComplete(_))
+        ));
+
+        // Packets larger than the message are skipped, and the stream continues with the next packet
+        let mut bytes = std::vec![0x04, 0x0e, 0xff];
+        bytes.resize(bytes.len() + 0xff, 0);
+        bytes.extend_from_slice(&[0x04, 0x0e, 0x04, 0x01, 0x03, 0x0c, 0x00]);
+        let mut reader = &bytes[..];
+        assert!(block_on(read_message::<_, 64>(&mut reader)).unwrap().is_none());
+        let message = block_on(read_message::<_, 64>(&mut reader)).unwrap().unwrap();
+        assert_eq!(message.data(), &bytes[bytes.len() - 6..]);
+        assert!(reader.is_empty());
+    }
+}
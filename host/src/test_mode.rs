@@ -0,0 +1,354 @@
+//! Direct Test Mode.
+//!
+//! The LE Receiver and Transmitter Test commands used for RF qualification and production testing, so test
+//! firmware can use the same stack build as the application. Enter test mode with [`Stack::test_mode`], which
+//! pauses advertising, scanning and connecting while the returned [`TestMode`] is held.
+//!
+//! The lowest version of a test command that covers the requested parameters is used, so that controllers
+//! only supporting the first version of the commands can run the basic tests.
+use bt_hci::cmd::le::LeTestEnd;
+use bt_hci::controller::ControllerCmdSync;
+use bt_hci::param::{CteKind, PhyKind};
+
+use crate::{BleHostError, Error, PacketPool, Stack};
+
+bt_hci::cmd! {
+    /// LE Receiver Test command, version 1.
+    LeReceiverTestV1(LE, 0x001d) {
+        LeReceiverTestV1Params {
+            rx_channel: u8,
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Receiver Test command, version 2.
+    LeReceiverTestV2(LE, 0x0033) {
+        LeReceiverTestV2Params {
+            rx_channel: u8,
+            phy: PhyKind,
+            modulation_index: u8,
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Receiver Test command, version 3.
+    LeReceiverTestV3(LE, 0x004f) {
+        LeReceiverTestV3Params<'a> {
+            rx_channel: u8,
+            phy: PhyKind,
+            modulation_index: u8,
+            expected_cte_length: u8,
+            expected_cte_kind: CteKind,
+            slot_durations: u8,
+            antenna_ids: &'a [u8],
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Transmitter Test command, version 1.
+    LeTransmitterTestV1(LE, 0x001e) {
+        LeTransmitterTestV1Params {
+            tx_channel: u8,
+            test_data_length: u8,
+            packet_payload: u8,
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Transmitter Test command, version 2.
+    LeTransmitterTestV2(LE, 0x0034) {
+        LeTransmitterTestV2Params {
+            tx_channel: u8,
+            test_data_length: u8,
+            packet_payload: u8,
+            phy: PhyKind,
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Transmitter Test command, version 3.
+    LeTransmitterTestV3(LE, 0x0050) {
+        LeTransmitterTestV3Params<'a> {
+            tx_channel: u8,
+            test_data_length: u8,
+            packet_payload: u8,
+            phy: PhyKind,
+            cte_length: u8,
+            cte_kind: CteKind,
+            antenna_ids: &'a [u8],
+        }
+        Return = ();
+    }
+}
+
+bt_hci::cmd! {
+    /// LE Transmitter Test command, version 4.
+    LeTransmitterTestV4(LE, 0x007b) {
+        LeTransmitterTestV4Params<'a> {
+            tx_channel: u8,
+            test_data_length: u8,
+            packet_payload: u8,
+            phy: PhyKind,
+            cte_length: u8,
+            cte_kind: CteKind,
+            antenna_ids: &'a [u8],
+            tx_power_level: i8,
+        }
+        Return = ();
+    }
+}
+
+/// Highest RF channel of the test commands, channel `k` is at 2402 + 2k MHz.
+pub const MAX_CHANNEL: u8 = 39;
+
+/// Packet payload sent by the transmitter test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Payload {
+    /// PRBS9 sequence.
+    Prbs9 = 0,
+    /// Repeated `11110000`.
+    Pattern11110000 = 1,
+    /// Repeated `10101010`.
+    Pattern10101010 = 2,
+    /// PRBS15 sequence.
+    Prbs15 = 3,
+    /// All bits set.
+    AllOnes = 4,
+    /// All bits cleared.
+    AllZeros = 5,
+    /// Repeated `00001111`.
+    Pattern00001111 = 6,
+    /// Repeated `01010101`.
+    Pattern01010101 = 7,
+}
+
+/// Constant Tone Extension sent or expected by a test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ToneExtension<'a> {
+    /// Length in units of 8 µs, from 2 to 20.
+    pub length: u8,
+    /// Type of the extension.
+    pub kind: CteKind,
+    /// Switching and sampling slot durations in µs, 1 or 2. Only used by the receiver test.
+    pub slot_durations: u8,
+    /// Antenna switching pattern.
+    pub antenna_ids: &'a [u8],
+}
+
+/// Parameters of the receiver test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ReceiverTest<'a> {
+    /// RF channel, from 0 to [`MAX_CHANNEL`].
+    pub channel: u8,
+    /// PHY to receive on, the coded PHY receives both coding schemes.
+    pub phy: PhyKind,
+    /// Assume a stable modulation index of the transmitter.
+    pub stable_modulation_index: bool,
+    /// Constant Tone Extension expected in the test packets.
+    pub cte: Option<ToneExtension<'a>>,
+}
+
+impl ReceiverTest<'_> {
+    /// Receiver test on the 1M PHY.
+    pub const fn new(channel: u8) -> Self {
+        Self {
+            channel,
+            phy: PhyKind::Le1M,
+            stable_modulation_index: false,
+            cte: None,
+        }
+    }
+}
+
+/// Parameters of the transmitter test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TransmitterTest<'a> {
+    /// RF channel, from 0 to [`MAX_CHANNEL`].
+    pub channel: u8,
+    /// Length of the payload in octets.
+    pub length: u8,
+    /// Payload of the test packets.
+    pub payload: Payload,
+    /// PHY to transmit on.
+    pub phy: PhyKind,
+    /// Constant Tone Extension added to the test packets.
+    pub cte: Option<ToneExtension<'a>>,
+    /// Transmit power level in dBm, `0x7E` for the minimum and `0x7F` for the maximum level.
+    pub power: Option<i8>,
+}
+
+impl TransmitterTest<'_> {
+    /// Transmitter test on the 1M PHY.
+    pub const fn new(channel: u8, length: u8, payload: Payload) -> Self {
+        Self {
+            channel,
+            length,
+            payload,
+            phy: PhyKind::Le1M,
+            cte: None,
+            power: None,
+        }
+    }
+}
+
+/// Handle to the stack in Direct Test Mode.
+///
+/// Advertising, scanning and connecting wait until this is dropped. End a running test with [`end`](Self::end)
+/// before dropping it.
+pub struct TestMode<'d, C, P: PacketPool> {
+    stack: &'d Stack<'d, C, P>,
+}
+
+impl<'d, C, P: PacketPool> TestMode<'d, C, P> {
+    pub(crate) fn new(stack: &'d Stack<'d, C, P>) -> Self {
+        Self { stack }
+    }
+
+    /// Start the receiver test, counting the test packets received until [`end`](Self::end).
+    pub async fn receiver_test(&mut self, test: &ReceiverTest<'_>) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeReceiverTestV1>
+            + ControllerCmdSync<LeReceiverTestV2>
+            + for<'t> ControllerCmdSync<LeReceiverTestV3<'t>>,
+    {
+        if test.channel > MAX_CHANNEL {
+            return Err(Error::InvalidValue.into());
+        }
+        let host = &self.stack.host;
+        // The coded PHY is received with either coding scheme.
+        let phy = match test.phy {
+            PhyKind::LeCodedS2 => PhyKind::LeCoded,
+            phy => phy,
+        };
+        let modulation_index = test.stable_modulation_index as u8;
+        match test.cte {
+            Some(cte) => {
+                host.command(LeReceiverTestV3::new(
+                    test.channel,
+                    phy,
+                    modulation_index,
+                    cte.length,
+                    cte.kind,
+                    cte.slot_durations,
+                    cte.antenna_ids,
+                ))
+                .await
+            }
+            None if phy != PhyKind::Le1M || test.stable_modulation_index => {
+                host.command(LeReceiverTestV2::new(test.channel, phy, modulation_index))
+                    .await
+            }
+            None => host.command(LeReceiverTestV1::new(test.channel)).await,
+        }
+    }
+
+    /// Start the transmitter test, sending test packets until [`end`](Self::end).
+    pub async fn transmitter_test(&mut self, test: &TransmitterTest<'_>) -> Result<(), BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeTransmitterTestV1>
+            + ControllerCmdSync<LeTransmitterTestV2>
+            + for<'t> ControllerCmdSync<LeTransmitterTestV3<'t>>
+            + for<'t> ControllerCmdSync<LeTransmitterTestV4<'t>>,
+    {
+        if test.channel > MAX_CHANNEL {
+            return Err(Error::InvalidValue.into());
+        }
+        let host = &self.stack.host;
+        let payload = test.payload as u8;
+        let (cte_length, cte_kind, antenna_ids) = match test.cte {
+            Some(cte) => (cte.length, cte.kind, cte.antenna_ids),
+            None => (0, CteKind::AoA, &[][..]),
+        };
+        match (test.power, test.cte) {
+            (Some(power), _) => {
+                host.command(LeTransmitterTestV4::new(
+                    test.channel,
+                    test.length,
+                    payload,
+                    test.phy,
+                    cte_length,
+                    cte_kind,
+                    antenna_ids,
+                    power,
+                ))
+                .await
+            }
+            (None, Some(_)) => {
+                host.command(LeTransmitterTestV3::new(
+                    test.channel,
+                    test.length,
+                    payload,
+                    test.phy,
+                    cte_length,
+                    cte_kind,
+                    antenna_ids,
+                ))
+                .await
+            }
+            (None, None) if test.phy != PhyKind::Le1M => {
+                host.command(LeTransmitterTestV2::new(test.channel, test.length, payload, test.phy))
+                    .await
+            }
+            (None, None) => {
+                host.command(LeTransmitterTestV1::new(test.channel, test.length, payload))
+                    .await
+            }
+        }
+    }
+
+    /// End the running test, returning the number of test packets received by a receiver test.
+    pub async fn end(&mut self) -> Result<u16, BleHostError<C::Error>>
+    where
+        C: ControllerCmdSync<LeTestEnd>,
+    {
+        self.stack.host.command(LeTestEnd::new()).await
+    }
+}
+
+impl<C, P: PacketPool> Drop for TestMode<'_, C, P> {
+    fn drop(&mut self) {
+        let host = &self.stack.host;
+        host.advertise_command_state.release();
+        host.scan_command_state.release();
+        host.connect_command_state.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bt_hci::WriteHci;
+
+    use super::*;
+
+    #[test]
+    fn transmitter_test_encoding() {
+        let mut buf = [0; 16];
+        let cmd = LeTransmitterTestV4::new(
+            19,
+            37,
+            Payload::Prbs9 as u8,
+            PhyKind::Le2M,
+            2,
+            CteKind::AoD1Us,
+            &[1, 2],
+            -4,
+        );
+        cmd.write_hci(&mut buf[..]).unwrap();
+        assert_eq!(cmd.size(), 13);
+        assert_eq!(&buf[..13], &[0x7b, 0x20, 10, 19, 37, 0, 2, 2, 1, 2, 1, 2, 0xfc]);
+    }
+}
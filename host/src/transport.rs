@@ -0,0 +1,242 @@
+//! Byte stream transports for protocols layered on top of BLE.
+//!
+//! Protocols such as PPP, SLIP or postcard-rpc expect a duplex byte stream. The types in this module
+//! provide one over a pair of GATT characteristics, from either the server or the client side, or over
+//! an L2CAP channel. All of them implement [`GattTransport`], so protocol code can be written once and
+//! run over any of these bearers.
+//!
+//! Message boundaries are not preserved: a write may be split over several ATT PDUs or SDUs, and a
+//! read returns whatever is available, up to the size of the provided buffer.
+use bt_hci::controller::Controller;
+use embedded_io_async::{ErrorType, Read, Write};
+
+#[cfg(feature = "gatt")]
+use crate::attribute::Characteristic;
+#[cfg(feature = "gatt")]
+use crate::gatt::{GattClient, GattConnection, GattConnectionEvent, GattEvent, Notification, NotificationListener};
+use crate::l2cap::L2capChannel;
+use crate::pdu::Sdu;
+#[cfg(feature = "gatt")]
+use crate::types::gatt_traits::FromGatt;
+#[cfg(feature = "gatt")]
+use crate::Error;
+use crate::{BleHostError, PacketPool, Stack};
+
+/// A duplex byte stream carrying a higher layer protocol.
+///
+/// Implemented by [`GattServerTransport`], [`GattClientTransport`] and [`L2capTransport`], and by any
+/// other `embedded-io-async` stream.
+pub trait GattTransport: Read + Write {}
+
+impl<T: Read + Write> GattTransport for T {}
+
+/// Server side byte stream over a pair of characteristics.
+///
+/// Bytes written by the peer to the RX characteristic are read from the stream, and bytes written to
+/// the stream are notified on the TX characteristic. The peer must subscribe to notifications of the TX
+/// characteristic, otherwise written bytes are discarded.
+///
+/// Reading consumes the events of the connection. GATT requests for other attributes are processed by
+/// the attribute server with its default behaviour.
+#[cfg(feature = "gatt")]
+pub struct GattServerTransport<'a, 'stack, 'server, P: PacketPool, R: FromGatt, T: FromGatt> {
+    connection: &'a GattConnection<'stack, 'server, P>,
+    rx: &'a Characteristic<R>,
+    tx: &'a Characteristic<T>,
+    pending: heapless::Vec<u8, 512>,
+    offset: usize,
+}
+
+#[cfg(feature = "gatt")]
+impl<'a, 'stack, 'server, P: PacketPool, R: FromGatt, T: FromGatt> GattServerTransport<'a, 'stack, 'server, P, R, T> {
+    /// Create a transport using `rx` for incoming and `tx` for outgoing bytes.
+    pub fn new(
+        connection: &'a GattConnection<'stack, 'server, P>,
+        rx: &'a Characteristic<R>,
+        tx: &'a Characteristic<T>,
+    ) -> Self {
+        Self {
+            connection,
+            rx,
+            tx,
+            pending: heapless::Vec::new(),
+            offset: 0,
+        }
+    }
+}
+
+#[cfg(feature = "gatt")]
+impl<P: PacketPool, R: FromGatt, T: FromGatt> ErrorType for GattServerTransport<'_, '_, '_, P, R, T> {
+    type Error = Error;
+}
+
+#[cfg(feature = "gatt")]
+impl<P: PacketPool, R: FromGatt, T: FromGatt> Read for GattServerTransport<'_, '_, '_, P, R, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.offset == self.pending.len() {
+            match self.connection.next().await {
+                GattConnectionEvent::Disconnected { .. } => return Err(Error::Disconnected),
+                GattConnectionEvent::AttTransactionTimeout => return Err(Error::Timeout),
+                GattConnectionEvent::Gatt {
+                    event: GattEvent::Write(event),
+                } if event.handle() == self.rx.handle => {
+                    self.pending.clear();
+                    self.offset = 0;
+                    self.pending
+                        .extend_from_slice(event.data())
+                        .map_err(|_| Error::InsufficientSpace)?;
+                    event.accept()?.send().await;
+                }
+                GattConnectionEvent::Gatt { event } => event.accept()?.send().await,
+                _ => {}
+            }
+        }
+        let n = buf.len().min(self.pending.len() - self.offset);
+        buf[..n].copy_from_slice(&self.pending[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "gatt")]
+impl<P: PacketPool, R: FromGatt, T: FromGatt> Write for GattServerTransport<'_, '_, '_, P, R, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.tx.notify_stream(self.connection, buf).await?;
+        Ok(buf.len())
+    }
+}
+
+/// Client side byte stream over a pair of characteristics of the peer.
+///
+/// Bytes written to the stream are written without response to the RX characteristic of the peer,
+/// and notifications of its TX characteristic are read from the stream. The [`GattClient::task`] must be
+/// running for notifications to be received.
+#[cfg(feature = "gatt")]
+pub struct GattClientTransport<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize, R: FromGatt> {
+    client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+    rx: &'a Characteristic<R>,
+    listener: NotificationListener<'a, 512>,
+    pending: Option<Notification<512>>,
+    offset: usize,
+}
+
+#[cfg(feature = "gatt")]
+impl<'a, 'reference, C: Controller, P: PacketPool, const MAX_SERVICES: usize, R: FromGatt>
+    GattClientTransport<'a, 'reference, C, P, MAX_SERVICES, R>
+{
+    /// Create a transport writing to the peer's `rx` characteristic, and subscribing to notifications
+    /// of its `tx` characteristic.
+    pub async fn new<T: FromGatt>(
+        client: &'a GattClient<'reference, C, P, MAX_SERVICES>,
+        rx: &'a Characteristic<R>,
+        tx: &Characteristic<T>,
+    ) -> Result<Self, BleHostError<C::Error>> {
+        let listener = client.subscribe(tx, false).await?;
+        Ok(Self {
+            client,
+            rx,
+            listener,
+            pending: None,
+            offset: 0,
+        })
+    }
+}
+
+#[cfg(feature = "gatt")]
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize, R: FromGatt> ErrorType
+    for GattClientTransport<'_, '_, C, P, MAX_SERVICES, R>
+{
+    type Error = BleHostError<C::Error>;
+}
+
+#[cfg(feature = "gatt")]
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize, R: FromGatt> Read
+    for GattClientTransport<'_, '_, C, P, MAX_SERVICES, R>
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pending.as_ref().is_none_or(|n| self.offset == n.as_ref().len()) {
+            self.pending = Some(self.listener.next().await);
+            self.offset = 0;
+        }
+        let data = &unwrap!(self.pending.as_ref()).as_ref()[self.offset..];
+        let n = buf.len().min(data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+#[cfg(feature = "gatt")]
+impl<C: Controller, P: PacketPool, const MAX_SERVICES: usize, R: FromGatt> Write
+    for GattClientTransport<'_, '_, C, P, MAX_SERVICES, R>
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        // Each write command carries a 3 byte opcode and handle
+        let chunk_len = usize::from(self.client.connection().att_mtu()) - 3;
+        for chunk in buf.chunks(chunk_len) {
+            self.client
+                .write_characteristic_without_response(self.rx, chunk)
+                .await?;
+        }
+        Ok(buf.len())
+    }
+}
+
+/// Byte stream over an L2CAP channel.
+///
+/// Written bytes are sent as SDUs of at most the MTU of the channel.
+pub struct L2capTransport<'a, 'd, C: Controller, P: PacketPool> {
+    stack: &'a Stack<'d, C, P>,
+    channel: &'a mut L2capChannel<'d, P>,
+    pending: Option<Sdu<P::Packet>>,
+    offset: usize,
+}
+
+impl<'a, 'd, C: Controller, P: PacketPool> L2capTransport<'a, 'd, C, P> {
+    /// Create a transport over the channel.
+    pub fn new(stack: &'a Stack<'d, C, P>, channel: &'a mut L2capChannel<'d, P>) -> Self {
+        Self {
+            stack,
+            channel,
+            pending: None,
+            offset: 0,
+        }
+    }
+}
+
+impl<C: Controller, P: PacketPool> ErrorType for L2capTransport<'_, '_, C, P> {
+    type Error = BleHostError<C::Error>;
+}
+
+impl<C: Controller, P: PacketPool> Read for L2capTransport<'_, '_, C, P> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pending.as_ref().is_none_or(|sdu| self.offset == sdu.len()) {
+            self.pending = Some(self.channel.receive_sdu(self.stack).await?);
+            self.offset = 0;
+        }
+        let data = &unwrap!(self.pending.as_ref()).as_ref()[self.offset..];
+        let n = buf.len().min(data.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        self.offset += n;
+        Ok(n)
+    }
+}
+
+impl<C: Controller, P: PacketPool> Write for L2capTransport<'_, '_, C, P> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        let chunk_len = usize::from(self.channel.tx_mtu());
+        for chunk in buf.chunks(chunk_len) {
+            self.channel.send(self.stack, chunk).await?;
+        }
+        Ok(buf.len())
+    }
+}
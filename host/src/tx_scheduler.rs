@@ -0,0 +1,311 @@
+//! Ordering of outbound PDUs waiting for the transmit loop.
+//!
+//! PDUs of a connection are sent in the order they were queued. Across connections, the one with the most
+//! urgent PDU is served first, without holding back the oldest PDU for more than [`STARVATION_LIMIT`] sends.
+use core::cell::RefCell;
+use core::future::poll_fn;
+use core::task::Poll;
+
+use bt_hci::param::ConnHandle;
+use embassy_sync::waitqueue::WakerRegistration;
+use embassy_time::Instant;
+use heapless::Vec;
+
+use crate::{config, Error};
+
+/// Scheduling class of an outbound PDU.
+///
+/// Each class has its own share of the queue, so a burst of normal traffic never prevents
+/// high priority PDUs from being queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub(crate) enum TxPriority {
+    /// Regular ATT, SMP and signaling traffic.
+    Normal,
+    /// Latency critical traffic, such as notifications of HID input reports.
+    High,
+}
+
+/// A PDU waiting to be sent.
+pub(crate) struct TxEntry<T> {
+    pub(crate) handle: ConnHandle,
+    pub(crate) item: T,
+    pub(crate) priority: TxPriority,
+    pub(crate) deadline: Option<Instant>,
+    seq: u32,
+}
+
+impl<T> TxEntry<T> {
+    /// Whether the PDU should be granted controller buffers ahead of other traffic on the connection,
+    /// including L2CAP channel segments.
+    pub(crate) fn urgent(&self) -> bool {
+        self.priority == TxPriority::High || self.deadline.is_some()
+    }
+
+    fn queued_before(&self, other: &Self) -> bool {
+        self.seq.wrapping_sub(other.seq) > u32::MAX / 2
+    }
+
+    // Entries are ranked by class, then earliest deadline first, then in the order they were queued.
+    fn sends_before(&self, other: &Self) -> bool {
+        if self.priority != other.priority {
+            return self.priority > other.priority;
+        }
+        match (self.deadline, other.deadline) {
+            (Some(a), Some(b)) if a != b => a < b,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            _ => self.queued_before(other),
+        }
+    }
+}
+
+const QUEUE_SIZE: usize = config::L2CAP_TX_QUEUE_SIZE;
+
+/// Number of PDUs that may be sent ahead of the oldest queued PDU.
+const STARVATION_LIMIT: usize = QUEUE_SIZE;
+
+struct State<T> {
+    entries: Vec<TxEntry<T>, { QUEUE_SIZE * 2 }>,
+    seq: u32,
+    /// PDUs sent ahead of the oldest queued PDU.
+    bypassed: usize,
+    push_waker: WakerRegistration,
+    pop_waker: WakerRegistration,
+}
+
+impl<T> State<T> {
+    fn has_space(&self, priority: TxPriority) -> bool {
+        self.entries.iter().filter(|e| e.priority == priority).count() < QUEUE_SIZE
+    }
+
+    fn push(&mut self, handle: ConnHandle, item: T, priority: TxPriority, deadline: Option<Instant>) {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        let entry = TxEntry {
+            handle,
+            item,
+            priority,
+            deadline,
+            seq,
+        };
+        // Space per class is checked by the caller, so the queue cannot be full
+        if self.entries.push(entry).is_err() {
+            unreachable!()
+        }
+        self.pop_waker.wake();
+    }
+
+    // Index of the first entry by the given order, among the entries matching the filter.
+    fn first(
+        &self,
+        filter: impl Fn(&TxEntry<T>) -> bool,
+        before: impl Fn(&TxEntry<T>, &TxEntry<T>) -> bool,
+    ) -> Option<usize> {
+        let entries = &self.entries;
+        (0..entries.len())
+            .filter(|&i| filter(&entries[i]))
+            .reduce(|a, b| if before(&entries[b], &entries[a]) { b } else { a })
+    }
+
+    fn pop(&mut self) -> Option<TxEntry<T>> {
+        // The oldest entry is always the next one of its connection
+        let oldest = self.first(|_| true, TxEntry::queued_before)?;
+        let next = if self.bypassed >= STARVATION_LIMIT {
+            oldest
+        } else {
+            // Serve the connection with the most urgent entry, in the order its entries were queued
+            let urgent = unwrap!(self.first(|_| true, TxEntry::sends_before));
+            let handle = self.entries[urgent].handle;
+            unwrap!(self.first(|e| e.handle == handle, TxEntry::queued_before))
+        };
+        if next == oldest {
+            self.bypassed = 0;
+        } else {
+            self.bypassed += 1;
+        }
+        self.push_waker.wake();
+        Some(self.entries.swap_remove(next))
+    }
+}
+
+/// Queue of outbound PDUs, ordered by priority and deadline across connections.
+pub(crate) struct TxScheduler<T> {
+    state: RefCell<State<T>>,
+}
+
+impl<T> TxScheduler<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: RefCell::new(State {
+                entries: Vec::new(),
+                seq: 0,
+                bypassed: 0,
+                push_waker: WakerRegistration::new(),
+                pop_waker: WakerRegistration::new(),
+            }),
+        }
+    }
+
+    /// Queue a PDU, waiting for space in its class.
+    pub(crate) async fn push(&self, handle: ConnHandle, item: T, priority: TxPriority, deadline: Option<Instant>) {
+        let mut item = Some(item);
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            if state.has_space(priority) {
+                state.push(handle, unwrap!(item.take()), priority, deadline);
+                Poll::Ready(())
+            } else {
+                state.push_waker.register(cx.waker());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Queue a PDU, failing if there is no space in its class.
+    pub(crate) fn try_push(
+        &self,
+        handle: ConnHandle,
+        item: T,
+        priority: TxPriority,
+        deadline: Option<Instant>,
+    ) -> Result<(), Error> {
+        let mut state = self.state.borrow_mut();
+        if !state.has_space(priority) {
+            return Err(Error::OutOfMemory);
+        }
+        state.push(handle, item, priority, deadline);
+        Ok(())
+    }
+
+    /// Wait for the next PDU to send.
+    pub(crate) async fn pop(&self) -> TxEntry<T> {
+        poll_fn(|cx| {
+            let mut state = self.state.borrow_mut();
+            match state.pop() {
+                Some(entry) => Poll::Ready(entry),
+                None => {
+                    state.pop_waker.register(cx.waker());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Number of queued PDUs.
+    pub(crate) fn len(&self) -> usize {
+        self.state.borrow().entries.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.state.borrow().entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embassy_time::Duration;
+
+    use super::*;
+
+    fn pop(scheduler: &TxScheduler<u8>) -> Option<u8> {
+        scheduler.state.borrow_mut().pop().map(|e| e.item)
+    }
+
+    #[test]
+    fn fifo_per_connection() {
+        let scheduler = TxScheduler::new();
+        let handle = ConnHandle::new(1);
+        let now = Instant::now();
+        scheduler.try_push(handle, 1, TxPriority::Normal, None).unwrap();
+        scheduler
+            .try_push(handle, 2, TxPriority::Normal, Some(now + Duration::from_secs(10)))
+            .unwrap();
+        scheduler.try_push(handle, 3, TxPriority::High, None).unwrap();
+
+        assert_eq!(pop(&scheduler), Some(1));
+        assert_eq!(pop(&scheduler), Some(2));
+        assert_eq!(pop(&scheduler), Some(3));
+        assert_eq!(pop(&scheduler), None);
+    }
+
+    #[test]
+    fn order_connections_by_priority_then_deadline() {
+        let scheduler = TxScheduler::new();
+        let now = Instant::now();
+        scheduler
+            .try_push(ConnHandle::new(1), 1, TxPriority::Normal, None)
+            .unwrap();
+        scheduler
+            .try_push(
+                ConnHandle::new(2),
+                2,
+                TxPriority::Normal,
+                Some(now + Duration::from_secs(30)),
+            )
+            .unwrap();
+        scheduler
+            .try_push(
+                ConnHandle::new(3),
+                3,
+                TxPriority::Normal,
+                Some(now + Duration::from_secs(10)),
+            )
+            .unwrap();
+        scheduler
+            .try_push(ConnHandle::new(4), 4, TxPriority::Normal, None)
+            .unwrap();
+        // The urgent entry of connection 4 pulls the entry queued before it
+        scheduler
+            .try_push(ConnHandle::new(4), 5, TxPriority::High, None)
+            .unwrap();
+
+        assert_eq!(pop(&scheduler), Some(4));
+        assert_eq!(pop(&scheduler), Some(5));
+        assert_eq!(pop(&scheduler), Some(3));
+        assert_eq!(pop(&scheduler), Some(2));
+        assert_eq!(pop(&scheduler), Some(1));
+        assert_eq!(pop(&scheduler), None);
+    }
+
+    #[test]
+    fn starvation_bound() {
+        let scheduler = TxScheduler::new();
+        scheduler
+            .try_push(ConnHandle::new(1), 0, TxPriority::Normal, None)
+            .unwrap();
+        for i in 0..STARVATION_LIMIT {
+            scheduler
+                .try_push(ConnHandle::new(2), i as u8 + 1, TxPriority::High, None)
+                .unwrap();
+        }
+        for i in 0..STARVATION_LIMIT {
+            assert_eq!(pop(&scheduler), Some(i as u8 + 1));
+            // Keep the urgent connection busy past the bound
+            if i == 0 {
+                scheduler
+                    .try_push(ConnHandle::new(2), 0xFF, TxPriority::High, None)
+                    .unwrap();
+            }
+        }
+        assert_eq!(pop(&scheduler), Some(0));
+        assert_eq!(pop(&scheduler), Some(0xFF));
+    }
+
+    #[test]
+    fn space_per_class() {
+        let scheduler = TxScheduler::new();
+        let handle = ConnHandle::new(1);
+        for i in 0..QUEUE_SIZE {
+            scheduler.try_push(handle, i as u8, TxPriority::Normal, None).unwrap();
+        }
+        assert_eq!(
+            scheduler.try_push(handle, 0, TxPriority::Normal, None),
+            Err(Error::OutOfMemory)
+        );
+        assert!(scheduler.try_push(handle, 0, TxPriority::High, None).is_ok());
+        assert_eq!(scheduler.len(), QUEUE_SIZE + 1);
+    }
+}
@@ -5,12 +5,15 @@ use heapless::{String, Vec};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[non_exhaustive]
 /// Error type to signify an issue when converting from GATT bytes to a concrete type
 pub enum FromGattError {
     /// Byte array's length did not match what was expected for the converted type
     InvalidLength,
     /// Attempt to encode as string failed due to an invalid character representation in the byte array
     InvalidCharacter,
+    /// Byte array did not hold one of the values allowed for the converted type
+    InvalidValue,
 }
 
 /// Trait to allow conversion of a fixed size type to and from a byte slice
@@ -37,6 +40,15 @@ pub trait AsGatt: Sized {
     /// Converts to gatt bytes.
     /// Must return a slice of len in MIN_SIZE..=MAX_SIZE
     fn as_gatt(&self) -> &[u8];
+
+    /// Checks bytes written by a client before they are stored.
+    ///
+    /// Writes are accepted unchecked by default, a write rejected here is answered with
+    /// `Value Not Allowed` or `Invalid Attribute Value Length`.
+    fn validate(data: &[u8]) -> Result<(), FromGattError> {
+        let _ = data;
+        Ok(())
+    }
 }
 
 /// Trait to allow conversion of gatt bytes into a type
@@ -44,6 +44,16 @@ impl From<u16> for Uuid {
     }
 }
 
+impl From<u32> for Uuid {
+    fn from(data: u32) -> Self {
+        Uuid::from_u32(data)
+    }
+}
+
+/// The Bluetooth Base UUID, 00000000-0000-1000-8000-00805F9B34FB, from which 16 and 32-bit UUIDs are
+/// shortened.
+const BASE_UUID: u128 = 0x0000_0000_0000_1000_8000_0080_5F9B_34FB;
+
 impl Uuid {
     /// Create a new 16-bit UUID.
     pub const fn new_short(val: u16) -> Self {
@@ -55,6 +65,54 @@ impl Uuid {
         Self::Uuid128(val)
     }
 
+    /// Create a 16-bit UUID from its numeric value, such as `0x180f` for the Battery service.
+    pub const fn from_u16(val: u16) -> Self {
+        Self::new_short(val)
+    }
+
+    /// Create a 32-bit UUID from its numeric value.
+    ///
+    /// ATT only carries 16 and 128-bit UUIDs, so the UUID is stored as a 16-bit UUID if its value fits,
+    /// and expanded with the Bluetooth Base UUID otherwise.
+    pub const fn from_u32(val: u32) -> Self {
+        if val <= u16::MAX as u32 {
+            Self::new_short(val as u16)
+        } else {
+            Self::from_u128(BASE_UUID | ((val as u128) << 96))
+        }
+    }
+
+    /// Create a 128-bit UUID from its numeric value, as written in its string form, such as
+    /// `0x6e400001_b5a3_f393_e0a9_e50e24dcca9e`.
+    pub const fn from_u128(val: u128) -> Self {
+        Self::Uuid128(val.to_le_bytes())
+    }
+
+    /// Get the value of the UUID as a 32-bit UUID, if it is a 16-bit UUID or a 128-bit UUID derived
+    /// from the Bluetooth Base UUID.
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Uuid::Uuid16(data) => Some(u16::from_le_bytes(*data).into()),
+            Uuid::Uuid128(data) => {
+                let val = u128::from_le_bytes(*data);
+                if val & ((1 << 96) - 1) == BASE_UUID {
+                    Some((val >> 96) as u32)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Get the numeric value of the UUID as a 128-bit UUID, expanding 16-bit UUIDs with the Bluetooth
+    /// Base UUID.
+    pub fn as_u128(&self) -> u128 {
+        match self {
+            Uuid::Uuid16(data) => BASE_UUID | (u128::from(u16::from_le_bytes(*data)) << 96),
+            Uuid::Uuid128(data) => u128::from_le_bytes(*data),
+        }
+    }
+
     /// Copy the UUID bytes into a slice.
     pub fn bytes(&self, data: &mut [u8]) {
         match self {
@@ -112,6 +170,29 @@ impl TryFrom<&[u8]> for Uuid {
     }
 }
 
+#[cfg(feature = "std")]
+impl From<uuid::Uuid> for Uuid {
+    fn from(uuid: uuid::Uuid) -> Self {
+        let uuid = Uuid::from_u128(uuid.as_u128());
+        // UUIDs derived from the Bluetooth base UUID use their short form
+        uuid.as_u32().map_or(uuid, Uuid::from_u32)
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Uuid> for uuid::Uuid {
+    fn from(uuid: &Uuid) -> Self {
+        uuid::Uuid::from_u128(uuid.as_u128())
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<Uuid> for uuid::Uuid {
+    fn from(uuid: Uuid) -> Self {
+        uuid::Uuid::from(&uuid)
+    }
+}
+
 impl Type for Uuid {
     fn size(&self) -> usize {
         self.as_raw().len()
@@ -143,3 +224,28 @@ impl Encode for Uuid {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uuid32() {
+        assert_eq!(Uuid::from_u32(0x180f), Uuid::from_u16(0x180f));
+        let uuid = Uuid::from_u32(0x1234_5678);
+        assert_eq!(uuid, Uuid::from_u128(0x12345678_0000_1000_8000_00805f9b34fb));
+        assert_eq!(uuid.as_u32(), Some(0x1234_5678));
+        assert_eq!(Uuid::from_u16(0x2a19).as_u32(), Some(0x2a19));
+        assert_eq!(Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e).as_u32(), None);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_uuid_crate() {
+        let battery = uuid::Uuid::from_u128(0x0000180f_0000_1000_8000_00805f9b34fb);
+        assert_eq!(Uuid::from(battery), Uuid::new_short(0x180f));
+        let custom = uuid::Uuid::from_u128(0x6e400001_b5a3_f393_e0a9_e50e24dcca9e);
+        assert_eq!(Uuid::from(custom), Uuid::from_u128(custom.as_u128()));
+        assert_eq!(uuid::Uuid::from(Uuid::from(battery)), battery);
+    }
+}
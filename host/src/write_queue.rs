@@ -0,0 +1,165 @@
+//! Byte queue for coalescing write commands to a characteristic.
+use core::task::{Context, Poll};
+
+use embassy_sync::waitqueue::WakerRegistration;
+
+/// What to do with written bytes that do not fit in a write queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum OverflowPolicy {
+    /// Discard writes that do not fit, keeping the data already queued.
+    DropNewest,
+    /// Discard the oldest queued bytes to make room for new writes.
+    DropOldest,
+}
+
+/// Queue of the data written with write commands to a single attribute.
+pub(crate) struct WriteQueue<const N: usize> {
+    handle: Option<u16>,
+    policy: OverflowPolicy,
+    data: [u8; N],
+    start: usize,
+    len: usize,
+    dropped: usize,
+    waker: WakerRegistration,
+}
+
+impl<const N: usize> WriteQueue<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            handle: None,
+            policy: OverflowPolicy::DropNewest,
+            data: [0; N],
+            start: 0,
+            len: 0,
+            dropped: 0,
+            waker: WakerRegistration::new(),
+        }
+    }
+
+    /// Start queueing write commands to the attribute `handle`.
+    pub(crate) fn register(&mut self, handle: u16, policy: OverflowPolicy) {
+        self.handle = Some(handle);
+        self.policy = policy;
+    }
+
+    /// Whether write commands to `handle` are queued.
+    pub(crate) fn queues(&self, handle: u16) -> bool {
+        self.handle == Some(handle)
+    }
+
+    /// Stop queueing and discard any queued data.
+    pub(crate) fn reset(&mut self) {
+        self.handle = None;
+        self.start = 0;
+        self.len = 0;
+        self.dropped = 0;
+    }
+
+    /// Wake a pending reader, for instance when the connection is gone.
+    pub(crate) fn wake(&mut self) {
+        self.waker.wake();
+    }
+
+    /// Number of bytes discarded because of the overflow policy.
+    pub(crate) fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Queue the data of a write command.
+    ///
+    /// Returns `false` if writes to `handle` are not queued.
+    pub(crate) fn push(&mut self, handle: u16, data: &[u8]) -> bool {
+        if self.handle != Some(handle) {
+            return false;
+        }
+        match self.policy {
+            OverflowPolicy::DropNewest => {
+                if N - self.len < data.len() {
+                    self.dropped = self.dropped.wrapping_add(data.len());
+                    return true;
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                let excess = (self.len + data.len()).saturating_sub(N);
+                let discard = excess.min(self.len);
+                self.start = (self.start + discard) % N;
+                self.len -= discard;
+                self.dropped = self.dropped.wrapping_add(excess);
+            }
+        }
+        let skip = data.len().saturating_sub(N);
+        for b in &data[skip..] {
+            self.data[(self.start + self.len) % N] = *b;
+            self.len += 1;
+        }
+        self.waker.wake();
+        true
+    }
+
+    /// Move queued bytes into `buf`, returning `Poll::Pending` if there are none.
+    pub(crate) fn poll_read(&mut self, cx: Option<&mut Context<'_>>, buf: &mut [u8]) -> Poll<usize> {
+        if self.len == 0 {
+            if let Some(cx) = cx {
+                self.waker.register(cx.waker());
+            }
+            return Poll::Pending;
+        }
+        let len = self.len.min(buf.len());
+        for b in &mut buf[..len] {
+            *b = self.data[self.start];
+            self.start = (self.start + 1) % N;
+        }
+        self.len -= len;
+        Poll::Ready(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read<const N: usize>(q: &mut WriteQueue<N>) -> heapless::Vec<u8, N> {
+        let mut buf = [0; N];
+        match q.poll_read(None, &mut buf) {
+            Poll::Ready(len) => heapless::Vec::from_slice(&buf[..len]).unwrap(),
+            Poll::Pending => heapless::Vec::new(),
+        }
+    }
+
+    #[test]
+    fn ignores_other_handles() {
+        let mut q: WriteQueue<8> = WriteQueue::new();
+        assert!(!q.push(3, &[1, 2]));
+        q.register(3, OverflowPolicy::DropNewest);
+        assert!(!q.push(4, &[1, 2]));
+        assert!(q.push(3, &[1, 2]));
+        assert_eq!(&read(&mut q)[..], &[1, 2]);
+        assert!(read(&mut q).is_empty());
+    }
+
+    #[test]
+    fn drop_newest_keeps_whole_writes() {
+        let mut q: WriteQueue<4> = WriteQueue::new();
+        q.register(3, OverflowPolicy::DropNewest);
+        assert!(q.push(3, &[1, 2, 3]));
+        assert!(q.push(3, &[4, 5]));
+        assert_eq!(q.dropped(), 2);
+        assert!(q.push(3, &[6]));
+        assert_eq!(&read(&mut q)[..], &[1, 2, 3, 6]);
+    }
+
+    #[test]
+    fn drop_oldest_keeps_latest_bytes() {
+        let mut q: WriteQueue<4> = WriteQueue::new();
+        q.register(3, OverflowPolicy::DropOldest);
+        assert!(q.push(3, &[1, 2, 3]));
+        assert!(q.push(3, &[4, 5]));
+        assert_eq!(q.dropped(), 1);
+        assert_eq!(&read(&mut q)[..], &[2, 3, 4, 5]);
+
+        assert!(q.push(3, &[1, 2, 3, 4, 5, 6]));
+        assert_eq!(q.dropped(), 3);
+        assert_eq!(&read(&mut q)[..], &[3, 4, 5, 6]);
+    }
+}
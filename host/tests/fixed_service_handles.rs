@@ -0,0 +1,36 @@
+use trouble_host::prelude::*;
+
+#[gatt_service(uuid = service::BATTERY)]
+struct LevelService {
+    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify)]
+    level: u8,
+}
+
+#[gatt_service(uuid = "408813df-5dd4-1f87-ec11-cdb000100000", handle_start = 0x100)]
+struct FixedService {
+    #[characteristic(uuid = "408813df-5dd4-1f87-ec11-cdb001100000", read, write)]
+    value: u8,
+}
+
+#[gatt_service(uuid = service::DEVICE_INFORMATION)]
+struct AfterService {
+    #[characteristic(uuid = characteristic::MODEL_NUMBER_STRING, read, value = "model", max_len = 16)]
+    model: &'static str,
+}
+
+#[gatt_server]
+struct Server {
+    level: LevelService,
+    fixed: FixedService,
+    after: AfterService,
+}
+
+#[test]
+fn gatt_server_fixed_service_handles() {
+    let server = Server::new_default("test").unwrap();
+    assert_eq!(server.level.handle, trouble_host::gap::GAP_SERVICE_HANDLE_END);
+    assert_eq!(server.fixed.handle, 0x100);
+    assert_eq!(server.fixed.value.handle, 0x102);
+    // Services after a fixed one continue after its handle range
+    assert_eq!(server.after.handle, 0x110);
+}
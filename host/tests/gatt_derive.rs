@@ -82,10 +82,10 @@ async fn gatt_client_server() {
             ..
         } = stack.build();
 
-        let gap = GapConfig::Peripheral(PeripheralConfig {
-            name: &name,
-            appearance: &appearance::power_device::GENERIC_POWER_DEVICE,
-        });
+        let gap = GapConfig::Peripheral(PeripheralConfig::new(
+            &name,
+            &appearance::power_device::GENERIC_POWER_DEVICE,
+        ));
         let server: Server = Server::new_with_config(
             gap,
         ).unwrap();
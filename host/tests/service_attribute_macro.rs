@@ -2,6 +2,7 @@
 
 use embassy_sync::blocking_mutex::raw::NoopRawMutex;
 use trouble_host::prelude::*;
+use trouble_host::types::gatt_traits::FromGattError;
 
 #[gatt_service(uuid = "7e701cf1-b1df-42a1-bb5f-6a1028c793b0")]
 struct CustomService {
@@ -9,14 +10,47 @@ struct CustomService {
     short_uuid: u8,
     #[characteristic(uuid = "7e711cf1-b1df-42a1-bb5f-6a1028c793b0", write_without_response, indicate)]
     long_uuid: f32,
-    #[characteristic(uuid = "2a38", read, notify)]
+    #[characteristic(uuid = "2a38", read, notify, latency_critical)]
     notify: [u8; 8],
+    #[characteristic(uuid = "2a39", read, write, store = BORROWED_STORE.init([0; 64]))]
+    borrowed: heapless::Vec<u8, 64>,
+    #[descriptor(uuid = "2901", read, value = "Channel")]
+    #[characteristic(uuid = "2a3a", read, notify, count = 4)]
+    channels: [u16; 4],
+    #[characteristic(uuid = "2a3b", write)]
+    command: Command,
+    #[characteristic(uuid = "2a3c", read, write, value = "trouble", max_len = 32)]
+    name: &'static str,
+    #[characteristic(uuid = "2a3d", read, write, notify, write_encrypted)]
+    secret: u32,
     non_characteristic_field: u8,
 }
 
+#[derive(GattEnum, Default, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum Command {
+    #[default]
+    Stop = 0,
+    Start = 4,
+}
+
+impl TryFrom<u8> for Command {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, ()> {
+        match value {
+            0 => Ok(Command::Stop),
+            4 => Ok(Command::Start),
+            _ => Err(()),
+        }
+    }
+}
+
+static BORROWED_STORE: static_cell::StaticCell<[u8; 64]> = static_cell::StaticCell::new();
+
 #[tokio::test]
 async fn gatt_service_derive() {
-    let mut table: AttributeTable<NoopRawMutex, 10> = AttributeTable::new();
+    let mut table: AttributeTable<NoopRawMutex, 40> = AttributeTable::new();
     let service = CustomService::new(&mut table);
 
     // Check all fields of service have been generated and are accessible
@@ -25,4 +59,125 @@ async fn gatt_service_derive() {
     let _characteristic_short_uuid = service.short_uuid;
     let _characteristic_long_uuid = service.long_uuid;
     let _notify = service.notify;
+    let borrowed = service.borrowed;
+    let _secret = service.secret;
+    let channels = service.channels;
+    assert!(channels.windows(2).all(|pair| pair[0].handle < pair[1].handle));
+    assert_eq!(CustomService::ATTRIBUTE_COUNT, 34);
+    let _command = service.command;
+
+    // Values shorter than max_len are read back at their written length
+    let name = table
+        .find_characteristic_by_value_handle::<heapless::String<32>>(service.name.handle)
+        .unwrap();
+    assert_eq!(table.get(&name).unwrap(), "trouble");
+    table.set(&name, &heapless::String::try_from("ok").unwrap()).unwrap();
+    assert_eq!(table.get(&name).unwrap(), "ok");
+
+    // Borrowed storage is read in place
+    table
+        .set(&borrowed, &heapless::Vec::from_slice(&[1, 2, 3]).unwrap())
+        .unwrap();
+    assert_eq!(table.with_value(&borrowed, |value| value.to_vec()).unwrap(), [1, 2, 3]);
+}
+
+#[test]
+fn gatt_enum_derive() {
+    assert_eq!(Command::Start.as_gatt(), &[4]);
+    assert_eq!(Command::from_gatt(&[4]), Ok(Command::Start));
+    assert_eq!(Command::from_gatt(&[1]), Err(FromGattError::InvalidValue));
+    assert_eq!(Command::from_gatt(&[0, 0]), Err(FromGattError::InvalidLength));
+    assert_eq!(Command::validate(&[2]), Err(FromGattError::InvalidValue));
+}
+
+#[gatt_service(uuid = service::BATTERY)]
+struct LevelService {
+    #[characteristic(uuid = characteristic::BATTERY_LEVEL, read, notify)]
+    level: u8,
+}
+
+/// A service built by hand, as exported by a library crate.
+struct ExternalService {
+    handle: u16,
+}
+
+impl GattService for ExternalService {
+    const ATTRIBUTE_COUNT: usize = 1;
+    const CCCD_COUNT: usize = 0;
+
+    fn register<M: embassy_sync::blocking_mutex::raw::RawMutex, const MAX: usize>(
+        table: &mut AttributeTable<'_, M, MAX>,
+    ) -> Self {
+        let service = table.add_service(Service::new(service::DEVICE_INFORMATION));
+        Self {
+            handle: service.build(),
+        }
+    }
+}
+
+#[gatt_server]
+struct OptionalServer {
+    level: LevelService,
+    external: ExternalService,
+    /// Left out along with its attributes, the type does not even need to exist.
+    #[cfg(any())]
+    disabled: DisabledService,
+}
+
+#[test]
+fn gatt_server_cfg_service() {
+    assert_eq!(
+        _ATTRIBUTE_TABLE_SIZE,
+        trouble_host::gap::GAP_SERVICE_ATTRIBUTE_COUNT
+            + LevelService::ATTRIBUTE_COUNT
+            + ExternalService::ATTRIBUTE_COUNT
+    );
+    assert_eq!(_CCCD_TABLE_SIZE, LevelService::CCCD_COUNT);
+    let server = OptionalServer::new_default("test").unwrap();
+    let _level = server.level.level;
+    // Handles are allocated by the server's table, after the services before it
+    assert!(server.external.handle > server.level.handle);
+}
+
+mod init_with {
+    use super::*;
+
+    fn read_offset() -> Result<i16, ()> {
+        Ok(-3)
+    }
+
+    fn read_missing() -> Result<u8, &'static str> {
+        Err("calibration data missing")
+    }
+
+    #[gatt_service(uuid = "7e701cf2-b1df-42a1-bb5f-6a1028c793b0")]
+    pub struct CalibrationService {
+        #[characteristic(uuid = "2a6f", read, init_with = read_offset)]
+        pub offset: i16,
+    }
+
+    #[gatt_service(uuid = "7e701cf3-b1df-42a1-bb5f-6a1028c793b0")]
+    pub struct MissingService {
+        #[characteristic(uuid = "2a70", read, init_with = read_missing)]
+        pub gain: u8,
+    }
+
+    #[gatt_server]
+    struct UncalibratedServer {
+        missing: MissingService,
+    }
+
+    #[test]
+    fn gatt_service_init_with() {
+        let mut table: AttributeTable<NoopRawMutex, 4> = AttributeTable::new();
+        let service = CalibrationService::new(&mut table).unwrap();
+        assert_eq!(table.get(&service.offset), Ok(-3));
+
+        // Without the GAP service, whose storage is taken by the other server of this test binary
+        let server = UncalibratedServer::new(AttributeTable::new());
+        assert_eq!(
+            server.map(|server| server.missing.handle).err(),
+            Some("failed to initialize characteristic 'MissingService::gain'")
+        );
+    }
 }